@@ -0,0 +1,134 @@
+//! Benchmarks for the code that runs on the audio thread for every block, and
+//! for what the main thread does for every change to a large project.
+//!
+//! Run with `cargo bench`. Criterion compares each run with the last one, so
+//! run it before and after a change to the realtime path.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use meadowlark::backend::channel_graph::{
+    channel_edges, edge_changes, ChannelNodes, EffectNodes, GraphNodes, RecordSource,
+};
+use meadowlark::backend::fader::Fader;
+use meadowlark::backend::fx_rack::{FxChainMix, FxRackSummer};
+use meadowlark::backend::resample::resample;
+
+const SAMPLE_RATE: f32 = 48_000.0;
+const BLOCK_FRAMES: usize = 512;
+const CHANNEL_COUNTS: [usize; 3] = [8, 64, 256];
+
+/// Something that isn't silence, so nothing takes a fast path for zeros.
+fn signal(frames: usize) -> Vec<f32> {
+    (0..frames).map(|i| (i as f32 * 0.05).sin() * 0.5).collect()
+}
+
+fn fader(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fader");
+    group.throughput(Throughput::Elements(BLOCK_FRAMES as u64));
+    let input = signal(BLOCK_FRAMES);
+
+    // A fader that sits still takes the fast path.
+    group.bench_function("still", |b| {
+        let mut fader = Fader::new(SAMPLE_RATE);
+        let (mut left, mut right) = (input.clone(), input.clone());
+        b.iter(|| fader.process(black_box(&mut left), black_box(&mut right)));
+    });
+
+    // One that keeps moving smooths every frame, like under automation.
+    group.bench_function("moving", |b| {
+        let mut fader = Fader::new(SAMPLE_RATE);
+        let (mut left, mut right) = (input.clone(), input.clone());
+        let mut gain_db = 0.0;
+        b.iter(|| {
+            gain_db = if gain_db == 0.0 { -12.0 } else { 0.0 };
+            fader.set(gain_db, 0.25, true);
+            fader.process(black_box(&mut left), black_box(&mut right));
+        });
+    });
+
+    group.bench_function("rack_of_8_chains", |b| {
+        let mut summer = FxRackSummer::new(BLOCK_FRAMES);
+        let mix = FxChainMix { gain_db: -3.0, pan: 0.25, muted: false };
+        b.iter(|| {
+            summer.clear(BLOCK_FRAMES);
+            for _ in 0..8 {
+                summer.add_chain(&mix, black_box(&input), black_box(&input));
+            }
+            black_box(summer.output());
+        });
+    });
+
+    group.finish();
+}
+
+fn resampler(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resample");
+    let input = signal(24_000);
+    group.throughput(Throughput::Elements(input.len() as u64));
+
+    for (from, to) in [(44_100.0, 48_000.0), (48_000.0, 44_100.0), (96_000.0, 48_000.0)] {
+        let id = BenchmarkId::from_parameter(format!("{}_to_{}", from, to));
+        group.bench_with_input(id, &(from, to), |b, &(from, to)| {
+            b.iter(|| resample(black_box(&input), from, to));
+        });
+    }
+
+    group.finish();
+}
+
+/// A project of `count` channels with two effects and an automation lane
+/// each. Every eighth one is a bus that the seven after it play into.
+fn project(count: usize) -> (Vec<ChannelNodes<u32>>, GraphNodes<u32>) {
+    let channels = (0..count)
+        .map(|i| {
+            let base = i as u32 * 10;
+            ChannelNodes {
+                fader: Some(base),
+                notes: Some(base + 1),
+                clips: None,
+                clip_chains: Vec::new(),
+                effects: vec![EffectNodes::Plugin(base + 2), EffectNodes::Plugin(base + 3)],
+                routed_to: if i % 8 == 0 { 0 } else { i / 8 * 8 },
+                sends: Vec::new(),
+                stem: None,
+                hardware_out: None,
+                automation: vec![(base + 4, base)],
+                record: (i % 4 == 1).then(|| RecordSource::Device { left: 0, right: 1 }),
+            }
+        })
+        .collect();
+    let base = count as u32 * 10;
+    let nodes = GraphNodes {
+        graph_in: base,
+        graph_out: base + 1,
+        master_bus: base + 2,
+        graph_in_channels: 2,
+        record: Some(base + 3),
+    };
+    (channels, nodes)
+}
+
+fn channel_graph(c: &mut Criterion) {
+    let mut group = c.benchmark_group("channel_graph");
+
+    for count in CHANNEL_COUNTS {
+        let (mut channels, nodes) = project(count);
+        group.bench_with_input(BenchmarkId::new("edges", count), &count, |b, _| {
+            b.iter(|| channel_edges(black_box(&channels), &nodes));
+        });
+
+        // Adding an effect to one channel, which is what every edit to an
+        // effect rack costs.
+        let connected = channel_edges(&channels, &nodes);
+        channels[count / 2].effects.push(u32::MAX);
+        let desired = channel_edges(&channels, &nodes);
+        group.bench_with_input(BenchmarkId::new("changes", count), &count, |b, _| {
+            b.iter(|| edge_changes(black_box(&connected), black_box(&desired)));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, fader, resampler, channel_graph);
+criterion_main!(benches);
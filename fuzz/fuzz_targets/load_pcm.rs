@@ -0,0 +1,34 @@
+//! Loads arbitrary bytes as an audio file. Loading has to either fail with an
+//! error or give a resource within the size limit, without panicking.
+//!
+//! Run with `cargo fuzz run load_pcm`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use meadowlark::backend::resource_loader::{PcmKey, ResampleQuality, ResourceLoader};
+use meadowlark::backend::waveform::peak_file_path;
+use meadowlark_core_types::time::SampleRate;
+
+/// Small enough that a header claiming a huge file fails quickly.
+const MAX_PCM_BYTES: usize = 16 * 1024 * 1024;
+
+fuzz_target!(|data: &[u8]| {
+    // The loader takes paths, and picks the format by the extension.
+    let path = std::env::temp_dir().join(format!("meadowlark-fuzz-{}.wav", std::process::id()));
+    if std::fs::write(&path, data).is_err() {
+        return;
+    }
+
+    let mut loader = ResourceLoader::new(SampleRate(48_000.0));
+    loader.set_max_pcm_bytes(MAX_PCM_BYTES);
+    for resample_quality in [ResampleQuality::Sinc, ResampleQuality::Linear] {
+        let key = PcmKey { path: path.clone(), resample_to_project_sr: true, resample_quality };
+        let (pcm, res) = loader.load_pcm(&key);
+        if res.is_ok() {
+            assert!(pcm.len_frames() as usize * 4 * pcm.channels() <= loader.max_pcm_bytes());
+            assert!(loader.waveform_peaks(&key).is_some());
+        }
+        let _ = std::fs::remove_file(peak_file_path(&path));
+    }
+});
@@ -0,0 +1,35 @@
+//! Reads arbitrary bytes as the peak file of an audio file. Reading has to
+//! either fail with an error or give peaks that can be drawn, without
+//! panicking.
+//!
+//! Run with `cargo fuzz run load_peak_file`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use meadowlark::backend::waveform::{load_peak_file, peak_file_path};
+use meadowlark::util::write_wav_f32;
+
+const SAMPLE_RATE: u32 = 48_000;
+
+fuzz_target!(|data: &[u8]| {
+    // A peak file is only read if it matches the audio file next to it.
+    let path = std::env::temp_dir().join(format!("meadowlark-fuzz-{}.wav", std::process::id()));
+    if !path.exists() {
+        let left: Vec<f32> = (0..4_096).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+        if write_wav_f32(&path, SAMPLE_RATE, &left, &left).is_err() {
+            return;
+        }
+    }
+    if std::fs::write(peak_file_path(&path), data).is_err() {
+        return;
+    }
+
+    if let Ok(Some(channels)) = load_peak_file(&path, SAMPLE_RATE) {
+        // Zoomed out, and zoomed in past the peaks.
+        for channel in channels.iter() {
+            channel.columns(&[], 0..channel.len_frames(), 64);
+            channel.columns(&[], 0..64, 64);
+        }
+    }
+});
@@ -0,0 +1,354 @@
+//! Decodes AIFF and AIFF-C files, which the PCM loader doesn't read. Integer
+//! samples of 1 to 32 bits (big-endian, or little-endian with the `sowt`
+//! compression type) and 32 and 64 bit floating point samples are supported.
+//! Compressed AIFF-C files are not.
+
+use std::fmt;
+
+/// The channels of a decoded file, each with every frame of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiffPcm {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AiffError {
+    /// The file doesn't start with a `FORM` chunk of type `AIFF` or `AIFC`.
+    NotAiff,
+    /// The `COMM` chunk is missing or too short.
+    MissingCommon,
+    NoChannels,
+    InvalidSampleRate,
+    /// The sample format, as the compression type and bits per sample.
+    UnsupportedFormat([u8; 4], i16),
+    /// The decoded file would be larger than this many bytes.
+    TooLarge(usize),
+}
+
+impl fmt::Display for AiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AiffError::NotAiff => write!(f, "not an AIFF file"),
+            AiffError::MissingCommon => write!(f, "AIFF file has no valid COMM chunk"),
+            AiffError::NoChannels => write!(f, "AIFF file has no channels"),
+            AiffError::InvalidSampleRate => write!(f, "AIFF file has an invalid sample rate"),
+            AiffError::UnsupportedFormat(compression, bits) => write!(
+                f,
+                "unsupported AIFF sample format {:?} with {} bits",
+                String::from_utf8_lossy(compression),
+                bits
+            ),
+            AiffError::TooLarge(max_bytes) => {
+                write!(f, "AIFF file is larger than {} bytes once decoded", max_bytes)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AiffError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleFormat {
+    /// Signed integers of this many bytes, left-justified.
+    IntBe(usize),
+    IntLe(usize),
+    Float32,
+    Float64,
+}
+
+impl SampleFormat {
+    fn bytes(&self) -> usize {
+        match self {
+            SampleFormat::IntBe(bytes) | SampleFormat::IntLe(bytes) => *bytes,
+            SampleFormat::Float32 => 4,
+            SampleFormat::Float64 => 8,
+        }
+    }
+
+    fn decode(&self, sample: &[u8]) -> f32 {
+        match self {
+            SampleFormat::IntBe(_) => int_sample(sample.iter()),
+            SampleFormat::IntLe(_) => int_sample(sample.iter().rev()),
+            SampleFormat::Float32 => {
+                f32::from_be_bytes([sample[0], sample[1], sample[2], sample[3]])
+            }
+            SampleFormat::Float64 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(sample);
+                f64::from_be_bytes(bytes) as f32
+            }
+        }
+    }
+}
+
+/// A signed integer sample from its bytes, most significant first, scaled to
+/// `[-1.0, 1.0)` whatever its size.
+fn int_sample<'a>(bytes: impl ExactSizeIterator<Item = &'a u8>) -> f32 {
+    let shift = 32 - 8 * bytes.len() as u32;
+    let value = bytes.fold(0u32, |value, byte| (value << 8) | u32::from(*byte));
+    ((value << shift) as i32) as f32 / 2_147_483_648.0
+}
+
+struct Common {
+    channels: usize,
+    frames: usize,
+    sample_rate: u32,
+    format: SampleFormat,
+}
+
+fn parse_common(chunk: &[u8], aifc: bool) -> Result<Common, AiffError> {
+    if chunk.len() < 18 || (aifc && chunk.len() < 22) {
+        return Err(AiffError::MissingCommon);
+    }
+    let channels = i16::from_be_bytes([chunk[0], chunk[1]]);
+    let frames = u32::from_be_bytes([chunk[2], chunk[3], chunk[4], chunk[5]]);
+    let bits = i16::from_be_bytes([chunk[6], chunk[7]]);
+    let compression = if aifc { [chunk[18], chunk[19], chunk[20], chunk[21]] } else { *b"NONE" };
+
+    if channels <= 0 {
+        return Err(AiffError::NoChannels);
+    }
+    let sample_rate = extended_to_f64(&chunk[8..18]).round();
+    if !(1.0..=f64::from(u32::MAX)).contains(&sample_rate) {
+        return Err(AiffError::InvalidSampleRate);
+    }
+
+    let int_bytes = if (1..=32).contains(&bits) { Some((bits as usize).div_ceil(8)) } else { None };
+    let format = match (&compression, int_bytes) {
+        (b"NONE" | b"twos", Some(bytes)) => SampleFormat::IntBe(bytes),
+        (b"sowt", Some(bytes)) => SampleFormat::IntLe(bytes),
+        (b"fl32" | b"FL32", _) => SampleFormat::Float32,
+        (b"fl64" | b"FL64", _) => SampleFormat::Float64,
+        _ => return Err(AiffError::UnsupportedFormat(compression, bits)),
+    };
+
+    Ok(Common {
+        channels: channels as usize,
+        frames: frames as usize,
+        sample_rate: sample_rate as u32,
+        format,
+    })
+}
+
+/// An 80 bit IEEE 754 extended precision number, as the sample rate is
+/// stored.
+fn extended_to_f64(bytes: &[u8]) -> f64 {
+    let sign = if bytes[0] & 0x80 == 0 { 1.0 } else { -1.0 };
+    let exponent = i32::from(u16::from_be_bytes([bytes[0] & 0x7f, bytes[1]]));
+    let mut mantissa = [0; 8];
+    mantissa.copy_from_slice(&bytes[2..10]);
+    let mantissa = u64::from_be_bytes(mantissa);
+    if exponent == 0x7fff {
+        return f64::NAN;
+    }
+    sign * mantissa as f64 * 2f64.powi(exponent - 16_383 - 63)
+}
+
+/// Decode a whole file. The frames that the file says it has but doesn't
+/// hold are left out. Fails with `AiffError::TooLarge` if the decoded
+/// samples would take more than `max_bytes`.
+pub fn decode_aiff(bytes: &[u8], max_bytes: usize) -> Result<AiffPcm, AiffError> {
+    if bytes.len() < 12 || &bytes[0..4] != b"FORM" {
+        return Err(AiffError::NotAiff);
+    }
+    let aifc = match &bytes[8..12] {
+        b"AIFF" => false,
+        b"AIFC" => true,
+        _ => return Err(AiffError::NotAiff),
+    };
+
+    let mut common = None;
+    let mut sound: &[u8] = &[];
+    let mut rest = &bytes[12..];
+    while rest.len() >= 8 {
+        let id = &rest[0..4];
+        let len = u32::from_be_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+        let chunk = &rest[8..][..len.min(rest.len() - 8)];
+        match id {
+            b"COMM" => common = Some(parse_common(chunk, aifc)?),
+            // The samples start after the offset and block size, and then
+            // `offset` more bytes.
+            b"SSND" if chunk.len() >= 8 => {
+                let offset = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                sound = chunk.get(8 + offset as usize..).unwrap_or(&[]);
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length.
+        rest = rest.get(8 + len + len % 2..).unwrap_or(&[]);
+    }
+    let common = common.ok_or(AiffError::MissingCommon)?;
+
+    let sample_bytes = common.format.bytes();
+    let frame_bytes = sample_bytes * common.channels;
+    let frames = common.frames.min(sound.len() / frame_bytes);
+    if frames.saturating_mul(common.channels).saturating_mul(4) > max_bytes {
+        return Err(AiffError::TooLarge(max_bytes));
+    }
+
+    let mut channels: Vec<Vec<f32>> =
+        (0..common.channels).map(|_| Vec::with_capacity(frames)).collect();
+    for frame in sound[..frames * frame_bytes].chunks_exact(frame_bytes) {
+        for (channel, sample) in channels.iter_mut().zip(frame.chunks_exact(sample_bytes)) {
+            channel.push(common.format.decode(sample));
+        }
+    }
+
+    Ok(AiffPcm { sample_rate: common.sample_rate, channels })
+}
+
+/// Encode an AIFF file of 16 bit samples, or an AIFF-C file with the given
+/// compression type and bits per sample.
+#[cfg(test)]
+pub fn encode_aiff(
+    sample_rate: u32,
+    channels: &[Vec<f32>],
+    aifc: Option<([u8; 4], i16)>,
+) -> Vec<u8> {
+    let (compression, bits) = aifc.unwrap_or((*b"NONE", 16));
+    let frames = channels[0].len();
+
+    let mut sound = Vec::new();
+    for frame in 0..frames {
+        for channel in channels {
+            let sample = channel[frame];
+            match &compression {
+                b"fl32" => sound.extend(sample.to_be_bytes()),
+                b"fl64" => sound.extend(f64::from(sample).to_be_bytes()),
+                _ => {
+                    let bytes = (bits as usize).div_ceil(8);
+                    let value = (f64::from(sample) * 2_147_483_648.0)
+                        .clamp(f64::from(i32::MIN), f64::from(i32::MAX))
+                        as i32;
+                    let value = (value >> (32 - bits)) << (32 - bits);
+                    let be = &value.to_be_bytes()[..bytes];
+                    if &compression == b"sowt" {
+                        sound.extend(be.iter().rev());
+                    } else {
+                        sound.extend(be);
+                    }
+                }
+            }
+        }
+    }
+
+    // The exponent and mantissa of the sample rate, with the integer bit set.
+    let exponent = 63 - u64::from(sample_rate).leading_zeros() as u16;
+    let mantissa = u64::from(sample_rate) << (63 - exponent);
+
+    let mut comm = Vec::new();
+    comm.extend((channels.len() as i16).to_be_bytes());
+    comm.extend((frames as u32).to_be_bytes());
+    comm.extend(bits.to_be_bytes());
+    comm.extend((16_383 + exponent).to_be_bytes());
+    comm.extend(mantissa.to_be_bytes());
+    if aifc.is_some() {
+        comm.extend(compression);
+        comm.extend([0, 0]);
+    }
+
+    let mut file = b"FORM".to_vec();
+    file.extend(((4 + 8 + comm.len() + 16 + sound.len()) as u32).to_be_bytes());
+    file.extend(if aifc.is_some() { b"AIFC" } else { b"AIFF" });
+    file.extend(b"COMM");
+    file.extend((comm.len() as u32).to_be_bytes());
+    file.extend(&comm);
+    file.extend(b"SSND");
+    file.extend((8 + sound.len() as u32).to_be_bytes());
+    file.extend([0; 8]);
+    file.extend(&sound);
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal() -> Vec<Vec<f32>> {
+        let left: Vec<f32> = (0..1_000).map(|i| (i as f32 * 0.05).sin() * 0.8).collect();
+        let right = left.iter().map(|s| -s * 0.5).collect();
+        vec![left, right]
+    }
+
+    fn assert_close(decoded: &[Vec<f32>], expected: &[Vec<f32>], tolerance: f32) {
+        assert_eq!(decoded.len(), expected.len());
+        for (decoded, expected) in decoded.iter().zip(expected) {
+            assert_eq!(decoded.len(), expected.len());
+            for (d, e) in decoded.iter().zip(expected) {
+                assert!((d - e).abs() <= tolerance, "{} != {}", d, e);
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_16_bit_aiff() {
+        let file = encode_aiff(44_100, &signal(), None);
+        let pcm = decode_aiff(&file, usize::MAX).unwrap();
+        assert_eq!(pcm.sample_rate, 44_100);
+        assert_close(&pcm.channels, &signal(), 1.0 / 32_768.0);
+    }
+
+    #[test]
+    fn decodes_every_aifc_sample_format() {
+        let formats: [([u8; 4], i16, f32); 7] = [
+            (*b"NONE", 8, 1.0 / 128.0),
+            (*b"twos", 12, 1.0 / 2_048.0),
+            (*b"NONE", 24, 1.0 / 8_388_608.0),
+            (*b"NONE", 32, 1e-7),
+            (*b"sowt", 16, 1.0 / 32_768.0),
+            (*b"fl32", 32, 0.0),
+            (*b"fl64", 64, 0.0),
+        ];
+        for (compression, bits, tolerance) in formats {
+            let file = encode_aiff(96_000, &signal(), Some((compression, bits)));
+            let pcm = decode_aiff(&file, usize::MAX).unwrap();
+            assert_eq!(pcm.sample_rate, 96_000);
+            assert_close(&pcm.channels, &signal(), tolerance);
+        }
+    }
+
+    #[test]
+    fn reads_odd_sample_rates() {
+        for sample_rate in [1, 8_000, 22_050, 44_100, 192_000, 2_822_400] {
+            let file = encode_aiff(sample_rate, &signal(), None);
+            assert_eq!(decode_aiff(&file, usize::MAX).unwrap().sample_rate, sample_rate);
+        }
+    }
+
+    #[test]
+    fn truncated_files_keep_the_frames_they_hold() {
+        let file = encode_aiff(44_100, &signal(), None);
+        // Half a frame more than 100 frames.
+        let pcm = decode_aiff(&file[..file.len() - 900 * 4 + 2], usize::MAX).unwrap();
+        assert_eq!(pcm.channels[0].len(), 100);
+        assert_close(
+            &pcm.channels,
+            &[signal()[0][..100].to_vec(), signal()[1][..100].to_vec()],
+            1.0 / 32_768.0,
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_cant_decode() {
+        assert_eq!(decode_aiff(b"RIFF\0\0\0\0WAVE", usize::MAX), Err(AiffError::NotAiff));
+
+        let compressed = encode_aiff(44_100, &signal(), Some((*b"ima4", 16)));
+        assert_eq!(
+            decode_aiff(&compressed, usize::MAX),
+            Err(AiffError::UnsupportedFormat(*b"ima4", 16))
+        );
+
+        let file = encode_aiff(44_100, &signal(), None);
+        assert_eq!(decode_aiff(&file, 1_000 * 2 * 4 - 1), Err(AiffError::TooLarge(7_999)));
+        assert!(decode_aiff(&file, 1_000 * 2 * 4).is_ok());
+
+        let mut no_channels = file.clone();
+        no_channels[20..22].copy_from_slice(&0i16.to_be_bytes());
+        assert_eq!(decode_aiff(&no_channels, usize::MAX), Err(AiffError::NoChannels));
+
+        let mut no_sample_rate = file;
+        no_sample_rate[28..38].fill(0);
+        assert_eq!(decode_aiff(&no_sample_rate, usize::MAX), Err(AiffError::InvalidSampleRate));
+    }
+}
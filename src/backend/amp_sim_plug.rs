@@ -0,0 +1,464 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{
+    Gradient, ParamF32, ParamF32Handle, Unit, DEFAULT_DB_GRADIENT, DEFAULT_SMOOTH_SECS,
+};
+use meadowlark_core_types::time::SampleRate;
+use pcm_loader::PcmRAM;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use super::dsp::{db_to_gain, Biquad, BiquadCoeffs};
+
+pub static AMP_SIM_PLUG_RDN: &str = "app.meadowlark.amp-sim";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// Cabinet impulse responses are truncated to this length. Almost all of the
+/// character of a speaker cabinet lives in the first few milliseconds, and
+/// direct convolution gets expensive quickly.
+pub const MAX_IR_FRAMES: usize = 1024;
+
+/// The range of the "gain" knob in decibels.
+const MAX_PRE_GAIN_DB: f32 = 48.0;
+
+/// Removes low end before the first stage so high gain settings stay tight.
+const INPUT_HPF_FREQ: f32 = 90.0;
+/// Tames the fizz created by the first stage before it hits the second stage.
+const INTERSTAGE_LPF_FREQ: f32 = 6_500.0;
+/// Biasing the first stage creates even harmonics like a real triode.
+const STAGE_1_BIAS: f32 = 0.2;
+
+const BASS_FREQ: f32 = 120.0;
+const MID_FREQ: f32 = 700.0;
+const TREBLE_FREQ: f32 = 3_200.0;
+
+pub struct AmpSimPlugFactory;
+
+impl PluginFactory for AmpSimPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: AMP_SIM_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Amp Sim".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(AmpSimPlugMainThread::new()))
+    }
+}
+
+pub struct AmpSimPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    coll_handle: basedrop::Handle,
+}
+
+impl AmpSimPlugHandle {
+    /// Use the given impulse response for the cabinet.
+    ///
+    /// The channels are summed to mono and the response is truncated to
+    /// `MAX_IR_FRAMES` frames.
+    pub fn set_cabinet_ir(&mut self, pcm: &PcmRAM) {
+        let frames = (pcm.len_frames() as usize).min(MAX_IR_FRAMES);
+
+        let mut l = vec![0.0; frames];
+        let mut r = vec![0.0; frames];
+        pcm.fill_stereo_f32(0, &mut l, &mut r);
+
+        let mut taps: Vec<f32> = l.iter().zip(r.iter()).map(|(l, r)| (l + r) * 0.5).collect();
+
+        // Fade out the truncated tail so the cut doesn't cause a click.
+        let fade_len = (frames / 8).max(1);
+        for (i, tap) in taps.iter_mut().rev().take(fade_len).enumerate() {
+            *tap *= i as f32 / fade_len as f32;
+        }
+
+        // Normalize the IR to unity gain at its loudest frequency (approximated
+        // with the sum of absolute values) so switching cabinets doesn't cause
+        // huge jumps in volume.
+        let norm: f32 = taps.iter().map(|t| t.abs()).sum();
+        if norm > f32::EPSILON {
+            taps.iter_mut().for_each(|t| *t /= norm);
+        }
+
+        self.send(ProcessMsg::SetCabinetIR(Owned::new(&self.coll_handle, taps)));
+    }
+
+    pub fn clear_cabinet_ir(&mut self) {
+        self.send(ProcessMsg::ClearCabinetIR);
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Amp sim plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetCabinetIR(Owned<Vec<f32>>),
+    ClearCabinetIR,
+}
+
+struct ParamsHandle {
+    pub gain: ParamF32Handle,
+    pub bass: ParamF32Handle,
+    pub mid: ParamF32Handle,
+    pub treble: ParamF32Handle,
+    pub master: ParamF32Handle,
+}
+
+struct Params {
+    pub gain: ParamF32,
+    pub bass: ParamF32,
+    pub mid: ParamF32,
+    pub treble: ParamF32,
+    pub master: ParamF32,
+}
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let (gain, gain_handle) = ParamF32::from_value(
+            0.5,
+            0.5,
+            0.0,
+            1.0,
+            Gradient::Linear,
+            Unit::Generic,
+            DEFAULT_SMOOTH_SECS,
+            sample_rate,
+            max_frames,
+        );
+
+        let tone = |value| {
+            ParamF32::from_value(
+                value,
+                0.0,
+                -12.0,
+                12.0,
+                Gradient::Linear,
+                Unit::Generic,
+                0.0,
+                sample_rate,
+                max_frames,
+            )
+        };
+        let (bass, bass_handle) = tone(0.0);
+        let (mid, mid_handle) = tone(0.0);
+        let (treble, treble_handle) = tone(0.0);
+
+        let (master, master_handle) = ParamF32::from_value(
+            -12.0,
+            -12.0,
+            -90.0,
+            6.0,
+            DEFAULT_DB_GRADIENT,
+            Unit::Decibels,
+            DEFAULT_SMOOTH_SECS,
+            sample_rate,
+            max_frames,
+        );
+
+        (
+            Params { gain, bass, mid, treble, master },
+            ParamsHandle {
+                gain: gain_handle,
+                bass: bass_handle,
+                mid: mid_handle,
+                treble: treble_handle,
+                master: master_handle,
+            },
+        )
+    }
+}
+
+pub struct AmpSimPlugMainThread {
+    params: ParamsHandle,
+}
+
+impl AmpSimPlugMainThread {
+    fn new() -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle }
+    }
+}
+
+impl PluginMainThread for AmpSimPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let sr = sample_rate.0 as f32;
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(AmpSimPlugAudioThread {
+                params,
+                from_handle_rx,
+                sample_rate: sr,
+                input_hpf: Biquad::new(BiquadCoeffs::highpass(sr, INPUT_HPF_FREQ, 0.707)),
+                interstage_lpf: Biquad::new(BiquadCoeffs::lowpass(sr, INTERSTAGE_LPF_FREQ, 0.707)),
+                bass: Biquad::default(),
+                mid: Biquad::default(),
+                treble: Biquad::default(),
+                tone_settings: [f32::NAN; 3],
+                cabinet_ir: None,
+                ir_history: Owned::new(coll_handle, vec![0.0; MAX_IR_FRAMES * 2]),
+                ir_pos: 0,
+                mono_buf: Owned::new(coll_handle, vec![0.0; max_frames as usize]),
+            }),
+            internal_handle: Some(Box::new(AmpSimPlugHandle {
+                to_audio_thread_tx,
+                coll_handle: coll_handle.clone(),
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        5
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        let (name, min, max, default) = match param_index {
+            0 => ("gain", 0.0, 1.0, 0.5),
+            1 => ("bass", -12.0, 12.0, 0.0),
+            2 => ("mid", -12.0, 12.0, 0.0),
+            3 => ("treble", -12.0, 12.0, 0.0),
+            4 => ("master", -90.0, 6.0, -12.0),
+            _ => return Err(()),
+        };
+
+        Ok(ext::params::ParamInfo::new(
+            ParamID(param_index as u32),
+            ParamInfoFlags::default_float(),
+            name.into(),
+            String::new(),
+            min,
+            max,
+            default,
+        ))
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => Ok(f64::from(self.params.gain.value())),
+            ParamID(1) => Ok(f64::from(self.params.bass.value())),
+            ParamID(2) => Ok(f64::from(self.params.mid.value())),
+            ParamID(3) => Ok(f64::from(self.params.treble.value())),
+            ParamID(4) => Ok(f64::from(self.params.master.value())),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(0) => Ok(format!("{:.1}", value * 10.0)),
+            ParamID(1) | ParamID(2) | ParamID(3) | ParamID(4) => Ok(format!("{:.2} dB", value)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => text.parse::<f64>().map(|v| v / 10.0).map_err(|_| ()),
+            ParamID(1) | ParamID(2) | ParamID(3) | ParamID(4) => text.parse().map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct AmpSimPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    sample_rate: f32,
+
+    input_hpf: Biquad,
+    interstage_lpf: Biquad,
+
+    bass: Biquad,
+    mid: Biquad,
+    treble: Biquad,
+    /// The bass, mid, and treble settings the tone stack coefficients were last
+    /// calculated with.
+    tone_settings: [f32; 3],
+
+    cabinet_ir: Option<Owned<Vec<f32>>>,
+    /// The input history for the cabinet convolution. This is twice the length
+    /// of the IR so the convolution can always read a contiguous slice.
+    ir_history: Owned<Vec<f32>>,
+    ir_pos: usize,
+
+    mono_buf: Owned<Vec<f32>>,
+}
+
+impl AmpSimPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                let value = param_value.value() as f32;
+                match param_value.param_id() {
+                    0 => self.params.gain.set_value(value),
+                    1 => self.params.bass.set_value(value),
+                    2 => self.params.mid.set_value(value),
+                    3 => self.params.treble.set_value(value),
+                    4 => self.params.master.set_value(value),
+                    _ => {}
+                }
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetCabinetIR(ir) => {
+                    // The old IR is dropped by the collector, not in this thread.
+                    self.cabinet_ir = Some(ir);
+                }
+                ProcessMsg::ClearCabinetIR => {
+                    self.cabinet_ir = None;
+                }
+            }
+        }
+    }
+
+    fn update_tone_stack(&mut self, frames: usize) {
+        let settings = [
+            self.params.bass.smoothed(frames)[0],
+            self.params.mid.smoothed(frames)[0],
+            self.params.treble.smoothed(frames)[0],
+        ];
+
+        if settings != self.tone_settings {
+            self.tone_settings = settings;
+
+            let sr = self.sample_rate;
+            self.bass.coeffs = BiquadCoeffs::low_shelf(sr, BASS_FREQ, 0.707, settings[0]);
+            self.mid.coeffs = BiquadCoeffs::peak(sr, MID_FREQ, 0.8, settings[1]);
+            self.treble.coeffs = BiquadCoeffs::high_shelf(sr, TREBLE_FREQ, 0.707, settings[2]);
+        }
+    }
+
+    #[inline]
+    fn convolve(&mut self, x: f32, ir: &[f32]) -> f32 {
+        let len = ir.len();
+
+        // Write the sample twice so `history[pos..pos + len]` is always the
+        // most recent `len` samples in order from newest to oldest.
+        self.ir_pos = if self.ir_pos == 0 { len - 1 } else { self.ir_pos - 1 };
+        self.ir_history[self.ir_pos] = x;
+        self.ir_history[self.ir_pos + len] = x;
+
+        let history = &self.ir_history[self.ir_pos..self.ir_pos + len];
+        history.iter().zip(ir.iter()).map(|(x, h)| x * h).sum()
+    }
+}
+
+impl PluginAudioThread for AmpSimPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.input_hpf.reset();
+        self.interstage_lpf.reset();
+        self.bass.reset();
+        self.mid.reset();
+        self.treble.reset();
+        self.ir_history.fill(0.0);
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+
+        let frames = proc_info.frames;
+
+        self.update_tone_stack(frames);
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let gain = self.params.gain.smoothed(frames);
+        let master = self.params.master.smoothed(frames);
+
+        // Guitars are mono, so the amp is too.
+        for i in 0..frames {
+            let x = self.input_hpf.process((in_l[i] + in_r[i]) * 0.5);
+
+            // First stage (biased for asymmetric clipping).
+            let pre_gain = db_to_gain(gain[i] * MAX_PRE_GAIN_DB * 0.5);
+            let x = (x * pre_gain + STAGE_1_BIAS).tanh() - STAGE_1_BIAS.tanh();
+            let x = self.interstage_lpf.process(x);
+
+            // Second stage.
+            let x = (x * pre_gain).tanh();
+
+            // Tone stack.
+            let x = self.bass.process(x);
+            let x = self.mid.process(x);
+            let x = self.treble.process(x);
+
+            self.mono_buf[i] = x * db_to_gain(master[i]);
+        }
+
+        if let Some(ir) = self.cabinet_ir.take() {
+            if !ir.is_empty() {
+                for i in 0..frames {
+                    let x = self.mono_buf[i];
+                    self.mono_buf[i] = self.convolve(x, &ir);
+                }
+            }
+            self.cabinet_ir = Some(ir);
+        }
+
+        out_l[0..frames].copy_from_slice(&self.mono_buf[0..frames]);
+        out_r[0..frames].copy_from_slice(&self.mono_buf[0..frames]);
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
@@ -0,0 +1,146 @@
+//! A channel for streaming audio from a plugin's audio thread to the UI for
+//! analysis (i.e. drawing a spectrum).
+//!
+//! The audio thread only copies samples into a ring buffer. All of the heavy
+//! lifting (FFTs, smoothing) happens on the main thread.
+
+use rtrb::{Consumer, Producer, RingBuffer};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use super::dsp::MIN_DB;
+
+pub const DEFAULT_ANALYSIS_FFT_SIZE: usize = 4096;
+
+/// Create a new analysis channel which can hold `capacity` samples.
+pub fn analysis_channel(capacity: usize) -> (AnalysisTx, AnalysisRx) {
+    let (tx, rx) = RingBuffer::<f32>::new(capacity);
+    (AnalysisTx { tx }, AnalysisRx { rx })
+}
+
+/// The audio thread side of an analysis channel.
+pub struct AnalysisTx {
+    tx: Producer<f32>,
+}
+
+impl AnalysisTx {
+    /// Push samples into the channel. Any samples that don't fit are dropped,
+    /// since it is fine for the analysis to miss some audio if the UI falls behind.
+    #[inline]
+    pub fn push(&mut self, samples: &[f32]) {
+        let n = samples.len().min(self.tx.slots());
+        if n == 0 {
+            return;
+        }
+
+        if let Ok(mut chunk) = self.tx.write_chunk(n) {
+            let (a, b) = chunk.as_mut_slices();
+            a.copy_from_slice(&samples[0..a.len()]);
+            b.copy_from_slice(&samples[a.len()..n]);
+            chunk.commit_all();
+        }
+    }
+}
+
+/// The main thread side of an analysis channel.
+pub struct AnalysisRx {
+    rx: Consumer<f32>,
+}
+
+/// Computes a smoothed magnitude spectrum from an `AnalysisRx`.
+pub struct SpectrumAnalyzer {
+    rx: AnalysisRx,
+
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+
+    /// The most recent `fft_size` samples, used as a ring buffer.
+    history: Vec<f32>,
+    history_pos: usize,
+    scratch: Vec<Complex<f32>>,
+
+    /// The magnitude of each bin from `0` to `fft_size / 2` in dB.
+    spectrum_db: Vec<f32>,
+
+    sample_rate: f32,
+    /// How much the spectrum is smoothed over time in the range `[0.0, 1.0)`.
+    smoothing: f32,
+}
+
+impl SpectrumAnalyzer {
+    pub fn new(rx: AnalysisRx, sample_rate: f32, fft_size: usize) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+
+        // Scale the window so a full-scale sine reads as 0dB.
+        let window: Vec<f32> = (0..fft_size)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / fft_size as f32).cos())
+            .collect();
+        let window_sum: f32 = window.iter().sum();
+        let window = window.iter().map(|w| w * 2.0 / window_sum).collect();
+
+        Self {
+            rx,
+            fft,
+            window,
+            history: vec![0.0; fft_size],
+            history_pos: 0,
+            scratch: vec![Complex::new(0.0, 0.0); fft_size],
+            spectrum_db: vec![MIN_DB; fft_size / 2 + 1],
+            sample_rate,
+            smoothing: 0.7,
+        }
+    }
+
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing.clamp(0.0, 0.99);
+    }
+
+    /// Pull in any new samples and update the spectrum. Returns `false` if there
+    /// were no new samples.
+    pub fn update(&mut self) -> bool {
+        let available = self.rx.rx.slots();
+        if available == 0 {
+            return false;
+        }
+
+        let n = self.history.len();
+        let chunk = match self.rx.rx.read_chunk(available) {
+            Ok(chunk) => chunk,
+            Err(_) => return false,
+        };
+        let (a, b) = chunk.as_slices();
+        for s in a.iter().chain(b.iter()).skip(available.saturating_sub(n)) {
+            self.history[self.history_pos] = *s;
+            self.history_pos = (self.history_pos + 1) % n;
+        }
+        chunk.commit_all();
+
+        // The oldest sample is at `history_pos`.
+        let (newer, older) = self.history.split_at(self.history_pos);
+        for ((c, x), w) in
+            self.scratch.iter_mut().zip(older.iter().chain(newer.iter())).zip(self.window.iter())
+        {
+            *c = Complex::new(x * w, 0.0);
+        }
+        self.fft.process(&mut self.scratch);
+
+        for (db, c) in self.spectrum_db.iter_mut().zip(self.scratch.iter()) {
+            let new_db = (20.0 * c.norm().max(1e-9).log10()).max(MIN_DB);
+            *db = if new_db > *db { new_db } else { new_db + self.smoothing * (*db - new_db) };
+        }
+
+        true
+    }
+
+    /// The magnitude of each bin from `0` to `fft_size / 2` in dB.
+    pub fn spectrum_db(&self) -> &[f32] {
+        &self.spectrum_db
+    }
+
+    /// The center frequency of the given bin in Hz.
+    pub fn bin_freq(&self, bin: usize) -> f32 {
+        bin as f32 * self.sample_rate / self.history.len() as f32
+    }
+}
@@ -0,0 +1,440 @@
+use basedrop::{Owned, Shared};
+use rtrb::{Consumer, Producer, RingBuffer};
+use serde::{Deserialize, Serialize};
+
+use super::tempo_bus::TempoBus;
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// How far a Newton step has to get before solving a bezier curve stops.
+const BEZIER_EPSILON: f64 = 1.0e-6;
+/// The most Newton steps taken to solve a bezier curve, before falling back
+/// to bisection.
+const BEZIER_NEWTON_STEPS: usize = 6;
+const BEZIER_BISECTION_STEPS: usize = 24;
+
+/// The shape of the ramp from a point of an automation lane to the next one.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AutomationCurve {
+    #[default]
+    Linear,
+    /// Bends the ramp by an amount in `[-1.0, 1.0]`. Positive amounts start
+    /// slowly and end quickly, negative ones the other way around.
+    Exponential(f32),
+    /// Starts and ends slowly.
+    SCurve,
+    /// Holds the value of the point until the next point.
+    Step,
+    /// A cubic bezier curve. The handles are the two control points, as
+    /// fractions of the time and of the change in value from this point to
+    /// the next one. The times are kept in `[0.0, 1.0]`, so the curve never
+    /// goes back in time.
+    Bezier { handle_a: (f32, f32), handle_b: (f32, f32) },
+}
+
+impl AutomationCurve {
+    /// Bend a position from 0.0 to 1.0 along the ramp to how far the value
+    /// has moved by then.
+    pub fn apply(&self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            AutomationCurve::Linear => t,
+            AutomationCurve::Exponential(amount) => {
+                // Six gives a bend of about 400:1 between the ends at full
+                // amount.
+                let k = f64::from(*amount) * 6.0;
+                if k.abs() < 1.0e-3 {
+                    t
+                } else {
+                    ((k * t).exp() - 1.0) / (k.exp() - 1.0)
+                }
+            }
+            AutomationCurve::SCurve => t * t * (3.0 - 2.0 * t),
+            AutomationCurve::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+            AutomationCurve::Bezier { handle_a, handle_b } => {
+                let (ax, ay) = (f64::from(handle_a.0), f64::from(handle_a.1));
+                let (bx, by) = (f64::from(handle_b.0), f64::from(handle_b.1));
+                let s = solve_bezier(ax, bx, t);
+                bezier(ay, by, s)
+            }
+        }
+    }
+
+    fn clamped(&self) -> Self {
+        let clamp_handle = |(x, y): (f32, f32)| (x.clamp(0.0, 1.0), y);
+        match *self {
+            AutomationCurve::Exponential(amount) => {
+                AutomationCurve::Exponential(amount.clamp(-1.0, 1.0))
+            }
+            AutomationCurve::Bezier { handle_a, handle_b } => AutomationCurve::Bezier {
+                handle_a: clamp_handle(handle_a),
+                handle_b: clamp_handle(handle_b),
+            },
+            curve => curve,
+        }
+    }
+}
+
+/// One coordinate of a cubic bezier curve from 0.0 to 1.0 with the control
+/// points `a` and `b`, at the curve parameter `s`.
+fn bezier(a: f64, b: f64, s: f64) -> f64 {
+    let r = 1.0 - s;
+    3.0 * r * r * s * a + 3.0 * r * s * s * b + s * s * s
+}
+
+/// The curve parameter at which the time coordinate of a bezier curve with
+/// the control points `a` and `b` (both in `[0.0, 1.0]`) is `t`.
+fn solve_bezier(a: f64, b: f64, t: f64) -> f64 {
+    let mut s = t;
+    for _ in 0..BEZIER_NEWTON_STEPS {
+        let error = bezier(a, b, s) - t;
+        if error.abs() < BEZIER_EPSILON {
+            return s;
+        }
+        let r = 1.0 - s;
+        let slope = 3.0 * r * r * a + 6.0 * r * s * (b - a) + 3.0 * s * s * (1.0 - b);
+        if slope.abs() < BEZIER_EPSILON {
+            break;
+        }
+        s = (s - error / slope).clamp(0.0, 1.0);
+    }
+
+    // The time coordinate only goes up, so bisection always finds it.
+    let (mut low, mut high) = (0.0, 1.0);
+    s = t;
+    for _ in 0..BEZIER_BISECTION_STEPS {
+        if bezier(a, b, s) < t {
+            low = s;
+        } else {
+            high = s;
+        }
+        s = (low + high) * 0.5;
+    }
+    s
+}
+
+/// A point of an automation lane. The value is normalized to `[0.0, 1.0]`, so
+/// lanes work the same way for every parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AutomationPoint {
+    /// The position in beats from the start of the project.
+    pub beats: f64,
+    pub value: f32,
+    /// The shape of the ramp from this point to the next one.
+    pub curve: AutomationCurve,
+}
+
+/// The value at a position between two points, along the curve of the first.
+fn ramp(a: &AutomationPoint, b: &AutomationPoint, beats: f64) -> f32 {
+    let t = (beats - a.beats) / (b.beats - a.beats);
+    a.value + (b.value - a.value) * a.curve.apply(t) as f32
+}
+
+/// The changes of a parameter over time. The value ramps from each point to
+/// the next one along the curve of the point, and holds the value of the
+/// first and last point before and after them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AutomationLane {
+    /// Sorted by their position. Points at the same position make a jump.
+    points: Vec<AutomationPoint>,
+}
+
+impl AutomationLane {
+    pub fn new() -> Self {
+        Self { points: Vec::new() }
+    }
+
+    pub fn points(&self) -> &[AutomationPoint] {
+        &self.points
+    }
+
+    /// Add a point, after any other points at the same position. Returns its
+    /// index.
+    pub fn add_point(&mut self, point: AutomationPoint) -> usize {
+        let point = clamp_point(point);
+        let index = self.points.partition_point(|p| p.beats <= point.beats);
+        self.points.insert(index, point);
+        index
+    }
+
+    /// Move a point to another position and value. Returns its new index, or
+    /// `None` if there is no such point.
+    pub fn move_point(&mut self, index: usize, to: AutomationPoint) -> Option<usize> {
+        if index >= self.points.len() {
+            return None;
+        }
+        self.points.remove(index);
+        Some(self.add_point(to))
+    }
+
+    pub fn remove_point(&mut self, index: usize) -> Option<AutomationPoint> {
+        (index < self.points.len()).then(|| self.points.remove(index))
+    }
+
+    /// Change the shape of the ramp from a point to the next one. Returns
+    /// false if there is no such point.
+    pub fn set_curve(&mut self, index: usize, curve: AutomationCurve) -> bool {
+        match self.points.get_mut(index) {
+            Some(point) => {
+                point.curve = curve.clamped();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The value at a position, or `None` if the lane has no points.
+    pub fn value_at(&self, beats: f64) -> Option<f32> {
+        let first = self.points.first()?;
+        let next = self.points.partition_point(|p| p.beats <= beats);
+        if next == 0 {
+            return Some(first.value);
+        }
+        let a = self.points[next - 1];
+        Some(match self.points.get(next) {
+            Some(b) => ramp(&a, b, beats),
+            None => a.value,
+        })
+    }
+
+    /// Write the value of every frame of a block that starts at `start_beat`
+    /// to `out`. Does nothing if the lane has no points.
+    ///
+    /// This doesn't allocate, so it can be used in the audio thread.
+    pub fn render(&self, start_beat: f64, beats_per_frame: f64, out: &mut [f32]) {
+        if self.points.is_empty() {
+            return;
+        }
+
+        // Walk along the points instead of searching for every frame.
+        let mut next = self.points.partition_point(|p| p.beats <= start_beat);
+        for (frame, out) in out.iter_mut().enumerate() {
+            let beats = start_beat + frame as f64 * beats_per_frame;
+            while next < self.points.len() && self.points[next].beats <= beats {
+                next += 1;
+            }
+            *out = match (next.checked_sub(1).map(|i| self.points[i]), self.points.get(next)) {
+                (Some(a), Some(b)) => ramp(&a, b, beats),
+                (Some(a), None) => a.value,
+                (None, Some(b)) => b.value,
+                (None, None) => unreachable!(),
+            };
+        }
+    }
+}
+
+fn clamp_point(point: AutomationPoint) -> AutomationPoint {
+    AutomationPoint {
+        beats: point.beats.max(0.0),
+        value: point.value.clamp(0.0, 1.0),
+        curve: point.curve.clamped(),
+    }
+}
+
+/// Edits the automation lanes of a node from the UI thread. Each edit sends
+/// a copy of the edited lane to the `AutomationReader` in the audio thread.
+pub struct AutomationHandle {
+    lanes: Vec<AutomationLane>,
+    to_audio_thread_tx: Producer<LaneMsg>,
+    coll_handle: basedrop::Handle,
+}
+
+/// Plays the automation lanes of a node in the audio thread, in time with
+/// the transport.
+pub struct AutomationReader {
+    from_handle_rx: Owned<Consumer<LaneMsg>>,
+    tempo_bus: TempoBus,
+    lanes: Vec<Option<Shared<AutomationLane>>>,
+}
+
+struct LaneMsg {
+    index: usize,
+    lane: Option<Shared<AutomationLane>>,
+}
+
+/// Create the two ends of the automation of a node with room for `num_lanes`
+/// lanes, e.g. one for each parameter.
+pub fn automation(
+    num_lanes: usize,
+    tempo_bus: TempoBus,
+    coll_handle: &basedrop::Handle,
+) -> (AutomationHandle, AutomationReader) {
+    // Room for every lane to be replaced at once, e.g. when a project is
+    // loaded.
+    let (to_audio_thread_tx, from_handle_rx) =
+        RingBuffer::<LaneMsg>::new(MSG_BUFFER_SIZE.max(num_lanes));
+    (
+        AutomationHandle {
+            lanes: vec![AutomationLane::new(); num_lanes],
+            to_audio_thread_tx,
+            coll_handle: coll_handle.clone(),
+        },
+        AutomationReader {
+            from_handle_rx: Owned::new(coll_handle, from_handle_rx),
+            tempo_bus,
+            lanes: vec![None; num_lanes],
+        },
+    )
+}
+
+impl AutomationHandle {
+    pub fn lane(&self, lane: usize) -> Option<&AutomationLane> {
+        self.lanes.get(lane)
+    }
+
+    /// Replace all of the points of a lane, e.g. when a project is loaded.
+    pub fn set_lane(&mut self, lane: usize, points: AutomationLane) {
+        if let Some(l) = self.lanes.get_mut(lane) {
+            *l = points;
+            self.send(lane);
+        }
+    }
+
+    /// Returns the index of the new point.
+    pub fn add_point(&mut self, lane: usize, point: AutomationPoint) -> Option<usize> {
+        let index = self.lanes.get_mut(lane)?.add_point(point);
+        self.send(lane);
+        Some(index)
+    }
+
+    /// Returns the new index of the point.
+    pub fn move_point(&mut self, lane: usize, index: usize, to: AutomationPoint) -> Option<usize> {
+        let index = self.lanes.get_mut(lane)?.move_point(index, to)?;
+        self.send(lane);
+        Some(index)
+    }
+
+    pub fn remove_point(&mut self, lane: usize, index: usize) -> Option<AutomationPoint> {
+        let point = self.lanes.get_mut(lane)?.remove_point(index)?;
+        self.send(lane);
+        Some(point)
+    }
+
+    pub fn set_curve(&mut self, lane: usize, index: usize, curve: AutomationCurve) -> bool {
+        if !self.lanes.get_mut(lane).map_or(false, |l| l.set_curve(index, curve)) {
+            return false;
+        }
+        self.send(lane);
+        true
+    }
+
+    fn send(&mut self, index: usize) {
+        // An empty lane leaves its parameter alone.
+        let lane = &self.lanes[index];
+        let lane = (!lane.points.is_empty()).then(|| Shared::new(&self.coll_handle, lane.clone()));
+        if let Err(e) = self.to_audio_thread_tx.push(LaneMsg { index, lane }) {
+            log::error!("Automation failed to send message: {}", e);
+        }
+    }
+}
+
+impl AutomationReader {
+    /// Take in the edits made since the last block. Call this at the start of
+    /// every block.
+    pub fn poll(&mut self) {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            if let Some(lane) = self.lanes.get_mut(msg.index) {
+                *lane = msg.lane;
+            }
+        }
+    }
+
+    /// Write the value of a lane for every frame of the block to `out`.
+    /// Returns false (and leaves `out` alone) if the lane has no points or
+    /// the transport is stopped, in which case the parameter keeps the value
+    /// it was given last.
+    pub fn render(
+        &self,
+        lane: usize,
+        playhead_frame: u64,
+        playing: bool,
+        sample_rate: f64,
+        out: &mut [f32],
+    ) -> bool {
+        let lane = match self.lanes.get(lane) {
+            Some(Some(lane)) if playing => lane,
+            _ => return false,
+        };
+        // The tempo map is followed from the start of the project, so the
+        // lane lines up with the timeline after tempo changes.
+        let (start_beat, beats_per_frame) =
+            self.tempo_bus.tempo().block_beats(playhead_frame, out.len(), sample_rate);
+        lane.render(start_beat, beats_per_frame, out);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(curve: AutomationCurve, t: f64, expected: f64) {
+        let value = curve.apply(t);
+        assert!(
+            (value - expected).abs() < 1.0e-4,
+            "{:?} at {} is {}, not {}",
+            curve,
+            t,
+            value,
+            expected
+        );
+    }
+
+    #[test]
+    fn curves_start_and_end_at_the_points() {
+        let curves = [
+            AutomationCurve::Linear,
+            AutomationCurve::Exponential(0.5),
+            AutomationCurve::Exponential(-1.0),
+            AutomationCurve::SCurve,
+            AutomationCurve::Step,
+            AutomationCurve::Bezier { handle_a: (0.0, 1.0), handle_b: (0.0, 1.0) },
+        ];
+        for curve in curves {
+            assert_close(curve, 0.0, 0.0);
+            assert_close(curve, 1.0, 1.0);
+        }
+    }
+
+    #[test]
+    fn exponential_bends_towards_its_amount() {
+        // (e^(3 * 0.5) - 1) / (e^3 - 1)
+        assert_close(AutomationCurve::Exponential(0.5), 0.5, 0.182426);
+        assert_close(AutomationCurve::Exponential(-0.5), 0.5, 0.817574);
+        assert_close(AutomationCurve::Exponential(0.0), 0.5, 0.5);
+    }
+
+    #[test]
+    fn s_curve_is_symmetric() {
+        assert_close(AutomationCurve::SCurve, 0.5, 0.5);
+        assert_close(AutomationCurve::SCurve, 0.25, 0.15625);
+    }
+
+    #[test]
+    fn step_holds_until_the_next_point() {
+        assert_close(AutomationCurve::Step, 0.5, 0.0);
+        assert_close(AutomationCurve::Step, 0.999, 0.0);
+    }
+
+    #[test]
+    fn bezier_follows_its_handles() {
+        // Handles on the diagonal make a straight line.
+        let straight = AutomationCurve::Bezier {
+            handle_a: (1.0 / 3.0, 1.0 / 3.0),
+            handle_b: (2.0 / 3.0, 2.0 / 3.0),
+        };
+        assert_close(straight, 0.5, 0.5);
+        assert_close(straight, 0.2, 0.2);
+
+        // Both handles in the top left corner: x = s^3 and y = 1 - (1 - s)^3.
+        let ease_out = AutomationCurve::Bezier { handle_a: (0.0, 1.0), handle_b: (0.0, 1.0) };
+        let s = 0.5f64.cbrt();
+        assert_close(ease_out, 0.5, 1.0 - (1.0 - s).powi(3));
+    }
+}
@@ -0,0 +1,274 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::{Cookie, EventHeader, ParamValueEvent};
+use dropseed::plugin::{
+    buffer::EventBuffer, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use super::automation::{automation, AutomationHandle, AutomationLane, AutomationReader};
+use super::macro_knob::MacroMapping;
+use super::tempo_bus::TempoBus;
+
+pub static AUTOMATION_PLUG_RDN: &str = "app.meadowlark.automation";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// The most parameters of a plugin that can be automated.
+pub const MAX_AUTOMATED_PARAMS: usize = 32;
+
+/// How many frames apart the values of a lane are sent while they change.
+pub const AUTOMATION_INTERVAL: usize = 64;
+
+/// A parameter of a plugin that follows an automation lane. Like everywhere
+/// else, the values of parameters are normalized.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutomatedParam {
+    pub param_id: u32,
+    /// The values of the parameter, or of the macro knob it is mapped to.
+    pub lane: AutomationLane,
+    /// How the parameter follows the knob, if the lane is of a macro knob.
+    pub mapping: Option<MacroMapping>,
+}
+
+/// The parameter a lane of the reader moves, without its points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ParamTarget {
+    param_id: u32,
+    mapping: Option<MacroMapping>,
+}
+
+impl ParamTarget {
+    /// The value of the parameter at a value of the lane.
+    fn value(&self, lane_value: f32) -> f64 {
+        match &self.mapping {
+            Some(mapping) => mapping.value(f64::from(lane_value)),
+            None => f64::from(lane_value),
+        }
+    }
+}
+
+/// Plays automation lanes in time with the transport, and sends their values
+/// as parameter events out of its automation port to the plugin whose
+/// parameters they are. Each automated plugin has one of these.
+pub struct AutomationPlugFactory {
+    tempo_bus: TempoBus,
+}
+
+impl AutomationPlugFactory {
+    pub fn new(tempo_bus: TempoBus) -> Self {
+        Self { tempo_bus }
+    }
+}
+
+impl PluginFactory for AutomationPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: AUTOMATION_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Automation".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(AutomationPlugMainThread { tempo_bus: self.tempo_bus.clone() }))
+    }
+}
+
+pub struct AutomationPlugHandle {
+    automation: AutomationHandle,
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    coll_handle: basedrop::Handle,
+    /// The parameters that were sent last, so that only changes are sent.
+    params: Vec<AutomatedParam>,
+}
+
+impl AutomationPlugHandle {
+    /// Replace the automated parameters. Parameters past
+    /// `MAX_AUTOMATED_PARAMS` are left alone.
+    pub fn set_params(&mut self, mut params: Vec<AutomatedParam>) {
+        params.truncate(MAX_AUTOMATED_PARAMS);
+        if params == self.params {
+            return;
+        }
+
+        for i in 0..MAX_AUTOMATED_PARAMS {
+            let lane = params.get(i).map(|param| &param.lane);
+            if lane != self.params.get(i).map(|param| &param.lane) {
+                self.automation.set_lane(i, lane.cloned().unwrap_or_default());
+            }
+        }
+
+        let targets: Vec<ParamTarget> = params
+            .iter()
+            .map(|param| ParamTarget { param_id: param.param_id, mapping: param.mapping })
+            .collect();
+        let targets = Shared::new(&self.coll_handle, targets);
+        match self.to_audio_thread_tx.push(ProcessMsg::SetTargets(targets)) {
+            Ok(()) => self.params = params,
+            Err(e) => log::error!("Automation plugin failed to send message: {}", e),
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetTargets(Shared<Vec<ParamTarget>>),
+}
+
+pub struct AutomationPlugMainThread {
+    tempo_bus: TempoBus,
+}
+
+impl PluginMainThread for AutomationPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+        let (automation, reader) =
+            automation(MAX_AUTOMATED_PARAMS, self.tempo_bus.clone(), coll_handle);
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(AutomationPlugAudioThread {
+                from_handle_rx,
+                reader,
+                sample_rate,
+                targets: None,
+                values: vec![0.0; max_frames as usize],
+            }),
+            internal_handle: Some(Box::new(AutomationPlugHandle {
+                automation,
+                to_audio_thread_tx,
+                coll_handle: coll_handle.clone(),
+                params: Vec::new(),
+            })),
+        })
+    }
+
+    fn has_automation_out_port(&self) -> bool {
+        true
+    }
+}
+
+pub struct AutomationPlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+    reader: AutomationReader,
+    sample_rate: SampleRate,
+
+    targets: Option<Shared<Vec<ParamTarget>>>,
+    /// The values of a lane for every frame of the block.
+    values: Vec<f32>,
+}
+
+impl PluginAudioThread for AutomationPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {}
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        _buffers: &mut ProcBuffers,
+        _in_events: &EventBuffer,
+        out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.reader.poll();
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetTargets(targets) => self.targets = Some(targets),
+            }
+        }
+
+        let targets = match &self.targets {
+            Some(targets) => targets,
+            None => return ProcessStatus::Continue,
+        };
+        let frames = proc_info.frames.min(self.values.len());
+        let values = &mut self.values[0..frames];
+
+        // The values at the playhead are also sent when it is moved while
+        // stopped, so the parameters are where the lanes are on the timeline
+        // before playback starts.
+        let playing = proc_info.transport.is_playing() || proc_info.transport.seek_info().is_some();
+        for (lane, target) in targets.iter().enumerate() {
+            if !self.reader.render(
+                lane,
+                proc_info.transport.playhead_frame(),
+                playing,
+                self.sample_rate.0,
+                values,
+            ) {
+                continue;
+            }
+
+            // The value at the start of every block is sent, so the lane
+            // takes over again after the parameter was moved by hand.
+            let mut last = None;
+            for frame in (0..frames).step_by(AUTOMATION_INTERVAL) {
+                let value = values[frame];
+                if last == Some(value) {
+                    continue;
+                }
+                last = Some(value);
+                out_events.push(
+                    ParamValueEvent::new(
+                        EventHeader::new(frame as u32),
+                        Cookie::empty(),
+                        -1,
+                        target.param_id,
+                        -1,
+                        -1,
+                        -1,
+                        target.value(value),
+                    )
+                    .as_unknown(),
+                );
+            }
+        }
+
+        ProcessStatus::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::macro_knob::MacroCurve;
+
+    #[test]
+    fn lanes_of_parameters_are_sent_as_they_are() {
+        let target = ParamTarget { param_id: 0, mapping: None };
+        assert_eq!(target.value(0.0), 0.0);
+        assert_eq!(target.value(0.25), 0.25);
+        assert_eq!(target.value(1.0), 1.0);
+    }
+
+    #[test]
+    fn macro_lanes_follow_the_mapping_of_the_parameter() {
+        let mapping = MacroMapping { min: 1.0, max: 0.5, curve: MacroCurve::Exponential };
+        let target = ParamTarget { param_id: 0, mapping: Some(mapping) };
+        assert_eq!(target.value(0.0), 1.0);
+        assert_eq!(target.value(0.5), 0.875);
+        assert_eq!(target.value(1.0), 0.5);
+    }
+}
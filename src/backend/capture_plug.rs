@@ -0,0 +1,379 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::path::Path;
+
+use crate::util::write_wav_f32;
+
+pub static CAPTURE_PLUG_RDN: &str = "app.meadowlark.capture";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// The longest capture that can be requested. Captures are held entirely in
+/// memory, so this keeps a typo from allocating gigabytes.
+pub const MAX_CAPTURE_SECS: f64 = 600.0;
+
+/// The most input that is kept from before a capture starts, so it can be
+/// included at the start of the capture.
+pub const MAX_PRE_ROLL_SECS: f64 = 2.0;
+
+pub struct CapturePlugFactory;
+
+impl PluginFactory for CapturePlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: CAPTURE_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Capture".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(CapturePlugMainThread {}))
+    }
+}
+
+/// A block of audio recorded by the capture plugin.
+pub struct CapturedAudio {
+    buffer: CaptureBuffer,
+    sample_rate: SampleRate,
+}
+
+impl CapturedAudio {
+    pub fn left(&self) -> &[f32] {
+        &self.buffer.left[0..self.buffer.len]
+    }
+
+    pub fn right(&self) -> &[f32] {
+        &self.buffer.right[0..self.buffer.len]
+    }
+
+    pub fn len_frames(&self) -> usize {
+        self.buffer.len
+    }
+
+    /// The number of frames at the start of the capture that were recorded
+    /// before the capture was started. The capture started at this frame, so
+    /// a clip made from it should start this much earlier on the timeline.
+    ///
+    /// This can be less than was requested if the plugin hadn't been running
+    /// for long enough.
+    pub fn pre_roll_frames(&self) -> usize {
+        self.buffer.pre_roll
+    }
+
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// Write the captured audio to a 32 bit floating point stereo WAV file.
+    pub fn write_wav<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        write_wav_f32(path, self.sample_rate.0 as u32, self.left(), self.right())
+    }
+}
+
+pub struct CapturePlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    from_audio_thread_rx: Consumer<CaptureBuffer>,
+    // Holds on to buffers returned from the audio thread until they are polled.
+    finished: Option<CaptureBuffer>,
+
+    coll_handle: basedrop::Handle,
+    sample_rate: SampleRate,
+    is_capturing: bool,
+}
+
+impl CapturePlugHandle {
+    /// Start recording the input of this node. Capturing stops on its own once
+    /// `len_secs` of audio has been recorded, or when `stop_capture()` is called.
+    ///
+    /// Up to `pre_roll_secs` (at most `MAX_PRE_ROLL_SECS`) of the input from
+    /// right before the capture started is included at the start of it, so a
+    /// performance that came in slightly early isn't cut off. See
+    /// `CapturedAudio::pre_roll_frames()`.
+    ///
+    /// Any capture that is currently in progress is discarded.
+    pub fn start_capture(&mut self, len_secs: f64, pre_roll_secs: f64) {
+        let len_secs = len_secs.clamp(0.0, MAX_CAPTURE_SECS);
+        let pre_roll_secs = pre_roll_secs.clamp(0.0, MAX_PRE_ROLL_SECS);
+        let frames = (len_secs * self.sample_rate.0).round() as usize;
+        let pre_roll_frames = (pre_roll_secs * self.sample_rate.0).round() as usize;
+
+        // Allocate here so the audio thread never has to.
+        let total = frames + pre_roll_frames;
+        let buffer = CaptureBuffer {
+            left: vec![0.0; total],
+            right: vec![0.0; total],
+            len: 0,
+            pre_roll: 0,
+            end: total,
+        };
+
+        self.finished = None;
+        self.is_capturing = true;
+        self.send(ProcessMsg::Start {
+            buffer: Owned::new(&self.coll_handle, buffer),
+            pre_roll_frames,
+        });
+    }
+
+    /// Stop the current capture early. The audio recorded so far can be
+    /// retrieved with `poll_capture()`.
+    pub fn stop_capture(&mut self) {
+        self.send(ProcessMsg::Stop);
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.is_capturing
+    }
+
+    /// Returns the captured audio once a capture has finished.
+    pub fn poll_capture(&mut self) -> Option<CapturedAudio> {
+        while let Ok(buffer) = self.from_audio_thread_rx.pop() {
+            self.finished = Some(buffer);
+            self.is_capturing = false;
+        }
+
+        self.finished.take().map(|buffer| CapturedAudio { buffer, sample_rate: self.sample_rate })
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Capture plugin failed to send message: {}", e);
+        }
+    }
+}
+
+struct CaptureBuffer {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    /// The number of frames that have been recorded so far.
+    len: usize,
+    /// The number of frames at the start that were recorded before the
+    /// capture started.
+    pre_roll: usize,
+    /// The capture is finished once `len` reaches this.
+    end: usize,
+}
+
+enum ProcessMsg {
+    Start {
+        buffer: Owned<CaptureBuffer>,
+        /// The number of frames at the start of `buffer` that are meant for
+        /// the input from before the capture started.
+        pre_roll_frames: usize,
+    },
+    Stop,
+}
+
+/// Holds the most recent input, so it can be copied to the start of a capture.
+struct PreRollBuffer {
+    left: Vec<f32>,
+    right: Vec<f32>,
+    /// Where the next frame is written.
+    write_pos: usize,
+    /// The number of frames that have been written, up to the size of the buffer.
+    filled: usize,
+}
+
+impl PreRollBuffer {
+    fn new(frames: usize) -> Self {
+        Self { left: vec![0.0; frames], right: vec![0.0; frames], write_pos: 0, filled: 0 }
+    }
+
+    fn push(&mut self, left: &[f32], right: &[f32]) {
+        let size = self.left.len();
+        if size == 0 {
+            return;
+        }
+
+        // Only the end of blocks longer than the buffer can be kept.
+        let skip = left.len().saturating_sub(size);
+        let (left, right) = (&left[skip..], &right[skip..]);
+
+        let first = left.len().min(size - self.write_pos);
+        self.left[self.write_pos..self.write_pos + first].copy_from_slice(&left[..first]);
+        self.right[self.write_pos..self.write_pos + first].copy_from_slice(&right[..first]);
+
+        let rest = left.len() - first;
+        self.left[..rest].copy_from_slice(&left[first..]);
+        self.right[..rest].copy_from_slice(&right[first..]);
+
+        self.write_pos = (self.write_pos + left.len()) % size;
+        self.filled = (self.filled + left.len()).min(size);
+    }
+
+    /// Copy up to `frames` of the most recent input to the start of `left` and
+    /// `right`. Returns the number of frames that were copied.
+    fn copy_latest(&self, frames: usize, left: &mut [f32], right: &mut [f32]) -> usize {
+        let size = self.left.len();
+        let n = frames.min(self.filled).min(left.len());
+        if n == 0 {
+            return 0;
+        }
+
+        let start = (self.write_pos + size - n) % size;
+        let first = n.min(size - start);
+        left[..first].copy_from_slice(&self.left[start..start + first]);
+        right[..first].copy_from_slice(&self.right[start..start + first]);
+        left[first..n].copy_from_slice(&self.left[..n - first]);
+        right[first..n].copy_from_slice(&self.right[..n - first]);
+
+        n
+    }
+}
+
+pub struct CapturePlugMainThread {}
+
+impl PluginMainThread for CapturePlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        _max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let (to_handle_tx, from_audio_thread_rx) =
+            RingBuffer::<CaptureBuffer>::new(MSG_BUFFER_SIZE);
+        let to_handle_tx = Owned::new(coll_handle, to_handle_tx);
+
+        let pre_roll_frames = (MAX_PRE_ROLL_SECS * sample_rate.0).ceil() as usize;
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(CapturePlugAudioThread {
+                from_handle_rx,
+                to_handle_tx,
+                capture: None,
+                pre_roll: PreRollBuffer::new(pre_roll_frames),
+            }),
+            internal_handle: Some(Box::new(CapturePlugHandle {
+                to_audio_thread_tx,
+                from_audio_thread_rx,
+                finished: None,
+                coll_handle: coll_handle.clone(),
+                sample_rate,
+                is_capturing: false,
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+}
+
+pub struct CapturePlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+    to_handle_tx: Owned<Producer<CaptureBuffer>>,
+
+    capture: Option<Owned<CaptureBuffer>>,
+
+    pre_roll: PreRollBuffer,
+}
+
+impl CapturePlugAudioThread {
+    fn poll(&mut self) {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::Start { mut buffer, pre_roll_frames } => {
+                    let CaptureBuffer { left, right, .. } = &mut *buffer;
+                    let n = self.pre_roll.copy_latest(pre_roll_frames, left, right);
+
+                    // Less pre-roll than requested may be available, which must
+                    // not make the rest of the capture longer.
+                    buffer.len = n;
+                    buffer.pre_roll = n;
+                    buffer.end = buffer.left.len() - (pre_roll_frames - n);
+
+                    // A previous capture (if any) is dropped by the collector.
+                    self.capture = Some(buffer);
+                }
+                ProcessMsg::Stop => self.finish(),
+            }
+        }
+    }
+
+    /// Hand the current capture back to the handle.
+    fn finish(&mut self) {
+        if let Some(mut capture) = self.capture.take() {
+            // Swap the contents out so the `Owned` wrapper (and not the vecs) is
+            // what gets dropped by the collector.
+            let buffer = CaptureBuffer {
+                left: std::mem::take(&mut capture.left),
+                right: std::mem::take(&mut capture.right),
+                len: capture.len,
+                pre_roll: capture.pre_roll,
+                end: capture.end,
+            };
+
+            if let Err(e) = self.to_handle_tx.push(buffer) {
+                log::error!("Capture plugin failed to send captured audio: {}", e);
+            }
+        }
+    }
+}
+
+impl PluginAudioThread for CapturePlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {}
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        _in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll();
+
+        let frames = proc_info.frames;
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        out_l[0..frames].copy_from_slice(&in_l[0..frames]);
+        out_r[0..frames].copy_from_slice(&in_r[0..frames]);
+
+        let mut done = false;
+        if let Some(capture) = &mut self.capture {
+            let start = capture.len;
+            let n = frames.min(capture.end - start);
+
+            capture.left[start..start + n].copy_from_slice(&in_l[0..n]);
+            capture.right[start..start + n].copy_from_slice(&in_r[0..n]);
+            capture.len += n;
+
+            done = capture.len == capture.end;
+        }
+        if done {
+            self.finish();
+        }
+
+        self.pre_roll.push(&in_l[0..frames], &in_r[0..frames]);
+
+        ProcessStatus::Continue
+    }
+}
@@ -0,0 +1,988 @@
+//! The edges that connect the channels of a project in the audio graph.
+//!
+//! The effects of a channel are chained one after the other, in the order of
+//! the effect rack, and the last one plays into the fader of the channel. The
+//! fader plays into the first effect of the channel it is routed to, or into
+//! its fader if it has no effects. The fader of the master channel plays into
+//! the master bus, which goes to the output of the graph.
+//!
+//! The outputs of the graph are the outputs of the engine, which are mapped to
+//! the outputs of the audio device in pairs. The master bus plays on the first
+//! pair. A channel with hardware outputs of its own plays its fader into a
+//! pair of its own instead of into the channel it is routed to.
+//!
+//! An FX rack among the effects splits the signal into parallel paths, one
+//! for each of its chains. The effects of a chain are chained like those of a
+//! channel and play into a fader plugin with the mix of the chain, and the
+//! graph sums the faders of the chains where they play into the next effect.
+//!
+//! The effects get their notes from the MIDI track plugin of the channel if
+//! it has one, which passes on the notes played into the graph, or else
+//! straight from the input of the graph.
+//!
+//! The drum rack plugin of a channel gets the same notes as the effects, and
+//! plays its main output into the channel the way a channel routed into it
+//! does. Each separate output of the rack plays into the channel it goes to
+//! the same way.
+//!
+//! The audio clips of a channel play out of its timeline track plugin into
+//! the channel the way a channel routed into it does. A clip with effects of
+//! its own plays out of a timeline track plugin of its own instead, through
+//! its effects, so they only process that clip.
+//!
+//! Each send of a channel has a fader plugin of its own that sets its level.
+//! A post-fader send takes the output of the fader of the channel, and a
+//! pre-fader send takes what plays into it, i.e. the output of the last
+//! effect, or the same channels that play into the fader if there are no
+//! effects. The send plays into the channel it goes to like a routed channel
+//! does.
+//!
+//! The automation plugins of a channel send parameter events to the fader or
+//! the effect they automate.
+//!
+//! Each armed channel plays its `RecordSource` into an input port of its own
+//! on the record plugin, in the order of the channels, so every channel
+//! records a take of its own input.
+//!
+//! This only works out which edges there should be. The caller compares them
+//! with the edges that are connected and sends the difference to the engine.
+//!
+//! Channels that play into the same channel are parallel paths, and their
+//! effects can delay them by different amounts. Dropseed's graph compiler
+//! makes up for that by holding back the edges of the faster paths.
+//! `delay_compensation()` does the same, so impulses can be sent through
+//! every path of the edges with `impulse_arrivals()` to check they line up.
+
+use super::record_plug::MAX_RECORDED_CHANNELS;
+
+/// The kind of port an edge connects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Note,
+    Audio,
+    /// Parameter events from an automation plugin.
+    Automation,
+}
+
+/// An edge from a channel of an output port of one node to a channel of an
+/// input port of another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge<N> {
+    pub kind: EdgeKind,
+    pub src: N,
+    /// The stable ID of the output port of `src`, or 0 for its main port.
+    pub src_port: u32,
+    pub src_channel: u16,
+    pub dst: N,
+    /// The stable ID of the input port of `dst`, or 0 for its main port.
+    pub dst_port: u32,
+    pub dst_channel: u16,
+}
+
+/// Where an armed channel records from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSource {
+    /// A pair of inputs of the graph.
+    Device { left: u16, right: u16 },
+    /// The fader of another channel.
+    Bus(usize),
+}
+
+/// A send of a channel that is in the audio graph.
+#[derive(Debug, Clone)]
+pub struct SendNodes<N> {
+    /// The fader plugin that sets the level of the send.
+    pub node: N,
+    /// The index of the channel the send goes to.
+    pub to: usize,
+    /// Whether the send is taken before the fader of the channel.
+    pub pre_fader: bool,
+}
+
+/// A clip with effects of its own that is in the audio graph.
+#[derive(Debug, Clone)]
+pub struct ClipChain<N> {
+    /// The timeline track plugin that only plays the clip.
+    pub player: N,
+    /// The plugins of the effects of the clip that are in the graph, in the
+    /// order the signal goes through them.
+    pub effects: Vec<N>,
+}
+
+/// The drum rack plugin of a channel that is in the audio graph.
+#[derive(Debug, Clone)]
+pub struct DrumRackNodes<N> {
+    pub plugin: N,
+    /// The index of the channel each separate output plays into. The `n`th
+    /// one is the port with the stable ID `n + 1`.
+    pub outputs: Vec<usize>,
+}
+
+/// An effect of a channel that is in the audio graph.
+#[derive(Debug, Clone)]
+pub enum EffectNodes<N> {
+    Plugin(N),
+    /// An FX rack with the chains that are in the graph. A rack without any
+    /// is left out.
+    Rack(Vec<FxChainNodes<N>>),
+}
+
+/// A chain of an FX rack that is in the audio graph.
+#[derive(Debug, Clone)]
+pub struct FxChainNodes<N> {
+    /// The plugins of the effects of the chain that are in the graph, in the
+    /// order the signal goes through them.
+    pub effects: Vec<N>,
+    /// The fader plugin that sets the gain and pan of the chain.
+    pub mix: N,
+}
+
+impl<N> EffectNodes<N> {
+    /// The nodes the signal that plays into the effect goes to.
+    fn inputs(&self) -> Vec<&N> {
+        match self {
+            EffectNodes::Plugin(plugin) => vec![plugin],
+            EffectNodes::Rack(chains) => {
+                chains.iter().map(|chain| chain.effects.first().unwrap_or(&chain.mix)).collect()
+            }
+        }
+    }
+
+    /// The nodes the output of the effect comes out of.
+    fn outputs(&self) -> Vec<&N> {
+        match self {
+            EffectNodes::Plugin(plugin) => vec![plugin],
+            EffectNodes::Rack(chains) => chains.iter().map(|chain| &chain.mix).collect(),
+        }
+    }
+
+    /// The plugins of the effect that get notes, i.e. not the faders of the
+    /// chains of a rack.
+    fn plugins(&self) -> Vec<&N> {
+        match self {
+            EffectNodes::Plugin(plugin) => vec![plugin],
+            EffectNodes::Rack(chains) => chains.iter().flat_map(|chain| &chain.effects).collect(),
+        }
+    }
+}
+
+/// The nodes of a channel that are in the audio graph. `N` identifies a
+/// node, i.e. a `PluginInstanceID`.
+#[derive(Debug, Clone)]
+pub struct ChannelNodes<N> {
+    /// `None` until the engine has added the fader. A channel without a
+    /// fader isn't connected to anything.
+    pub fader: Option<N>,
+    /// The MIDI track plugin that plays the notes of the clips of the
+    /// channel, if it has one.
+    pub notes: Option<N>,
+    /// The timeline track plugin that plays the audio clips of the channel,
+    /// if it has any without effects of their own.
+    pub clips: Option<N>,
+    /// The clips of the channel with effects of their own.
+    pub clip_chains: Vec<ClipChain<N>>,
+    /// The drum rack plugin that plays the pads of the channel, if it has
+    /// any with a sample.
+    pub drums: Option<DrumRackNodes<N>>,
+    /// The effects of the channel that are in the graph, in the order the
+    /// signal goes through them.
+    pub effects: Vec<EffectNodes<N>>,
+    /// The index of the channel this one plays into. Ignored for the master
+    /// channel at index 0.
+    pub routed_to: usize,
+    /// The sends of the channel whose fader is in the graph.
+    pub sends: Vec<SendNodes<N>>,
+    /// The first of the pair of outputs of the graph the channel is rendered
+    /// to as a stem, if any.
+    pub stem: Option<u16>,
+    /// The first of the pair of outputs of the graph the channel plays on
+    /// instead of the channel it is routed to, if it has hardware outputs of
+    /// its own. Ignored for the master channel.
+    pub hardware_out: Option<u16>,
+    /// The automation plugins of the channel, each with the plugin whose
+    /// parameters it moves.
+    pub automation: Vec<(N, N)>,
+    /// Where the channel records from, if it is armed.
+    pub record: Option<RecordSource>,
+}
+
+/// The nodes the channels are connected to that aren't part of a channel.
+#[derive(Debug, Clone)]
+pub struct GraphNodes<N> {
+    pub graph_in: N,
+    pub graph_out: N,
+    /// Where the master channel goes, i.e. the EQ and the meters of the
+    /// master bus.
+    pub master_bus: N,
+    /// The number of inputs of the graph.
+    pub graph_in_channels: u16,
+    /// The plugin that records the armed channels, once it was added.
+    pub record: Option<N>,
+}
+
+/// Both channels of a stereo edge, the left one going to `dst_channel`.
+fn stereo<N: Clone>(src: &N, dst: &N, dst_channel: u16) -> [Edge<N>; 2] {
+    stereo_from_port(src, 0, dst, dst_channel)
+}
+
+/// Both channels of a stereo edge out of the port of `src` with the stable
+/// ID `src_port`.
+fn stereo_from_port<N: Clone>(src: &N, src_port: u32, dst: &N, dst_channel: u16) -> [Edge<N>; 2] {
+    let edge = |channel: u16| Edge {
+        kind: EdgeKind::Audio,
+        src: src.clone(),
+        src_port,
+        src_channel: channel,
+        dst: dst.clone(),
+        dst_port: 0,
+        dst_channel: dst_channel + channel,
+    };
+    [edge(0), edge(1)]
+}
+
+impl<N> ChannelNodes<N> {
+    /// Every node the signal that plays into the channel goes to. Without
+    /// effects, the pre-fader sends take the same signal as the fader.
+    fn inputs(&self) -> Vec<&N> {
+        let fader = match &self.fader {
+            Some(fader) => fader,
+            None => return Vec::new(),
+        };
+        match self.effects.first() {
+            Some(effect) => effect.inputs(),
+            None => {
+                let mut inputs = vec![fader];
+                inputs
+                    .extend(self.sends.iter().filter(|send| send.pre_fader).map(|send| &send.node));
+                inputs
+            }
+        }
+    }
+}
+
+/// Every edge the channels should be connected with.
+pub fn channel_edges<N: Clone>(
+    channels: &[ChannelNodes<N>],
+    nodes: &GraphNodes<N>,
+) -> Vec<Edge<N>> {
+    let mut edges = Vec::new();
+    for (index, channel) in channels.iter().enumerate() {
+        let fader = match &channel.fader {
+            Some(fader) => fader,
+            None => continue,
+        };
+
+        let note_edge = |src: &N, dst: &N| Edge {
+            kind: EdgeKind::Note,
+            src: src.clone(),
+            src_port: 0,
+            src_channel: 0,
+            dst: dst.clone(),
+            dst_port: 0,
+            dst_channel: 0,
+        };
+        let notes = match &channel.notes {
+            Some(notes) => {
+                edges.push(note_edge(&nodes.graph_in, notes));
+                notes
+            }
+            None => &nodes.graph_in,
+        };
+
+        for (i, effect) in channel.effects.iter().enumerate() {
+            for plugin in effect.plugins() {
+                edges.push(note_edge(notes, plugin));
+            }
+            if let EffectNodes::Rack(chains) = effect {
+                for chain in chains.iter() {
+                    for (j, chain_effect) in chain.effects.iter().enumerate() {
+                        let next = chain.effects.get(j + 1).unwrap_or(&chain.mix);
+                        edges.extend(stereo(chain_effect, next, 0));
+                    }
+                }
+            }
+            let next = channel.effects.get(i + 1).map_or_else(|| vec![fader], EffectNodes::inputs);
+            for output in effect.outputs() {
+                for input in next.iter() {
+                    edges.extend(stereo(output, input, 0));
+                }
+            }
+        }
+
+        if let Some(drums) = &channel.drums {
+            edges.push(note_edge(notes, &drums.plugin));
+            for input in channel.inputs() {
+                edges.extend(stereo(&drums.plugin, input, 0));
+            }
+            for (i, to) in drums.outputs.iter().enumerate() {
+                for input in channels.get(*to).map(ChannelNodes::inputs).unwrap_or_default() {
+                    edges.extend(stereo_from_port(&drums.plugin, i as u32 + 1, input, 0));
+                }
+            }
+        }
+        if let Some(clips) = &channel.clips {
+            for input in channel.inputs() {
+                edges.extend(stereo(clips, input, 0));
+            }
+        }
+        for chain in channel.clip_chains.iter() {
+            let mut src = &chain.player;
+            for effect in chain.effects.iter() {
+                edges.extend(stereo(src, effect, 0));
+                src = effect;
+            }
+            for input in channel.inputs() {
+                edges.extend(stereo(src, input, 0));
+            }
+        }
+
+        let outputs = if index == 0 {
+            vec![&nodes.master_bus]
+        } else if channel.hardware_out.is_some() {
+            Vec::new()
+        } else if channel.routed_to != index {
+            channels.get(channel.routed_to).map(ChannelNodes::inputs).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        for output in outputs {
+            edges.extend(stereo(fader, output, 0));
+        }
+
+        for send in channel.sends.iter() {
+            match (send.pre_fader, channel.effects.last()) {
+                (true, Some(last)) => {
+                    for output in last.outputs() {
+                        edges.extend(stereo(output, &send.node, 0));
+                    }
+                }
+                // The send gets what plays into the channel along with the
+                // fader.
+                (true, None) => {}
+                (false, _) => edges.extend(stereo(fader, &send.node, 0)),
+            }
+            if send.to != index {
+                for output in channels.get(send.to).map(ChannelNodes::inputs).unwrap_or_default() {
+                    edges.extend(stereo(&send.node, output, 0));
+                }
+            }
+        }
+
+        if let (Some(hardware_out), true) = (channel.hardware_out, index != 0) {
+            edges.extend(stereo(fader, &nodes.graph_out, hardware_out));
+        }
+        if let (Some(stem), true) = (channel.stem, index != 0) {
+            edges.extend(stereo(fader, &nodes.graph_out, stem));
+        }
+
+        for (automation, target) in channel.automation.iter() {
+            edges.push(Edge {
+                kind: EdgeKind::Automation,
+                src: automation.clone(),
+                src_port: 0,
+                src_channel: 0,
+                dst: target.clone(),
+                dst_port: 0,
+                dst_channel: 0,
+            });
+        }
+    }
+    if let Some(record) = &nodes.record {
+        edges.extend(record_edges(channels, nodes, record));
+    }
+    edges
+}
+
+/// The edges from the sources of the armed channels to their ports on the
+/// record plugin. The `n`th armed channel records the port with the stable
+/// ID `n`, up to `MAX_RECORDED_CHANNELS`. Inputs the graph doesn't have are
+/// left unconnected, so they record silence.
+fn record_edges<N: Clone>(
+    channels: &[ChannelNodes<N>],
+    nodes: &GraphNodes<N>,
+    record: &N,
+) -> Vec<Edge<N>> {
+    let armed = channels.iter().filter_map(|channel| channel.record).take(MAX_RECORDED_CHANNELS);
+
+    let mut edges = Vec::new();
+    for (port, source) in armed.enumerate() {
+        let edge = |src: &N, src_channel: u16, dst_channel: u16| Edge {
+            kind: EdgeKind::Audio,
+            src: src.clone(),
+            src_port: 0,
+            src_channel,
+            dst: record.clone(),
+            dst_port: port as u32,
+            dst_channel,
+        };
+        match source {
+            RecordSource::Device { left, right } => {
+                for (src_channel, dst_channel) in [(left, 0), (right, 1)] {
+                    if src_channel < nodes.graph_in_channels {
+                        edges.push(edge(&nodes.graph_in, src_channel, dst_channel));
+                    }
+                }
+            }
+            RecordSource::Bus(bus) => {
+                if let Some(fader) = channels.get(bus).and_then(|bus| bus.fader.as_ref()) {
+                    edges.extend([edge(fader, 0, 0), edge(fader, 1, 1)]);
+                }
+            }
+        }
+    }
+    edges
+}
+
+/// How many frames each edge has to hold back its signal so that everything
+/// that plays into a node arrives at the same time, given the latency of each
+/// node in frames. Only audio edges are held back.
+pub fn delay_compensation<N: Clone + PartialEq>(
+    edges: &[Edge<N>],
+    latency: impl Fn(&N) -> u32,
+) -> Vec<u32> {
+    // When the signal is ready at the input of each node, i.e. the latency
+    // of the slowest path into it. A cycle would never settle, so this gives
+    // up after as many rounds as there are edges.
+    let mut ready: Vec<(N, u32)> = Vec::new();
+    let ready_at = |ready: &[(N, u32)], node: &N| {
+        ready.iter().find(|(n, _)| n == node).map_or(0, |(_, frames)| *frames)
+    };
+    let audio = || edges.iter().filter(|edge| edge.kind == EdgeKind::Audio);
+    for _ in 0..edges.len() {
+        let mut changed = false;
+        for edge in audio() {
+            let arrival = ready_at(&ready, &edge.src) + latency(&edge.src);
+            match ready.iter_mut().find(|(n, _)| *n == edge.dst) {
+                Some((_, frames)) if *frames >= arrival => {}
+                Some((_, frames)) => {
+                    *frames = arrival;
+                    changed = true;
+                }
+                None => {
+                    ready.push((edge.dst.clone(), arrival));
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    edges
+        .iter()
+        .map(|edge| match edge.kind {
+            EdgeKind::Audio => {
+                let arrival = ready_at(&ready, &edge.src) + latency(&edge.src);
+                ready_at(&ready, &edge.dst).saturating_sub(arrival)
+            }
+            _ => 0,
+        })
+        .collect()
+}
+
+/// The frame an impulse played out of `src` at frame 0 arrives at `dst`
+/// along each path of audio edges, with each edge held back by the frames
+/// in `delays`. The paths are followed on the left side of the signal.
+pub fn impulse_arrivals<N: PartialEq>(
+    edges: &[Edge<N>],
+    delays: &[u32],
+    latency: impl Fn(&N) -> u32,
+    src: &N,
+    dst: &N,
+) -> Vec<u32> {
+    let mut arrivals = Vec::new();
+    let mut stack = vec![(src, 0, 0)];
+    while let Some((node, frame, depth)) = stack.pop() {
+        if node == dst {
+            arrivals.push(frame);
+            continue;
+        }
+        // A cycle would never end.
+        if depth == edges.len() {
+            continue;
+        }
+        let out = frame + latency(node);
+        for (edge, delay) in edges.iter().zip(delays).rev() {
+            if edge.kind == EdgeKind::Audio && edge.src == *node && edge.src_channel == 0 {
+                stack.push((&edge.dst, out + delay, depth + 1));
+            }
+        }
+    }
+    arrivals
+}
+
+/// The edges to connect and the edges to disconnect to get from the
+/// `connected` edges to the `desired` ones.
+pub fn edge_changes<N: Clone + PartialEq>(
+    connected: &[Edge<N>],
+    desired: &[Edge<N>],
+) -> (Vec<Edge<N>>, Vec<Edge<N>>) {
+    let connect = desired.iter().filter(|edge| !connected.contains(edge)).cloned().collect();
+    let disconnect = connected.iter().filter(|edge| !desired.contains(edge)).cloned().collect();
+    (connect, disconnect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAPH_IN: u32 = 100;
+    const GRAPH_OUT: u32 = 101;
+    const MASTER_BUS: u32 = 102;
+
+    fn nodes() -> GraphNodes<u32> {
+        GraphNodes {
+            graph_in: GRAPH_IN,
+            graph_out: GRAPH_OUT,
+            master_bus: MASTER_BUS,
+            graph_in_channels: 2,
+            record: None,
+        }
+    }
+
+    fn channel(fader: Option<u32>, effects: &[u32], routed_to: usize) -> ChannelNodes<u32> {
+        ChannelNodes {
+            fader,
+            notes: None,
+            clips: None,
+            clip_chains: Vec::new(),
+            drums: None,
+            effects: effects.iter().map(|effect| EffectNodes::Plugin(*effect)).collect(),
+            routed_to,
+            sends: Vec::new(),
+            stem: None,
+            hardware_out: None,
+            automation: Vec::new(),
+            record: None,
+        }
+    }
+
+    /// The nodes `src` plays audio into.
+    fn audio_outputs(edges: &[Edge<u32>], src: u32) -> Vec<u32> {
+        let mut outputs: Vec<u32> = edges
+            .iter()
+            .filter(|edge| edge.kind == EdgeKind::Audio && edge.src == src)
+            .map(|edge| edge.dst)
+            .collect();
+        outputs.dedup();
+        outputs
+    }
+
+    #[test]
+    fn channels_play_through_their_faders_into_the_master_bus() {
+        let channels =
+            vec![channel(Some(0), &[], 0), channel(Some(1), &[], 0), channel(Some(2), &[20], 1)];
+        let edges = channel_edges(&channels, &nodes());
+
+        assert_eq!(audio_outputs(&edges, 20), vec![2]);
+        assert_eq!(audio_outputs(&edges, 2), vec![1]);
+        assert_eq!(audio_outputs(&edges, 1), vec![0]);
+        assert_eq!(audio_outputs(&edges, 0), vec![MASTER_BUS]);
+
+        // Both sides of the signal are connected, each to its own side.
+        let fader_out: Vec<(u16, u16)> = edges
+            .iter()
+            .filter(|edge| edge.src == 1)
+            .map(|edge| (edge.src_channel, edge.dst_channel))
+            .collect();
+        assert_eq!(fader_out, vec![(0, 0), (1, 1)]);
+
+        // Effects get the notes played into the graph.
+        assert!(edges.contains(&Edge {
+            kind: EdgeKind::Note,
+            src: GRAPH_IN,
+            src_port: 0,
+            src_channel: 0,
+            dst: 20,
+            dst_port: 0,
+            dst_channel: 0,
+        }));
+    }
+
+    #[test]
+    fn effects_are_chained_in_order() {
+        let channels = vec![
+            channel(Some(0), &[30], 0),
+            channel(Some(1), &[10, 11, 12], 0),
+            channel(Some(2), &[], 1),
+        ];
+        let edges = channel_edges(&channels, &nodes());
+
+        assert_eq!(audio_outputs(&edges, 10), vec![11]);
+        assert_eq!(audio_outputs(&edges, 11), vec![12]);
+        assert_eq!(audio_outputs(&edges, 12), vec![1]);
+        // Channels play into the start of the chain of the channel they are
+        // routed to.
+        assert_eq!(audio_outputs(&edges, 2), vec![10]);
+        assert_eq!(audio_outputs(&edges, 1), vec![30]);
+        assert_eq!(audio_outputs(&edges, 30), vec![0]);
+        assert_eq!(audio_outputs(&edges, 0), vec![MASTER_BUS]);
+
+        // Nothing else plays into the middle of the chain.
+        let into_11: Vec<u32> =
+            edges.iter().filter(|edge| edge.dst == 11).map(|edge| edge.src).collect();
+        assert_eq!(into_11, vec![10, 10, GRAPH_IN]);
+    }
+
+    #[test]
+    fn racks_split_the_signal_into_parallel_chains() {
+        let mut channels = vec![channel(Some(0), &[], 0), channel(Some(1), &[10], 0)];
+        channels[1].effects.push(EffectNodes::Rack(vec![
+            FxChainNodes { effects: vec![20, 21], mix: 80 },
+            FxChainNodes { effects: Vec::new(), mix: 81 },
+        ]));
+        channels[1].effects.push(EffectNodes::Plugin(11));
+        channels[1].sends = vec![SendNodes { node: 50, to: 0, pre_fader: true }];
+        channels[1].notes = Some(5);
+        let edges = channel_edges(&channels, &nodes());
+
+        // The effect before the rack plays into every chain, and the faders
+        // of the chains into the effect after it.
+        assert_eq!(audio_outputs(&edges, 10), vec![20, 81]);
+        assert_eq!(audio_outputs(&edges, 20), vec![21]);
+        assert_eq!(audio_outputs(&edges, 21), vec![80]);
+        assert_eq!(audio_outputs(&edges, 80), vec![11]);
+        assert_eq!(audio_outputs(&edges, 81), vec![11]);
+        assert_eq!(audio_outputs(&edges, 11), vec![1, 50]);
+
+        // The effects in the chains get notes, the faders of the chains don't.
+        let notes: Vec<u32> = edges
+            .iter()
+            .filter(|edge| edge.kind == EdgeKind::Note && edge.src == 5)
+            .map(|edge| edge.dst)
+            .collect();
+        assert_eq!(notes, vec![10, 20, 21, 11]);
+
+        // A rack at the start or the end of the channel takes the place of an
+        // effect there.
+        channels[1].effects.remove(2);
+        channels[1].effects.remove(0);
+        channels.push(channel(Some(2), &[], 1));
+        let edges = channel_edges(&channels, &nodes());
+        assert_eq!(audio_outputs(&edges, 2), vec![20, 81]);
+        assert_eq!(audio_outputs(&edges, 80), vec![1, 50]);
+        assert_eq!(audio_outputs(&edges, 81), vec![1, 50]);
+    }
+
+    #[test]
+    fn effects_get_the_notes_of_the_midi_track() {
+        let mut channels = vec![channel(Some(0), &[], 0), channel(Some(1), &[10, 11], 0)];
+        channels[1].notes = Some(5);
+        let edges = channel_edges(&channels, &nodes());
+
+        let note_edges: Vec<(u32, u32)> = edges
+            .iter()
+            .filter(|edge| edge.kind == EdgeKind::Note)
+            .map(|edge| (edge.src, edge.dst))
+            .collect();
+        assert_eq!(note_edges, vec![(GRAPH_IN, 5), (5, 10), (5, 11)]);
+    }
+
+    #[test]
+    fn automation_goes_to_the_plugins_it_moves() {
+        let mut channels = vec![channel(Some(0), &[], 0), channel(Some(1), &[10, 11], 0)];
+        channels[1].automation = vec![(40, 1), (41, 11)];
+        let edges = channel_edges(&channels, &nodes());
+
+        let automation_edges: Vec<(u32, u32)> = edges
+            .iter()
+            .filter(|edge| edge.kind == EdgeKind::Automation)
+            .map(|edge| (edge.src, edge.dst))
+            .collect();
+        assert_eq!(automation_edges, vec![(40, 1), (41, 11)]);
+        // Automation doesn't change how the audio goes.
+        assert_eq!(audio_outputs(&edges, 40), Vec::<u32>::new());
+        assert_eq!(audio_outputs(&edges, 11), vec![1]);
+
+        // Nothing is connected on a channel without a fader.
+        channels[1].fader = None;
+        let edges = channel_edges(&channels, &nodes());
+        assert!(edges.iter().all(|edge| edge.kind != EdgeKind::Automation));
+    }
+
+    #[test]
+    fn armed_channels_record_their_own_inputs() {
+        const RECORD: u32 = 103;
+        let mut nodes = nodes();
+        nodes.record = Some(RECORD);
+        let mut channels =
+            vec![channel(Some(0), &[], 0), channel(Some(1), &[], 0), channel(Some(2), &[], 0)];
+        channels[1].record = Some(RecordSource::Bus(0));
+        channels[2].record = Some(RecordSource::Device { left: 1, right: 1 });
+        let edges = channel_edges(&channels, &nodes);
+
+        let recorded: Vec<(u32, u16, u32, u16)> = edges
+            .iter()
+            .filter(|edge| edge.dst == RECORD)
+            .map(|edge| (edge.src, edge.src_channel, edge.dst_port, edge.dst_channel))
+            .collect();
+        // The first armed channel takes the master fader, the second the
+        // right input on both sides, each into a port of its own.
+        assert_eq!(
+            recorded,
+            vec![(0, 0, 0, 0), (0, 1, 0, 1), (GRAPH_IN, 1, 1, 0), (GRAPH_IN, 1, 1, 1)]
+        );
+
+        // Inputs the graph doesn't have are left out, as are channels that
+        // aren't armed.
+        channels[1].record = None;
+        channels[2].record = Some(RecordSource::Device { left: 0, right: 5 });
+        let edges = channel_edges(&channels, &nodes);
+        let recorded: Vec<(u32, u16, u32, u16)> = edges
+            .iter()
+            .filter(|edge| edge.dst == RECORD)
+            .map(|edge| (edge.src, edge.src_channel, edge.dst_port, edge.dst_channel))
+            .collect();
+        assert_eq!(recorded, vec![(GRAPH_IN, 0, 0, 0)]);
+    }
+
+    #[test]
+    fn channels_without_a_fader_are_left_out() {
+        let channels =
+            vec![channel(Some(0), &[], 0), channel(None, &[10], 0), channel(Some(2), &[], 1)];
+        let edges = channel_edges(&channels, &nodes());
+
+        assert!(edges.iter().all(|edge| edge.src != 10 && edge.dst != 10));
+        assert!(audio_outputs(&edges, 2).is_empty());
+    }
+
+    #[test]
+    fn hardware_outputs_take_the_place_of_the_routing() {
+        let mut channels = vec![
+            channel(Some(0), &[], 0),
+            channel(Some(1), &[], 0),
+            channel(Some(2), &[], 1),
+            channel(Some(3), &[], 1),
+        ];
+        channels[1].hardware_out = Some(2);
+        channels[3].hardware_out = Some(4);
+        let edges = channel_edges(&channels, &nodes());
+
+        let graph_out: Vec<(u32, u16, u16)> = edges
+            .iter()
+            .filter(|edge| edge.dst == GRAPH_OUT)
+            .map(|edge| (edge.src, edge.src_channel, edge.dst_channel))
+            .collect();
+        assert_eq!(graph_out, vec![(1, 0, 2), (1, 1, 3), (3, 0, 4), (3, 1, 5)]);
+
+        // A bus on outputs of its own still gets the channels routed to it,
+        // and a channel on outputs of its own doesn't play into its bus.
+        assert_eq!(audio_outputs(&edges, 1), vec![GRAPH_OUT]);
+        assert_eq!(audio_outputs(&edges, 2), vec![1]);
+        assert_eq!(audio_outputs(&edges, 3), vec![GRAPH_OUT]);
+        assert_eq!(audio_outputs(&edges, 0), vec![MASTER_BUS]);
+    }
+
+    #[test]
+    fn stems_come_from_the_faders() {
+        let mut channels = vec![channel(Some(0), &[], 0), channel(Some(1), &[], 0)];
+        channels[0].stem = Some(0);
+        channels[1].stem = Some(2);
+        let edges = channel_edges(&channels, &nodes());
+
+        let stem: Vec<(u32, u16, u16)> = edges
+            .iter()
+            .filter(|edge| edge.dst == GRAPH_OUT)
+            .map(|edge| (edge.src, edge.src_channel, edge.dst_channel))
+            .collect();
+        assert_eq!(stem, vec![(1, 0, 2), (1, 1, 3)]);
+    }
+
+    #[test]
+    fn clips_play_into_the_start_of_the_channel() {
+        let mut channels = vec![channel(Some(0), &[], 0), channel(Some(1), &[10, 11], 0)];
+        channels[1].clips = Some(60);
+        channels[1].clip_chains = vec![
+            ClipChain { player: 61, effects: vec![70, 71] },
+            ClipChain { player: 62, effects: Vec::new() },
+        ];
+        let edges = channel_edges(&channels, &nodes());
+
+        assert_eq!(audio_outputs(&edges, 60), vec![10]);
+        // The effects of a clip only get the clip, before the effects of the
+        // channel.
+        assert_eq!(audio_outputs(&edges, 61), vec![70]);
+        assert_eq!(audio_outputs(&edges, 70), vec![71]);
+        assert_eq!(audio_outputs(&edges, 71), vec![10]);
+        let into_70: Vec<u32> =
+            edges.iter().filter(|edge| edge.dst == 70).map(|edge| edge.src).collect();
+        assert_eq!(into_70, vec![61, 61]);
+        assert_eq!(audio_outputs(&edges, 62), vec![10]);
+
+        // Without effects on the channel, the clips go to the fader and to
+        // the pre-fader sends.
+        channels[1].effects.clear();
+        channels[1].sends = vec![SendNodes { node: 50, to: 0, pre_fader: true }];
+        let edges = channel_edges(&channels, &nodes());
+        assert_eq!(audio_outputs(&edges, 60), vec![1, 50]);
+        assert_eq!(audio_outputs(&edges, 71), vec![1, 50]);
+
+        // Nothing is connected on a channel without a fader.
+        channels[1].fader = None;
+        let edges = channel_edges(&channels, &nodes());
+        assert!(edges.iter().all(|edge| ![60, 61, 62, 70, 71].contains(&edge.src)));
+    }
+
+    #[test]
+    fn drum_racks_play_their_outputs_into_channels() {
+        let mut channels = vec![
+            channel(Some(0), &[], 0),
+            channel(Some(1), &[10], 0),
+            channel(Some(2), &[20], 0),
+            channel(Some(3), &[], 0),
+        ];
+        channels[1].notes = Some(40);
+        channels[1].drums = Some(DrumRackNodes { plugin: 80, outputs: vec![2, 3] });
+        let edges = channel_edges(&channels, &nodes());
+
+        // The rack gets the notes of the channel.
+        assert!(edges.contains(&Edge {
+            kind: EdgeKind::Note,
+            src: 40,
+            src_port: 0,
+            src_channel: 0,
+            dst: 80,
+            dst_port: 0,
+            dst_channel: 0,
+        }));
+
+        // The main output goes to the start of the channel, and each separate
+        // output to the start of its own channel.
+        let from_ports: Vec<(u32, u32)> = edges
+            .iter()
+            .filter(|edge| edge.src == 80 && edge.src_channel == 0)
+            .map(|edge| (edge.src_port, edge.dst))
+            .collect();
+        assert_eq!(from_ports, vec![(0, 10), (1, 20), (2, 3)]);
+    }
+
+    #[test]
+    fn post_fader_sends_take_the_fader() {
+        let mut channels =
+            vec![channel(Some(0), &[], 0), channel(Some(1), &[10], 0), channel(Some(2), &[20], 0)];
+        channels[2].sends = vec![SendNodes { node: 50, to: 1, pre_fader: false }];
+        let edges = channel_edges(&channels, &nodes());
+
+        assert_eq!(audio_outputs(&edges, 20), vec![2]);
+        assert_eq!(audio_outputs(&edges, 2), vec![0, 50]);
+        // The send plays into the start of the chain of the channel it goes
+        // to, with the level set on its own fader.
+        assert_eq!(audio_outputs(&edges, 50), vec![10]);
+        let into_send: Vec<(u32, u16, u16)> = edges
+            .iter()
+            .filter(|edge| edge.dst == 50)
+            .map(|edge| (edge.src, edge.src_channel, edge.dst_channel))
+            .collect();
+        assert_eq!(into_send, vec![(2, 0, 0), (2, 1, 1)]);
+    }
+
+    #[test]
+    fn pre_fader_sends_take_what_plays_into_the_fader() {
+        let mut channels = vec![
+            channel(Some(0), &[], 0),
+            channel(Some(1), &[], 0),
+            channel(Some(2), &[20, 21], 0),
+            channel(Some(3), &[], 1),
+        ];
+        channels[1].sends = vec![SendNodes { node: 51, to: 2, pre_fader: true }];
+        channels[2].sends = vec![SendNodes { node: 52, to: 0, pre_fader: true }];
+        let edges = channel_edges(&channels, &nodes());
+
+        // With effects, the send takes the last one.
+        assert_eq!(audio_outputs(&edges, 21), vec![2, 52]);
+        assert_eq!(audio_outputs(&edges, 52), vec![0]);
+
+        // Without effects, the channels routed into it play into the send
+        // too.
+        assert_eq!(audio_outputs(&edges, 3), vec![1, 51]);
+        assert_eq!(audio_outputs(&edges, 51), vec![20]);
+        assert!(edges.iter().all(|edge| !(edge.src == 1 && edge.dst == 51)));
+
+        // A send that plays into a channel without effects also goes to the
+        // pre-fader sends of that channel.
+        channels[2].sends[0].to = 1;
+        let edges = channel_edges(&channels, &nodes());
+        assert_eq!(audio_outputs(&edges, 52), vec![1, 51]);
+    }
+
+    #[test]
+    fn sends_of_channels_without_a_fader_are_left_out() {
+        let mut channels = vec![channel(Some(0), &[], 0), channel(None, &[], 0)];
+        channels[1].sends = vec![SendNodes { node: 50, to: 0, pre_fader: false }];
+        let edges = channel_edges(&channels, &nodes());
+
+        assert!(edges.iter().all(|edge| edge.src != 50 && edge.dst != 50));
+    }
+
+    #[test]
+    fn parallel_paths_line_up_after_delay_compensation() {
+        // Three channels with latent effects play into the master, one of
+        // them through a rack whose chains have different latencies, and
+        // one of them sends to another channel before its effects.
+        let mut channels = vec![
+            channel(Some(0), &[], 0),
+            channel(Some(1), &[10], 0),
+            channel(Some(2), &[20, 21], 0),
+            channel(Some(3), &[], 1),
+        ];
+        channels[3].effects.push(EffectNodes::Rack(vec![
+            FxChainNodes { effects: vec![30], mix: 80 },
+            FxChainNodes { effects: Vec::new(), mix: 81 },
+        ]));
+        channels[1].clips = Some(61);
+        channels[2].clips = Some(62);
+        channels[3].clips = Some(63);
+        channels[2].sends = vec![SendNodes { node: 50, to: 1, pre_fader: false }];
+        let latency = |node: &u32| match node {
+            10 => 64,
+            20 => 256,
+            21 => 32,
+            30 => 128,
+            _ => 0,
+        };
+        let edges = channel_edges(&channels, &nodes());
+
+        let arrivals = |delays: &[u32]| -> Vec<u32> {
+            [61, 62, 63]
+                .iter()
+                .flat_map(|clip| impulse_arrivals(&edges, delays, latency, clip, &MASTER_BUS))
+                .collect()
+        };
+
+        // Without making up for the latency the impulses are smeared.
+        let uncompensated = arrivals(&vec![0; edges.len()]);
+        assert!(uncompensated.iter().any(|frame| *frame != uncompensated[0]));
+
+        // Every path, the send and both chains of the rack included, arrives
+        // at the master bus at once, as late as the slowest path.
+        let delays = delay_compensation(&edges, latency);
+        let compensated = arrivals(&delays);
+        assert_eq!(compensated.len(), 5);
+        assert!(compensated.iter().all(|frame| *frame == 288 + 64), "{:?}", compensated);
+
+        // Only the audio is held back.
+        for (edge, delay) in edges.iter().zip(&delays) {
+            assert!(edge.kind == EdgeKind::Audio || *delay == 0);
+        }
+    }
+
+    #[test]
+    fn only_the_differences_are_changed() {
+        let before = channel_edges(
+            &[channel(Some(0), &[], 0), channel(Some(1), &[], 0), channel(Some(2), &[], 0)],
+            &nodes(),
+        );
+        let after = channel_edges(
+            &[channel(Some(0), &[], 0), channel(Some(1), &[], 0), channel(Some(2), &[], 1)],
+            &nodes(),
+        );
+
+        let (connect, disconnect) = edge_changes(&before, &after);
+        assert_eq!(connect, stereo(&2, &1, 0).to_vec());
+        assert_eq!(disconnect, stereo(&2, &0, 0).to_vec());
+
+        let (connect, disconnect) = edge_changes(&after, &after);
+        assert!(connect.is_empty() && disconnect.is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+//! Offline spectral "collision" analysis between two signals.
+//!
+//! Both signals are split into short time slices and each slice is reduced to
+//! the energy in a set of logarithmically spaced bands. A band collides when
+//! both signals are loud in it at the same time and at a similar level, which
+//! is where one is most likely to mask the other.
+
+use pcm_loader::PcmRAM;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::dsp::{gain_to_db, MIN_DB};
+
+pub const NUM_COLLISION_BANDS: usize = 32;
+
+const MIN_BAND_FREQ: f32 = 30.0;
+const MAX_BAND_FREQ: f32 = 16_000.0;
+
+const FFT_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollisionSettings {
+    /// The length of each time slice of the map in seconds.
+    pub slice_secs: f32,
+    /// Bands quieter than this in either signal never collide.
+    pub threshold_db: f32,
+    /// How far apart the levels of the two signals in a band can be (in dB)
+    /// before the quieter one is no longer considered to be masked.
+    pub masking_range_db: f32,
+}
+
+impl Default for CollisionSettings {
+    fn default() -> Self {
+        Self { slice_secs: 0.25, threshold_db: -60.0, masking_range_db: 18.0 }
+    }
+}
+
+/// How strongly two signals collide in each band over time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollisionMap {
+    pub slice_secs: f32,
+    pub num_slices: usize,
+    /// The edges of the bands in Hz, from the bottom of the lowest band to the
+    /// top of the highest (`NUM_COLLISION_BANDS + 1` values).
+    pub band_edges: Vec<f32>,
+    /// The amount of collision from `0.0` to `1.0`, stored slice by slice with
+    /// `NUM_COLLISION_BANDS` values per slice.
+    pub heat: Vec<f32>,
+}
+
+impl CollisionMap {
+    pub fn get(&self, slice: usize, band: usize) -> f32 {
+        self.heat[slice * NUM_COLLISION_BANDS + band]
+    }
+
+    /// The average amount of collision in each band over the whole map.
+    pub fn band_totals(&self) -> Vec<f32> {
+        let mut totals = vec![0.0; NUM_COLLISION_BANDS];
+        if self.num_slices == 0 {
+            return totals;
+        }
+
+        for slice in self.heat.chunks_exact(NUM_COLLISION_BANDS) {
+            for (t, h) in totals.iter_mut().zip(slice.iter()) {
+                *t += h;
+            }
+        }
+        totals.iter_mut().for_each(|t| *t /= self.num_slices as f32);
+        totals
+    }
+}
+
+/// Add the given frames of the resource, summed to mono, into `out` starting
+/// at `dst_start`. `out` is grown as needed.
+pub fn mix_into_mono(out: &mut Vec<f32>, pcm: &PcmRAM, src_frames: Range<usize>, dst_start: usize) {
+    let src_frames = src_frames.start..src_frames.end.min(pcm.len_frames() as usize);
+    let len = src_frames.end.saturating_sub(src_frames.start);
+    if len == 0 {
+        return;
+    }
+
+    let mut l = vec![0.0; len];
+    let mut r = vec![0.0; len];
+    pcm.fill_stereo_f32(src_frames.start, &mut l, &mut r);
+
+    if out.len() < dst_start + len {
+        out.resize(dst_start + len, 0.0);
+    }
+    for (i, (l, r)) in l.iter().zip(r.iter()).enumerate() {
+        out[dst_start + i] += (l + r) * 0.5;
+    }
+}
+
+/// Compare the spectrums of the two mono signals over time.
+pub fn analyze_collisions(
+    a: &[f32],
+    b: &[f32],
+    sample_rate: f32,
+    settings: &CollisionSettings,
+) -> CollisionMap {
+    let band_edges: Vec<f32> = (0..=NUM_COLLISION_BANDS)
+        .map(|i| {
+            MIN_BAND_FREQ
+                * (MAX_BAND_FREQ / MIN_BAND_FREQ).powf(i as f32 / NUM_COLLISION_BANDS as f32)
+        })
+        .collect();
+
+    let slice_frames = ((settings.slice_secs * sample_rate) as usize).max(1);
+    let len = a.len().max(b.len());
+    let num_slices = (len + slice_frames - 1) / slice_frames;
+
+    let mut analyzer = BandAnalyzer::new(sample_rate, &band_edges);
+    let mut heat = Vec::with_capacity(num_slices * NUM_COLLISION_BANDS);
+
+    for slice in 0..num_slices {
+        // Center the FFT window on the middle of the slice.
+        let center = slice * slice_frames + slice_frames / 2;
+
+        let bands_a = analyzer.bands_db(a, center);
+        let bands_b = analyzer.bands_db(b, center);
+
+        heat.extend(
+            bands_a.iter().zip(bands_b.iter()).map(|(a, b)| collision_amount(*a, *b, settings)),
+        );
+    }
+
+    CollisionMap { slice_secs: slice_frames as f32 / sample_rate, num_slices, band_edges, heat }
+}
+
+fn collision_amount(a_db: f32, b_db: f32, settings: &CollisionSettings) -> f32 {
+    let quieter = a_db.min(b_db);
+    if quieter <= settings.threshold_db {
+        return 0.0;
+    }
+
+    // Signals at similar levels mask each other the most.
+    let closeness = (1.0 - (a_db - b_db).abs() / settings.masking_range_db).max(0.0);
+    // Collisions between quiet parts matter less than ones between loud parts.
+    let loudness = ((quieter - settings.threshold_db) / -settings.threshold_db).clamp(0.0, 1.0);
+
+    closeness * loudness
+}
+
+struct BandAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    buf: Vec<Complex<f32>>,
+    /// The range of FFT bins in each band.
+    band_bins: Vec<Range<usize>>,
+}
+
+impl BandAnalyzer {
+    fn new(sample_rate: f32, band_edges: &[f32]) -> Self {
+        let bin_width = sample_rate / FFT_SIZE as f32;
+        let max_bin = FFT_SIZE / 2;
+
+        let band_bins = band_edges
+            .windows(2)
+            .map(|edges| {
+                let start = ((edges[0] / bin_width).round() as usize).min(max_bin);
+                let end = ((edges[1] / bin_width).round() as usize).clamp(start + 1, max_bin + 1);
+                start..end
+            })
+            .collect();
+
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window: (0..FFT_SIZE)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+                .collect(),
+            buf: vec![Complex::new(0.0, 0.0); FFT_SIZE],
+            band_bins,
+        }
+    }
+
+    /// The average level in dB of each band of the window centered on `center`.
+    fn bands_db(&mut self, signal: &[f32], center: usize) -> [f32; NUM_COLLISION_BANDS] {
+        let start = center as isize - (FFT_SIZE / 2) as isize;
+        for (i, b) in self.buf.iter_mut().enumerate() {
+            let pos = start + i as isize;
+            let x =
+                if pos >= 0 && (pos as usize) < signal.len() { signal[pos as usize] } else { 0.0 };
+            *b = Complex::new(x * self.window[i], 0.0);
+        }
+        self.fft.process(&mut self.buf);
+
+        // Normalize so a full scale sine reads close to 0dB.
+        let norm = 4.0 / FFT_SIZE as f32;
+
+        let mut bands = [MIN_DB; NUM_COLLISION_BANDS];
+        for (band, bins) in bands.iter_mut().zip(self.band_bins.iter()) {
+            let power: f32 = self.buf[bins.clone()].iter().map(|c| c.norm_sqr()).sum::<f32>()
+                / bins.len() as f32;
+            *band = gain_to_db(power.sqrt() * norm);
+        }
+        bands
+    }
+}
@@ -0,0 +1,90 @@
+//! The compressor of the `CompressorPlug`, apart from the plugin so it can be
+//! run without an engine.
+
+use super::dsp::{db_to_gain, gain_to_db, EnvelopeFollower};
+
+/// How many decibels a level is turned down by, with a soft knee of `knee_db`
+/// centered on the threshold.
+pub fn gain_reduction_db(level_db: f32, threshold_db: f32, ratio: f32, knee_db: f32) -> f32 {
+    let over = level_db - threshold_db;
+    let slope = 1.0 - 1.0 / ratio.max(1.0);
+    if 2.0 * over <= -knee_db {
+        0.0
+    } else if 2.0 * over.abs() < knee_db {
+        slope * (over + knee_db * 0.5).powi(2) / (2.0 * knee_db)
+    } else {
+        slope * over
+    }
+}
+
+/// The settings of a compressor for one block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressorSettings {
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub knee_db: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+/// A stereo compressor with a linked detector, so the stereo image doesn't
+/// shift.
+pub struct Compressor {
+    sample_rate: f32,
+    detector: EnvelopeFollower,
+    /// The attack and release in milliseconds the detector was set to.
+    times: (f32, f32),
+}
+
+impl Compressor {
+    pub fn new(sample_rate: f32, attack_ms: f32, release_ms: f32) -> Self {
+        Self {
+            sample_rate,
+            detector: EnvelopeFollower::new(sample_rate, attack_ms / 1_000.0, release_ms / 1_000.0),
+            times: (attack_ms, release_ms),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.detector.reset();
+    }
+
+    /// Compress a block in place, with the level detected on `sidechain`
+    /// instead if there is one. `makeup_db` has the makeup gain of every
+    /// frame. Returns the most gain reduction in the block in decibels.
+    pub fn process(
+        &mut self,
+        settings: &CompressorSettings,
+        makeup_db: &[f32],
+        left: &mut [f32],
+        right: &mut [f32],
+        sidechain: Option<(&[f32], &[f32])>,
+    ) -> f32 {
+        let times = (settings.attack_ms, settings.release_ms);
+        if times != self.times {
+            self.times = times;
+            self.detector.set_times(self.sample_rate, times.0 / 1_000.0, times.1 / 1_000.0);
+        }
+
+        let mut max_reduction_db: f32 = 0.0;
+        for i in 0..left.len() {
+            let (detect_l, detect_r) = match sidechain {
+                Some((l, r)) => (l[i], r[i]),
+                None => (left[i], right[i]),
+            };
+            let env = self.detector.process(detect_l.abs().max(detect_r.abs()));
+            let reduction_db = gain_reduction_db(
+                gain_to_db(env),
+                settings.threshold_db,
+                settings.ratio,
+                settings.knee_db,
+            );
+            max_reduction_db = max_reduction_db.max(reduction_db);
+
+            let gain = db_to_gain(makeup_db[i] - reduction_db);
+            left[i] *= gain;
+            right[i] *= gain;
+        }
+        max_reduction_db
+    }
+}
@@ -0,0 +1,403 @@
+//! A compressor with a soft knee. The level can be detected on a sidechain
+//! input instead of the signal itself, i.e. to duck a bass under the kick.
+
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::audio_ports::{
+    AudioPortInfo, AudioPortType, MainPortsLayout, PluginAudioPortsExt,
+};
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{Gradient, ParamF32, ParamF32Handle, Unit};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use super::compressor::{Compressor, CompressorSettings};
+use super::param_state::ParamState;
+
+pub static COMPRESSOR_PLUG_RDN: &str = "app.meadowlark.compressor";
+
+/// The stable ID of the main input port.
+pub const MAIN_PORT_ID: u32 = 0;
+/// The stable ID of the sidechain input port.
+pub const SIDECHAIN_PORT_ID: u32 = 1;
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+pub struct CompressorPlugFactory;
+
+impl PluginFactory for CompressorPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: COMPRESSOR_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Compressor".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(CompressorPlugMainThread::new()))
+    }
+}
+
+pub struct CompressorPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+
+    gain_reduction_db: Arc<AtomicU32>,
+    values: ParamValues,
+}
+
+impl CompressorPlugHandle {
+    /// Detect the level on the sidechain input instead of the main input.
+    pub fn set_sidechain(&mut self, sidechain: bool) {
+        self.send(ProcessMsg::SetSidechain(sidechain));
+    }
+
+    /// The current amount of gain reduction in decibels (for metering).
+    pub fn gain_reduction_db(&self) -> f32 {
+        f32::from_bits(self.gain_reduction_db.load(Ordering::Relaxed))
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Compressor plugin failed to send message: {}", e);
+        }
+    }
+}
+
+impl ParamState for CompressorPlugHandle {
+    fn param_values(&self) -> Vec<f32> {
+        self.values.iter().map(|value| f32::from_bits(value.load(Ordering::Relaxed))).collect()
+    }
+
+    fn set_param_values(&mut self, values: &[f32]) {
+        let mut params = PARAMS.map(|(_, _, _, default, _)| default);
+        for (param, value) in params.iter_mut().zip(values) {
+            *param = *value;
+        }
+        self.send(ProcessMsg::SetParams(params));
+    }
+}
+
+enum ProcessMsg {
+    SetSidechain(bool),
+    SetParams([f32; PARAMS.len()]),
+}
+
+/// The values of the parameters as bits of `f32`s, which the audio thread
+/// keeps up to date for the handle.
+type ParamValues = Arc<[AtomicU32; PARAMS.len()]>;
+
+struct ParamsHandle {
+    pub threshold: ParamF32Handle,
+    pub ratio: ParamF32Handle,
+    pub attack: ParamF32Handle,
+    pub release: ParamF32Handle,
+    pub knee: ParamF32Handle,
+    pub makeup: ParamF32Handle,
+}
+
+struct Params {
+    pub threshold: ParamF32,
+    pub ratio: ParamF32,
+    pub attack: ParamF32,
+    pub release: ParamF32,
+    pub knee: ParamF32,
+    pub makeup: ParamF32,
+}
+
+/// The name, range, default and gradient of each parameter, by ID.
+const PARAMS: [(&str, f32, f32, f32, Gradient); 6] = [
+    ("threshold", -60.0, 0.0, -18.0, Gradient::Linear),
+    ("ratio", 1.0, 20.0, 4.0, Gradient::Exponential),
+    ("attack", 0.1, 100.0, 10.0, Gradient::Exponential),
+    ("release", 10.0, 1_000.0, 100.0, Gradient::Exponential),
+    ("knee", 0.0, 12.0, 6.0, Gradient::Linear),
+    ("makeup", 0.0, 24.0, 0.0, Gradient::Linear),
+];
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let param = |id: usize| {
+            let (_, min, max, default, gradient) = PARAMS[id];
+            ParamF32::from_value(
+                default,
+                default,
+                min,
+                max,
+                gradient,
+                Unit::Generic,
+                0.0,
+                sample_rate,
+                max_frames,
+            )
+        };
+        let (threshold, threshold_handle) = param(0);
+        let (ratio, ratio_handle) = param(1);
+        let (attack, attack_handle) = param(2);
+        let (release, release_handle) = param(3);
+        let (knee, knee_handle) = param(4);
+        let (makeup, makeup_handle) = param(5);
+
+        (
+            Params { threshold, ratio, attack, release, knee, makeup },
+            ParamsHandle {
+                threshold: threshold_handle,
+                ratio: ratio_handle,
+                attack: attack_handle,
+                release: release_handle,
+                knee: knee_handle,
+                makeup: makeup_handle,
+            },
+        )
+    }
+}
+
+pub struct CompressorPlugMainThread {
+    params: ParamsHandle,
+}
+
+impl CompressorPlugMainThread {
+    fn new() -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle }
+    }
+}
+
+impl PluginMainThread for CompressorPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let gain_reduction_db = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+        let values =
+            Arc::new(PARAMS.map(|(_, _, _, default, _)| AtomicU32::new(default.to_bits())));
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(CompressorPlugAudioThread {
+                params,
+                from_handle_rx,
+                sidechain: false,
+                compressor: Compressor::new(
+                    sample_rate.0 as f32,
+                    self.params.attack.value(),
+                    self.params.release.value(),
+                ),
+                gain_reduction_db: Arc::clone(&gain_reduction_db),
+                values: Arc::clone(&values),
+            }),
+            internal_handle: Some(Box::new(CompressorPlugHandle {
+                to_audio_thread_tx,
+                gain_reduction_db,
+                values,
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<PluginAudioPortsExt, String> {
+        Ok(PluginAudioPortsExt {
+            inputs: vec![
+                AudioPortInfo {
+                    stable_id: MAIN_PORT_ID,
+                    channels: 2,
+                    port_type: AudioPortType::Stereo,
+                    display_name: None,
+                },
+                AudioPortInfo {
+                    stable_id: SIDECHAIN_PORT_ID,
+                    channels: 2,
+                    port_type: AudioPortType::Stereo,
+                    display_name: Some("sidechain".into()),
+                },
+            ],
+            outputs: vec![AudioPortInfo {
+                stable_id: 0,
+                channels: 2,
+                port_type: AudioPortType::Stereo,
+                display_name: None,
+            }],
+            main_ports_layout: MainPortsLayout::InOut,
+        })
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        PARAMS.len() as u32
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        let (name, min, max, default, _) = PARAMS.get(param_index).ok_or(())?;
+
+        Ok(ext::params::ParamInfo::new(
+            ParamID(param_index as u32),
+            ParamInfoFlags::default_float(),
+            (*name).into(),
+            String::new(),
+            f64::from(*min),
+            f64::from(*max),
+            f64::from(*default),
+        ))
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => Ok(f64::from(self.params.threshold.value())),
+            ParamID(1) => Ok(f64::from(self.params.ratio.value())),
+            ParamID(2) => Ok(f64::from(self.params.attack.value())),
+            ParamID(3) => Ok(f64::from(self.params.release.value())),
+            ParamID(4) => Ok(f64::from(self.params.knee.value())),
+            ParamID(5) => Ok(f64::from(self.params.makeup.value())),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(0) | ParamID(4) | ParamID(5) => Ok(format!("{:.1} dB", value)),
+            ParamID(1) => Ok(format!("{:.1}:1", value)),
+            ParamID(2) | ParamID(3) => Ok(format!("{:.1} ms", value)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0..=5) => text.parse().map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct CompressorPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    sidechain: bool,
+
+    compressor: Compressor,
+
+    gain_reduction_db: Arc<AtomicU32>,
+    values: ParamValues,
+}
+
+impl CompressorPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                self.set_param(param_value.param_id(), param_value.value() as f32);
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetSidechain(sidechain) => self.sidechain = sidechain,
+                ProcessMsg::SetParams(values) => {
+                    for (id, value) in values.into_iter().enumerate() {
+                        self.set_param(id as u32, value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        let (_, min, max, _, _) = match PARAMS.get(id as usize) {
+            Some(param) => *param,
+            None => return,
+        };
+        let value = value.clamp(min, max);
+        match id {
+            0 => self.params.threshold.set_value(value),
+            1 => self.params.ratio.set_value(value),
+            2 => self.params.attack.set_value(value),
+            3 => self.params.release.set_value(value),
+            4 => self.params.knee.set_value(value),
+            _ => self.params.makeup.set_value(value),
+        }
+        self.values[id as usize].store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl PluginAudioThread for CompressorPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.compressor.reset();
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+
+        let frames = proc_info.frames;
+
+        let settings = CompressorSettings {
+            threshold_db: self.params.threshold.smoothed(frames)[0],
+            ratio: self.params.ratio.smoothed(frames)[0],
+            knee_db: self.params.knee.smoothed(frames)[0],
+            attack_ms: self.params.attack.smoothed(frames)[0],
+            release_ms: self.params.release.smoothed(frames)[0],
+        };
+        let makeup = self.params.makeup.smoothed(frames);
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (sc_l, sc_r) = buffers.audio_in[1].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let out_l = &mut out_l[0..frames];
+        let out_r = &mut out_r[0..frames];
+        out_l.copy_from_slice(&in_l[0..frames]);
+        out_r.copy_from_slice(&in_r[0..frames]);
+
+        let sidechain = self.sidechain.then(|| (&sc_l[0..frames], &sc_r[0..frames]));
+        let max_reduction_db =
+            self.compressor.process(&settings, &makeup.values[0..frames], out_l, out_r, sidechain);
+
+        self.gain_reduction_db.store(max_reduction_db.to_bits(), Ordering::Relaxed);
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
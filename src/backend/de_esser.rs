@@ -0,0 +1,311 @@
+//! The de-esser of the `DeEsserPlug`, apart from the plugin so it can be run
+//! without an engine.
+
+use super::dsp::{db_to_gain, gain_to_db, Biquad, BiquadCoeffs, EnvelopeFollower};
+
+const DETECTOR_Q: f32 = 1.4;
+const DETECTOR_ATTACK_SECS: f32 = 0.001;
+const DETECTOR_RELEASE_SECS: f32 = 0.06;
+
+pub const MIN_FREQ: f32 = 2_000.0;
+pub const MAX_FREQ: f32 = 16_000.0;
+
+/// The sibilance band is first isolated with a highpass at this frequency before
+/// the zero-crossing rate is measured.
+const TRACKING_HPF_FREQ: f32 = 3_000.0;
+/// The length of the window used to estimate the sibilance frequency.
+const TRACKING_WINDOW_SECS: f32 = 0.02;
+/// How quickly the tracked frequency glides toward the latest estimate (per window).
+const TRACKING_SMOOTHING: f32 = 0.25;
+
+/// How the gain reduction is applied to the signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeEsserMode {
+    /// Only the sibilance band is turned down. The rest of the signal is untouched.
+    Split,
+    /// The whole signal is turned down whenever sibilance is detected.
+    Wideband,
+}
+
+/// The settings of a de-esser for one block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeEsserSettings {
+    pub threshold_db: f32,
+    /// The most the signal is turned down by, in decibels.
+    pub range_db: f32,
+    /// The center of the detection band, unless `auto_freq` is set.
+    pub freq: f32,
+    pub mode: DeEsserMode,
+    /// Only send the detected sibilance band to the output, so the user can
+    /// hear what is being turned down.
+    pub listen: bool,
+    /// Let the detection band follow the frequency of the sibilance instead
+    /// of staying at `freq`.
+    pub auto_freq: bool,
+}
+
+pub struct DeEsser {
+    sample_rate: f32,
+
+    /// The center frequency of the detection band.
+    current_freq: f32,
+
+    band_l: Biquad,
+    band_r: Biquad,
+    detector: EnvelopeFollower,
+
+    tracking_hpf: Biquad,
+    tracking_window_frames: usize,
+    tracking_frames: usize,
+    tracking_crossings: usize,
+    tracking_last_sign: bool,
+    tracking_sibilant: bool,
+}
+
+impl DeEsser {
+    pub fn new(sample_rate: f32, freq: f32) -> Self {
+        let band_coeffs = BiquadCoeffs::bandpass(sample_rate, freq, DETECTOR_Q);
+        let tracking_coeffs = BiquadCoeffs::highpass(sample_rate, TRACKING_HPF_FREQ, 0.707);
+
+        Self {
+            sample_rate,
+            current_freq: freq,
+            band_l: Biquad::new(band_coeffs),
+            band_r: Biquad::new(band_coeffs),
+            detector: EnvelopeFollower::new(
+                sample_rate,
+                DETECTOR_ATTACK_SECS,
+                DETECTOR_RELEASE_SECS,
+            ),
+            tracking_hpf: Biquad::new(tracking_coeffs),
+            tracking_window_frames: (TRACKING_WINDOW_SECS * sample_rate) as usize,
+            tracking_frames: 0,
+            tracking_crossings: 0,
+            tracking_last_sign: false,
+            tracking_sibilant: false,
+        }
+    }
+
+    /// The center frequency of the detection band that is currently in use.
+    pub fn freq(&self) -> f32 {
+        self.current_freq
+    }
+
+    pub fn reset(&mut self) {
+        self.band_l.reset();
+        self.band_r.reset();
+        self.detector.reset();
+        self.tracking_hpf.reset();
+    }
+
+    /// De-ess a block in place. Returns the most gain reduction in the block
+    /// in decibels.
+    pub fn process(
+        &mut self,
+        settings: &DeEsserSettings,
+        left: &mut [f32],
+        right: &mut [f32],
+    ) -> f32 {
+        if !settings.auto_freq {
+            self.set_band_freq(settings.freq);
+        }
+
+        let threshold_gain = db_to_gain(settings.threshold_db);
+        let mut max_reduction_db: f32 = 0.0;
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            if settings.auto_freq {
+                self.track_freq((*l + *r) * 0.5, threshold_gain);
+            }
+
+            let band_l = self.band_l.process(*l);
+            let band_r = self.band_r.process(*r);
+
+            // Use a linked detector so the stereo image does not shift.
+            let env = self.detector.process(band_l.abs().max(band_r.abs()));
+
+            let over_db = gain_to_db(env) - settings.threshold_db;
+            let reduction_db = over_db.clamp(0.0, settings.range_db);
+            max_reduction_db = max_reduction_db.max(reduction_db);
+
+            let gain = db_to_gain(-reduction_db);
+
+            if settings.listen {
+                *l = band_l;
+                *r = band_r;
+            } else {
+                match settings.mode {
+                    DeEsserMode::Split => {
+                        *l -= band_l * (1.0 - gain);
+                        *r -= band_r * (1.0 - gain);
+                    }
+                    DeEsserMode::Wideband => {
+                        *l *= gain;
+                        *r *= gain;
+                    }
+                }
+            }
+        }
+
+        max_reduction_db
+    }
+
+    fn set_band_freq(&mut self, freq: f32) {
+        if (freq - self.current_freq).abs() > 1.0 {
+            self.current_freq = freq;
+
+            let coeffs = BiquadCoeffs::bandpass(self.sample_rate, freq, DETECTOR_Q);
+            self.band_l.coeffs = coeffs;
+            self.band_r.coeffs = coeffs;
+        }
+    }
+
+    /// Estimate the frequency of the sibilance with the zero-crossing rate of the
+    /// highpassed signal. This is cheap and is more than accurate enough to
+    /// center a wide detection band.
+    fn track_freq(&mut self, mid: f32, threshold_gain: f32) {
+        let x = self.tracking_hpf.process(mid);
+
+        let sign = x >= 0.0;
+        if sign != self.tracking_last_sign {
+            self.tracking_crossings += 1;
+            self.tracking_last_sign = sign;
+        }
+        if x.abs() >= threshold_gain {
+            self.tracking_sibilant = true;
+        }
+
+        self.tracking_frames += 1;
+        if self.tracking_frames >= self.tracking_window_frames {
+            // Only follow the signal while there is actually sibilance present,
+            // otherwise the band would drift toward whatever noise is in the
+            // quiet parts.
+            if self.tracking_sibilant {
+                let estimate = self.tracking_crossings as f32 * self.sample_rate
+                    / (2.0 * self.tracking_frames as f32);
+                let estimate = estimate.clamp(MIN_FREQ, MAX_FREQ);
+
+                let freq = self.current_freq + (estimate - self.current_freq) * TRACKING_SMOOTHING;
+                self.set_band_freq(freq);
+            }
+
+            self.tracking_frames = 0;
+            self.tracking_crossings = 0;
+            self.tracking_sibilant = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    fn settings(mode: DeEsserMode) -> DeEsserSettings {
+        DeEsserSettings {
+            threshold_db: -30.0,
+            range_db: 12.0,
+            freq: 6_000.0,
+            mode,
+            listen: false,
+            auto_freq: false,
+        }
+    }
+
+    fn sine(freq: f32, amp: f32, frames: usize) -> Vec<f32> {
+        (0..frames).map(|i| amp * (std::f32::consts::TAU * freq * i as f32 / SR).sin()).collect()
+    }
+
+    /// The energy above 3 kHz in the second half of a signal, once the
+    /// detector has settled.
+    fn sibilant_energy(signal: &[f32]) -> f32 {
+        let mut hpf = Biquad::new(BiquadCoeffs::highpass(SR, 3_000.0, 0.707));
+        let filtered: Vec<f32> = signal.iter().map(|x| hpf.process(*x)).collect();
+        filtered[signal.len() / 2..].iter().map(|x| x * x).sum()
+    }
+
+    fn process(de_esser: &mut DeEsser, settings: &DeEsserSettings, input: &[f32]) -> Vec<f32> {
+        let mut left = input.to_vec();
+        let mut right = input.to_vec();
+        de_esser.process(settings, &mut left, &mut right);
+        assert_eq!(left, right);
+        left
+    }
+
+    #[test]
+    fn reduces_sibilant_energy() {
+        let frames = SR as usize / 2;
+        let voice = sine(300.0, 0.3, frames);
+        let ess = sine(6_500.0, 0.25, frames);
+        let input: Vec<f32> = voice.iter().zip(ess.iter()).map(|(v, s)| v + s).collect();
+
+        for mode in [DeEsserMode::Split, DeEsserMode::Wideband] {
+            let mut de_esser = DeEsser::new(SR, 6_000.0);
+            let output = process(&mut de_esser, &settings(mode), &input);
+
+            let reduction_db = 10.0 * (sibilant_energy(&output) / sibilant_energy(&input)).log10();
+            assert!(
+                reduction_db < -3.0,
+                "{:?} only reduced the sibilance by {} dB",
+                mode,
+                reduction_db
+            );
+        }
+    }
+
+    #[test]
+    fn split_mode_keeps_the_rest_of_the_signal() {
+        let frames = SR as usize / 2;
+        let voice = sine(300.0, 0.3, frames);
+        let ess = sine(6_500.0, 0.25, frames);
+        let input: Vec<f32> = voice.iter().zip(ess.iter()).map(|(v, s)| v + s).collect();
+
+        let mut de_esser = DeEsser::new(SR, 6_000.0);
+        let output = process(&mut de_esser, &settings(DeEsserMode::Split), &input);
+
+        // With the sibilance band removed from both, the voice is untouched.
+        let mut lpf_in = Biquad::new(BiquadCoeffs::lowpass(SR, 1_000.0, 0.707));
+        let mut lpf_out = Biquad::new(BiquadCoeffs::lowpass(SR, 1_000.0, 0.707));
+        let low_in: f32 = input.iter().map(|x| lpf_in.process(*x).powi(2)).sum();
+        let low_out: f32 = output.iter().map(|x| lpf_out.process(*x).powi(2)).sum();
+        assert!((10.0 * (low_out / low_in).log10()).abs() < 0.5);
+    }
+
+    #[test]
+    fn leaves_signal_below_threshold_alone() {
+        let input = sine(6_500.0, 0.01, SR as usize / 4);
+
+        let mut de_esser = DeEsser::new(SR, 6_000.0);
+        let mut left = input.clone();
+        let mut right = input.clone();
+        let reduction_db = de_esser.process(&settings(DeEsserMode::Split), &mut left, &mut right);
+
+        assert_eq!(reduction_db, 0.0);
+        assert_eq!(left, input);
+    }
+
+    #[test]
+    fn reduction_is_limited_to_range() {
+        let input = sine(6_000.0, 1.0, SR as usize / 4);
+
+        let mut de_esser = DeEsser::new(SR, 6_000.0);
+        let mut left = input.clone();
+        let mut right = input;
+        let settings = DeEsserSettings { range_db: 6.0, ..settings(DeEsserMode::Wideband) };
+        let reduction_db = de_esser.process(&settings, &mut left, &mut right);
+
+        assert_eq!(reduction_db, 6.0);
+    }
+
+    #[test]
+    fn auto_freq_follows_the_sibilance() {
+        let input = sine(9_000.0, 0.5, SR as usize);
+
+        let mut de_esser = DeEsser::new(SR, 4_000.0);
+        let settings = DeEsserSettings { auto_freq: true, ..settings(DeEsserMode::Split) };
+        process(&mut de_esser, &settings, &input);
+
+        assert!((de_esser.freq() - 9_000.0).abs() < 500.0, "tracked {} Hz", de_esser.freq());
+    }
+}
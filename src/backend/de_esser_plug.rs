@@ -0,0 +1,363 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{Gradient, ParamF32, ParamF32Handle, Unit};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+pub use super::de_esser::DeEsserMode;
+use super::de_esser::{DeEsser, DeEsserSettings, MAX_FREQ, MIN_FREQ};
+use super::dsp::gain_to_db;
+use crate::util;
+
+pub static DE_ESSER_PLUG_RDN: &str = "app.meadowlark.de-esser";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+pub struct DeEsserPlugFactory;
+
+impl PluginFactory for DeEsserPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: DE_ESSER_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "De-Esser".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(DeEsserPlugMainThread::new()))
+    }
+}
+
+pub struct DeEsserPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+
+    gain_reduction_db: Arc<AtomicU32>,
+    tracked_freq: Arc<AtomicU32>,
+}
+
+impl DeEsserPlugHandle {
+    pub fn set_mode(&mut self, mode: DeEsserMode) {
+        self.send(ProcessMsg::SetMode(mode));
+    }
+
+    /// When enabled, only the detected sibilance band is sent to the output so
+    /// the user can hear what is being turned down.
+    pub fn set_listen(&mut self, listen: bool) {
+        self.send(ProcessMsg::SetListen(listen));
+    }
+
+    /// When enabled, the detection band automatically follows the frequency of
+    /// the sibilance instead of staying at the "frequency" parameter.
+    pub fn set_auto_freq(&mut self, auto_freq: bool) {
+        self.send(ProcessMsg::SetAutoFreq(auto_freq));
+    }
+
+    /// The current amount of gain reduction in decibels (for metering).
+    pub fn gain_reduction_db(&self) -> f32 {
+        f32::from_bits(self.gain_reduction_db.load(Ordering::Relaxed))
+    }
+
+    /// The center frequency of the detection band that is currently in use.
+    pub fn tracked_freq(&self) -> f32 {
+        f32::from_bits(self.tracked_freq.load(Ordering::Relaxed))
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("De-esser plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetMode(DeEsserMode),
+    SetListen(bool),
+    SetAutoFreq(bool),
+}
+
+struct ParamsHandle {
+    pub threshold: ParamF32Handle,
+    pub range: ParamF32Handle,
+    pub freq: ParamF32Handle,
+}
+
+struct Params {
+    pub threshold: ParamF32,
+    pub range: ParamF32,
+    pub freq: ParamF32,
+}
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let (threshold, threshold_handle) = ParamF32::from_value(
+            -24.0,
+            -24.0,
+            -60.0,
+            0.0,
+            Gradient::Linear,
+            Unit::Decibels,
+            0.0,
+            sample_rate,
+            max_frames,
+        );
+        let (range, range_handle) = ParamF32::from_value(
+            12.0,
+            12.0,
+            0.0,
+            24.0,
+            Gradient::Linear,
+            Unit::Decibels,
+            0.0,
+            sample_rate,
+            max_frames,
+        );
+        let (freq, freq_handle) = ParamF32::from_value(
+            6_000.0,
+            6_000.0,
+            MIN_FREQ,
+            MAX_FREQ,
+            Gradient::Exponential,
+            Unit::Generic,
+            0.0,
+            sample_rate,
+            max_frames,
+        );
+
+        (
+            Params { threshold, range, freq },
+            ParamsHandle { threshold: threshold_handle, range: range_handle, freq: freq_handle },
+        )
+    }
+}
+
+pub struct DeEsserPlugMainThread {
+    params: ParamsHandle,
+}
+
+impl DeEsserPlugMainThread {
+    fn new() -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle }
+    }
+}
+
+impl PluginMainThread for DeEsserPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let sr = sample_rate.0 as f32;
+        let freq = self.params.freq.value();
+
+        let gain_reduction_db = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+        let tracked_freq = Arc::new(AtomicU32::new(freq.to_bits()));
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(DeEsserPlugAudioThread {
+                params,
+                from_handle_rx,
+                mode: DeEsserMode::Split,
+                listen: false,
+                auto_freq: false,
+                de_esser: DeEsser::new(sr, freq),
+                gain_reduction_db: Arc::clone(&gain_reduction_db),
+                tracked_freq: Arc::clone(&tracked_freq),
+            }),
+            internal_handle: Some(Box::new(DeEsserPlugHandle {
+                to_audio_thread_tx,
+                gain_reduction_db,
+                tracked_freq,
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        3
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        match param_index {
+            0 => Ok(ext::params::ParamInfo::new(
+                ParamID(0),
+                ParamInfoFlags::default_float(),
+                "threshold".into(),
+                String::new(),
+                -60.0,
+                0.0,
+                -24.0,
+            )),
+            1 => Ok(ext::params::ParamInfo::new(
+                ParamID(1),
+                ParamInfoFlags::default_float(),
+                "range".into(),
+                String::new(),
+                0.0,
+                24.0,
+                12.0,
+            )),
+            2 => Ok(ext::params::ParamInfo::new(
+                ParamID(2),
+                ParamInfoFlags::default_float(),
+                "frequency".into(),
+                String::new(),
+                f64::from(MIN_FREQ),
+                f64::from(MAX_FREQ),
+                6_000.0,
+            )),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => Ok(f64::from(self.params.threshold.value())),
+            ParamID(1) => Ok(f64::from(self.params.range.value())),
+            ParamID(2) => Ok(f64::from(self.params.freq.value())),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(0) | ParamID(1) => Ok(util::Unit::Decibels.format(value)),
+            ParamID(2) => Ok(util::Unit::Hertz.format(value)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) | ParamID(1) => util::Unit::Decibels.parse(text).ok_or(()),
+            ParamID(2) => util::Unit::Hertz.parse(text).ok_or(()),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct DeEsserPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    mode: DeEsserMode,
+    listen: bool,
+    auto_freq: bool,
+
+    de_esser: DeEsser,
+
+    gain_reduction_db: Arc<AtomicU32>,
+    tracked_freq: Arc<AtomicU32>,
+}
+
+impl DeEsserPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                match param_value.param_id() {
+                    0 => self.params.threshold.set_value(param_value.value() as f32),
+                    1 => self.params.range.set_value(param_value.value() as f32),
+                    2 => self.params.freq.set_value(param_value.value() as f32),
+                    _ => {}
+                }
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetMode(mode) => self.mode = mode,
+                ProcessMsg::SetListen(listen) => self.listen = listen,
+                ProcessMsg::SetAutoFreq(auto_freq) => self.auto_freq = auto_freq,
+            }
+        }
+    }
+}
+
+impl PluginAudioThread for DeEsserPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.de_esser.reset();
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+
+        let frames = proc_info.frames;
+
+        // The smoothed output of a parameter in decibels is a gain.
+        let settings = DeEsserSettings {
+            threshold_db: gain_to_db(self.params.threshold.smoothed(frames)[0]),
+            range_db: gain_to_db(self.params.range.smoothed(frames)[0]),
+            freq: self.params.freq.smoothed(frames)[0],
+            mode: self.mode,
+            listen: self.listen,
+            auto_freq: self.auto_freq,
+        };
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let out_l = &mut out_l[0..frames];
+        let out_r = &mut out_r[0..frames];
+        out_l.copy_from_slice(&in_l[0..frames]);
+        out_r.copy_from_slice(&in_r[0..frames]);
+
+        let max_reduction_db = self.de_esser.process(&settings, out_l, out_r);
+
+        self.gain_reduction_db.store(max_reduction_db.to_bits(), Ordering::Relaxed);
+        self.tracked_freq.store(self.de_esser.freq().to_bits(), Ordering::Relaxed);
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
@@ -0,0 +1,75 @@
+//! The delay of the `DelayPlug`, apart from the plugin so it can be run
+//! without an engine.
+
+use super::dsp::{Biquad, BiquadCoeffs, DelayLine};
+
+const TONE_Q: f32 = 0.707;
+
+/// The settings of a delay for one block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelaySettings {
+    /// The delay time in frames.
+    pub delay_frames: f32,
+    pub feedback: f32,
+    /// The cutoff of the lowpass in the feedback path.
+    pub tone: f32,
+    pub mix: f32,
+    pub ping_pong: bool,
+}
+
+/// A stereo feedback delay with a lowpass in the feedback path.
+pub struct StereoDelay {
+    sample_rate: f32,
+    lines: [DelayLine; 2],
+    /// The lowpass filters in the feedback path.
+    tone_filters: [Biquad; 2],
+    /// The cutoff the filters were set to.
+    tone: f32,
+}
+
+impl StereoDelay {
+    /// `max_delay_frames` is the longest delay the lines are allocated for.
+    pub fn new(sample_rate: f32, max_delay_frames: usize, tone: f32) -> Self {
+        Self {
+            sample_rate,
+            lines: [DelayLine::new(max_delay_frames), DelayLine::new(max_delay_frames)],
+            tone_filters: [Biquad::new(BiquadCoeffs::lowpass(sample_rate, tone, TONE_Q)); 2],
+            tone,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.lines.iter_mut().for_each(DelayLine::reset);
+        self.tone_filters.iter_mut().for_each(Biquad::reset);
+    }
+
+    /// Delay a block in place.
+    pub fn process(&mut self, settings: &DelaySettings, left: &mut [f32], right: &mut [f32]) {
+        if settings.tone != self.tone {
+            self.tone = settings.tone;
+            let coeffs = BiquadCoeffs::lowpass(self.sample_rate, settings.tone, TONE_Q);
+            self.tone_filters.iter_mut().for_each(|f| f.coeffs = coeffs);
+        }
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let (dry_l, dry_r) = (*l, *r);
+            let wet_l = self.lines[0].read(settings.delay_frames);
+            let wet_r = self.lines[1].read(settings.delay_frames);
+
+            let fb_l = self.tone_filters[0].process(wet_l) * settings.feedback;
+            let fb_r = self.tone_filters[1].process(wet_r) * settings.feedback;
+
+            if settings.ping_pong {
+                // The input goes in on the left only, and each repeat crosses over.
+                self.lines[0].write((dry_l + dry_r) * 0.5 + fb_r);
+                self.lines[1].write(fb_l);
+            } else {
+                self.lines[0].write(dry_l + fb_l);
+                self.lines[1].write(dry_r + fb_r);
+            }
+
+            *l = dry_l + (wet_l - dry_l) * settings.mix;
+            *r = dry_r + (wet_r - dry_r) * settings.mix;
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! A stereo delay whose time can follow the tempo, with a lowpass filter in
+//! the feedback path so each repeat is darker than the last, and an optional
+//! ping-pong mode where the repeats bounce between the channels.
+
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{Gradient, ParamF32, ParamF32Handle, Unit};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use super::delay::{DelaySettings, StereoDelay};
+use super::param_state::ParamState;
+use super::tempo_bus::{SyncedTime, TempoBus};
+
+pub static DELAY_PLUG_RDN: &str = "app.meadowlark.delay";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// The longest delay. The delay lines are allocated for this when the plugin
+/// is activated, so a delay in beats is cut short at very slow tempos.
+pub const MAX_DELAY_SECS: f32 = 4.0;
+
+const MIN_DELAY_SECS: f32 = 0.001;
+
+const MIN_TONE_FREQ: f32 = 500.0;
+const MAX_TONE_FREQ: f32 = 20_000.0;
+
+pub struct DelayPlugFactory {
+    tempo_bus: TempoBus,
+}
+
+impl DelayPlugFactory {
+    pub fn new(tempo_bus: TempoBus) -> Self {
+        Self { tempo_bus }
+    }
+}
+
+impl PluginFactory for DelayPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: DELAY_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Delay".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(DelayPlugMainThread::new(self.tempo_bus.clone())))
+    }
+}
+
+pub struct DelayPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    values: ParamValues,
+}
+
+impl DelayPlugHandle {
+    /// Set the delay time. A time in beats follows the tempo.
+    pub fn set_time(&mut self, time: SyncedTime) {
+        let time = match time {
+            SyncedTime::Seconds(secs) => {
+                SyncedTime::Seconds(secs.clamp(MIN_DELAY_SECS, MAX_DELAY_SECS))
+            }
+            SyncedTime::Beats(beats) => SyncedTime::Beats(beats.max(0.0)),
+        };
+        self.send(ProcessMsg::SetTime(time));
+    }
+
+    /// Bounce the repeats between the left and right channel.
+    pub fn set_ping_pong(&mut self, ping_pong: bool) {
+        self.send(ProcessMsg::SetPingPong(ping_pong));
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Delay plugin failed to send message: {}", e);
+        }
+    }
+}
+
+impl ParamState for DelayPlugHandle {
+    fn param_values(&self) -> Vec<f32> {
+        self.values.iter().map(|value| f32::from_bits(value.load(Ordering::Relaxed))).collect()
+    }
+
+    fn set_param_values(&mut self, values: &[f32]) {
+        let mut params = PARAMS.map(|(_, _, _, default, _)| default);
+        for (param, value) in params.iter_mut().zip(values) {
+            *param = *value;
+        }
+        self.send(ProcessMsg::SetParams(params));
+    }
+}
+
+enum ProcessMsg {
+    SetTime(SyncedTime),
+    SetPingPong(bool),
+    SetParams([f32; PARAMS.len()]),
+}
+
+/// The values of the parameters as bits of `f32`s, which the audio thread
+/// keeps up to date for the handle.
+type ParamValues = Arc<[AtomicU32; PARAMS.len()]>;
+
+struct ParamsHandle {
+    pub feedback: ParamF32Handle,
+    pub mix: ParamF32Handle,
+    pub tone: ParamF32Handle,
+}
+
+struct Params {
+    pub feedback: ParamF32,
+    pub mix: ParamF32,
+    pub tone: ParamF32,
+}
+
+/// The name, range, default and gradient of each parameter, by ID.
+const PARAMS: [(&str, f32, f32, f32, Gradient); 3] = [
+    ("feedback", 0.0, 0.95, 0.4, Gradient::Linear),
+    ("mix", 0.0, 1.0, 0.3, Gradient::Linear),
+    ("tone", MIN_TONE_FREQ, MAX_TONE_FREQ, 6_000.0, Gradient::Exponential),
+];
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let param = |id: usize| {
+            let (_, min, max, default, gradient) = PARAMS[id];
+            ParamF32::from_value(
+                default,
+                default,
+                min,
+                max,
+                gradient,
+                Unit::Generic,
+                0.0,
+                sample_rate,
+                max_frames,
+            )
+        };
+        let (feedback, feedback_handle) = param(0);
+        let (mix, mix_handle) = param(1);
+        let (tone, tone_handle) = param(2);
+
+        (
+            Params { feedback, mix, tone },
+            ParamsHandle { feedback: feedback_handle, mix: mix_handle, tone: tone_handle },
+        )
+    }
+}
+
+pub struct DelayPlugMainThread {
+    params: ParamsHandle,
+    tempo_bus: TempoBus,
+}
+
+impl DelayPlugMainThread {
+    fn new(tempo_bus: TempoBus) -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle, tempo_bus }
+    }
+}
+
+impl PluginMainThread for DelayPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let sr = sample_rate.0 as f32;
+        let max_delay_frames = (MAX_DELAY_SECS * sr).ceil() as usize;
+        let tone = self.params.tone.value();
+        let values =
+            Arc::new(PARAMS.map(|(_, _, _, default, _)| AtomicU32::new(default.to_bits())));
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(DelayPlugAudioThread {
+                params,
+                from_handle_rx,
+                sample_rate: sr,
+                tempo_bus: self.tempo_bus.clone(),
+                time: SyncedTime::Beats(0.5),
+                ping_pong: false,
+                delay: StereoDelay::new(sr, max_delay_frames, tone),
+                values: Arc::clone(&values),
+            }),
+            internal_handle: Some(Box::new(DelayPlugHandle { to_audio_thread_tx, values })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        PARAMS.len() as u32
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        let (name, min, max, default, _) = PARAMS.get(param_index).ok_or(())?;
+
+        Ok(ext::params::ParamInfo::new(
+            ParamID(param_index as u32),
+            ParamInfoFlags::default_float(),
+            (*name).into(),
+            String::new(),
+            f64::from(*min),
+            f64::from(*max),
+            f64::from(*default),
+        ))
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => Ok(f64::from(self.params.feedback.value())),
+            ParamID(1) => Ok(f64::from(self.params.mix.value())),
+            ParamID(2) => Ok(f64::from(self.params.tone.value())),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(0) | ParamID(1) => Ok(format!("{:.0}%", value * 100.0)),
+            ParamID(2) => Ok(format!("{:.0} Hz", value)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) | ParamID(1) => {
+                text.trim_end_matches('%').trim().parse::<f64>().map(|v| v / 100.0).map_err(|_| ())
+            }
+            ParamID(2) => text.trim_end_matches("Hz").trim().parse().map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct DelayPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    sample_rate: f32,
+    tempo_bus: TempoBus,
+
+    time: SyncedTime,
+    ping_pong: bool,
+
+    delay: StereoDelay,
+
+    values: ParamValues,
+}
+
+impl DelayPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                self.set_param(param_value.param_id(), param_value.value() as f32);
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetTime(time) => self.time = time,
+                ProcessMsg::SetPingPong(ping_pong) => self.ping_pong = ping_pong,
+                ProcessMsg::SetParams(values) => {
+                    for (id, value) in values.into_iter().enumerate() {
+                        self.set_param(id as u32, value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        let (_, min, max, _, _) = match PARAMS.get(id as usize) {
+            Some(param) => *param,
+            None => return,
+        };
+        let value = value.clamp(min, max);
+        match id {
+            0 => self.params.feedback.set_value(value),
+            1 => self.params.mix.set_value(value),
+            _ => self.params.tone.set_value(value),
+        }
+        self.values[id as usize].store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl PluginAudioThread for DelayPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.delay.reset();
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+
+        let frames = proc_info.frames;
+
+        // Read the tempo once per block so a delay in beats follows every change.
+        let settings = DelaySettings {
+            delay_frames: self.time.secs(self.tempo_bus.bpm()).max(MIN_DELAY_SECS)
+                * self.sample_rate,
+            feedback: self.params.feedback.smoothed(frames)[0],
+            tone: self.params.tone.smoothed(frames)[0],
+            mix: self.params.mix.smoothed(frames)[0],
+            ping_pong: self.ping_pong,
+        };
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let out_l = &mut out_l[0..frames];
+        let out_r = &mut out_r[0..frames];
+        out_l.copy_from_slice(&in_l[0..frames]);
+        out_r.copy_from_slice(&in_r[0..frames]);
+
+        self.delay.process(&settings, out_l, out_r);
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
@@ -0,0 +1,288 @@
+//! Offline spectral noise reduction.
+//!
+//! A noise profile is learned from a selection of a recording that contains
+//! only noise (i.e. room tone before someone starts talking). That profile is
+//! then subtracted from the spectrum of the whole recording.
+
+use pcm_loader::PcmRAM;
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::dsp::db_to_gain;
+
+pub const DEFAULT_FFT_SIZE: usize = 2048;
+
+/// The number of overlapping windows per FFT frame.
+const OVERLAP: usize = 4;
+
+/// The average magnitude spectrum of the noise in a recording.
+#[derive(Debug, Clone)]
+pub struct NoiseProfile {
+    fft_size: usize,
+    /// The average magnitude of each bin from `0` to `fft_size / 2` (inclusive).
+    magnitudes: Vec<f32>,
+}
+
+impl NoiseProfile {
+    /// Learn the noise profile from the given selection of the resource.
+    ///
+    /// Returns `None` if the selection is shorter than `fft_size`.
+    pub fn learn(pcm: &PcmRAM, selection: Range<usize>, fft_size: usize) -> Option<Self> {
+        let selection = selection.start..selection.end.min(pcm.len_frames() as usize);
+        if selection.end < selection.start + fft_size {
+            return None;
+        }
+
+        let (l, r) = read_stereo(pcm, selection);
+        let mono: Vec<f32> = l.iter().zip(r.iter()).map(|(l, r)| (l + r) * 0.5).collect();
+
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        let window = hann_window(fft_size);
+        let hop = fft_size / OVERLAP;
+
+        let mut magnitudes = vec![0.0; fft_size / 2 + 1];
+        let mut buf = vec![Complex::new(0.0, 0.0); fft_size];
+        let mut num_frames = 0;
+
+        let mut start = 0;
+        while start + fft_size <= mono.len() {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = Complex::new(mono[start + i] * window[i], 0.0);
+            }
+            fft.process(&mut buf);
+
+            for (m, b) in magnitudes.iter_mut().zip(buf.iter()) {
+                *m += b.norm();
+            }
+
+            num_frames += 1;
+            start += hop;
+        }
+
+        magnitudes.iter_mut().for_each(|m| *m /= num_frames as f32);
+
+        Some(Self { fft_size, magnitudes })
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DenoiseSettings {
+    /// The most the noise will be turned down by in dB.
+    pub reduction_db: f32,
+    /// How much of the noise profile is subtracted. Higher values remove more
+    /// noise at the cost of more artifacts.
+    pub sensitivity: f32,
+    /// How much the gain of each bin is smoothed over time in the range
+    /// `[0.0, 1.0)`. This reduces "musical noise" artifacts.
+    pub smoothing: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        Self { reduction_db: 18.0, sensitivity: 1.5, smoothing: 0.5 }
+    }
+}
+
+/// Remove the noise described by `profile` from the whole resource.
+///
+/// Returns the denoised left and right channels.
+pub fn denoise(
+    pcm: &PcmRAM,
+    profile: &NoiseProfile,
+    settings: &DenoiseSettings,
+) -> (Vec<f32>, Vec<f32>) {
+    let (l, r) = read_stereo(pcm, 0..pcm.len_frames() as usize);
+
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(profile.fft_size);
+    let inverse = planner.plan_fft_inverse(profile.fft_size);
+
+    let l = denoise_channel(&l, profile, settings, &forward, &inverse);
+    let r = denoise_channel(&r, profile, settings, &forward, &inverse);
+
+    (l, r)
+}
+
+fn denoise_channel(
+    input: &[f32],
+    profile: &NoiseProfile,
+    settings: &DenoiseSettings,
+    forward: &Arc<dyn Fft<f32>>,
+    inverse: &Arc<dyn Fft<f32>>,
+) -> Vec<f32> {
+    let n = profile.fft_size;
+    let hop = n / OVERLAP;
+    let window = hann_window(n);
+
+    let floor = db_to_gain(-settings.reduction_db.abs());
+    let smoothing = settings.smoothing.clamp(0.0, 0.99);
+
+    let mut output = vec![0.0; input.len()];
+    let mut norm = vec![0.0; input.len()];
+
+    let mut buf = vec![Complex::new(0.0, 0.0); n];
+    let mut gains = vec![1.0; n / 2 + 1];
+
+    // Start before the beginning so the first samples are covered by a full
+    // set of overlapping windows.
+    let mut start = -((n - hop) as isize);
+    while start < input.len() as isize {
+        for (i, b) in buf.iter_mut().enumerate() {
+            let pos = start + i as isize;
+            let x = if pos < 0 { 0.0 } else { input.get(pos as usize).copied().unwrap_or(0.0) };
+            *b = Complex::new(x * window[i], 0.0);
+        }
+        forward.process(&mut buf);
+
+        for bin in 0..=n / 2 {
+            let mag = buf[bin].norm();
+            let noise = profile.magnitudes[bin] * settings.sensitivity;
+
+            let g = if mag > f32::EPSILON { (1.0 - noise / mag).max(floor) } else { floor };
+            gains[bin] = smoothing * gains[bin] + (1.0 - smoothing) * g;
+
+            buf[bin] *= gains[bin];
+            if bin > 0 && bin < n / 2 {
+                buf[n - bin] = buf[bin].conj();
+            }
+        }
+
+        inverse.process(&mut buf);
+
+        for (i, b) in buf.iter().enumerate() {
+            let pos = start + i as isize;
+            if pos >= 0 && (pos as usize) < output.len() {
+                output[pos as usize] += b.re / n as f32 * window[i];
+                norm[pos as usize] += window[i] * window[i];
+            }
+        }
+
+        start += hop as isize;
+    }
+
+    for (y, w) in output.iter_mut().zip(norm.iter()) {
+        if *w > f32::EPSILON {
+            *y /= w;
+        }
+    }
+
+    output
+}
+
+fn read_stereo(pcm: &PcmRAM, frames: Range<usize>) -> (Vec<f32>, Vec<f32>) {
+    let len = frames.end.saturating_sub(frames.start);
+
+    let mut l = vec![0.0; len];
+    let mut r = vec![0.0; len];
+    pcm.fill_stereo_f32(frames.start, &mut l, &mut r);
+
+    (l, r)
+}
+
+/// A periodic Hann window, which sums to a constant when overlapped by 75%.
+fn hann_window(size: usize) -> Vec<f32> {
+    (0..size).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / size as f32).cos()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::dsp::WhiteNoise;
+    use pcm_loader::PcmRAMType;
+
+    const SR: u32 = 48_000;
+    const FFT_SIZE: usize = 256;
+
+    fn pcm(samples: Vec<f32>) -> PcmRAM {
+        PcmRAM::new(PcmRAMType::F32(vec![samples]), SR)
+    }
+
+    fn noise(len: usize, amplitude: f32, seed: u32) -> Vec<f32> {
+        let mut noise = WhiteNoise::new(seed);
+        (0..len).map(|_| noise.next() * amplitude).collect()
+    }
+
+    /// A sine that falls right on bin `bin` of the FFT.
+    fn tone(len: usize, bin: usize, amplitude: f32) -> Vec<f32> {
+        let w = 2.0 * PI * bin as f32 / FFT_SIZE as f32;
+        (0..len).map(|i| (i as f32 * w).sin() * amplitude).collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn a_profile_needs_a_whole_fft_of_noise() {
+        let pcm = pcm(noise(1_000, 0.1, 1));
+        assert!(NoiseProfile::learn(&pcm, 0..FFT_SIZE - 1, FFT_SIZE).is_none());
+        // Selections are clamped to the end of the resource.
+        assert!(NoiseProfile::learn(&pcm, 800..2_000, FFT_SIZE).is_none());
+
+        let profile = NoiseProfile::learn(&pcm, 0..FFT_SIZE, FFT_SIZE).unwrap();
+        assert_eq!(profile.fft_size(), FFT_SIZE);
+        assert_eq!(profile.magnitudes.len(), FFT_SIZE / 2 + 1);
+    }
+
+    #[test]
+    fn the_profile_is_the_spectrum_of_the_selection() {
+        let pcm = pcm(tone(4 * FFT_SIZE, 10, 0.5));
+        let profile = NoiseProfile::learn(&pcm, 0..4 * FFT_SIZE, FFT_SIZE).unwrap();
+
+        let loudest = (0..profile.magnitudes.len())
+            .max_by(|a, b| profile.magnitudes[*a].total_cmp(&profile.magnitudes[*b]))
+            .unwrap();
+        assert_eq!(loudest, 10);
+        assert!(profile.magnitudes[40] < profile.magnitudes[10] * 1e-3);
+    }
+
+    #[test]
+    fn a_recording_without_its_noise_is_left_as_it_is() {
+        let samples = tone(8 * FFT_SIZE, 10, 0.5);
+        let silence = pcm(vec![0.0; 2 * FFT_SIZE]);
+        let profile = NoiseProfile::learn(&silence, 0..2 * FFT_SIZE, FFT_SIZE).unwrap();
+
+        let (l, r) = denoise(&pcm(samples.clone()), &profile, &DenoiseSettings::default());
+        assert_eq!(l.len(), samples.len());
+        assert_eq!(l, r);
+        assert!(l.iter().zip(samples.iter()).all(|(a, b)| (a - b).abs() < 1e-4));
+    }
+
+    #[test]
+    fn noise_is_turned_down_as_far_as_the_reduction() {
+        let samples = noise(16 * FFT_SIZE, 0.1, 1);
+        let pcm = pcm(samples.clone());
+        let profile = NoiseProfile::learn(&pcm, 0..4 * FFT_SIZE, FFT_SIZE).unwrap();
+
+        let settings = DenoiseSettings { reduction_db: 12.0, ..DenoiseSettings::default() };
+        let (l, _) = denoise(&pcm, &profile, &settings);
+        let reduction = rms(&samples) / rms(&l);
+        assert!(reduction > db_to_gain(9.0), "reduced by {}", reduction);
+        assert!(reduction < db_to_gain(15.0), "reduced by {}", reduction);
+    }
+
+    #[test]
+    fn a_tone_stays_while_the_noise_around_it_goes() {
+        let len = 16 * FFT_SIZE;
+        let noise_only = noise(4 * FFT_SIZE, 0.05, 1);
+        let clean = tone(len, 10, 0.5);
+        let noisy: Vec<f32> = clean.iter().zip(noise(len, 0.05, 2)).map(|(t, n)| t + n).collect();
+
+        let profile = NoiseProfile::learn(&pcm(noise_only), 0..4 * FFT_SIZE, FFT_SIZE).unwrap();
+        let (l, _) = denoise(&pcm(noisy.clone()), &profile, &DenoiseSettings::default());
+
+        let error = |samples: &[f32]| {
+            let diff: Vec<f32> = samples.iter().zip(clean.iter()).map(|(a, b)| a - b).collect();
+            rms(&diff)
+        };
+        assert!(error(&l) < error(&noisy) * 0.6);
+        assert!((rms(&l) - rms(&clean)).abs() < rms(&clean) * 0.1);
+    }
+}
@@ -0,0 +1,508 @@
+//! Writes recorded tracks to disk on a pool of threads, so a slow disk never
+//! blocks the audio thread.
+//!
+//! Each track that is recording has a ring buffer that the audio thread pushes
+//! into with a `TrackRecorder`. The buffers are big enough to hold the input
+//! for as long as a disk can stall (`WORST_CASE_SEEK_SECS`), and the worker
+//! threads drain them into WAV files. If a buffer does overflow anyway, the
+//! block that didn't fit is dropped and counted, so the recording can be
+//! flagged instead of silently missing audio. See `DiskWriterPool::status()`.
+
+use crossbeam::channel::{self, Receiver, Sender, TryRecvError};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::util::WavWriter;
+
+/// The longest a write to disk is expected to stall, e.g. while a hard drive
+/// seeks between the files of many tracks.
+pub const WORST_CASE_SEEK_SECS: f64 = 0.5;
+
+/// The number of writer threads that `DiskWriterPool::default()` starts. Each
+/// thread writes many tracks.
+pub const DEFAULT_DISK_WRITER_THREADS: usize = 4;
+
+/// How long a writer thread sleeps when none of its buffers had anything in
+/// them.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The longest `DiskWriterPool::discard_tracks()` waits for the writers to
+/// close the files it deletes.
+const DISCARD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Recorded tracks are interleaved stereo.
+const CHANNELS: usize = 2;
+
+/// The size of the ring buffer of a track in frames: enough for the longest
+/// stall, the time between two polls and one more block from the audio thread.
+pub fn ring_buffer_frames(sample_rate: SampleRate, max_block_frames: usize) -> usize {
+    let secs = WORST_CASE_SEEK_SECS + POLL_INTERVAL.as_secs_f64();
+    (secs * sample_rate.0).ceil() as usize + max_block_frames
+}
+
+/// Identifies a track that was started with `DiskWriterPool::start_track()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RecordingTrackId(u64);
+
+/// The state of a recording track, shared between the audio thread, its
+/// writer thread and the pool.
+#[derive(Default)]
+struct TrackShared {
+    written_frames: AtomicU64,
+    dropped_frames: AtomicU64,
+    /// The most frames that were waiting in the ring buffer at once.
+    peak_buffered_frames: AtomicU64,
+    /// Set by the pool to ask the writer to write what is left and close the
+    /// file.
+    stop: AtomicBool,
+    /// Set by the writer once the file is closed.
+    finished: AtomicBool,
+    /// Set by the writer if writing the file failed. The track stops.
+    failed: AtomicBool,
+}
+
+/// The end of a track's ring buffer that the audio thread writes into.
+pub struct TrackRecorder {
+    tx: Producer<f32>,
+    shared: Arc<TrackShared>,
+}
+
+impl TrackRecorder {
+    /// Push a block of input. This is realtime-safe.
+    ///
+    /// If the ring buffer doesn't have room for the whole block, none of it is
+    /// pushed and it is counted as dropped. Returns false in that case.
+    pub fn write(&mut self, left: &[f32], right: &[f32]) -> bool {
+        let frames = left.len().min(right.len());
+        let chunk = match self.tx.write_chunk_uninit(frames * CHANNELS) {
+            Ok(chunk) => chunk,
+            Err(_) => {
+                self.shared.dropped_frames.fetch_add(frames as u64, Ordering::Relaxed);
+                return false;
+            }
+        };
+        chunk.fill_from_iter(
+            left[0..frames].iter().zip(right[0..frames].iter()).flat_map(|(l, r)| [*l, *r]),
+        );
+        true
+    }
+}
+
+/// What a recording track is doing, for showing to the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackRecordingStatus {
+    pub id: RecordingTrackId,
+    pub path: PathBuf,
+    pub written_frames: u64,
+    /// Frames that were lost because the disk couldn't keep up. A track with
+    /// dropped frames has gaps and should be flagged.
+    pub dropped_frames: u64,
+    /// The most the ring buffer has been filled, from 0.0 to 1.0. A value near
+    /// 1.0 means the disk barely kept up.
+    pub peak_buffer_use: f32,
+    pub finished: bool,
+    pub failed: bool,
+}
+
+/// A track being written by a writer thread.
+struct Job {
+    rx: Consumer<f32>,
+    wav: WavWriter,
+    shared: Arc<TrackShared>,
+}
+
+struct Worker {
+    tx: Option<Sender<Job>>,
+    join_handle: Option<JoinHandle<()>>,
+    tracks: usize,
+}
+
+struct PoolTrack {
+    id: RecordingTrackId,
+    path: PathBuf,
+    capacity_frames: usize,
+    worker: usize,
+    shared: Arc<TrackShared>,
+}
+
+/// Writes recording tracks to disk on a fixed number of threads.
+pub struct DiskWriterPool {
+    workers: Vec<Worker>,
+    tracks: Vec<PoolTrack>,
+    next_id: u64,
+}
+
+impl DiskWriterPool {
+    pub fn new(threads: usize) -> std::io::Result<Self> {
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let (tx, rx) = channel::unbounded();
+                let join_handle = std::thread::Builder::new()
+                    .name(format!("disk-writer-{}", i))
+                    .spawn(move || run_worker(rx))?;
+                Ok(Worker { tx: Some(tx), join_handle: Some(join_handle), tracks: 0 })
+            })
+            .collect::<std::io::Result<Vec<Worker>>>()?;
+
+        Ok(Self { workers, tracks: Vec::new(), next_id: 0 })
+    }
+
+    /// Create the file for a new track and start writing to it. Returns the
+    /// recorder to send to the audio thread.
+    ///
+    /// `max_block_frames` is the most frames the audio thread will write at
+    /// once.
+    pub fn start_track(
+        &mut self,
+        path: &Path,
+        sample_rate: SampleRate,
+        max_block_frames: usize,
+    ) -> std::io::Result<(RecordingTrackId, TrackRecorder)> {
+        let wav = WavWriter::create(path, sample_rate.0 as u32)?;
+
+        let capacity_frames = ring_buffer_frames(sample_rate, max_block_frames);
+        let (tx, rx) = RingBuffer::<f32>::new(capacity_frames * CHANNELS);
+        let shared = Arc::new(TrackShared::default());
+
+        // The thread with the fewest tracks gets the new one.
+        let worker = (0..self.workers.len()).min_by_key(|i| self.workers[*i].tracks).unwrap_or(0);
+        let job = Job { rx, wav, shared: Arc::clone(&shared) };
+        let sent = self.workers[worker].tx.as_ref().map_or(false, |tx| tx.send(job).is_ok());
+        if !sent {
+            // Nothing is going to write the file that was just created.
+            let _ = std::fs::remove_file(path);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "The disk writer thread has stopped",
+            ));
+        }
+        self.workers[worker].tracks += 1;
+
+        let id = RecordingTrackId(self.next_id);
+        self.next_id += 1;
+        self.tracks.push(PoolTrack {
+            id,
+            path: path.to_path_buf(),
+            capacity_frames,
+            worker,
+            shared: Arc::clone(&shared),
+        });
+
+        Ok((id, TrackRecorder { tx, shared }))
+    }
+
+    /// Stop a track. What is left in its buffer is still written before the
+    /// file is closed; `status()` shows when that is done.
+    pub fn stop_track(&mut self, id: RecordingTrackId) {
+        if let Some(track) = self.tracks.iter().find(|t| t.id == id) {
+            track.shared.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stop_all(&mut self) {
+        for track in self.tracks.iter() {
+            track.shared.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The status of every track that was started and not yet removed with
+    /// `remove_finished()`.
+    pub fn status(&self) -> Vec<TrackRecordingStatus> {
+        self.tracks
+            .iter()
+            .map(|track| {
+                let shared = &track.shared;
+                TrackRecordingStatus {
+                    id: track.id,
+                    path: track.path.clone(),
+                    written_frames: shared.written_frames.load(Ordering::Relaxed),
+                    dropped_frames: shared.dropped_frames.load(Ordering::Relaxed),
+                    peak_buffer_use: shared.peak_buffered_frames.load(Ordering::Relaxed) as f32
+                        / track.capacity_frames.max(1) as f32,
+                    finished: shared.finished.load(Ordering::Relaxed),
+                    failed: shared.failed.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
+    /// The frames dropped by all tracks together.
+    pub fn dropped_frames(&self) -> u64 {
+        self.tracks.iter().map(|t| t.shared.dropped_frames.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Stop tracks and throw away what they recorded, i.e. when a recording
+    /// fails to start. Once the writers have closed the files, the files are
+    /// deleted and the tracks are forgotten.
+    ///
+    /// This waits for the writers, which only takes a poll of theirs for
+    /// tracks that haven't recorded anything yet.
+    pub fn discard_tracks(&mut self, ids: &[RecordingTrackId]) {
+        for id in ids.iter() {
+            self.stop_track(*id);
+        }
+
+        let deadline = Instant::now() + DISCARD_TIMEOUT;
+        let is_open = |track: &PoolTrack| {
+            ids.contains(&track.id) && !track.shared.finished.load(Ordering::Relaxed)
+        };
+        while self.tracks.iter().any(is_open) && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        let mut i = 0;
+        while i < self.tracks.len() {
+            if !ids.contains(&self.tracks[i].id) {
+                i += 1;
+                continue;
+            }
+            let track = self.tracks.remove(i);
+            self.workers[track.worker].tracks -= 1;
+            if let Err(e) = std::fs::remove_file(&track.path) {
+                log::error!("Failed to delete discarded track {:?}: {}", &track.path, e);
+            }
+        }
+    }
+
+    /// Forget the tracks whose files are closed, returning their final status.
+    pub fn remove_finished(&mut self) -> Vec<TrackRecordingStatus> {
+        let finished: Vec<TrackRecordingStatus> =
+            self.status().into_iter().filter(|status| status.finished).collect();
+        for status in finished.iter() {
+            if let Some(i) = self.tracks.iter().position(|t| t.id == status.id) {
+                let track = self.tracks.remove(i);
+                self.workers[track.worker].tracks -= 1;
+            }
+        }
+        finished
+    }
+}
+
+impl Default for DiskWriterPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_DISK_WRITER_THREADS).expect("Failed to start the disk writer threads")
+    }
+}
+
+impl Drop for DiskWriterPool {
+    fn drop(&mut self) {
+        // Let every track finish writing, then stop the threads.
+        self.stop_all();
+        for worker in self.workers.iter_mut() {
+            worker.tx = None;
+        }
+        for worker in self.workers.iter_mut() {
+            if let Some(join_handle) = worker.join_handle.take() {
+                if join_handle.join().is_err() {
+                    log::error!("A disk writer thread panicked");
+                }
+            }
+        }
+    }
+}
+
+fn run_worker(rx: Receiver<Job>) {
+    let mut jobs: Vec<Job> = Vec::new();
+    let mut scratch: Vec<f32> = Vec::new();
+
+    loop {
+        // Pick up new tracks. The pool is gone once the channel is
+        // disconnected, and the thread ends when its last track is done.
+        let disconnected = loop {
+            match rx.try_recv() {
+                Ok(job) => jobs.push(job),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+        if disconnected && jobs.is_empty() {
+            return;
+        }
+
+        let mut wrote = false;
+        let mut i = 0;
+        while i < jobs.len() {
+            match drain(&mut jobs[i], &mut scratch) {
+                Drained::Wrote => {
+                    wrote = true;
+                    i += 1;
+                }
+                Drained::Empty => i += 1,
+                Drained::Done => {
+                    let job = jobs.swap_remove(i);
+                    if let Err(e) = job.wav.finish() {
+                        log::error!("Failed to finish recorded track: {}", e);
+                        job.shared.failed.store(true, Ordering::Relaxed);
+                    }
+                    job.shared.finished.store(true, Ordering::Relaxed);
+                }
+                Drained::Failed => {
+                    let job = jobs.swap_remove(i);
+                    job.shared.failed.store(true, Ordering::Relaxed);
+                    job.shared.finished.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        if !wrote {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+enum Drained {
+    Wrote,
+    Empty,
+    /// The track was stopped and everything in its buffer is written.
+    Done,
+    Failed,
+}
+
+/// Write everything in the ring buffer of a track to its file.
+fn drain(job: &mut Job, scratch: &mut Vec<f32>) -> Drained {
+    // Read this first, so anything pushed before the stop is written.
+    let stop = job.shared.stop.load(Ordering::Relaxed);
+
+    let frames = job.rx.slots() / CHANNELS;
+    job.shared.peak_buffered_frames.fetch_max(frames as u64, Ordering::Relaxed);
+    if frames == 0 {
+        return if stop { Drained::Done } else { Drained::Empty };
+    }
+
+    scratch.clear();
+    if let Ok(chunk) = job.rx.read_chunk(frames * CHANNELS) {
+        let (first, second) = chunk.as_slices();
+        scratch.extend_from_slice(first);
+        scratch.extend_from_slice(second);
+        chunk.commit_all();
+    }
+    if let Err(e) = job.wav.write_interleaved(scratch) {
+        log::error!("Failed to write recorded track: {}", e);
+        return Drained::Failed;
+    }
+    job.shared.written_frames.store(job.wav.frames(), Ordering::Relaxed);
+
+    Drained::Wrote
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "meadowlark-disk-writer-{}-{}.wav",
+            std::process::id(),
+            name
+        ))
+    }
+
+    /// Poll the status of a track until `done` is true for it.
+    fn wait_for(
+        pool: &DiskWriterPool,
+        id: RecordingTrackId,
+        done: impl Fn(&TrackRecordingStatus) -> bool,
+    ) -> TrackRecordingStatus {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let status = pool.status().into_iter().find(|s| s.id == id).unwrap();
+            if done(&status) {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "the track never got there: {:?}", status);
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn samples(bytes: &[u8]) -> Vec<f32> {
+        bytes[44..].chunks_exact(4).map(|s| f32::from_le_bytes([s[0], s[1], s[2], s[3]])).collect()
+    }
+
+    #[test]
+    fn ring_buffers_hold_the_longest_stall_and_a_block() {
+        assert_eq!(ring_buffer_frames(SampleRate(48_000.0), 512), 24_480 + 512);
+        assert_eq!(ring_buffer_frames(SampleRate(100.0), 0), 51);
+    }
+
+    #[test]
+    fn recorded_blocks_are_written_in_order() {
+        let path = temp_path("written");
+        let mut pool = DiskWriterPool::new(2).unwrap();
+        let (id, mut recorder) = pool.start_track(&path, SampleRate(48_000.0), 64).unwrap();
+
+        for block in 0..4 {
+            let left: Vec<f32> = (0..64).map(|i| (block * 64 + i) as f32).collect();
+            let right: Vec<f32> = left.iter().map(|x| -x).collect();
+            assert!(recorder.write(&left, &right));
+        }
+        pool.stop_track(id);
+        let status = wait_for(&pool, id, |s| s.finished);
+        assert_eq!(status.written_frames, 256);
+        assert_eq!(status.dropped_frames, 0);
+        assert!(!status.failed);
+        assert!(status.peak_buffer_use > 0.0 && status.peak_buffer_use <= 1.0);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(u32::from_le_bytes([bytes[40], bytes[41], bytes[42], bytes[43]]), 256 * 8);
+        let samples = samples(&bytes);
+        assert_eq!(samples.len(), 512);
+        assert_eq!(samples[0..4], [0.0, -0.0, 1.0, -1.0]);
+        assert_eq!(samples[510..], [255.0, -255.0]);
+
+        // The track is forgotten once it is finished.
+        assert_eq!(pool.remove_finished(), [status]);
+        assert!(pool.status().is_empty());
+    }
+
+    #[test]
+    fn blocks_that_dont_fit_are_dropped_and_counted() {
+        let path = temp_path("dropped");
+        let mut pool = DiskWriterPool::new(1).unwrap();
+        // Room for 51 frames.
+        let (id, mut recorder) = pool.start_track(&path, SampleRate(100.0), 0).unwrap();
+
+        assert!(!recorder.write(&[0.5; 60], &[0.5; 60]));
+        // The shorter channel decides the length of a block.
+        assert!(recorder.write(&[0.25; 40], &[0.25; 20]));
+        assert_eq!(pool.dropped_frames(), 60);
+
+        pool.stop_track(id);
+        let status = wait_for(&pool, id, |s| s.finished);
+        assert_eq!(status.written_frames, 20);
+        assert_eq!(status.dropped_frames, 60);
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples(&bytes), [0.25; 40]);
+    }
+
+    #[test]
+    fn discarded_tracks_are_deleted() {
+        let kept_path = temp_path("kept");
+        let discarded_path = temp_path("discarded");
+        let mut pool = DiskWriterPool::new(1).unwrap();
+        let (kept, _) = pool.start_track(&kept_path, SampleRate(48_000.0), 64).unwrap();
+        let (discarded, mut recorder) =
+            pool.start_track(&discarded_path, SampleRate(48_000.0), 64).unwrap();
+        assert!(recorder.write(&[0.5; 64], &[0.5; 64]));
+
+        pool.discard_tracks(&[discarded]);
+        assert!(!discarded_path.exists());
+        let status = pool.status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].id, kept);
+        assert!(!status[0].finished);
+
+        // Dropping the pool finishes the tracks that are left.
+        drop(pool);
+        let bytes = std::fs::read(&kept_path).unwrap();
+        std::fs::remove_file(&kept_path).unwrap();
+        assert_eq!(bytes.len(), 44);
+    }
+}
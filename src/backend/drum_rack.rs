@@ -0,0 +1,565 @@
+//! A rack of drum pads on one track. Each pad plays a sample on a key of its
+//! own, tuned and filtered on its own, and pads in the same choke group cut
+//! each other off (i.e. a closed hi-hat stops an open one). A pad can also be
+//! sent to an output of its own instead of the main output of the rack, so it
+//! can be processed on a channel of its own.
+//!
+//! The `DrumRackPlug` plays the rack in the audio graph with `DrumVoices`,
+//! with a port for the main output and one for each separate output. The
+//! pads get the notes of the channel, and the main output plays into the
+//! channel the way its audio clips do.
+
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::path::{Path, PathBuf};
+
+use super::dsp::{db_to_gain, pan_gains, Biquad, BiquadCoeffs};
+use super::midi_transform::{MAX_KEY, MIN_KEY};
+
+/// The number of pads in a bank. Pads are added a bank at a time.
+pub const DRUM_PADS_PER_BANK: usize = 16;
+
+/// The most pads a rack can hold.
+pub const MAX_DRUM_PADS: usize = 128;
+
+/// The key of the first pad of a new rack, where a kick drum is in General
+/// MIDI.
+pub const FIRST_DRUM_PAD_KEY: i16 = 36;
+
+/// The most choke groups a rack has. Groups are numbered from 1.
+pub const MAX_CHOKE_GROUPS: u8 = 16;
+
+/// How far a pad can be tuned up or down in semitones.
+pub const MAX_PAD_TUNE: f32 = 24.0;
+
+/// The range of the cutoff of the lowpass filter of a pad in Hz. At the top
+/// the filter is off.
+pub const MIN_PAD_CUTOFF_HZ: f32 = 20.0;
+pub const MAX_PAD_CUTOFF_HZ: f32 = 20_000.0;
+
+/// The range of the gain of a pad in decibels.
+pub const MAX_PAD_DB: f32 = 12.0;
+
+/// The most outputs a rack has, including the main output, so the plugin
+/// that plays it has a fixed set of ports.
+pub const MAX_DRUM_OUTPUTS: usize = 17;
+
+/// The most pads that play at once. Past that, the pad that started first is
+/// cut off.
+pub const MAX_DRUM_VOICES: usize = 32;
+
+const PAD_FILTER_Q: f32 = 0.707;
+
+/// How long a pad that was choked takes to fade out, so it doesn't click.
+const CHOKE_FADE_SECS: f32 = 0.005;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DrumPad {
+    pub name: String,
+    /// The key that plays the pad.
+    pub key: i16,
+    /// The sample the pad plays, if one was chosen.
+    pub sample: Option<PathBuf>,
+    /// The tuning in semitones. Tuning a sample up also makes it shorter.
+    pub tune: f32,
+    pub cutoff_hz: f32,
+    pub gain_db: f32,
+    /// From -1.0 (left) to 1.0 (right).
+    pub pan: f32,
+    /// The pads in the same group cut each other off.
+    pub choke_group: Option<u8>,
+    /// Whether the pad plays on an output of its own instead of the main
+    /// output of the rack.
+    pub separate_output: bool,
+    /// The channel the separate output plays into.
+    #[serde(default)]
+    pub output_channel: usize,
+}
+
+impl DrumPad {
+    pub fn new(name: String, key: i16) -> Self {
+        Self {
+            name,
+            key,
+            sample: None,
+            tune: 0.0,
+            cutoff_hz: MAX_PAD_CUTOFF_HZ,
+            gain_db: 0.0,
+            pan: 0.0,
+            choke_group: None,
+            separate_output: false,
+            output_channel: 0,
+        }
+    }
+
+    /// How much faster than its own rate the sample is played.
+    pub fn pitch_ratio(&self) -> f64 {
+        2.0f64.powf(f64::from(self.tune) / 12.0)
+    }
+
+    /// The lowpass filter of the pad, or `None` if it is off.
+    pub fn filter_coeffs(&self, sample_rate: f32) -> Option<BiquadCoeffs> {
+        if self.cutoff_hz >= MAX_PAD_CUTOFF_HZ {
+            return None;
+        }
+        let cutoff = self.cutoff_hz.min(sample_rate * 0.49);
+        Some(BiquadCoeffs::lowpass(sample_rate, cutoff, PAD_FILTER_Q))
+    }
+
+    /// The gains of the left and right output of the pad, with an equal power
+    /// pan law.
+    pub fn gains(&self) -> (f32, f32) {
+        let gain = db_to_gain(self.gain_db);
+        let (l, r) = pan_gains(self.pan);
+        (gain * l, gain * r)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DrumRack {
+    pub pads: Vec<DrumPad>,
+}
+
+impl DrumRack {
+    /// Add a bank of pads on the keys after the highest pad, named with
+    /// `name(number)` where pads are numbered from 1. Returns the number of
+    /// pads that were added, which is less than a bank when the rack is full
+    /// or the keys run out.
+    pub fn add_bank(&mut self, name: impl Fn(usize) -> String) -> usize {
+        let first_key = self.pads.iter().map(|pad| pad.key + 1).max().unwrap_or(FIRST_DRUM_PAD_KEY);
+        let mut added = 0;
+        for key in first_key..=MAX_KEY {
+            if added == DRUM_PADS_PER_BANK || self.pads.len() == MAX_DRUM_PADS {
+                break;
+            }
+            self.pads.push(DrumPad::new(name(self.pads.len() + 1), key));
+            added += 1;
+        }
+        added
+    }
+
+    /// The pad a key plays. When several pads share a key, the first one
+    /// wins.
+    pub fn pad_for_key(&self, key: i16) -> Option<usize> {
+        if !(MIN_KEY..=MAX_KEY).contains(&key) {
+            return None;
+        }
+        self.pads.iter().position(|pad| pad.key == key)
+    }
+
+    /// The pads that stop when the given pad is played.
+    pub fn choked_by(&self, pad: usize) -> SmallVec<[usize; 8]> {
+        let group = match self.pads.get(pad).and_then(|p| p.choke_group) {
+            Some(group) => group,
+            None => return SmallVec::new(),
+        };
+        self.pads
+            .iter()
+            .enumerate()
+            .filter(|(i, p)| *i != pad && p.choke_group == Some(group))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The output of the rack a pad plays on. Output 0 is the main output,
+    /// and the pads with separate outputs get the outputs after it in order.
+    pub fn output_of(&self, pad: usize) -> usize {
+        match self.pads.get(pad) {
+            Some(p) if p.separate_output => {
+                1 + self.pads[..pad].iter().filter(|p| p.separate_output).count()
+            }
+            _ => 0,
+        }
+    }
+
+    /// The number of stereo outputs of the rack, including the main output.
+    pub fn num_outputs(&self) -> usize {
+        1 + self.pads.iter().filter(|p| p.separate_output).count()
+    }
+
+    /// The pads that can be played, with the samples `load` found for them.
+    /// Pads without a sample, whose sample didn't load, or whose key belongs
+    /// to an earlier pad are left out.
+    pub fn kit<S>(
+        &self,
+        sample_rate: f32,
+        mut load: impl FnMut(&Path) -> Option<S>,
+    ) -> Vec<KitPad<S>> {
+        let mut kit = Vec::new();
+        for (index, pad) in self.pads.iter().enumerate() {
+            if self.pad_for_key(pad.key) != Some(index) {
+                continue;
+            }
+            let sample = match pad.sample.as_deref().and_then(&mut load) {
+                Some(sample) => sample,
+                None => continue,
+            };
+            // Past the ports of the plugin, a pad plays on the main output.
+            let output = self.output_of(index);
+            kit.push(KitPad {
+                key: pad.key,
+                sample,
+                pitch_ratio: pad.pitch_ratio(),
+                filter: pad.filter_coeffs(sample_rate),
+                gains: pad.gains(),
+                output: if output < MAX_DRUM_OUTPUTS { output } else { 0 },
+                choke_group: pad.choke_group,
+            });
+        }
+        kit
+    }
+
+    /// The channel each separate output plays into, from output 1 on.
+    pub fn output_channels(&self) -> Vec<usize> {
+        self.pads
+            .iter()
+            .filter(|pad| pad.separate_output)
+            .map(|pad| pad.output_channel)
+            .take(MAX_DRUM_OUTPUTS - 1)
+            .collect()
+    }
+}
+
+/// The audio of the sample of a pad, at the sample rate of the project.
+pub trait PadSample {
+    fn len_frames(&self) -> usize;
+
+    /// Fill both sides from frame `start` on, with silence past the end.
+    fn fill_stereo(&self, start: usize, left: &mut [f32], right: &mut [f32]);
+}
+
+/// A pad the way `DrumVoices` plays it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KitPad<S> {
+    pub key: i16,
+    pub sample: S,
+    /// How much faster than its own rate the sample is played.
+    pub pitch_ratio: f64,
+    pub filter: Option<BiquadCoeffs>,
+    pub gains: (f32, f32),
+    /// The output of the rack the pad plays on.
+    pub output: usize,
+    pub choke_group: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Voice {
+    /// The index of the pad in the kit.
+    pad: usize,
+    /// The frame of the sample that plays next, between two frames when the
+    /// pad is tuned.
+    pos: f64,
+    velocity: f32,
+    filter_l: Biquad,
+    filter_r: Biquad,
+    /// The gain of the fade out of a choked pad, or `None` if it wasn't.
+    fade: Option<f32>,
+}
+
+/// The pads of a drum rack that are playing. Each pad plays its sample once
+/// to the end, so the end of a note doesn't stop it, only a pad that chokes
+/// it.
+pub struct DrumVoices {
+    voices: Vec<Voice>,
+    /// How much the gain of a fade out drops each frame.
+    fade_step: f32,
+    /// Where the frames of the samples are read to before they are tuned.
+    read_l: Vec<f32>,
+    read_r: Vec<f32>,
+}
+
+impl DrumVoices {
+    /// Voices that can render blocks of up to `max_frames`.
+    pub fn new(sample_rate: f32, max_frames: usize) -> Self {
+        // A pad tuned all the way up reads that much more of its sample, plus
+        // the frame to interpolate toward.
+        let max_ratio = 2.0f32.powf(MAX_PAD_TUNE / 12.0);
+        let read_len = (max_frames as f32 * max_ratio).ceil() as usize + 2;
+        Self {
+            voices: Vec::with_capacity(MAX_DRUM_VOICES),
+            fade_step: 1.0 / (CHOKE_FADE_SECS * sample_rate).max(1.0),
+            read_l: vec![0.0; read_len],
+            read_r: vec![0.0; read_len],
+        }
+    }
+
+    /// The number of pads that are playing.
+    pub fn len(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voices.is_empty()
+    }
+
+    /// Stop every pad at once, e.g. when the pads changed.
+    pub fn clear(&mut self) {
+        self.voices.clear();
+    }
+
+    /// Play the pad of a key, with a velocity from 0.0 to 1.0, and fade out
+    /// the pads it chokes.
+    pub fn note_on<S>(&mut self, kit: &[KitPad<S>], key: i16, velocity: f32) {
+        let pad = match kit.iter().position(|pad| pad.key == key) {
+            Some(pad) => pad,
+            None => return,
+        };
+
+        if let Some(group) = kit[pad].choke_group {
+            for voice in self.voices.iter_mut() {
+                if voice.pad != pad
+                    && voice.fade.is_none()
+                    && kit[voice.pad].choke_group == Some(group)
+                {
+                    voice.fade = Some(1.0);
+                }
+            }
+        }
+
+        if self.voices.len() == MAX_DRUM_VOICES {
+            self.voices.remove(0);
+        }
+        let filter = Biquad::new(kit[pad].filter.unwrap_or_default());
+        self.voices.push(Voice {
+            pad,
+            pos: 0.0,
+            velocity: velocity.clamp(0.0, 1.0),
+            filter_l: filter,
+            filter_r: filter,
+            fade: None,
+        });
+    }
+
+    /// Mix the pads that play on `output` into a block, and move them on by
+    /// its length. Every output has to be rendered for every block, or its
+    /// pads fall behind.
+    pub fn render<S: PadSample>(
+        &mut self,
+        kit: &[KitPad<S>],
+        output: usize,
+        left: &mut [f32],
+        right: &mut [f32],
+    ) {
+        let Self { voices, fade_step, read_l, read_r } = self;
+        let frames = left.len().min(right.len());
+
+        voices.retain_mut(|voice| {
+            let pad = match kit.get(voice.pad) {
+                Some(pad) => pad,
+                None => return false,
+            };
+            if pad.output != output {
+                return true;
+            }
+
+            let first = voice.pos as usize;
+            let start = voice.pos - first as f64;
+            let read_len =
+                ((start + pad.pitch_ratio * frames as f64).ceil() as usize + 2).min(read_l.len());
+            pad.sample.fill_stereo(first, &mut read_l[..read_len], &mut read_r[..read_len]);
+
+            let (gain_l, gain_r) = pad.gains;
+            for i in 0..frames {
+                let pos = start + pad.pitch_ratio * i as f64;
+                let j = (pos as usize).min(read_len - 2);
+                let frac = (pos - j as f64) as f32;
+                let l = read_l[j] + (read_l[j + 1] - read_l[j]) * frac;
+                let r = read_r[j] + (read_r[j + 1] - read_r[j]) * frac;
+
+                let mut gain = voice.velocity;
+                if let Some(fade) = &mut voice.fade {
+                    gain *= *fade;
+                    *fade = (*fade - *fade_step).max(0.0);
+                }
+                left[i] += voice.filter_l.process(l) * gain * gain_l;
+                right[i] += voice.filter_r.process(r) * gain * gain_r;
+            }
+
+            voice.pos += pad.pitch_ratio * frames as f64;
+            voice.pos < pad.sample.len_frames() as f64 && voice.fade != Some(0.0)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: f32 = 48_000.0;
+
+    /// A sample with the same frames on both sides.
+    #[derive(Debug, Clone, PartialEq)]
+    struct Frames(Vec<f32>);
+
+    impl PadSample for Frames {
+        fn len_frames(&self) -> usize {
+            self.0.len()
+        }
+
+        fn fill_stereo(&self, start: usize, left: &mut [f32], right: &mut [f32]) {
+            for (i, (l, r)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+                let frame = self.0.get(start + i).copied().unwrap_or(0.0);
+                *l = frame;
+                *r = frame;
+            }
+        }
+    }
+
+    fn rack(keys: &[i16]) -> DrumRack {
+        let pads = keys
+            .iter()
+            .map(|key| DrumPad {
+                sample: Some(PathBuf::from(format!("{}.wav", key))),
+                ..DrumPad::new(String::new(), *key)
+            })
+            .collect();
+        DrumRack { pads }
+    }
+
+    fn kit(rack: &DrumRack, frames: &[f32]) -> Vec<KitPad<Frames>> {
+        rack.kit(SR, |_| Some(Frames(frames.to_vec())))
+    }
+
+    /// Render a block of every output, with the left side of each.
+    fn render(voices: &mut DrumVoices, kit: &[KitPad<Frames>], frames: usize) -> Vec<Vec<f32>> {
+        (0..MAX_DRUM_OUTPUTS)
+            .map(|output| {
+                let mut left = vec![0.0; frames];
+                let mut right = vec![0.0; frames];
+                voices.render(kit, output, &mut left, &mut right);
+                left
+            })
+            .collect()
+    }
+
+    fn ramp(frames: usize) -> Vec<f32> {
+        (0..frames).map(|i| i as f32 / frames as f32).collect()
+    }
+
+    #[test]
+    fn banks_fill_the_keys_after_the_last_pad() {
+        let mut rack = DrumRack::default();
+        assert_eq!(rack.add_bank(|n| n.to_string()), DRUM_PADS_PER_BANK);
+        assert_eq!(rack.add_bank(|n| n.to_string()), DRUM_PADS_PER_BANK);
+        assert_eq!(rack.pads[0].key, FIRST_DRUM_PAD_KEY);
+        assert_eq!(rack.pads[DRUM_PADS_PER_BANK].key, FIRST_DRUM_PAD_KEY + 16);
+        assert_eq!(rack.pads[DRUM_PADS_PER_BANK].name, "17");
+        assert_eq!(rack.pad_for_key(FIRST_DRUM_PAD_KEY + 1), Some(1));
+        assert_eq!(rack.pad_for_key(-1), None);
+    }
+
+    #[test]
+    fn only_pads_that_can_play_are_in_the_kit() {
+        let mut rack = rack(&[36, 38, 36, 40]);
+        rack.pads[3].sample = None;
+        let kit = rack.kit(SR, |path| Some(path.to_path_buf()));
+        assert_eq!(kit.len(), 2);
+        assert_eq!(kit[0].sample, PathBuf::from("36.wav"));
+        assert_eq!(kit[1].sample, PathBuf::from("38.wav"));
+
+        assert!(rack.kit(SR, |_| None::<Frames>).is_empty());
+    }
+
+    #[test]
+    fn a_pad_plays_its_sample_once_at_its_velocity() {
+        let rack = rack(&[36]);
+        let kit = kit(&rack, &[1.0; 100]);
+        let mut voices = DrumVoices::new(SR, 64);
+
+        // Keys without a pad play nothing.
+        voices.note_on(&kit, 37, 1.0);
+        assert!(voices.is_empty());
+
+        voices.note_on(&kit, 36, 0.5);
+        let first = render(&mut voices, &kit, 64);
+        assert!(first[0].iter().all(|x| (x - 0.5 * pan_gains(0.0).0).abs() < 1e-6));
+        let second = render(&mut voices, &kit, 64);
+        assert!(second[0][..36].iter().all(|x| *x > 0.0));
+        assert!(second[0][36..].iter().all(|x| *x == 0.0));
+        assert!(voices.is_empty());
+    }
+
+    #[test]
+    fn tuning_changes_the_rate_of_the_sample() {
+        let mut rack = rack(&[36]);
+        rack.pads[0].tune = 12.0;
+        let frames = ramp(128);
+        let kit = kit(&rack, &frames);
+        let mut voices = DrumVoices::new(SR, 64);
+        voices.note_on(&kit, 36, 1.0);
+
+        // An octave up reads two frames for every frame it plays.
+        let out = render(&mut voices, &kit, 64);
+        let gain = pan_gains(0.0).0;
+        for (i, x) in out[0].iter().enumerate() {
+            assert!((x - frames[2 * i] * gain).abs() < 1e-5);
+        }
+        assert!(voices.is_empty());
+
+        // Half way between two frames is half way between their values.
+        rack.pads[0].tune = -12.0;
+        let kit = self::kit(&rack, &frames);
+        voices.note_on(&kit, 36, 1.0);
+        let out = render(&mut voices, &kit, 4);
+        let expected = (frames[0] + frames[1]) * 0.5 * gain;
+        assert!((out[0][1] - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn pads_in_a_choke_group_cut_each_other_off() {
+        let mut rack = rack(&[42, 46, 36]);
+        rack.pads[0].choke_group = Some(1);
+        rack.pads[1].choke_group = Some(1);
+        let kit = kit(&rack, &[1.0; 48_000]);
+        let mut voices = DrumVoices::new(SR, 512);
+
+        voices.note_on(&kit, 46, 1.0);
+        voices.note_on(&kit, 36, 1.0);
+        voices.note_on(&kit, 42, 1.0);
+        assert_eq!(voices.len(), 3);
+
+        // The open hi-hat fades out, and the kick in no group keeps playing.
+        render(&mut voices, &kit, 512);
+        assert_eq!(voices.len(), 2);
+        assert!(voices.voices.iter().all(|voice| voice.pad != 1));
+
+        // A pad doesn't choke itself.
+        voices.note_on(&kit, 42, 1.0);
+        assert_eq!(voices.len(), 3);
+        assert!(voices.voices.iter().all(|voice| voice.fade.is_none()));
+    }
+
+    #[test]
+    fn the_oldest_pad_stops_when_too_many_play() {
+        let rack = rack(&[36, 38]);
+        let kit = kit(&rack, &[1.0; 1_000]);
+        let mut voices = DrumVoices::new(SR, 64);
+
+        voices.note_on(&kit, 38, 1.0);
+        for _ in 0..MAX_DRUM_VOICES {
+            voices.note_on(&kit, 36, 1.0);
+        }
+        assert_eq!(voices.len(), MAX_DRUM_VOICES);
+        assert!(voices.voices.iter().all(|voice| voice.pad == 0));
+    }
+
+    #[test]
+    fn separate_outputs_only_play_their_pads() {
+        let mut rack = rack(&[36, 38, 42]);
+        rack.pads[1].separate_output = true;
+        rack.pads[1].output_channel = 3;
+        rack.pads[2].separate_output = true;
+        rack.pads[2].output_channel = 5;
+        assert_eq!(rack.num_outputs(), 3);
+        assert_eq!(rack.output_channels(), vec![3, 5]);
+
+        let kit = kit(&rack, &[1.0; 100]);
+        let mut voices = DrumVoices::new(SR, 64);
+        voices.note_on(&kit, 38, 1.0);
+        let out = render(&mut voices, &kit, 64);
+        assert!(out[0].iter().all(|x| *x == 0.0));
+        assert!(out[1].iter().all(|x| *x > 0.0));
+        assert!(out[2].iter().all(|x| *x == 0.0));
+    }
+}
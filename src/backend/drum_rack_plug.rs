@@ -0,0 +1,220 @@
+//! Plays the drum rack of a channel with the notes that come in. See
+//! `drum_rack`.
+
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::NoteOnEvent;
+use dropseed::plugin::ext::audio_ports::{
+    AudioPortInfo, AudioPortType, MainPortsLayout, PluginAudioPortsExt,
+};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use pcm_loader::PcmRAM;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use super::drum_rack::{DrumVoices, KitPad, PadSample, MAX_DRUM_OUTPUTS};
+
+pub static DRUM_RACK_PLUG_RDN: &str = "app.meadowlark.drum-rack";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// The sample of a pad, loaded at the sample rate of the project.
+#[derive(Clone)]
+pub struct PadPcm(pub Shared<PcmRAM>);
+
+impl PartialEq for PadPcm {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(&*self.0, &*other.0)
+    }
+}
+
+impl PadSample for PadPcm {
+    fn len_frames(&self) -> usize {
+        self.0.len_frames() as usize
+    }
+
+    fn fill_stereo(&self, start: usize, left: &mut [f32], right: &mut [f32]) {
+        self.0.fill_stereo_f32(start, left, right);
+    }
+}
+
+pub struct DrumRackPlugFactory;
+
+impl PluginFactory for DrumRackPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: DRUM_RACK_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Drum Rack".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(DrumRackPlugMainThread))
+    }
+}
+
+pub struct DrumRackPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    coll_handle: basedrop::Handle,
+    /// The pads that were sent last, so that they are only sent again when
+    /// they changed.
+    kit: Option<Shared<Vec<KitPad<PadPcm>>>>,
+}
+
+impl DrumRackPlugHandle {
+    /// Replace the pads that are played. The pads that are playing stop.
+    pub fn set_kit(&mut self, kit: Vec<KitPad<PadPcm>>) {
+        if self.kit.as_deref() == Some(&kit) {
+            return;
+        }
+        let kit = Shared::new(&self.coll_handle, kit);
+        match self.to_audio_thread_tx.push(ProcessMsg::SetKit(Shared::clone(&kit))) {
+            Ok(()) => self.kit = Some(kit),
+            Err(e) => log::error!("Drum rack plugin failed to send message: {}", e),
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetKit(Shared<Vec<KitPad<PadPcm>>>),
+}
+
+pub struct DrumRackPlugMainThread;
+
+impl PluginMainThread for DrumRackPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(DrumRackPlugAudioThread {
+                from_handle_rx,
+                kit: None,
+                voices: DrumVoices::new(sample_rate.0 as f32, max_frames as usize),
+            }),
+            internal_handle: Some(Box::new(DrumRackPlugHandle {
+                to_audio_thread_tx,
+                coll_handle: coll_handle.clone(),
+                kit: None,
+            })),
+        })
+    }
+
+    /// The main output, and one for each separate output a rack can have.
+    /// Outputs that no pad plays on stay silent.
+    fn audio_ports_ext(&mut self) -> Result<PluginAudioPortsExt, String> {
+        Ok(PluginAudioPortsExt {
+            inputs: Vec::new(),
+            outputs: (0..MAX_DRUM_OUTPUTS as u32)
+                .map(|stable_id| AudioPortInfo {
+                    stable_id,
+                    channels: 2,
+                    port_type: AudioPortType::Stereo,
+                    display_name: None,
+                })
+                .collect(),
+            main_ports_layout: MainPortsLayout::OutOnly,
+        })
+    }
+
+    fn note_ports_ext(&mut self) -> Result<ext::note_ports::PluginNotePortsExt, String> {
+        Ok(ext::note_ports::PluginNotePortsExt::single_in())
+    }
+}
+
+pub struct DrumRackPlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    kit: Option<Shared<Vec<KitPad<PadPcm>>>>,
+    voices: DrumVoices,
+}
+
+impl DrumRackPlugAudioThread {
+    /// Mix the pads that are playing into every output from frame `from` to
+    /// frame `to` of the block.
+    fn render(&mut self, buffers: &mut ProcBuffers, from: usize, to: usize) {
+        let kit = match &self.kit {
+            Some(kit) => kit,
+            None => return,
+        };
+        for (output, port) in buffers.audio_out.iter_mut().enumerate() {
+            let (mut out_l, mut out_r) = port.stereo_f32_mut().unwrap();
+            self.voices.render(kit, output, &mut out_l[from..to], &mut out_r[from..to]);
+        }
+    }
+}
+
+impl PluginAudioThread for DrumRackPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.voices.clear();
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                // The old pads are dropped by the collector.
+                ProcessMsg::SetKit(kit) => {
+                    self.kit = Some(kit);
+                    self.voices.clear();
+                }
+            }
+        }
+
+        let frames = proc_info.frames;
+        for port in buffers.audio_out.iter_mut() {
+            let (mut out_l, mut out_r) = port.stereo_f32_mut().unwrap();
+            out_l[0..frames].fill(0.0);
+            out_r[0..frames].fill(0.0);
+        }
+
+        // Each pad starts on the frame of its note.
+        let mut from = 0;
+        for e in in_events.iter() {
+            if let Some(note_on) = e.as_event::<NoteOnEvent>() {
+                let at = (e.header().time() as usize).clamp(from, frames);
+                self.render(buffers, from, at);
+                from = at;
+                if let Some(kit) = &self.kit {
+                    self.voices.note_on(kit, note_on.0.key(), note_on.0.velocity() as f32);
+                }
+            }
+        }
+        self.render(buffers, from, frames);
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, _in_events: &EventBuffer, _out_events: &mut EventBuffer) {}
+}
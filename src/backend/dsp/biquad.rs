@@ -0,0 +1,160 @@
+use std::f32::consts::PI;
+
+use super::flush_denormal;
+
+/// The coefficients of a biquad filter, normalized so that `a0 == 1.0`.
+///
+/// The formulas are taken from the "Audio EQ Cookbook" by Robert Bristow-Johnson.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BiquadCoeffs {
+    pub b0: f32,
+    pub b1: f32,
+    pub b2: f32,
+    pub a1: f32,
+    pub a2: f32,
+}
+
+impl BiquadCoeffs {
+    /// Coefficients which pass the signal through unchanged.
+    pub const IDENTITY: Self = Self { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0 };
+
+    pub fn lowpass(sample_rate: f32, freq: f32, q: f32) -> Self {
+        let (cos_w0, alpha) = Self::prepare(sample_rate, freq, q);
+
+        let b1 = 1.0 - cos_w0;
+        Self::normalize(b1 / 2.0, b1, b1 / 2.0, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
+    }
+
+    pub fn highpass(sample_rate: f32, freq: f32, q: f32) -> Self {
+        let (cos_w0, alpha) = Self::prepare(sample_rate, freq, q);
+
+        let b1 = -(1.0 + cos_w0);
+        Self::normalize(-b1 / 2.0, b1, -b1 / 2.0, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
+    }
+
+    /// A band-pass filter with a constant 0 dB peak gain.
+    pub fn bandpass(sample_rate: f32, freq: f32, q: f32) -> Self {
+        let (cos_w0, alpha) = Self::prepare(sample_rate, freq, q);
+
+        Self::normalize(alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha)
+    }
+
+    pub fn peak(sample_rate: f32, freq: f32, q: f32, gain_db: f32) -> Self {
+        let (cos_w0, alpha) = Self::prepare(sample_rate, freq, q);
+        let a = 10.0f32.powf(gain_db / 40.0);
+
+        Self::normalize(
+            1.0 + alpha * a,
+            -2.0 * cos_w0,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos_w0,
+            1.0 - alpha / a,
+        )
+    }
+
+    pub fn low_shelf(sample_rate: f32, freq: f32, q: f32, gain_db: f32) -> Self {
+        let (cos_w0, alpha) = Self::prepare(sample_rate, freq, q);
+        let a = 10.0f32.powf(gain_db / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        Self::normalize(
+            a * ((a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+            a * ((a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha),
+            (a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+            (a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha,
+        )
+    }
+
+    pub fn high_shelf(sample_rate: f32, freq: f32, q: f32, gain_db: f32) -> Self {
+        let (cos_w0, alpha) = Self::prepare(sample_rate, freq, q);
+        let a = 10.0f32.powf(gain_db / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        Self::normalize(
+            a * ((a + 1.0) + (a - 1.0) * cos_w0 + sqrt_a_alpha),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+            a * ((a + 1.0) + (a - 1.0) * cos_w0 - sqrt_a_alpha),
+            (a + 1.0) - (a - 1.0) * cos_w0 + sqrt_a_alpha,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+            (a + 1.0) - (a - 1.0) * cos_w0 - sqrt_a_alpha,
+        )
+    }
+
+    /// The magnitude response of the filter at the given frequency in dB.
+    pub fn magnitude_db(&self, sample_rate: f32, freq: f32) -> f32 {
+        let w = 2.0 * PI * freq / sample_rate;
+        let (cos_w, cos_2w) = (w.cos(), (2.0 * w).cos());
+        let (sin_w, sin_2w) = (w.sin(), (2.0 * w).sin());
+
+        // |H(e^jw)|^2 = |b0 + b1 e^-jw + b2 e^-2jw|^2 / |1 + a1 e^-jw + a2 e^-2jw|^2
+        let num_re = self.b0 + self.b1 * cos_w + self.b2 * cos_2w;
+        let num_im = -(self.b1 * sin_w + self.b2 * sin_2w);
+        let den_re = 1.0 + self.a1 * cos_w + self.a2 * cos_2w;
+        let den_im = -(self.a1 * sin_w + self.a2 * sin_2w);
+
+        let num = num_re * num_re + num_im * num_im;
+        let den = den_re * den_re + den_im * den_im;
+
+        10.0 * (num / den.max(f32::MIN_POSITIVE)).max(f32::MIN_POSITIVE).log10()
+    }
+
+    fn prepare(sample_rate: f32, freq: f32, q: f32) -> (f32, f32) {
+        // Keep the frequency safely below nyquist so the filter stays stable.
+        let freq = freq.clamp(1.0, sample_rate * 0.49);
+        let w0 = 2.0 * PI * freq / sample_rate;
+
+        (w0.cos(), w0.sin() / (2.0 * q.max(0.01)))
+    }
+
+    fn normalize(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        let a0_recip = 1.0 / a0;
+
+        Self {
+            b0: b0 * a0_recip,
+            b1: b1 * a0_recip,
+            b2: b2 * a0_recip,
+            a1: a1 * a0_recip,
+            a2: a2 * a0_recip,
+        }
+    }
+}
+
+impl Default for BiquadCoeffs {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// A single-channel biquad filter in transposed direct form II.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Biquad {
+    pub coeffs: BiquadCoeffs,
+
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    pub fn new(coeffs: BiquadCoeffs) -> Self {
+        Self { coeffs, z1: 0.0, z2: 0.0 }
+    }
+
+    #[inline]
+    pub fn process(&mut self, x: f32) -> f32 {
+        let c = &self.coeffs;
+
+        let y = c.b0 * x + self.z1;
+        self.z1 = flush_denormal(c.b1 * x - c.a1 * y + self.z2);
+        self.z2 = flush_denormal(c.b2 * x - c.a2 * y);
+
+        y
+    }
+
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+}
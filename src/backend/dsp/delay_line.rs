@@ -0,0 +1,49 @@
+/// A delay line with a fixed longest delay, read with linear interpolation so
+/// the delay can change smoothly.
+#[derive(Debug, Clone)]
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    write_pos: usize,
+}
+
+impl DelayLine {
+    /// Allocates room for a delay of up to `max_frames`.
+    pub fn new(max_frames: usize) -> Self {
+        Self { buffer: vec![0.0; max_frames.max(1) + 1], write_pos: 0 }
+    }
+
+    /// The longest delay in frames.
+    pub fn max_delay(&self) -> usize {
+        self.buffer.len() - 1
+    }
+
+    /// The sample from `delay` frames ago, where 1.0 is the last sample that
+    /// was written. The delay is clamped to the length of the line.
+    #[inline]
+    pub fn read(&self, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        let delay = delay.clamp(1.0, self.max_delay() as f32);
+
+        let pos = self.write_pos as f32 - delay;
+        let pos = if pos < 0.0 { pos + len as f32 } else { pos };
+        let i0 = (pos as usize).min(len - 1);
+        let i1 = if i0 + 1 == len { 0 } else { i0 + 1 };
+        let frac = pos - i0 as f32;
+
+        self.buffer[i0] + (self.buffer[i1] - self.buffer[i0]) * frac
+    }
+
+    #[inline]
+    pub fn write(&mut self, x: f32) {
+        self.buffer[self.write_pos] = x;
+        self.write_pos += 1;
+        if self.write_pos == self.buffer.len() {
+            self.write_pos = 0;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.iter_mut().for_each(|s| *s = 0.0);
+        self.write_pos = 0;
+    }
+}
@@ -0,0 +1,52 @@
+use super::flush_denormal;
+
+/// A peak envelope follower with separate attack and release times.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EnvelopeFollower {
+    attack_coeff: f32,
+    release_coeff: f32,
+
+    envelope: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(sample_rate: f32, attack_secs: f32, release_secs: f32) -> Self {
+        Self {
+            attack_coeff: Self::coeff(sample_rate, attack_secs),
+            release_coeff: Self::coeff(sample_rate, release_secs),
+            envelope: 0.0,
+        }
+    }
+
+    pub fn set_times(&mut self, sample_rate: f32, attack_secs: f32, release_secs: f32) {
+        self.attack_coeff = Self::coeff(sample_rate, attack_secs);
+        self.release_coeff = Self::coeff(sample_rate, release_secs);
+    }
+
+    /// Feed a single sample into the follower and return the new envelope value.
+    #[inline]
+    pub fn process(&mut self, x: f32) -> f32 {
+        let x = x.abs();
+
+        let coeff = if x > self.envelope { self.attack_coeff } else { self.release_coeff };
+        self.envelope = flush_denormal(x + coeff * (self.envelope - x));
+
+        self.envelope
+    }
+
+    pub fn value(&self) -> f32 {
+        self.envelope
+    }
+
+    pub fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+
+    fn coeff(sample_rate: f32, secs: f32) -> f32 {
+        if secs <= 0.0 {
+            0.0
+        } else {
+            (-1.0 / (secs * sample_rate)).exp()
+        }
+    }
+}
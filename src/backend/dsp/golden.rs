@@ -0,0 +1,451 @@
+//! Audio regression tests for the DSP building blocks and the processors of
+//! the built-in effects. Each processor runs a few canonical stereo inputs at
+//! several sample rates and block sizes. The output must not depend on the
+//! block size, and its fingerprint must match the reference stored in
+//! `tests/golden/` within `TOLERANCE`.
+//!
+//! A fingerprint is the first `HEAD_FRAMES` frames of the output, then the RMS
+//! of every `WINDOW_FRAMES` frames, for each channel. This catches both a
+//! change to the very start of a response and a change anywhere in its tail
+//! without storing every sample.
+//!
+//! A missing reference is a failure. After adding a processor or input, or a
+//! change that is meant to change the sound, write the references with
+//! `MEADOWLARK_BLESS_GOLDEN=1 cargo test golden` and commit the new files.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::{Biquad, BiquadCoeffs, DelayLine, EnvelopeFollower, LevelMatcher, WhiteNoise};
+use crate::backend::compressor::{Compressor, CompressorSettings};
+use crate::backend::de_esser::{DeEsser, DeEsserMode, DeEsserSettings};
+use crate::backend::delay::{DelaySettings, StereoDelay};
+use crate::backend::dsp::pitch::PitchShifter;
+use crate::backend::fader::Fader;
+use crate::backend::fx_rack::{FxChainMix, FxRackSummer};
+use crate::backend::reverb::{ReverbEffect, ReverbSettings};
+
+const SAMPLE_RATES: [u32; 3] = [44_100, 48_000, 96_000];
+
+/// Includes sizes that don't divide the length of the inputs.
+const BLOCK_SIZES: [usize; 5] = [1, 64, 333, 512, 4096];
+
+/// The length of every input in seconds.
+const INPUT_SECS: f64 = 0.25;
+
+/// How many frames at the start of an output are kept as they are.
+const HEAD_FRAMES: usize = 16;
+
+/// How many frames of an output each RMS value of a fingerprint covers.
+const WINDOW_FRAMES: usize = 1024;
+
+/// How far a fingerprint may be from its reference.
+const TOLERANCE: f32 = 1.0e-4;
+
+const BLESS_VAR: &str = "MEADOWLARK_BLESS_GOLDEN";
+
+/// Processes a block of a stereo input into an output of the same length.
+type Process = Box<dyn FnMut(&[f32], &[f32], &mut [f32], &mut [f32])>;
+
+/// A processor under test. `new` is given the sample rate and the largest
+/// block it will be asked to process.
+struct Processor {
+    name: &'static str,
+    new: fn(f32, usize) -> Process,
+}
+
+fn per_frame(mut f: impl FnMut(f32, f32) -> (f32, f32) + 'static) -> Process {
+    Box::new(move |in_l, in_r, out_l, out_r| {
+        for i in 0..in_l.len() {
+            (out_l[i], out_r[i]) = f(in_l[i], in_r[i]);
+        }
+    })
+}
+
+/// Runs a processor that works in place, like the ones the plugins use.
+fn in_place(mut f: impl FnMut(&mut [f32], &mut [f32]) + 'static) -> Process {
+    Box::new(move |in_l, in_r, out_l, out_r| {
+        out_l.copy_from_slice(in_l);
+        out_r.copy_from_slice(in_r);
+        f(out_l, out_r);
+    })
+}
+
+fn processors() -> Vec<Processor> {
+    vec![
+        Processor {
+            name: "biquad_lowpass",
+            new: |sr, _| {
+                let mut biquads = [Biquad::new(BiquadCoeffs::lowpass(sr, 1_000.0, 0.707)); 2];
+                per_frame(move |l, r| (biquads[0].process(l), biquads[1].process(r)))
+            },
+        },
+        Processor {
+            name: "biquad_peak",
+            new: |sr, _| {
+                let mut biquads = [Biquad::new(BiquadCoeffs::peak(sr, 3_000.0, 2.0, 9.0)); 2];
+                per_frame(move |l, r| (biquads[0].process(l), biquads[1].process(r)))
+            },
+        },
+        Processor {
+            name: "biquad_high_shelf",
+            new: |sr, _| {
+                let mut biquads =
+                    [Biquad::new(BiquadCoeffs::high_shelf(sr, 8_000.0, 0.707, -6.0)); 2];
+                per_frame(move |l, r| (biquads[0].process(l), biquads[1].process(r)))
+            },
+        },
+        Processor {
+            name: "delay_line",
+            new: |sr, _| {
+                // A feedback delay of a little over 10 ms, so the read interpolates.
+                let delay = sr * 0.0101;
+                let mut lines = [DelayLine::new(sr as usize), DelayLine::new(sr as usize)];
+                per_frame(move |l, r| {
+                    let (y_l, y_r) = (lines[0].read(delay), lines[1].read(delay));
+                    lines[0].write(l + y_l * 0.5);
+                    lines[1].write(r + y_r * 0.5);
+                    (y_l, y_r)
+                })
+            },
+        },
+        Processor {
+            name: "envelope_follower",
+            new: |sr, _| {
+                let mut env = EnvelopeFollower::new(sr, 0.005, 0.1);
+                per_frame(move |l, r| {
+                    let y = env.process(l.abs().max(r.abs()));
+                    (y, y)
+                })
+            },
+        },
+        Processor {
+            name: "level_matcher",
+            new: |sr, _| {
+                // Matches the input against itself at half the level.
+                let mut matcher = LevelMatcher::new(sr);
+                per_frame(move |l, r| {
+                    let gain = matcher.process((l, r), (l * 0.5, r * 0.5));
+                    (gain, gain)
+                })
+            },
+        },
+        Processor {
+            name: "pitch_shifter",
+            new: |sr, _| {
+                let mut shifters = [PitchShifter::new(sr, 0.03), PitchShifter::new(sr, 0.03)];
+                per_frame(move |l, r| (shifters[0].process(l, 1.5), shifters[1].process(r, 1.5)))
+            },
+        },
+        Processor {
+            name: "fx_rack_summer",
+            new: |_, max_frames| {
+                let mut summer = FxRackSummer::new(max_frames);
+                let dry = FxChainMix::default();
+                let wet = FxChainMix { gain_db: -6.0, pan: 0.5, muted: false };
+                Box::new(move |in_l, in_r, out_l, out_r| {
+                    summer.clear(in_l.len());
+                    summer.add_chain(&dry, in_l, in_r);
+                    summer.add_chain(&wet, in_l, in_r);
+                    let (l, r) = summer.output();
+                    out_l.copy_from_slice(l);
+                    out_r.copy_from_slice(r);
+                })
+            },
+        },
+        Processor {
+            name: "fader",
+            new: |sr, _| {
+                // Starts at unity, so the output follows the smoothing to the new setting.
+                let mut fader = Fader::new(sr);
+                fader.set(-6.0, 0.3, true);
+                in_place(move |l, r| fader.process(l, r))
+            },
+        },
+        Processor {
+            name: "compressor",
+            new: |sr, max_frames| {
+                let settings = CompressorSettings {
+                    threshold_db: -18.0,
+                    ratio: 4.0,
+                    knee_db: 6.0,
+                    attack_ms: 5.0,
+                    release_ms: 80.0,
+                };
+                let mut compressor = Compressor::new(sr, settings.attack_ms, settings.release_ms);
+                let makeup_db = vec![3.0; max_frames];
+                in_place(move |l, r| {
+                    compressor.process(&settings, &makeup_db[0..l.len()], l, r, None);
+                })
+            },
+        },
+        Processor {
+            name: "de_esser_split",
+            new: |sr, _| {
+                let settings = DeEsserSettings {
+                    threshold_db: -30.0,
+                    range_db: 12.0,
+                    freq: 6_000.0,
+                    mode: DeEsserMode::Split,
+                    listen: false,
+                    auto_freq: false,
+                };
+                let mut de_esser = DeEsser::new(sr, settings.freq);
+                in_place(move |l, r| {
+                    de_esser.process(&settings, l, r);
+                })
+            },
+        },
+        Processor {
+            name: "de_esser_auto_wideband",
+            new: |sr, _| {
+                let settings = DeEsserSettings {
+                    threshold_db: -30.0,
+                    range_db: 12.0,
+                    freq: 6_000.0,
+                    mode: DeEsserMode::Wideband,
+                    listen: false,
+                    auto_freq: true,
+                };
+                let mut de_esser = DeEsser::new(sr, settings.freq);
+                in_place(move |l, r| {
+                    de_esser.process(&settings, l, r);
+                })
+            },
+        },
+        Processor {
+            name: "delay",
+            new: |sr, _| {
+                let settings = DelaySettings {
+                    delay_frames: sr * 0.0373,
+                    feedback: 0.6,
+                    tone: 4_000.0,
+                    mix: 0.5,
+                    ping_pong: false,
+                };
+                let mut delay = StereoDelay::new(sr, sr as usize, 8_000.0);
+                in_place(move |l, r| delay.process(&settings, l, r))
+            },
+        },
+        Processor {
+            name: "delay_ping_pong",
+            new: |sr, _| {
+                let settings = DelaySettings {
+                    delay_frames: sr * 0.0373,
+                    feedback: 0.6,
+                    tone: 4_000.0,
+                    mix: 1.0,
+                    ping_pong: true,
+                };
+                let mut delay = StereoDelay::new(sr, sr as usize, 4_000.0);
+                in_place(move |l, r| delay.process(&settings, l, r))
+            },
+        },
+        Processor {
+            name: "reverb",
+            new: |sr, _| {
+                let settings = ReverbSettings {
+                    size: 0.7,
+                    damping: 0.4,
+                    width: 1.0,
+                    mix: 0.35,
+                    frozen: false,
+                };
+                let mut reverb = ReverbEffect::new(sr);
+                in_place(move |l, r| reverb.process(&settings, l, r))
+            },
+        },
+    ]
+}
+
+fn sine(sample_rate: u32, frames: usize, freq: f64) -> Vec<f32> {
+    (0..frames)
+        .map(|i| {
+            let t = i as f64 / f64::from(sample_rate);
+            (0.5 * (2.0 * std::f64::consts::PI * freq * t).sin()) as f32
+        })
+        .collect()
+}
+
+/// The canonical stereo inputs, by name. The channels differ so a processor
+/// that mixes them up is caught.
+fn inputs(sample_rate: u32) -> Vec<(&'static str, Vec<f32>, Vec<f32>)> {
+    let frames = (f64::from(sample_rate) * INPUT_SECS) as usize;
+
+    let mut impulse_l = vec![0.0; frames];
+    let mut impulse_r = vec![0.0; frames];
+    impulse_l[0] = 1.0;
+    impulse_r[1] = -0.5;
+
+    let mut noise_l = WhiteNoise::new(1);
+    let mut noise_r = WhiteNoise::new(2);
+
+    vec![
+        ("impulse", impulse_l, impulse_r),
+        ("sine", sine(sample_rate, frames, 440.0), sine(sample_rate, frames, 660.0)),
+        (
+            "noise",
+            (0..frames).map(|_| noise_l.next() * 0.5).collect(),
+            (0..frames).map(|_| noise_r.next() * 0.5).collect(),
+        ),
+    ]
+}
+
+fn run(
+    processor: &Processor,
+    sample_rate: u32,
+    block_size: usize,
+    in_l: &[f32],
+    in_r: &[f32],
+) -> (Vec<f32>, Vec<f32>) {
+    let mut process = (processor.new)(sample_rate as f32, block_size);
+    let mut out_l = vec![0.0; in_l.len()];
+    let mut out_r = vec![0.0; in_r.len()];
+    for (((in_l, in_r), out_l), out_r) in in_l
+        .chunks(block_size)
+        .zip(in_r.chunks(block_size))
+        .zip(out_l.chunks_mut(block_size))
+        .zip(out_r.chunks_mut(block_size))
+    {
+        process(in_l, in_r, out_l, out_r);
+    }
+    (out_l, out_r)
+}
+
+fn fingerprint(out_l: &[f32], out_r: &[f32]) -> Vec<f32> {
+    let mut print = Vec::new();
+    for channel in [out_l, out_r] {
+        print.extend_from_slice(&channel[0..HEAD_FRAMES]);
+        print.extend(channel.chunks(WINDOW_FRAMES).map(|window| {
+            (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt()
+        }));
+    }
+    print
+}
+
+fn reference_path(processor: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.txt", processor))
+}
+
+/// One case per line: the input, the sample rate and the fingerprint. The
+/// `Display` of an `f32` reads back exactly.
+fn write_references(processor: &str, prints: &[(String, Vec<f32>)]) {
+    let path = reference_path(processor);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let lines: Vec<String> = prints
+        .iter()
+        .map(|(case, print)| {
+            let values: Vec<String> = print.iter().map(|s| s.to_string()).collect();
+            format!("{} {}", case, values.join(" "))
+        })
+        .collect();
+    std::fs::write(path, lines.join("\n") + "\n").unwrap();
+}
+
+fn read_references(processor: &str) -> Option<HashMap<String, Vec<f32>>> {
+    let text = std::fs::read_to_string(reference_path(processor)).ok()?;
+    Some(
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut fields = line.split_whitespace();
+                let case = format!("{} {}", fields.next().unwrap(), fields.next().unwrap());
+                let print = fields.map(|s| s.parse().expect("malformed reference")).collect();
+                (case, print)
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn outputs_match_golden_references() {
+    let bless = std::env::var_os(BLESS_VAR).is_some();
+    let mut failures = Vec::new();
+
+    for processor in processors() {
+        let references = read_references(processor.name);
+        if references.is_none() && !bless {
+            failures.push(format!(
+                "{} has no references at {:?}",
+                processor.name,
+                reference_path(processor.name)
+            ));
+            continue;
+        }
+
+        let mut prints = Vec::new();
+        for sample_rate in SAMPLE_RATES {
+            for (input_name, in_l, in_r) in inputs(sample_rate) {
+                let case = format!("{} {}", input_name, sample_rate);
+                let name = format!("{} {} at {} Hz", processor.name, input_name, sample_rate);
+                let (out_l, out_r) = run(&processor, sample_rate, BLOCK_SIZES[0], &in_l, &in_r);
+                assert!(
+                    out_l.iter().chain(out_r.iter()).all(|s| s.is_finite()),
+                    "{} is not finite",
+                    name
+                );
+
+                for block_size in &BLOCK_SIZES[1..] {
+                    let (blocked_l, blocked_r) =
+                        run(&processor, sample_rate, *block_size, &in_l, &in_r);
+                    if let Some(i) = out_l
+                        .iter()
+                        .zip(out_r.iter())
+                        .zip(blocked_l.iter().zip(blocked_r.iter()))
+                        .position(|(a, b)| a != b)
+                    {
+                        failures.push(format!(
+                            "{} changes with a block size of {} at frame {}",
+                            name, block_size, i
+                        ));
+                    }
+                }
+
+                let print = fingerprint(&out_l, &out_r);
+                if let Some(references) = &references {
+                    match references.get(&case) {
+                        None if !bless => {
+                            failures.push(format!("{} has no reference", name));
+                        }
+                        Some(reference) if reference.len() != print.len() => {
+                            failures.push(format!(
+                                "{} has a fingerprint of {} values but its reference has {}",
+                                name,
+                                print.len(),
+                                reference.len()
+                            ));
+                        }
+                        Some(reference) => {
+                            if let Some((i, (a, b))) = print
+                                .iter()
+                                .zip(reference.iter())
+                                .enumerate()
+                                .find(|(_, (a, b))| (*a - *b).abs() > TOLERANCE)
+                            {
+                                failures.push(format!(
+                                    "{} differs from its reference at value {}: {} instead of {}",
+                                    name, i, a, b
+                                ));
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                prints.push((case, print));
+            }
+        }
+
+        if bless {
+            write_references(processor.name, &prints);
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nIf the change is intended, run again with {}=1.",
+        failures.join("\n"),
+        BLESS_VAR
+    );
+}
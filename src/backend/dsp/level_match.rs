@@ -0,0 +1,82 @@
+use super::db_to_gain;
+use super::loudness::{LoudnessMeter, MIN_LUFS};
+
+/// The most gain compensation that will ever be applied in either direction.
+pub const MAX_COMPENSATION_DB: f32 = 24.0;
+
+/// How quickly the compensation gain follows changes in the measured loudness.
+const GAIN_SMOOTH_SECS: f32 = 0.3;
+
+/// Measures the loudness of a signal before and after an effect and works out
+/// the gain needed to bring the processed signal back to the level of the
+/// unprocessed one.
+///
+/// This is what makes bypass comparisons fair: without it, whichever side is
+/// louder tends to sound "better".
+#[derive(Debug, Clone)]
+pub struct LevelMatcher {
+    pre_meter: LoudnessMeter,
+    post_meter: LoudnessMeter,
+
+    pre_lufs: f32,
+    post_lufs: f32,
+
+    target_db: f32,
+    current_db: f32,
+    smooth_coeff: f32,
+}
+
+impl LevelMatcher {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            pre_meter: LoudnessMeter::new(sample_rate),
+            post_meter: LoudnessMeter::new(sample_rate),
+            pre_lufs: MIN_LUFS,
+            post_lufs: MIN_LUFS,
+            target_db: 0.0,
+            current_db: 0.0,
+            smooth_coeff: (-1.0 / (GAIN_SMOOTH_SECS * sample_rate)).exp(),
+        }
+    }
+
+    /// Feed one stereo frame of the unprocessed and processed signals and
+    /// return the linear gain to apply to the processed frame.
+    #[inline]
+    pub fn process(&mut self, pre: (f32, f32), post: (f32, f32)) -> f32 {
+        if let Some(reading) = self.pre_meter.process(pre.0, pre.1) {
+            self.pre_lufs = reading.short_term;
+        }
+        if let Some(reading) = self.post_meter.process(post.0, post.1) {
+            self.post_lufs = reading.short_term;
+            self.update_target();
+        }
+
+        self.current_db = self.target_db + self.smooth_coeff * (self.current_db - self.target_db);
+        db_to_gain(self.current_db)
+    }
+
+    /// The compensation currently being applied in dB.
+    pub fn compensation_db(&self) -> f32 {
+        self.current_db
+    }
+
+    pub fn reset(&mut self) {
+        self.pre_meter.reset();
+        self.post_meter.reset();
+        self.pre_lufs = MIN_LUFS;
+        self.post_lufs = MIN_LUFS;
+        self.target_db = 0.0;
+        self.current_db = 0.0;
+    }
+
+    fn update_target(&mut self) {
+        // Hold the last compensation through silence instead of letting it
+        // snap back to unity (or jump to the limit) between phrases.
+        if self.pre_lufs <= MIN_LUFS || self.post_lufs <= MIN_LUFS {
+            return;
+        }
+
+        self.target_db =
+            (self.pre_lufs - self.post_lufs).clamp(-MAX_COMPENSATION_DB, MAX_COMPENSATION_DB);
+    }
+}
@@ -0,0 +1,121 @@
+use super::{Biquad, BiquadCoeffs};
+
+/// The length of one measurement block in seconds.
+pub const BLOCK_SECS: f32 = 0.1;
+/// The number of blocks in the "momentary" window (400ms).
+const MOMENTARY_BLOCKS: usize = 4;
+/// The number of blocks in the "short-term" window (3s).
+const SHORT_TERM_BLOCKS: usize = 30;
+
+/// The lowest loudness reported. Anything quieter than this is silence.
+pub const MIN_LUFS: f32 = -70.0;
+
+/// The K-weighting pre-filter from ITU-R BS.1770.
+#[derive(Debug, Default, Clone, Copy)]
+struct KWeighting {
+    shelf: Biquad,
+    highpass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f32) -> Self {
+        Self {
+            shelf: Biquad::new(BiquadCoeffs::high_shelf(sample_rate, 1_681.97, 0.7072, 4.0)),
+            highpass: Biquad::new(BiquadCoeffs::highpass(sample_rate, 38.135, 0.5003)),
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32) -> f32 {
+        self.highpass.process(self.shelf.process(x))
+    }
+
+    fn reset(&mut self) {
+        self.shelf.reset();
+        self.highpass.reset();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessReading {
+    /// The loudness over the last 400ms in LUFS.
+    pub momentary: f32,
+    /// The loudness over the last 3s in LUFS.
+    pub short_term: f32,
+}
+
+/// Measures momentary and short-term loudness of a stereo signal as described
+/// in ITU-R BS.1770 / EBU R128.
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    weighting_l: KWeighting,
+    weighting_r: KWeighting,
+
+    block_frames: usize,
+    frames_in_block: usize,
+    block_sum: f64,
+
+    /// The mean square of the most recent blocks, used as a ring buffer.
+    blocks: [f64; SHORT_TERM_BLOCKS],
+    block_pos: usize,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            weighting_l: KWeighting::new(sample_rate),
+            weighting_r: KWeighting::new(sample_rate),
+            block_frames: ((sample_rate * BLOCK_SECS).round() as usize).max(1),
+            frames_in_block: 0,
+            block_sum: 0.0,
+            blocks: [0.0; SHORT_TERM_BLOCKS],
+            block_pos: 0,
+        }
+    }
+
+    /// Feed a single stereo frame into the meter. A new reading is returned
+    /// every `BLOCK_SECS` seconds.
+    #[inline]
+    pub fn process(&mut self, l: f32, r: f32) -> Option<LoudnessReading> {
+        let l = self.weighting_l.process(l);
+        let r = self.weighting_r.process(r);
+        self.block_sum += f64::from(l * l + r * r);
+
+        self.frames_in_block += 1;
+        if self.frames_in_block < self.block_frames {
+            return None;
+        }
+
+        self.blocks[self.block_pos] = self.block_sum / self.block_frames as f64;
+        self.block_pos = (self.block_pos + 1) % SHORT_TERM_BLOCKS;
+        self.frames_in_block = 0;
+        self.block_sum = 0.0;
+
+        Some(LoudnessReading {
+            momentary: self.window_loudness(MOMENTARY_BLOCKS),
+            short_term: self.window_loudness(SHORT_TERM_BLOCKS),
+        })
+    }
+
+    pub fn reset(&mut self) {
+        self.weighting_l.reset();
+        self.weighting_r.reset();
+        self.frames_in_block = 0;
+        self.block_sum = 0.0;
+        self.blocks = [0.0; SHORT_TERM_BLOCKS];
+        self.block_pos = 0;
+    }
+
+    fn window_loudness(&self, num_blocks: usize) -> f32 {
+        let sum: f64 = (1..=num_blocks)
+            .map(|i| self.blocks[(self.block_pos + SHORT_TERM_BLOCKS - i) % SHORT_TERM_BLOCKS])
+            .sum();
+        let mean_square = sum / num_blocks as f64;
+
+        if mean_square <= 0.0 {
+            MIN_LUFS
+        } else {
+            ((-0.691 + 10.0 * mean_square.log10()) as f32).max(MIN_LUFS)
+        }
+    }
+}
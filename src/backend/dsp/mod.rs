@@ -0,0 +1,56 @@
+//! Small, allocation-free DSP building blocks shared by the internal plugins.
+//!
+//! Everything in here is safe to use in the realtime thread as long as it is
+//! constructed beforehand (i.e. when the plugin is activated).
+
+mod biquad;
+mod delay_line;
+mod denormal;
+mod envelope;
+#[cfg(test)]
+mod golden;
+mod level_match;
+pub mod loudness;
+mod noise;
+pub mod pitch;
+mod reverb;
+
+pub use biquad::{Biquad, BiquadCoeffs};
+pub use delay_line::DelayLine;
+pub use denormal::{flush_denormal, DenormalGuard};
+pub use envelope::EnvelopeFollower;
+pub use level_match::{LevelMatcher, MAX_COMPENSATION_DB};
+pub use noise::{PinkNoise, WhiteNoise};
+pub use reverb::Reverb;
+
+/// The lowest gain in decibels that we bother to represent. Anything below this
+/// is treated as silence.
+pub const MIN_DB: f32 = -90.0;
+
+/// Convert a value in decibels to a linear gain factor.
+#[inline]
+pub fn db_to_gain(db: f32) -> f32 {
+    if db <= MIN_DB {
+        0.0
+    } else {
+        10.0f32.powf(db * 0.05)
+    }
+}
+
+/// The gains of the left and right channel for a pan from -1.0 (left) to 1.0
+/// (right), with an equal power pan law.
+#[inline]
+pub fn pan_gains(pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+    (angle.cos(), angle.sin())
+}
+
+/// Convert a linear gain factor to a value in decibels.
+#[inline]
+pub fn gain_to_db(gain: f32) -> f32 {
+    if gain <= 0.0 {
+        MIN_DB
+    } else {
+        (20.0 * gain.log10()).max(MIN_DB)
+    }
+}
@@ -0,0 +1,62 @@
+/// A fast white noise generator (xorshift32). This is not suitable for anything
+/// other than audio.
+#[derive(Debug, Clone, Copy)]
+pub struct WhiteNoise {
+    state: u32,
+}
+
+impl WhiteNoise {
+    pub fn new(seed: u32) -> Self {
+        // The state must never be zero.
+        Self { state: if seed == 0 { 0x9E37_79B9 } else { seed } }
+    }
+
+    /// Return the next sample in the range `[-1.0, 1.0)`.
+    #[inline]
+    pub fn next(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+
+        (self.state as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+impl Default for WhiteNoise {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+/// A pink (-3dB per octave) noise generator using Paul Kellet's filter.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PinkNoise {
+    white: WhiteNoise,
+    b: [f32; 7],
+}
+
+impl PinkNoise {
+    pub fn new(seed: u32) -> Self {
+        Self { white: WhiteNoise::new(seed), b: [0.0; 7] }
+    }
+
+    /// Return the next sample, roughly in the range `[-1.0, 1.0]`.
+    #[inline]
+    pub fn next(&mut self) -> f32 {
+        let white = self.white.next();
+        let b = &mut self.b;
+
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+
+        // Bring the output back to roughly unity gain.
+        pink * 0.11
+    }
+}
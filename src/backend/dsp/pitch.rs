@@ -0,0 +1,239 @@
+use std::f32::consts::PI;
+
+/// The frequency of A4 that is used when no other reference is given.
+pub const DEFAULT_A4_FREQ: f32 = 440.0;
+
+/// Convert a (fractional) MIDI note number to a frequency in Hz.
+#[inline]
+pub fn midi_note_to_freq(note: f32, a4_freq: f32) -> f32 {
+    a4_freq * 2.0f32.powf((note - 69.0) / 12.0)
+}
+
+/// Convert a frequency in Hz to a fractional MIDI note number.
+#[inline]
+pub fn freq_to_midi_note(freq: f32, a4_freq: f32) -> f32 {
+    69.0 + 12.0 * (freq / a4_freq).log2()
+}
+
+/// Convert an interval in semitones to a playback-rate ratio.
+#[inline]
+pub fn semitones_to_ratio(semitones: f32) -> f32 {
+    2.0f32.powf(semitones / 12.0)
+}
+
+/// A monophonic pitch detector using the YIN algorithm.
+///
+/// Samples are pushed one at a time into an internal ring buffer, and an
+/// estimate is made once every `hop` samples.
+pub struct PitchDetector {
+    sample_rate: f32,
+
+    buffer: Vec<f32>,
+    write_pos: usize,
+
+    frame: Vec<f32>,
+    diff: Vec<f32>,
+
+    min_tau: usize,
+    max_tau: usize,
+
+    hop: usize,
+    hop_count: usize,
+
+    threshold: f32,
+
+    pitch: Option<f32>,
+    clarity: f32,
+}
+
+impl PitchDetector {
+    /// Create a new pitch detector that can detect frequencies in the range
+    /// `[min_freq, max_freq]`.
+    pub fn new(sample_rate: f32, min_freq: f32, max_freq: f32) -> Self {
+        let max_tau = (sample_rate / min_freq).ceil() as usize;
+        let min_tau = ((sample_rate / max_freq).floor() as usize).max(2);
+
+        // The analysis window must be at least twice the longest period.
+        let window = (max_tau * 2).next_power_of_two();
+
+        Self {
+            sample_rate,
+            buffer: vec![0.0; window],
+            write_pos: 0,
+            frame: vec![0.0; window],
+            diff: vec![0.0; max_tau + 1],
+            min_tau,
+            max_tau,
+            hop: window / 4,
+            hop_count: 0,
+            threshold: 0.15,
+            pitch: None,
+            clarity: 0.0,
+        }
+    }
+
+    /// Push a sample into the detector. Returns `true` if a new estimate was made.
+    #[inline]
+    pub fn push(&mut self, x: f32) -> bool {
+        self.buffer[self.write_pos] = x;
+        self.write_pos = (self.write_pos + 1) % self.buffer.len();
+
+        self.hop_count += 1;
+        if self.hop_count >= self.hop {
+            self.hop_count = 0;
+            self.analyze();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The most recently detected frequency in Hz, or `None` if the signal
+    /// was not pitched (or too quiet).
+    pub fn pitch(&self) -> Option<f32> {
+        self.pitch
+    }
+
+    /// How confident the detector is in the last estimate in the range `[0.0, 1.0]`.
+    pub fn clarity(&self) -> f32 {
+        self.clarity
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_pos = 0;
+        self.hop_count = 0;
+        self.pitch = None;
+        self.clarity = 0.0;
+    }
+
+    fn analyze(&mut self) {
+        // Unroll the ring buffer so the oldest sample is first.
+        let len = self.buffer.len();
+        let (newer, older) = self.buffer.split_at(self.write_pos);
+        self.frame[0..older.len()].copy_from_slice(older);
+        self.frame[older.len()..len].copy_from_slice(newer);
+
+        let energy: f32 = self.frame.iter().map(|x| x * x).sum::<f32>() / len as f32;
+        if energy < 1.0e-7 {
+            self.pitch = None;
+            self.clarity = 0.0;
+            return;
+        }
+
+        let window = len - self.max_tau;
+
+        // Difference function.
+        for tau in 1..=self.max_tau {
+            let mut sum = 0.0;
+            for i in 0..window {
+                let d = self.frame[i] - self.frame[i + tau];
+                sum += d * d;
+            }
+            self.diff[tau] = sum;
+        }
+
+        // Cumulative mean normalized difference function.
+        self.diff[0] = 1.0;
+        let mut running_sum = 0.0;
+        for tau in 1..=self.max_tau {
+            running_sum += self.diff[tau];
+            self.diff[tau] =
+                if running_sum > 0.0 { self.diff[tau] * tau as f32 / running_sum } else { 1.0 };
+        }
+
+        // Find the first dip below the threshold.
+        let mut found = None;
+        let mut tau = self.min_tau;
+        while tau < self.max_tau {
+            if self.diff[tau] < self.threshold {
+                while tau + 1 < self.max_tau && self.diff[tau + 1] < self.diff[tau] {
+                    tau += 1;
+                }
+                found = Some(tau);
+                break;
+            }
+            tau += 1;
+        }
+
+        if let Some(tau) = found {
+            // Parabolic interpolation for sub-sample accuracy.
+            let s0 = self.diff[tau - 1];
+            let s1 = self.diff[tau];
+            let s2 = self.diff[tau + 1];
+            let denom = 2.0 * (2.0 * s1 - s2 - s0);
+            let offset = if denom.abs() > f32::EPSILON { (s2 - s0) / denom } else { 0.0 };
+
+            self.pitch = Some(self.sample_rate / (tau as f32 + offset));
+            self.clarity = (1.0 - s1).clamp(0.0, 1.0);
+        } else {
+            self.pitch = None;
+            self.clarity = 0.0;
+        }
+    }
+}
+
+/// A simple realtime pitch shifter using two crossfaded read heads on a delay
+/// line.
+///
+/// This has a latency of roughly `window_secs`, and it is not formant
+/// preserving, but it is cheap and works well for small intervals.
+pub struct PitchShifter {
+    buffer: Vec<f32>,
+    write_pos: usize,
+
+    window_len: f32,
+    phase: f32,
+}
+
+impl PitchShifter {
+    pub fn new(sample_rate: f32, window_secs: f32) -> Self {
+        let window_len = (sample_rate * window_secs).max(4.0);
+        let buffer_len = (window_len as usize + 4).next_power_of_two();
+
+        Self { buffer: vec![0.0; buffer_len], write_pos: 0, window_len, phase: 0.0 }
+    }
+
+    /// Process a single sample, shifting it by `ratio` (where `2.0` is up an
+    /// octave and `0.5` is down an octave).
+    #[inline]
+    pub fn process(&mut self, x: f32, ratio: f32) -> f32 {
+        let mask = self.buffer.len() - 1;
+
+        self.buffer[self.write_pos] = x;
+
+        self.phase += (1.0 - ratio) / self.window_len;
+        self.phase -= self.phase.floor();
+
+        let phase_b = (self.phase + 0.5).fract();
+
+        let a = self.read(self.phase * self.window_len, mask);
+        let b = self.read(phase_b * self.window_len, mask);
+
+        // Raised-cosine crossfade. The two gains always sum to 1.
+        let gain_a = 0.5 - 0.5 * (2.0 * PI * self.phase).cos();
+        let gain_b = 1.0 - gain_a;
+
+        self.write_pos = (self.write_pos + 1) & mask;
+
+        a * gain_a + b * gain_b
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_pos = 0;
+        self.phase = 0.0;
+    }
+
+    #[inline]
+    fn read(&self, delay: f32, mask: usize) -> f32 {
+        let delay = delay + 1.0;
+        let delay_int = delay as usize;
+        let frac = delay - delay_int as f32;
+
+        let i0 = self.write_pos.wrapping_sub(delay_int) & mask;
+        let i1 = i0.wrapping_sub(1) & mask;
+
+        self.buffer[i0] + (self.buffer[i1] - self.buffer[i0]) * frac
+    }
+}
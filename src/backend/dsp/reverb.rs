@@ -0,0 +1,141 @@
+use super::flush_denormal;
+
+/// The lengths of the comb and allpass filters at 44.1 kHz, from Freeverb.
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+
+/// How much longer the filters of the right channel are, which decorrelates
+/// the channels.
+const STEREO_SPREAD: usize = 23;
+
+const TUNING_SAMPLE_RATE: f32 = 44_100.0;
+
+/// The combs are summed, so the input is turned down to make room.
+const INPUT_GAIN: f32 = 0.015;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+const ROOM_SCALE: f32 = 0.28;
+const ROOM_OFFSET: f32 = 0.7;
+const DAMP_SCALE: f32 = 0.4;
+
+#[derive(Debug, Clone)]
+struct Comb {
+    buffer: Vec<f32>,
+    pos: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Self { buffer: vec![0.0; len.max(1)], pos: 0, filter_store: 0.0 }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32, feedback: f32, damp: f32) -> f32 {
+        let out = self.buffer[self.pos];
+        self.filter_store = flush_denormal(out * (1.0 - damp) + self.filter_store * damp);
+        self.buffer[self.pos] = x + self.filter_store * feedback;
+        self.pos = (self.pos + 1) % self.buffer.len();
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Allpass {
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Self { buffer: vec![0.0; len.max(1)], pos: 0 }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32) -> f32 {
+        let buffered = self.buffer[self.pos];
+        self.buffer[self.pos] = flush_denormal(x + buffered * ALLPASS_FEEDBACK);
+        self.pos = (self.pos + 1) % self.buffer.len();
+        buffered - x
+    }
+}
+
+/// An algorithmic stereo reverb after Freeverb: parallel damped combs into
+/// allpasses in series, for each channel.
+#[derive(Debug, Clone)]
+pub struct Reverb {
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+
+    feedback: f32,
+    damp: f32,
+    input_gain: f32,
+    /// How much of each channel goes to the same and to the other side.
+    wet_gains: (f32, f32),
+}
+
+impl Reverb {
+    pub fn new(sample_rate: f32) -> Self {
+        let scale = |len: usize| (len as f32 * sample_rate / TUNING_SAMPLE_RATE) as usize;
+        let combs =
+            |spread| COMB_TUNINGS.iter().map(|len| Comb::new(scale(len + spread))).collect();
+        let allpasses =
+            |spread| ALLPASS_TUNINGS.iter().map(|len| Allpass::new(scale(len + spread))).collect();
+
+        let mut reverb = Self {
+            combs: [combs(0), combs(STEREO_SPREAD)],
+            allpasses: [allpasses(0), allpasses(STEREO_SPREAD)],
+            feedback: 0.0,
+            damp: 0.0,
+            input_gain: INPUT_GAIN,
+            wet_gains: (1.0, 0.0),
+        };
+        reverb.set(0.5, 0.5, 1.0, false);
+        reverb
+    }
+
+    /// Set the size of the room, how quickly the highs die down and the
+    /// stereo width, all from 0.0 to 1.0. A frozen reverb holds its tail
+    /// forever and takes no more input.
+    pub fn set(&mut self, room_size: f32, damping: f32, width: f32, frozen: bool) {
+        let width = width.clamp(0.0, 1.0);
+        self.wet_gains = (width * 0.5 + 0.5, (1.0 - width) * 0.5);
+        if frozen {
+            self.feedback = 1.0;
+            self.damp = 0.0;
+            self.input_gain = 0.0;
+        } else {
+            self.feedback = room_size.clamp(0.0, 1.0) * ROOM_SCALE + ROOM_OFFSET;
+            self.damp = damping.clamp(0.0, 1.0) * DAMP_SCALE;
+            self.input_gain = INPUT_GAIN;
+        }
+    }
+
+    /// Feed a frame in and return the reverb of it, without the dry signal.
+    #[inline]
+    pub fn process(&mut self, l: f32, r: f32) -> (f32, f32) {
+        let input = (l + r) * self.input_gain;
+        let mut out = [0.0; 2];
+        for (channel, out) in out.iter_mut().enumerate() {
+            for comb in self.combs[channel].iter_mut() {
+                *out += comb.process(input, self.feedback, self.damp);
+            }
+            for allpass in self.allpasses[channel].iter_mut() {
+                *out = allpass.process(*out);
+            }
+        }
+
+        let (same, other) = self.wet_gains;
+        (out[0] * same + out[1] * other, out[1] * same + out[0] * other)
+    }
+
+    pub fn reset(&mut self) {
+        for comb in self.combs.iter_mut().flatten() {
+            comb.buffer.iter_mut().for_each(|s| *s = 0.0);
+            comb.filter_store = 0.0;
+        }
+        for allpass in self.allpasses.iter_mut().flatten() {
+            allpass.buffer.iter_mut().for_each(|s| *s = 0.0);
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{
+    ParamF32, ParamF32Handle, Unit, DEFAULT_DB_GRADIENT, DEFAULT_SMOOTH_SECS,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use super::analysis::{analysis_channel, AnalysisTx, SpectrumAnalyzer, DEFAULT_ANALYSIS_FFT_SIZE};
+use super::dsp::{db_to_gain, Biquad, BiquadCoeffs, LevelMatcher};
+
+pub static EQ_PLUG_RDN: &str = "app.meadowlark.eq";
+
+const MSG_BUFFER_SIZE: usize = 64;
+
+/// How many seconds of audio the analysis channels can hold before samples
+/// start getting dropped.
+const ANALYSIS_BUFFER_SECS: f64 = 0.25;
+
+pub const NUM_BANDS: usize = 6;
+
+pub const MIN_FREQ: f32 = 20.0;
+pub const MAX_FREQ: f32 = 20_000.0;
+pub const MAX_GAIN_DB: f32 = 24.0;
+pub const MIN_Q: f32 = 0.1;
+pub const MAX_Q: f32 = 18.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EqBandType {
+    LowCut,
+    LowShelf,
+    Peak,
+    HighShelf,
+    HighCut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqBand {
+    pub type_: EqBandType,
+    pub freq: f32,
+    /// Ignored by the low cut and high cut types.
+    pub gain_db: f32,
+    pub q: f32,
+    pub enabled: bool,
+}
+
+impl EqBand {
+    pub fn coeffs(&self, sample_rate: f32) -> BiquadCoeffs {
+        if !self.enabled {
+            return BiquadCoeffs::IDENTITY;
+        }
+
+        let (freq, q, gain) = (self.freq, self.q, self.gain_db);
+        match self.type_ {
+            EqBandType::LowCut => BiquadCoeffs::highpass(sample_rate, freq, q),
+            EqBandType::LowShelf => BiquadCoeffs::low_shelf(sample_rate, freq, q, gain),
+            EqBandType::Peak => BiquadCoeffs::peak(sample_rate, freq, q, gain),
+            EqBandType::HighShelf => BiquadCoeffs::high_shelf(sample_rate, freq, q, gain),
+            EqBandType::HighCut => BiquadCoeffs::lowpass(sample_rate, freq, q),
+        }
+    }
+
+    /// Clamp all values to their valid ranges.
+    pub fn clamped(self) -> Self {
+        Self {
+            freq: self.freq.clamp(MIN_FREQ, MAX_FREQ),
+            gain_db: self.gain_db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB),
+            q: self.q.clamp(MIN_Q, MAX_Q),
+            ..self
+        }
+    }
+}
+
+pub const DEFAULT_BANDS: [EqBand; NUM_BANDS] = [
+    EqBand { type_: EqBandType::LowCut, freq: 30.0, gain_db: 0.0, q: 0.707, enabled: false },
+    EqBand { type_: EqBandType::LowShelf, freq: 100.0, gain_db: 0.0, q: 0.707, enabled: true },
+    EqBand { type_: EqBandType::Peak, freq: 400.0, gain_db: 0.0, q: 1.0, enabled: true },
+    EqBand { type_: EqBandType::Peak, freq: 2_000.0, gain_db: 0.0, q: 1.0, enabled: true },
+    EqBand { type_: EqBandType::HighShelf, freq: 8_000.0, gain_db: 0.0, q: 0.707, enabled: true },
+    EqBand { type_: EqBandType::HighCut, freq: 18_000.0, gain_db: 0.0, q: 0.707, enabled: false },
+];
+
+/// The combined magnitude response of all the bands at the given frequency in dB.
+pub fn eq_response_db(bands: &[EqBand], sample_rate: f32, freq: f32) -> f32 {
+    bands
+        .iter()
+        .filter(|b| b.enabled)
+        .map(|b| b.coeffs(sample_rate).magnitude_db(sample_rate, freq))
+        .sum()
+}
+
+pub struct EqPlugFactory;
+
+impl PluginFactory for EqPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: EQ_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "EQ".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(EqPlugMainThread::new()))
+    }
+}
+
+pub struct EqPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+
+    bands: [EqBand; NUM_BANDS],
+    sample_rate: f32,
+
+    bypassed: bool,
+    auto_gain: bool,
+    compensation_db: Arc<AtomicU32>,
+
+    /// The spectrum of the signal going into the EQ.
+    pre_analyzer: SpectrumAnalyzer,
+    /// The spectrum of the signal coming out of the EQ.
+    post_analyzer: SpectrumAnalyzer,
+}
+
+impl EqPlugHandle {
+    pub fn set_band(&mut self, index: usize, band: EqBand) {
+        if index < NUM_BANDS {
+            let band = band.clamped();
+            self.bands[index] = band;
+            self.send(ProcessMsg::SetBand { index, band });
+        }
+    }
+
+    pub fn bands(&self) -> &[EqBand; NUM_BANDS] {
+        &self.bands
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    /// While bypassed the input is passed through untouched. The EQ keeps
+    /// running in the background so switching back is seamless.
+    pub fn set_bypassed(&mut self, bypassed: bool) {
+        self.bypassed = bypassed;
+        self.send(ProcessMsg::SetBypassed(bypassed));
+    }
+
+    pub fn bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// When enabled, the output of the EQ is continuously matched to the
+    /// loudness of its input so toggling bypass compares tone, not level.
+    pub fn set_auto_gain(&mut self, auto_gain: bool) {
+        self.auto_gain = auto_gain;
+        self.send(ProcessMsg::SetAutoGain(auto_gain));
+    }
+
+    pub fn auto_gain(&self) -> bool {
+        self.auto_gain
+    }
+
+    /// The gain currently applied by auto gain in dB.
+    pub fn compensation_db(&self) -> f32 {
+        f32::from_bits(self.compensation_db.load(Ordering::Relaxed))
+    }
+
+    /// Pull in the latest audio from the plugin and update both spectrums.
+    /// Returns `true` if either of them changed.
+    pub fn update_spectrums(&mut self) -> bool {
+        let pre = self.pre_analyzer.update();
+        let post = self.post_analyzer.update();
+        pre || post
+    }
+
+    pub fn pre_spectrum(&self) -> &SpectrumAnalyzer {
+        &self.pre_analyzer
+    }
+
+    pub fn post_spectrum(&self) -> &SpectrumAnalyzer {
+        &self.post_analyzer
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("EQ plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetBand { index: usize, band: EqBand },
+    SetBypassed(bool),
+    SetAutoGain(bool),
+}
+
+struct ParamsHandle {
+    pub output: ParamF32Handle,
+}
+
+struct Params {
+    pub output: ParamF32,
+}
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let (output, output_handle) = ParamF32::from_value(
+            0.0,
+            0.0,
+            -90.0,
+            12.0,
+            DEFAULT_DB_GRADIENT,
+            Unit::Decibels,
+            DEFAULT_SMOOTH_SECS,
+            sample_rate,
+            max_frames,
+        );
+
+        (Params { output }, ParamsHandle { output: output_handle })
+    }
+}
+
+pub struct EqPlugMainThread {
+    params: ParamsHandle,
+}
+
+impl EqPlugMainThread {
+    fn new() -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle }
+    }
+}
+
+impl PluginMainThread for EqPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let analysis_capacity = (sample_rate.0 * ANALYSIS_BUFFER_SECS) as usize;
+        let (pre_tx, pre_rx) = analysis_channel(analysis_capacity);
+        let (post_tx, post_rx) = analysis_channel(analysis_capacity);
+
+        let sr = sample_rate.0 as f32;
+
+        let mut filters_l = [Biquad::default(); NUM_BANDS];
+        let mut filters_r = [Biquad::default(); NUM_BANDS];
+        for (i, band) in DEFAULT_BANDS.iter().enumerate() {
+            filters_l[i].coeffs = band.coeffs(sr);
+            filters_r[i].coeffs = band.coeffs(sr);
+        }
+
+        let compensation_db = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(EqPlugAudioThread {
+                params,
+                from_handle_rx,
+                sample_rate: sr,
+                filters_l,
+                filters_r,
+                pre_tx: Owned::new(coll_handle, pre_tx),
+                post_tx: Owned::new(coll_handle, post_tx),
+                mono_buf: Owned::new(coll_handle, vec![0.0; max_frames as usize]),
+                bypassed: false,
+                auto_gain: false,
+                level_matcher: LevelMatcher::new(sr),
+                compensation_db: Arc::clone(&compensation_db),
+            }),
+            internal_handle: Some(Box::new(EqPlugHandle {
+                to_audio_thread_tx,
+                bands: DEFAULT_BANDS,
+                sample_rate: sr,
+                bypassed: false,
+                auto_gain: false,
+                compensation_db,
+                pre_analyzer: SpectrumAnalyzer::new(pre_rx, sr, DEFAULT_ANALYSIS_FFT_SIZE),
+                post_analyzer: SpectrumAnalyzer::new(post_rx, sr, DEFAULT_ANALYSIS_FFT_SIZE),
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        1
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        match param_index {
+            0 => Ok(ext::params::ParamInfo::new(
+                ParamID(0),
+                ParamInfoFlags::default_float(),
+                "output".into(),
+                String::new(),
+                -90.0,
+                12.0,
+                0.0,
+            )),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => Ok(f64::from(self.params.output.value())),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(0) => Ok(format!("{:.2} dB", value)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => text.parse().map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct EqPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    sample_rate: f32,
+
+    filters_l: [Biquad; NUM_BANDS],
+    filters_r: [Biquad; NUM_BANDS],
+
+    pre_tx: Owned<AnalysisTx>,
+    post_tx: Owned<AnalysisTx>,
+    mono_buf: Owned<Vec<f32>>,
+
+    bypassed: bool,
+    auto_gain: bool,
+    level_matcher: LevelMatcher,
+    compensation_db: Arc<AtomicU32>,
+}
+
+impl EqPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                if param_value.param_id() == 0 {
+                    self.params.output.set_value(param_value.value() as f32);
+                }
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetBand { index, band } => {
+                    let coeffs = band.coeffs(self.sample_rate);
+                    self.filters_l[index].coeffs = coeffs;
+                    self.filters_r[index].coeffs = coeffs;
+                }
+                ProcessMsg::SetBypassed(bypassed) => {
+                    self.bypassed = bypassed;
+                }
+                ProcessMsg::SetAutoGain(auto_gain) => {
+                    self.auto_gain = auto_gain;
+                }
+            }
+        }
+    }
+}
+
+impl PluginAudioThread for EqPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        for f in self.filters_l.iter_mut().chain(self.filters_r.iter_mut()) {
+            f.reset();
+        }
+        self.level_matcher.reset();
+        self.compensation_db.store(0.0f32.to_bits(), Ordering::Relaxed);
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+
+        let frames = proc_info.frames;
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        for i in 0..frames {
+            self.mono_buf[i] = (in_l[i] + in_r[i]) * 0.5;
+        }
+        self.pre_tx.push(&self.mono_buf[0..frames]);
+
+        let output = self.params.output.smoothed(frames);
+
+        for i in 0..frames {
+            let mut l = in_l[i];
+            let mut r = in_r[i];
+            for (fl, fr) in self.filters_l.iter_mut().zip(self.filters_r.iter_mut()) {
+                l = fl.process(l);
+                r = fr.process(r);
+            }
+
+            let gain = db_to_gain(output[i]);
+            l *= gain;
+            r *= gain;
+
+            // Always keep measuring, even while bypassed or with auto gain off,
+            // so the compensation is already settled when it gets switched on.
+            let compensation = self.level_matcher.process((in_l[i], in_r[i]), (l, r));
+            if self.auto_gain {
+                l *= compensation;
+                r *= compensation;
+            }
+
+            if self.bypassed {
+                out_l[i] = in_l[i];
+                out_r[i] = in_r[i];
+            } else {
+                out_l[i] = l;
+                out_r[i] = r;
+            }
+
+            self.mono_buf[i] = (out_l[i] + out_r[i]) * 0.5;
+        }
+        self.post_tx.push(&self.mono_buf[0..frames]);
+
+        self.compensation_db
+            .store(self.level_matcher.compensation_db().to_bits(), Ordering::Relaxed);
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
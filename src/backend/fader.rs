@@ -0,0 +1,220 @@
+//! The fader at the end of a channel: its gain, pan, mute and solo.
+//!
+//! Changes to the fader are smoothed so that moving it doesn't click. Solo is
+//! solo-in-place: soloing a channel silences the channels that have nothing
+//! to do with it, but keeps the channels it plays through (and the ones that
+//! play through it) so it is heard with its effects and in its place in the
+//! mix.
+//!
+//! Each channel has a `FaderPlug` at its end in the audio graph.
+
+use smallvec::SmallVec;
+
+use super::dsp::{db_to_gain, gain_to_db, pan_gains};
+
+/// How long it takes the fader to get most of the way to a new setting.
+pub const FADER_SMOOTHING_SECS: f32 = 0.02;
+
+/// The gain in decibels of a fader at a normalized position from 0.0 to 1.0.
+/// The top of the fader is unity gain, and the gain follows a cubic curve
+/// below that so the fader has more room near the top. Positions above 1.0
+/// boost the signal, which only the faders of sends go to.
+pub fn fader_db(normalized: f64) -> f32 {
+    gain_to_db((normalized.max(0.0) as f32).powi(3))
+}
+
+/// The pan from -1.0 (left) to 1.0 (right) of a normalized pan from 0.0 to
+/// 1.0.
+pub fn fader_pan(normalized: f64) -> f32 {
+    (normalized.clamp(0.0, 1.0) * 2.0 - 1.0) as f32
+}
+
+/// A stereo fader with smoothed gains.
+pub struct Fader {
+    /// How much of the distance to the target is left after each frame.
+    coeff: f32,
+    target: (f32, f32),
+    current: (f32, f32),
+}
+
+impl Fader {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            coeff: (-1.0 / (FADER_SMOOTHING_SECS * sample_rate)).exp(),
+            target: (1.0, 1.0),
+            current: (1.0, 1.0),
+        }
+    }
+
+    /// Move the fader. A fader that isn't audible fades out, because its
+    /// channel is muted or another channel is soloed.
+    pub fn set(&mut self, gain_db: f32, pan: f32, audible: bool) {
+        self.target = if audible {
+            let gain = db_to_gain(gain_db);
+            let (l, r) = pan_gains(pan);
+            (gain * l, gain * r)
+        } else {
+            (0.0, 0.0)
+        };
+    }
+
+    /// Jump to the setting of the fader without smoothing, i.e. when playback
+    /// starts.
+    pub fn reset(&mut self) {
+        self.current = self.target;
+    }
+
+    /// Apply the fader to a block in place.
+    pub fn process(&mut self, left: &mut [f32], right: &mut [f32]) {
+        if self.current == self.target {
+            let (l_gain, r_gain) = self.current;
+            left.iter_mut().for_each(|s| *s *= l_gain);
+            right.iter_mut().for_each(|s| *s *= r_gain);
+            return;
+        }
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            self.current.0 = self.target.0 + (self.current.0 - self.target.0) * self.coeff;
+            self.current.1 = self.target.1 + (self.current.1 - self.target.1) * self.coeff;
+            *l *= self.current.0;
+            *r *= self.current.1;
+        }
+
+        // Settle once the difference can't be heard, so that the next block
+        // takes the fast path.
+        let close = |current: f32, target: f32| (current - target).abs() < 1.0e-5;
+        if close(self.current.0, self.target.0) && close(self.current.1, self.target.1) {
+            self.current = self.target;
+        }
+    }
+}
+
+/// What decides whether a channel is heard.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SoloState {
+    pub muted: bool,
+    pub soloed: bool,
+    /// The channels this channel plays into, through its output and its
+    /// sends.
+    pub outputs: SmallVec<[usize; 4]>,
+}
+
+/// Whether each channel is heard. A muted channel never is. Once any channel
+/// is soloed, only the soloed channels are heard, along with the channels
+/// they play into and the channels that play into them.
+pub fn audible_channels(channels: &[SoloState]) -> Vec<bool> {
+    if !channels.iter().any(|c| c.soloed) {
+        return channels.iter().map(|c| !c.muted).collect();
+    }
+
+    let mut inputs: Vec<SmallVec<[usize; 4]>> = vec![SmallVec::new(); channels.len()];
+    for (i, channel) in channels.iter().enumerate() {
+        for output in channel.outputs.iter().filter(|o| **o != i && **o < channels.len()) {
+            inputs[*output].push(i);
+        }
+    }
+
+    let soloed: Vec<usize> = (0..channels.len()).filter(|i| channels[*i].soloed).collect();
+    let downstream = reachable(channels.len(), &soloed, |i| &channels[i].outputs);
+    let upstream = reachable(channels.len(), &soloed, |i| &inputs[i]);
+
+    channels.iter().enumerate().map(|(i, c)| !c.muted && (downstream[i] || upstream[i])).collect()
+}
+
+/// The nodes below `len` that can be reached from `start` (including those)
+/// by following `edges`.
+fn reachable<'a>(
+    len: usize,
+    start: &[usize],
+    edges: impl Fn(usize) -> &'a SmallVec<[usize; 4]>,
+) -> Vec<bool> {
+    let mut seen = vec![false; len];
+    let mut stack = start.to_vec();
+    while let Some(i) = stack.pop() {
+        if i >= len || seen[i] {
+            continue;
+        }
+        seen[i] = true;
+        stack.extend(edges(i).iter().copied());
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(muted: bool, soloed: bool, outputs: &[usize]) -> SoloState {
+        SoloState { muted, soloed, outputs: outputs.iter().copied().collect() }
+    }
+
+    #[test]
+    fn muted_channels_are_silent() {
+        let channels =
+            [channel(false, false, &[]), channel(true, false, &[0]), channel(false, false, &[0])];
+        assert_eq!(audible_channels(&channels), vec![true, false, true]);
+    }
+
+    #[test]
+    fn solo_silences_unrelated_channels() {
+        // 1 and 2 play into the master, 3 plays into 1.
+        let channels = [
+            channel(false, false, &[]),
+            channel(false, false, &[0]),
+            channel(false, true, &[0]),
+            channel(false, false, &[1]),
+        ];
+        assert_eq!(audible_channels(&channels), vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn solo_keeps_the_channels_in_its_path() {
+        // 2 plays through the bus at 1, and sends to the return at 3. 4
+        // plays into 2.
+        let channels = [
+            channel(false, false, &[]),
+            channel(false, false, &[0]),
+            channel(false, true, &[1, 3]),
+            channel(false, false, &[0]),
+            channel(false, false, &[2]),
+            channel(false, false, &[0]),
+        ];
+        assert_eq!(audible_channels(&channels), vec![true, true, true, true, true, false]);
+
+        // Soloing the bus keeps what plays into it, but not the return the
+        // channel sends to.
+        let mut channels = channels;
+        channels[2].soloed = false;
+        channels[1].soloed = true;
+        assert_eq!(audible_channels(&channels), vec![true, true, true, false, true, false]);
+    }
+
+    #[test]
+    fn mute_wins_over_solo() {
+        let channels =
+            [channel(false, false, &[]), channel(true, true, &[0]), channel(false, true, &[0])];
+        assert_eq!(audible_channels(&channels), vec![true, false, true]);
+    }
+
+    #[test]
+    fn routing_loops_and_bad_indices_are_ignored() {
+        let channels = [
+            channel(false, false, &[]),
+            channel(false, true, &[1, 2, 9]),
+            channel(false, false, &[1]),
+        ];
+        assert_eq!(audible_channels(&channels), vec![false, true, true]);
+    }
+
+    #[test]
+    fn silent_fader_fades_out() {
+        let mut fader = Fader::new(48_000.0);
+        fader.set(0.0, 0.0, false);
+
+        let mut left = vec![1.0; 48_000];
+        let mut right = vec![1.0; 48_000];
+        fader.process(&mut left, &mut right);
+        assert!(left[0] > 0.9 && right[0] > 0.9);
+        assert!(left[47_999] < 1.0e-6 && right[47_999] < 1.0e-6);
+    }
+}
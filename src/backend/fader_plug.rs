@@ -0,0 +1,304 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::fader::{fader_db, fader_pan, Fader};
+use crate::util::Unit;
+
+pub static FADER_PLUG_RDN: &str = "app.meadowlark.fader";
+
+/// The normalized gain of the fader, like `ChannelState::out_gain_normalized`.
+pub const FADER_PARAM_GAIN: u32 = 0;
+/// The normalized pan of the fader, like `ChannelState::out_pan_normalized`.
+pub const FADER_PARAM_PAN: u32 = 1;
+
+/// The names and default values of the parameters.
+const PARAMS: [(&str, f64); 2] = [("Gain", 1.0), ("Pan", 0.5)];
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// How long a ducked fader takes to fade out, and to fade back in.
+pub const DUCK_SECS: f32 = 0.005;
+
+pub struct FaderPlugFactory;
+
+impl PluginFactory for FaderPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: FADER_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Fader".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(FaderPlugMainThread::new()))
+    }
+}
+
+/// The fader at the end of a channel in the audio graph. Its gain and pan
+/// are also parameters, so they can be automated.
+pub struct FaderPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    /// The last setting that was sent, so that nothing is sent when it didn't
+    /// change.
+    last: Option<(f64, f64, bool)>,
+    /// Set by the audio thread once a ducked fader has faded out.
+    silent: Arc<AtomicBool>,
+}
+
+impl FaderPlugHandle {
+    /// Move the fader to a normalized gain and pan, like the ones of a
+    /// `ChannelState`.
+    pub fn set(&mut self, gain: f64, pan: f64, audible: bool) {
+        let setting = (gain, pan, audible);
+        if self.last == Some(setting) {
+            return;
+        }
+        match self.to_audio_thread_tx.push(ProcessMsg::Set(setting)) {
+            Ok(()) => self.last = Some(setting),
+            Err(e) => log::error!("Fader plugin failed to send message: {}", e),
+        }
+    }
+
+    /// Fade the fader out over `DUCK_SECS` on top of its setting, or back in.
+    /// See `is_silent()`.
+    pub fn set_ducked(&mut self, ducked: bool) {
+        if let Err(e) = self.to_audio_thread_tx.push(ProcessMsg::Duck(ducked)) {
+            log::error!("Fader plugin failed to send message: {}", e);
+        }
+    }
+
+    /// Whether the fader is ducked and has faded out all the way.
+    pub fn is_silent(&self) -> bool {
+        self.silent.load(Ordering::Relaxed)
+    }
+}
+
+enum ProcessMsg {
+    Set((f64, f64, bool)),
+    Duck(bool),
+}
+
+/// The values of the parameters as bits of `f64`s, which the audio thread
+/// keeps up to date for the main thread.
+type ParamValues = Arc<[AtomicU64; 2]>;
+
+pub struct FaderPlugMainThread {
+    values: ParamValues,
+}
+
+impl FaderPlugMainThread {
+    fn new() -> Self {
+        Self { values: Arc::new(PARAMS.map(|(_, default)| AtomicU64::new(default.to_bits()))) }
+    }
+}
+
+impl PluginMainThread for FaderPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        _max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+        let silent = Arc::new(AtomicBool::new(false));
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(FaderPlugAudioThread {
+                from_handle_rx,
+                fader: Fader::new(sample_rate.0 as f32),
+                setting: (PARAMS[0].1, PARAMS[1].1, true),
+                applied: None,
+                values: Arc::clone(&self.values),
+                ducked: false,
+                duck_gain: 1.0,
+                duck_step: 1.0 / (DUCK_SECS * sample_rate.0 as f32),
+                silent: Arc::clone(&silent),
+            }),
+            internal_handle: Some(Box::new(FaderPlugHandle {
+                to_audio_thread_tx,
+                last: None,
+                silent,
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        PARAMS.len() as u32
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        let (name, default) = PARAMS.get(param_index).ok_or(())?;
+
+        Ok(ext::params::ParamInfo::new(
+            ParamID(param_index as u32),
+            ParamInfoFlags::default_float(),
+            (*name).into(),
+            String::new(),
+            0.0,
+            1.0,
+            *default,
+        ))
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        let value = self.values.get(param_id.0 as usize).ok_or(())?;
+        Ok(f64::from_bits(value.load(Ordering::Relaxed)))
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(FADER_PARAM_GAIN) => Ok(Unit::Decibels.format(f64::from(fader_db(value)))),
+            ParamID(FADER_PARAM_PAN) => Ok(format!("{:.0}", fader_pan(value) * 100.0)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(FADER_PARAM_GAIN | FADER_PARAM_PAN) => text.parse().map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct FaderPlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+    fader: Fader,
+    /// The normalized gain and pan, and whether the channel is heard.
+    setting: (f64, f64, bool),
+    /// The setting the fader was last moved to.
+    applied: Option<(f64, f64, bool)>,
+    values: ParamValues,
+    ducked: bool,
+    /// Goes from 1.0 to 0.0 in a straight line while ducked, and back.
+    duck_gain: f32,
+    /// How much `duck_gain` moves per frame.
+    duck_step: f32,
+    silent: Arc<AtomicBool>,
+}
+
+impl FaderPlugAudioThread {
+    fn poll(&mut self) {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::Set(setting) => self.setting = setting,
+                ProcessMsg::Duck(ducked) => self.ducked = ducked,
+            }
+        }
+    }
+
+    fn duck(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let target = if self.ducked { 0.0 } else { 1.0 };
+        if self.duck_gain != target {
+            for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+                self.duck_gain = if self.ducked {
+                    (self.duck_gain - self.duck_step).max(0.0)
+                } else {
+                    (self.duck_gain + self.duck_step).min(1.0)
+                };
+                *l *= self.duck_gain;
+                *r *= self.duck_gain;
+            }
+        } else if self.ducked {
+            left.fill(0.0);
+            right.fill(0.0);
+        }
+        self.silent.store(self.ducked && self.duck_gain == 0.0, Ordering::Relaxed);
+    }
+
+    /// Take in the parameter events, i.e. from automation. They win over a
+    /// setting from the handle in the same block.
+    fn read_params(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                let value = param_value.value().clamp(0.0, 1.0);
+                match param_value.param_id() {
+                    FADER_PARAM_GAIN => self.setting.0 = value,
+                    FADER_PARAM_PAN => self.setting.1 = value,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn apply(&mut self) {
+        if self.applied == Some(self.setting) {
+            return;
+        }
+        let (gain, pan, audible) = self.setting;
+        self.fader.set(fader_db(gain), fader_pan(pan), audible);
+        self.values[0].store(gain.to_bits(), Ordering::Relaxed);
+        self.values[1].store(pan.to_bits(), Ordering::Relaxed);
+        self.applied = Some(self.setting);
+    }
+}
+
+impl PluginAudioThread for FaderPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        self.poll();
+        self.apply();
+        self.fader.reset();
+        self.duck_gain = if self.ducked { 0.0 } else { 1.0 };
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {}
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll();
+        self.read_params(in_events);
+        self.apply();
+
+        let frames = proc_info.frames;
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let out_l = &mut out_l[0..frames];
+        let out_r = &mut out_r[0..frames];
+        out_l.copy_from_slice(&in_l[0..frames]);
+        out_r.copy_from_slice(&in_r[0..frames]);
+
+        self.fader.process(out_l, out_r);
+        self.duck(out_l, out_r);
+
+        ProcessStatus::Continue
+    }
+}
@@ -0,0 +1,84 @@
+//! A rack of parallel effect chains. The input of the rack is fed to every
+//! chain, and the outputs of the chains are summed with their own gain and
+//! pan, e.g. to blend a heavily compressed copy of a signal with the dry one.
+//!
+//! In the audio graph the chains are parallel paths that the graph sums (see
+//! `channel_graph`), so `FxRackSummer` only runs a rack without an engine,
+//! i.e. in the golden tests.
+
+use serde::{Deserialize, Serialize};
+
+use super::dsp::{db_to_gain, pan_gains};
+
+/// The most chains a rack can hold.
+pub const MAX_FX_CHAINS: usize = 8;
+
+/// The range of the gain of a chain in decibels.
+pub const MAX_FX_CHAIN_DB: f32 = 12.0;
+
+/// How the output of a chain goes into the output of the rack.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FxChainMix {
+    pub gain_db: f32,
+    /// From -1.0 (left) to 1.0 (right).
+    pub pan: f32,
+    pub muted: bool,
+}
+
+impl FxChainMix {
+    /// The gains of the left and right output of the chain.
+    pub fn gains(&self) -> (f32, f32) {
+        if self.muted {
+            return (0.0, 0.0);
+        }
+        let gain = db_to_gain(self.gain_db);
+        let (l, r) = pan_gains(self.pan);
+        (gain * l, gain * r)
+    }
+}
+
+impl Default for FxChainMix {
+    fn default() -> Self {
+        Self { gain_db: 0.0, pan: 0.0, muted: false }
+    }
+}
+
+/// Sums the outputs of the chains of a rack, a block at a time.
+pub struct FxRackSummer {
+    out_l: Vec<f32>,
+    out_r: Vec<f32>,
+    frames: usize,
+}
+
+impl FxRackSummer {
+    /// Allocate room for blocks of up to `max_frames` frames.
+    pub fn new(max_frames: usize) -> Self {
+        Self { out_l: vec![0.0; max_frames], out_r: vec![0.0; max_frames], frames: 0 }
+    }
+
+    /// Start a new block of `frames` frames.
+    pub fn clear(&mut self, frames: usize) {
+        self.frames = frames.min(self.out_l.len());
+        self.out_l[0..self.frames].fill(0.0);
+        self.out_r[0..self.frames].fill(0.0);
+    }
+
+    /// Add the stereo output of a chain.
+    pub fn add_chain(&mut self, mix: &FxChainMix, in_l: &[f32], in_r: &[f32]) {
+        let (gain_l, gain_r) = mix.gains();
+        if gain_l == 0.0 && gain_r == 0.0 {
+            return;
+        }
+        for (out, s) in self.out_l[0..self.frames].iter_mut().zip(in_l.iter()) {
+            *out += s * gain_l;
+        }
+        for (out, s) in self.out_r[0..self.frames].iter_mut().zip(in_r.iter()) {
+            *out += s * gain_r;
+        }
+    }
+
+    /// The sum of the chains added since the last `clear()`.
+    pub fn output(&self) -> (&[f32], &[f32]) {
+        (&self.out_l[0..self.frames], &self.out_r[0..self.frames])
+    }
+}
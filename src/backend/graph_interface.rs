@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use dropseed::plugin::{ParamID, PluginInstanceID, PluginPreset, PluginSaveState};
+use dropseed::{
+    EdgeReq, EdgeReqPortID, ModifyGraphRequest, ParamModifiedInfo, PluginHandle, PluginIDReq,
+    PortType, RescanPluginDirectoriesRes, ScannedPluginKey,
+};
+use fnv::FnvHashMap;
+
+use super::channel_graph::{Edge, EdgeKind};
+
+/// Identifies a plugin added through the `PluginHost`. Unlike a
+/// `PluginInstanceID`, this is known as soon as the plugin is requested, before
+/// the engine has added it to the graph.
+pub type HostedPluginID = u64;
+
+/// A CLAP plugin that was found by the scanner and can be added to the graph.
+#[derive(Debug, Clone)]
+pub struct AvailablePlugin {
+    pub key: ScannedPluginKey,
+    pub name: String,
+    pub vendor: String,
+    pub version: String,
+    pub product_url: Option<String>,
+    pub manual_url: Option<String>,
+    pub support_url: Option<String>,
+}
+
+/// What to do with a plugin the engine added for the host.
+pub enum PluginAdded {
+    Hosted(HostedPluginID),
+    /// The plugin was removed while it was being added. Send this request to
+    /// take it out of the graph again.
+    Removed(ModifyGraphRequest),
+}
+
+/// A plugin in the graph (or on its way there) and what the main thread knows
+/// about it.
+pub struct HostedPlugin {
+    pub id: HostedPluginID,
+    /// `None` until the engine has added the plugin to the graph.
+    pub plugin_id: Option<PluginInstanceID>,
+    /// `None` while the plugin is deactivated.
+    pub handle: Option<PluginHandle>,
+    /// The last values of the parameters the plugin reported, in the units
+    /// of the plugin.
+    pub params: FnvHashMap<ParamID, f64>,
+}
+
+/// Hosts external plugins in the audio graph: keeps the list of plugins the
+/// scanner found, builds the requests that add them to the graph and removes
+/// them again, and keeps the handles and parameter values of the plugins on
+/// the main thread.
+///
+/// The engine adds plugins in the order they were requested, so each new
+/// plugin is matched to the oldest pending request for the same plugin.
+pub struct PluginHost {
+    available: Vec<AvailablePlugin>,
+    clap_scan_paths: Vec<PathBuf>,
+    hosted: Vec<HostedPlugin>,
+    pending: VecDeque<(HostedPluginID, String)>,
+    next_id: HostedPluginID,
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        Self {
+            available: Vec::new(),
+            clap_scan_paths: Vec::new(),
+            hosted: Vec::new(),
+            pending: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// The CLAP plugins found by the last scan, sorted by name.
+    pub fn available(&self) -> &[AvailablePlugin] {
+        &self.available
+    }
+
+    pub fn find_available(&self, rdn: &str) -> Option<&AvailablePlugin> {
+        self.available.iter().find(|p| p.key.rdn == rdn)
+    }
+
+    pub fn clap_scan_paths(&self) -> &[PathBuf] {
+        &self.clap_scan_paths
+    }
+
+    pub fn add_clap_scan_path(&mut self, path: PathBuf) {
+        if !self.clap_scan_paths.contains(&path) {
+            self.clap_scan_paths.push(path);
+        }
+    }
+
+    pub fn remove_clap_scan_path(&mut self, path: &PathBuf) {
+        self.clap_scan_paths.retain(|p| p != path);
+    }
+
+    /// Replace the list of available plugins with the result of a scan.
+    /// Internal plugins aren't listed, since the app adds those itself.
+    pub fn set_scan_result(&mut self, res: &RescanPluginDirectoriesRes) {
+        for (path, e) in res.failed_plugins.iter() {
+            log::warn!("Failed to scan plugin {:?}: {}", path, e);
+        }
+
+        let non_empty = |s: &String| (!s.is_empty()).then(|| s.clone());
+        self.available = res
+            .clap_plugins
+            .iter()
+            .map(|info| AvailablePlugin {
+                key: info.key.clone(),
+                name: info.description.name.clone(),
+                vendor: info.description.vendor.clone(),
+                version: info.description.version.clone(),
+                product_url: non_empty(&info.description.url),
+                manual_url: non_empty(&info.description.manual_url),
+                support_url: non_empty(&info.description.support_url),
+            })
+            .collect();
+        self.available.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
+    }
+
+    /// Build the request that adds a plugin to the graph, restoring the state
+    /// it saved if there is one. The plugin isn't connected to anything until
+    /// the channels are connected again, once the engine has added it. See
+    /// `channel_graph`.
+    pub fn add_plugin(
+        &mut self,
+        key: ScannedPluginKey,
+        preset: Option<PluginPreset>,
+    ) -> (HostedPluginID, ModifyGraphRequest) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push_back((id, key.rdn.clone()));
+        self.hosted.push(HostedPlugin {
+            id,
+            plugin_id: None,
+            handle: None,
+            params: FnvHashMap::default(),
+        });
+
+        let mut save_state = PluginSaveState::new_with_default_preset(key);
+        save_state.preset = preset;
+
+        let request = ModifyGraphRequest {
+            add_plugin_instances: vec![save_state],
+            remove_plugin_instances: vec![],
+            connect_new_edges: vec![],
+            disconnect_edges: vec![],
+        };
+        (id, request)
+    }
+
+    /// Forget a plugin. Returns the request that removes it from the graph,
+    /// unless the engine hasn't added it yet, in which case it is removed as
+    /// soon as it is added.
+    pub fn remove_plugin(&mut self, id: HostedPluginID) -> Option<ModifyGraphRequest> {
+        let index = self.hosted.iter().position(|p| p.id == id)?;
+        let plugin = self.hosted.remove(index);
+        Some(remove_request(plugin.plugin_id?))
+    }
+
+    /// Match a plugin the engine just added to the request it came from.
+    /// Returns `None` if it wasn't added through the host.
+    pub fn on_plugin_added(&mut self, plugin_id: &PluginInstanceID) -> Option<PluginAdded> {
+        let rdn = plugin_id.rdn();
+        let position =
+            self.pending.iter().position(|(_, pending)| pending.as_str() == rdn.as_str())?;
+        let (id, _) = self.pending.remove(position)?;
+
+        match self.hosted.iter_mut().find(|p| p.id == id) {
+            Some(plugin) => {
+                plugin.plugin_id = Some(plugin_id.clone());
+                Some(PluginAdded::Hosted(id))
+            }
+            None => Some(PluginAdded::Removed(remove_request(plugin_id.clone()))),
+        }
+    }
+
+    /// Store the handle of a plugin that was activated, along with the values
+    /// of its parameters.
+    pub fn on_plugin_activated(
+        &mut self,
+        plugin_id: &PluginInstanceID,
+        handle: PluginHandle,
+        param_values: FnvHashMap<ParamID, f64>,
+    ) -> Option<HostedPluginID> {
+        let plugin = self.plugin_mut(plugin_id)?;
+        plugin.handle = Some(handle);
+        plugin.params = param_values;
+        Some(plugin.id)
+    }
+
+    /// The handle can't be used until the plugin is activated again.
+    pub fn on_plugin_deactivated(
+        &mut self,
+        plugin_id: &PluginInstanceID,
+    ) -> Option<HostedPluginID> {
+        let plugin = self.plugin_mut(plugin_id)?;
+        plugin.handle = None;
+        Some(plugin.id)
+    }
+
+    /// Store the values of the parameters the plugin changed. Returns the ID
+    /// of the plugin and the new values.
+    pub fn on_params_modified(
+        &mut self,
+        plugin_id: &PluginInstanceID,
+        modified_params: &[ParamModifiedInfo],
+    ) -> Option<(HostedPluginID, Vec<(ParamID, f64)>)> {
+        let plugin = self.plugin_mut(plugin_id)?;
+        let values: Vec<(ParamID, f64)> = modified_params
+            .iter()
+            .filter_map(|info| Some((info.param_id, info.new_value?)))
+            .collect();
+        for (param_id, value) in values.iter() {
+            plugin.params.insert(*param_id, *value);
+        }
+        Some((plugin.id, values))
+    }
+
+    pub fn plugin(&self, id: HostedPluginID) -> Option<&HostedPlugin> {
+        self.hosted.iter().find(|p| p.id == id)
+    }
+
+    /// The handle of a plugin, to reach its parameters from the main thread.
+    /// `None` while the plugin isn't activated.
+    pub fn handle_mut(&mut self, id: HostedPluginID) -> Option<&mut PluginHandle> {
+        self.hosted.iter_mut().find(|p| p.id == id)?.handle.as_mut()
+    }
+
+    /// Forget every plugin, e.g. when the engine is deactivated and the graph
+    /// is gone. The list of available plugins is kept.
+    pub fn forget_hosted(&mut self) {
+        self.hosted.clear();
+        self.pending.clear();
+    }
+
+    /// True while any plugin that was asked for hasn't been added yet.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn hosted_ids(&self) -> impl Iterator<Item = HostedPluginID> + '_ {
+        self.hosted.iter().map(|p| p.id)
+    }
+
+    /// The ID of a plugin in the graph, or `None` until the engine has added
+    /// it.
+    pub fn plugin_id(&self, id: HostedPluginID) -> Option<&PluginInstanceID> {
+        self.plugin(id)?.plugin_id.as_ref()
+    }
+
+    /// Whether a plugin in the graph was added through the host and is still
+    /// there.
+    pub fn is_hosted(&self, plugin_id: &PluginInstanceID) -> bool {
+        self.hosted.iter().any(|p| p.plugin_id.as_ref() == Some(plugin_id))
+    }
+
+    fn plugin_mut(&mut self, plugin_id: &PluginInstanceID) -> Option<&mut HostedPlugin> {
+        self.hosted.iter_mut().find(|p| p.plugin_id.as_ref() == Some(plugin_id))
+    }
+}
+
+impl Default for PluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The request for an edge between the channels. See `channel_graph`.
+pub fn edge_request(edge: &Edge<PluginInstanceID>) -> EdgeReq {
+    EdgeReq {
+        edge_type: match edge.kind {
+            EdgeKind::Note => PortType::Note,
+            EdgeKind::Audio => PortType::Audio,
+            EdgeKind::Automation => PortType::ParamAutomation,
+        },
+        src_plugin_id: PluginIDReq::Existing(edge.src.clone()),
+        dst_plugin_id: PluginIDReq::Existing(edge.dst.clone()),
+        src_port_id: match edge.src_port {
+            0 => EdgeReqPortID::Main,
+            id => EdgeReqPortID::StableID(id),
+        },
+        src_port_channel: edge.src_channel,
+        dst_port_id: match edge.dst_port {
+            0 => EdgeReqPortID::Main,
+            id => EdgeReqPortID::StableID(id),
+        },
+        dst_port_channel: edge.dst_channel,
+        // Plugins without a note input or an audio output (i.e. effects and
+        // note effects) leave out the edges they don't have.
+        log_error_on_fail: false,
+    }
+}
+
+fn remove_request(plugin_id: PluginInstanceID) -> ModifyGraphRequest {
+    ModifyGraphRequest {
+        add_plugin_instances: vec![],
+        remove_plugin_instances: vec![plugin_id],
+        connect_new_edges: vec![],
+        disconnect_edges: vec![],
+    }
+}
@@ -0,0 +1,112 @@
+//! Changes to the audio graph without clicks.
+//!
+//! The engine compiles a modified graph in the background and switches from
+//! the old schedule to the new one between two blocks. Whatever was playing
+//! through a plugin that was added, removed or moved jumps at that point,
+//! which clicks. So while the graph changes, the fader of the master channel
+//! is ducked: the requests are held back until it has faded out, and it fades
+//! back in once the engine has swapped in the new graph. The mix dips for a
+//! moment instead of clicking.
+//!
+//! Requests made while a change is in progress are held back as well, and are
+//! sent together once the engine has answered the ones before them.
+
+use std::time::{Duration, Instant};
+
+use dropseed::ModifyGraphRequest;
+
+use super::fader_plug::FaderPlugHandle;
+
+/// How long to wait for the fader to fade out, or for the engine to answer,
+/// before carrying on anyway.
+const GRAPH_SWAP_TIMEOUT: Duration = Duration::from_millis(500);
+
+enum Phase {
+    Idle,
+    /// The fader is fading out.
+    FadingOut(Instant),
+    /// The requests were sent, and this many have not been answered yet.
+    Swapping(Instant, usize),
+}
+
+pub struct GraphSwap {
+    phase: Phase,
+    pending: Vec<ModifyGraphRequest>,
+}
+
+impl GraphSwap {
+    pub fn new() -> Self {
+        Self { phase: Phase::Idle, pending: Vec::new() }
+    }
+
+    /// Hold a request back until the master fader has faded out, see
+    /// `poll()`. Returns the request instead if it can be sent right away,
+    /// because nothing is held back and there is no master fader.
+    pub fn queue(
+        &mut self,
+        request: ModifyGraphRequest,
+        master_fader: Option<&mut FaderPlugHandle>,
+    ) -> Option<ModifyGraphRequest> {
+        if let Phase::Idle = self.phase {
+            match master_fader {
+                Some(master_fader) => master_fader.set_ducked(true),
+                None => return Some(request),
+            }
+            self.phase = Phase::FadingOut(Instant::now());
+        }
+        self.pending.push(request);
+        None
+    }
+
+    /// The engine answered one of the requests.
+    pub fn on_graph_modified(&mut self) {
+        if let Phase::Swapping(_, unanswered) = &mut self.phase {
+            *unanswered = unanswered.saturating_sub(1);
+        }
+    }
+
+    /// Returns the requests to send to the engine now, and fades the master
+    /// fader back in once they were answered. `master_fader` is `None` if the
+    /// master channel has no fader in the graph (anymore).
+    pub fn poll(&mut self, master_fader: Option<&mut FaderPlugHandle>) -> Vec<ModifyGraphRequest> {
+        match self.phase {
+            Phase::Idle => Vec::new(),
+            Phase::FadingOut(started) => {
+                let silent = master_fader.map_or(true, |fader| fader.is_silent());
+                if !silent && started.elapsed() < GRAPH_SWAP_TIMEOUT {
+                    return Vec::new();
+                }
+                self.phase = Phase::Swapping(Instant::now(), self.pending.len());
+                std::mem::take(&mut self.pending)
+            }
+            Phase::Swapping(started, unanswered) => {
+                if unanswered > 0 && started.elapsed() < GRAPH_SWAP_TIMEOUT {
+                    return Vec::new();
+                }
+                if self.pending.is_empty() {
+                    if let Some(fader) = master_fader {
+                        fader.set_ducked(false);
+                    }
+                    self.phase = Phase::Idle;
+                } else {
+                    // Still silent, so these go out on the next poll.
+                    self.phase = Phase::FadingOut(Instant::now());
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    /// Forget the requests that were held back, e.g. when the engine is
+    /// deactivated and the graph is gone.
+    pub fn clear(&mut self) {
+        self.phase = Phase::Idle;
+        self.pending.clear();
+    }
+}
+
+impl Default for GraphSwap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,541 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::{NoteOffEvent, NoteOnEvent, ParamValueEvent};
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{
+    Gradient, ParamF32, ParamF32Handle, Unit, DEFAULT_DB_GRADIENT, DEFAULT_SMOOTH_SECS,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use smallvec::SmallVec;
+
+use super::dsp::db_to_gain;
+use super::dsp::pitch::{
+    freq_to_midi_note, semitones_to_ratio, PitchDetector, PitchShifter, DEFAULT_A4_FREQ,
+};
+use super::scale::MusicalKey;
+
+pub static HARMONIZER_PLUG_RDN: &str = "app.meadowlark.harmonizer";
+
+pub const MAX_VOICES: usize = 4;
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// The maximum number of held notes on the MIDI sidechain that are tracked.
+const MAX_HELD_NOTES: usize = 16;
+
+const SHIFTER_WINDOW_SECS: f32 = 0.03;
+
+/// The range of vocal pitches that the input pitch detector looks for.
+const DETECTOR_MIN_FREQ: f32 = 70.0;
+const DETECTOR_MAX_FREQ: f32 = 1_200.0;
+
+/// The time it takes for a voice to fade in or out when it is turned on/off.
+const VOICE_FADE_SECS: f32 = 0.01;
+
+/// The number of parameters that each voice has (interval, level, pan).
+const PARAMS_PER_VOICE: usize = 3;
+
+pub struct HarmonizerPlugFactory;
+
+impl PluginFactory for HarmonizerPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: HARMONIZER_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Harmonizer".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(HarmonizerPlugMainThread::new()))
+    }
+}
+
+/// How the interval of each voice is decided.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HarmonyMode {
+    /// The interval parameter of each voice is a fixed number of semitones.
+    Chromatic,
+    /// The interval parameter of each voice is a number of scale degrees in the
+    /// given key. The pitch of the input is detected to find the correct
+    /// interval for each note that is sung.
+    Scale(MusicalKey),
+    /// Each voice follows a note held on the MIDI sidechain input (lowest held
+    /// note goes to the first voice, and so on).
+    Midi,
+}
+
+pub struct HarmonizerPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+}
+
+impl HarmonizerPlugHandle {
+    pub fn set_mode(&mut self, mode: HarmonyMode) {
+        self.send(ProcessMsg::SetMode(mode));
+    }
+
+    /// Set the number of active voices (clamped to `MAX_VOICES`).
+    pub fn set_num_voices(&mut self, num_voices: usize) {
+        self.send(ProcessMsg::SetNumVoices(num_voices.min(MAX_VOICES)));
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Harmonizer plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetMode(HarmonyMode),
+    SetNumVoices(usize),
+}
+
+struct VoiceParamsHandle {
+    pub interval: ParamF32Handle,
+    pub level: ParamF32Handle,
+    pub pan: ParamF32Handle,
+}
+
+struct VoiceParams {
+    pub interval: ParamF32,
+    pub level: ParamF32,
+    pub pan: ParamF32,
+}
+
+struct ParamsHandle {
+    pub dry: ParamF32Handle,
+    pub voices: Vec<VoiceParamsHandle>,
+}
+
+struct Params {
+    pub dry: ParamF32,
+    pub voices: Vec<VoiceParams>,
+}
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let (dry, dry_handle) = ParamF32::from_value(
+            0.0,
+            0.0,
+            -90.0,
+            6.0,
+            DEFAULT_DB_GRADIENT,
+            Unit::Decibels,
+            DEFAULT_SMOOTH_SECS,
+            sample_rate,
+            max_frames,
+        );
+
+        let mut voices = Vec::with_capacity(MAX_VOICES);
+        let mut voice_handles = Vec::with_capacity(MAX_VOICES);
+        for i in 0..MAX_VOICES {
+            let (interval, interval_handle) = ParamF32::from_value(
+                default_interval(i),
+                default_interval(i),
+                -24.0,
+                24.0,
+                Gradient::Linear,
+                Unit::Generic,
+                0.0,
+                sample_rate,
+                max_frames,
+            );
+            let (level, level_handle) = ParamF32::from_value(
+                -6.0,
+                -6.0,
+                -90.0,
+                6.0,
+                DEFAULT_DB_GRADIENT,
+                Unit::Decibels,
+                DEFAULT_SMOOTH_SECS,
+                sample_rate,
+                max_frames,
+            );
+            let (pan, pan_handle) = ParamF32::from_value(
+                default_pan(i),
+                default_pan(i),
+                -1.0,
+                1.0,
+                Gradient::Linear,
+                Unit::Generic,
+                DEFAULT_SMOOTH_SECS,
+                sample_rate,
+                max_frames,
+            );
+
+            voices.push(VoiceParams { interval, level, pan });
+            voice_handles.push(VoiceParamsHandle {
+                interval: interval_handle,
+                level: level_handle,
+                pan: pan_handle,
+            });
+        }
+
+        (Params { dry, voices }, ParamsHandle { dry: dry_handle, voices: voice_handles })
+    }
+}
+
+fn default_interval(voice: usize) -> f32 {
+    [4.0, 7.0, -5.0, 12.0][voice % 4]
+}
+
+fn default_pan(voice: usize) -> f32 {
+    [-0.5, 0.5, -0.25, 0.25][voice % 4]
+}
+
+pub struct HarmonizerPlugMainThread {
+    params: ParamsHandle,
+}
+
+impl HarmonizerPlugMainThread {
+    fn new() -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle }
+    }
+}
+
+impl PluginMainThread for HarmonizerPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let sr = sample_rate.0 as f32;
+
+        let voices = (0..MAX_VOICES)
+            .map(|_| Voice {
+                shifter: PitchShifter::new(sr, SHIFTER_WINDOW_SECS),
+                ratio: 1.0,
+                gain: 0.0,
+                target_gain: 0.0,
+            })
+            .collect();
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(HarmonizerPlugAudioThread {
+                params,
+                from_handle_rx,
+                mode: HarmonyMode::Chromatic,
+                num_voices: 2,
+                voices: Owned::new(coll_handle, voices),
+                detector: Owned::new(
+                    coll_handle,
+                    PitchDetector::new(sr, DETECTOR_MIN_FREQ, DETECTOR_MAX_FREQ),
+                ),
+                held_notes: SmallVec::new(),
+                fade_step: 1.0 / (VOICE_FADE_SECS * sr),
+            }),
+            internal_handle: Some(Box::new(HarmonizerPlugHandle { to_audio_thread_tx })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+
+    fn note_ports_ext(&mut self) -> Result<ext::note_ports::PluginNotePortsExt, String> {
+        // The MIDI sidechain input.
+        Ok(ext::note_ports::PluginNotePortsExt::single_in())
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        (1 + MAX_VOICES * PARAMS_PER_VOICE) as u32
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        if param_index == 0 {
+            return Ok(ext::params::ParamInfo::new(
+                ParamID(0),
+                ParamInfoFlags::default_float(),
+                "dry".into(),
+                String::new(),
+                -90.0,
+                6.0,
+                0.0,
+            ));
+        }
+
+        let voice = (param_index - 1) / PARAMS_PER_VOICE;
+        if voice >= MAX_VOICES {
+            return Err(());
+        }
+        let module = format!("voice {}", voice + 1);
+
+        let (name, min, max, default) = match (param_index - 1) % PARAMS_PER_VOICE {
+            0 => ("interval", -24.0, 24.0, f64::from(default_interval(voice))),
+            1 => ("level", -90.0, 6.0, -6.0),
+            _ => ("pan", -1.0, 1.0, f64::from(default_pan(voice))),
+        };
+
+        Ok(ext::params::ParamInfo::new(
+            ParamID(param_index as u32),
+            ParamInfoFlags::default_float(),
+            name.into(),
+            module,
+            min,
+            max,
+            default,
+        ))
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        if param_id.0 == 0 {
+            return Ok(f64::from(self.params.dry.value()));
+        }
+
+        let index = param_id.0 as usize - 1;
+        let voice = self.params.voices.get(index / PARAMS_PER_VOICE).ok_or(())?;
+        let value = match index % PARAMS_PER_VOICE {
+            0 => voice.interval.value(),
+            1 => voice.level.value(),
+            _ => voice.pan.value(),
+        };
+
+        Ok(f64::from(value))
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        if param_id.0 == 0 {
+            return Ok(format!("{:.2} dB", value));
+        }
+        if param_id.0 as usize > MAX_VOICES * PARAMS_PER_VOICE {
+            return Err(());
+        }
+
+        match (param_id.0 as usize - 1) % PARAMS_PER_VOICE {
+            0 => Ok(format!("{:+.0}", value.round())),
+            1 => Ok(format!("{:.2} dB", value)),
+            _ => Ok(format!("{:.0}", value * 100.0)),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        if param_id.0 as usize > MAX_VOICES * PARAMS_PER_VOICE {
+            return Err(());
+        }
+
+        text.parse().map_err(|_| ())
+    }
+}
+
+struct Voice {
+    shifter: PitchShifter,
+    ratio: f32,
+
+    gain: f32,
+    target_gain: f32,
+}
+
+pub struct HarmonizerPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    mode: HarmonyMode,
+    num_voices: usize,
+
+    voices: Owned<Vec<Voice>>,
+    detector: Owned<PitchDetector>,
+
+    /// The notes currently held on the MIDI sidechain, sorted from lowest to highest.
+    held_notes: SmallVec<[i16; MAX_HELD_NOTES]>,
+
+    fade_step: f32,
+}
+
+impl HarmonizerPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                let id = param_value.param_id() as usize;
+                let value = param_value.value() as f32;
+
+                if id == 0 {
+                    self.params.dry.set_value(value);
+                } else if let Some(voice) = self.params.voices.get_mut((id - 1) / PARAMS_PER_VOICE)
+                {
+                    match (id - 1) % PARAMS_PER_VOICE {
+                        0 => voice.interval.set_value(value),
+                        1 => voice.level.set_value(value),
+                        _ => voice.pan.set_value(value),
+                    }
+                }
+            } else if let Some(note_on) = e.as_event::<NoteOnEvent>() {
+                let key = note_on.0.key();
+                if !self.held_notes.contains(&key) && self.held_notes.len() < MAX_HELD_NOTES {
+                    let pos = self.held_notes.iter().position(|n| *n > key);
+                    self.held_notes.insert(pos.unwrap_or(self.held_notes.len()), key);
+                }
+            } else if let Some(note_off) = e.as_event::<NoteOffEvent>() {
+                let key = note_off.0.key();
+                self.held_notes.retain(|n| *n != key);
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetMode(mode) => {
+                    self.mode = mode;
+                    self.held_notes.clear();
+                }
+                ProcessMsg::SetNumVoices(num_voices) => self.num_voices = num_voices,
+            }
+        }
+    }
+
+    /// Decide the pitch ratio and the target gain of every voice for this block.
+    fn update_voices(&mut self, frames: usize) {
+        let input_note = self.detector.pitch().map(|f| freq_to_midi_note(f, DEFAULT_A4_FREQ));
+
+        for (i, (voice, params)) in
+            self.voices.iter_mut().zip(self.params.voices.iter_mut()).enumerate()
+        {
+            let interval = params.interval.smoothed(frames)[0];
+
+            if i >= self.num_voices {
+                voice.target_gain = 0.0;
+                continue;
+            }
+
+            match self.mode {
+                HarmonyMode::Chromatic => {
+                    voice.ratio = semitones_to_ratio(interval.round());
+                    voice.target_gain = 1.0;
+                }
+                HarmonyMode::Scale(key) => {
+                    // If no pitch was detected then just keep the previous ratio.
+                    if let Some(input_note) = input_note {
+                        let target = key
+                            .transpose_degrees(input_note.round() as i32, interval.round() as i32);
+                        voice.ratio = semitones_to_ratio(target as f32 - input_note);
+                    }
+                    voice.target_gain = 1.0;
+                }
+                HarmonyMode::Midi => match (self.held_notes.get(i), input_note) {
+                    (Some(note), Some(input_note)) => {
+                        voice.ratio = semitones_to_ratio(f32::from(*note) - input_note);
+                        voice.target_gain = 1.0;
+                    }
+                    (Some(_), None) => {
+                        // Keep the voice sounding at its previous ratio through
+                        // unpitched parts (consonants, breaths).
+                        voice.target_gain = 1.0;
+                    }
+                    (None, _) => voice.target_gain = 0.0,
+                },
+            }
+        }
+    }
+}
+
+impl PluginAudioThread for HarmonizerPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.shifter.reset();
+            voice.gain = 0.0;
+        }
+        self.detector.reset();
+        self.held_notes.clear();
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+
+        let frames = proc_info.frames;
+
+        self.update_voices(frames);
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let in_l = &in_l[0..frames];
+        let in_r = &in_r[0..frames];
+        let out_l = &mut out_l[0..frames];
+        let out_r = &mut out_r[0..frames];
+
+        let dry = self.params.dry.smoothed(frames);
+        for i in 0..frames {
+            let g = db_to_gain(dry[i]);
+            out_l[i] = in_l[i] * g;
+            out_r[i] = in_r[i] * g;
+        }
+
+        for (voice, params) in self.voices.iter_mut().zip(self.params.voices.iter_mut()) {
+            let level = params.level.smoothed(frames);
+            let pan = params.pan.smoothed(frames);
+
+            if voice.gain == 0.0 && voice.target_gain == 0.0 {
+                continue;
+            }
+
+            for i in 0..frames {
+                let mono = (in_l[i] + in_r[i]) * 0.5;
+                let shifted = voice.shifter.process(mono, voice.ratio);
+
+                if voice.gain < voice.target_gain {
+                    voice.gain = (voice.gain + self.fade_step).min(voice.target_gain);
+                } else if voice.gain > voice.target_gain {
+                    voice.gain = (voice.gain - self.fade_step).max(voice.target_gain);
+                }
+
+                // Equal-power panning.
+                let angle = (pan[i] + 1.0) * std::f32::consts::FRAC_PI_4;
+                let g = db_to_gain(level[i]) * voice.gain;
+
+                out_l[i] += shifted * g * angle.cos();
+                out_r[i] += shifted * g * angle.sin();
+            }
+        }
+
+        for i in 0..frames {
+            self.detector.push((in_l[i] + in_r[i]) * 0.5);
+        }
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
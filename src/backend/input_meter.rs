@@ -0,0 +1,127 @@
+//! Meters the inputs of the audio device whenever they are open, so levels
+//! can be set before recording. This runs on its own input stream, regardless
+//! of whether anything is armed or the transport is playing.
+
+use std::error::Error;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::Stream;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Inputs past this many are not metered.
+pub const MAX_METERED_INPUTS: usize = 32;
+
+/// The peak of each input since the UI last read it, as the bits of an `f32`
+/// so the audio thread can update it without locking.
+struct InputPeaks {
+    peaks: Vec<AtomicU32>,
+    /// Set when an input reached full scale since the UI last read it.
+    clipped: Vec<AtomicBool>,
+}
+
+/// The peak level of one device input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputLevel {
+    /// The highest absolute sample value since the last read.
+    pub peak: f32,
+    pub clipped: bool,
+}
+
+pub struct InputMeterHandle {
+    _cpal_stream: Stream,
+    peaks: Arc<InputPeaks>,
+    device_name: String,
+    device_lost: Arc<AtomicBool>,
+}
+
+impl InputMeterHandle {
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    /// The number of inputs that are metered.
+    pub fn num_channels(&self) -> usize {
+        self.peaks.peaks.len()
+    }
+
+    /// Returns `true` once the input device has gone away. The meters stay at
+    /// zero after this and the handle should be dropped.
+    pub fn device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
+    /// The level of every input since the last call, resetting them.
+    pub fn take_levels(&self) -> Vec<InputLevel> {
+        self.peaks
+            .peaks
+            .iter()
+            .zip(self.peaks.clipped.iter())
+            .map(|(peak, clipped)| InputLevel {
+                peak: f32::from_bits(peak.swap(0, Ordering::Relaxed)),
+                clipped: clipped.swap(false, Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// This is temporary, like `system_io::temp_spawn_cpal_default_output_only()`.
+/// Eventually the inputs will be opened together with the outputs using
+/// `rainout`.
+pub fn temp_spawn_cpal_default_input_meter() -> Result<InputMeterHandle, Box<dyn Error>> {
+    let cpal_host = cpal::default_host();
+
+    let device = cpal_host
+        .default_input_device()
+        .ok_or("CPAL: no default audio in device found".to_string())?;
+
+    let device_name = device.name().unwrap_or_else(|_| String::from("Unknown device"));
+    log::info!("Selected default CPAL input device: {:?}", &device_name);
+
+    let config = device.default_input_config()?;
+    let num_in_channels = usize::from(config.channels());
+    let num_metered = num_in_channels.min(MAX_METERED_INPUTS);
+
+    let peaks = Arc::new(InputPeaks {
+        peaks: (0..num_metered).map(|_| AtomicU32::new(0)).collect(),
+        clipped: (0..num_metered).map(|_| AtomicBool::new(false)).collect(),
+    });
+
+    let device_lost = Arc::new(AtomicBool::new(false));
+    let device_lost_clone = Arc::clone(&device_lost);
+    let error_callback = move |e: cpal::StreamError| {
+        log::error!("CPAL input stream error: {}", e);
+
+        if let cpal::StreamError::DeviceNotAvailable = e {
+            device_lost_clone.store(true, Ordering::Relaxed);
+        }
+    };
+
+    let peaks_clone = Arc::clone(&peaks);
+    let cpal_stream = device.build_input_stream(
+        &config.into(),
+        move |audio_buffer: &[f32], _: &cpal::InputCallbackInfo| {
+            for ch in 0..num_metered {
+                let peak = audio_buffer
+                    .iter()
+                    .skip(ch)
+                    .step_by(num_in_channels)
+                    .fold(0.0f32, |peak, s| peak.max(s.abs()));
+
+                // Keep the highest peak until the UI reads it. Positive floats
+                // order the same as their bits.
+                peaks_clone.peaks[ch].fetch_max(peak.to_bits(), Ordering::Relaxed);
+                if peak >= 1.0 {
+                    peaks_clone.clipped[ch].store(true, Ordering::Relaxed);
+                }
+            }
+        },
+        error_callback,
+    )?;
+
+    cpal_stream.play()?;
+
+    log::info!("Started CPAL input meter stream with {} channels", num_in_channels);
+
+    Ok(InputMeterHandle { _cpal_stream: cpal_stream, peaks, device_name, device_lost })
+}
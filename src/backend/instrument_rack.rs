@@ -0,0 +1,136 @@
+//! A rack that plays several instrument chains on one track. Each chain gets
+//! the notes inside its key and velocity zone, so chains can split the
+//! keyboard (zones that don't overlap) or layer it (zones that do), and the
+//! outputs of the chains are mixed with their own gain and pan.
+//!
+//! This is only the model of the rack: the zones and the mix of its chains,
+//! which are edited in the inspector and saved with the channel. The chains
+//! don't host instruments yet, so the rack is not part of the audio graph and
+//! makes no sound of its own. Instruments play as effects of the channel (see
+//! `channel_graph`). `route` and `mix_chain` are what a host of the chains
+//! runs for each note and each block.
+
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+use super::dsp::{db_to_gain, pan_gains};
+use super::midi_transform::{TransformNote, MAX_KEY, MIN_KEY};
+
+/// The most chains a rack can hold.
+pub const MAX_CHAINS: usize = 16;
+
+/// The range of the gain of a chain in decibels.
+pub const MAX_CHAIN_DB: f32 = 12.0;
+
+/// The notes a chain plays. Both ends of the ranges are included.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RackZone {
+    pub low_key: i16,
+    pub high_key: i16,
+    /// The velocities are from 0.0 to 1.0.
+    pub min_velocity: f32,
+    pub max_velocity: f32,
+}
+
+impl RackZone {
+    /// Note offs aren't matched by velocity, so that a note always ends.
+    pub fn contains(&self, note: &TransformNote, on: bool) -> bool {
+        (self.low_key..=self.high_key).contains(&note.key)
+            && (!on || (self.min_velocity..=self.max_velocity).contains(&note.velocity))
+    }
+}
+
+impl Default for RackZone {
+    fn default() -> Self {
+        Self { low_key: MIN_KEY, high_key: MAX_KEY, min_velocity: 0.0, max_velocity: 1.0 }
+    }
+}
+
+/// One instrument of a rack and its place in the mix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RackChain {
+    pub name: String,
+    pub zone: RackZone,
+    pub gain_db: f32,
+    /// From -1.0 (left) to 1.0 (right).
+    pub pan: f32,
+    pub muted: bool,
+    pub soloed: bool,
+}
+
+impl RackChain {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            zone: RackZone::default(),
+            gain_db: 0.0,
+            pan: 0.0,
+            muted: false,
+            soloed: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstrumentRack {
+    pub chains: Vec<RackChain>,
+}
+
+impl InstrumentRack {
+    /// True if the chain is heard, taking the other chains being soloed into
+    /// account.
+    pub fn is_audible(&self, chain: usize) -> bool {
+        let any_soloed = self.chains.iter().any(|c| c.soloed);
+        self.chains.get(chain).map_or(false, |c| !c.muted && (!any_soloed || c.soloed))
+    }
+
+    /// The chains an incoming note on or note off is sent to.
+    ///
+    /// Note offs go to every chain the note could have been sent to, even
+    /// muted ones, since the chain may have been muted while the note was
+    /// held.
+    pub fn route(&self, note: &TransformNote, on: bool) -> SmallVec<[usize; 4]> {
+        self.chains
+            .iter()
+            .enumerate()
+            .filter(|(i, chain)| chain.zone.contains(note, on) && (!on || self.is_audible(*i)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The gains of the left and right output of a chain, with an equal power
+    /// pan law.
+    pub fn chain_gains(&self, chain: usize) -> (f32, f32) {
+        let chain = match self.chains.get(chain) {
+            Some(c) if self.is_audible(chain) => c,
+            _ => return (0.0, 0.0),
+        };
+        let gain = db_to_gain(chain.gain_db);
+        let (l, r) = pan_gains(chain.pan);
+        (gain * l, gain * r)
+    }
+
+    /// Add the stereo output of a chain to the output of the rack.
+    ///
+    /// This doesn't allocate, so it can be used in the audio thread.
+    pub fn mix_chain(
+        &self,
+        chain: usize,
+        in_l: &[f32],
+        in_r: &[f32],
+        out_l: &mut [f32],
+        out_r: &mut [f32],
+    ) {
+        let (gain_l, gain_r) = self.chain_gains(chain);
+        if gain_l == 0.0 && gain_r == 0.0 {
+            return;
+        }
+        for (out, s) in out_l.iter_mut().zip(in_l.iter()) {
+            *out += s * gain_l;
+        }
+        for (out, s) in out_r.iter_mut().zip(in_r.iter()) {
+            *out += s * gain_r;
+        }
+    }
+}
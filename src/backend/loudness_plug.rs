@@ -0,0 +1,157 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use super::dsp::loudness::{LoudnessMeter, LoudnessReading};
+
+pub static LOUDNESS_PLUG_RDN: &str = "app.meadowlark.loudness";
+
+/// Readings are sent every 100ms, so this is enough for a few seconds of the
+/// UI not polling.
+const READING_BUFFER_SIZE: usize = 128;
+
+pub struct LoudnessPlugFactory;
+
+impl PluginFactory for LoudnessPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: LOUDNESS_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Loudness Meter".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(LoudnessPlugMainThread {}))
+    }
+}
+
+/// A loudness reading tagged with the position of the transport at the time it
+/// was measured.
+#[derive(Debug, Clone, Copy)]
+pub struct TimedLoudnessReading {
+    pub reading: LoudnessReading,
+    /// The position of the playhead in frames at the end of the measurement.
+    pub playhead_frame: u64,
+    /// Whether the transport was playing when this reading was measured.
+    pub playing: bool,
+}
+
+pub struct LoudnessPlugHandle {
+    from_audio_thread_rx: Consumer<TimedLoudnessReading>,
+    latest: Option<LoudnessReading>,
+}
+
+impl LoudnessPlugHandle {
+    /// Pop all readings that were measured since the last call.
+    pub fn poll_readings(&mut self) -> impl Iterator<Item = TimedLoudnessReading> + '_ {
+        std::iter::from_fn(move || {
+            let reading = self.from_audio_thread_rx.pop().ok()?;
+            self.latest = Some(reading.reading);
+            Some(reading)
+        })
+    }
+
+    /// The most recent reading returned by `poll_readings()`.
+    pub fn latest(&self) -> Option<LoudnessReading> {
+        self.latest
+    }
+}
+
+pub struct LoudnessPlugMainThread {}
+
+impl PluginMainThread for LoudnessPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        _max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_handle_tx, from_audio_thread_rx) =
+            RingBuffer::<TimedLoudnessReading>::new(READING_BUFFER_SIZE);
+        let to_handle_tx = Owned::new(coll_handle, to_handle_tx);
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(LoudnessPlugAudioThread {
+                to_handle_tx,
+                meter: LoudnessMeter::new(sample_rate.0 as f32),
+            }),
+            internal_handle: Some(Box::new(LoudnessPlugHandle {
+                from_audio_thread_rx,
+                latest: None,
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+}
+
+pub struct LoudnessPlugAudioThread {
+    to_handle_tx: Owned<Producer<TimedLoudnessReading>>,
+    meter: LoudnessMeter,
+}
+
+impl PluginAudioThread for LoudnessPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.meter.reset();
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        _in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        let frames = proc_info.frames;
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        out_l[0..frames].copy_from_slice(&in_l[0..frames]);
+        out_r[0..frames].copy_from_slice(&in_r[0..frames]);
+
+        let playing = proc_info.transport.is_playing();
+        let playhead_frame = proc_info.transport.playhead_frame();
+
+        for i in 0..frames {
+            if let Some(reading) = self.meter.process(in_l[i], in_r[i]) {
+                let playhead_frame =
+                    if playing { playhead_frame + i as u64 + 1 } else { playhead_frame };
+
+                // If the UI isn't keeping up then dropping readings is fine.
+                let _ = self.to_handle_tx.push(TimedLoudnessReading {
+                    reading,
+                    playhead_frame,
+                    playing,
+                });
+            }
+        }
+
+        ProcessStatus::Continue
+    }
+}
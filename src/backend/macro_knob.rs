@@ -0,0 +1,68 @@
+//! Macro knobs, which turn several parameters at once. Every parameter a knob
+//! is mapped to follows the knob within a range of its own, along a curve of
+//! its own.
+//!
+//! TODO: Move the parameters from the audio thread once the parameters of
+//! effects can be automated in the graph.
+
+use serde::{Deserialize, Serialize};
+
+/// The most macro knobs a channel can have.
+pub const MAX_MACRO_KNOBS: usize = 8;
+
+/// How a parameter follows a macro knob between the ends of its range.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MacroCurve {
+    Linear,
+    /// Moves slowly near the bottom of the knob and quickly near the top.
+    Exponential,
+    /// Moves quickly near the bottom of the knob and slowly near the top.
+    Logarithmic,
+    /// Moves slowly near both ends of the knob.
+    SCurve,
+}
+
+impl MacroCurve {
+    /// Bend a knob position from 0.0 to 1.0. The ends stay where they are.
+    pub fn apply(&self, amount: f64) -> f64 {
+        let amount = amount.clamp(0.0, 1.0);
+        match self {
+            MacroCurve::Linear => amount,
+            MacroCurve::Exponential => amount * amount,
+            MacroCurve::Logarithmic => amount.sqrt(),
+            MacroCurve::SCurve => amount * amount * (3.0 - 2.0 * amount),
+        }
+    }
+}
+
+impl Default for MacroCurve {
+    fn default() -> Self {
+        MacroCurve::Linear
+    }
+}
+
+/// How one parameter follows a macro knob. Values are normalized, and `min`
+/// may be above `max` for a parameter that goes down as the knob goes up.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MacroMapping {
+    /// The value of the parameter when the knob is all the way down.
+    pub min: f64,
+    /// The value of the parameter when the knob is all the way up.
+    pub max: f64,
+    pub curve: MacroCurve,
+}
+
+impl MacroMapping {
+    /// The normalized value of the parameter at a knob position from 0.0 to
+    /// 1.0.
+    pub fn value(&self, amount: f64) -> f64 {
+        let value = self.min + (self.max - self.min) * self.curve.apply(amount);
+        value.clamp(0.0, 1.0)
+    }
+}
+
+impl Default for MacroMapping {
+    fn default() -> Self {
+        Self { min: 0.0, max: 1.0, curve: MacroCurve::default() }
+    }
+}
@@ -0,0 +1,398 @@
+//! A click on every beat while the transport plays, with an accent on the
+//! first beat of each bar. It can also count in a number of bars while the
+//! transport is stopped, i.e. before a recording starts.
+
+use basedrop::{Owned, Shared};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use pcm_loader::PcmRAM;
+use rtrb::{Consumer, Producer, RingBuffer};
+use smallvec::SmallVec;
+use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::dsp::db_to_gain;
+use super::tempo_bus::TempoBus;
+use super::time_signature::TimeSignature;
+
+pub static METRONOME_PLUG_RDN: &str = "app.meadowlark.metronome";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// The most bars that can be counted in.
+pub const MAX_COUNT_IN_BARS: u32 = 4;
+
+pub const DEFAULT_METRONOME_DB: f32 = -6.0;
+
+/// How long a built-in click sounds.
+const TONE_CLICK_SECS: f32 = 0.05;
+
+/// The sound of the clicks.
+#[derive(Clone)]
+pub enum ClickSound {
+    /// Short sine blips, higher on the accent.
+    Beep,
+    /// A dry knock that dies away quickly.
+    Woodblock,
+    /// Samples chosen by the user, for the accent and for the other beats.
+    Samples { accent: Shared<PcmRAM>, beat: Shared<PcmRAM> },
+}
+
+impl ClickSound {
+    /// The frequency and the decay time in seconds of a built-in click, or
+    /// `None` for samples.
+    fn tone(&self, accent: bool) -> Option<(f32, f32)> {
+        match (self, accent) {
+            (ClickSound::Beep, true) => Some((1_500.0, 0.02)),
+            (ClickSound::Beep, false) => Some((1_000.0, 0.02)),
+            (ClickSound::Woodblock, true) => Some((1_200.0, 0.006)),
+            (ClickSound::Woodblock, false) => Some((800.0, 0.006)),
+            (ClickSound::Samples { .. }, _) => None,
+        }
+    }
+}
+
+pub struct MetronomePlugFactory {
+    tempo_bus: TempoBus,
+}
+
+impl MetronomePlugFactory {
+    pub fn new(tempo_bus: TempoBus) -> Self {
+        Self { tempo_bus }
+    }
+}
+
+impl PluginFactory for MetronomePlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: METRONOME_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Metronome".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(MetronomePlugMainThread { tempo_bus: self.tempo_bus.clone() }))
+    }
+}
+
+pub struct MetronomePlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+
+    count_in_finished: Arc<AtomicBool>,
+}
+
+impl MetronomePlugHandle {
+    /// Click along with the transport, or stop.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.send(ProcessMsg::SetEnabled(enabled));
+    }
+
+    /// Click on the beats of `signature`, accenting the first beat of each
+    /// bar, with a bar starting at `bar_start_beats` on the timeline. This is
+    /// sent again whenever the playhead passes a change of signature.
+    pub fn set_meter(&mut self, signature: TimeSignature, bar_start_beats: f64) {
+        if signature.is_valid() {
+            self.send(ProcessMsg::SetMeter { signature, bar_start_beats });
+        }
+    }
+
+    pub fn set_sound(&mut self, sound: ClickSound) {
+        self.send(ProcessMsg::SetSound(sound));
+    }
+
+    pub fn set_level_db(&mut self, db: f32) {
+        self.send(ProcessMsg::SetGain(db_to_gain(db)));
+    }
+
+    /// Click `bars` bars at the current tempo, whether or not the metronome
+    /// is enabled. `count_in_finished()` turns true when the bar after the
+    /// last one starts.
+    pub fn count_in(&mut self, bars: u32) {
+        self.count_in_finished.store(false, Ordering::Relaxed);
+        self.send(ProcessMsg::CountIn(bars.min(MAX_COUNT_IN_BARS)));
+    }
+
+    pub fn cancel_count_in(&mut self) {
+        self.send(ProcessMsg::CountIn(0));
+    }
+
+    pub fn count_in_finished(&self) -> bool {
+        self.count_in_finished.load(Ordering::Relaxed)
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Metronome plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetEnabled(bool),
+    SetMeter { signature: TimeSignature, bar_start_beats: f64 },
+    SetSound(ClickSound),
+    SetGain(f32),
+    CountIn(u32),
+}
+
+pub struct MetronomePlugMainThread {
+    tempo_bus: TempoBus,
+}
+
+impl PluginMainThread for MetronomePlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        _max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let count_in_finished = Arc::new(AtomicBool::new(false));
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(MetronomePlugAudioThread {
+                from_handle_rx,
+                tempo_bus: self.tempo_bus.clone(),
+                sample_rate: sample_rate.0 as f32,
+                enabled: false,
+                signature: TimeSignature::default(),
+                bar_start_beats: 0.0,
+                sound: ClickSound::Beep,
+                gain: db_to_gain(DEFAULT_METRONOME_DB),
+                count_in: None,
+                count_in_finished: Arc::clone(&count_in_finished),
+                click: None,
+            }),
+            internal_handle: Some(Box::new(MetronomePlugHandle {
+                to_audio_thread_tx,
+                count_in_finished,
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_out())
+    }
+}
+
+/// A count-in that is running.
+#[derive(Debug, Clone, Copy)]
+struct CountIn {
+    /// The beat that is clicked next, counted from 0.
+    beat: u32,
+    beats: u32,
+    /// The frames from the start of the current block to the next beat.
+    next_frame: f64,
+}
+
+/// A click that is sounding.
+#[derive(Debug, Clone, Copy)]
+struct Click {
+    accent: bool,
+    /// The frames since the click started.
+    pos: usize,
+}
+
+pub struct MetronomePlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+    tempo_bus: TempoBus,
+    sample_rate: f32,
+
+    enabled: bool,
+    signature: TimeSignature,
+    /// The start of a bar of `signature`, in beats from the start of the
+    /// project.
+    bar_start_beats: f64,
+    sound: ClickSound,
+    gain: f32,
+
+    count_in: Option<CountIn>,
+    count_in_finished: Arc<AtomicBool>,
+
+    click: Option<Click>,
+}
+
+impl MetronomePlugAudioThread {
+    fn poll(&mut self) {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetEnabled(enabled) => self.enabled = enabled,
+                ProcessMsg::SetMeter { signature, bar_start_beats } => {
+                    self.signature = signature;
+                    self.bar_start_beats = bar_start_beats;
+                }
+                ProcessMsg::SetSound(sound) => {
+                    self.sound = sound;
+                    self.click = None;
+                }
+                ProcessMsg::SetGain(gain) => self.gain = gain,
+                ProcessMsg::CountIn(0) => self.count_in = None,
+                ProcessMsg::CountIn(bars) => {
+                    self.count_in = Some(CountIn {
+                        beat: 0,
+                        beats: bars * self.signature.numerator,
+                        next_frame: 0.0,
+                    });
+                }
+            }
+        }
+    }
+
+    /// The clicks that start in the current block, by frame, and whether each
+    /// is accented.
+    fn beats_in_block(&mut self, proc_info: &ProcInfo) -> SmallVec<[(usize, bool); 4]> {
+        let frames = proc_info.frames as f64;
+        let numerator = self.signature.numerator;
+        let beat_length = self.signature.beat_length();
+        let mut beats = SmallVec::new();
+
+        if let Some(count_in) = &mut self.count_in {
+            // The transport is stopped, so the count-in clicks at the tempo
+            // of the playhead.
+            let sample_rate = f64::from(self.sample_rate);
+            let bpm = self
+                .tempo_bus
+                .tempo()
+                .bpm_at_frame(proc_info.transport.playhead_frame(), sample_rate);
+            let frames_per_beat = 60.0 / bpm.max(1.0) * sample_rate;
+            let frames_per_click = frames_per_beat * beat_length;
+            while count_in.next_frame < frames {
+                if count_in.beat == count_in.beats {
+                    self.count_in = None;
+                    self.count_in_finished.store(true, Ordering::Relaxed);
+                    return beats;
+                }
+                let accent = count_in.beat % numerator == 0;
+                beats.push((count_in.next_frame as usize, accent));
+                count_in.beat += 1;
+                count_in.next_frame += frames_per_click;
+            }
+            count_in.next_frame -= frames;
+        } else if self.enabled && proc_info.transport.is_playing() {
+            // The playhead is turned into beats through the tempo map, so the
+            // clicks stay on the beats of the timeline after tempo changes.
+            let (start_beat, beats_per_frame) = self.tempo_bus.tempo().block_beats(
+                proc_info.transport.playhead_frame(),
+                proc_info.frames,
+                f64::from(self.sample_rate),
+            );
+
+            // The clicks are counted from the start of the bar the meter was
+            // given for.
+            let mut click = ((start_beat - self.bar_start_beats) / beat_length).ceil() as i64;
+            loop {
+                let beat = self.bar_start_beats + click as f64 * beat_length;
+                let frame = (beat - start_beat) / beats_per_frame;
+                if frame >= frames {
+                    break;
+                }
+                beats.push((frame as usize, click.rem_euclid(i64::from(numerator)) == 0));
+                click += 1;
+            }
+        }
+
+        beats
+    }
+
+    /// Write the sounding click into the outputs, which start out silent.
+    fn render(&mut self, out_l: &mut [f32], out_r: &mut [f32]) {
+        let click = match &mut self.click {
+            Some(click) => click,
+            None => return,
+        };
+
+        let len = match (&self.sound, self.sound.tone(click.accent)) {
+            (_, Some((freq, decay_secs))) => {
+                let len = (TONE_CLICK_SECS * self.sample_rate) as usize;
+                let decay = 1.0 / (decay_secs * self.sample_rate);
+                let phase_inc = freq * TAU / self.sample_rate;
+                let n = out_l.len().min(len.saturating_sub(click.pos));
+                for i in 0..n {
+                    let t = (click.pos + i) as f32;
+                    let s = (t * phase_inc).sin() * (-t * decay).exp() * self.gain;
+                    out_l[i] += s;
+                    out_r[i] += s;
+                }
+                len
+            }
+            (ClickSound::Samples { accent, beat }, None) => {
+                let pcm = if click.accent { accent } else { beat };
+                let len = pcm.len_frames() as usize;
+                let n = out_l.len().min(len.saturating_sub(click.pos));
+                if n > 0 {
+                    pcm.fill_stereo_f32(click.pos, &mut out_l[..n], &mut out_r[..n]);
+                    out_l[..n].iter_mut().for_each(|s| *s *= self.gain);
+                    out_r[..n].iter_mut().for_each(|s| *s *= self.gain);
+                }
+                len
+            }
+            _ => 0,
+        };
+
+        click.pos += out_l.len();
+        if click.pos >= len {
+            self.click = None;
+        }
+    }
+}
+
+impl PluginAudioThread for MetronomePlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.click = None;
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        _in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll();
+
+        let frames = proc_info.frames;
+        let beats = self.beats_in_block(proc_info);
+
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+        let (out_l, out_r) = (&mut out_l[0..frames], &mut out_r[0..frames]);
+        out_l.iter_mut().for_each(|s| *s = 0.0);
+        out_r.iter_mut().for_each(|s| *s = 0.0);
+
+        // Each click cuts off the one before it.
+        let mut start = 0;
+        for (frame, accent) in beats {
+            let frame = frame.min(frames);
+            self.render(&mut out_l[start..frame], &mut out_r[start..frame]);
+            self.click = Some(Click { accent, pos: 0 });
+            start = frame;
+        }
+        self.render(&mut out_l[start..], &mut out_r[start..]);
+
+        ProcessStatus::Continue
+    }
+}
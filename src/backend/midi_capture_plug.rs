@@ -0,0 +1,300 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::{NoteOffEvent, NoteOnEvent};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::collections::VecDeque;
+
+pub static MIDI_CAPTURE_PLUG_RDN: &str = "app.meadowlark.midi_capture";
+
+/// Enough for a few seconds of very dense playing without the UI polling.
+const EVENT_BUFFER_SIZE: usize = 2048;
+
+/// The most notes the UI can play in one block of audio.
+const PLAYED_NOTE_BUFFER_SIZE: usize = 256;
+
+/// How much of the incoming MIDI is kept.
+pub const MAX_HISTORY_SECS: f64 = 600.0;
+
+/// A pause this long (with no notes held) separates one performance from the
+/// next.
+pub const PERFORMANCE_GAP_SECS: f64 = 4.0;
+
+pub struct MidiCapturePlugFactory;
+
+impl PluginFactory for MidiCapturePlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: MIDI_CAPTURE_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "MIDI Capture".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(MidiCapturePlugMainThread {}))
+    }
+}
+
+/// A note on or note off received by the plugin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NoteMsg {
+    /// The number of frames the plugin had processed when the event arrived.
+    frame: u64,
+    channel: i16,
+    key: i16,
+    velocity: f32,
+    on: bool,
+}
+
+/// A note of a captured performance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapturedNote {
+    /// The start of the note in seconds from the start of the performance.
+    pub start_secs: f64,
+    pub len_secs: f64,
+    /// The MIDI channel, from 0 to 15.
+    pub channel: i16,
+    pub key: i16,
+    pub velocity: f32,
+}
+
+pub struct MidiCapturePlugHandle {
+    from_audio_thread_rx: Consumer<NoteMsg>,
+    to_audio_thread_tx: Producer<NoteMsg>,
+
+    /// All of the events of the last `MAX_HISTORY_SECS`, oldest first.
+    history: VecDeque<NoteMsg>,
+    /// The `(channel, key)` of every note that is held right now.
+    held: Vec<(i16, i16)>,
+
+    sample_rate: SampleRate,
+}
+
+impl MidiCapturePlugHandle {
+    /// Move the events received since the last call into the history. This
+    /// has to be called regularly, whether or not anything is being recorded.
+    pub fn poll(&mut self) {
+        while let Ok(msg) = self.from_audio_thread_rx.pop() {
+            self.held.retain(|held| *held != (msg.channel, msg.key));
+            if msg.on {
+                self.held.push((msg.channel, msg.key));
+            }
+            self.history.push_back(msg);
+        }
+
+        let max_frames = (MAX_HISTORY_SECS * self.sample_rate.0) as u64;
+        if let Some(newest) = self.history.back().map(|msg| msg.frame) {
+            while self.history.front().map_or(false, |msg| newest - msg.frame > max_frames) {
+                self.history.pop_front();
+            }
+        }
+    }
+
+    /// The notes of the most recent performance, which is everything played
+    /// since the last pause of at least `PERFORMANCE_GAP_SECS`. The first note
+    /// starts at zero. Notes that are still held end at the last event.
+    ///
+    /// Returns an empty list if nothing has been played.
+    pub fn last_performance(&self) -> Vec<CapturedNote> {
+        // Pair up the note ons and offs.
+        let newest = self.history.back().map_or(0, |msg| msg.frame);
+        let mut notes: Vec<(u64, u64, &NoteMsg)> = Vec::new();
+        let mut held: Vec<&NoteMsg> = Vec::new();
+        for msg in self.history.iter() {
+            if let Some(i) =
+                held.iter().position(|on| on.key == msg.key && on.channel == msg.channel)
+            {
+                let on = held.remove(i);
+                notes.push((on.frame, msg.frame, on));
+            }
+            if msg.on {
+                held.push(msg);
+            }
+        }
+        for on in held {
+            notes.push((on.frame, newest, on));
+        }
+        notes.sort_by_key(|(start, ..)| *start);
+
+        // The latest end of each note and all of the notes before it, so a long
+        // note that was held over a pause keeps the performance together.
+        let held_until: Vec<u64> = notes
+            .iter()
+            .scan(0, |held_until, (_, end, ..)| {
+                *held_until = (*held_until).max(*end);
+                Some(*held_until)
+            })
+            .collect();
+
+        // Walk back from the last note until there is a long enough pause.
+        let gap = (PERFORMANCE_GAP_SECS * self.sample_rate.0) as u64;
+        let mut first = notes.len();
+        for i in (0..notes.len()).rev() {
+            if first < notes.len() && held_until[i] + gap < notes[first].0 {
+                break;
+            }
+            first = i;
+        }
+        let performance_start = notes.get(first).map_or(0, |(start, ..)| *start);
+
+        let to_secs = |frames: u64| frames as f64 / self.sample_rate.0;
+        notes[first..]
+            .iter()
+            .map(|(start, end, on)| CapturedNote {
+                start_secs: to_secs(start - performance_start),
+                len_secs: to_secs(end - start),
+                channel: on.channel,
+                key: on.key,
+                velocity: on.velocity,
+            })
+            .collect()
+    }
+
+    /// The `(channel, key)` of every note that is held on the MIDI input as of
+    /// the last `poll()`, whether it came from a device or was played from
+    /// the UI.
+    pub fn held_notes(&self) -> &[(i16, i16)] {
+        &self.held
+    }
+
+    /// Forget everything that has been played so far.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Play a note as if it came from a MIDI device, e.g. from musical typing.
+    /// It is timed by the audio thread, so it is captured just like the notes
+    /// of a device.
+    pub fn play_note(&mut self, channel: i16, key: i16, velocity: f32) {
+        self.send_note(NoteMsg { frame: 0, channel, key, velocity, on: true });
+    }
+
+    /// End a note started with `play_note()`.
+    pub fn release_note(&mut self, channel: i16, key: i16) {
+        self.send_note(NoteMsg { frame: 0, channel, key, velocity: 0.0, on: false });
+    }
+
+    fn send_note(&mut self, msg: NoteMsg) {
+        if self.to_audio_thread_tx.push(msg).is_err() {
+            log::warn!("Dropped a note played on the MIDI input");
+        }
+    }
+}
+
+pub struct MidiCapturePlugMainThread {}
+
+impl PluginMainThread for MidiCapturePlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        _max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_handle_tx, from_audio_thread_rx) = RingBuffer::<NoteMsg>::new(EVENT_BUFFER_SIZE);
+        let to_handle_tx = Owned::new(coll_handle, to_handle_tx);
+        let (to_audio_thread_tx, from_handle_rx) =
+            RingBuffer::<NoteMsg>::new(PLAYED_NOTE_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(MidiCapturePlugAudioThread {
+                to_handle_tx,
+                from_handle_rx,
+                frame: 0,
+            }),
+            internal_handle: Some(Box::new(MidiCapturePlugHandle {
+                from_audio_thread_rx,
+                to_audio_thread_tx,
+                history: VecDeque::new(),
+                held: Vec::new(),
+                sample_rate,
+            })),
+        })
+    }
+
+    fn note_ports_ext(&mut self) -> Result<ext::note_ports::PluginNotePortsExt, String> {
+        Ok(ext::note_ports::PluginNotePortsExt::single_in())
+    }
+}
+
+pub struct MidiCapturePlugAudioThread {
+    to_handle_tx: Owned<Producer<NoteMsg>>,
+
+    /// Notes played from the UI. They arrive at the start of the block.
+    ///
+    /// TODO: Pass these on to the instrument of the armed channel along with
+    /// the notes of the device, once channels have instruments.
+    from_handle_rx: Owned<Consumer<NoteMsg>>,
+
+    /// The number of frames processed so far. This keeps counting whether or
+    /// not the transport is playing.
+    frame: u64,
+}
+
+impl PluginAudioThread for MidiCapturePlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {}
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        _buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        // These come first to keep the history in order.
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            let _ = self.to_handle_tx.push(NoteMsg { frame: self.frame, ..msg });
+        }
+
+        for e in in_events.iter() {
+            let msg = if let Some(note_on) = e.as_event::<NoteOnEvent>() {
+                NoteMsg {
+                    frame: self.frame + u64::from(e.header().time()),
+                    channel: note_on.0.channel(),
+                    key: note_on.0.key(),
+                    velocity: note_on.0.velocity() as f32,
+                    on: true,
+                }
+            } else if let Some(note_off) = e.as_event::<NoteOffEvent>() {
+                NoteMsg {
+                    frame: self.frame + u64::from(e.header().time()),
+                    channel: note_off.0.channel(),
+                    key: note_off.0.key(),
+                    velocity: 0.0,
+                    on: false,
+                }
+            } else {
+                continue;
+            };
+
+            // If the UI isn't keeping up then dropping events is all we can do.
+            let _ = self.to_handle_tx.push(msg);
+        }
+
+        self.frame += proc_info.frames as u64;
+
+        ProcessStatus::Continue
+    }
+}
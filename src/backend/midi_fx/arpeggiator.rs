@@ -0,0 +1,374 @@
+use smallvec::SmallVec;
+
+use super::{push_event, MidiEffect, MidiFxEvent, MidiFxEventKind, MidiFxTransport};
+use crate::backend::dsp::WhiteNoise;
+
+/// The most notes that are arpeggiated at once. Notes held past this are
+/// ignored.
+pub const MAX_ARP_NOTES: usize = 16;
+
+/// The most octaves the held notes are repeated over.
+pub const MAX_OCTAVES: u8 = 4;
+
+/// The order the held notes are played in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArpPattern {
+    Up,
+    Down,
+    /// Up and back down, without playing the highest and lowest notes twice.
+    UpDown,
+    /// In the order the notes were pressed.
+    AsPlayed,
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HeldNote {
+    channel: i16,
+    key: i16,
+    velocity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SoundingNote {
+    channel: i16,
+    key: i16,
+    /// The beat the note ends at.
+    off_beat: f64,
+}
+
+/// Plays the held notes one at a time, one per step of the rate.
+///
+/// The steps line up with the beats of the transport while it plays, so the
+/// arpeggio stays in time with the project. While it is stopped the
+/// arpeggiator keeps its own time, so it can still be played live.
+pub struct Arpeggiator {
+    pub pattern: ArpPattern,
+    /// The length of a step in beats, e.g. 0.25 for sixteenth notes.
+    pub rate_beats: f64,
+    /// The number of octaves the held notes are repeated over, from 1 to
+    /// `MAX_OCTAVES`.
+    pub octaves: u8,
+    /// How long each note is held, as a part of a step from 0.0 to 1.0. At
+    /// 1.0 the notes are legato.
+    pub gate: f64,
+    /// Keep playing the notes after they are released, until new notes are
+    /// pressed.
+    pub latch: bool,
+
+    /// The notes taking part, in the order they were pressed.
+    held: SmallVec<[HeldNote; MAX_ARP_NOTES]>,
+    /// The number of keys that are physically down. With latch on, `held`
+    /// is replaced when a key goes down while this is zero.
+    keys_down: usize,
+    sounding: Option<SoundingNote>,
+    last_step: Option<i64>,
+    step_count: usize,
+    /// The time kept while the transport is stopped.
+    free_beat: f64,
+    random: WhiteNoise,
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Self {
+            pattern: ArpPattern::Up,
+            rate_beats: 0.25,
+            octaves: 1,
+            gate: 0.5,
+            latch: false,
+            held: SmallVec::new(),
+            keys_down: 0,
+            sounding: None,
+            last_step: None,
+            step_count: 0,
+            free_beat: 0.0,
+            random: WhiteNoise::new(0x1234_5678),
+        }
+    }
+
+    pub fn set_latch(&mut self, latch: bool, output: &mut Vec<MidiFxEvent>) {
+        self.latch = latch;
+        // Let go of the notes that were only held by the latch.
+        if !latch && self.keys_down == 0 {
+            self.held.clear();
+            self.stop(0, output);
+        }
+    }
+
+    fn note_on(&mut self, note: HeldNote) {
+        if self.latch && self.keys_down == 0 {
+            self.held.clear();
+            self.step_count = 0;
+        }
+        self.keys_down += 1;
+        if !self.held.iter().any(|n| n.key == note.key) && self.held.len() < MAX_ARP_NOTES {
+            self.held.push(note);
+        }
+    }
+
+    fn note_off(&mut self, key: i16) {
+        self.keys_down = self.keys_down.saturating_sub(1);
+        if !self.latch {
+            self.held.retain(|n| n.key != key);
+        }
+    }
+
+    fn stop(&mut self, frame: u32, output: &mut Vec<MidiFxEvent>) {
+        if let Some(note) = self.sounding.take() {
+            push_event(output, MidiFxEvent::note_off(frame, note.channel, note.key));
+        }
+        self.last_step = None;
+        self.step_count = 0;
+    }
+
+    /// The note to play on the given step of the pattern.
+    fn note_for_step(&mut self, step: usize) -> Option<HeldNote> {
+        if self.held.is_empty() {
+            return None;
+        }
+
+        let mut notes = self.held.clone();
+        if self.pattern != ArpPattern::AsPlayed {
+            notes.sort_by_key(|n| n.key);
+        }
+        let octaves = usize::from(self.octaves.clamp(1, MAX_OCTAVES));
+        let len = notes.len() * octaves;
+        let note_at = |i: usize| {
+            let note = notes[i % notes.len()];
+            HeldNote { key: note.key + 12 * (i / notes.len()) as i16, ..note }
+        };
+
+        let i = match self.pattern {
+            ArpPattern::Up | ArpPattern::AsPlayed => step % len,
+            ArpPattern::Down => len - 1 - step % len,
+            ArpPattern::UpDown => {
+                if len < 2 {
+                    0
+                } else {
+                    let cycle = 2 * len - 2;
+                    let i = step % cycle;
+                    if i < len {
+                        i
+                    } else {
+                        cycle - i
+                    }
+                }
+            }
+            ArpPattern::Random => {
+                let random = f64::from(self.random.next()) * 0.5 + 0.5;
+                ((random * len as f64) as usize).min(len - 1)
+            }
+        };
+        Some(note_at(i))
+    }
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiEffect for Arpeggiator {
+    fn process(
+        &mut self,
+        transport: &MidiFxTransport,
+        frames: u32,
+        input: &[MidiFxEvent],
+        output: &mut Vec<MidiFxEvent>,
+    ) {
+        let beats_per_frame = transport.beats_per_frame();
+        let start_beat = if transport.playing { transport.beat } else { self.free_beat };
+        let rate = self.rate_beats.max(1.0 / 64.0);
+        let mut events = input.iter().peekable();
+
+        // Go through the block one frame at a time, so every step and note
+        // off lands on the exact frame it is due.
+        for frame in 0..frames {
+            while let Some(event) = events.next_if(|e| e.frame <= frame) {
+                match event.kind {
+                    MidiFxEventKind::NoteOn { key, velocity } => {
+                        self.note_on(HeldNote { channel: event.channel, key, velocity })
+                    }
+                    MidiFxEventKind::NoteOff { key } => self.note_off(key),
+                }
+            }
+
+            if self.held.is_empty() {
+                if self.sounding.is_some() || self.last_step.is_some() {
+                    self.stop(frame, output);
+                }
+                continue;
+            }
+
+            let beat = start_beat + f64::from(frame) * beats_per_frame;
+
+            if let Some(note) = self.sounding {
+                if beat >= note.off_beat {
+                    push_event(output, MidiFxEvent::note_off(frame, note.channel, note.key));
+                    self.sounding = None;
+                }
+            }
+
+            let step = (beat / rate).floor() as i64;
+            if self.last_step == Some(step) {
+                continue;
+            }
+            self.last_step = Some(step);
+
+            if let Some(note) = self.sounding.take() {
+                push_event(output, MidiFxEvent::note_off(frame, note.channel, note.key));
+            }
+            let step_count = self.step_count;
+            self.step_count += 1;
+            if let Some(note) = self.note_for_step(step_count) {
+                if (0..=127).contains(&note.key) {
+                    push_event(
+                        output,
+                        MidiFxEvent::note_on(frame, note.channel, note.key, note.velocity),
+                    );
+                    let gate = self.gate.clamp(0.0, 1.0);
+                    self.sounding = Some(SoundingNote {
+                        channel: note.channel,
+                        key: note.key,
+                        off_beat: (step as f64 + gate) * rate,
+                    });
+                }
+            }
+        }
+
+        // Pick up any events past the end of the block.
+        for event in events {
+            match event.kind {
+                MidiFxEventKind::NoteOn { key, velocity } => {
+                    self.note_on(HeldNote { channel: event.channel, key, velocity })
+                }
+                MidiFxEventKind::NoteOff { key } => self.note_off(key),
+            }
+        }
+
+        if !transport.playing {
+            self.free_beat = start_beat + f64::from(frames) * beats_per_frame;
+        }
+    }
+
+    fn all_notes_off(&mut self, output: &mut Vec<MidiFxEvent>) {
+        self.held.clear();
+        self.keys_down = 0;
+        self.stop(0, output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+    /// A sixteenth note at 120 bpm.
+    const FRAMES_PER_STEP: u32 = 6_000;
+
+    fn transport() -> MidiFxTransport {
+        MidiFxTransport { playing: true, beat: 0.0, bpm: 120.0, sample_rate: SAMPLE_RATE }
+    }
+
+    /// Hold the keys and return the first `steps` notes that are played.
+    fn play(arp: &mut Arpeggiator, keys: &[i16], steps: usize) -> Vec<i16> {
+        let input: Vec<MidiFxEvent> =
+            keys.iter().map(|&key| MidiFxEvent::note_on(0, 0, key, 1.0)).collect();
+        let mut output = Vec::with_capacity(512);
+        arp.process(&transport(), FRAMES_PER_STEP * steps as u32, &input, &mut output);
+        output
+            .iter()
+            .filter_map(|event| match event.kind {
+                MidiFxEventKind::NoteOn { key, .. } => Some(key),
+                MidiFxEventKind::NoteOff { .. } => None,
+            })
+            .take(steps)
+            .collect()
+    }
+
+    fn arp(pattern: ArpPattern, octaves: u8) -> Arpeggiator {
+        Arpeggiator { pattern, octaves, ..Arpeggiator::new() }
+    }
+
+    #[test]
+    fn patterns_play_the_notes_in_order() {
+        let keys = [67, 60, 64];
+        assert_eq!(play(&mut arp(ArpPattern::Up, 1), &keys, 6), vec![60, 64, 67, 60, 64, 67]);
+        assert_eq!(play(&mut arp(ArpPattern::Down, 1), &keys, 6), vec![67, 64, 60, 67, 64, 60]);
+        assert_eq!(
+            play(&mut arp(ArpPattern::UpDown, 1), &keys, 7),
+            vec![60, 64, 67, 64, 60, 64, 67]
+        );
+        assert_eq!(play(&mut arp(ArpPattern::AsPlayed, 1), &keys, 6), vec![67, 60, 64, 67, 60, 64]);
+    }
+
+    #[test]
+    fn up_down_with_one_note_repeats_it() {
+        assert_eq!(play(&mut arp(ArpPattern::UpDown, 1), &[60], 3), vec![60, 60, 60]);
+    }
+
+    #[test]
+    fn octaves_repeat_the_notes_higher_up() {
+        assert_eq!(play(&mut arp(ArpPattern::Up, 2), &[64, 60], 5), vec![60, 64, 72, 76, 60]);
+        assert_eq!(play(&mut arp(ArpPattern::Down, 3), &[60], 4), vec![84, 72, 60, 84]);
+    }
+
+    #[test]
+    fn octaves_are_limited() {
+        let steps = usize::from(MAX_OCTAVES) * 2;
+        let keys = play(&mut arp(ArpPattern::Up, 200), &[60], steps);
+        let expected: Vec<i16> =
+            (0..steps).map(|i| 60 + 12 * (i % usize::from(MAX_OCTAVES)) as i16).collect();
+        assert_eq!(keys, expected);
+
+        // No octaves still plays the notes once.
+        assert_eq!(play(&mut arp(ArpPattern::Up, 0), &[60], 2), vec![60, 60]);
+    }
+
+    #[test]
+    fn random_stays_within_the_octave_range() {
+        let keys = play(&mut arp(ArpPattern::Random, 2), &[60, 63], 64);
+        assert_eq!(keys.len(), 64);
+        assert!(keys.iter().all(|key| [60, 63, 72, 75].contains(key)));
+        // Every note comes up at some point.
+        for key in [60, 63, 72, 75] {
+            assert!(keys.contains(&key));
+        }
+    }
+
+    #[test]
+    fn notes_above_the_midi_range_are_skipped() {
+        let keys = play(&mut arp(ArpPattern::Up, 2), &[120], 4);
+        assert_eq!(keys, vec![120, 120]);
+    }
+
+    #[test]
+    fn each_note_ends_before_the_next_starts() {
+        let mut arp = arp(ArpPattern::Up, 1);
+        let input = [MidiFxEvent::note_on(0, 0, 60, 1.0), MidiFxEvent::note_on(0, 0, 64, 1.0)];
+        let mut output = Vec::with_capacity(512);
+        arp.process(&transport(), FRAMES_PER_STEP * 4, &input, &mut output);
+
+        let mut sounding = None;
+        for event in output.iter() {
+            match event.kind {
+                MidiFxEventKind::NoteOn { key, .. } => {
+                    assert_eq!(sounding, None);
+                    sounding = Some(key);
+                }
+                MidiFxEventKind::NoteOff { key } => {
+                    assert_eq!(sounding, Some(key));
+                    sounding = None;
+                }
+            }
+        }
+
+        // Releasing the keys ends the arpeggio.
+        output.clear();
+        let input = [MidiFxEvent::note_off(0, 0, 60), MidiFxEvent::note_off(0, 0, 64)];
+        arp.process(&transport(), FRAMES_PER_STEP * 4, &input, &mut output);
+        assert!(output.iter().all(|event| matches!(event.kind, MidiFxEventKind::NoteOff { .. })));
+    }
+}
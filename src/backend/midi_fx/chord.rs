@@ -0,0 +1,323 @@
+use smallvec::SmallVec;
+
+use super::{push_event, HeldKeys, MidiEffect, MidiFxEvent, MidiFxEventKind, MidiFxTransport};
+
+/// The most notes in a chord.
+pub const MAX_CHORD_NOTES: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordType {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Major7,
+    Minor7,
+    Dominant7,
+    /// The root and the fifth.
+    Power,
+    Octave,
+}
+
+impl ChordType {
+    pub const ALL: [ChordType; 11] = [
+        ChordType::Major,
+        ChordType::Minor,
+        ChordType::Diminished,
+        ChordType::Augmented,
+        ChordType::Sus2,
+        ChordType::Sus4,
+        ChordType::Major7,
+        ChordType::Minor7,
+        ChordType::Dominant7,
+        ChordType::Power,
+        ChordType::Octave,
+    ];
+
+    /// The semitones of each note of the chord above the played note, from
+    /// low to high.
+    pub fn intervals(&self) -> &'static [i16] {
+        match self {
+            ChordType::Major => &[0, 4, 7],
+            ChordType::Minor => &[0, 3, 7],
+            ChordType::Diminished => &[0, 3, 6],
+            ChordType::Augmented => &[0, 4, 8],
+            ChordType::Sus2 => &[0, 2, 7],
+            ChordType::Sus4 => &[0, 5, 7],
+            ChordType::Major7 => &[0, 4, 7, 11],
+            ChordType::Minor7 => &[0, 3, 7, 10],
+            ChordType::Dominant7 => &[0, 4, 7, 10],
+            ChordType::Power => &[0, 7],
+            ChordType::Octave => &[0, 12],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChordType::Major => "Major",
+            ChordType::Minor => "Minor",
+            ChordType::Diminished => "Diminished",
+            ChordType::Augmented => "Augmented",
+            ChordType::Sus2 => "Sus2",
+            ChordType::Sus4 => "Sus4",
+            ChordType::Major7 => "Major 7th",
+            ChordType::Minor7 => "Minor 7th",
+            ChordType::Dominant7 => "Dominant 7th",
+            ChordType::Power => "Power",
+            ChordType::Octave => "Octave",
+        }
+    }
+}
+
+/// How the notes of a chord are spread out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChordVoicing {
+    /// Every note within an octave of the played note.
+    Close,
+    /// The lowest note moved up an octave.
+    FirstInversion,
+    /// The two lowest notes moved up an octave.
+    SecondInversion,
+    /// The second highest note moved down an octave.
+    Drop2,
+    /// Every other note moved up an octave.
+    Spread,
+}
+
+impl ChordVoicing {
+    pub const ALL: [ChordVoicing; 5] = [
+        ChordVoicing::Close,
+        ChordVoicing::FirstInversion,
+        ChordVoicing::SecondInversion,
+        ChordVoicing::Drop2,
+        ChordVoicing::Spread,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChordVoicing::Close => "Close",
+            ChordVoicing::FirstInversion => "First Inversion",
+            ChordVoicing::SecondInversion => "Second Inversion",
+            ChordVoicing::Drop2 => "Drop 2",
+            ChordVoicing::Spread => "Spread",
+        }
+    }
+
+    /// Apply this voicing to the intervals of a close chord.
+    fn apply(&self, intervals: &[i16]) -> SmallVec<[i16; MAX_CHORD_NOTES]> {
+        let mut notes: SmallVec<[i16; MAX_CHORD_NOTES]> =
+            intervals.iter().take(MAX_CHORD_NOTES).copied().collect();
+        let len = notes.len();
+        match self {
+            ChordVoicing::Close => {}
+            ChordVoicing::FirstInversion => notes.iter_mut().take(1).for_each(|n| *n += 12),
+            ChordVoicing::SecondInversion => {
+                notes.iter_mut().take(len.saturating_sub(1).min(2)).for_each(|n| *n += 12)
+            }
+            ChordVoicing::Drop2 => {
+                if len >= 3 {
+                    notes[len - 2] -= 12;
+                }
+            }
+            ChordVoicing::Spread => notes.iter_mut().skip(1).step_by(2).for_each(|n| *n += 12),
+        }
+        notes
+    }
+}
+
+/// Turns every played note into a chord built on top of it.
+pub struct ChordEffect {
+    pub chord: ChordType,
+    pub voicing: ChordVoicing,
+    /// The velocity of every note but the played one, as a part of the
+    /// played velocity from 0.0 to 1.0.
+    pub chord_velocity: f32,
+
+    held: HeldKeys,
+}
+
+impl ChordEffect {
+    pub fn new() -> Self {
+        Self {
+            chord: ChordType::Major,
+            voicing: ChordVoicing::Close,
+            chord_velocity: 1.0,
+            held: HeldKeys::new(),
+        }
+    }
+}
+
+impl Default for ChordEffect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiEffect for ChordEffect {
+    fn process(
+        &mut self,
+        _transport: &MidiFxTransport,
+        _frames: u32,
+        input: &[MidiFxEvent],
+        output: &mut Vec<MidiFxEvent>,
+    ) {
+        for event in input {
+            match event.kind {
+                MidiFxEventKind::NoteOn { key, velocity } => {
+                    let keys: SmallVec<[i16; MAX_CHORD_NOTES]> = self
+                        .voicing
+                        .apply(self.chord.intervals())
+                        .iter()
+                        .map(|interval| key + interval)
+                        .collect();
+                    let chord_velocity = velocity * self.chord_velocity.clamp(0.0, 1.0);
+
+                    // The chord is remembered, so changing it while a note is
+                    // held still ends the notes that were played.
+                    for &out_key in self.held.press(event.channel, key, &keys) {
+                        let velocity = if out_key == key { velocity } else { chord_velocity };
+                        push_event(
+                            output,
+                            MidiFxEvent::note_on(event.frame, event.channel, out_key, velocity),
+                        );
+                    }
+                }
+                MidiFxEventKind::NoteOff { key } => {
+                    for out_key in self.held.release(event.channel, key) {
+                        push_event(
+                            output,
+                            MidiFxEvent::note_off(event.frame, event.channel, out_key),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn all_notes_off(&mut self, output: &mut Vec<MidiFxEvent>) {
+        self.held.release_all(output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transport() -> MidiFxTransport {
+        MidiFxTransport { playing: true, beat: 0.0, bpm: 120.0, sample_rate: 48_000.0 }
+    }
+
+    fn run(chord: &mut ChordEffect, input: &[MidiFxEvent]) -> Vec<MidiFxEvent> {
+        let mut output = Vec::with_capacity(64);
+        chord.process(&transport(), 256, input, &mut output);
+        output
+    }
+
+    fn keys_on(events: &[MidiFxEvent]) -> Vec<i16> {
+        events
+            .iter()
+            .filter_map(|event| match event.kind {
+                MidiFxEventKind::NoteOn { key, .. } => Some(key),
+                MidiFxEventKind::NoteOff { .. } => None,
+            })
+            .collect()
+    }
+
+    fn keys_off(events: &[MidiFxEvent]) -> Vec<i16> {
+        events
+            .iter()
+            .filter_map(|event| match event.kind {
+                MidiFxEventKind::NoteOff { key } => Some(key),
+                MidiFxEventKind::NoteOn { .. } => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn voicings_move_the_notes_of_the_chord() {
+        let major = ChordType::Major.intervals();
+        let seventh = ChordType::Major7.intervals();
+
+        assert_eq!(ChordVoicing::Close.apply(major).to_vec(), vec![0, 4, 7]);
+        assert_eq!(ChordVoicing::FirstInversion.apply(major).to_vec(), vec![12, 4, 7]);
+        assert_eq!(ChordVoicing::SecondInversion.apply(major).to_vec(), vec![12, 16, 7]);
+        assert_eq!(ChordVoicing::Drop2.apply(seventh).to_vec(), vec![0, 4, -5, 11]);
+        assert_eq!(ChordVoicing::Spread.apply(seventh).to_vec(), vec![0, 16, 7, 23]);
+
+        // Chords that are too small to invert twice keep their top note.
+        let power = ChordType::Power.intervals();
+        assert_eq!(ChordVoicing::SecondInversion.apply(power).to_vec(), vec![12, 7]);
+        assert_eq!(ChordVoicing::Drop2.apply(power).to_vec(), vec![0, 7]);
+    }
+
+    #[test]
+    fn voicings_keep_the_notes_of_the_chord() {
+        let pitch_classes = |notes: &[i16]| {
+            let mut classes: Vec<i16> = notes.iter().map(|n| n.rem_euclid(12)).collect();
+            classes.sort_unstable();
+            classes
+        };
+        for chord in ChordType::ALL {
+            let close = pitch_classes(chord.intervals());
+            for voicing in ChordVoicing::ALL {
+                let notes = voicing.apply(chord.intervals());
+                assert_eq!(notes.len(), chord.intervals().len());
+                assert_eq!(pitch_classes(&notes), close, "{:?} {:?}", chord, voicing);
+            }
+        }
+    }
+
+    #[test]
+    fn chords_are_built_on_the_played_note() {
+        let mut chord =
+            ChordEffect { chord: ChordType::Minor7, chord_velocity: 0.5, ..ChordEffect::new() };
+        let output = run(&mut chord, &[MidiFxEvent::note_on(7, 2, 57, 0.8)]);
+        assert!(output.iter().all(|event| (event.frame, event.channel) == (7, 2)));
+
+        // Only the played note keeps its velocity.
+        let notes: Vec<(i16, f32)> = output
+            .iter()
+            .filter_map(|event| match event.kind {
+                MidiFxEventKind::NoteOn { key, velocity } => Some((key, velocity)),
+                MidiFxEventKind::NoteOff { .. } => None,
+            })
+            .collect();
+        assert_eq!(notes, vec![(57, 0.8), (60, 0.4), (64, 0.4), (67, 0.4)]);
+    }
+
+    #[test]
+    fn notes_outside_the_midi_range_are_dropped() {
+        let mut chord = ChordEffect::new();
+        assert_eq!(
+            keys_on(&run(&mut chord, &[MidiFxEvent::note_on(0, 0, 122, 1.0)])),
+            vec![122, 126]
+        );
+    }
+
+    #[test]
+    fn released_notes_end_the_chord_they_played() {
+        let mut chord = ChordEffect::new();
+        run(&mut chord, &[MidiFxEvent::note_on(0, 0, 60, 1.0)]);
+
+        // Changing the chord while the key is held still ends what it played.
+        chord.chord = ChordType::Sus4;
+        chord.voicing = ChordVoicing::Spread;
+        let output = run(&mut chord, &[MidiFxEvent::note_off(0, 0, 60)]);
+        assert_eq!(keys_off(&output), vec![60, 64, 67]);
+    }
+
+    #[test]
+    fn shared_notes_keep_sounding() {
+        let mut chord = ChordEffect { chord: ChordType::Octave, ..ChordEffect::new() };
+        run(
+            &mut chord,
+            &[MidiFxEvent::note_on(0, 0, 48, 1.0), MidiFxEvent::note_on(0, 0, 60, 1.0)],
+        );
+
+        // 60 is also the octave of 48, so it keeps sounding until 60 is let go.
+        let output = run(&mut chord, &[MidiFxEvent::note_off(0, 0, 48)]);
+        assert_eq!(keys_off(&output), vec![48]);
+    }
+}
@@ -0,0 +1,366 @@
+//! MIDI effects, which turn the notes going to an instrument into other notes
+//! (i.e. an arpeggiator). They are chained in a `MidiFxChain` that runs in
+//! front of the instrument, inside the MIDI track plugin of the channel, so
+//! it gets both the notes of the clips and the notes played live.
+//!
+//! Everything in here is safe to use in the realtime thread as long as it is
+//! constructed beforehand, and as long as a block has no more than
+//! `MAX_BLOCK_EVENTS` events.
+
+mod arpeggiator;
+mod chord;
+mod note_repeat;
+mod scale_lock;
+
+pub use arpeggiator::{ArpPattern, Arpeggiator, MAX_ARP_NOTES, MAX_OCTAVES};
+pub use chord::{ChordEffect, ChordType, ChordVoicing, MAX_CHORD_NOTES};
+pub use note_repeat::{NoteRepeat, MAX_REPEAT_NOTES};
+pub use scale_lock::{ScaleLock, ScaleLockMode};
+
+use smallvec::SmallVec;
+
+/// The most events an effect can put out in one block. Events past this are
+/// dropped.
+pub const MAX_BLOCK_EVENTS: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MidiFxEventKind {
+    /// Velocity is from 0.0 to 1.0.
+    NoteOn {
+        key: i16,
+        velocity: f32,
+    },
+    NoteOff {
+        key: i16,
+    },
+}
+
+/// A note event in a block, at a frame from the start of the block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiFxEvent {
+    pub frame: u32,
+    /// The MIDI channel, from 0 to 15.
+    pub channel: i16,
+    pub kind: MidiFxEventKind,
+}
+
+impl MidiFxEvent {
+    pub fn note_on(frame: u32, channel: i16, key: i16, velocity: f32) -> Self {
+        Self { frame, channel, kind: MidiFxEventKind::NoteOn { key, velocity } }
+    }
+
+    pub fn note_off(frame: u32, channel: i16, key: i16) -> Self {
+        Self { frame, channel, kind: MidiFxEventKind::NoteOff { key } }
+    }
+}
+
+/// Where the transport is at the start of a block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiFxTransport {
+    pub playing: bool,
+    /// The position in beats at the first frame of the block.
+    pub beat: f64,
+    pub bpm: f64,
+    pub sample_rate: f64,
+}
+
+impl MidiFxTransport {
+    pub fn beats_per_frame(&self) -> f64 {
+        self.bpm / 60.0 / self.sample_rate
+    }
+}
+
+/// A node of a `MidiFxChain`.
+pub trait MidiEffect: Send {
+    /// Turn the events of a block into new ones. `input` is sorted by frame,
+    /// and `output` has to be too.
+    fn process(
+        &mut self,
+        transport: &MidiFxTransport,
+        frames: u32,
+        input: &[MidiFxEvent],
+        output: &mut Vec<MidiFxEvent>,
+    );
+
+    /// End every note that the effect is playing, e.g. when it is removed or
+    /// the transport jumps. The note offs are put at frame 0.
+    fn all_notes_off(&mut self, output: &mut Vec<MidiFxEvent>);
+}
+
+/// Runs MIDI effects one after another.
+pub struct MidiFxChain {
+    effects: Vec<Box<dyn MidiEffect>>,
+    scratch: [Vec<MidiFxEvent>; 2],
+}
+
+impl MidiFxChain {
+    pub fn new() -> Self {
+        Self {
+            effects: Vec::new(),
+            scratch: [Vec::with_capacity(MAX_BLOCK_EVENTS), Vec::with_capacity(MAX_BLOCK_EVENTS)],
+        }
+    }
+
+    /// This allocates, so it must not be called in the realtime thread.
+    ///
+    /// The effects run in the order they are pushed, so e.g. a `ScaleLock`
+    /// followed by a `ChordEffect` plays chords on the notes of the key,
+    /// and an `Arpeggiator` after that plays them one note at a time.
+    pub fn push(&mut self, effect: Box<dyn MidiEffect>) {
+        self.effects.push(effect);
+    }
+
+    /// Remove an effect, returning the note offs for the notes it was playing.
+    /// Those still have to go through the effects after it.
+    pub fn remove(&mut self, index: usize) -> Option<(Box<dyn MidiEffect>, Vec<MidiFxEvent>)> {
+        if index >= self.effects.len() {
+            return None;
+        }
+        let mut effect = self.effects.remove(index);
+        let mut note_offs = Vec::with_capacity(MAX_BLOCK_EVENTS);
+        effect.all_notes_off(&mut note_offs);
+        Some((effect, note_offs))
+    }
+
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Run a block of events through every effect in order.
+    pub fn process(
+        &mut self,
+        transport: &MidiFxTransport,
+        frames: u32,
+        input: &[MidiFxEvent],
+        output: &mut Vec<MidiFxEvent>,
+    ) {
+        let [a, b] = &mut self.scratch;
+        a.clear();
+        a.extend_from_slice(&input[0..input.len().min(MAX_BLOCK_EVENTS)]);
+
+        for effect in self.effects.iter_mut() {
+            b.clear();
+            effect.process(transport, frames, a, b);
+            b.truncate(MAX_BLOCK_EVENTS);
+            std::mem::swap(a, b);
+        }
+
+        output.extend_from_slice(a);
+    }
+
+    /// End every note the chain is playing, e.g. before it is replaced. Only
+    /// the notes of the last effect are sounding, but every effect forgets
+    /// the keys it holds.
+    pub fn all_notes_off(&mut self, output: &mut Vec<MidiFxEvent>) {
+        let [a, _] = &mut self.scratch;
+        a.clear();
+        for effect in self.effects.iter_mut() {
+            a.clear();
+            effect.all_notes_off(a);
+        }
+        output.extend_from_slice(a);
+    }
+}
+
+impl Default for MidiFxChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Push an event unless the block is already full, so the realtime thread
+/// never allocates.
+fn push_event(output: &mut Vec<MidiFxEvent>, event: MidiFxEvent) {
+    if output.len() < output.capacity() {
+        output.push(event);
+    }
+}
+
+/// The most held keys an effect keeps track of.
+const MAX_HELD_KEYS: usize = 128;
+
+/// The most notes a single key can be turned into.
+const MAX_MAPPED_NOTES: usize = 8;
+
+struct HeldKey {
+    channel: i16,
+    key: i16,
+    outputs: SmallVec<[i16; MAX_MAPPED_NOTES]>,
+}
+
+/// Remembers which notes each held key was turned into, so the right notes
+/// end when it is released even if the effect was changed in between.
+struct HeldKeys {
+    held: Vec<HeldKey>,
+}
+
+impl HeldKeys {
+    fn new() -> Self {
+        Self { held: Vec::with_capacity(MAX_HELD_KEYS) }
+    }
+
+    /// Store the notes a key was turned into, dropping those out of the MIDI
+    /// range. Returns the notes to start.
+    fn press(&mut self, channel: i16, key: i16, outputs: &[i16]) -> &[i16] {
+        self.held.retain(|h| h.channel != channel || h.key != key);
+        if self.held.len() >= MAX_HELD_KEYS {
+            return &[];
+        }
+
+        let outputs =
+            outputs.iter().copied().filter(|k| (0..=127).contains(k)).take(MAX_MAPPED_NOTES);
+        self.held.push(HeldKey { channel, key, outputs: outputs.collect() });
+        &self.held[self.held.len() - 1].outputs
+    }
+
+    /// Forget a key. Returns the notes to end, leaving out any that another
+    /// held key was also turned into.
+    fn release(&mut self, channel: i16, key: i16) -> SmallVec<[i16; MAX_MAPPED_NOTES]> {
+        let i = match self.held.iter().position(|h| h.channel == channel && h.key == key) {
+            Some(i) => i,
+            None => return SmallVec::new(),
+        };
+        let mut outputs = self.held.remove(i).outputs;
+        outputs.retain(|out| {
+            !self.held.iter().any(|h| h.channel == channel && h.outputs.contains(out))
+        });
+        outputs
+    }
+
+    fn release_all(&mut self, output: &mut Vec<MidiFxEvent>) {
+        for held in self.held.drain(..) {
+            for &key in held.outputs.iter() {
+                push_event(output, MidiFxEvent::note_off(0, held.channel, key));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::scale::{MusicalKey, Scale};
+
+    fn transport() -> MidiFxTransport {
+        MidiFxTransport { playing: true, beat: 0.0, bpm: 120.0, sample_rate: 48_000.0 }
+    }
+
+    fn run(chain: &mut MidiFxChain, input: &[MidiFxEvent]) -> Vec<MidiFxEvent> {
+        let mut output = Vec::with_capacity(MAX_BLOCK_EVENTS);
+        chain.process(&transport(), 256, input, &mut output);
+        output
+    }
+
+    /// A chain that locks to C major and then plays major triads.
+    fn scale_then_chord() -> MidiFxChain {
+        let mut chain = MidiFxChain::new();
+        chain.push(Box::new(ScaleLock::new(MusicalKey::new(0, Scale::Major))));
+        chain.push(Box::new(ChordEffect::new()));
+        chain
+    }
+
+    fn keys_on(events: &[MidiFxEvent]) -> Vec<i16> {
+        events
+            .iter()
+            .filter_map(|event| match event.kind {
+                MidiFxEventKind::NoteOn { key, .. } => Some(key),
+                MidiFxEventKind::NoteOff { .. } => None,
+            })
+            .collect()
+    }
+
+    fn keys_off(events: &[MidiFxEvent]) -> Vec<i16> {
+        let mut keys: Vec<i16> = events
+            .iter()
+            .filter_map(|event| match event.kind {
+                MidiFxEventKind::NoteOff { key } => Some(key),
+                MidiFxEventKind::NoteOn { .. } => None,
+            })
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn empty_chain_passes_events_through() {
+        let mut chain = MidiFxChain::new();
+        let input = [MidiFxEvent::note_on(3, 0, 60, 0.5), MidiFxEvent::note_off(100, 0, 60)];
+        assert_eq!(run(&mut chain, &input), input.to_vec());
+
+        let mut output = Vec::new();
+        chain.all_notes_off(&mut output);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn effects_run_in_the_order_they_were_pushed() {
+        let mut chain = scale_then_chord();
+        // C# snaps to a key of C major before the chord is built on it, so
+        // every note of the chord is a step of a major triad above it.
+        let output = run(&mut chain, &[MidiFxEvent::note_on(0, 0, 61, 1.0)]);
+        let keys = keys_on(&output);
+        assert_eq!(keys.len(), ChordType::Major.intervals().len());
+        let root = keys[0];
+        assert!(MusicalKey::new(0, Scale::Major).contains(i32::from(root)));
+        for (key, interval) in keys.iter().zip(ChordType::Major.intervals()) {
+            assert_eq!(*key, root + interval);
+        }
+
+        // Releasing the key ends the whole chord.
+        let output = run(&mut chain, &[MidiFxEvent::note_off(10, 0, 61)]);
+        let mut sorted = keys.clone();
+        sorted.sort_unstable();
+        assert_eq!(keys_off(&output), sorted);
+    }
+
+    #[test]
+    fn all_notes_off_ends_what_the_last_effect_plays() {
+        let mut chain = scale_then_chord();
+        let keys = keys_on(&run(&mut chain, &[MidiFxEvent::note_on(0, 0, 60, 1.0)]));
+
+        let mut output = Vec::new();
+        chain.all_notes_off(&mut output);
+        let mut sorted = keys;
+        sorted.sort_unstable();
+        assert_eq!(keys_off(&output), sorted);
+        assert!(output.iter().all(|event| event.frame == 0));
+
+        // The keys are forgotten, so releasing them later does nothing.
+        assert!(run(&mut chain, &[MidiFxEvent::note_off(0, 0, 60)]).is_empty());
+    }
+
+    #[test]
+    fn removed_effects_return_their_note_offs() {
+        let mut chain = scale_then_chord();
+        let keys = keys_on(&run(&mut chain, &[MidiFxEvent::note_on(0, 0, 64, 1.0)]));
+
+        let (_, note_offs) = chain.remove(1).unwrap();
+        let mut sorted = keys;
+        sorted.sort_unstable();
+        assert_eq!(keys_off(&note_offs), sorted);
+        assert_eq!(chain.len(), 1);
+        assert!(chain.remove(1).is_none());
+    }
+
+    #[test]
+    fn blocks_are_cut_at_the_event_limit() {
+        let input: Vec<MidiFxEvent> =
+            (0..MAX_BLOCK_EVENTS as u32 + 10).map(|i| MidiFxEvent::note_off(i, 0, 60)).collect();
+        assert_eq!(run(&mut MidiFxChain::new(), &input).len(), MAX_BLOCK_EVENTS);
+
+        // Chords turn every note into three, which doesn't fit either.
+        let mut chain = MidiFxChain::new();
+        chain.push(Box::new(ChordEffect::new()));
+        let input: Vec<MidiFxEvent> = (0..MAX_BLOCK_EVENTS as u32)
+            .map(|i| match i % 2 {
+                0 => MidiFxEvent::note_on(i, 0, 60, 1.0),
+                _ => MidiFxEvent::note_off(i, 0, 60),
+            })
+            .collect();
+        assert_eq!(run(&mut chain, &input).len(), MAX_BLOCK_EVENTS);
+    }
+}
@@ -0,0 +1,316 @@
+use smallvec::SmallVec;
+
+use super::{push_event, MidiEffect, MidiFxEvent, MidiFxEventKind, MidiFxTransport};
+
+/// The most keys that repeat at once. Keys held past this aren't repeated.
+pub const MAX_REPEAT_NOTES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RepeatingNote {
+    channel: i16,
+    key: i16,
+    /// The velocity of the next repeat.
+    velocity: f32,
+    /// The step the key was last played on, so a key pressed right on a step
+    /// isn't played twice.
+    step: i64,
+    /// The beat the sounding repeat ends at, or `None` if it has ended.
+    off_beat: Option<f64>,
+}
+
+/// Retriggers every held key on each step of the rate, like the note repeat
+/// of a drum machine. The key is played as soon as it is pressed, and then
+/// again on every step for as long as it is held.
+///
+/// This sits in front of the instrument, so what it plays is also what gets
+/// recorded.
+pub struct NoteRepeat {
+    /// The length of a step in beats, e.g. 0.25 for sixteenth notes.
+    pub rate_beats: f64,
+    /// How long each repeat is held, as a part of a step from 0.0 to 1.0.
+    pub gate: f64,
+    /// Added to the velocity on every repeat, so rolls can swell or fade out.
+    /// The velocity stays between 0.0 and 1.0.
+    pub velocity_ramp: f32,
+
+    held: SmallVec<[RepeatingNote; MAX_REPEAT_NOTES]>,
+    last_step: Option<i64>,
+    /// The time kept while the transport is stopped.
+    free_beat: f64,
+}
+
+impl NoteRepeat {
+    pub fn new() -> Self {
+        Self {
+            rate_beats: 0.25,
+            gate: 0.5,
+            velocity_ramp: 0.0,
+            held: SmallVec::new(),
+            last_step: None,
+            free_beat: 0.0,
+        }
+    }
+
+    fn trigger(
+        &mut self,
+        i: usize,
+        frame: u32,
+        beat: f64,
+        step: i64,
+        output: &mut Vec<MidiFxEvent>,
+    ) {
+        let rate = self.rate_beats.max(1.0 / 64.0);
+        let gate = self.gate.clamp(0.0, 1.0);
+        let ramp = self.velocity_ramp;
+        let note = &mut self.held[i];
+
+        if note.off_beat.take().is_some() {
+            push_event(output, MidiFxEvent::note_off(frame, note.channel, note.key));
+        }
+        push_event(output, MidiFxEvent::note_on(frame, note.channel, note.key, note.velocity));
+        note.off_beat = Some(beat + rate * gate);
+        note.step = step;
+        note.velocity = (note.velocity + ramp).clamp(0.0, 1.0);
+    }
+
+    fn release(&mut self, channel: i16, key: i16, frame: u32, output: &mut Vec<MidiFxEvent>) {
+        if let Some(i) = self.held.iter().position(|n| n.channel == channel && n.key == key) {
+            let note = self.held.remove(i);
+            if note.off_beat.is_some() {
+                push_event(output, MidiFxEvent::note_off(frame, note.channel, note.key));
+            }
+        }
+    }
+}
+
+impl Default for NoteRepeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MidiEffect for NoteRepeat {
+    fn process(
+        &mut self,
+        transport: &MidiFxTransport,
+        frames: u32,
+        input: &[MidiFxEvent],
+        output: &mut Vec<MidiFxEvent>,
+    ) {
+        let beats_per_frame = transport.beats_per_frame();
+        let start_beat = if transport.playing { transport.beat } else { self.free_beat };
+        let rate = self.rate_beats.max(1.0 / 64.0);
+        let mut events = input.iter().peekable();
+
+        for frame in 0..frames {
+            let beat = start_beat + f64::from(frame) * beats_per_frame;
+            let step = (beat / rate).floor() as i64;
+            let new_step = self.last_step.replace(step).map_or(false, |last| last != step);
+
+            // Events past the end of the block are handled on its last frame.
+            let last_frame = frame + 1 == frames;
+            while let Some(event) = events.next_if(|e| e.frame <= frame || last_frame) {
+                match event.kind {
+                    MidiFxEventKind::NoteOn { key, velocity } => {
+                        self.release(event.channel, key, frame, output);
+                        if self.held.len() < MAX_REPEAT_NOTES {
+                            self.held.push(RepeatingNote {
+                                channel: event.channel,
+                                key,
+                                velocity,
+                                step,
+                                off_beat: None,
+                            });
+                            self.trigger(self.held.len() - 1, frame, beat, step, output);
+                        } else {
+                            push_event(output, *event);
+                        }
+                    }
+                    MidiFxEventKind::NoteOff { key } => {
+                        if self.held.iter().any(|n| n.channel == event.channel && n.key == key) {
+                            self.release(event.channel, key, frame, output);
+                        } else {
+                            push_event(output, *event);
+                        }
+                    }
+                }
+            }
+
+            for note in self.held.iter_mut() {
+                if note.off_beat.map_or(false, |off_beat| beat >= off_beat) {
+                    note.off_beat = None;
+                    push_event(output, MidiFxEvent::note_off(frame, note.channel, note.key));
+                }
+            }
+
+            if new_step {
+                for i in 0..self.held.len() {
+                    if self.held[i].step != step {
+                        self.trigger(i, frame, beat, step, output);
+                    }
+                }
+            }
+        }
+
+        if !transport.playing {
+            self.free_beat = start_beat + f64::from(frames) * beats_per_frame;
+        }
+    }
+
+    fn all_notes_off(&mut self, output: &mut Vec<MidiFxEvent>) {
+        for note in self.held.drain(..) {
+            if note.off_beat.is_some() {
+                push_event(output, MidiFxEvent::note_off(0, note.channel, note.key));
+            }
+        }
+        self.last_step = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f64 = 48_000.0;
+
+    fn transport(beat: f64, bpm: f64) -> MidiFxTransport {
+        MidiFxTransport { playing: true, beat, bpm, sample_rate: SAMPLE_RATE }
+    }
+
+    /// The frames the notes start on.
+    fn note_ons(output: &[MidiFxEvent]) -> Vec<u32> {
+        output
+            .iter()
+            .filter(|event| matches!(event.kind, MidiFxEventKind::NoteOn { .. }))
+            .map(|event| event.frame)
+            .collect()
+    }
+
+    fn assert_frames(frames: &[u32], expected: &[u32]) {
+        assert_eq!(frames.len(), expected.len(), "{:?} != {:?}", frames, expected);
+        for (frame, expected) in frames.iter().zip(expected) {
+            // Rounding can put a step a frame late.
+            assert!(frame.abs_diff(*expected) <= 1, "{:?} != {:?}", frames, expected);
+        }
+    }
+
+    #[test]
+    fn held_keys_repeat_on_every_step() {
+        let mut repeat = NoteRepeat::new();
+        let mut output = Vec::with_capacity(64);
+        // A sixteenth note at 120 bpm is 6000 frames.
+        repeat.process(
+            &transport(0.0, 120.0),
+            24_000,
+            &[MidiFxEvent::note_on(0, 0, 36, 1.0)],
+            &mut output,
+        );
+        assert_frames(&note_ons(&output), &[0, 6_000, 12_000, 18_000]);
+
+        // Each repeat is held for half a step.
+        let note_offs: Vec<u32> = output
+            .iter()
+            .filter(|event| matches!(event.kind, MidiFxEventKind::NoteOff { .. }))
+            .map(|event| event.frame)
+            .collect();
+        assert_frames(&note_offs, &[3_000, 9_000, 15_000, 21_000]);
+    }
+
+    #[test]
+    fn the_rate_follows_the_tempo() {
+        let mut repeat = NoteRepeat::new();
+        let mut output = Vec::with_capacity(64);
+
+        repeat.process(
+            &transport(0.0, 120.0),
+            24_000,
+            &[MidiFxEvent::note_on(0, 0, 36, 1.0)],
+            &mut output,
+        );
+        assert_frames(&note_ons(&output), &[0, 6_000, 12_000, 18_000]);
+
+        // At half the tempo the steps are twice as far apart, and the first
+        // one lands on the start of the block, where the beat is.
+        output.clear();
+        repeat.process(&transport(1.0, 60.0), 24_000, &[], &mut output);
+        assert_frames(&note_ons(&output), &[0, 12_000]);
+
+        // And at twice the tempo they are twice as close.
+        output.clear();
+        repeat.process(&transport(1.5, 240.0), 12_000, &[], &mut output);
+        assert_frames(&note_ons(&output), &[0, 3_000, 6_000, 9_000]);
+    }
+
+    #[test]
+    fn a_key_pressed_on_a_step_is_played_once() {
+        let mut repeat = NoteRepeat::new();
+        let mut output = Vec::with_capacity(64);
+        repeat.process(
+            &transport(0.0, 120.0),
+            3_000,
+            &[MidiFxEvent::note_on(0, 0, 36, 1.0)],
+            &mut output,
+        );
+        assert_eq!(note_ons(&output), vec![0]);
+
+        // A key pressed between steps is played right away, then on the
+        // steps after that.
+        output.clear();
+        repeat.process(
+            &transport(0.125, 120.0),
+            9_000,
+            &[MidiFxEvent::note_on(1_000, 0, 38, 1.0)],
+            &mut output,
+        );
+        let keys: Vec<(i16, u32)> = output
+            .iter()
+            .filter_map(|event| match event.kind {
+                MidiFxEventKind::NoteOn { key, .. } => Some((key, event.frame)),
+                MidiFxEventKind::NoteOff { .. } => None,
+            })
+            .collect();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[0], (38, 1_000));
+        assert!(keys[1..].iter().all(|(_, frame)| frame.abs_diff(3_000) <= 1));
+    }
+
+    #[test]
+    fn released_keys_stop_repeating() {
+        let mut repeat = NoteRepeat::new();
+        let mut output = Vec::with_capacity(64);
+        repeat.process(
+            &transport(0.0, 120.0),
+            6_000,
+            &[MidiFxEvent::note_on(0, 0, 36, 1.0), MidiFxEvent::note_off(1_000, 0, 36)],
+            &mut output,
+        );
+        assert_eq!(
+            output,
+            vec![MidiFxEvent::note_on(0, 0, 36, 1.0), MidiFxEvent::note_off(1_000, 0, 36)]
+        );
+
+        output.clear();
+        repeat.process(&transport(0.25, 120.0), 24_000, &[], &mut output);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn the_velocity_ramps_between_repeats() {
+        let mut repeat = NoteRepeat { velocity_ramp: -0.25, ..NoteRepeat::new() };
+        let mut output = Vec::with_capacity(64);
+        repeat.process(
+            &transport(0.0, 120.0),
+            30_000,
+            &[MidiFxEvent::note_on(0, 0, 36, 1.0)],
+            &mut output,
+        );
+        let velocities: Vec<f32> = output
+            .iter()
+            .filter_map(|event| match event.kind {
+                MidiFxEventKind::NoteOn { velocity, .. } => Some(velocity),
+                MidiFxEventKind::NoteOff { .. } => None,
+            })
+            .collect();
+        assert_eq!(velocities, vec![1.0, 0.75, 0.5, 0.25, 0.0]);
+    }
+}
@@ -0,0 +1,168 @@
+use super::{push_event, HeldKeys, MidiEffect, MidiFxEvent, MidiFxEventKind, MidiFxTransport};
+use crate::backend::scale::MusicalKey;
+
+/// What happens to notes that aren't in the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleLockMode {
+    /// Move them to the nearest note in the key.
+    Snap,
+    /// Don't play them.
+    Drop,
+}
+
+/// Keeps the played notes in a musical key.
+pub struct ScaleLock {
+    pub key: MusicalKey,
+    pub mode: ScaleLockMode,
+
+    held: HeldKeys,
+}
+
+impl ScaleLock {
+    pub fn new(key: MusicalKey) -> Self {
+        Self { key, mode: ScaleLockMode::Snap, held: HeldKeys::new() }
+    }
+}
+
+impl MidiEffect for ScaleLock {
+    fn process(
+        &mut self,
+        _transport: &MidiFxTransport,
+        _frames: u32,
+        input: &[MidiFxEvent],
+        output: &mut Vec<MidiFxEvent>,
+    ) {
+        for event in input {
+            match event.kind {
+                MidiFxEventKind::NoteOn { key, velocity } => {
+                    let out_key = match self.mode {
+                        ScaleLockMode::Snap => Some(self.key.snap(i32::from(key)) as i16),
+                        ScaleLockMode::Drop => self.key.contains(i32::from(key)).then(|| key),
+                    };
+                    let outputs = match &out_key {
+                        Some(out_key) => std::slice::from_ref(out_key),
+                        None => &[],
+                    };
+                    for &out_key in self.held.press(event.channel, key, outputs) {
+                        push_event(
+                            output,
+                            MidiFxEvent::note_on(event.frame, event.channel, out_key, velocity),
+                        );
+                    }
+                }
+                MidiFxEventKind::NoteOff { key } => {
+                    for out_key in self.held.release(event.channel, key) {
+                        push_event(
+                            output,
+                            MidiFxEvent::note_off(event.frame, event.channel, out_key),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn all_notes_off(&mut self, output: &mut Vec<MidiFxEvent>) {
+        self.held.release_all(output);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::scale::Scale;
+
+    fn transport() -> MidiFxTransport {
+        MidiFxTransport { playing: true, beat: 0.0, bpm: 120.0, sample_rate: 48_000.0 }
+    }
+
+    fn run(lock: &mut ScaleLock, input: &[MidiFxEvent]) -> Vec<MidiFxEvent> {
+        let mut output = Vec::with_capacity(64);
+        lock.process(&transport(), 256, input, &mut output);
+        output
+    }
+
+    /// The note a key is played as, if it is played at all.
+    fn play(lock: &mut ScaleLock, key: i16) -> Option<i16> {
+        let output = run(lock, &[MidiFxEvent::note_on(0, 0, key, 1.0)]);
+        run(lock, &[MidiFxEvent::note_off(0, 0, key)]);
+        match output.as_slice() {
+            [] => None,
+            [MidiFxEvent { kind: MidiFxEventKind::NoteOn { key, .. }, .. }] => Some(*key),
+            _ => panic!("unexpected events {:?}", output),
+        }
+    }
+
+    #[test]
+    fn notes_in_the_key_are_left_alone() {
+        let key = MusicalKey::new(2, Scale::Major);
+        let mut lock = ScaleLock::new(key);
+        for note in 0..128 {
+            if key.contains(i32::from(note)) {
+                assert_eq!(play(&mut lock, note), Some(note));
+            }
+        }
+    }
+
+    #[test]
+    fn notes_snap_to_the_nearest_note_in_the_key() {
+        // D major: D E F# G A B C#
+        let mut lock = ScaleLock::new(MusicalKey::new(2, Scale::Major));
+        assert_eq!(play(&mut lock, 63), Some(62));
+        assert_eq!(play(&mut lock, 65), Some(64));
+        // Halfway between B and C# the lower one wins, across the C the
+        // octave starts at.
+        assert_eq!(play(&mut lock, 60), Some(59));
+
+        // A minor pentatonic: A C D E G, so B goes up into the next octave.
+        let mut lock = ScaleLock::new(MusicalKey::new(9, Scale::MinorPentatonic));
+        assert_eq!(play(&mut lock, 71), Some(72));
+        assert_eq!(play(&mut lock, 70), Some(69));
+        // F# is two steps from E and one from G.
+        assert_eq!(play(&mut lock, 66), Some(67));
+    }
+
+    #[test]
+    fn notes_snapped_out_of_the_midi_range_are_not_played() {
+        // C is between B and C# in E major, and the B below 0 doesn't exist.
+        let mut lock = ScaleLock::new(MusicalKey::new(4, Scale::Major));
+        assert_eq!(play(&mut lock, 0), None);
+        assert_eq!(play(&mut lock, 12), Some(11));
+    }
+
+    #[test]
+    fn drop_mode_skips_notes_outside_the_key() {
+        let mut lock = ScaleLock::new(MusicalKey::new(0, Scale::Major));
+        lock.mode = ScaleLockMode::Drop;
+        assert_eq!(play(&mut lock, 61), None);
+        assert_eq!(play(&mut lock, 62), Some(62));
+
+        // The note off of a dropped note isn't sent either.
+        run(&mut lock, &[MidiFxEvent::note_on(0, 0, 61, 1.0)]);
+        assert!(run(&mut lock, &[MidiFxEvent::note_off(0, 0, 61)]).is_empty());
+    }
+
+    #[test]
+    fn keys_snapped_to_the_same_note_share_it() {
+        let mut lock = ScaleLock::new(MusicalKey::new(0, Scale::Major));
+        run(&mut lock, &[MidiFxEvent::note_on(0, 0, 61, 1.0), MidiFxEvent::note_on(0, 0, 60, 1.0)]);
+
+        // The C keeps sounding until both keys are let go.
+        assert!(run(&mut lock, &[MidiFxEvent::note_off(0, 0, 61)]).is_empty());
+        assert_eq!(
+            run(&mut lock, &[MidiFxEvent::note_off(5, 0, 60)]),
+            vec![MidiFxEvent::note_off(5, 0, 60)]
+        );
+    }
+
+    #[test]
+    fn changing_the_key_still_ends_the_held_notes() {
+        let mut lock = ScaleLock::new(MusicalKey::new(0, Scale::Major));
+        run(&mut lock, &[MidiFxEvent::note_on(0, 0, 61, 1.0)]);
+        lock.key = MusicalKey::new(1, Scale::Major);
+        assert_eq!(
+            run(&mut lock, &[MidiFxEvent::note_off(0, 0, 61)]),
+            vec![MidiFxEvent::note_off(0, 0, 60)]
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Sends MIDI to hardware devices, e.g. to play an external synth from a MIDI
+//! track.
+//!
+//! The devices are written to on their own thread, so a slow driver never
+//! holds up the caller. Every message carries the time it should be sent at,
+//! which the thread moves by the latency offset of its device before sending
+//! it.
+
+use crossbeam::channel::{self, Receiver, RecvTimeoutError, Sender};
+use midir::{MidiOutput, MidiOutputConnection};
+use smallvec::SmallVec;
+use std::error::Error;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The name the app shows up as to the MIDI system.
+const CLIENT_NAME: &str = "Meadowlark";
+
+/// MIDI channels are numbered 1 to this in the UI, and 0 to 15 on the wire.
+pub const MIDI_CHANNELS: u8 = 16;
+
+/// The largest latency offset of a device, either way.
+pub const MAX_LATENCY_OFFSET_MS: f64 = 500.0;
+
+/// How long the sending thread waits for new messages when nothing is due.
+const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A message for a hardware device. Channels are 0 to 15.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiOutMessage {
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        channel: u8,
+        key: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8,
+    },
+    /// Bank select as both the coarse (CC 0) and fine (CC 32) controller, so
+    /// banks up to 16383 can be selected. Most devices only switch banks when
+    /// the program change that follows arrives.
+    BankSelect {
+        channel: u8,
+        bank: u16,
+    },
+    AllNotesOff {
+        channel: u8,
+    },
+}
+
+impl MidiOutMessage {
+    pub fn to_bytes(&self) -> SmallVec<[u8; 6]> {
+        let status = |kind: u8, channel: u8| kind | (channel & 0x0F);
+        match *self {
+            MidiOutMessage::NoteOn { channel, key, velocity } => {
+                SmallVec::from_slice(&[status(0x90, channel), key & 0x7F, velocity & 0x7F])
+            }
+            MidiOutMessage::NoteOff { channel, key } => {
+                SmallVec::from_slice(&[status(0x80, channel), key & 0x7F, 0])
+            }
+            MidiOutMessage::ProgramChange { channel, program } => {
+                SmallVec::from_slice(&[status(0xC0, channel), program & 0x7F])
+            }
+            MidiOutMessage::BankSelect { channel, bank } => SmallVec::from_slice(&[
+                status(0xB0, channel),
+                0,
+                ((bank >> 7) & 0x7F) as u8,
+                status(0xB0, channel),
+                32,
+                (bank & 0x7F) as u8,
+            ]),
+            MidiOutMessage::AllNotesOff { channel } => {
+                SmallVec::from_slice(&[status(0xB0, channel), 123, 0])
+            }
+        }
+    }
+}
+
+/// The names of the MIDI output devices that are connected right now.
+pub fn output_device_names() -> Result<Vec<String>, Box<dyn Error>> {
+    let midi_out = MidiOutput::new(CLIENT_NAME)?;
+    Ok(midi_out.ports().iter().filter_map(|port| midi_out.port_name(port).ok()).collect())
+}
+
+enum Command {
+    Open(String),
+    Close(String),
+    SetLatencyOffset { device: String, offset_ms: f64 },
+    Send { device: String, at: Instant, message: MidiOutMessage },
+}
+
+/// Owns the thread that sends to the MIDI output devices.
+pub struct MidiOutHandle {
+    tx: Option<Sender<Command>>,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl MidiOutHandle {
+    pub fn new() -> std::io::Result<Self> {
+        let (tx, rx) = channel::unbounded();
+        let join_handle =
+            std::thread::Builder::new().name("midi-out".into()).spawn(move || run(rx))?;
+        Ok(Self { tx: Some(tx), join_handle: Some(join_handle) })
+    }
+
+    /// Connect to a device by name. Messages for a device that isn't open are
+    /// dropped.
+    pub fn open(&mut self, device: &str) {
+        self.command(Command::Open(device.to_string()));
+    }
+
+    pub fn close(&mut self, device: &str) {
+        self.command(Command::Close(device.to_string()));
+    }
+
+    /// Move every message for a device by this many milliseconds. Positive
+    /// offsets delay the messages. Negative offsets make up for a slow device,
+    /// but only for messages that are sent ahead of their time.
+    pub fn set_latency_offset(&mut self, device: &str, offset_ms: f64) {
+        let offset_ms = offset_ms.clamp(-MAX_LATENCY_OFFSET_MS, MAX_LATENCY_OFFSET_MS);
+        self.command(Command::SetLatencyOffset { device: device.to_string(), offset_ms });
+    }
+
+    /// Send a message at the given time, or right away if it has passed.
+    pub fn send_at(&mut self, device: &str, at: Instant, message: MidiOutMessage) {
+        self.command(Command::Send { device: device.to_string(), at, message });
+    }
+
+    pub fn send(&mut self, device: &str, message: MidiOutMessage) {
+        self.send_at(device, Instant::now(), message);
+    }
+
+    fn command(&mut self, command: Command) {
+        let sent = self.tx.as_ref().map_or(false, |tx| tx.send(command).is_ok());
+        if !sent {
+            log::error!("The MIDI output thread has stopped");
+        }
+    }
+}
+
+impl Drop for MidiOutHandle {
+    fn drop(&mut self) {
+        self.tx = None;
+        if let Some(join_handle) = self.join_handle.take() {
+            if join_handle.join().is_err() {
+                log::error!("The MIDI output thread panicked");
+            }
+        }
+    }
+}
+
+struct Device {
+    name: String,
+    connection: MidiOutputConnection,
+    offset_ms: f64,
+}
+
+/// A message waiting for its time to be sent.
+struct Pending {
+    at: Instant,
+    device: String,
+    message: MidiOutMessage,
+}
+
+fn run(rx: Receiver<Command>) {
+    let mut devices: Vec<Device> = Vec::new();
+    // Sorted by time, with the next message last.
+    let mut pending: Vec<Pending> = Vec::new();
+
+    loop {
+        let timeout = pending
+            .last()
+            .map_or(IDLE_INTERVAL, |next| next.at.saturating_duration_since(Instant::now()));
+
+        match rx.recv_timeout(timeout) {
+            Ok(Command::Open(name)) => {
+                if !devices.iter().any(|d| d.name == name) {
+                    match connect(&name) {
+                        Ok(connection) => devices.push(Device { name, connection, offset_ms: 0.0 }),
+                        Err(e) => log::error!("Failed to open MIDI output {:?}: {}", &name, e),
+                    }
+                }
+            }
+            Ok(Command::Close(name)) => {
+                devices.retain(|d| d.name != name);
+                pending.retain(|p| p.device != name);
+            }
+            Ok(Command::SetLatencyOffset { device, offset_ms }) => {
+                if let Some(device) = devices.iter_mut().find(|d| d.name == device) {
+                    device.offset_ms = offset_ms;
+                }
+            }
+            Ok(Command::Send { device, at, message }) => {
+                let offset_ms = match devices.iter().find(|d| d.name == device) {
+                    Some(device) => device.offset_ms,
+                    None => continue,
+                };
+                let offset = Duration::from_secs_f64(offset_ms.abs() / 1_000.0);
+                let at = if offset_ms >= 0.0 {
+                    at + offset
+                } else {
+                    at.checked_sub(offset).unwrap_or(at)
+                };
+
+                let i = pending.iter().position(|p| p.at <= at).unwrap_or(pending.len());
+                pending.insert(i, Pending { at, device, message });
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        let now = Instant::now();
+        while pending.last().map_or(false, |next| next.at <= now) {
+            if let Some(next) = pending.pop() {
+                if let Some(device) = devices.iter_mut().find(|d| d.name == next.device) {
+                    if let Err(e) = device.connection.send(&next.message.to_bytes()) {
+                        log::error!("Failed to send MIDI to {:?}: {}", &device.name, e);
+                    }
+                }
+            }
+        }
+    }
+
+    // Don't leave notes hanging on the hardware.
+    for device in devices.iter_mut() {
+        for channel in 0..MIDI_CHANNELS {
+            let _ = device.connection.send(&MidiOutMessage::AllNotesOff { channel }.to_bytes());
+        }
+    }
+}
+
+fn connect(name: &str) -> Result<MidiOutputConnection, Box<dyn Error>> {
+    let midi_out = MidiOutput::new(CLIENT_NAME)?;
+    let port = midi_out
+        .ports()
+        .into_iter()
+        .find(|port| midi_out.port_name(port).map_or(false, |n| n == name))
+        .ok_or_else(|| format!("No MIDI output named {:?}", name))?;
+    midi_out.connect(&port, CLIENT_NAME).map_err(|e| e.to_string().into())
+}
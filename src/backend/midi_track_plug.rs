@@ -0,0 +1,569 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::{EventHeader, NoteEvent, NoteOffEvent, NoteOnEvent};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::time::{Duration, Instant};
+
+use super::midi_fx::{
+    MidiFxChain, MidiFxEvent, MidiFxEventKind, MidiFxTransport, MAX_BLOCK_EVENTS,
+};
+use super::midi_transform::{MidiTransform, TransformNote, MAX_ZONES};
+use super::tempo_bus::TempoBus;
+
+pub static MIDI_TRACK_PLUG_RDN: &str = "app.meadowlark.midi-track";
+
+const MSG_BUFFER_SIZE: usize = 16;
+/// The notes the plugin played that the handle hasn't picked up yet. Notes
+/// past this are only played by the instrument.
+const PLAYED_BUFFER_SIZE: usize = 1024;
+
+/// The most notes that sound at once. Notes started past this aren't played.
+pub const MAX_SOUNDING_NOTES: usize = 128;
+
+/// A note on the timeline, in beats from the start of the project.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackNote {
+    pub start_beats: f64,
+    pub end_beats: f64,
+    /// The MIDI channel, from 0 to 15.
+    pub channel: i16,
+    pub key: i16,
+    /// The velocity in the range `[0.0, 1.0]`.
+    pub velocity: f32,
+}
+
+/// A note on or off the plugin sent out of its note port, for a channel that
+/// also sends its MIDI to a hardware device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayedNote {
+    /// When the note sounds: the time its block was processed at, plus its
+    /// frame in the block.
+    pub at: Instant,
+    pub on: bool,
+    /// The MIDI channel, from 0 to 15.
+    pub channel: i16,
+    pub key: i16,
+    /// The velocity in the range `[0.0, 1.0]`.
+    pub velocity: f32,
+}
+
+/// Plays the notes of the MIDI clips of a channel in time with the transport,
+/// out of its note port, to drive an instrument. Notes that come into its
+/// note port, i.e. notes played live, go through the MIDI input processing of
+/// the channel and are played along with them. Both go through the MIDI
+/// effects of the channel on the way out.
+pub struct MidiTrackPlugFactory {
+    tempo_bus: TempoBus,
+}
+
+impl MidiTrackPlugFactory {
+    pub fn new(tempo_bus: TempoBus) -> Self {
+        Self { tempo_bus }
+    }
+}
+
+impl PluginFactory for MidiTrackPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: MIDI_TRACK_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "MIDI Track".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(MidiTrackPlugMainThread { tempo_bus: self.tempo_bus.clone() }))
+    }
+}
+
+pub struct MidiTrackPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    from_audio_thread_rx: Consumer<PlayedNote>,
+    coll_handle: basedrop::Handle,
+    /// The notes that were sent last, so that they are only sent again when
+    /// they changed.
+    notes: Option<Shared<Vec<TrackNote>>>,
+    /// Likewise for the MIDI input processing.
+    midi_input: MidiTransform,
+}
+
+impl MidiTrackPlugHandle {
+    /// Replace the notes that are played, e.g. after a clip was edited. Notes
+    /// that are sounding keep sounding until their old end.
+    pub fn set_notes(&mut self, mut notes: Vec<TrackNote>) {
+        notes.sort_by(|a, b| {
+            a.start_beats.partial_cmp(&b.start_beats).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        if self.notes.as_deref() == Some(&notes) {
+            return;
+        }
+        let notes = Shared::new(&self.coll_handle, notes);
+        match self.to_audio_thread_tx.push(ProcessMsg::SetNotes(Shared::clone(&notes))) {
+            Ok(()) => self.notes = Some(notes),
+            Err(e) => log::error!("MIDI track plugin failed to send message: {}", e),
+        }
+    }
+
+    /// Replace the processing of the notes that come into the note port. The
+    /// notes held through the old processing are ended.
+    pub fn set_midi_input(&mut self, transform: &MidiTransform) {
+        if &self.midi_input == transform {
+            return;
+        }
+        let msg = ProcessMsg::SetMidiInput(Owned::new(&self.coll_handle, transform.clone()));
+        match self.to_audio_thread_tx.push(msg) {
+            Ok(()) => self.midi_input = transform.clone(),
+            Err(e) => log::error!("MIDI track plugin failed to send message: {}", e),
+        }
+    }
+
+    /// Pop the notes that were played since the last call, with the effects
+    /// applied. They should be popped regularly even if they aren't used, or
+    /// the oldest notes are what is returned once they are.
+    pub fn poll_played(&mut self) -> impl Iterator<Item = PlayedNote> + '_ {
+        std::iter::from_fn(move || self.from_audio_thread_rx.pop().ok())
+    }
+
+    /// Set whether notes that are held at the playhead when playback starts
+    /// or the playhead jumps are started there, instead of only the notes
+    /// that start after it. This is on by default.
+    pub fn set_chase_notes(&mut self, chase: bool) {
+        if let Err(e) = self.to_audio_thread_tx.push(ProcessMsg::SetChaseNotes(chase)) {
+            log::error!("MIDI track plugin failed to send message: {}", e);
+        }
+    }
+
+    /// Replace the MIDI effects the notes go through. The notes the old
+    /// effects were playing are ended.
+    pub fn set_midi_fx(&mut self, chain: MidiFxChain) {
+        let chain = Owned::new(&self.coll_handle, chain);
+        if let Err(e) = self.to_audio_thread_tx.push(ProcessMsg::SetMidiFx(chain)) {
+            log::error!("MIDI track plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetNotes(Shared<Vec<TrackNote>>),
+    SetChaseNotes(bool),
+    SetMidiFx(Owned<MidiFxChain>),
+    SetMidiInput(Owned<MidiTransform>),
+}
+
+pub struct MidiTrackPlugMainThread {
+    tempo_bus: TempoBus,
+}
+
+impl PluginMainThread for MidiTrackPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        _max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+        let (to_handle_tx, from_audio_thread_rx) =
+            RingBuffer::<PlayedNote>::new(PLAYED_BUFFER_SIZE);
+        let to_handle_tx = Owned::new(coll_handle, to_handle_tx);
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(MidiTrackPlugAudioThread {
+                from_handle_rx,
+                to_handle_tx,
+                tempo_bus: self.tempo_bus.clone(),
+                sample_rate,
+                notes: None,
+                chase_notes: true,
+                midi_input: Owned::new(coll_handle, MidiTransform::default()),
+                held_input: Vec::with_capacity(MAX_SOUNDING_NOTES * MAX_ZONES),
+                sounding: Vec::with_capacity(MAX_SOUNDING_NOTES),
+                scheduled: Vec::with_capacity(MAX_SOUNDING_NOTES * 3),
+                was_playing: false,
+                midi_fx: Owned::new(coll_handle, MidiFxChain::new()),
+                fx_input: Vec::with_capacity(MAX_BLOCK_EVENTS),
+                fx_output: Vec::with_capacity(MAX_BLOCK_EVENTS * 2),
+            }),
+            internal_handle: Some(Box::new(MidiTrackPlugHandle {
+                to_audio_thread_tx,
+                from_audio_thread_rx,
+                coll_handle: coll_handle.clone(),
+                notes: None,
+                midi_input: MidiTransform::default(),
+            })),
+        })
+    }
+
+    fn note_ports_ext(&mut self) -> Result<ext::note_ports::PluginNotePortsExt, String> {
+        let ext::note_ports::PluginNotePortsExt { inputs, .. } =
+            ext::note_ports::PluginNotePortsExt::single_in();
+        Ok(ext::note_ports::PluginNotePortsExt {
+            inputs,
+            ..ext::note_ports::PluginNotePortsExt::single_out()
+        })
+    }
+}
+
+/// A note that was started and hasn't been ended yet.
+#[derive(Debug, Clone, Copy)]
+struct SoundingNote {
+    channel: i16,
+    key: i16,
+    end_beats: f64,
+}
+
+/// A note that came into the note port and is held, with the note it became.
+/// A key in more than one zone is held once for each of them.
+#[derive(Debug, Clone, Copy)]
+struct HeldInput {
+    channel: i16,
+    key: i16,
+    out_channel: i16,
+    out_key: i16,
+}
+
+/// A note on or off to send in the current block.
+#[derive(Debug, Clone, Copy)]
+struct ScheduledEvent {
+    frame: u32,
+    on: bool,
+    channel: i16,
+    key: i16,
+    velocity: f32,
+}
+
+pub struct MidiTrackPlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+    to_handle_tx: Owned<Producer<PlayedNote>>,
+    tempo_bus: TempoBus,
+    sample_rate: SampleRate,
+
+    /// Sorted by their start.
+    notes: Option<Shared<Vec<TrackNote>>>,
+    chase_notes: bool,
+
+    midi_input: Owned<MidiTransform>,
+    /// The notes that came in and haven't been released yet. Their note offs
+    /// end the notes they became, even if the processing changed since.
+    held_input: Vec<HeldInput>,
+
+    sounding: Vec<SoundingNote>,
+    /// The events of the current block, which are sorted before they are sent.
+    scheduled: Vec<ScheduledEvent>,
+
+    /// Whether the last block was played, to tell when playback starts.
+    was_playing: bool,
+
+    midi_fx: Owned<MidiFxChain>,
+    fx_input: Vec<MidiFxEvent>,
+    /// The note offs of the effects that were replaced, then what the effects
+    /// put out for this block.
+    fx_output: Vec<MidiFxEvent>,
+}
+
+impl MidiTrackPlugAudioThread {
+    /// End every sounding note at the start of the block.
+    fn end_all(&mut self) {
+        for note in self.sounding.drain(..) {
+            self.scheduled.push(ScheduledEvent {
+                frame: 0,
+                on: false,
+                channel: note.channel,
+                key: note.key,
+                velocity: 0.0,
+            });
+        }
+    }
+
+    /// Start the notes that are held at the playhead at the start of the
+    /// block, after playback started or the playhead jumped into the middle
+    /// of them.
+    fn chase(&mut self, start_beat: f64) {
+        let notes = match &self.notes {
+            Some(notes) => notes,
+            None => return,
+        };
+
+        let first = notes.partition_point(|note| note.start_beats < start_beat);
+        for note in notes[..first].iter().filter(|note| note.end_beats > start_beat) {
+            if self.sounding.len() >= MAX_SOUNDING_NOTES {
+                break;
+            }
+            if self.sounding.iter().any(|s| s.channel == note.channel && s.key == note.key) {
+                continue;
+            }
+            self.scheduled.push(ScheduledEvent {
+                frame: 0,
+                on: true,
+                channel: note.channel,
+                key: note.key,
+                velocity: note.velocity,
+            });
+            self.sounding.push(SoundingNote {
+                channel: note.channel,
+                key: note.key,
+                end_beats: note.end_beats,
+            });
+        }
+    }
+
+    /// End the notes that came into the note port and are held, at the start
+    /// of the block.
+    fn end_input(&mut self) {
+        for held in self.held_input.drain(..) {
+            self.scheduled.push(ScheduledEvent {
+                frame: 0,
+                on: false,
+                channel: held.out_channel,
+                key: held.out_key,
+                velocity: 0.0,
+            });
+        }
+    }
+
+    /// Pass on the notes that came into the note port, through the MIDI input
+    /// processing.
+    fn schedule_input(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            let frame = e.header().time();
+            let (on, note) = if let Some(note_on) = e.as_event::<NoteOnEvent>() {
+                (true, &note_on.0)
+            } else if let Some(note_off) = e.as_event::<NoteOffEvent>() {
+                (false, &note_off.0)
+            } else {
+                continue;
+            };
+            let (channel, key) = (note.channel(), note.key());
+
+            if !on {
+                let scheduled = &mut self.scheduled;
+                self.held_input.retain(|held| {
+                    if held.channel != channel || held.key != key {
+                        return true;
+                    }
+                    scheduled.push(ScheduledEvent {
+                        frame,
+                        on: false,
+                        channel: held.out_channel,
+                        key: held.out_key,
+                        velocity: 0.0,
+                    });
+                    false
+                });
+                continue;
+            }
+
+            let input = TransformNote { channel, key, velocity: note.velocity() as f32 };
+            for (_, out) in self.midi_input.process(input, true) {
+                if self.held_input.len() >= self.held_input.capacity() {
+                    break;
+                }
+                self.held_input.push(HeldInput {
+                    channel,
+                    key,
+                    out_channel: out.channel,
+                    out_key: out.key,
+                });
+                self.scheduled.push(ScheduledEvent {
+                    frame,
+                    on: true,
+                    channel: out.channel,
+                    key: out.key,
+                    velocity: out.velocity,
+                });
+            }
+        }
+    }
+
+    fn schedule(&mut self, start_beat: f64, beats_per_frame: f64, frames: u32) {
+        let end_beat = start_beat + f64::from(frames) * beats_per_frame;
+        let to_frame = |beat: f64| {
+            (((beat - start_beat) / beats_per_frame).max(0.0) as u32).min(frames.saturating_sub(1))
+        };
+
+        // Notes that start before the playhead are left to `chase()`.
+        if let Some(notes) = &self.notes {
+            let first = notes.partition_point(|note| note.start_beats < start_beat);
+            for note in notes[first..].iter().take_while(|note| note.start_beats < end_beat) {
+                if self.sounding.len() >= MAX_SOUNDING_NOTES {
+                    break;
+                }
+                // Retrigger the key if it is still sounding.
+                if let Some(i) = self
+                    .sounding
+                    .iter()
+                    .position(|s| s.channel == note.channel && s.key == note.key)
+                {
+                    self.sounding.swap_remove(i);
+                    self.scheduled.push(ScheduledEvent {
+                        frame: to_frame(note.start_beats),
+                        on: false,
+                        channel: note.channel,
+                        key: note.key,
+                        velocity: 0.0,
+                    });
+                }
+                self.scheduled.push(ScheduledEvent {
+                    frame: to_frame(note.start_beats),
+                    on: true,
+                    channel: note.channel,
+                    key: note.key,
+                    velocity: note.velocity,
+                });
+                self.sounding.push(SoundingNote {
+                    channel: note.channel,
+                    key: note.key,
+                    end_beats: note.end_beats,
+                });
+            }
+        }
+
+        let scheduled = &mut self.scheduled;
+        self.sounding.retain(|note| {
+            if note.end_beats < end_beat {
+                scheduled.push(ScheduledEvent {
+                    frame: to_frame(note.end_beats),
+                    on: false,
+                    channel: note.channel,
+                    key: note.key,
+                    velocity: 0.0,
+                });
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+impl PluginAudioThread for MidiTrackPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {}
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        _buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.fx_output.clear();
+        self.scheduled.clear();
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetNotes(notes) => self.notes = Some(notes),
+                ProcessMsg::SetChaseNotes(chase) => self.chase_notes = chase,
+                ProcessMsg::SetMidiFx(midi_fx) => {
+                    // The old chain is dropped by the collector.
+                    let mut old = std::mem::replace(&mut self.midi_fx, midi_fx);
+                    old.all_notes_off(&mut self.fx_output);
+                }
+                ProcessMsg::SetMidiInput(midi_input) => {
+                    // The old processing is dropped by the collector.
+                    self.midi_input = midi_input;
+                    self.end_input();
+                }
+            }
+        }
+
+        self.schedule_input(in_events);
+
+        let playing = proc_info.transport.is_playing();
+        let (start_beat, beats_per_frame) = self.tempo_bus.tempo().block_beats(
+            proc_info.transport.playhead_frame(),
+            proc_info.frames,
+            self.sample_rate.0,
+        );
+        if playing {
+            // The transport says when the playhead was moved, since a tempo
+            // change also moves the beat a block starts on.
+            let jumped = proc_info.transport.seek_info().is_some();
+            if jumped {
+                self.end_all();
+            }
+            if (jumped || !self.was_playing) && self.chase_notes {
+                self.chase(start_beat);
+            }
+
+            self.schedule(start_beat, beats_per_frame, proc_info.frames as u32);
+        } else {
+            self.end_all();
+        }
+        self.was_playing = playing;
+
+        // Note offs go first, so a key that ends where it starts again is
+        // played again.
+        self.scheduled.sort_unstable_by_key(|event| (event.frame, event.on));
+        self.fx_input.clear();
+        for event in self.scheduled.iter().take(MAX_BLOCK_EVENTS) {
+            self.fx_input.push(if event.on {
+                MidiFxEvent::note_on(event.frame, event.channel, event.key, event.velocity)
+            } else {
+                MidiFxEvent::note_off(event.frame, event.channel, event.key)
+            });
+        }
+
+        let transport = MidiFxTransport {
+            playing,
+            beat: start_beat,
+            bpm: beats_per_frame * 60.0 * self.sample_rate.0,
+            sample_rate: self.sample_rate.0,
+        };
+        self.midi_fx.process(
+            &transport,
+            proc_info.frames as u32,
+            &self.fx_input,
+            &mut self.fx_output,
+        );
+
+        let now = Instant::now();
+        for event in self.fx_output.iter() {
+            let header = EventHeader::new(event.frame);
+            let (on, key, velocity) = match event.kind {
+                MidiFxEventKind::NoteOn { key, velocity } => {
+                    let note =
+                        NoteEvent::new(header, -1, 0, key, event.channel, f64::from(velocity));
+                    out_events.push(NoteOnEvent(note).as_unknown());
+                    (true, key, velocity)
+                }
+                MidiFxEventKind::NoteOff { key } => {
+                    let note = NoteEvent::new(header, -1, 0, key, event.channel, 0.0);
+                    out_events.push(NoteOffEvent(note).as_unknown());
+                    (false, key, 0.0)
+                }
+            };
+            // The handle may not be keeping up, in which case the note is
+            // only played by the instrument.
+            let _ = self.to_handle_tx.push(PlayedNote {
+                at: now + Duration::from_secs_f64(f64::from(event.frame) / self.sample_rate.0),
+                on,
+                channel: event.channel,
+                key,
+                velocity,
+            });
+        }
+
+        ProcessStatus::Continue
+    }
+}
@@ -0,0 +1,241 @@
+//! The processing a track applies to incoming notes before they reach its
+//! instrument: filtering by channel, key and velocity, transposing, shaping
+//! the velocity, and splitting or layering the keyboard into zones.
+
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+
+/// Lets every MIDI channel through.
+pub const ALL_CHANNELS: u16 = 0xFFFF;
+
+pub const MIN_KEY: i16 = 0;
+pub const MAX_KEY: i16 = 127;
+
+/// The most zones a keyboard can be split into.
+pub const MAX_ZONES: usize = 8;
+
+/// A note coming in or going out. Velocity is from 0.0 to 1.0, and is ignored
+/// for note offs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransformNote {
+    /// The MIDI channel, from 0 to 15.
+    pub channel: i16,
+    pub key: i16,
+    pub velocity: f32,
+}
+
+/// How the velocity of incoming notes is changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    /// Soft playing comes out louder.
+    Soft,
+    /// Soft playing comes out quieter.
+    Hard,
+    /// Every note has the same velocity.
+    Fixed(f32),
+}
+
+impl VelocityCurve {
+    pub fn apply(&self, velocity: f32) -> f32 {
+        let velocity = velocity.clamp(0.0, 1.0);
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Soft => velocity.sqrt(),
+            VelocityCurve::Hard => velocity * velocity,
+            VelocityCurve::Fixed(fixed) => fixed.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// A range of keys that is sent on to the instrument. Zones that don't overlap
+/// split the keyboard, and zones that do overlap layer it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KeyZone {
+    pub low_key: i16,
+    pub high_key: i16,
+    /// Added to the keys in this zone, on top of the transpose of the track.
+    pub transpose: i16,
+}
+
+impl KeyZone {
+    pub fn contains(&self, key: i16) -> bool {
+        (self.low_key..=self.high_key).contains(&key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MidiTransform {
+    /// One bit for each MIDI channel that is let through, with channel 0 as
+    /// the lowest bit.
+    pub channels: u16,
+    /// Notes outside of these keys are dropped, before transposing.
+    pub low_key: i16,
+    pub high_key: i16,
+    /// Notes played softer or harder than this are dropped, before the
+    /// velocity curve.
+    pub min_velocity: f32,
+    pub max_velocity: f32,
+    /// In semitones.
+    pub transpose: i16,
+    pub velocity_curve: VelocityCurve,
+    /// If there are any zones, only the keys in them are let through, once
+    /// for each zone they are in. The zones are matched before transposing.
+    pub zones: Vec<KeyZone>,
+}
+
+impl Default for MidiTransform {
+    fn default() -> Self {
+        Self {
+            channels: ALL_CHANNELS,
+            low_key: MIN_KEY,
+            high_key: MAX_KEY,
+            min_velocity: 0.0,
+            max_velocity: 1.0,
+            transpose: 0,
+            velocity_curve: VelocityCurve::Linear,
+            zones: Vec::new(),
+        }
+    }
+}
+
+impl MidiTransform {
+    /// True if this lets every note through unchanged.
+    pub fn is_identity(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Process an incoming note on or note off. Returns the notes that come
+    /// out along with the index of the zone each came out of (always 0 if
+    /// there are no zones), so the zones can play different instruments.
+    ///
+    /// Note offs aren't filtered by velocity, so that a note always ends.
+    /// Changing the transform while notes are held can still leave them
+    /// hanging, so the caller should end all notes when it changes.
+    pub fn process(&self, note: TransformNote, on: bool) -> SmallVec<[(usize, TransformNote); 4]> {
+        let mut out = SmallVec::new();
+
+        let channel_passes =
+            (0..16).contains(&note.channel) && self.channels & (1 << note.channel) != 0;
+        let key_passes = (self.low_key..=self.high_key).contains(&note.key);
+        let velocity_passes =
+            !on || (self.min_velocity..=self.max_velocity).contains(&note.velocity);
+        if !channel_passes || !key_passes || !velocity_passes {
+            return out;
+        }
+
+        let velocity = if on { self.velocity_curve.apply(note.velocity) } else { 0.0 };
+        let transposed = |transpose: i16| {
+            let key = note.key + self.transpose + transpose;
+            if (MIN_KEY..=MAX_KEY).contains(&key) {
+                Some(TransformNote { key, velocity, ..note })
+            } else {
+                None
+            }
+        };
+
+        if self.zones.is_empty() {
+            out.extend(transposed(0).map(|note| (0, note)));
+        } else {
+            for (i, zone) in self.zones.iter().enumerate() {
+                if zone.contains(note.key) {
+                    out.extend(transposed(zone.transpose).map(|note| (i, note)));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(channel: i16, key: i16, velocity: f32) -> TransformNote {
+        TransformNote { channel, key, velocity }
+    }
+
+    fn keys(out: &[(usize, TransformNote)]) -> Vec<(usize, i16)> {
+        out.iter().map(|(zone, note)| (*zone, note.key)).collect()
+    }
+
+    #[test]
+    fn the_default_lets_notes_through_unchanged() {
+        let transform = MidiTransform::default();
+        assert!(transform.is_identity());
+        for channel in 0..16 {
+            let out = transform.process(note(channel, 60, 0.5), true);
+            assert_eq!(out.as_slice(), &[(0, note(channel, 60, 0.5))]);
+        }
+    }
+
+    #[test]
+    fn velocity_curves_keep_the_ends_of_the_range() {
+        for curve in [VelocityCurve::Linear, VelocityCurve::Soft, VelocityCurve::Hard] {
+            assert_eq!(curve.apply(0.0), 0.0);
+            assert_eq!(curve.apply(1.0), 1.0);
+            assert_eq!(curve.apply(2.0), 1.0);
+        }
+        assert!(VelocityCurve::Soft.apply(0.25) > 0.25);
+        assert!(VelocityCurve::Hard.apply(0.25) < 0.25);
+        assert_eq!(VelocityCurve::Fixed(0.8).apply(0.1), 0.8);
+        assert_eq!(VelocityCurve::Fixed(1.5).apply(0.1), 1.0);
+    }
+
+    #[test]
+    fn notes_outside_the_filters_are_dropped() {
+        let transform = MidiTransform {
+            channels: 1 << 2,
+            low_key: 36,
+            high_key: 72,
+            min_velocity: 0.2,
+            max_velocity: 0.8,
+            ..MidiTransform::default()
+        };
+        assert_eq!(transform.process(note(2, 60, 0.5), true).len(), 1);
+        assert!(transform.process(note(3, 60, 0.5), true).is_empty());
+        assert!(transform.process(note(16, 60, 0.5), true).is_empty());
+        assert!(transform.process(note(2, 35, 0.5), true).is_empty());
+        assert!(transform.process(note(2, 73, 0.5), true).is_empty());
+        assert!(transform.process(note(2, 60, 0.1), true).is_empty());
+        assert!(transform.process(note(2, 60, 0.9), true).is_empty());
+
+        // Note offs end their note whatever their velocity.
+        let off = transform.process(note(2, 60, 0.0), false);
+        assert_eq!(off.as_slice(), &[(0, note(2, 60, 0.0))]);
+    }
+
+    #[test]
+    fn transposing_drops_keys_that_leave_the_keyboard() {
+        let transform = MidiTransform {
+            transpose: 12,
+            velocity_curve: VelocityCurve::Fixed(1.0),
+            ..MidiTransform::default()
+        };
+        assert_eq!(transform.process(note(0, 60, 0.5), true).as_slice(), &[(0, note(0, 72, 1.0))]);
+        assert!(transform.process(note(0, 120, 0.5), true).is_empty());
+    }
+
+    #[test]
+    fn zones_split_and_layer_the_keyboard() {
+        let transform = MidiTransform {
+            transpose: 1,
+            zones: vec![
+                KeyZone { low_key: 0, high_key: 59, transpose: -12 },
+                KeyZone { low_key: 60, high_key: 127, transpose: 0 },
+                KeyZone { low_key: 48, high_key: 71, transpose: 12 },
+            ],
+            ..MidiTransform::default()
+        };
+        assert_eq!(keys(&transform.process(note(0, 40, 0.5), true)), vec![(0, 29)]);
+        assert_eq!(keys(&transform.process(note(0, 50, 0.5), true)), vec![(0, 39), (2, 63)]);
+        assert_eq!(keys(&transform.process(note(0, 65, 0.5), true)), vec![(1, 66), (2, 78)]);
+        assert_eq!(keys(&transform.process(note(0, 100, 0.5), true)), vec![(1, 101)]);
+
+        // Zones are matched before transposing, so a key at the top of a
+        // zone stays in it.
+        assert_eq!(keys(&transform.process(note(0, 59, 0.5), true)), vec![(0, 48), (2, 72)]);
+    }
+}
@@ -25,7 +25,62 @@
 //! [`Rusty DAW Engine`]: https://github.com/RustyDAW/rusty-daw-engine
 //! [`CLAP`]: https://github.com/free-audio/clap
 
+pub mod aiff;
+pub mod amp_sim_plug;
+pub mod analysis;
+pub mod automation;
+pub mod automation_plug;
+pub mod capture_plug;
+pub mod channel_graph;
+pub mod collision;
+pub mod compressor;
+pub mod compressor_plug;
+pub mod de_esser;
+pub mod de_esser_plug;
+pub mod delay;
+pub mod delay_plug;
+pub mod denoise;
+pub mod disk_writer;
+pub mod drum_rack;
+pub mod drum_rack_plug;
+pub mod dsp;
+pub mod eq_plug;
+pub mod fader;
+pub mod fader_plug;
+pub mod fx_rack;
+pub mod graph_interface;
+pub mod graph_swap;
+pub mod harmonizer_plug;
+pub mod input_meter;
+pub mod instrument_rack;
+pub mod loudness_plug;
+pub mod macro_knob;
+pub mod metronome_plug;
+pub mod midi_capture_plug;
+pub mod midi_fx;
+pub mod midi_out;
+pub mod midi_track_plug;
+pub mod midi_transform;
+pub mod offline_render;
+pub mod param_state;
+pub mod record_plug;
+pub mod resample;
 pub mod resource_loader;
+#[cfg(test)]
+mod resource_loader_fuzz;
+pub mod reverb;
+pub mod reverb_plug;
 pub mod sample_browser_plug;
+pub mod sample_edit;
+pub mod scale;
+pub mod signal_gen_plug;
+pub mod silence;
 pub mod system_io;
+pub mod tap_tempo;
+pub mod tempo_bus;
+pub mod tempo_map;
+pub mod time_signature;
 pub mod timeline_track;
+pub mod tuner_plug;
+pub mod vocoder_plug;
+pub mod waveform;
@@ -0,0 +1,241 @@
+//! Renders the outputs of the engine to WAV files faster than realtime.
+//!
+//! The engine is activated without an audio device, and its audio thread is
+//! driven by a thread of its own that renders block after block as fast as it
+//! can and writes them to disk. Each stereo pair of outputs of the engine goes
+//! to a file of its own, so stems are rendered in a single pass.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use dropseed::DSEngineAudioThread;
+
+use super::dsp::{DenormalGuard, WhiteNoise};
+use crate::util::{WavSampleFormat, WavWriter};
+
+/// The number of output channels of the engine that go to each file.
+const FILE_CHANNELS: usize = 2;
+
+/// The engine is rendered this many frames at a time.
+const BLOCK_FRAMES: usize = 512;
+
+/// The sample rates a project can be exported at.
+pub const EXPORT_SAMPLE_RATES: [u32; 4] = [44_100, 48_000, 88_200, 96_000];
+
+/// The noise added before the samples are rounded to integers, which turns
+/// the distortion of rounding quiet parts into a steady, much less audible
+/// hiss.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportDither {
+    None,
+    /// Triangular noise of one step of the integer format, the usual choice
+    /// when the file isn't processed any further.
+    Triangular,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportOptions {
+    pub format: WavSampleFormat,
+    pub sample_rate: u32,
+    /// Ignored for floating point files, which don't need any.
+    pub dither: ExportDither,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: WavSampleFormat::Int24,
+            sample_rate: 48_000,
+            dither: ExportDither::Triangular,
+        }
+    }
+}
+
+/// A render that is running on its own thread.
+pub struct OfflineRender {
+    frames: u64,
+    rendered: Arc<AtomicU64>,
+    cancel: Arc<AtomicBool>,
+    join_handle: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl OfflineRender {
+    /// Start rendering `frames` frames of the outputs of the engine, the
+    /// first two outputs to the first path, the next two to the second, and
+    /// so on. The engine must have been activated at the sample rate of the
+    /// options with two outputs for every path, and its transport should be
+    /// playing from where the render starts.
+    pub fn spawn(
+        mut engine_audio_thread: DSEngineAudioThread,
+        frames: u64,
+        paths: &[PathBuf],
+        options: ExportOptions,
+    ) -> std::io::Result<Self> {
+        let mut wavs = paths
+            .iter()
+            .map(|path| WavWriter::create_with_format(path, options.sample_rate, options.format))
+            .collect::<std::io::Result<Vec<WavWriter>>>()?;
+        let out_channels = FILE_CHANNELS * wavs.len();
+
+        let rendered = Arc::new(AtomicU64::new(0));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let rendered_clone = Arc::clone(&rendered);
+        let cancel_clone = Arc::clone(&cancel);
+
+        let join_handle =
+            std::thread::Builder::new().name("offline-render".into()).spawn(move || {
+                let _denormal_guard = DenormalGuard::enable();
+
+                let mut dither = Dither::new(&options);
+                let mut block = vec![0.0; BLOCK_FRAMES * out_channels];
+                let mut file_block = vec![0.0; BLOCK_FRAMES * FILE_CHANNELS];
+                let mut done = 0;
+                while done < frames && !cancel_clone.load(Ordering::Relaxed) {
+                    let block_frames = (frames - done).min(BLOCK_FRAMES as u64) as usize;
+                    let block = &mut block[0..block_frames * out_channels];
+                    let file_block = &mut file_block[0..block_frames * FILE_CHANNELS];
+
+                    engine_audio_thread.process_cpal_interleaved_output_only(out_channels, block);
+                    for (file, wav) in wavs.iter_mut().enumerate() {
+                        // Pick the pair of the file out of the frames of all
+                        // the outputs.
+                        let first = file * FILE_CHANNELS;
+                        for (out, frame) in file_block
+                            .chunks_exact_mut(FILE_CHANNELS)
+                            .zip(block.chunks_exact(out_channels))
+                        {
+                            out.copy_from_slice(&frame[first..first + FILE_CHANNELS]);
+                        }
+                        dither.apply(file_block);
+                        wav.write_interleaved(file_block)?;
+                    }
+
+                    done += block_frames as u64;
+                    rendered_clone.store(done, Ordering::Relaxed);
+                }
+                wavs.into_iter().try_for_each(WavWriter::finish)
+            })?;
+
+        Ok(Self { frames, rendered, cancel, join_handle: Some(join_handle) })
+    }
+
+    /// How much of the render is done, from 0.0 to 1.0.
+    pub fn progress(&self) -> f32 {
+        if self.frames == 0 {
+            return 1.0;
+        }
+        (self.rendered.load(Ordering::Relaxed) as f64 / self.frames as f64) as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.join_handle.as_ref().map_or(true, |join_handle| join_handle.is_finished())
+    }
+
+    /// Stop rendering. The file keeps what was rendered so far.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Wait for the render to finish, and return whether the file was written.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        match self.join_handle.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => {
+                Err(std::io::Error::new(std::io::ErrorKind::Other, "Render thread panicked"))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for OfflineRender {
+    fn drop(&mut self) {
+        self.cancel();
+        if let Some(join_handle) = self.join_handle.take() {
+            if join_handle.join().is_err() {
+                log::error!("Offline render thread panicked");
+            }
+        }
+    }
+}
+
+/// Adds triangular noise, the sum of two uniform noises of one step each.
+struct Dither {
+    noise: WhiteNoise,
+    /// Half of one step of the integer format, or 0.0 for no dither.
+    amplitude: f32,
+}
+
+impl Dither {
+    fn new(options: &ExportOptions) -> Self {
+        let amplitude = match (options.dither, options.format.int_max()) {
+            (ExportDither::Triangular, Some(int_max)) => 0.5 / int_max,
+            _ => 0.0,
+        };
+        Self { noise: WhiteNoise::new(0x5EED), amplitude }
+    }
+
+    fn apply(&mut self, samples: &mut [f32]) {
+        if self.amplitude == 0.0 {
+            return;
+        }
+        for s in samples.iter_mut() {
+            *s += (self.noise.next() + self.noise.next()) * self.amplitude;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(format: WavSampleFormat, dither: ExportDither) -> ExportOptions {
+        ExportOptions { format, dither, ..ExportOptions::default() }
+    }
+
+    #[test]
+    fn dither_is_within_one_step_of_the_integer_format() {
+        for format in [WavSampleFormat::Int16, WavSampleFormat::Int24] {
+            let step = 1.0 / format.int_max().unwrap();
+            let mut dither = Dither::new(&options(format, ExportDither::Triangular));
+            let mut samples = vec![0.0; 48_000];
+            dither.apply(&mut samples);
+
+            assert!(samples.iter().all(|s| s.abs() <= step));
+            let mean = samples.iter().sum::<f32>() / samples.len() as f32;
+            assert!(mean.abs() < step * 0.01, "{:?} has an offset of {}", format, mean / step);
+            // The sum of two uniform noises is more often near zero than
+            // near the edges.
+            let near_zero = samples.iter().filter(|s| s.abs() < step * 0.5).count();
+            assert!(near_zero > samples.len() * 7 / 10);
+        }
+    }
+
+    #[test]
+    fn floating_point_and_undithered_files_are_left_alone() {
+        let samples: Vec<f32> = (0..1_000).map(|i| (i as f32 * 0.01).sin()).collect();
+        for options in [
+            options(WavSampleFormat::Float32, ExportDither::Triangular),
+            options(WavSampleFormat::Int16, ExportDither::None),
+        ] {
+            let mut dithered = samples.clone();
+            Dither::new(&options).apply(&mut dithered);
+            assert_eq!(dithered, samples);
+        }
+    }
+
+    #[test]
+    fn dither_keeps_levels_below_one_step() {
+        // A quarter of a step is rounded away without dither, but dithered
+        // it is kept on average.
+        let step = 1.0 / WavSampleFormat::Int16.int_max().unwrap();
+        let mut samples = vec![step * 0.25; 48_000];
+        Dither::new(&options(WavSampleFormat::Int16, ExportDither::Triangular)).apply(&mut samples);
+
+        let rounded = samples.iter().map(|s| (s / step).round()).sum::<f32>();
+        let level = rounded / samples.len() as f32;
+        assert!((level - 0.25).abs() < 0.02, "kept a level of {} steps", level);
+    }
+}
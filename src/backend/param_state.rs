@@ -0,0 +1,99 @@
+//! The state the internal effects save with the project: the values of their
+//! parameters by ID, as little-endian `f32`s.
+
+use std::any::Any;
+
+use super::compressor_plug::CompressorPlugHandle;
+use super::delay_plug::DelayPlugHandle;
+use super::reverb_plug::ReverbPlugHandle;
+
+/// The version of the saved state. A state of another version is ignored.
+pub const PARAM_STATE_VERSION: u32 = 1;
+
+/// The handle of an internal effect whose parameters are saved with the
+/// project.
+pub trait ParamState {
+    /// The values of the parameters by ID, as the audio thread last set them.
+    fn param_values(&self) -> Vec<f32>;
+
+    /// Move the parameters to `values`, by ID.
+    fn set_param_values(&mut self, values: &[f32]);
+}
+
+pub fn encode(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+/// Returns `None` if the state is of another version or doesn't have exactly
+/// `len` finite values.
+pub fn decode(version: u32, bytes: &[u8], len: usize) -> Option<Vec<f32>> {
+    if version != PARAM_STATE_VERSION || bytes.len() != len * 4 {
+        return None;
+    }
+    let values: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|value| f32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+        .collect();
+    if values.iter().all(|value| value.is_finite()) {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+/// The internal handle of a plugin as a `ParamState`, or `None` if the plugin
+/// doesn't save its parameters this way.
+pub fn param_state_mut(internal: &mut (dyn Any + Send)) -> Option<&mut dyn ParamState> {
+    if internal.is::<CompressorPlugHandle>() {
+        internal.downcast_mut::<CompressorPlugHandle>().map(|h| h as &mut dyn ParamState)
+    } else if internal.is::<DelayPlugHandle>() {
+        internal.downcast_mut::<DelayPlugHandle>().map(|h| h as &mut dyn ParamState)
+    } else if internal.is::<ReverbPlugHandle>() {
+        internal.downcast_mut::<ReverbPlugHandle>().map(|h| h as &mut dyn ParamState)
+    } else {
+        None
+    }
+}
+
+/// The latest state of an internal effect, as its version and bytes.
+pub fn save_state(internal: &mut (dyn Any + Send)) -> Option<(u32, Vec<u8>)> {
+    param_state_mut(internal).map(|state| (PARAM_STATE_VERSION, encode(&state.param_values())))
+}
+
+/// Give an internal effect back the state it saved. Returns `false` if it
+/// isn't an internal effect or the state doesn't fit it.
+pub fn load_state(internal: &mut (dyn Any + Send), version: u32, bytes: &[u8]) -> bool {
+    let state = match param_state_mut(internal) {
+        Some(state) => state,
+        None => return false,
+    };
+    match decode(version, bytes, state.param_values().len()) {
+        Some(values) => {
+            state.set_param_values(&values);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_survive_a_round_trip() {
+        let values = [-18.0, 4.0, 0.1, 1_000.0, 6.0, f32::MIN_POSITIVE];
+        let bytes = encode(&values);
+        assert_eq!(bytes.len(), values.len() * 4);
+        assert_eq!(decode(PARAM_STATE_VERSION, &bytes, values.len()).unwrap(), values);
+    }
+
+    #[test]
+    fn states_that_dont_fit_are_ignored() {
+        let bytes = encode(&[0.5, 0.25]);
+        assert_eq!(decode(PARAM_STATE_VERSION + 1, &bytes, 2), None);
+        assert_eq!(decode(PARAM_STATE_VERSION, &bytes, 3), None);
+        assert_eq!(decode(PARAM_STATE_VERSION, &bytes[1..], 2), None);
+        assert_eq!(decode(PARAM_STATE_VERSION, &encode(&[0.5, f32::NAN]), 2), None);
+    }
+}
@@ -0,0 +1,235 @@
+//! Records the armed channels while the transport plays. Each armed channel
+//! plays its `RecordInput` into an input port of its own, see
+//! `channel_graph`. The input is pushed into the ring buffers of a
+//! `DiskWriterPool`, which write it to a file for each channel.
+
+use basedrop::{Owned, Shared};
+use dropseed::plugin::ext::audio_ports::{
+    AudioPortInfo, AudioPortType, MainPortsLayout, PluginAudioPortsExt,
+};
+use dropseed::plugin::{
+    buffer::EventBuffer, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use super::disk_writer::TrackRecorder;
+
+pub static RECORD_PLUG_RDN: &str = "app.meadowlark.record";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// The most channels that are recorded at once, one for each input port.
+pub const MAX_RECORDED_CHANNELS: usize = 16;
+
+pub struct RecordPlugFactory;
+
+impl PluginFactory for RecordPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: RECORD_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Record".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(RecordPlugMainThread {}))
+    }
+}
+
+pub struct RecordPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    from_audio_thread_rx: Consumer<u64>,
+
+    coll_handle: basedrop::Handle,
+    sample_rate: SampleRate,
+    max_frames: usize,
+    /// The position of the playhead in frames when the first frame of the
+    /// current recording was recorded.
+    start_frame: Option<u64>,
+}
+
+impl RecordPlugHandle {
+    /// Start recording into the given recorders, one for each armed channel
+    /// in the order of the channels, i.e. of the input ports. Nothing is
+    /// recorded until the transport plays. Any recording that is
+    /// in progress is stopped.
+    pub fn start(&mut self, recorders: Vec<TrackRecorder>) {
+        self.start_frame = None;
+        self.send(ProcessMsg::Start(Owned::new(&self.coll_handle, recorders)));
+    }
+
+    /// Stop recording. The rest of the input is written to the files by the
+    /// `DiskWriterPool`, which has to be asked to stop the tracks.
+    pub fn stop(&mut self) {
+        self.send(ProcessMsg::Stop);
+    }
+
+    /// The position of the playhead in frames when the current recording
+    /// started, once it has. That is where the recorded clips go on the
+    /// timeline.
+    pub fn start_frame(&mut self) -> Option<u64> {
+        while let Ok(frame) = self.from_audio_thread_rx.pop() {
+            self.start_frame = Some(frame);
+        }
+        self.start_frame
+    }
+
+    pub fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// The most frames the audio thread records at once, for sizing the ring
+    /// buffers of the recorders.
+    pub fn max_frames(&self) -> usize {
+        self.max_frames
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Record plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    Start(Owned<Vec<TrackRecorder>>),
+    Stop,
+}
+
+pub struct RecordPlugMainThread {}
+
+impl PluginMainThread for RecordPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let (to_handle_tx, from_audio_thread_rx) = RingBuffer::<u64>::new(MSG_BUFFER_SIZE);
+        let to_handle_tx = Owned::new(coll_handle, to_handle_tx);
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(RecordPlugAudioThread {
+                from_handle_rx,
+                to_handle_tx,
+                recorders: None,
+                started: false,
+            }),
+            internal_handle: Some(Box::new(RecordPlugHandle {
+                to_audio_thread_tx,
+                from_audio_thread_rx,
+                coll_handle: coll_handle.clone(),
+                sample_rate,
+                max_frames: max_frames as usize,
+                start_frame: None,
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<PluginAudioPortsExt, String> {
+        Ok(PluginAudioPortsExt {
+            inputs: (0..MAX_RECORDED_CHANNELS as u32)
+                .map(|stable_id| AudioPortInfo {
+                    stable_id,
+                    channels: 2,
+                    port_type: AudioPortType::Stereo,
+                    display_name: None,
+                })
+                .collect(),
+            outputs: vec![AudioPortInfo {
+                stable_id: 0,
+                channels: 2,
+                port_type: AudioPortType::Stereo,
+                display_name: None,
+            }],
+            main_ports_layout: MainPortsLayout::InOut,
+        })
+    }
+}
+
+pub struct RecordPlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+    to_handle_tx: Owned<Producer<u64>>,
+
+    /// Dropped by the collector, so the ring buffers are never freed here.
+    recorders: Option<Owned<Vec<TrackRecorder>>>,
+    /// Set once the first frame of the current recording was recorded.
+    started: bool,
+}
+
+impl RecordPlugAudioThread {
+    fn poll(&mut self) {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::Start(recorders) => {
+                    self.recorders = Some(recorders);
+                    self.started = false;
+                }
+                ProcessMsg::Stop => self.recorders = None,
+            }
+        }
+    }
+}
+
+impl PluginAudioThread for RecordPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {}
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        _in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll();
+
+        let frames = proc_info.frames;
+
+        // The output isn't connected to anything.
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+        out_l[0..frames].fill(0.0);
+        out_r[0..frames].fill(0.0);
+
+        if let Some(recorders) = &mut self.recorders {
+            if proc_info.transport.is_playing() {
+                if !self.started {
+                    self.started = true;
+                    if let Err(e) = self.to_handle_tx.push(proc_info.transport.playhead_frame()) {
+                        log::error!("Record plugin failed to send the start frame: {}", e);
+                    }
+                }
+                for (recorder, input) in recorders.iter_mut().zip(buffers.audio_in.iter()) {
+                    let (in_l, in_r) = input.stereo_f32().unwrap();
+                    // Dropped blocks are counted by the pool.
+                    recorder.write(&in_l[0..frames], &in_r[0..frames]);
+                }
+            }
+        }
+
+        ProcessStatus::Continue
+    }
+}
@@ -0,0 +1,183 @@
+//! Offline sample rate conversion with a windowed sinc filter, for resources
+//! that were recorded at a different sample rate than the project runs at.
+
+use pcm_loader::{PcmRAM, PcmRAMType};
+use std::f64::consts::PI;
+
+/// The number of zero crossings of the sinc on each side of a sample. More
+/// makes the filter steeper, at the cost of speed.
+const SINC_ZERO_CROSSINGS: f64 = 16.0;
+
+/// Where the filter cuts off, as a fraction of the lower of the two Nyquist
+/// frequencies. Leaves room for the filter to roll off before aliasing starts.
+const CUTOFF: f64 = 0.95;
+
+/// The number of points per input sample at which the filter is tabulated.
+/// The filter between two points is interpolated linearly, which is off by
+/// less than -100 dB.
+const TABLE_PHASES: usize = 1024;
+
+/// Convert a resource to the given sample rate. Every channel is kept, so a
+/// surround file stays in its layout.
+pub fn resample_pcm(pcm: &PcmRAM, sample_rate: u32) -> PcmRAM {
+    let len = pcm.len_frames() as usize;
+    let from = f64::from(pcm.sample_rate());
+    let to = f64::from(sample_rate);
+
+    let channels = (0..pcm.channels())
+        .map(|channel| {
+            let mut samples = vec![0.0; len];
+            // The channel exists, so this can't fail.
+            let _ = pcm.fill_channel_f32(channel, 0, &mut samples);
+            resample(&samples, from, to)
+        })
+        .collect();
+    PcmRAM::new(PcmRAMType::F32(channels), sample_rate)
+}
+
+/// Convert one channel from the sample rate `from` to the sample rate `to`.
+pub fn resample(input: &[f32], from: f64, to: f64) -> Vec<f32> {
+    if input.is_empty() || from <= 0.0 || to <= 0.0 || from == to {
+        return input.to_vec();
+    }
+
+    let ratio = to / from;
+    let out_len = (input.len() as f64 * ratio).round() as usize;
+    // When going down, everything above the new Nyquist frequency has to go
+    // or it folds back as aliasing.
+    let cutoff = CUTOFF * ratio.min(1.0);
+    let half_width = SINC_ZERO_CROSSINGS / cutoff;
+    let filter = FilterTable::new(cutoff, half_width);
+    let last_frame = input.len() - 1;
+
+    (0..out_len)
+        .map(|i| {
+            let center = i as f64 / ratio;
+            let first = (center - half_width).ceil().max(0.0) as usize;
+            let last = ((center + half_width).floor() as usize).min(last_frame);
+
+            let mut sum = 0.0;
+            for (j, sample) in input.iter().enumerate().take(last + 1).skip(first) {
+                sum += f64::from(*sample) * filter.at(j as f64 - center);
+            }
+            sum as f32
+        })
+        .collect()
+}
+
+/// The windowed sinc, from its center to the edge of the window, so that
+/// converting a resource doesn't take a `sin()` and two `cos()` per tap.
+struct FilterTable {
+    points: Vec<f64>,
+}
+
+impl FilterTable {
+    fn new(cutoff: f64, half_width: f64) -> Self {
+        // One more point past the edge, which is zero, so `at()` never reads
+        // out of bounds.
+        let len = (half_width * TABLE_PHASES as f64).ceil() as usize + 2;
+        let points = (0..len)
+            .map(|i| {
+                let x = i as f64 / TABLE_PHASES as f64;
+                cutoff * sinc(cutoff * x) * blackman(x / half_width)
+            })
+            .collect();
+        Self { points }
+    }
+
+    /// The filter at `x` samples from its center.
+    fn at(&self, x: f64) -> f64 {
+        let pos = x.abs() * TABLE_PHASES as f64;
+        let i = pos as usize;
+        if i + 1 >= self.points.len() {
+            return 0.0;
+        }
+        let frac = pos - i as f64;
+        self.points[i] + (self.points[i + 1] - self.points[i]) * frac
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1.0e-9 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// A Blackman window from -1.0 to 1.0.
+fn blackman(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, sample_rate: f64, len: usize) -> Vec<f32> {
+        (0..len).map(|i| (2.0 * PI * freq * i as f64 / sample_rate).sin() as f32).collect()
+    }
+
+    /// The RMS level of the middle half of a signal, away from the edges where
+    /// the filter runs out of input.
+    fn rms(signal: &[f32]) -> f64 {
+        let middle = &signal[signal.len() / 4..signal.len() * 3 / 4];
+        (middle.iter().map(|s| f64::from(*s).powi(2)).sum::<f64>() / middle.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn output_is_as_long_as_the_input_at_the_new_rate() {
+        let input = vec![0.0; 44_100];
+        assert_eq!(resample(&input, 44_100.0, 48_000.0).len(), 48_000);
+        assert_eq!(resample(&input, 44_100.0, 22_050.0).len(), 22_050);
+        assert_eq!(resample(&input[..1_000], 48_000.0, 48_000.0).len(), 1_000);
+        assert!(resample(&[], 44_100.0, 48_000.0).is_empty());
+    }
+
+    #[test]
+    fn a_sine_in_the_passband_is_kept() {
+        let input = sine(1_000.0, 44_100.0, 8_192);
+        let output = resample(&input, 44_100.0, 48_000.0);
+
+        let expected = sine(1_000.0, 48_000.0, output.len());
+        let start = output.len() / 4;
+        let end = output.len() * 3 / 4;
+        let max_error = output[start..end]
+            .iter()
+            .zip(&expected[start..end])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(max_error < 1.0e-3, "off by up to {}", max_error);
+    }
+
+    #[test]
+    fn a_tone_above_the_new_nyquist_frequency_is_removed_when_going_down() {
+        // 15 kHz is above the Nyquist frequency of 22.05 kHz / 2.
+        let input = sine(15_000.0, 44_100.0, 8_192);
+        let output = resample(&input, 44_100.0, 22_050.0);
+
+        let level_db = 20.0 * (rms(&output) / rms(&input)).log10();
+        assert!(level_db < -60.0, "only attenuated to {} dB", level_db);
+    }
+
+    #[test]
+    fn the_table_is_within_100_db_of_the_filter() {
+        let cutoff = CUTOFF * 0.5;
+        let half_width = SINC_ZERO_CROSSINGS / cutoff;
+        let table = FilterTable::new(cutoff, half_width);
+
+        let steps = 100_000;
+        let max_error = (0..steps)
+            .map(|i| {
+                let x = half_width * i as f64 / steps as f64;
+                let exact = cutoff * sinc(cutoff * x) * blackman(x / half_width);
+                (table.at(x) - exact).abs()
+            })
+            .fold(0.0, f64::max);
+        assert!(20.0 * (max_error / cutoff).log10() < -100.0, "off by {}", max_error);
+    }
+}
@@ -1,10 +1,27 @@
 use basedrop::{Collector, Shared};
 use meadowlark_core_types::time::SampleRate;
-use pcm_loader::{error::PcmLoadError, PcmLoader, PcmRAM, PcmRAMType, ResampleQuality};
-use std::path::PathBuf;
+use pcm_loader::{error::PcmLoadError, PcmLoader, PcmRAM, PcmRAMType};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use super::aiff::{decode_aiff, AiffError};
+use super::resample::resample_pcm;
+use super::waveform::{load_peak_file, save_peak_file, PeakBuilder, WaveformPeaks};
 use crate::util::TwoXHashMap;
 
+/// How resources are converted to the sample rate of the project.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResampleQuality {
+    /// Fast, but dulls the highs and lets some aliasing through. Good enough
+    /// for previews.
+    Linear,
+    /// A windowed sinc filter. Slower to load, but transparent.
+    #[default]
+    Sinc,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Hash, Eq)]
 pub struct PcmKey {
     pub path: PathBuf,
@@ -19,17 +36,55 @@ pub struct PcmKey {
      */
 }
 
+/// The largest a resource can be once it is decoded (and resampled), so a
+/// corrupt or malicious file can't take all of the memory.
+pub const DEFAULT_MAX_PCM_BYTES: usize = 1024 * 1024 * 1024;
+
+/// How many frames of a resource are read at a time to compute its peaks.
+const PEAK_CHUNK_FRAMES: usize = 1 << 16;
+
+/// How often unused resources are collected by default.
+pub const DEFAULT_COLLECT_INTERVAL: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CollectorStats {
+    /// The number of resources currently loaded, including ones that are no
+    /// longer used but have not been collected yet.
+    pub loaded_resources: usize,
+    /// The number of allocations the collector is still responsible for.
+    pub outstanding_allocations: usize,
+    /// How long the last collection took.
+    pub last_collection_duration: Duration,
+}
+
 pub struct ResourceLoader {
     pcm_loader: PcmLoader,
 
     loaded: TwoXHashMap<PcmKey, Shared<PcmRAM>>,
 
+    /// The left and right waveform peaks of the loaded resources that were
+    /// asked for.
+    peaks: TwoXHashMap<PcmKey, Arc<[WaveformPeaks; 2]>>,
+
+    /// The resources whose peaks are being computed on a worker thread, and
+    /// where the workers send them with the sample rate they were computed
+    /// at. See `request_waveform_peaks()`.
+    pending_peaks: TwoXHashMap<PcmKey, ()>,
+    peaks_tx: Sender<(PcmKey, u32, [WaveformPeaks; 2])>,
+    peaks_rx: Receiver<(PcmKey, u32, [WaveformPeaks; 2])>,
+
     /// The resource to send when the resource could not be loaded.
     empty_pcm: Shared<PcmRAM>,
 
     project_sr: SampleRate,
 
+    max_pcm_bytes: usize,
+
     collector: Collector,
+
+    collect_interval: Duration,
+    last_collect: Instant,
+    stats: CollectorStats,
 }
 
 impl ResourceLoader {
@@ -41,12 +96,22 @@ impl ResourceLoader {
             PcmRAM::new(PcmRAMType::F32(vec![Vec::new()]), project_sample_rate.as_u32()),
         );
 
+        let (peaks_tx, peaks_rx) = mpsc::channel();
+
         Self {
             pcm_loader: PcmLoader::new(),
             loaded: Default::default(),
+            peaks: Default::default(),
+            pending_peaks: Default::default(),
+            peaks_tx,
+            peaks_rx,
             empty_pcm,
             project_sr: project_sample_rate,
+            max_pcm_bytes: DEFAULT_MAX_PCM_BYTES,
             collector,
+            collect_interval: DEFAULT_COLLECT_INTERVAL,
+            last_collect: Instant::now(),
+            stats: CollectorStats::default(),
         }
     }
 
@@ -71,11 +136,37 @@ impl ResourceLoader {
             return Ok(Shared::clone(pcm));
         }
 
-        let target_sample_rate =
-            if key.resample_to_project_sr { Some(self.project_sr.as_u32()) } else { None };
-
-        let pcm =
-            self.pcm_loader.load(&key.path, target_sample_rate, key.resample_quality, None)?;
+        let pcm = if is_aiff(&key.path) {
+            // There is no linear resampler for these, so they are always
+            // resampled with the sinc filter.
+            let pcm = load_aiff(&key.path, self.max_pcm_bytes)?;
+            self.resample_to_project_sr(key, pcm)?
+        } else {
+            match key.resample_quality {
+                ResampleQuality::Linear => {
+                    let target_sample_rate = if key.resample_to_project_sr {
+                        Some(self.project_sr.as_u32())
+                    } else {
+                        None
+                    };
+                    self.pcm_loader.load(
+                        &key.path,
+                        target_sample_rate,
+                        pcm_loader::ResampleQuality::Linear,
+                        Some(self.max_pcm_bytes),
+                    )?
+                }
+                ResampleQuality::Sinc => {
+                    let pcm = self.pcm_loader.load(
+                        &key.path,
+                        None,
+                        pcm_loader::ResampleQuality::Linear,
+                        Some(self.max_pcm_bytes),
+                    )?;
+                    self.resample_to_project_sr(key, pcm)?
+                }
+            }
+        };
 
         let pcm = Shared::new(&self.collector.handle(), pcm);
 
@@ -86,12 +177,238 @@ impl ResourceLoader {
         Ok(pcm)
     }
 
+    /// Resample a resource with the sinc filter if the key asks for the
+    /// sample rate of the project.
+    fn resample_to_project_sr(&self, key: &PcmKey, pcm: PcmRAM) -> Result<PcmRAM, PcmLoadError> {
+        let project_sr = self.project_sr.as_u32();
+        if !key.resample_to_project_sr || pcm.sample_rate() == project_sr {
+            return Ok(pcm);
+        }
+
+        // The result is f32 with the same channels.
+        let ratio = f64::from(project_sr) / f64::from(pcm.sample_rate().max(1));
+        let bytes = (pcm.len_frames() as f64 * ratio).ceil() * pcm.channels() as f64 * 4.0;
+        if bytes > self.max_pcm_bytes as f64 {
+            return Err(PcmLoadError::FileTooLarge(self.max_pcm_bytes));
+        }
+
+        log::debug!("Resampling PCM file from {} to {}", pcm.sample_rate(), project_sr);
+        Ok(resample_pcm(&pcm, project_sr))
+    }
+
+    /// The left and right waveform peaks of a resource, for drawing it.
+    ///
+    /// The peaks are cached in a file next to the resource, so they only have
+    /// to be computed the first time a file is drawn at a given sample rate.
+    /// Returns `None` if the resource could not be loaded.
+    ///
+    /// This blocks until the peaks are computed. Prefer
+    /// `request_waveform_peaks()` for anything that can be drawn without them
+    /// at first.
+    pub fn waveform_peaks(&mut self, key: &PcmKey) -> Option<Arc<[WaveformPeaks; 2]>> {
+        if let Some(peaks) = self.peaks.get(key) {
+            return Some(Arc::clone(peaks));
+        }
+
+        let (pcm, res) = self.load_pcm(key);
+        res.ok()?;
+        let peaks = Arc::new(compute_peaks(&key.path, &pcm));
+        self.peaks.insert(key.to_owned(), Arc::clone(&peaks));
+        Some(peaks)
+    }
+
+    /// Like `waveform_peaks()`, but the peaks that aren't known yet are
+    /// computed on a worker thread, and this returns `None` until
+    /// `poll_waveform_peaks()` received them.
+    pub fn request_waveform_peaks(&mut self, key: &PcmKey) -> Option<Arc<[WaveformPeaks; 2]>> {
+        if let Some(peaks) = self.peaks.get(key) {
+            return Some(Arc::clone(peaks));
+        }
+        if self.pending_peaks.contains_key(key) {
+            return None;
+        }
+
+        let (pcm, res) = self.load_pcm(key);
+        res.ok()?;
+        self.pending_peaks.insert(key.to_owned(), ());
+
+        let key = key.to_owned();
+        let peaks_tx = self.peaks_tx.clone();
+        let sample_rate = self.project_sr.as_u32();
+        let spawned = std::thread::Builder::new().name("waveform-peaks".into()).spawn(move || {
+            let peaks = compute_peaks(&key.path, &pcm);
+            // The loader is gone if this fails.
+            let _ = peaks_tx.send((key, sample_rate, peaks));
+        });
+        if let Err(e) = spawned {
+            log::error!("Failed to spawn the waveform peaks thread: {}", e);
+        }
+        None
+    }
+
+    /// The peaks of a resource if they are known, without computing them.
+    pub fn cached_waveform_peaks(&self, key: &PcmKey) -> Option<&Arc<[WaveformPeaks; 2]>> {
+        self.peaks.get(key)
+    }
+
+    /// Take the peaks the worker threads computed since the last call.
+    /// Returns `true` if there were any, i.e. the waveforms need to be drawn
+    /// again.
+    pub fn poll_waveform_peaks(&mut self) -> bool {
+        let mut received = false;
+        while let Ok((key, sample_rate, peaks)) = self.peaks_rx.try_recv() {
+            self.pending_peaks.remove(&key);
+            // Peaks of resources loaded at an old sample rate are stale.
+            if sample_rate == self.project_sr.as_u32() {
+                self.peaks.insert(key, Arc::new(peaks));
+                received = true;
+            }
+        }
+        received
+    }
+
+    /// The largest a resource can be once it is decoded, in bytes. Loading a
+    /// larger one fails.
+    pub fn set_max_pcm_bytes(&mut self, max_bytes: usize) {
+        self.max_pcm_bytes = max_bytes;
+    }
+
+    pub fn max_pcm_bytes(&self) -> usize {
+        self.max_pcm_bytes
+    }
+
+    /// A handle to the collector that frees what the audio thread lets go of.
+    pub fn coll_handle(&self) -> basedrop::Handle {
+        self.collector.handle()
+    }
+
+    /// The sample rate that resources are resampled to.
+    pub fn project_sr(&self) -> SampleRate {
+        self.project_sr
+    }
+
+    /// Change the sample rate that resources are resampled to, i.e. after the
+    /// audio device changed. Resources that were loaded at the old sample rate
+    /// are loaded again the next time they are asked for.
+    pub fn set_project_sr(&mut self, project_sample_rate: SampleRate) {
+        if project_sample_rate == self.project_sr {
+            return;
+        }
+        self.project_sr = project_sample_rate;
+        self.loaded.clear();
+        self.peaks.clear();
+        self.pending_peaks.clear();
+        self.empty_pcm = Shared::new(
+            &self.collector.handle(),
+            PcmRAM::new(PcmRAMType::F32(vec![Vec::new()]), project_sample_rate.as_u32()),
+        );
+    }
+
     /// Drop all of the loaded resources that are no longer being used.
+    ///
+    /// This can get expensive when a lot of resources are loaded, so prefer
+    /// `collect_if_due()` unless resources need to be freed right away (i.e.
+    /// after closing a large project).
     pub fn collect(&mut self) {
+        let start = Instant::now();
+
         // If no other extant Shared pointers to the resource exists, then
         // remove that entry.
         self.loaded.retain(|_, pcm| Shared::get_mut(pcm).is_none());
+        let loaded = &self.loaded;
+        self.peaks.retain(|key, _| loaded.contains_key(key));
 
         self.collector.collect();
+
+        self.last_collect = Instant::now();
+        self.stats = CollectorStats {
+            loaded_resources: self.loaded.len(),
+            outstanding_allocations: self.collector.alloc_count(),
+            last_collection_duration: self.last_collect - start,
+        };
+    }
+
+    /// Call `collect()` if at least `collect_interval()` has passed since the
+    /// last collection. Returns `true` if a collection happened.
+    pub fn collect_if_due(&mut self) -> bool {
+        if self.last_collect.elapsed() >= self.collect_interval {
+            self.collect();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn set_collect_interval(&mut self, interval: Duration) {
+        self.collect_interval = interval;
+    }
+
+    pub fn collect_interval(&self) -> Duration {
+        self.collect_interval
+    }
+
+    /// Statistics from the last collection.
+    pub fn stats(&self) -> CollectorStats {
+        self.stats
+    }
+}
+
+fn is_aiff(path: &Path) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+    ["aif", "aiff", "aifc"].iter().any(|aiff| extension.eq_ignore_ascii_case(aiff))
+}
+
+/// Decode an AIFF file, which the PCM loader doesn't read, at its own sample
+/// rate.
+fn load_aiff(path: &Path, max_bytes: usize) -> Result<PcmRAM, PcmLoadError> {
+    // A file of 64 bit samples is twice as large as it is once decoded.
+    let len = std::fs::metadata(path).map_err(PcmLoadError::PathNotFound)?.len();
+    if len > (max_bytes as u64).saturating_mul(2).saturating_add(1 << 20) {
+        return Err(PcmLoadError::FileTooLarge(max_bytes));
+    }
+    let bytes = std::fs::read(path).map_err(PcmLoadError::PathNotFound)?;
+
+    let aiff = decode_aiff(&bytes, max_bytes).map_err(|e| {
+        log::error!("Could not decode {:?}: {}", path, e);
+        match e {
+            AiffError::TooLarge(max_bytes) => PcmLoadError::FileTooLarge(max_bytes),
+            AiffError::NoChannels => PcmLoadError::NoChannelsFound,
+            _ => PcmLoadError::UnkownFormat,
+        }
+    })?;
+    Ok(PcmRAM::new(PcmRAMType::F32(aiff.channels), aiff.sample_rate))
+}
+
+/// The left and right peaks of a resource, read from its peak file if that is
+/// up to date, or else computed a chunk at a time and saved to it.
+fn compute_peaks(path: &Path, pcm: &PcmRAM) -> [WaveformPeaks; 2] {
+    let sample_rate = pcm.sample_rate();
+    let cached = match load_peak_file(path, sample_rate) {
+        Ok(cached) => cached,
+        Err(e) => {
+            log::warn!("Could not read the peak file of {:?}: {}", path, e);
+            None
+        }
+    };
+    if let Some(peaks) = cached.and_then(|channels| <[WaveformPeaks; 2]>::try_from(channels).ok()) {
+        return peaks;
+    }
+
+    log::debug!("Computing waveform peaks of {:?}", path);
+    let len = pcm.len_frames() as usize;
+    let mut left = vec![0.0; PEAK_CHUNK_FRAMES.min(len)];
+    let mut right = vec![0.0; PEAK_CHUNK_FRAMES.min(len)];
+    let (mut left_peaks, mut right_peaks) = (PeakBuilder::default(), PeakBuilder::default());
+    for start in (0..len).step_by(PEAK_CHUNK_FRAMES) {
+        let frames = PEAK_CHUNK_FRAMES.min(len - start);
+        pcm.fill_stereo_f32(start, &mut left[..frames], &mut right[..frames]);
+        left_peaks.push(&left[..frames]);
+        right_peaks.push(&right[..frames]);
+    }
+    let peaks = [left_peaks.finish(), right_peaks.finish()];
+
+    if let Err(e) = save_peak_file(path, sample_rate, &peaks) {
+        log::warn!("Could not write the peak file of {:?}: {}", path, e);
     }
+    peaks
 }
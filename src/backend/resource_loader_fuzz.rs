@@ -0,0 +1,176 @@
+//! Feeds corrupt audio and peak files to the resource loader. Whatever the
+//! input, loading has to either fail with an error or give a resource within
+//! the size limit, without panicking.
+//!
+//! These run a fixed number of mutations with the tests. The targets in
+//! `fuzz/` do the same under `cargo fuzz`, for longer runs.
+
+use meadowlark_core_types::time::SampleRate;
+use std::path::{Path, PathBuf};
+
+use super::aiff::encode_aiff;
+use super::resource_loader::{PcmKey, ResampleQuality, ResourceLoader};
+use super::waveform::{load_peak_file, peak_file_path};
+use crate::util::{fuzz_iterations, write_wav_f32, Mutator};
+
+const SAMPLE_RATE: u32 = 44_100;
+const PROJECT_SR: f64 = 48_000.0;
+const SEED_FRAMES: usize = 4_096;
+
+/// Numbers that are likely to be out of range in a header.
+const TOKENS: &[&[u8]] = &[
+    b"RIFF",
+    b"WAVE",
+    b"fmt ",
+    b"data",
+    b"LIST",
+    b"MLPK",
+    &[0x00, 0x00, 0x00, 0x00],
+    &[0xff, 0xff, 0xff, 0xff],
+    &[0xff, 0xff, 0xff, 0x7f],
+    &[0x00, 0x00, 0x00, 0x80],
+    &[0x01, 0x00],
+    &[0xff, 0xff],
+    &[0x00, 0x00, 0x80, 0x7f],
+    &[0x00, 0x00, 0xc0, 0x7f],
+];
+
+/// The same for AIFF files, whose numbers are big-endian.
+const AIFF_TOKENS: &[&[u8]] = &[
+    b"FORM",
+    b"AIFF",
+    b"AIFC",
+    b"COMM",
+    b"SSND",
+    b"sowt",
+    b"fl64",
+    &[0x00, 0x00, 0x00, 0x00],
+    &[0xff, 0xff, 0xff, 0xff],
+    &[0x7f, 0xff, 0xff, 0xff],
+    &[0x7f, 0xff],
+    &[0x40, 0x0e],
+];
+
+/// A directory of its own for each test, so they can run at the same time.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("meadowlark-fuzz-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_seed(path: &Path) -> Vec<u8> {
+    let left: Vec<f32> = (0..SEED_FRAMES).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+    let right: Vec<f32> = left.iter().map(|s| -s).collect();
+    write_wav_f32(path, SAMPLE_RATE, &left, &right).unwrap();
+    std::fs::read(path).unwrap()
+}
+
+fn key(path: &Path, resample_quality: ResampleQuality) -> PcmKey {
+    PcmKey { path: path.to_owned(), resample_to_project_sr: true, resample_quality }
+}
+
+#[test]
+fn mutated_audio_files_load_or_fail_cleanly() {
+    let dir = test_dir("audio");
+    let seed_path = dir.join("seed.wav");
+    let seed = write_seed(&seed_path);
+
+    let mut loader = ResourceLoader::new(SampleRate(PROJECT_SR));
+    let mut mutator = Mutator::new(764);
+    for i in 0..fuzz_iterations(500) {
+        let path = dir.join(format!("{}.wav", i));
+        std::fs::write(&path, mutator.mutate(&seed, TOKENS)).unwrap();
+
+        let quality = if i % 2 == 0 { ResampleQuality::Sinc } else { ResampleQuality::Linear };
+        let (pcm, res) = loader.load_pcm(&key(&path, quality));
+        if res.is_ok() {
+            assert!(pcm.len_frames() as usize * 4 * pcm.channels() <= loader.max_pcm_bytes());
+            assert!(loader.waveform_peaks(&key(&path, quality)).is_some());
+        }
+        loader.collect();
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(peak_file_path(&path));
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mutated_aiff_files_load_or_fail_cleanly() {
+    let dir = test_dir("aiff");
+    let left: Vec<f32> = (0..SEED_FRAMES).map(|i| (i as f32 * 0.01).sin() * 0.5).collect();
+    let right = left.iter().map(|s| -s).collect();
+    let seed = encode_aiff(SAMPLE_RATE, &[left, right], Some((*b"NONE", 24)));
+
+    // Mutated sample rates can resample a short file into a huge one.
+    let mut loader = ResourceLoader::new(SampleRate(PROJECT_SR));
+    loader.set_max_pcm_bytes(1 << 24);
+    let seed_path = dir.join("seed.aiff");
+    std::fs::write(&seed_path, &seed).unwrap();
+    let (pcm, res) = loader.load_pcm(&key(&seed_path, ResampleQuality::Sinc));
+    assert!(res.is_ok());
+    assert_eq!(pcm.sample_rate(), PROJECT_SR as u32);
+    assert_eq!(pcm.channels(), 2);
+
+    let mut mutator = Mutator::new(760);
+    for i in 0..fuzz_iterations(500) {
+        let path = dir.join(format!("{}.aif", i));
+        std::fs::write(&path, mutator.mutate(&seed, AIFF_TOKENS)).unwrap();
+
+        let (pcm, res) = loader.load_pcm(&key(&path, ResampleQuality::Sinc));
+        if res.is_ok() {
+            assert!(pcm.len_frames() as usize * 4 * pcm.channels() <= loader.max_pcm_bytes());
+        }
+        loader.collect();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mutated_peak_files_load_or_fail_cleanly() {
+    let dir = test_dir("peaks");
+    let path = dir.join("seed.wav");
+    write_seed(&path);
+
+    let mut loader = ResourceLoader::new(SampleRate(PROJECT_SR));
+    let seed_peaks = loader.waveform_peaks(&key(&path, ResampleQuality::Sinc)).unwrap();
+    let peaks_path = peak_file_path(&path);
+    let seed = std::fs::read(&peaks_path).unwrap();
+
+    let cached = load_peak_file(&path, PROJECT_SR as u32).unwrap().unwrap();
+    assert_eq!(cached.len(), 2);
+    assert_eq!(cached[0].len_frames(), seed_peaks[0].len_frames());
+
+    let mut mutator = Mutator::new(764);
+    for _ in 0..fuzz_iterations(5_000) {
+        std::fs::write(&peaks_path, mutator.mutate(&seed, TOKENS)).unwrap();
+        if let Ok(Some(channels)) = load_peak_file(&path, PROJECT_SR as u32) {
+            // Zoomed out, and zoomed in past the peaks.
+            for channel in channels.iter() {
+                channel.columns(&[], 0..channel.len_frames(), 64);
+                channel.columns(&[], 0..64, 64);
+            }
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resources_over_the_size_limit_fail() {
+    let dir = test_dir("limit");
+    let path = dir.join("seed.wav");
+    write_seed(&path);
+
+    let mut loader = ResourceLoader::new(SampleRate(PROJECT_SR));
+    loader.set_max_pcm_bytes(SEED_FRAMES);
+    for quality in [ResampleQuality::Sinc, ResampleQuality::Linear] {
+        let (pcm, res) = loader.load_pcm(&key(&path, quality));
+        assert!(res.is_err());
+        assert_eq!(pcm.len_frames(), 0);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,50 @@
+//! The reverb of the `ReverbPlug`, apart from the plugin so it can be run
+//! without an engine.
+
+use super::dsp::Reverb;
+
+/// The settings of a reverb for one block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReverbSettings {
+    pub size: f32,
+    pub damping: f32,
+    pub width: f32,
+    pub mix: f32,
+    /// Hold the current tail forever.
+    pub frozen: bool,
+}
+
+/// A stereo reverb with a dry/wet mix.
+pub struct ReverbEffect {
+    reverb: Reverb,
+    /// The settings the reverb was set to, or `None` if it needs to be set
+    /// again.
+    settings: Option<(f32, f32, f32, bool)>,
+}
+
+impl ReverbEffect {
+    pub fn new(sample_rate: f32) -> Self {
+        Self { reverb: Reverb::new(sample_rate), settings: None }
+    }
+
+    pub fn reset(&mut self) {
+        self.reverb.reset();
+    }
+
+    /// Reverberate a block in place.
+    pub fn process(&mut self, settings: &ReverbSettings, left: &mut [f32], right: &mut [f32]) {
+        let reverb_settings = (settings.size, settings.damping, settings.width, settings.frozen);
+        if self.settings != Some(reverb_settings) {
+            self.settings = Some(reverb_settings);
+            self.reverb.set(settings.size, settings.damping, settings.width, settings.frozen);
+        }
+
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            let (dry_l, dry_r) = (*l, *r);
+            let (wet_l, wet_r) = self.reverb.process(dry_l, dry_r);
+
+            *l = dry_l + (wet_l - dry_l) * settings.mix;
+            *r = dry_r + (wet_r - dry_r) * settings.mix;
+        }
+    }
+}
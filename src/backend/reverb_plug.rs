@@ -0,0 +1,325 @@
+//! An algorithmic stereo reverb. Freezing it holds the current tail forever,
+//! i.e. to build a pad out of the end of a phrase.
+
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{Gradient, ParamF32, ParamF32Handle, Unit};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use super::param_state::ParamState;
+use super::reverb::{ReverbEffect, ReverbSettings};
+
+pub static REVERB_PLUG_RDN: &str = "app.meadowlark.reverb";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+pub struct ReverbPlugFactory;
+
+impl PluginFactory for ReverbPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: REVERB_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Reverb".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(ReverbPlugMainThread::new()))
+    }
+}
+
+pub struct ReverbPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    values: ParamValues,
+}
+
+impl ReverbPlugHandle {
+    /// Hold the current tail forever and stop taking in more of the input.
+    pub fn set_freeze(&mut self, frozen: bool) {
+        self.send(ProcessMsg::SetFreeze(frozen));
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Reverb plugin failed to send message: {}", e);
+        }
+    }
+}
+
+impl ParamState for ReverbPlugHandle {
+    fn param_values(&self) -> Vec<f32> {
+        self.values.iter().map(|value| f32::from_bits(value.load(Ordering::Relaxed))).collect()
+    }
+
+    fn set_param_values(&mut self, values: &[f32]) {
+        let mut params = PARAMS.map(|(_, default)| default);
+        for (param, value) in params.iter_mut().zip(values) {
+            *param = *value;
+        }
+        self.send(ProcessMsg::SetParams(params));
+    }
+}
+
+enum ProcessMsg {
+    SetFreeze(bool),
+    SetParams([f32; PARAMS.len()]),
+}
+
+/// The values of the parameters as bits of `f32`s, which the audio thread
+/// keeps up to date for the handle.
+type ParamValues = Arc<[AtomicU32; PARAMS.len()]>;
+
+struct ParamsHandle {
+    pub size: ParamF32Handle,
+    pub damping: ParamF32Handle,
+    pub width: ParamF32Handle,
+    pub mix: ParamF32Handle,
+}
+
+struct Params {
+    pub size: ParamF32,
+    pub damping: ParamF32,
+    pub width: ParamF32,
+    pub mix: ParamF32,
+}
+
+/// The name and default of each parameter, by ID. They all range from 0.0 to
+/// 1.0.
+const PARAMS: [(&str, f32); 4] = [("size", 0.5), ("damping", 0.5), ("width", 1.0), ("mix", 0.25)];
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let param = |id: usize| {
+            let (_, default) = PARAMS[id];
+            ParamF32::from_value(
+                default,
+                default,
+                0.0,
+                1.0,
+                Gradient::Linear,
+                Unit::Generic,
+                0.0,
+                sample_rate,
+                max_frames,
+            )
+        };
+        let (size, size_handle) = param(0);
+        let (damping, damping_handle) = param(1);
+        let (width, width_handle) = param(2);
+        let (mix, mix_handle) = param(3);
+
+        (
+            Params { size, damping, width, mix },
+            ParamsHandle {
+                size: size_handle,
+                damping: damping_handle,
+                width: width_handle,
+                mix: mix_handle,
+            },
+        )
+    }
+}
+
+pub struct ReverbPlugMainThread {
+    params: ParamsHandle,
+}
+
+impl ReverbPlugMainThread {
+    fn new() -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle }
+    }
+}
+
+impl PluginMainThread for ReverbPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+        let values = Arc::new(PARAMS.map(|(_, default)| AtomicU32::new(default.to_bits())));
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(ReverbPlugAudioThread {
+                params,
+                from_handle_rx,
+                reverb: ReverbEffect::new(sample_rate.0 as f32),
+                frozen: false,
+                values: Arc::clone(&values),
+            }),
+            internal_handle: Some(Box::new(ReverbPlugHandle { to_audio_thread_tx, values })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        PARAMS.len() as u32
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        let (name, default) = PARAMS.get(param_index).ok_or(())?;
+
+        Ok(ext::params::ParamInfo::new(
+            ParamID(param_index as u32),
+            ParamInfoFlags::default_float(),
+            (*name).into(),
+            String::new(),
+            0.0,
+            1.0,
+            f64::from(*default),
+        ))
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => Ok(f64::from(self.params.size.value())),
+            ParamID(1) => Ok(f64::from(self.params.damping.value())),
+            ParamID(2) => Ok(f64::from(self.params.width.value())),
+            ParamID(3) => Ok(f64::from(self.params.mix.value())),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(0..=3) => Ok(format!("{:.0}%", value * 100.0)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0..=3) => {
+                text.trim_end_matches('%').trim().parse::<f64>().map(|v| v / 100.0).map_err(|_| ())
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct ReverbPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    reverb: ReverbEffect,
+    frozen: bool,
+
+    values: ParamValues,
+}
+
+impl ReverbPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                self.set_param(param_value.param_id(), param_value.value() as f32);
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetFreeze(frozen) => self.frozen = frozen,
+                ProcessMsg::SetParams(values) => {
+                    for (id, value) in values.into_iter().enumerate() {
+                        self.set_param(id as u32, value);
+                    }
+                }
+            }
+        }
+    }
+
+    fn set_param(&mut self, id: u32, value: f32) {
+        let value = value.clamp(0.0, 1.0);
+        match id {
+            0 => self.params.size.set_value(value),
+            1 => self.params.damping.set_value(value),
+            2 => self.params.width.set_value(value),
+            3 => self.params.mix.set_value(value),
+            _ => return,
+        }
+        self.values[id as usize].store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl PluginAudioThread for ReverbPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.reverb.reset();
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+
+        let frames = proc_info.frames;
+
+        let settings = ReverbSettings {
+            size: self.params.size.smoothed(frames)[0],
+            damping: self.params.damping.smoothed(frames)[0],
+            width: self.params.width.smoothed(frames)[0],
+            mix: self.params.mix.smoothed(frames)[0],
+            frozen: self.frozen,
+        };
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let out_l = &mut out_l[0..frames];
+        let out_r = &mut out_r[0..frames];
+        out_l.copy_from_slice(&in_l[0..frames]);
+        out_r.copy_from_slice(&in_r[0..frames]);
+
+        self.reverb.process(&settings, out_l, out_r);
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
@@ -0,0 +1,393 @@
+//! Destructive editing of audio buffers, used by the sample editor.
+//!
+//! Every edit replaces a range of frames with new content. The replaced and
+//! inserted content is stored in the undo history, so an edit can always be
+//! undone by swapping them back.
+
+use pcm_loader::PcmRAM;
+use std::ops::Range;
+use std::path::Path;
+
+use super::dsp::{db_to_gain, Biquad, BiquadCoeffs};
+use crate::util::write_wav_f32;
+
+/// The maximum number of edits that can be undone.
+pub const MAX_UNDO: usize = 64;
+
+/// The cutoff of the highpass filter used to remove DC offset.
+const DC_REMOVAL_FREQ: f32 = 5.0;
+
+/// A stereo audio buffer.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SampleBuffer {
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+}
+
+impl SampleBuffer {
+    pub fn from_pcm(pcm: &PcmRAM) -> Self {
+        let len = pcm.len_frames() as usize;
+
+        let mut left = vec![0.0; len];
+        let mut right = vec![0.0; len];
+        pcm.fill_stereo_f32(0, &mut left, &mut right);
+
+        Self { left, right }
+    }
+
+    pub fn len_frames(&self) -> usize {
+        self.left.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> SampleBuffer {
+        SampleBuffer { left: self.left[range.clone()].to_vec(), right: self.right[range].to_vec() }
+    }
+
+    /// Replace the given range with `with` and return the content that was removed.
+    fn splice(&mut self, range: Range<usize>, with: &SampleBuffer) -> SampleBuffer {
+        SampleBuffer {
+            left: self.left.splice(range.clone(), with.left.iter().copied()).collect(),
+            right: self.right.splice(range, with.right.iter().copied()).collect(),
+        }
+    }
+
+    fn map_range(&self, range: Range<usize>, mut f: impl FnMut(usize, f32) -> f32) -> SampleBuffer {
+        let mut out = self.slice(range.clone());
+        for (i, (l, r)) in out.left.iter_mut().zip(out.right.iter_mut()).enumerate() {
+            *l = f(i, *l);
+            *r = f(i, *r);
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleEditOp {
+    Silence,
+    FadeIn,
+    FadeOut,
+    /// Normalize the peak of the selection to the given level in dB.
+    Normalize(f32),
+    RemoveDC,
+}
+
+/// A single step in the undo history.
+#[derive(Debug, Clone)]
+struct EditRecord {
+    start: usize,
+    removed: SampleBuffer,
+    inserted: SampleBuffer,
+}
+
+/// An audio buffer that is being edited, along with its undo history.
+#[derive(Debug, Clone)]
+pub struct SampleEditor {
+    buffer: SampleBuffer,
+    sample_rate: u32,
+
+    undo_stack: Vec<EditRecord>,
+    redo_stack: Vec<EditRecord>,
+
+    /// True if there are edits that have not been saved.
+    dirty: bool,
+}
+
+impl SampleEditor {
+    pub fn new(pcm: &PcmRAM) -> Self {
+        Self {
+            buffer: SampleBuffer::from_pcm(pcm),
+            sample_rate: pcm.sample_rate(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    pub fn buffer(&self) -> &SampleBuffer {
+        &self.buffer
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Clamp a range to the length of the buffer.
+    pub fn clamp_range(&self, range: Range<usize>) -> Range<usize> {
+        let len = self.buffer.len_frames();
+        range.start.min(len)..range.end.min(len).max(range.start.min(len))
+    }
+
+    /// Remove the range from the buffer and return it.
+    pub fn cut(&mut self, range: Range<usize>) -> SampleBuffer {
+        let range = self.clamp_range(range);
+        self.replace(range, SampleBuffer::default())
+    }
+
+    pub fn copy(&self, range: Range<usize>) -> SampleBuffer {
+        self.buffer.slice(self.clamp_range(range))
+    }
+
+    /// Replace the range with the given content. Pass an empty range to insert.
+    pub fn paste(&mut self, range: Range<usize>, content: &SampleBuffer) {
+        let range = self.clamp_range(range);
+        self.replace(range, content.clone());
+    }
+
+    pub fn apply(&mut self, range: Range<usize>, op: SampleEditOp) {
+        let range = self.clamp_range(range);
+        let len = range.end - range.start;
+        if len == 0 {
+            return;
+        }
+
+        let new = match op {
+            SampleEditOp::Silence => self.buffer.map_range(range.clone(), |_, _| 0.0),
+            SampleEditOp::FadeIn => {
+                self.buffer.map_range(range.clone(), |i, x| x * i as f32 / len as f32)
+            }
+            SampleEditOp::FadeOut => {
+                self.buffer.map_range(range.clone(), |i, x| x * (len - i) as f32 / len as f32)
+            }
+            SampleEditOp::Normalize(target_db) => {
+                let selection = self.buffer.slice(range.clone());
+                let peak = selection
+                    .left
+                    .iter()
+                    .chain(selection.right.iter())
+                    .fold(0.0f32, |peak, x| peak.max(x.abs()));
+                if peak <= f32::EPSILON {
+                    return;
+                }
+
+                let gain = db_to_gain(target_db) / peak;
+                self.buffer.map_range(range.clone(), |_, x| x * gain)
+            }
+            SampleEditOp::RemoveDC => {
+                let coeffs =
+                    BiquadCoeffs::highpass(self.sample_rate as f32, DC_REMOVAL_FREQ, 0.707);
+                let mut new = self.buffer.slice(range.clone());
+
+                let mut filter = Biquad::new(coeffs);
+                new.left.iter_mut().for_each(|x| *x = filter.process(*x));
+                let mut filter = Biquad::new(coeffs);
+                new.right.iter_mut().for_each(|x| *x = filter.process(*x));
+
+                new
+            }
+        };
+
+        self.replace(range, new);
+    }
+
+    pub fn undo(&mut self) -> bool {
+        if let Some(record) = self.undo_stack.pop() {
+            let range = record.start..record.start + record.inserted.len_frames();
+            self.buffer.splice(range, &record.removed);
+
+            self.redo_stack.push(record);
+            self.dirty = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if let Some(record) = self.redo_stack.pop() {
+            let range = record.start..record.start + record.removed.len_frames();
+            self.buffer.splice(range, &record.inserted);
+
+            self.undo_stack.push(record);
+            self.dirty = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Write the edited buffer to a new file.
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
+        write_wav_f32(path, self.sample_rate, &self.buffer.left, &self.buffer.right)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn replace(&mut self, range: Range<usize>, with: SampleBuffer) -> SampleBuffer {
+        let start = range.start;
+        let removed = self.buffer.splice(range, &with);
+
+        self.undo_stack.push(EditRecord { start, removed: removed.clone(), inserted: with });
+        if self.undo_stack.len() > MAX_UNDO {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+        self.dirty = true;
+
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pcm_loader::PcmRAMType;
+
+    /// An editor of 8 frames, counting up on the left and down on the right.
+    fn editor() -> SampleEditor {
+        let left: Vec<f32> = (0..8).map(|i| i as f32 * 0.1).collect();
+        let right = left.iter().map(|x| -x).collect();
+        SampleEditor::new(&PcmRAM::new(PcmRAMType::F32(vec![left, right]), 48_000))
+    }
+
+    fn left(editor: &SampleEditor) -> Vec<i32> {
+        editor.buffer().left.iter().map(|x| (x * 10.0).round() as i32).collect()
+    }
+
+    #[test]
+    fn cut_copy_and_paste_move_frames_around() {
+        let mut editor = editor();
+        assert!(!editor.is_dirty());
+
+        let cut = editor.cut(2..4);
+        assert_eq!(cut.right.len(), 2);
+        assert_eq!(left(&editor), [0, 1, 4, 5, 6, 7]);
+        assert!(editor.is_dirty());
+
+        // Inserted with an empty range, and pasted over a range.
+        editor.paste(0..0, &cut);
+        assert_eq!(left(&editor), [2, 3, 0, 1, 4, 5, 6, 7]);
+        let first = editor.copy(0..1);
+        editor.paste(6..8, &first);
+        assert_eq!(left(&editor), [2, 3, 0, 1, 4, 5, 2]);
+        assert_eq!(editor.buffer().right[6], -0.2);
+
+        // Ranges past the end are clamped.
+        assert_eq!(editor.copy(5..100).len_frames(), 2);
+        assert!(editor.copy(50..100).is_empty());
+        editor.cut(6..100);
+        assert_eq!(left(&editor), [2, 3, 0, 1, 4, 5]);
+    }
+
+    #[test]
+    fn edits_are_undone_and_redone_in_order() {
+        let mut editor = editor();
+        editor.cut(0..2);
+        editor.apply(0..6, SampleEditOp::Silence);
+        assert_eq!(left(&editor), [0; 6]);
+
+        assert!(editor.undo());
+        assert_eq!(left(&editor), [2, 3, 4, 5, 6, 7]);
+        assert!(editor.undo());
+        assert_eq!(left(&editor), [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert!(!editor.undo());
+        assert!(!editor.can_undo());
+
+        assert!(editor.redo());
+        assert_eq!(left(&editor), [2, 3, 4, 5, 6, 7]);
+
+        // A new edit forgets what could be redone.
+        editor.cut(0..1);
+        assert!(!editor.can_redo());
+        assert!(!editor.redo());
+    }
+
+    #[test]
+    fn only_the_last_edits_are_kept() {
+        let mut editor = editor();
+        for _ in 0..MAX_UNDO + 3 {
+            editor.apply(0..8, SampleEditOp::FadeIn);
+        }
+        let mut undone = 0;
+        while editor.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO);
+    }
+
+    #[test]
+    fn fades_ramp_over_the_selection() {
+        let mut editor = editor();
+        // A buffer of ones makes the gain of the fades easy to read.
+        let ones = SampleBuffer { left: vec![1.0; 8], right: vec![1.0; 8] };
+        editor.paste(0..8, &ones);
+
+        editor.apply(0..4, SampleEditOp::FadeIn);
+        assert_eq!(editor.buffer().left[0..5], [0.0, 0.25, 0.5, 0.75, 1.0]);
+        editor.apply(4..8, SampleEditOp::FadeOut);
+        assert_eq!(editor.buffer().right[3..8], [0.75, 1.0, 0.75, 0.5, 0.25]);
+    }
+
+    #[test]
+    fn normalizing_sets_the_peak_of_the_selection() {
+        let mut editor = editor();
+        editor.apply(0..4, SampleEditOp::Normalize(-6.0));
+        let peak = db_to_gain(-6.0);
+        // The peak is on the right, which counts down.
+        assert!((editor.buffer().right[3] + peak).abs() < 1e-6);
+        assert!((editor.buffer().left[1] - peak / 3.0).abs() < 1e-6);
+        assert_eq!(left(&editor)[4..], [4, 5, 6, 7]);
+
+        // Silence can't be normalized, and isn't an edit.
+        editor.apply(0..8, SampleEditOp::Silence);
+        editor.apply(0..8, SampleEditOp::Normalize(0.0));
+        assert_eq!(left(&editor), [0; 8]);
+        editor.undo();
+        assert!((editor.buffer().right[3] + peak).abs() < 1e-6);
+    }
+
+    #[test]
+    fn dc_offset_is_removed() {
+        let frames = 48_000;
+        let offset = SampleBuffer {
+            left: (0..frames).map(|i| 0.5 + 0.1 * (i as f32 * 0.05).sin()).collect(),
+            right: vec![-0.25; frames],
+        };
+        let mut editor = editor();
+        editor.paste(0..8, &offset);
+        editor.apply(0..frames, SampleEditOp::RemoveDC);
+
+        // Once the filter has settled.
+        let tail = frames / 2..frames;
+        let mean = |samples: &[f32]| samples.iter().sum::<f32>() / samples.len() as f32;
+        assert!(mean(&editor.buffer().left[tail.clone()]).abs() < 0.005);
+        assert!(mean(&editor.buffer().right[tail.clone()]).abs() < 0.005);
+        let peak = editor.buffer().left[tail].iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+        assert!((peak - 0.1).abs() < 0.01, "the signal has a peak of {}", peak);
+    }
+
+    #[test]
+    fn saving_writes_the_edited_buffer() {
+        let path =
+            std::env::temp_dir().join(format!("meadowlark-sample-edit-{}.wav", std::process::id()));
+        let mut editor = editor();
+        editor.cut(0..4);
+        editor.save(&path).unwrap();
+        assert!(!editor.is_dirty());
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // Four stereo frames of 32 bit floats after the header.
+        assert_eq!(bytes.len(), 44 + 4 * 2 * 4);
+        assert_eq!(bytes[44..48], 0.4f32.to_le_bytes());
+
+        // Undoing makes it differ from the file again.
+        editor.undo();
+        assert!(editor.is_dirty());
+    }
+}
@@ -0,0 +1,141 @@
+//! Musical keys and scales, used by the plugins that need to be aware of the
+//! key of the project.
+
+/// A musical scale, defined as a set of semitone offsets from the root note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    NaturalMinor,
+    HarmonicMinor,
+    MelodicMinor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+}
+
+impl Scale {
+    pub const ALL: [Scale; 13] = [
+        Scale::Chromatic,
+        Scale::Major,
+        Scale::NaturalMinor,
+        Scale::HarmonicMinor,
+        Scale::MelodicMinor,
+        Scale::Dorian,
+        Scale::Phrygian,
+        Scale::Lydian,
+        Scale::Mixolydian,
+        Scale::Locrian,
+        Scale::MajorPentatonic,
+        Scale::MinorPentatonic,
+        Scale::Blues,
+    ];
+
+    /// The semitone offsets of each degree of this scale from the root note.
+    pub fn intervals(&self) -> &'static [u8] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::NaturalMinor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scale::Chromatic => "Chromatic",
+            Scale::Major => "Major",
+            Scale::NaturalMinor => "Natural Minor",
+            Scale::HarmonicMinor => "Harmonic Minor",
+            Scale::MelodicMinor => "Melodic Minor",
+            Scale::Dorian => "Dorian",
+            Scale::Phrygian => "Phrygian",
+            Scale::Lydian => "Lydian",
+            Scale::Mixolydian => "Mixolydian",
+            Scale::Locrian => "Locrian",
+            Scale::MajorPentatonic => "Major Pentatonic",
+            Scale::MinorPentatonic => "Minor Pentatonic",
+            Scale::Blues => "Blues",
+        }
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Scale::Major
+    }
+}
+
+pub static NOTE_NAMES: [&str; 12] =
+    ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// A musical key (i.e. "A minor").
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct MusicalKey {
+    /// The root note of the key in the range `[0, 11]`, where `0` is C.
+    pub root: u8,
+    pub scale: Scale,
+}
+
+impl MusicalKey {
+    pub fn new(root: u8, scale: Scale) -> Self {
+        Self { root: root % 12, scale }
+    }
+
+    /// Returns `true` if the given MIDI note is in this key.
+    pub fn contains(&self, note: i32) -> bool {
+        let pc = (note - i32::from(self.root)).rem_euclid(12) as u8;
+        self.scale.intervals().contains(&pc)
+    }
+
+    /// Snap the given MIDI note to the nearest note in this key. When the note
+    /// lies exactly between two scale notes, the lower one is chosen.
+    pub fn snap(&self, note: i32) -> i32 {
+        for offset in 0..12 {
+            if self.contains(note - offset) {
+                return note - offset;
+            }
+            if self.contains(note + offset) {
+                return note + offset;
+            }
+        }
+        note
+    }
+
+    /// Move the given MIDI note by the given number of scale degrees. The note
+    /// is snapped to the key first.
+    pub fn transpose_degrees(&self, note: i32, degrees: i32) -> i32 {
+        let intervals = self.scale.intervals();
+        let len = intervals.len() as i32;
+
+        let snapped = self.snap(note);
+        let rel = snapped - i32::from(self.root);
+        let octave = rel.div_euclid(12);
+        let pc = rel.rem_euclid(12) as u8;
+        let degree = intervals.iter().position(|i| *i == pc).unwrap_or(0) as i32;
+
+        let new_degree = degree + degrees;
+        let new_octave = octave + new_degree.div_euclid(len);
+        let new_pc = i32::from(intervals[new_degree.rem_euclid(len) as usize]);
+
+        i32::from(self.root) + new_octave * 12 + new_pc
+    }
+
+    pub fn name(&self) -> String {
+        format!("{} {}", NOTE_NAMES[usize::from(self.root)], self.scale.name())
+    }
+}
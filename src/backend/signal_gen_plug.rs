@@ -0,0 +1,452 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{
+    Gradient, ParamF32, ParamF32Handle, Unit, DEFAULT_DB_GRADIENT, DEFAULT_SMOOTH_SECS,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::f32::consts::TAU;
+
+use super::dsp::{db_to_gain, PinkNoise, WhiteNoise};
+use super::tempo_bus::{SyncedTime, TempoBus};
+
+pub static SIGNAL_GEN_PLUG_RDN: &str = "app.meadowlark.signal-generator";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+const MIN_FREQ: f32 = 10.0;
+const MAX_FREQ: f32 = 22_000.0;
+
+pub struct SignalGenPlugFactory {
+    tempo_bus: TempoBus,
+}
+
+impl SignalGenPlugFactory {
+    pub fn new(tempo_bus: TempoBus) -> Self {
+        Self { tempo_bus }
+    }
+}
+
+impl PluginFactory for SignalGenPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: SIGNAL_GEN_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Signal Generator".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(SignalGenPlugMainThread::new(self.tempo_bus.clone())))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    /// A sine wave at the frequency set by the "frequency" parameter.
+    Sine,
+    WhiteNoise,
+    PinkNoise,
+    /// A logarithmic sine sweep. A duration in beats follows the tempo.
+    Sweep {
+        start_freq: f32,
+        end_freq: f32,
+        duration: SyncedTime,
+        /// Restart the sweep once it reaches the end instead of holding the
+        /// last frequency.
+        repeat: bool,
+    },
+}
+
+/// What happens to the signal coming into the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    /// The input is discarded and only the generated signal is output.
+    Replace,
+    /// The generated signal is added on top of the input.
+    Mix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputChannels {
+    Both,
+    Left,
+    Right,
+}
+
+pub struct SignalGenPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+}
+
+impl SignalGenPlugHandle {
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        let waveform = match waveform {
+            Waveform::Sweep { start_freq, end_freq, duration, repeat } => Waveform::Sweep {
+                start_freq: start_freq.clamp(MIN_FREQ, MAX_FREQ),
+                end_freq: end_freq.clamp(MIN_FREQ, MAX_FREQ),
+                duration: match duration {
+                    SyncedTime::Seconds(secs) => SyncedTime::Seconds(secs.max(0.01)),
+                    SyncedTime::Beats(beats) => SyncedTime::Beats(beats.max(0.01)),
+                },
+                repeat,
+            },
+            w => w,
+        };
+
+        self.send(ProcessMsg::SetWaveform(waveform));
+    }
+
+    pub fn set_input_mode(&mut self, mode: InputMode) {
+        self.send(ProcessMsg::SetInputMode(mode));
+    }
+
+    pub fn set_output_channels(&mut self, channels: OutputChannels) {
+        self.send(ProcessMsg::SetOutputChannels(channels));
+    }
+
+    /// Turn the generator on or off. Turning it on restarts any sweep from the
+    /// beginning.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.send(ProcessMsg::SetEnabled(enabled));
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Signal generator plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetWaveform(Waveform),
+    SetInputMode(InputMode),
+    SetOutputChannels(OutputChannels),
+    SetEnabled(bool),
+}
+
+struct ParamsHandle {
+    pub freq: ParamF32Handle,
+    pub level: ParamF32Handle,
+}
+
+struct Params {
+    pub freq: ParamF32,
+    pub level: ParamF32,
+}
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let (freq, freq_handle) = ParamF32::from_value(
+            1_000.0,
+            1_000.0,
+            MIN_FREQ,
+            MAX_FREQ,
+            Gradient::Exponential,
+            Unit::Generic,
+            DEFAULT_SMOOTH_SECS,
+            sample_rate,
+            max_frames,
+        );
+
+        let (level, level_handle) = ParamF32::from_value(
+            -18.0,
+            -18.0,
+            -90.0,
+            0.0,
+            DEFAULT_DB_GRADIENT,
+            Unit::Decibels,
+            DEFAULT_SMOOTH_SECS,
+            sample_rate,
+            max_frames,
+        );
+
+        (Params { freq, level }, ParamsHandle { freq: freq_handle, level: level_handle })
+    }
+}
+
+pub struct SignalGenPlugMainThread {
+    params: ParamsHandle,
+    tempo_bus: TempoBus,
+}
+
+impl SignalGenPlugMainThread {
+    fn new(tempo_bus: TempoBus) -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle, tempo_bus }
+    }
+}
+
+impl PluginMainThread for SignalGenPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(SignalGenPlugAudioThread {
+                params,
+                from_handle_rx,
+                sample_rate: sample_rate.0 as f32,
+                tempo_bus: self.tempo_bus.clone(),
+                waveform: Waveform::Sine,
+                input_mode: InputMode::Replace,
+                output_channels: OutputChannels::Both,
+                enabled: false,
+                phase: 0.0,
+                sweep_time: 0.0,
+                white: WhiteNoise::default(),
+                pink: PinkNoise::default(),
+            }),
+            internal_handle: Some(Box::new(SignalGenPlugHandle { to_audio_thread_tx })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        2
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        match param_index {
+            0 => Ok(ext::params::ParamInfo::new(
+                ParamID(0),
+                ParamInfoFlags::default_float(),
+                "frequency".into(),
+                String::new(),
+                f64::from(MIN_FREQ),
+                f64::from(MAX_FREQ),
+                1_000.0,
+            )),
+            1 => Ok(ext::params::ParamInfo::new(
+                ParamID(1),
+                ParamInfoFlags::default_float(),
+                "level".into(),
+                String::new(),
+                -90.0,
+                0.0,
+                -18.0,
+            )),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => Ok(f64::from(self.params.freq.value())),
+            ParamID(1) => Ok(f64::from(self.params.level.value())),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(0) => Ok(format!("{:.1} Hz", value)),
+            ParamID(1) => Ok(format!("{:.2} dB", value)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) | ParamID(1) => text.parse().map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+pub struct SignalGenPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    sample_rate: f32,
+    tempo_bus: TempoBus,
+
+    waveform: Waveform,
+    input_mode: InputMode,
+    output_channels: OutputChannels,
+    enabled: bool,
+
+    /// The phase of the oscillator in the range `[0.0, 1.0)`.
+    phase: f32,
+    /// The time in seconds since the current sweep started.
+    sweep_time: f32,
+
+    white: WhiteNoise,
+    pink: PinkNoise,
+}
+
+impl SignalGenPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                let value = param_value.value() as f32;
+                match param_value.param_id() {
+                    0 => self.params.freq.set_value(value),
+                    1 => self.params.level.set_value(value),
+                    _ => {}
+                }
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetWaveform(waveform) => {
+                    self.waveform = waveform;
+                    self.sweep_time = 0.0;
+                }
+                ProcessMsg::SetInputMode(mode) => self.input_mode = mode,
+                ProcessMsg::SetOutputChannels(channels) => self.output_channels = channels,
+                ProcessMsg::SetEnabled(enabled) => {
+                    if enabled && !self.enabled {
+                        self.phase = 0.0;
+                        self.sweep_time = 0.0;
+                    }
+                    self.enabled = enabled;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn next_sine(&mut self, freq: f32) -> f32 {
+        let s = (self.phase * TAU).sin();
+
+        self.phase += freq / self.sample_rate;
+        self.phase -= self.phase.floor();
+
+        s
+    }
+
+    #[inline]
+    fn sweep_freq(
+        &mut self,
+        start_freq: f32,
+        end_freq: f32,
+        duration_secs: f32,
+        repeat: bool,
+    ) -> f32 {
+        let t = (self.sweep_time / duration_secs).min(1.0);
+
+        self.sweep_time += 1.0 / self.sample_rate;
+        if repeat && self.sweep_time >= duration_secs {
+            self.sweep_time = 0.0;
+        }
+
+        start_freq * (end_freq / start_freq).powf(t)
+    }
+}
+
+impl PluginAudioThread for SignalGenPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {}
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+
+        let frames = proc_info.frames;
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        match self.input_mode {
+            InputMode::Replace => {
+                out_l[0..frames].fill(0.0);
+                out_r[0..frames].fill(0.0);
+            }
+            InputMode::Mix => {
+                out_l[0..frames].copy_from_slice(&in_l[0..frames]);
+                out_r[0..frames].copy_from_slice(&in_r[0..frames]);
+            }
+        }
+
+        if !self.enabled {
+            return ProcessStatus::Continue;
+        }
+
+        let freq = self.params.freq.smoothed(frames);
+        let level = self.params.level.smoothed(frames);
+        // Synced sweeps follow the tempo at the playhead, through the tempo
+        // map like the rest of the timeline.
+        let bpm = self
+            .tempo_bus
+            .tempo()
+            .bpm_at_frame(proc_info.transport.playhead_frame(), f64::from(self.sample_rate));
+
+        let (write_l, write_r) = match self.output_channels {
+            OutputChannels::Both => (true, true),
+            OutputChannels::Left => (true, false),
+            OutputChannels::Right => (false, true),
+        };
+
+        for i in 0..frames {
+            let s = match self.waveform {
+                Waveform::Sine => self.next_sine(freq[i]),
+                Waveform::WhiteNoise => self.white.next(),
+                Waveform::PinkNoise => self.pink.next(),
+                Waveform::Sweep { start_freq, end_freq, duration, repeat } => {
+                    let freq = self.sweep_freq(start_freq, end_freq, duration.secs(bpm), repeat);
+                    self.next_sine(freq)
+                }
+            };
+
+            let s = s * db_to_gain(level[i]);
+
+            if write_l {
+                out_l[i] += s;
+            }
+            if write_r {
+                out_r[i] += s;
+            }
+        }
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
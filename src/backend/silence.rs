@@ -0,0 +1,198 @@
+//! Detection of silent regions in PCM resources, used by the "strip silence"
+//! clip operation.
+
+use pcm_loader::PcmRAM;
+use std::ops::Range;
+
+use super::dsp::db_to_gain;
+
+/// The number of frames read from the resource at a time.
+const CHUNK_FRAMES: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StripSilenceSettings {
+    /// Anything with a peak below this level (in dB) is considered silent.
+    pub threshold_db: f32,
+    /// How long the signal must stay below the threshold before a region ends.
+    /// This keeps short pauses (i.e. between words) from splitting a region.
+    pub hold_secs: f64,
+    /// The amount of time kept before the start of each region.
+    pub pre_padding_secs: f64,
+    /// The amount of time kept after the end of each region.
+    pub post_padding_secs: f64,
+    /// Regions shorter than this (after padding) are treated as silence.
+    pub min_region_secs: f64,
+}
+
+impl Default for StripSilenceSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: -48.0,
+            hold_secs: 0.25,
+            pre_padding_secs: 0.01,
+            post_padding_secs: 0.05,
+            min_region_secs: 0.05,
+        }
+    }
+}
+
+/// Find the regions (in frames) of the given resource that are not silent.
+///
+/// The returned regions are sorted and never overlap.
+pub fn find_non_silent_regions(pcm: &PcmRAM, settings: &StripSilenceSettings) -> Vec<Range<usize>> {
+    let sample_rate = f64::from(pcm.sample_rate());
+    let secs_to_frames = |secs: f64| (secs.max(0.0) * sample_rate).round() as usize;
+
+    let len = pcm.len_frames() as usize;
+    let threshold = db_to_gain(settings.threshold_db);
+    let hold_frames = secs_to_frames(settings.hold_secs);
+
+    let mut regions: Vec<Range<usize>> = Vec::new();
+
+    let mut region_start: Option<usize> = None;
+    let mut last_loud = 0;
+
+    let mut buf_l = vec![0.0; CHUNK_FRAMES];
+    let mut buf_r = vec![0.0; CHUNK_FRAMES];
+    let mut chunk_start = 0;
+    while chunk_start < len {
+        let frames = CHUNK_FRAMES.min(len - chunk_start);
+        pcm.fill_stereo_f32(chunk_start, &mut buf_l[0..frames], &mut buf_r[0..frames]);
+
+        for (i, (l, r)) in buf_l[0..frames].iter().zip(buf_r[0..frames].iter()).enumerate() {
+            let frame = chunk_start + i;
+
+            if l.abs().max(r.abs()) >= threshold {
+                if region_start.is_none() {
+                    region_start = Some(frame);
+                }
+                last_loud = frame;
+            } else if let Some(start) = region_start {
+                if frame - last_loud > hold_frames {
+                    regions.push(start..last_loud + 1);
+                    region_start = None;
+                }
+            }
+        }
+
+        chunk_start += frames;
+    }
+    if let Some(start) = region_start {
+        regions.push(start..last_loud + 1);
+    }
+
+    // Apply the padding and merge any regions that now overlap.
+    let pre_padding = secs_to_frames(settings.pre_padding_secs);
+    let post_padding = secs_to_frames(settings.post_padding_secs);
+    let min_region_frames = secs_to_frames(settings.min_region_secs);
+
+    let mut padded: Vec<Range<usize>> = Vec::with_capacity(regions.len());
+    for r in regions {
+        let r = r.start.saturating_sub(pre_padding)..(r.end + post_padding).min(len);
+
+        match padded.last_mut() {
+            Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+            _ => padded.push(r),
+        }
+    }
+
+    padded.retain(|r| r.end - r.start >= min_region_frames);
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pcm_loader::PcmRAMType;
+
+    const SR: u32 = 1_000;
+
+    /// A mono resource that is silent except for the given ranges of frames.
+    fn pcm(len: usize, loud: &[Range<usize>]) -> PcmRAM {
+        let mut samples = vec![0.0; len];
+        for range in loud {
+            samples[range.clone()].fill(0.5);
+        }
+        PcmRAM::new(PcmRAMType::F32(vec![samples]), SR)
+    }
+
+    fn settings(
+        hold_secs: f64,
+        pre_padding_secs: f64,
+        post_padding_secs: f64,
+    ) -> StripSilenceSettings {
+        StripSilenceSettings {
+            threshold_db: -48.0,
+            hold_secs,
+            pre_padding_secs,
+            post_padding_secs,
+            min_region_secs: 0.0,
+        }
+    }
+
+    #[test]
+    fn silence_has_no_regions() {
+        assert!(find_non_silent_regions(&pcm(10_000, &[]), &settings(0.0, 0.0, 0.0)).is_empty());
+
+        // Just under the threshold.
+        let quiet = vec![db_to_gain(-48.5); 1_000];
+        let quiet = PcmRAM::new(PcmRAMType::F32(vec![quiet]), SR);
+        assert!(find_non_silent_regions(&quiet, &settings(0.0, 0.0, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn regions_cover_what_is_loud() {
+        let pcm = pcm(10_000, &[1_000..2_000, 3_000..3_500, 9_000..10_000]);
+        assert_eq!(
+            find_non_silent_regions(&pcm, &settings(0.0, 0.0, 0.0)),
+            vec![1_000..2_000, 3_000..3_500, 9_000..10_000]
+        );
+    }
+
+    #[test]
+    fn regions_span_the_chunks_they_are_read_in() {
+        let loud = CHUNK_FRAMES - 100..3 * CHUNK_FRAMES + 100;
+        let pcm = pcm(4 * CHUNK_FRAMES, std::slice::from_ref(&loud));
+        assert_eq!(find_non_silent_regions(&pcm, &settings(0.0, 0.0, 0.0)), vec![loud]);
+    }
+
+    #[test]
+    fn pauses_shorter_than_the_hold_dont_split_a_region() {
+        let pcm = pcm(10_000, &[1_000..2_000, 2_200..3_000, 5_000..6_000]);
+        // A hold of 250 frames bridges the pause of 200, but not the one of
+        // 2000.
+        assert_eq!(
+            find_non_silent_regions(&pcm, &settings(0.25, 0.0, 0.0)),
+            vec![1_000..3_000, 5_000..6_000]
+        );
+        assert_eq!(
+            find_non_silent_regions(&pcm, &settings(0.1, 0.0, 0.0)),
+            vec![1_000..2_000, 2_200..3_000, 5_000..6_000]
+        );
+    }
+
+    #[test]
+    fn padding_is_kept_around_regions_and_merges_them() {
+        let pcm = pcm(10_000, &[50..1_000, 1_300..2_000, 5_000..9_980]);
+        assert_eq!(
+            find_non_silent_regions(&pcm, &settings(0.0, 0.1, 0.05)),
+            vec![0..1_050, 1_200..2_050, 4_900..10_000]
+        );
+        // Padding that closes the gap between two regions joins them.
+        assert_eq!(
+            find_non_silent_regions(&pcm, &settings(0.0, 0.2, 0.1)),
+            vec![0..2_100, 4_800..10_000]
+        );
+    }
+
+    #[test]
+    fn short_regions_are_treated_as_silence() {
+        let pcm = pcm(10_000, &[1_000..1_010, 3_000..3_100]);
+        let settings = StripSilenceSettings { min_region_secs: 0.05, ..settings(0.0, 0.0, 0.0) };
+        assert_eq!(find_non_silent_regions(&pcm, &settings), vec![3_000..3_100]);
+
+        // The padding counts toward the length.
+        let settings = StripSilenceSettings { pre_padding_secs: 0.04, ..settings };
+        assert_eq!(find_non_silent_regions(&pcm, &settings), vec![960..1_010, 2_960..3_100]);
+    }
+}
@@ -4,20 +4,109 @@ use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::Stream;
 use dropseed::DSEngineAudioThread;
 use meadowlark_core_types::time::SampleRate;
-use rtrb::{Producer, RingBuffer};
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use super::dsp::DenormalGuard;
 
 const HANDLE_TO_STREAM_MSG_SIZE: usize = 8;
 
+/// The number of output channels the engine renders, in pairs: the master bus
+/// on the first pair, then the channels that play on hardware outputs of
+/// their own. Each is mapped to a channel of the audio device.
+pub const ENGINE_OUT_CHANNELS: usize = 8;
+
+/// The engine renders into a scratch buffer of this many frames at a time
+/// before the channels are mapped onto the device buffer.
+const SCRATCH_FRAMES: usize = 1024;
+
+/// The size of the blocks the engine renders ahead in when buffering for mixing.
+const RENDER_AHEAD_BLOCK_FRAMES: usize = 512;
+
+/// How the engine's output is delivered to the audio device.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputBuffering {
+    /// The engine renders directly in the device's callback. This has the
+    /// lowest latency, which is what you want while recording.
+    #[default]
+    Tracking,
+    /// The engine renders on its own thread into a buffer that stays up to
+    /// `extra_blocks` blocks ahead of the device. This adds latency but
+    /// absorbs the occasional slow block, which is what you want while
+    /// mixing a heavy project.
+    Mixing { extra_blocks: usize },
+}
+
+/// Maps each output channel of the engine to a channel of the audio device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputChannelMap {
+    /// The device channel that each engine channel is sent to, or `None` if
+    /// it is not sent anywhere.
+    pub channels: [Option<usize>; ENGINE_OUT_CHANNELS],
+}
+
+impl OutputChannelMap {
+    /// Engine channel `n` is sent to device channel `n`.
+    pub fn identity() -> Self {
+        let mut channels = [None; ENGINE_OUT_CHANNELS];
+        for (i, ch) in channels.iter_mut().enumerate() {
+            *ch = Some(i);
+        }
+        Self { channels }
+    }
+
+    /// Resolve this map for a device with the given number of channels.
+    ///
+    /// Engine channels mapped to a device channel that doesn't exist fall back
+    /// to device channel `engine_channel % num_device_channels`, so (for
+    /// example) a stereo master is summed onto a mono device instead of going
+    /// silent.
+    pub fn resolve(&self, num_device_channels: usize) -> Self {
+        if num_device_channels == 0 {
+            return Self { channels: [None; ENGINE_OUT_CHANNELS] };
+        }
+
+        let mut resolved = *self;
+        for (i, ch) in resolved.channels.iter_mut().enumerate() {
+            if let Some(device_ch) = ch {
+                if *device_ch >= num_device_channels {
+                    *ch = Some(i % num_device_channels);
+                }
+            }
+        }
+        resolved
+    }
+}
+
+impl Default for OutputChannelMap {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
 #[derive(Debug)]
 enum HandleToStreamMsg {
     NewEngineAudioThread(DSEngineAudioThread),
     DropEngineAudioThread,
+    SetOutputChannelMap(OutputChannelMap),
 }
 
 pub struct SystemIOStreamHandle {
     cpal_stream: Stream,
     to_stream_tx: Producer<HandleToStreamMsg>,
     sample_rate: SampleRate,
+    num_out_channels: usize,
+    output_channel_map: OutputChannelMap,
+    device_name: String,
+    device_lost: Arc<AtomicBool>,
+
+    buffering: OutputBuffering,
+    underruns: Arc<AtomicU64>,
+    /// Declared after the stream so the stream stops pulling audio before
+    /// the render thread is stopped.
+    render_thread: Option<RenderThread>,
 }
 
 impl SystemIOStreamHandle {
@@ -25,21 +114,94 @@ impl SystemIOStreamHandle {
         self.sample_rate
     }
 
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    /// Returns `true` once the audio device has gone away (i.e. it was
+    /// unplugged). The stream will not produce any more audio after this and
+    /// should be dropped.
+    pub fn device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
+    pub fn buffering(&self) -> OutputBuffering {
+        self.buffering
+    }
+
+    /// The number of times the render thread did not keep up with the device
+    /// when buffering for mixing.
+    pub fn underruns(&self) -> u64 {
+        self.underruns.load(Ordering::Relaxed)
+    }
+
+    /// The number of output channels of the audio device.
+    pub fn num_out_channels(&self) -> usize {
+        self.num_out_channels
+    }
+
+    /// Send the engine's output channels to the given device channels.
+    ///
+    /// Returns the map that is actually used, which differs from `map` if the
+    /// device has fewer channels than `map` asks for (see
+    /// `OutputChannelMap::resolve()`).
+    pub fn set_output_channel_map(&mut self, map: OutputChannelMap) -> OutputChannelMap {
+        let resolved = map.resolve(self.num_out_channels);
+        if resolved != map {
+            log::warn!(
+                "Output device only has {} channels, falling back to {:?}",
+                self.num_out_channels,
+                &resolved.channels
+            );
+        }
+
+        if let Err(e) = self.to_stream_tx.push(HandleToStreamMsg::SetOutputChannelMap(resolved)) {
+            log::error!("Failed to send the output channel map to the audio thread: {}", e);
+            return self.output_channel_map;
+        }
+        self.wake_render_thread();
+
+        self.output_channel_map = resolved;
+        resolved
+    }
+
+    pub fn output_channel_map(&self) -> OutputChannelMap {
+        self.output_channel_map
+    }
+
     pub fn engine_activated(&mut self, engine_audio_thread: DSEngineAudioThread) {
-        self.to_stream_tx
-            .push(HandleToStreamMsg::NewEngineAudioThread(engine_audio_thread))
-            .unwrap();
+        if let Err(e) =
+            self.to_stream_tx.push(HandleToStreamMsg::NewEngineAudioThread(engine_audio_thread))
+        {
+            log::error!("Failed to send the engine to the audio thread: {}", e);
+            return;
+        }
+        self.wake_render_thread();
     }
 
     pub fn engine_deactivated(&mut self) {
-        self.to_stream_tx.push(HandleToStreamMsg::DropEngineAudioThread).unwrap();
+        if let Err(e) = self.to_stream_tx.push(HandleToStreamMsg::DropEngineAudioThread) {
+            log::error!("Failed to tell the audio thread to drop the engine: {}", e);
+            return;
+        }
+        self.wake_render_thread();
+    }
+
+    /// The render thread only reads messages when it wakes up, which it
+    /// doesn't do on its own while its buffer is full.
+    fn wake_render_thread(&self) {
+        if let Some(render_thread) = &self.render_thread {
+            render_thread.unpark();
+        }
     }
 }
 
 /// This is temporary. Eventually we will have a more sophisticated and
 /// configurable system using `rainout`.
-pub fn temp_spawn_cpal_default_output_only() -> Result<SystemIOStreamHandle, Box<dyn Error>> {
-    let (to_stream_tx, mut from_handle_rx) =
+pub fn temp_spawn_cpal_default_output_only(
+    buffering: OutputBuffering,
+) -> Result<SystemIOStreamHandle, Box<dyn Error>> {
+    let (to_stream_tx, from_handle_rx) =
         RingBuffer::<HandleToStreamMsg>::new(HANDLE_TO_STREAM_MSG_SIZE);
 
     let cpal_host = cpal::default_host();
@@ -48,45 +210,211 @@ pub fn temp_spawn_cpal_default_output_only() -> Result<SystemIOStreamHandle, Box
         .default_output_device()
         .ok_or("CPAL: no default audio out device found".to_string())?;
 
-    log::info!("Selected default CPAL output device: {:?}", &device.name());
+    let device_name = device.name().unwrap_or_else(|_| String::from("Unknown device"));
+    log::info!("Selected default CPAL output device: {:?}", &device_name);
 
     let config = device.default_output_config()?;
 
     let num_out_channels = usize::from(config.channels());
     let sample_rate: SampleRate = config.sample_rate().0.into();
 
-    let mut engine_audio_thread: Option<DSEngineAudioThread> = None;
+    let output_channel_map = OutputChannelMap::identity().resolve(num_out_channels);
+    let mut renderer = EngineRenderer {
+        from_handle_rx,
+        engine_audio_thread: None,
+        channel_map: output_channel_map,
+        scratch: vec![0.0; SCRATCH_FRAMES * ENGINE_OUT_CHANNELS],
+        num_out_channels,
+    };
+
+    let device_lost = Arc::new(AtomicBool::new(false));
+    let device_lost_clone = Arc::clone(&device_lost);
+    let error_callback = move |e: cpal::StreamError| {
+        log::error!("CPAL stream error: {}", e);
+
+        if let cpal::StreamError::DeviceNotAvailable = e {
+            device_lost_clone.store(true, Ordering::Relaxed);
+        }
+    };
 
-    log::info!("Starting CPAL stream with config {:?}...", &config);
+    let underruns = Arc::new(AtomicU64::new(0));
 
-    let cpal_stream = device.build_output_stream(
-        &config.into(),
-        move |audio_buffer: &mut [f32], _: &cpal::OutputCallbackInfo| {
-            while let Ok(msg) = from_handle_rx.pop() {
-                match msg {
-                    HandleToStreamMsg::NewEngineAudioThread(new_engine_audio_thread) => {
-                        engine_audio_thread = Some(new_engine_audio_thread);
+    log::info!("Starting CPAL stream with config {:?} and {:?}...", &config, &buffering);
+
+    let (cpal_stream, render_thread) = match buffering {
+        OutputBuffering::Tracking => {
+            let cpal_stream = device.build_output_stream(
+                &config.into(),
+                move |audio_buffer: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    renderer.poll();
+                    renderer.render(audio_buffer);
+                },
+                error_callback,
+            )?;
+
+            (cpal_stream, None)
+        }
+        OutputBuffering::Mixing { extra_blocks } => {
+            let capacity = (extra_blocks.max(1) + 1) * RENDER_AHEAD_BLOCK_FRAMES * num_out_channels;
+            let (mut ring_tx, mut ring_rx) = RingBuffer::<f32>::new(capacity);
+            // The most samples that can be buffered while there is room for
+            // another block.
+            let watermark = capacity - RENDER_AHEAD_BLOCK_FRAMES * num_out_channels;
+
+            // The engine renders on its own thread and stays up to
+            // `extra_blocks` blocks ahead of the device. It sleeps while the
+            // buffer is full, and the device callback only wakes it once a
+            // whole block fits again, so it isn't woken on every callback.
+            let run = Arc::new(AtomicBool::new(true));
+            let run_clone = Arc::clone(&run);
+            let join_handle =
+                std::thread::Builder::new().name("engine-render".into()).spawn(move || {
+                    let mut block = vec![0.0; RENDER_AHEAD_BLOCK_FRAMES * num_out_channels];
+                    while run_clone.load(Ordering::Relaxed) {
+                        renderer.poll();
+
+                        if ring_tx.slots() < block.len() {
+                            std::thread::park();
+                            continue;
+                        }
+
+                        renderer.render(&mut block);
+                        if let Ok(chunk) = ring_tx.write_chunk_uninit(block.len()) {
+                            chunk.fill_from_iter(block.iter().copied());
+                        }
                     }
-                    HandleToStreamMsg::DropEngineAudioThread => {
-                        engine_audio_thread = None;
+                })?;
+            let render_thread = join_handle.thread().clone();
+            // Stops the thread again if the stream can't be built.
+            let render_thread_guard = RenderThread { run, join_handle: Some(join_handle) };
+
+            let underruns_clone = Arc::clone(&underruns);
+            let cpal_stream = device.build_output_stream(
+                &config.into(),
+                move |audio_buffer: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let available = ring_rx.slots().min(audio_buffer.len());
+                    if let Ok(chunk) = ring_rx.read_chunk(available) {
+                        let (first, second) = chunk.as_slices();
+                        audio_buffer[0..first.len()].copy_from_slice(first);
+                        audio_buffer[first.len()..available].copy_from_slice(second);
+                        chunk.commit_all();
+                    }
+                    if ring_rx.slots() <= watermark {
+                        render_thread.unpark();
                     }
+
+                    if available < audio_buffer.len() {
+                        audio_buffer[available..].fill(0.0);
+                        underruns_clone.fetch_add(1, Ordering::Relaxed);
+                    }
+                },
+                error_callback,
+            )?;
+
+            (cpal_stream, Some(render_thread_guard))
+        }
+    };
+
+    cpal_stream.play()?;
+
+    log::info!("Successfully started CPAL stream");
+
+    Ok(SystemIOStreamHandle {
+        cpal_stream,
+        to_stream_tx,
+        sample_rate,
+        num_out_channels,
+        output_channel_map,
+        device_name,
+        device_lost,
+        buffering,
+        underruns,
+        render_thread,
+    })
+}
+
+/// Renders the engine and maps its channels onto the device's channels.
+struct EngineRenderer {
+    from_handle_rx: Consumer<HandleToStreamMsg>,
+    engine_audio_thread: Option<DSEngineAudioThread>,
+    channel_map: OutputChannelMap,
+    scratch: Vec<f32>,
+    num_out_channels: usize,
+}
+
+impl EngineRenderer {
+    fn poll(&mut self) {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                HandleToStreamMsg::NewEngineAudioThread(new_engine_audio_thread) => {
+                    self.engine_audio_thread = Some(new_engine_audio_thread);
+                }
+                HandleToStreamMsg::DropEngineAudioThread => {
+                    self.engine_audio_thread = None;
+                }
+                HandleToStreamMsg::SetOutputChannelMap(map) => {
+                    self.channel_map = map;
                 }
             }
+        }
+    }
+
+    /// Fill an interleaved device buffer.
+    fn render(&mut self, audio_buffer: &mut [f32]) {
+        // The callback thread is owned by the audio backend, so enable this for
+        // every block instead of once per thread.
+        let _denormal_guard = DenormalGuard::enable();
 
-            if let Some(engine_audio_thread) = &mut engine_audio_thread {
-                engine_audio_thread
-                    .process_cpal_interleaved_output_only(num_out_channels, audio_buffer);
+        let num_out_channels = self.num_out_channels;
+
+        let engine_audio_thread = match &mut self.engine_audio_thread {
+            Some(engine_audio_thread) => engine_audio_thread,
+            None => {
+                audio_buffer.fill(0.0);
+                return;
             }
-        },
-        |e| {
-            // TODO: Better handling of the system IO stream crashing.
-            panic!("{}", e);
-        },
-    )?;
+        };
 
-    cpal_stream.play()?;
+        for device_buffer in audio_buffer.chunks_mut(SCRATCH_FRAMES * num_out_channels) {
+            let frames = device_buffer.len() / num_out_channels;
+            let scratch = &mut self.scratch[0..frames * ENGINE_OUT_CHANNELS];
 
-    log::info!("Successfully started CPAL stream");
+            engine_audio_thread.process_cpal_interleaved_output_only(ENGINE_OUT_CHANNELS, scratch);
 
-    Ok(SystemIOStreamHandle { cpal_stream, to_stream_tx, sample_rate })
+            device_buffer.fill(0.0);
+            for (engine_ch, device_ch) in self.channel_map.channels.iter().enumerate() {
+                if let Some(device_ch) = *device_ch {
+                    for frame in 0..frames {
+                        device_buffer[frame * num_out_channels + device_ch] +=
+                            scratch[frame * ENGINE_OUT_CHANNELS + engine_ch];
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct RenderThread {
+    run: Arc<AtomicBool>,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    fn unpark(&self) {
+        if let Some(join_handle) = &self.join_handle {
+            join_handle.thread().unpark();
+        }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.run.store(false, Ordering::Relaxed);
+        self.unpark();
+        if let Some(join_handle) = self.join_handle.take() {
+            if join_handle.join().is_err() {
+                log::error!("Engine render thread panicked");
+            }
+        }
+    }
 }
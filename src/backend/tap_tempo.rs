@@ -0,0 +1,116 @@
+//! Finding a tempo from taps on a button or key.
+
+use std::time::{Duration, Instant};
+
+/// The number of most recent taps that are averaged.
+const MAX_TAPS: usize = 8;
+
+/// A tap that comes longer than this after the previous one starts over.
+const RESET_AFTER: Duration = Duration::from_secs(2);
+
+/// Averages the intervals between taps into a tempo.
+#[derive(Debug, Clone, Default)]
+pub struct TapTempo {
+    taps: Vec<Instant>,
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        Self { taps: Vec::with_capacity(MAX_TAPS) }
+    }
+
+    /// Register a tap at the given time. Returns the tempo in beats per minute
+    /// once there are at least two taps.
+    pub fn tap(&mut self, now: Instant) -> Option<f64> {
+        if let Some(last) = self.taps.last() {
+            if now.saturating_duration_since(*last) > RESET_AFTER {
+                self.taps.clear();
+            }
+        }
+
+        if self.taps.len() == MAX_TAPS {
+            self.taps.remove(0);
+        }
+        self.taps.push(now);
+
+        self.bpm()
+    }
+
+    /// The tempo of the current taps, or `None` if there are less than two.
+    pub fn bpm(&self) -> Option<f64> {
+        let (first, last) = (self.taps.first()?, self.taps.last()?);
+        let intervals = self.taps.len() - 1;
+        if intervals == 0 {
+            return None;
+        }
+
+        let average = last.saturating_duration_since(*first).as_secs_f64() / intervals as f64;
+        if average <= 0.0 {
+            return None;
+        }
+        Some(60.0 / average)
+    }
+
+    pub fn reset(&mut self) {
+        self.taps.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn taps_average_into_a_tempo() {
+        let start = Instant::now();
+        let mut tap_tempo = TapTempo::new();
+        assert_eq!(tap_tempo.tap(start), None);
+        assert_eq!(tap_tempo.tap(start + ms(500)), Some(120.0));
+        // The intervals are 500ms and 700ms.
+        assert_eq!(tap_tempo.tap(start + ms(1_200)), Some(100.0));
+        assert_eq!(tap_tempo.bpm(), Some(100.0));
+
+        // Taps at the same time have no tempo.
+        let mut tap_tempo = TapTempo::new();
+        tap_tempo.tap(start);
+        assert_eq!(tap_tempo.tap(start), None);
+    }
+
+    #[test]
+    fn only_the_latest_taps_count() {
+        let start = Instant::now();
+        let mut tap_tempo = TapTempo::new();
+        let mut now = start;
+        // A slow start, then steady taps at 150 BPM.
+        for _ in 0..3 {
+            now += ms(1_000);
+            tap_tempo.tap(now);
+        }
+        for _ in 0..MAX_TAPS - 1 {
+            now += ms(400);
+            tap_tempo.tap(now);
+        }
+        assert_eq!(tap_tempo.bpm(), Some(150.0));
+    }
+
+    #[test]
+    fn a_long_pause_starts_over() {
+        let start = Instant::now();
+        let mut tap_tempo = TapTempo::new();
+        tap_tempo.tap(start);
+        tap_tempo.tap(start + ms(500));
+
+        // Two seconds is still the same run of taps.
+        assert_eq!(tap_tempo.tap(start + ms(2_500)), Some(60.0 / 1.25));
+        assert_eq!(tap_tempo.tap(start + ms(4_501)), None);
+        assert_eq!(tap_tempo.tap(start + ms(5_101)), Some(100.0));
+
+        tap_tempo.reset();
+        assert_eq!(tap_tempo.bpm(), None);
+        assert_eq!(tap_tempo.tap(start + ms(5_500)), None);
+    }
+}
@@ -0,0 +1,91 @@
+//! The current tempo of the project, shared with every node in the audio graph.
+
+use basedrop::{Shared, SharedCell};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::tempo_map::ProjectTempo;
+
+/// The current tempo in beats per minute, which the audio thread can read
+/// without locking. Every clone reads and writes the same tempo.
+///
+/// Nodes with tempo-synced parameters should read this once per process cycle
+/// and convert their `SyncedTime` values with it, so they follow every tempo
+/// change (including ramps, which are written once per block) without being
+/// told about it.
+///
+/// Nodes that need to know where the playhead is in beats have to use the
+/// whole `ProjectTempo` instead, since the current tempo says nothing about
+/// the tempo changes before the playhead.
+///
+/// TODO: Pass the tempo map to hosted plugins through the engine's
+/// transport. The internal plugins all read it from here, but the transport
+/// of the pinned dropseed has no way to set a tempo yet, so hosted plugins see
+/// its default.
+#[derive(Clone)]
+pub struct TempoBus {
+    bpm_bits: Arc<AtomicU64>,
+    tempo: Arc<SharedCell<ProjectTempo>>,
+    coll_handle: basedrop::Handle,
+}
+
+impl TempoBus {
+    pub fn new(coll_handle: &basedrop::Handle, bpm: f64) -> Self {
+        let tempo = Shared::new(coll_handle, ProjectTempo { start_bpm: bpm, ..Default::default() });
+        Self {
+            bpm_bits: Arc::new(AtomicU64::new(bpm.to_bits())),
+            tempo: Arc::new(SharedCell::new(tempo)),
+            coll_handle: coll_handle.clone(),
+        }
+    }
+
+    /// The tempo map the audio thread converts positions with. This is
+    /// realtime-safe, and the copy stays the same while it is held.
+    pub fn tempo(&self) -> Shared<ProjectTempo> {
+        self.tempo.get()
+    }
+
+    /// Replace the tempo map of the audio thread, i.e. after a tempo change
+    /// was added. This allocates, so it must not be called in the realtime
+    /// thread. The old copy is dropped by the collector.
+    pub fn set_tempo(&self, tempo: ProjectTempo) {
+        self.tempo.set(Shared::new(&self.coll_handle, tempo));
+    }
+
+    pub fn bpm(&self) -> f64 {
+        f64::from_bits(self.bpm_bits.load(Ordering::Relaxed))
+    }
+
+    pub fn set_bpm(&self, bpm: f64) {
+        self.bpm_bits.store(bpm.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl std::fmt::Debug for TempoBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TempoBus").field("bpm", &self.bpm()).finish()
+    }
+}
+
+/// A length of time that is either fixed or a number of beats that follows the
+/// tempo (i.e. a delay time or the period of an LFO).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncedTime {
+    Seconds(f32),
+    Beats(f32),
+}
+
+impl SyncedTime {
+    /// The length in seconds at the given tempo.
+    pub fn secs(&self, bpm: f64) -> f32 {
+        match self {
+            SyncedTime::Seconds(secs) => *secs,
+            SyncedTime::Beats(beats) => *beats * 60.0 / bpm as f32,
+        }
+    }
+
+    /// The rate in Hz of something that repeats once every this length.
+    pub fn hz(&self, bpm: f64) -> f32 {
+        1.0 / self.secs(bpm).max(f32::EPSILON)
+    }
+}
@@ -0,0 +1,370 @@
+//! Changes of the tempo over the course of the project.
+
+use serde::{Deserialize, Serialize};
+
+/// A change of the tempo at a position on the timeline.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TempoChange {
+    /// Where the change is, in beats.
+    pub beats: f64,
+    /// The tempo from here on, in beats per minute.
+    pub bpm: f64,
+    /// If true, the tempo glides from the previous change to this one instead
+    /// of jumping here.
+    pub ramp: bool,
+}
+
+impl TempoChange {
+    /// A change needs a positive tempo and a position that isn't before the
+    /// start.
+    pub fn is_valid(&self) -> bool {
+        self.bpm > 0.0 && self.bpm.is_finite() && self.beats >= 0.0 && self.beats.is_finite()
+    }
+}
+
+/// The tempo changes of a project, sorted by position. The tempo before the
+/// first change is the tempo of the project, which is given to every method
+/// as `start_bpm`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TempoMap {
+    changes: Vec<TempoChange>,
+}
+
+/// A stretch of the timeline from one change to the next.
+struct Segment {
+    beats: f64,
+    bpm: f64,
+    /// The change that ends this stretch, or `None` for the last one.
+    end: Option<TempoChange>,
+}
+
+impl Segment {
+    /// How much the tempo goes up per beat.
+    fn slope(&self) -> f64 {
+        match self.end {
+            Some(end) if end.ramp && end.beats > self.beats => {
+                (end.bpm - self.bpm) / (end.beats - self.beats)
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn bpm_at(&self, beats: f64) -> f64 {
+        self.bpm + self.slope() * (beats - self.beats)
+    }
+
+    /// The time from the start of this stretch to the given position.
+    fn seconds_to(&self, beats: f64) -> f64 {
+        let slope = self.slope();
+        if slope.abs() < f64::EPSILON {
+            (beats - self.beats) * 60.0 / self.bpm
+        } else {
+            60.0 / slope * (self.bpm_at(beats) / self.bpm).ln()
+        }
+    }
+
+    /// The position the given time after the start of this stretch.
+    fn beats_after(&self, seconds: f64) -> f64 {
+        let slope = self.slope();
+        if slope.abs() < f64::EPSILON {
+            self.beats + seconds * self.bpm / 60.0
+        } else {
+            self.beats + self.bpm * ((slope * seconds / 60.0).exp() - 1.0) / slope
+        }
+    }
+
+    fn contains(&self, beats: f64) -> bool {
+        self.end.map_or(true, |end| beats < end.beats)
+    }
+}
+
+impl TempoMap {
+    pub fn new(changes: Vec<TempoChange>) -> Self {
+        let mut map = Self::default();
+        for change in changes {
+            map.insert(change);
+        }
+        map
+    }
+
+    pub fn changes(&self) -> &[TempoChange] {
+        &self.changes
+    }
+
+    /// Add a change, replacing the one at the same position if there is one.
+    /// Returns the index of the change.
+    ///
+    /// Changes that aren't valid are ignored and return `None`.
+    pub fn insert(&mut self, change: TempoChange) -> Option<usize> {
+        if !change.is_valid() {
+            return None;
+        }
+
+        match self.changes.binary_search_by(|c| c.beats.total_cmp(&change.beats)) {
+            Ok(i) => {
+                self.changes[i] = change;
+                Some(i)
+            }
+            Err(i) => {
+                self.changes.insert(i, change);
+                Some(i)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<TempoChange> {
+        if index < self.changes.len() {
+            Some(self.changes.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// The stretches between the changes, from the start of the project on.
+    /// This doesn't allocate, so the audio thread can convert positions.
+    fn segments(&self, start_bpm: f64) -> impl Iterator<Item = Segment> + '_ {
+        let starts = std::iter::once((0.0, start_bpm))
+            .chain(self.changes.iter().map(|change| (change.beats, change.bpm)));
+        let ends = self.changes.iter().copied().map(Some).chain(std::iter::once(None));
+        starts.zip(ends).map(|((beats, bpm), end)| Segment { beats, bpm, end })
+    }
+
+    /// The tempo at the given position in beats.
+    pub fn bpm_at(&self, start_bpm: f64, beats: f64) -> f64 {
+        self.segments(start_bpm)
+            .find(|segment| segment.contains(beats))
+            .map_or(start_bpm, |segment| segment.bpm_at(beats))
+    }
+
+    /// The time from the start of the project to the given position.
+    pub fn beats_to_seconds(&self, start_bpm: f64, beats: f64) -> f64 {
+        let mut seconds = 0.0;
+        for segment in self.segments(start_bpm) {
+            match segment.end {
+                Some(end) if beats >= end.beats => seconds += segment.seconds_to(end.beats),
+                _ => return seconds + segment.seconds_to(beats),
+            }
+        }
+        seconds
+    }
+
+    /// The position at the given time from the start of the project.
+    pub fn seconds_to_beats(&self, start_bpm: f64, seconds: f64) -> f64 {
+        let mut elapsed = 0.0;
+        for segment in self.segments(start_bpm) {
+            if let Some(end) = segment.end {
+                let length = segment.seconds_to(end.beats);
+                if seconds >= elapsed + length {
+                    elapsed += length;
+                    continue;
+                }
+            }
+            return segment.beats_after(seconds - elapsed);
+        }
+        0.0
+    }
+}
+
+/// The tempo of a project at every position: the tempo it starts at and the
+/// changes after that. The audio thread gets a copy of this through the
+/// `TempoBus` to turn the playhead into beats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectTempo {
+    pub start_bpm: f64,
+    pub map: TempoMap,
+}
+
+impl ProjectTempo {
+    pub fn new(start_bpm: f64, map: TempoMap) -> Self {
+        Self { start_bpm, map }
+    }
+
+    pub fn bpm_at(&self, beats: f64) -> f64 {
+        self.map.bpm_at(self.start_bpm, beats)
+    }
+
+    /// The position in beats of a frame counted from the start of the
+    /// project.
+    pub fn beats_at_frame(&self, frame: u64, sample_rate: f64) -> f64 {
+        self.map.seconds_to_beats(self.start_bpm, frame as f64 / sample_rate)
+    }
+
+    /// The tempo at a frame counted from the start of the project, i.e. at
+    /// the playhead.
+    pub fn bpm_at_frame(&self, frame: u64, sample_rate: f64) -> f64 {
+        self.bpm_at(self.beats_at_frame(frame, sample_rate))
+    }
+
+    /// The frame counted from the start of the project that a position in
+    /// beats falls on. It can have a fraction.
+    pub fn frame_at_beats(&self, beats: f64, sample_rate: f64) -> f64 {
+        self.map.beats_to_seconds(self.start_bpm, beats) * sample_rate
+    }
+
+    /// The position in beats of the first frame of a block, and how far each
+    /// frame moves. Within a block the tempo is taken to change at a steady
+    /// rate, so the block still ends exactly where the next one starts.
+    pub fn block_beats(&self, frame: u64, frames: usize, sample_rate: f64) -> (f64, f64) {
+        let start_beat = self.beats_at_frame(frame, sample_rate);
+        if frames == 0 {
+            return (start_beat, self.bpm_at(start_beat) / 60.0 / sample_rate);
+        }
+        let end_beat = self.beats_at_frame(frame + frames as u64, sample_rate);
+        (start_beat, (end_beat - start_beat) / frames as f64)
+    }
+}
+
+impl Default for ProjectTempo {
+    fn default() -> Self {
+        Self::new(120.0, TempoMap::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn change(beats: f64, bpm: f64, ramp: bool) -> TempoChange {
+        TempoChange { beats, bpm, ramp }
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1.0e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn constant_tempo_without_changes() {
+        let map = TempoMap::default();
+        assert_close(map.bpm_at(120.0, 37.0), 120.0);
+        assert_close(map.beats_to_seconds(120.0, 8.0), 4.0);
+        assert_close(map.seconds_to_beats(120.0, 4.0), 8.0);
+    }
+
+    #[test]
+    fn tempo_jumps_at_a_change() {
+        let map = TempoMap::new(vec![change(4.0, 60.0, false)]);
+        assert_close(map.bpm_at(120.0, 3.999), 120.0);
+        assert_close(map.bpm_at(120.0, 4.0), 60.0);
+        // Two seconds for the first four beats, then one beat per second.
+        assert_close(map.beats_to_seconds(120.0, 6.0), 4.0);
+        assert_close(map.seconds_to_beats(120.0, 4.0), 6.0);
+    }
+
+    #[test]
+    fn tempo_ramps_into_a_change() {
+        let map = TempoMap::new(vec![change(4.0, 180.0, true)]);
+        assert_close(map.bpm_at(120.0, 2.0), 150.0);
+        assert_close(map.bpm_at(120.0, 10.0), 180.0);
+
+        // A ramp from 120 to 180 over 4 beats takes 60 / 15 * ln(1.5)
+        // seconds, which is between the times at either tempo.
+        let ramp_secs = 4.0 * (1.5f64).ln();
+        assert_close(map.beats_to_seconds(120.0, 4.0), ramp_secs);
+        assert!(ramp_secs > 4.0 * 60.0 / 180.0 && ramp_secs < 2.0);
+        assert_close(map.beats_to_seconds(120.0, 7.0), ramp_secs + 1.0);
+    }
+
+    #[test]
+    fn seconds_and_beats_round_trip() {
+        let map = TempoMap::new(vec![
+            change(2.0, 90.0, false),
+            change(6.0, 140.0, true),
+            change(9.0, 70.0, true),
+            change(12.0, 100.0, false),
+        ]);
+        for i in 0..200 {
+            let beats = f64::from(i) * 0.1;
+            let seconds = map.beats_to_seconds(120.0, beats);
+            assert_close(map.seconds_to_beats(120.0, seconds), beats);
+        }
+    }
+
+    #[test]
+    fn insert_keeps_changes_sorted_and_replaces() {
+        let mut map = TempoMap::default();
+        assert_eq!(map.insert(change(8.0, 100.0, false)), Some(0));
+        assert_eq!(map.insert(change(4.0, 90.0, false)), Some(0));
+        assert_eq!(map.insert(change(8.0, 110.0, true)), Some(1));
+        assert_eq!(map.changes(), &[change(4.0, 90.0, false), change(8.0, 110.0, true)]);
+
+        assert_eq!(map.insert(change(2.0, 0.0, false)), None);
+        assert_eq!(map.insert(change(-1.0, 100.0, false)), None);
+        assert_eq!(map.insert(change(f64::NAN, 100.0, false)), None);
+        assert_eq!(map.changes().len(), 2);
+
+        assert_eq!(map.remove(0), Some(change(4.0, 90.0, false)));
+        assert_eq!(map.remove(5), None);
+    }
+
+    #[test]
+    fn blocks_follow_the_tempo_map() {
+        let tempo = ProjectTempo::new(120.0, TempoMap::new(vec![change(4.0, 60.0, false)]));
+        let sample_rate = 48_000.0;
+
+        let (start, beats_per_frame) = tempo.block_beats(0, 512, sample_rate);
+        assert_close(start, 0.0);
+        assert_close(beats_per_frame, 2.0 / sample_rate);
+
+        // After the change a beat takes a whole second.
+        let frame = 3 * 48_000;
+        let (start, beats_per_frame) = tempo.block_beats(frame, 512, sample_rate);
+        assert_close(start, 5.0);
+        assert_close(beats_per_frame, 1.0 / sample_rate);
+        assert_close(tempo.frame_at_beats(5.0, sample_rate), frame as f64);
+
+        // Consecutive blocks line up.
+        let (start, beats_per_frame) = tempo.block_beats(95_900, 512, sample_rate);
+        assert_close(
+            tempo.beats_at_frame(95_900 + 512, sample_rate),
+            start + 512.0 * beats_per_frame,
+        );
+    }
+
+    #[test]
+    fn the_tempo_at_a_frame_follows_the_tempo_map() {
+        let tempo = ProjectTempo::new(120.0, TempoMap::new(vec![change(4.0, 60.0, false)]));
+        let sample_rate = 48_000.0;
+        assert_close(tempo.bpm_at_frame(0, sample_rate), 120.0);
+        // The change is 2 seconds in.
+        assert_close(tempo.bpm_at_frame(2 * 48_000 - 1, sample_rate), 120.0);
+        assert_close(tempo.bpm_at_frame(2 * 48_000, sample_rate), 60.0);
+    }
+
+    /// Any tempo map with up to eight changes, jumps and ramps mixed.
+    fn tempo_map() -> impl Strategy<Value = TempoMap> {
+        let change = (0.0..64.0f64, 20.0..300.0f64, any::<bool>())
+            .prop_map(|(beats, bpm, ramp)| TempoChange { beats, bpm, ramp });
+        prop::collection::vec(change, 0..8).prop_map(TempoMap::new)
+    }
+
+    proptest! {
+        #[test]
+        fn beats_round_trip_through_seconds(
+            map in tempo_map(),
+            start_bpm in 20.0..300.0f64,
+            beats in 0.0..128.0f64,
+        ) {
+            let seconds = map.beats_to_seconds(start_bpm, beats);
+            let back = map.seconds_to_beats(start_bpm, seconds);
+            prop_assert!((back - beats).abs() < 1.0e-6 * beats.max(1.0), "{} != {}", back, beats);
+        }
+
+        #[test]
+        fn time_only_moves_forward(
+            map in tempo_map(),
+            start_bpm in 20.0..300.0f64,
+            a in 0.0..128.0f64,
+            b in 0.0..128.0f64,
+        ) {
+            let (earlier, later) = if a <= b { (a, b) } else { (b, a) };
+            let earlier_secs = map.beats_to_seconds(start_bpm, earlier);
+            let later_secs = map.beats_to_seconds(start_bpm, later);
+            prop_assert!(earlier_secs <= later_secs);
+            prop_assert!(
+                map.seconds_to_beats(start_bpm, earlier_secs)
+                    <= map.seconds_to_beats(start_bpm, later_secs)
+            );
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Time signatures, and the bars they divide the timeline into.
+
+use serde::{Deserialize, Serialize};
+
+/// The most beats a bar can have.
+pub const MAX_TIME_SIGNATURE_NUMERATOR: u32 = 32;
+/// The shortest note value a beat can be, as the denominator of a time
+/// signature.
+pub const MAX_TIME_SIGNATURE_DENOMINATOR: u32 = 32;
+
+/// How many beats a bar has and how long each one is, i.e. 3/4 or 6/8.
+///
+/// Positions on the timeline are counted in quarter notes, so a beat of this
+/// signature is only a beat of the timeline when the denominator is 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self { numerator: 4, denominator: 4 }
+    }
+}
+
+impl TimeSignature {
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        Self { numerator, denominator }
+    }
+
+    /// A signature needs at least one beat per bar, and a denominator that
+    /// is a power of two.
+    pub fn is_valid(&self) -> bool {
+        (1..=MAX_TIME_SIGNATURE_NUMERATOR).contains(&self.numerator)
+            && self.denominator.is_power_of_two()
+            && self.denominator <= MAX_TIME_SIGNATURE_DENOMINATOR
+    }
+
+    /// The length of a beat of this signature in quarter notes.
+    pub fn beat_length(&self) -> f64 {
+        4.0 / f64::from(self.denominator)
+    }
+
+    /// The length of a bar in quarter notes.
+    pub fn bar_length(&self) -> f64 {
+        f64::from(self.numerator) * self.beat_length()
+    }
+}
+
+/// A change of the time signature at the start of a bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeSignatureChange {
+    /// The bar the signature starts at, counting from 0.
+    pub bar: u32,
+    pub signature: TimeSignature,
+}
+
+/// The time signatures of a project, sorted by bar. The project is in 4/4
+/// until the first change.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeSignatureTrack {
+    changes: Vec<TimeSignatureChange>,
+}
+
+/// Where a bar with a new signature starts.
+#[derive(Debug, Clone, Copy)]
+struct Section {
+    bar: u32,
+    beats: f64,
+    signature: TimeSignature,
+}
+
+/// A position on the timeline as the bar it is in and the beat of that bar,
+/// both counting from 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarPosition {
+    pub bar: u32,
+    /// In beats of the signature of the bar, so it can have a fraction.
+    pub beat: f64,
+    /// Where the bar starts, in quarter notes.
+    pub bar_start_beats: f64,
+}
+
+impl TimeSignatureTrack {
+    pub fn new(changes: Vec<TimeSignatureChange>) -> Self {
+        let mut track = Self::default();
+        for change in changes {
+            track.insert(change);
+        }
+        track
+    }
+
+    pub fn changes(&self) -> &[TimeSignatureChange] {
+        &self.changes
+    }
+
+    /// Add a change, replacing the one at the same bar if there is one.
+    /// Returns the index of the change, or `None` if its signature isn't
+    /// valid.
+    pub fn insert(&mut self, change: TimeSignatureChange) -> Option<usize> {
+        if !change.signature.is_valid() {
+            return None;
+        }
+
+        match self.changes.binary_search_by_key(&change.bar, |c| c.bar) {
+            Ok(i) => {
+                self.changes[i] = change;
+                Some(i)
+            }
+            Err(i) => {
+                self.changes.insert(i, change);
+                Some(i)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<TimeSignatureChange> {
+        (index < self.changes.len()).then(|| self.changes.remove(index))
+    }
+
+    /// The start of every stretch of bars with the same signature, the first
+    /// one at bar 0.
+    fn sections(&self) -> Vec<Section> {
+        let mut sections =
+            vec![Section { bar: 0, beats: 0.0, signature: TimeSignature::default() }];
+        for change in self.changes.iter() {
+            let last = sections[sections.len() - 1];
+            let beats = last.beats + f64::from(change.bar - last.bar) * last.signature.bar_length();
+            let section = Section { bar: change.bar, beats, signature: change.signature };
+            // A change at bar 0 replaces the default signature.
+            if change.bar == 0 {
+                sections[0] = section;
+            } else {
+                sections.push(section);
+            }
+        }
+        sections
+    }
+
+    /// The last section that starts at or before the position in quarter
+    /// notes.
+    fn section_at_beats(&self, beats: f64) -> Section {
+        let sections = self.sections();
+        let i = sections.partition_point(|section| section.beats <= beats);
+        sections[i.saturating_sub(1)]
+    }
+
+    /// The signature that applies at a position in quarter notes.
+    pub fn signature_at(&self, beats: f64) -> TimeSignature {
+        self.section_at_beats(beats).signature
+    }
+
+    /// The signature that applies at a position in quarter notes, and where
+    /// the bars with that signature start.
+    pub fn meter_at(&self, beats: f64) -> (TimeSignature, f64) {
+        let section = self.section_at_beats(beats);
+        (section.signature, section.beats)
+    }
+
+    /// The start of the first bar at or after a position in quarter notes.
+    pub fn next_bar(&self, beats: f64) -> f64 {
+        let position = self.position_at(beats);
+        if position.beat > f64::EPSILON {
+            self.beats_at(position.bar.saturating_add(1), 0.0)
+        } else {
+            position.bar_start_beats
+        }
+    }
+
+    /// The bar and beat a position in quarter notes falls on.
+    pub fn position_at(&self, beats: f64) -> BarPosition {
+        let section = self.section_at_beats(beats.max(0.0));
+        let bar_length = section.signature.bar_length();
+        let bars = ((beats.max(0.0) - section.beats) / bar_length).floor();
+        let bar_start_beats = section.beats + bars * bar_length;
+        BarPosition {
+            bar: section.bar.saturating_add(bars as u32),
+            beat: (beats.max(0.0) - bar_start_beats) / section.signature.beat_length(),
+            bar_start_beats,
+        }
+    }
+
+    /// The position in quarter notes of a beat of a bar, both counting from 0.
+    pub fn beats_at(&self, bar: u32, beat: f64) -> f64 {
+        let sections = self.sections();
+        let i = sections.partition_point(|section| section.bar <= bar);
+        let section = sections[i.saturating_sub(1)];
+        let signature = section.signature;
+        section.beats
+            + f64::from(bar - section.bar) * signature.bar_length()
+            + beat * signature.beat_length()
+    }
+
+    /// Show a position in quarter notes as the 1-based `bar:beat` it falls
+    /// on, like `Unit::BarsBeats` does for a single signature.
+    pub fn format_position(&self, beats: f64) -> String {
+        let mut position = self.position_at(beats);
+        let whole = (position.beat - position.beat.round()).abs() < 0.005;
+        if whole {
+            // Snap to the whole beat first, so a position just under the next
+            // bar isn't shown past the last beat.
+            position = self.position_at(self.beats_at(position.bar, position.beat.round()));
+        }
+        let beat = position.beat + 1.0;
+        if whole {
+            format!("{}:{}", position.bar + 1, beat.round() as i64)
+        } else {
+            format!("{}:{:.2}", position.bar + 1, beat)
+        }
+    }
+
+    /// Read a position written as `bar:beat` or `bar`, both 1-based, into
+    /// quarter notes.
+    pub fn parse_position(&self, text: &str) -> Option<f64> {
+        let text = text.trim();
+        let (bar, beat) = match text.split_once(':') {
+            Some((bar, beat)) => (bar.trim().parse::<f64>().ok()?, beat.trim().parse().ok()?),
+            None => (text.parse::<f64>().ok()?, 1.0),
+        };
+        if !(bar >= 1.0 && bar.fract() == 0.0 && bar <= f64::from(u32::MAX) && beat >= 1.0) {
+            return None;
+        }
+        Some(self.beats_at(bar as u32 - 1, beat - 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(bar: u32, numerator: u32, denominator: u32) -> TimeSignatureChange {
+        TimeSignatureChange { bar, signature: TimeSignature::new(numerator, denominator) }
+    }
+
+    /// 4/4 for two bars, then 3/4 for two bars, then 7/8.
+    fn track() -> TimeSignatureTrack {
+        TimeSignatureTrack::new(vec![change(4, 7, 8), change(2, 3, 4)])
+    }
+
+    #[test]
+    fn signatures_have_a_power_of_two_beat() {
+        assert!(TimeSignature::new(4, 4).is_valid());
+        assert!(TimeSignature::new(7, 8).is_valid());
+        assert!(TimeSignature::new(MAX_TIME_SIGNATURE_NUMERATOR, 1).is_valid());
+        assert!(!TimeSignature::new(0, 4).is_valid());
+        assert!(!TimeSignature::new(MAX_TIME_SIGNATURE_NUMERATOR + 1, 4).is_valid());
+        assert!(!TimeSignature::new(4, 6).is_valid());
+        assert!(!TimeSignature::new(4, 0).is_valid());
+        assert!(!TimeSignature::new(4, MAX_TIME_SIGNATURE_DENOMINATOR * 2).is_valid());
+
+        assert_eq!(TimeSignature::new(6, 8).beat_length(), 0.5);
+        assert_eq!(TimeSignature::new(6, 8).bar_length(), 3.0);
+        assert_eq!(TimeSignature::new(2, 2).bar_length(), 4.0);
+    }
+
+    #[test]
+    fn changes_stay_sorted_and_replace_the_one_at_their_bar() {
+        let mut track = track();
+        assert_eq!(track.changes(), [change(2, 3, 4), change(4, 7, 8)]);
+
+        assert_eq!(track.insert(change(2, 5, 4)), Some(0));
+        assert_eq!(track.insert(change(0, 6, 8)), Some(0));
+        assert_eq!(track.insert(change(3, 4, 3)), None);
+        assert_eq!(track.changes(), [change(0, 6, 8), change(2, 5, 4), change(4, 7, 8)]);
+
+        assert_eq!(track.remove(1), Some(change(2, 5, 4)));
+        assert_eq!(track.remove(2), None);
+        assert_eq!(track.changes(), [change(0, 6, 8), change(4, 7, 8)]);
+    }
+
+    #[test]
+    fn signatures_apply_from_the_start_of_their_bar() {
+        let track = track();
+        assert_eq!(track.signature_at(0.0), TimeSignature::default());
+        assert_eq!(track.signature_at(7.9), TimeSignature::default());
+        assert_eq!(track.meter_at(8.0), (TimeSignature::new(3, 4), 8.0));
+        assert_eq!(track.meter_at(13.9), (TimeSignature::new(3, 4), 8.0));
+        assert_eq!(track.meter_at(14.0), (TimeSignature::new(7, 8), 14.0));
+        assert_eq!(track.meter_at(100.0), (TimeSignature::new(7, 8), 14.0));
+
+        // A change at bar 0 replaces 4/4.
+        let track = TimeSignatureTrack::new(vec![change(0, 3, 4)]);
+        assert_eq!(track.meter_at(0.0), (TimeSignature::new(3, 4), 0.0));
+        assert_eq!(track.position_at(3.0).bar, 1);
+    }
+
+    #[test]
+    fn positions_are_counted_in_the_bars_of_their_signature() {
+        let track = track();
+        let position = |beats| {
+            let position = track.position_at(beats);
+            (position.bar, position.beat, position.bar_start_beats)
+        };
+        assert_eq!(position(0.0), (0, 0.0, 0.0));
+        assert_eq!(position(5.5), (1, 1.5, 4.0));
+        assert_eq!(position(11.0), (3, 0.0, 11.0));
+        // 7/8 counts in eighths.
+        assert_eq!(position(15.0), (4, 2.0, 14.0));
+        assert_eq!(position(17.5), (5, 0.0, 17.5));
+        // Before the start is the start.
+        assert_eq!(position(-1.0), (0, 0.0, 0.0));
+
+        for (bar, beat) in [(0, 0.0), (1, 3.5), (2, 0.0), (3, 2.0), (4, 6.0), (9, 1.0)] {
+            let beats = track.beats_at(bar, beat);
+            let position = track.position_at(beats);
+            assert_eq!((position.bar, position.beat), (bar, beat));
+        }
+    }
+
+    #[test]
+    fn next_bar_is_the_start_of_the_bar_at_or_after() {
+        let track = track();
+        assert_eq!(track.next_bar(0.0), 0.0);
+        assert_eq!(track.next_bar(0.5), 4.0);
+        assert_eq!(track.next_bar(8.0), 8.0);
+        assert_eq!(track.next_bar(9.0), 11.0);
+        assert_eq!(track.next_bar(12.0), 14.0);
+        assert_eq!(track.next_bar(14.5), 17.5);
+    }
+
+    #[test]
+    fn positions_are_shown_and_read_from_one() {
+        let track = track();
+        assert_eq!(track.format_position(0.0), "1:1");
+        assert_eq!(track.format_position(9.5), "3:2.50");
+        assert_eq!(track.format_position(15.0), "5:3");
+        // Just under the next bar is the next bar, not a beat past the last.
+        assert_eq!(track.format_position(10.999), "4:1");
+        assert_eq!(track.format_position(17.499), "6:1");
+
+        for beats in [0.0, 4.0, 9.5, 11.0, 15.0, 20.0] {
+            assert_eq!(track.parse_position(&track.format_position(beats)), Some(beats));
+        }
+        assert_eq!(track.parse_position(" 4 "), Some(11.0));
+        assert_eq!(track.parse_position("0:1"), None);
+        assert_eq!(track.parse_position("1:0"), None);
+        assert_eq!(track.parse_position("1.5"), None);
+        assert_eq!(track.parse_position("bar"), None);
+    }
+}
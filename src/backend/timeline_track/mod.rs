@@ -1,13 +1,71 @@
-use basedrop::Shared;
+use basedrop::{Owned, Shared};
 use dropseed::plugin::HostRequestChannelSender;
 use dropseed::plugin::{
     buffer::EventBuffer, ext, HostInfo, PluginActivatedInfo, PluginAudioThread, PluginDescriptor,
     PluginFactory, PluginInstanceID, PluginMainThread, ProcBuffers, ProcInfo, ProcessStatus,
 };
 use meadowlark_core_types::time::SampleRate;
+use pcm_loader::PcmRAM;
+use rtrb::{Consumer, Producer, RingBuffer};
 
 pub static TIMELINE_TRACK_PLUG_RDN: &str = "app.meadowlark.timeline-track";
 
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// An audio clip on the timeline, in frames from the start of the project.
+#[derive(Clone)]
+pub struct TrackClip {
+    pub start_frame: u64,
+    pub end_frame: u64,
+    /// The frame of the PCM resource that plays at the start of the clip.
+    pub pcm_start_frame: u64,
+    /// The gain of the clip as a factor.
+    pub gain: f32,
+    pub fade_in_frames: u64,
+    pub fade_out_frames: u64,
+    pub pcm: Shared<PcmRAM>,
+}
+
+impl PartialEq for TrackClip {
+    fn eq(&self, other: &Self) -> bool {
+        self.start_frame == other.start_frame
+            && self.end_frame == other.end_frame
+            && self.pcm_start_frame == other.pcm_start_frame
+            && self.gain == other.gain
+            && self.fade_in_frames == other.fade_in_frames
+            && self.fade_out_frames == other.fade_out_frames
+            && std::ptr::eq(&*self.pcm, &*other.pcm)
+    }
+}
+
+impl std::fmt::Debug for TrackClip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrackClip")
+            .field("start_frame", &self.start_frame)
+            .field("end_frame", &self.end_frame)
+            .field("pcm_start_frame", &self.pcm_start_frame)
+            .field("gain", &self.gain)
+            .finish()
+    }
+}
+
+/// The gain of the fades of a clip `length` frames long at a frame of it. The
+/// fades are linear, and a fade in that overlaps the fade out multiplies with
+/// it.
+pub fn fade_gain(frame: u64, length: u64, fade_in_frames: u64, fade_out_frames: u64) -> f32 {
+    let fade = |frames_from_edge: u64, fade_frames: u64| {
+        if frames_from_edge >= fade_frames {
+            1.0
+        } else {
+            frames_from_edge as f32 / fade_frames as f32
+        }
+    };
+    fade(frame, fade_in_frames) * fade(length.saturating_sub(frame + 1), fade_out_frames)
+}
+
+/// Plays the audio clips of a channel in time with the transport, out of its
+/// audio port. A clip with effects of its own gets a plugin of its own that
+/// only plays that clip, so the effects only process the clip.
 pub struct TimelineTrackPlugFactory;
 
 impl PluginFactory for TimelineTrackPlugFactory {
@@ -15,7 +73,7 @@ impl PluginFactory for TimelineTrackPlugFactory {
         PluginDescriptor {
             id: TIMELINE_TRACK_PLUG_RDN.into(),
             version: "0.1".into(),
-            name: "Sample Browser".into(),
+            name: "Timeline Track".into(),
             vendor: "Meadowlark".into(),
             description: String::new(),
             url: String::new(),
@@ -27,38 +85,67 @@ impl PluginFactory for TimelineTrackPlugFactory {
 
     fn instantiate(
         &mut self,
-        host_request_channel: HostRequestChannelSender,
+        _host_request_channel: HostRequestChannelSender,
         _host_info: Shared<HostInfo>,
         _plugin_id: PluginInstanceID,
         _coll_handle: &basedrop::Handle,
     ) -> Result<Box<dyn PluginMainThread>, String> {
-        Ok(Box::new(TimelineTrackPlugMainThread::new(host_request_channel)))
+        Ok(Box::new(TimelineTrackPlugMainThread))
     }
 }
 
-pub struct TimelineTrackPlugHandle {}
-
-pub struct TimelineTrackPlugMainThread {
-    host_request_channel: HostRequestChannelSender,
+pub struct TimelineTrackPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+    coll_handle: basedrop::Handle,
+    /// The clips that were sent last, so that they are only sent again when
+    /// they changed.
+    clips: Option<Shared<Vec<TrackClip>>>,
 }
 
-impl TimelineTrackPlugMainThread {
-    pub fn new(host_request_channel: HostRequestChannelSender) -> Self {
-        Self { host_request_channel }
+impl TimelineTrackPlugHandle {
+    /// Replace the clips that are played, e.g. after a clip was moved.
+    pub fn set_clips(&mut self, mut clips: Vec<TrackClip>) {
+        clips.sort_by_key(|clip| clip.start_frame);
+        if self.clips.as_deref() == Some(&clips) {
+            return;
+        }
+        let clips = Shared::new(&self.coll_handle, clips);
+        match self.to_audio_thread_tx.push(ProcessMsg::SetClips(Shared::clone(&clips))) {
+            Ok(()) => self.clips = Some(clips),
+            Err(e) => log::error!("Timeline track plugin failed to send message: {}", e),
+        }
     }
 }
 
+enum ProcessMsg {
+    SetClips(Shared<Vec<TrackClip>>),
+}
+
+pub struct TimelineTrackPlugMainThread;
+
 impl PluginMainThread for TimelineTrackPlugMainThread {
     fn activate(
         &mut self,
-        sample_rate: SampleRate,
+        _sample_rate: SampleRate,
         _min_frames: u32,
         max_frames: u32,
         coll_handle: &basedrop::Handle,
     ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
         Ok(PluginActivatedInfo {
-            audio_thread: Box::new(TimelineTrackPlugAudioThread {}),
-            internal_handle: Some(Box::new(TimelineTrackPlugHandle {})),
+            audio_thread: Box::new(TimelineTrackPlugAudioThread {
+                from_handle_rx,
+                clips: None,
+                clip_buf_l: Owned::new(coll_handle, vec![0.0; max_frames as usize]),
+                clip_buf_r: Owned::new(coll_handle, vec![0.0; max_frames as usize]),
+            }),
+            internal_handle: Some(Box::new(TimelineTrackPlugHandle {
+                to_audio_thread_tx,
+                coll_handle: coll_handle.clone(),
+                clips: None,
+            })),
         })
     }
 
@@ -67,7 +154,16 @@ impl PluginMainThread for TimelineTrackPlugMainThread {
     }
 }
 
-pub struct TimelineTrackPlugAudioThread {}
+pub struct TimelineTrackPlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    /// Sorted by their start.
+    clips: Option<Shared<Vec<TrackClip>>>,
+
+    /// Where each clip is read to before it is mixed into the output.
+    clip_buf_l: Owned<Vec<f32>>,
+    clip_buf_r: Owned<Vec<f32>>,
+}
 
 impl PluginAudioThread for TimelineTrackPlugAudioThread {
     fn start_processing(&mut self) -> Result<(), ()> {
@@ -80,11 +176,81 @@ impl PluginAudioThread for TimelineTrackPlugAudioThread {
         &mut self,
         proc_info: &ProcInfo,
         buffers: &mut ProcBuffers,
-        in_events: &EventBuffer,
+        _in_events: &EventBuffer,
         _out_events: &mut EventBuffer,
     ) -> ProcessStatus {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                // The old clips are dropped by the collector.
+                ProcessMsg::SetClips(clips) => self.clips = Some(clips),
+            }
+        }
+
+        let frames = proc_info.frames;
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+        out_l[0..frames].fill(0.0);
+        out_r[0..frames].fill(0.0);
+
+        let clips = match &self.clips {
+            Some(clips) if proc_info.transport.is_playing() => clips,
+            _ => return ProcessStatus::Continue,
+        };
+
+        let block_start = proc_info.transport.playhead_frame();
+        let block_end = block_start + frames as u64;
+        let last = clips.partition_point(|clip| clip.start_frame < block_end);
+        for clip in clips[..last].iter().filter(|clip| clip.end_frame > block_start) {
+            let from = clip.start_frame.max(block_start);
+            let to = clip.end_frame.min(block_end);
+            let offset = (from - block_start) as usize;
+            let len = (to - from) as usize;
+
+            let clip_buf_l = &mut self.clip_buf_l[0..len];
+            let clip_buf_r = &mut self.clip_buf_r[0..len];
+            let frame_in_clip = from - clip.start_frame;
+            clip.pcm.fill_stereo_f32(
+                (clip.pcm_start_frame + frame_in_clip) as usize,
+                clip_buf_l,
+                clip_buf_r,
+            );
+
+            let length = clip.end_frame - clip.start_frame;
+            for i in 0..len {
+                let gain = clip.gain
+                    * fade_gain(
+                        frame_in_clip + i as u64,
+                        length,
+                        clip.fade_in_frames,
+                        clip.fade_out_frames,
+                    );
+                out_l[offset + i] += clip_buf_l[i] * gain;
+                out_r[offset + i] += clip_buf_r[i] * gain;
+            }
+        }
+
         ProcessStatus::Continue
     }
 
-    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {}
+    fn param_flush(&mut self, _in_events: &EventBuffer, _out_events: &mut EventBuffer) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_ramp_from_the_edges_of_the_clip() {
+        assert_eq!(fade_gain(0, 100, 10, 0), 0.0);
+        assert_eq!(fade_gain(5, 100, 10, 0), 0.5);
+        assert_eq!(fade_gain(10, 100, 10, 0), 1.0);
+
+        assert_eq!(fade_gain(99, 100, 0, 10), 0.0);
+        assert_eq!(fade_gain(94, 100, 0, 10), 0.5);
+        assert_eq!(fade_gain(50, 100, 0, 10), 1.0);
+
+        // Without fades the whole clip plays at its gain.
+        assert!((0..100).all(|frame| fade_gain(frame, 100, 0, 0) == 1.0));
+        // Fades that overlap multiply.
+        assert_eq!(fade_gain(50, 100, 100, 100), 0.5 * (49.0 / 100.0));
+    }
 }
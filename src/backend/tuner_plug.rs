@@ -0,0 +1,249 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use super::dsp::pitch::{freq_to_midi_note, PitchDetector, DEFAULT_A4_FREQ};
+use super::dsp::{Biquad, BiquadCoeffs};
+use super::scale::NOTE_NAMES;
+
+pub static TUNER_PLUG_RDN: &str = "app.meadowlark.tuner";
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+/// The range of frequencies the tuner can detect. This covers a 5-string bass
+/// up to the highest frets of a guitar.
+const MIN_FREQ: f32 = 30.0;
+const MAX_FREQ: f32 = 1_500.0;
+
+/// The input is decimated by this factor before pitch detection. This keeps the
+/// cost of detecting low bass notes reasonable.
+const DECIMATION: usize = 4;
+
+/// Readings with a clarity lower than this are ignored.
+const MIN_CLARITY: f32 = 0.8;
+
+pub const MIN_REFERENCE_PITCH: f32 = 400.0;
+pub const MAX_REFERENCE_PITCH: f32 = 480.0;
+
+pub struct TunerPlugFactory;
+
+impl PluginFactory for TunerPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: TUNER_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Tuner".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(TunerPlugMainThread {}))
+    }
+}
+
+/// A single reading from the tuner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TunerReading {
+    /// The detected frequency in Hz.
+    pub freq: f32,
+    /// The nearest MIDI note to the detected frequency.
+    pub note: i32,
+    /// How far off the detected frequency is from `note` in the range `[-50.0, 50.0]`.
+    pub cents: f32,
+}
+
+impl TunerReading {
+    /// The name of the note with its octave (i.e. "A4").
+    pub fn note_name(&self) -> String {
+        format!("{}{}", NOTE_NAMES[self.note.rem_euclid(12) as usize], self.note.div_euclid(12) - 1)
+    }
+}
+
+pub struct TunerPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+
+    /// The detected frequency, or `0.0` if no pitch is detected.
+    freq: Arc<AtomicU32>,
+
+    reference_pitch: f32,
+}
+
+impl TunerPlugHandle {
+    /// The current reading, or `None` if no pitch is detected.
+    pub fn reading(&self) -> Option<TunerReading> {
+        let freq = f32::from_bits(self.freq.load(Ordering::Relaxed));
+        if freq <= 0.0 {
+            return None;
+        }
+
+        let exact_note = freq_to_midi_note(freq, self.reference_pitch);
+        let note = exact_note.round();
+
+        Some(TunerReading { freq, note: note as i32, cents: (exact_note - note) * 100.0 })
+    }
+
+    /// The frequency of A4 that the readings are relative to.
+    pub fn reference_pitch(&self) -> f32 {
+        self.reference_pitch
+    }
+
+    pub fn set_reference_pitch(&mut self, freq: f32) {
+        self.reference_pitch = freq.clamp(MIN_REFERENCE_PITCH, MAX_REFERENCE_PITCH);
+    }
+
+    /// Silence the output of the tuner while still analyzing the input.
+    pub fn set_mute(&mut self, mute: bool) {
+        if let Err(e) = self.to_audio_thread_tx.push(ProcessMsg::SetMute(mute)) {
+            log::error!("Tuner plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetMute(bool),
+}
+
+pub struct TunerPlugMainThread {}
+
+impl PluginMainThread for TunerPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        _max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let sr = sample_rate.0 as f32;
+        let decimated_sr = sr / DECIMATION as f32;
+
+        let freq = Arc::new(AtomicU32::new(0.0f32.to_bits()));
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(TunerPlugAudioThread {
+                from_handle_rx,
+                mute: false,
+                anti_alias: Biquad::new(BiquadCoeffs::lowpass(sr, decimated_sr * 0.4, 0.707)),
+                decimation_count: 0,
+                detector: Owned::new(
+                    coll_handle,
+                    PitchDetector::new(decimated_sr, MIN_FREQ, MAX_FREQ),
+                ),
+                freq: Arc::clone(&freq),
+            }),
+            internal_handle: Some(Box::new(TunerPlugHandle {
+                to_audio_thread_tx,
+                freq,
+                reference_pitch: DEFAULT_A4_FREQ,
+            })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<ext::audio_ports::PluginAudioPortsExt, String> {
+        Ok(ext::audio_ports::PluginAudioPortsExt::stereo_in_out())
+    }
+}
+
+pub struct TunerPlugAudioThread {
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    mute: bool,
+
+    anti_alias: Biquad,
+    decimation_count: usize,
+    detector: Owned<PitchDetector>,
+
+    freq: Arc<AtomicU32>,
+}
+
+impl TunerPlugAudioThread {
+    fn poll(&mut self) {
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetMute(mute) => self.mute = mute,
+            }
+        }
+    }
+}
+
+impl PluginAudioThread for TunerPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        self.anti_alias.reset();
+        self.detector.reset();
+        self.freq.store(0.0f32.to_bits(), Ordering::Relaxed);
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        _in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll();
+
+        let frames = proc_info.frames;
+
+        let (in_l, in_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let in_l = &in_l[0..frames];
+        let in_r = &in_r[0..frames];
+        let out_l = &mut out_l[0..frames];
+        let out_r = &mut out_r[0..frames];
+
+        let mut new_estimate = false;
+        for i in 0..frames {
+            let x = self.anti_alias.process((in_l[i] + in_r[i]) * 0.5);
+
+            self.decimation_count += 1;
+            if self.decimation_count >= DECIMATION {
+                self.decimation_count = 0;
+                new_estimate |= self.detector.push(x);
+            }
+        }
+
+        if new_estimate {
+            let freq = match self.detector.pitch() {
+                Some(freq) if self.detector.clarity() >= MIN_CLARITY => freq,
+                _ => 0.0,
+            };
+            self.freq.store(freq.to_bits(), Ordering::Relaxed);
+        }
+
+        if self.mute {
+            out_l.fill(0.0);
+            out_r.fill(0.0);
+        } else {
+            out_l.copy_from_slice(in_l);
+            out_r.copy_from_slice(in_r);
+        }
+
+        ProcessStatus::Continue
+    }
+}
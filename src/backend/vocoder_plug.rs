@@ -0,0 +1,444 @@
+use basedrop::{Owned, Shared};
+use dropseed::plugin::event::ParamValueEvent;
+use dropseed::plugin::ext::audio_ports::{
+    AudioPortInfo, AudioPortType, MainPortsLayout, PluginAudioPortsExt,
+};
+use dropseed::plugin::ext::params::{ParamID, ParamInfoFlags};
+use dropseed::plugin::{
+    buffer::EventBuffer, ext, HostInfo, HostRequestChannelSender, PluginActivatedInfo,
+    PluginAudioThread, PluginDescriptor, PluginFactory, PluginInstanceID, PluginMainThread,
+    ProcBuffers, ProcInfo, ProcessStatus,
+};
+use meadowlark_core_types::parameter::{
+    Gradient, ParamF32, ParamF32Handle, Unit, DEFAULT_DB_GRADIENT, DEFAULT_SMOOTH_SECS,
+};
+use meadowlark_core_types::time::SampleRate;
+use rtrb::{Consumer, Producer, RingBuffer};
+
+use super::dsp::{db_to_gain, pitch::semitones_to_ratio, Biquad, BiquadCoeffs, EnvelopeFollower};
+
+pub static VOCODER_PLUG_RDN: &str = "app.meadowlark.vocoder";
+
+/// The stable ID of the main input port, which carries the modulator (i.e. the vocals).
+pub const MODULATOR_PORT_ID: u32 = 0;
+/// The stable ID of the sidechain input port, which carries the carrier (i.e. the synth).
+pub const CARRIER_PORT_ID: u32 = 1;
+
+const MSG_BUFFER_SIZE: usize = 16;
+
+pub const MIN_BANDS: usize = 4;
+pub const MAX_BANDS: usize = 32;
+pub const DEFAULT_BANDS: usize = 16;
+
+const LOWEST_BAND_FREQ: f32 = 100.0;
+const HIGHEST_BAND_FREQ: f32 = 8_000.0;
+
+const ENVELOPE_ATTACK_SECS: f32 = 0.002;
+
+/// Sibilance in the modulator above this frequency is passed straight through,
+/// since most carriers don't have enough high end to make consonants intelligible.
+const SIBILANCE_HPF_FREQ: f32 = 5_000.0;
+
+pub struct VocoderPlugFactory;
+
+impl PluginFactory for VocoderPlugFactory {
+    fn description(&self) -> PluginDescriptor {
+        PluginDescriptor {
+            id: VOCODER_PLUG_RDN.into(),
+            version: "0.1".into(),
+            name: "Vocoder".into(),
+            vendor: "Meadowlark".into(),
+            description: String::new(),
+            url: String::new(),
+            manual_url: String::new(),
+            support_url: String::new(),
+            features: String::new(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        _host_request_channel: HostRequestChannelSender,
+        _host_info: Shared<HostInfo>,
+        _plugin_id: PluginInstanceID,
+        _coll_handle: &basedrop::Handle,
+    ) -> Result<Box<dyn PluginMainThread>, String> {
+        Ok(Box::new(VocoderPlugMainThread::new()))
+    }
+}
+
+pub struct VocoderPlugHandle {
+    to_audio_thread_tx: Producer<ProcessMsg>,
+}
+
+impl VocoderPlugHandle {
+    /// Set the number of bands. This is clamped to the range `[MIN_BANDS, MAX_BANDS]`.
+    pub fn set_num_bands(&mut self, num_bands: usize) {
+        self.send(ProcessMsg::SetNumBands(num_bands.clamp(MIN_BANDS, MAX_BANDS)));
+    }
+
+    fn send(&mut self, msg: ProcessMsg) {
+        if let Err(e) = self.to_audio_thread_tx.push(msg) {
+            log::error!("Vocoder plugin failed to send message: {}", e);
+        }
+    }
+}
+
+enum ProcessMsg {
+    SetNumBands(usize),
+}
+
+struct ParamsHandle {
+    pub release: ParamF32Handle,
+    pub formant: ParamF32Handle,
+    pub sibilance: ParamF32Handle,
+    pub output: ParamF32Handle,
+}
+
+struct Params {
+    pub release: ParamF32,
+    pub formant: ParamF32,
+    pub sibilance: ParamF32,
+    pub output: ParamF32,
+}
+
+impl Params {
+    fn new(sample_rate: SampleRate, max_frames: usize) -> (Self, ParamsHandle) {
+        let (release, release_handle) = ParamF32::from_value(
+            40.0,
+            40.0,
+            5.0,
+            500.0,
+            Gradient::Exponential,
+            Unit::Generic,
+            0.0,
+            sample_rate,
+            max_frames,
+        );
+
+        let (formant, formant_handle) = ParamF32::from_value(
+            0.0,
+            0.0,
+            -12.0,
+            12.0,
+            Gradient::Linear,
+            Unit::Generic,
+            0.0,
+            sample_rate,
+            max_frames,
+        );
+
+        let (sibilance, sibilance_handle) = ParamF32::from_value(
+            -12.0,
+            -12.0,
+            -90.0,
+            0.0,
+            DEFAULT_DB_GRADIENT,
+            Unit::Decibels,
+            DEFAULT_SMOOTH_SECS,
+            sample_rate,
+            max_frames,
+        );
+
+        let (output, output_handle) = ParamF32::from_value(
+            0.0,
+            0.0,
+            -90.0,
+            12.0,
+            DEFAULT_DB_GRADIENT,
+            Unit::Decibels,
+            DEFAULT_SMOOTH_SECS,
+            sample_rate,
+            max_frames,
+        );
+
+        (
+            Params { release, formant, sibilance, output },
+            ParamsHandle {
+                release: release_handle,
+                formant: formant_handle,
+                sibilance: sibilance_handle,
+                output: output_handle,
+            },
+        )
+    }
+}
+
+pub struct VocoderPlugMainThread {
+    params: ParamsHandle,
+}
+
+impl VocoderPlugMainThread {
+    fn new() -> Self {
+        // These parameters will be re-initialized later with the correct sample_rate
+        // and max_frames when the plugin is activated.
+        let (_params, params_handle) = Params::new(Default::default(), 0);
+
+        Self { params: params_handle }
+    }
+}
+
+impl PluginMainThread for VocoderPlugMainThread {
+    fn activate(
+        &mut self,
+        sample_rate: SampleRate,
+        _min_frames: u32,
+        max_frames: u32,
+        coll_handle: &basedrop::Handle,
+    ) -> Result<PluginActivatedInfo, String> {
+        let (params, params_handle) = Params::new(sample_rate, max_frames as usize);
+        self.params = params_handle;
+
+        let (to_audio_thread_tx, from_handle_rx) = RingBuffer::<ProcessMsg>::new(MSG_BUFFER_SIZE);
+        let from_handle_rx = Owned::new(coll_handle, from_handle_rx);
+
+        let sr = sample_rate.0 as f32;
+
+        let mut audio_thread = VocoderPlugAudioThread {
+            params,
+            from_handle_rx,
+            sample_rate: sr,
+            bands: [Band::default(); MAX_BANDS],
+            num_bands: DEFAULT_BANDS,
+            band_settings: (f32::NAN, f32::NAN),
+            sibilance_hpf: Biquad::new(BiquadCoeffs::highpass(sr, SIBILANCE_HPF_FREQ, 0.707)),
+        };
+        audio_thread.update_bands();
+
+        Ok(PluginActivatedInfo {
+            audio_thread: Box::new(audio_thread),
+            internal_handle: Some(Box::new(VocoderPlugHandle { to_audio_thread_tx })),
+        })
+    }
+
+    fn audio_ports_ext(&mut self) -> Result<PluginAudioPortsExt, String> {
+        Ok(PluginAudioPortsExt {
+            inputs: vec![
+                AudioPortInfo {
+                    stable_id: MODULATOR_PORT_ID,
+                    channels: 2,
+                    port_type: AudioPortType::Stereo,
+                    display_name: Some("modulator".into()),
+                },
+                AudioPortInfo {
+                    stable_id: CARRIER_PORT_ID,
+                    channels: 2,
+                    port_type: AudioPortType::Stereo,
+                    display_name: Some("carrier".into()),
+                },
+            ],
+            outputs: vec![AudioPortInfo {
+                stable_id: 0,
+                channels: 2,
+                port_type: AudioPortType::Stereo,
+                display_name: None,
+            }],
+            main_ports_layout: MainPortsLayout::InOut,
+        })
+    }
+
+    // --- Parameters ---------------------------------------------------------------------------------
+
+    fn num_params(&mut self) -> u32 {
+        4
+    }
+
+    fn param_info(&mut self, param_index: usize) -> Result<ext::params::ParamInfo, ()> {
+        let (name, min, max, default) = match param_index {
+            0 => ("release", 5.0, 500.0, 40.0),
+            1 => ("formant", -12.0, 12.0, 0.0),
+            2 => ("sibilance", -90.0, 0.0, -12.0),
+            3 => ("output", -90.0, 12.0, 0.0),
+            _ => return Err(()),
+        };
+
+        Ok(ext::params::ParamInfo::new(
+            ParamID(param_index as u32),
+            ParamInfoFlags::default_float(),
+            name.into(),
+            String::new(),
+            min,
+            max,
+            default,
+        ))
+    }
+
+    fn param_value(&self, param_id: ParamID) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) => Ok(f64::from(self.params.release.value())),
+            ParamID(1) => Ok(f64::from(self.params.formant.value())),
+            ParamID(2) => Ok(f64::from(self.params.sibilance.value())),
+            ParamID(3) => Ok(f64::from(self.params.output.value())),
+            _ => Err(()),
+        }
+    }
+
+    fn param_value_to_text(&self, param_id: ParamID, value: f64) -> Result<String, ()> {
+        match param_id {
+            ParamID(0) => Ok(format!("{:.0} ms", value)),
+            ParamID(1) => Ok(format!("{:.1} st", value)),
+            ParamID(2) | ParamID(3) => Ok(format!("{:.2} dB", value)),
+            _ => Err(()),
+        }
+    }
+
+    fn param_text_to_value(&self, param_id: ParamID, text: &str) -> Result<f64, ()> {
+        match param_id {
+            ParamID(0) | ParamID(1) | ParamID(2) | ParamID(3) => text.parse().map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+struct Band {
+    /// Analyzes the modulator.
+    modulator: Biquad,
+    /// Filters the carrier. When the formant is shifted, this band's frequency
+    /// is different from the analysis band's frequency.
+    carrier_l: Biquad,
+    carrier_r: Biquad,
+    envelope: EnvelopeFollower,
+}
+
+pub struct VocoderPlugAudioThread {
+    params: Params,
+
+    from_handle_rx: Owned<Consumer<ProcessMsg>>,
+
+    sample_rate: f32,
+
+    bands: [Band; MAX_BANDS],
+    num_bands: usize,
+    /// The release and formant settings the bands were last configured with.
+    band_settings: (f32, f32),
+
+    sibilance_hpf: Biquad,
+}
+
+impl VocoderPlugAudioThread {
+    fn poll(&mut self, in_events: &EventBuffer) {
+        for e in in_events.iter() {
+            if let Some(param_value) = e.as_event::<ParamValueEvent>() {
+                let value = param_value.value() as f32;
+                match param_value.param_id() {
+                    0 => self.params.release.set_value(value),
+                    1 => self.params.formant.set_value(value),
+                    2 => self.params.sibilance.set_value(value),
+                    3 => self.params.output.set_value(value),
+                    _ => {}
+                }
+            }
+        }
+
+        while let Ok(msg) = self.from_handle_rx.pop() {
+            match msg {
+                ProcessMsg::SetNumBands(num_bands) => {
+                    if self.num_bands != num_bands {
+                        self.num_bands = num_bands;
+                        // Force the bands to be recalculated.
+                        self.band_settings = (f32::NAN, f32::NAN);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Spread the bands logarithmically between `LOWEST_BAND_FREQ` and `HIGHEST_BAND_FREQ`.
+    fn update_bands(&mut self) {
+        let release_ms = self.params.release.smoothed(1)[0];
+        let formant = self.params.formant.smoothed(1)[0];
+
+        if (release_ms, formant) == self.band_settings {
+            return;
+        }
+        let bands_changed = formant != self.band_settings.1 || self.band_settings.1.is_nan();
+        self.band_settings = (release_ms, formant);
+
+        let sr = self.sample_rate;
+        let nyquist_limit = sr * 0.45;
+
+        let num_bands = self.num_bands;
+        let ratio = (HIGHEST_BAND_FREQ / LOWEST_BAND_FREQ).powf(1.0 / (num_bands - 1) as f32);
+        // Make neighbouring bands cross over at roughly -3dB.
+        let q = ratio.sqrt() / (ratio - 1.0);
+        let formant_ratio = semitones_to_ratio(formant);
+
+        for (i, band) in self.bands[0..num_bands].iter_mut().enumerate() {
+            band.envelope.set_times(sr, ENVELOPE_ATTACK_SECS, release_ms / 1_000.0);
+
+            if bands_changed {
+                let freq = (LOWEST_BAND_FREQ * ratio.powi(i as i32)).min(nyquist_limit);
+                let carrier_freq = (freq * formant_ratio).min(nyquist_limit);
+
+                band.modulator.coeffs = BiquadCoeffs::bandpass(sr, freq, q);
+                band.carrier_l.coeffs = BiquadCoeffs::bandpass(sr, carrier_freq, q);
+                band.carrier_r.coeffs = band.carrier_l.coeffs;
+            }
+        }
+    }
+}
+
+impl PluginAudioThread for VocoderPlugAudioThread {
+    fn start_processing(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn stop_processing(&mut self) {
+        for band in self.bands.iter_mut() {
+            band.modulator.reset();
+            band.carrier_l.reset();
+            band.carrier_r.reset();
+            band.envelope.reset();
+        }
+        self.sibilance_hpf.reset();
+    }
+
+    fn process(
+        &mut self,
+        proc_info: &ProcInfo,
+        buffers: &mut ProcBuffers,
+        in_events: &EventBuffer,
+        _out_events: &mut EventBuffer,
+    ) -> ProcessStatus {
+        self.poll(in_events);
+        self.update_bands();
+
+        let frames = proc_info.frames;
+
+        let (mod_l, mod_r) = buffers.audio_in[0].stereo_f32().unwrap();
+        let (car_l, car_r) = buffers.audio_in[1].stereo_f32().unwrap();
+        let (mut out_l, mut out_r) = buffers.audio_out[0].stereo_f32_mut().unwrap();
+
+        let sibilance = self.params.sibilance.smoothed(frames);
+        let output = self.params.output.smoothed(frames);
+
+        // Compensate for the energy lost by splitting the carrier into narrow bands.
+        let band_gain = (self.num_bands as f32).sqrt();
+
+        for i in 0..frames {
+            let modulator = (mod_l[i] + mod_r[i]) * 0.5;
+
+            let mut l = 0.0;
+            let mut r = 0.0;
+            for band in self.bands[0..self.num_bands].iter_mut() {
+                let env = band.envelope.process(band.modulator.process(modulator));
+
+                l += band.carrier_l.process(car_l[i]) * env;
+                r += band.carrier_r.process(car_r[i]) * env;
+            }
+
+            let sibilance = self.sibilance_hpf.process(modulator) * db_to_gain(sibilance[i]);
+
+            let gain = db_to_gain(output[i]);
+            out_l[i] = (l * band_gain + sibilance) * gain;
+            out_r[i] = (r * band_gain + sibilance) * gain;
+        }
+
+        ProcessStatus::Continue
+    }
+
+    fn param_flush(&mut self, in_events: &EventBuffer, _out_events: &mut EventBuffer) {
+        self.poll(in_events);
+    }
+}
@@ -0,0 +1,334 @@
+//! Precomputed peaks for drawing waveforms.
+//!
+//! Drawing a waveform means finding the minimum and maximum sample of every
+//! column of pixels. Doing that straight from the samples gets slower the
+//! further out the view is zoomed, since every sample has to be looked at on
+//! every redraw. Instead the peaks are computed once at several resolutions,
+//! and drawing uses the coarsest resolution that is still finer than a column.
+
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// The number of frames summarized by each peak at the finest resolution.
+/// Below this the samples themselves are used.
+const BASE_BLOCK_FRAMES: usize = 32;
+
+/// Appended to the name of an audio file to get the name of its peak file.
+pub const PEAK_FILE_EXTENSION: &str = "peaks";
+
+const PEAK_FILE_MAGIC: &[u8; 4] = b"MLPK";
+const PEAK_FILE_VERSION: u32 = 1;
+
+/// The `(min, max)` of a range of samples.
+pub type Peak = (f32, f32);
+
+fn fold_peaks(peaks: impl Iterator<Item = Peak>) -> Peak {
+    peaks.fold((0.0, 0.0), |(min, max), (a, b)| (min.min(a), max.max(b)))
+}
+
+/// The peaks of one channel at several resolutions.
+#[derive(Debug, Clone)]
+pub struct WaveformPeaks {
+    len_frames: usize,
+
+    /// `levels[0]` holds one peak per `BASE_BLOCK_FRAMES` frames, and every
+    /// level after that holds one peak per two peaks of the level before it.
+    levels: Vec<Vec<Peak>>,
+}
+
+impl WaveformPeaks {
+    pub fn new(samples: &[f32]) -> Self {
+        let mut builder = PeakBuilder::default();
+        builder.push(samples);
+        builder.finish()
+    }
+
+    /// Build the coarser levels on top of the finest one, i.e. after reading
+    /// it from a peak file.
+    fn from_base_level(len_frames: usize, base: Vec<Peak>) -> Self {
+        let mut levels = vec![base];
+
+        while levels.last().map(|level| level.len() > 1).unwrap_or(false) {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| fold_peaks(pair.iter().copied()))
+                .collect();
+            levels.push(next);
+        }
+
+        Self { len_frames, levels }
+    }
+
+    pub fn len_frames(&self) -> usize {
+        self.len_frames
+    }
+
+    /// The peak of each of `columns` equally sized columns covering `range` (in
+    /// frames). `samples` must be the samples these peaks were computed from,
+    /// and are used when zoomed in too far for the precomputed peaks. Without
+    /// them (i.e. on the timeline, which doesn't keep the samples) the finest
+    /// peaks are used instead.
+    pub fn columns(&self, samples: &[f32], range: Range<usize>, columns: usize) -> Vec<Peak> {
+        let range = range.start.min(self.len_frames)..range.end.min(self.len_frames);
+        if columns == 0 || range.is_empty() {
+            return Vec::new();
+        }
+
+        let frames_per_column = (range.end - range.start) as f64 / columns as f64;
+
+        // The coarsest level that still has at least one peak per column.
+        let mut level = None;
+        let mut block = BASE_BLOCK_FRAMES;
+        for i in 0..self.levels.len() {
+            if block as f64 > frames_per_column {
+                break;
+            }
+            level = Some(i);
+            block *= 2;
+        }
+        if level.is_none() && samples.is_empty() && !self.levels.is_empty() {
+            level = Some(0);
+            block *= 2;
+        }
+        let block = block / 2;
+
+        (0..columns)
+            .map(|column| {
+                let start = range.start + (column as f64 * frames_per_column) as usize;
+                let end = (range.start + ((column + 1) as f64 * frames_per_column) as usize)
+                    .min(range.end)
+                    .max(start + 1)
+                    .min(self.len_frames);
+                if start >= end {
+                    return (0.0, 0.0);
+                }
+
+                match level {
+                    Some(level) => {
+                        let peaks = &self.levels[level];
+                        let first = start / block;
+                        let last = ((end + block - 1) / block).min(peaks.len());
+                        fold_peaks(peaks[first..last].iter().copied())
+                    }
+                    // The peaks may be from a peak file that doesn't quite match.
+                    None => {
+                        fold_peaks(samples.get(start..end).unwrap_or(&[]).iter().map(|s| (*s, *s)))
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Computes the peaks of a channel from its samples a chunk at a time, so a
+/// long file never has to be in memory as one buffer of samples.
+#[derive(Debug, Default)]
+pub struct PeakBuilder {
+    len_frames: usize,
+    base: Vec<Peak>,
+}
+
+impl PeakBuilder {
+    /// Add the next samples of the channel. The chunks don't have to line up
+    /// with the blocks of the peaks.
+    pub fn push(&mut self, mut samples: &[f32]) {
+        let filled = self.len_frames % BASE_BLOCK_FRAMES;
+        if filled != 0 {
+            let (rest, after) = samples.split_at((BASE_BLOCK_FRAMES - filled).min(samples.len()));
+            if let Some(last) = self.base.last_mut() {
+                *last = fold_peaks(std::iter::once(*last).chain(rest.iter().map(|s| (*s, *s))));
+            }
+            self.len_frames += rest.len();
+            samples = after;
+        }
+
+        self.base.extend(
+            samples
+                .chunks(BASE_BLOCK_FRAMES)
+                .map(|chunk| fold_peaks(chunk.iter().map(|s| (*s, *s)))),
+        );
+        self.len_frames += samples.len();
+    }
+
+    pub fn finish(self) -> WaveformPeaks {
+        WaveformPeaks::from_base_level(self.len_frames, self.base)
+    }
+}
+
+/// The peak file of an audio file, which is kept next to it.
+pub fn peak_file_path(source: &Path) -> PathBuf {
+    let mut name = source.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(PEAK_FILE_EXTENSION);
+    source.with_file_name(name)
+}
+
+/// What a peak file was computed from. A peak file is stale once any of this
+/// changes, i.e. when the audio file was edited or is loaded at another
+/// sample rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PeakFileSource {
+    len_bytes: u64,
+    modified_secs: u64,
+    sample_rate: u32,
+}
+
+impl PeakFileSource {
+    fn of(source: &Path, sample_rate: u32) -> io::Result<Self> {
+        let metadata = std::fs::metadata(source)?;
+        let modified_secs = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        Ok(Self { len_bytes: metadata.len(), modified_secs, sample_rate })
+    }
+}
+
+/// Write the peaks of the channels of an audio file, computed at the given
+/// sample rate, to its peak file. Only the finest level is stored, since the
+/// others are quick to build from it.
+pub fn save_peak_file(
+    source: &Path,
+    sample_rate: u32,
+    channels: &[WaveformPeaks],
+) -> io::Result<()> {
+    let from = PeakFileSource::of(source, sample_rate)?;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(PEAK_FILE_MAGIC);
+    bytes.extend_from_slice(&PEAK_FILE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&from.len_bytes.to_le_bytes());
+    bytes.extend_from_slice(&from.modified_secs.to_le_bytes());
+    bytes.extend_from_slice(&from.sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(BASE_BLOCK_FRAMES as u32).to_le_bytes());
+    bytes.extend_from_slice(&(channels.len() as u32).to_le_bytes());
+    for channel in channels {
+        let base = channel.levels.first().map(Vec::as_slice).unwrap_or(&[]);
+        bytes.extend_from_slice(&(channel.len_frames as u64).to_le_bytes());
+        bytes.extend_from_slice(&(base.len() as u64).to_le_bytes());
+        for (min, max) in base {
+            bytes.extend_from_slice(&min.to_le_bytes());
+            bytes.extend_from_slice(&max.to_le_bytes());
+        }
+    }
+
+    std::fs::File::create(peak_file_path(source))?.write_all(&bytes)
+}
+
+/// Read the peaks of the channels of an audio file from its peak file.
+/// Returns `None` if there is no peak file, or if it is stale.
+pub fn load_peak_file(source: &Path, sample_rate: u32) -> io::Result<Option<Vec<WaveformPeaks>>> {
+    let mut file = match std::fs::File::open(peak_file_path(source)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    let mut reader = PeakFileReader { bytes: &bytes };
+
+    if reader.take(4)? != PEAK_FILE_MAGIC || reader.u32()? != PEAK_FILE_VERSION {
+        return Ok(None);
+    }
+    let from = PeakFileSource {
+        len_bytes: reader.u64()?,
+        modified_secs: reader.u64()?,
+        sample_rate: reader.u32()?,
+    };
+    if from != PeakFileSource::of(source, sample_rate)?
+        || reader.u32()? as usize != BASE_BLOCK_FRAMES
+    {
+        return Ok(None);
+    }
+
+    let num_channels = reader.u32()?;
+    let mut channels = Vec::new();
+    for _ in 0..num_channels {
+        let len_frames = reader.u64()? as usize;
+        let len = reader.u64()? as usize;
+        if len != len_frames / BASE_BLOCK_FRAMES + usize::from(len_frames % BASE_BLOCK_FRAMES != 0)
+        {
+            return Err(invalid_peak_file());
+        }
+        let mut base = Vec::with_capacity(len.min(reader.bytes.len() / 8));
+        for _ in 0..len {
+            base.push((reader.f32()?, reader.f32()?));
+        }
+        channels.push(WaveformPeaks::from_base_level(len_frames, base));
+    }
+    Ok(Some(channels))
+}
+
+fn invalid_peak_file() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "The peak file is truncated or corrupt")
+}
+
+struct PeakFileReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> PeakFileReader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid_peak_file());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(len: usize) -> Vec<f32> {
+        (0..len).map(|i| ((i * 7919) % 1000) as f32 / 500.0 - 1.0).collect()
+    }
+
+    #[test]
+    fn peaks_built_in_chunks_match_peaks_built_at_once() {
+        let samples = signal(10 * BASE_BLOCK_FRAMES + 5);
+        let whole = WaveformPeaks::new(&samples);
+
+        for chunk_len in [1, 7, BASE_BLOCK_FRAMES, 3 * BASE_BLOCK_FRAMES + 1] {
+            let mut builder = PeakBuilder::default();
+            for chunk in samples.chunks(chunk_len) {
+                builder.push(chunk);
+            }
+            let chunked = builder.finish();
+            assert_eq!(chunked.len_frames, whole.len_frames);
+            assert_eq!(chunked.levels, whole.levels, "chunks of {}", chunk_len);
+        }
+    }
+
+    #[test]
+    fn zoomed_in_columns_without_samples_use_the_finest_peaks() {
+        let samples = signal(4 * BASE_BLOCK_FRAMES);
+        let peaks = WaveformPeaks::new(&samples);
+
+        let columns = peaks.columns(&[], 0..BASE_BLOCK_FRAMES, BASE_BLOCK_FRAMES);
+        assert!(columns.iter().all(|column| *column == peaks.levels[0][0]));
+        assert_eq!(
+            peaks.columns(&samples, 0..1, 1),
+            [fold_peaks(std::iter::once((samples[0], samples[0])))]
+        );
+    }
+}
@@ -0,0 +1,7 @@
+// TODO: Remove these
+#![allow(unused_variables)]
+#![allow(dead_code)]
+
+pub mod backend;
+pub mod ui;
+pub mod util;
@@ -1,13 +1,7 @@
-// TODO: Remove these
-#![allow(unused_variables)]
-#![allow(dead_code)]
-
 use log::LevelFilter;
 use std::error::Error;
 
-mod backend;
-mod ui;
-mod util;
+use meadowlark::ui;
 
 fn main() -> Result<(), Box<dyn Error>> {
     setup_logging()?;
@@ -8,6 +8,9 @@ use std::sync::{
 use std::{error::Error, time::Duration};
 use vizia::prelude::*;
 
+use crate::backend::offline_render::ExportOptions;
+use crate::util::Unit;
+
 pub mod icons;
 
 pub mod state;
@@ -48,8 +51,26 @@ pub fn run_ui() -> Result<(), Box<dyn Error>> {
             .expect("Failed to find default stylesheet");
         cx.add_stylesheet("src/ui/resources/themes/default_theme/browser.css")
             .expect("Failed to find default stylesheet");
+        cx.add_stylesheet("src/ui/resources/themes/default_theme/tuner.css")
+            .expect("Failed to find default stylesheet");
+        cx.add_stylesheet("src/ui/resources/themes/default_theme/inspector.css")
+            .expect("Failed to find default stylesheet");
+        cx.add_stylesheet("src/ui/resources/themes/default_theme/sample_editor.css")
+            .expect("Failed to find default stylesheet");
+        cx.add_stylesheet("src/ui/resources/themes/default_theme/eq.css")
+            .expect("Failed to find default stylesheet");
+        cx.add_stylesheet("src/ui/resources/themes/default_theme/collision.css")
+            .expect("Failed to find default stylesheet");
+        cx.add_stylesheet("src/ui/resources/themes/default_theme/console.css")
+            .expect("Failed to find default stylesheet");
+        cx.add_stylesheet("src/ui/resources/themes/default_theme/onscreen_instruments.css")
+            .expect("Failed to find default stylesheet");
+        cx.add_stylesheet("src/ui/resources/themes/default_theme/context_menu.css")
+            .expect("Failed to find default stylesheet");
 
         UiData::new().unwrap().build(cx);
+        macros_keymap(cx);
+        history_keymap(cx);
 
         VStack::new(cx, |cx| {
             // TODO - Move to menu bar
@@ -59,7 +80,7 @@ pub fn run_ui() -> Result<(), Box<dyn Error>> {
                     |cx| {
                         cx.emit(UiEvent::SaveProject);
                     },
-                    |cx| Label::new(cx, "SAVE"),
+                    |cx| Label::new(cx, localized("menu-save")),
                 )
                 .width(Pixels(100.0));
 
@@ -68,28 +89,215 @@ pub fn run_ui() -> Result<(), Box<dyn Error>> {
                     |cx| {
                         cx.emit(UiEvent::LoadProject);
                     },
-                    |cx| Label::new(cx, "LOAD"),
+                    |cx| Label::new(cx, localized("menu-load")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(UiEvent::ExportMaster(ExportOptions::default()));
+                    },
+                    |cx| {
+                        Binding::new(
+                            cx,
+                            UiData::state.then(UiState::export_progress),
+                            |cx, progress| match progress.get(cx) {
+                                Some(progress) => {
+                                    Label::new(
+                                        cx,
+                                        Unit::Percent.format(f64::from(progress)).as_str(),
+                                    );
+                                }
+                                None => {
+                                    Label::new(cx, localized("menu-export"));
+                                }
+                            },
+                        );
+                    },
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(UiEvent::ExportStems(ExportOptions::default()));
+                    },
+                    |cx| Label::new(cx, localized("menu-export-stems")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        if let Some(ui_data) = cx.data::<UiData>() {
+                            if ui_data.state.recording_audio {
+                                cx.emit(UiEvent::StopRecording);
+                            } else {
+                                cx.emit(UiEvent::StartRecording);
+                            }
+                        }
+                    },
+                    |cx| Label::new(cx, localized("menu-record")),
+                )
+                .width(Pixels(100.0))
+                .checked(UiData::state.then(UiState::recording_audio));
+
+                Button::new(
+                    cx,
+                    |cx| cx.emit(UiEvent::ToggleLoop),
+                    |cx| Label::new(cx, localized("menu-loop")),
+                )
+                .width(Pixels(100.0))
+                .checked(
+                    UiData::state.then(
+                        UiState::timeline_grid
+                            .then(TimelineGridState::loop_region.then(LoopRegion::enabled)),
+                    ),
+                );
+
+                Button::new(
+                    cx,
+                    |cx| cx.emit(UiEvent::ToggleMetronome),
+                    |cx| Label::new(cx, localized("menu-metronome")),
+                )
+                .width(Pixels(100.0))
+                .checked(UiData::state.then(UiState::metronome.then(MetronomeState::enabled)));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::ToggleTuner);
+                    },
+                    |cx| Label::new(cx, localized("menu-tuner")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::ToggleInspector);
+                    },
+                    |cx| Label::new(cx, localized("menu-inspector")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::ToggleLoudnessHistory);
+                    },
+                    |cx| Label::new(cx, localized("menu-loudness")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::ToggleSampleEditor);
+                    },
+                    |cx| Label::new(cx, localized("menu-editor")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::ToggleEq);
+                    },
+                    |cx| Label::new(cx, localized("menu-eq")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::ToggleCollisions);
+                    },
+                    |cx| Label::new(cx, localized("menu-collisions")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::ToggleConsole);
+                    },
+                    |cx| Label::new(cx, localized("menu-console")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::ToggleKeyboard);
+                    },
+                    |cx| Label::new(cx, localized("menu-keyboard")),
+                )
+                .width(Pixels(100.0));
+
+                Button::new(
+                    cx,
+                    |cx| {
+                        cx.emit(PanelEvent::TogglePads);
+                    },
+                    |cx| Label::new(cx, localized("menu-pads")),
                 )
                 .width(Pixels(100.0));
-                Label::new(cx, "File").width(Pixels(50.0)).child_space(Stretch(1.0)).class("small");
-                Label::new(cx, "Edit").width(Pixels(50.0)).child_space(Stretch(1.0)).class("small");
-                Label::new(cx, "View").width(Pixels(50.0)).child_space(Stretch(1.0)).class("small");
-                Label::new(cx, "Help").width(Pixels(50.0)).child_space(Stretch(1.0)).class("small");
+                Label::new(cx, localized("menu-file"))
+                    .width(Pixels(50.0))
+                    .child_space(Stretch(1.0))
+                    .class("small");
+                Button::new(
+                    cx,
+                    |cx| {
+                        if let Some(ui_data) = cx.data::<UiData>() {
+                            let items = edit_menu(ui_data);
+                            if !items.is_empty() {
+                                open_context_menu(cx, items);
+                            }
+                        }
+                    },
+                    |cx| Label::new(cx, localized("menu-edit")),
+                )
+                .width(Pixels(50.0))
+                .child_space(Stretch(1.0))
+                .class("small");
+                Button::new(
+                    cx,
+                    |cx| {
+                        if let Some(ui_data) = cx.data::<UiData>() {
+                            let items = view_menu(ui_data);
+                            open_context_menu(cx, items);
+                        }
+                    },
+                    |cx| Label::new(cx, localized("menu-view")),
+                )
+                .width(Pixels(50.0))
+                .child_space(Stretch(1.0))
+                .class("small");
+                Label::new(cx, localized("menu-help"))
+                    .width(Pixels(50.0))
+                    .child_space(Stretch(1.0))
+                    .class("small");
             })
             .class("menu_bar");
             top_bar(cx);
             HStack::new(cx, |cx| {
-                browser(cx);
-                channels(cx);
-                VStack::new(cx, |cx| {
-                    timeline(cx);
-                    piano_roll(cx);
-                })
-                .overflow(Overflow::Hidden)
-                .class("main")
-                .toggle_class(
-                    "hidden",
-                    UiData::state.then(UiState::panels.then(PanelState::hide_piano_roll)),
+                Binding::new(
+                    cx,
+                    UiData::state.then(UiState::panels.then(PanelState::side_panel_order)),
+                    |cx, order| {
+                        for panel in order.get(cx) {
+                            match panel {
+                                SidePanel::Browser => browser(cx),
+                                SidePanel::ChannelRack => channels(cx),
+                                SidePanel::Main => main_panels(cx),
+                                SidePanel::Tuner => tuner(cx),
+                                SidePanel::Inspector => inspector(cx),
+                            }
+                        }
+                    },
                 );
             })
             .col_between(Pixels(1.0));
@@ -98,6 +306,8 @@ pub fn run_ui() -> Result<(), Box<dyn Error>> {
         .background_color(Color::from("#0A0A0A"))
         .row_between(Pixels(1.0));
 
+        context_menu(cx);
+
         let run_poll_timer_clone = Arc::clone(&run_poll_timer_clone);
         cx.spawn(move |cx| {
             while run_poll_timer_clone.load(Ordering::Relaxed) {
@@ -118,3 +328,148 @@ pub fn run_ui() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// The area holding the timeline and the panels stacked below it.
+fn main_panels(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Binding::new(
+            cx,
+            UiData::state.then(UiState::panels.then(PanelState::main_panel_order)),
+            |cx, order| {
+                for panel in order.get(cx) {
+                    match panel {
+                        MainPanel::Timeline => timeline(cx),
+                        MainPanel::PianoRoll => piano_roll(cx),
+                        MainPanel::SampleEditor => sample_editor(cx),
+                        MainPanel::Eq => eq(cx),
+                        MainPanel::Collisions => collision(cx),
+                        MainPanel::Console => console(cx),
+                        MainPanel::Keyboard => keyboard(cx),
+                        MainPanel::Pads => pads(cx),
+                    }
+                }
+            },
+        );
+    })
+    .overflow(Overflow::Hidden)
+    .class("main")
+    .toggle_class("hidden", UiData::state.then(UiState::panels.then(PanelState::hide_piano_roll)));
+}
+
+/// CTRL + Z => Undo, CTRL + SHIFT + Z or CTRL + Y => Redo.
+fn history_keymap(cx: &mut Context) {
+    Keymap::from(vec![
+        (
+            KeyChord::new(Modifiers::CTRL, Code::KeyZ),
+            KeymapEntry::new(UiEvent::Undo, |cx| {
+                cx.emit(UiEvent::Undo);
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::SHIFT, Code::KeyZ),
+            KeymapEntry::new(UiEvent::Redo, |cx| {
+                cx.emit(UiEvent::Redo);
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL, Code::KeyY),
+            KeymapEntry::new(UiEvent::Redo, |cx| {
+                cx.emit(UiEvent::Redo);
+            }),
+        ),
+    ])
+    .build(cx);
+}
+
+/// The entries of the "Edit" menu: undoing and redoing the last edit.
+fn edit_menu(ui_data: &UiData) -> Vec<ContextMenuItem> {
+    let l = &ui_data.localization;
+    let mut items = Vec::new();
+
+    if let Some(label) = ui_data.history.undo_label() {
+        items.push(ContextMenuItem::ui(
+            l.tr_args("item-undo", &[("action", &l.tr(label))]),
+            UiEvent::Undo,
+        ));
+    }
+    if let Some(label) = ui_data.history.redo_label() {
+        items.push(ContextMenuItem::ui(
+            l.tr_args("item-redo", &[("action", &l.tr(label))]),
+            UiEvent::Redo,
+        ));
+    }
+
+    items
+}
+
+/// The entries of the "View" menu: saving and restoring workspaces, moving
+/// the panels around, and changing the language.
+///
+/// TODO: Detaching panels into their own windows once vizia supports
+/// multiple windows.
+fn view_menu(ui_data: &UiData) -> Vec<ContextMenuItem> {
+    let l = &ui_data.localization;
+    let panel_name = |name: &str| l.tr(&format!("panel-{}", name.replace('_', "-")));
+
+    let mut items = vec![
+        ContextMenuItem::new(
+            l.tr("item-save-workspace"),
+            ContextMenuAction::Rename {
+                target: RenameTarget::Workspace { global: true },
+                current: String::new(),
+            },
+        ),
+        ContextMenuItem::new(
+            l.tr("item-save-project-workspace"),
+            ContextMenuAction::Rename {
+                target: RenameTarget::Workspace { global: false },
+                current: String::new(),
+            },
+        ),
+    ];
+
+    for workspace in ui_data.state.workspaces.iter() {
+        items.push(ContextMenuItem::ui(
+            l.tr_args("item-project-workspace", &[("name", &workspace.name)]),
+            UiEvent::LoadWorkspace(workspace.name.clone()),
+        ));
+    }
+    for workspace in ui_data.global_workspaces.iter() {
+        items.push(ContextMenuItem::ui(
+            l.tr_args("item-workspace", &[("name", &workspace.name)]),
+            UiEvent::LoadWorkspace(workspace.name.clone()),
+        ));
+    }
+
+    for panel in ui_data.state.panels.side_panel_order.iter() {
+        let name = panel_name(panel.name());
+        items.push(ContextMenuItem::new(
+            l.tr_args("item-move-left", &[("panel", &name)]),
+            ContextMenuAction::Panel(PanelEvent::MoveSidePanel(*panel, -1)),
+        ));
+        items.push(ContextMenuItem::new(
+            l.tr_args("item-move-right", &[("panel", &name)]),
+            ContextMenuAction::Panel(PanelEvent::MoveSidePanel(*panel, 1)),
+        ));
+    }
+    for panel in ui_data.state.panels.main_panel_order.iter() {
+        let name = panel_name(panel.name());
+        items.push(ContextMenuItem::new(
+            l.tr_args("item-move-up", &[("panel", &name)]),
+            ContextMenuAction::Panel(PanelEvent::MoveMainPanel(*panel, -1)),
+        ));
+        items.push(ContextMenuItem::new(
+            l.tr_args("item-move-down", &[("panel", &name)]),
+            ContextMenuAction::Panel(PanelEvent::MoveMainPanel(*panel, 1)),
+        ));
+    }
+
+    for (locale, language) in l.available_locales() {
+        items.push(ContextMenuItem::ui(
+            l.tr_args("item-language", &[("language", &language)]),
+            UiEvent::SetLocale(locale.to_string()),
+        ));
+    }
+
+    items
+}
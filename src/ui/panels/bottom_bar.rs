@@ -1,5 +1,8 @@
 use vizia::prelude::*;
 
+use crate::ui::state::localized;
+use crate::ui::UiData;
+
 const MATERIAL_CLOSE: &str = "\u{e5cd}";
 
 pub fn bottom_bar(cx: &mut Context) {
@@ -7,14 +10,14 @@ pub fn bottom_bar(cx: &mut Context) {
         // TODO - Replace with list bound to app data
         HStack::new(cx, |cx| {
             HStack::new(cx, |cx| {
-                Label::new(cx, "MEADOWLARK PROJECT");
+                Label::new(cx, localized("tab-meadowlark-project"));
                 Label::new(cx, MATERIAL_CLOSE).font("material").font_size(10.0).left(Pixels(5.0));
             })
             .class("tab")
             .class("selected");
 
             HStack::new(cx, |cx| {
-                Label::new(cx, "OTHER PROJECT");
+                Label::new(cx, localized("tab-other-project"));
                 Label::new(cx, MATERIAL_CLOSE).font("material").font_size(10.0).left(Pixels(5.0));
             })
             .class("tab");
@@ -22,6 +25,10 @@ pub fn bottom_bar(cx: &mut Context) {
         .child_left(Pixels(1.0))
         .child_right(Pixels(1.0))
         .col_between(Pixels(1.0));
+
+        // Describes the last change that was made, e.g. the new value of a
+        // parameter or the lane that was selected with the keyboard.
+        Label::new(cx, UiData::announcement).class("announcement");
     })
     .class("bottom_bar");
 }
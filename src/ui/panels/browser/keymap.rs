@@ -41,6 +41,13 @@ pub fn browser_keymap(cx: &mut Context) {
                 cx.emit(BrowserEvent::StopSelected);
             }),
         ),
+        // E => Open the selected sample in the sample editor
+        (
+            KeyChord::new(Modifiers::empty(), Code::KeyE),
+            KeymapEntry::new(BrowserEvent::EditSelected, |cx| {
+                cx.emit(BrowserEvent::EditSelected);
+            }),
+        ),
     ])
     .build(cx);
 }
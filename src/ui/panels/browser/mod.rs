@@ -8,7 +8,7 @@ use keymap::*;
 use vizia::state::{Index, Then};
 
 use crate::ui::file_derived_lenses::children;
-use crate::ui::state::{BrowserEvent, BrowserState, File, PanelEvent, PanelState};
+use crate::ui::state::{localized, BrowserEvent, BrowserState, File, PanelEvent, PanelState};
 use crate::ui::{Panel, ResizableStack, UiData, UiEvent, UiState};
 
 // A simple file browser.
@@ -51,7 +51,7 @@ pub fn browser(cx: &mut Context) {
                     cx,
                     |cx| {
                         // Header
-                        Label::new(cx, "BROWSER").text_wrap(false).class("small");
+                        Label::new(cx, localized("panel-browser")).text_wrap(false).class("small");
                         Label::new(cx, "BROWSE2").on_release(|cx| {
                             if let Some(folder_path) = rfd::FileDialog::new().pick_folder() {
                                 cx.emit(BrowserEvent::SetRootPath(folder_path.clone()));
@@ -11,6 +11,27 @@ pub fn channels_keymap(cx: &mut Context) {
                 cx.emit(ChannelEvent::AddChannel);
             }),
         ),
+        // Delete => Remove the selected channels.
+        (
+            KeyChord::new(Modifiers::empty(), Code::Delete),
+            KeymapEntry::new(ChannelEvent::RemoveChannel, |cx| {
+                cx.emit(ChannelEvent::RemoveChannel);
+            }),
+        ),
+        // ArrowUp => Select the channel above the selected channel.
+        (
+            KeyChord::new(Modifiers::empty(), Code::ArrowUp),
+            KeymapEntry::new(ChannelEvent::SelectPreviousChannel, |cx| {
+                cx.emit(ChannelEvent::SelectPreviousChannel);
+            }),
+        ),
+        // ArrowDown => Select the channel below the selected channel.
+        (
+            KeyChord::new(Modifiers::empty(), Code::ArrowDown),
+            KeymapEntry::new(ChannelEvent::SelectNextChannel, |cx| {
+                cx.emit(ChannelEvent::SelectNextChannel);
+            }),
+        ),
     ])
     .build(cx);
 }
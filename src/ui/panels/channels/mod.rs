@@ -3,10 +3,15 @@ use vizia::{prelude::*, state::RatioLens};
 mod keymap;
 use keymap::*;
 
+use crate::ui::icons::IconCode;
 use crate::ui::state::{
-    ChannelEvent, ChannelState, ClipState, PanelEvent, PanelState, UiData, UiState,
+    localized, ChannelEvent, ChannelState, ClipState, ClipType, HardwareOutput, Localization,
+    PanelEvent, PanelState, RecordInput, UiData, UiEvent, UiState,
+};
+use crate::ui::{
+    is_context_menu_key, open_context_menu, open_context_menu_below, ContextMenuAction,
+    ContextMenuItem, Icon, Panel, RenameTarget,
 };
-use crate::ui::Panel;
 
 pub fn channels(cx: &mut Context) {
     channels_keymap(cx);
@@ -20,7 +25,7 @@ pub fn channels(cx: &mut Context) {
                 Panel::new(
                     cx,
                     |cx| {
-                        Label::new(cx, "CHANNEL RACK").class("small");
+                        Label::new(cx, localized("panel-channel-rack")).class("small");
 
                         // Button to toggle the orientation of the channels & clips.
                         // TODO: Replace with toggle button when we have a design for it.
@@ -151,15 +156,15 @@ fn clips(cx: &mut Context) {
     Panel::new(
         cx,
         |cx| {
-            Label::new(cx, "CLIPS").class("small").text_wrap(false);
+            Label::new(cx, localized("panel-clips")).class("small").text_wrap(false);
         },
         |cx| {
             ScrollView::new(cx, 0.0, 0.0, false, false, |cx| {
                 // List of clips. Visibility is determined by whether the associated channel is selected.
-                List::new(cx, UiData::state.then(UiState::clips), |cx, _, pattern| {
+                List::new(cx, UiData::state.then(UiState::clips), |cx, index, pattern| {
                     let channel_index = pattern.get(cx).channel;
 
-                    VStack::new(cx, |cx| {
+                    ClipEntry::new(cx, index, |cx| {
                         Label::new(cx, pattern.then(ClipState::name))
                             .text_wrap(false)
                             .background_color(
@@ -186,6 +191,242 @@ fn clips(cx: &mut Context) {
     .checked(UiData::state.then(UiState::panels.then(PanelState::hide_clips)));
 }
 
+/// A clip in the clips panel.
+pub struct ClipEntry {
+    clip_index: usize,
+}
+
+impl ClipEntry {
+    pub fn new(cx: &mut Context, index: usize, content: impl FnOnce(&mut Context)) -> Handle<Self> {
+        Self { clip_index: index }.build(cx, content)
+    }
+
+    fn context_menu(&self, cx: &EventContext) -> Option<Vec<ContextMenuItem>> {
+        let ui_data = cx.data::<UiData>()?;
+        let clip = ui_data.state.clips.get(self.clip_index)?;
+        let l = &ui_data.localization;
+
+        let index = self.clip_index;
+        let mut items = vec![
+            ContextMenuItem::new(
+                l.tr("item-rename"),
+                ContextMenuAction::Rename {
+                    target: RenameTarget::Clip(index),
+                    current: clip.name.clone(),
+                },
+            ),
+            ContextMenuItem::ui(l.tr("item-duplicate"), UiEvent::DuplicateClip(index)),
+        ];
+        if let ClipType::PianoRoll(piano_roll) = &clip.type_ {
+            items.push(ContextMenuItem::ui(
+                l.tr("item-duplicate-alias"),
+                UiEvent::DuplicateClipAsAlias(index),
+            ));
+            if piano_roll.link.is_some() {
+                items.push(ContextMenuItem::ui(l.tr("item-unlink"), UiEvent::UnlinkClip(index)));
+            }
+        }
+        if let ClipType::Container(_) = &clip.type_ {
+            items.push(ContextMenuItem::ui(l.tr("item-unpack"), UiEvent::UnpackContainer(index)));
+        }
+        items.extend([
+            ContextMenuItem::ui(l.tr("item-copy-inserts"), UiEvent::CopyClipEffects(index)),
+            ContextMenuItem::ui(l.tr("item-paste-inserts"), UiEvent::PasteClipEffects(index)),
+        ]);
+        if !clip.effects.is_empty() {
+            items.push(ContextMenuItem::ui(
+                l.tr("item-clear-inserts"),
+                UiEvent::ClearClipEffects(index),
+            ));
+        }
+        items.extend([
+            ContextMenuItem::ui(
+                l.tr(if clip.muted { "item-unmute" } else { "item-mute" }),
+                UiEvent::ToggleClipMute(index),
+            ),
+            ContextMenuItem::ui(l.tr("item-copy"), UiEvent::CopyClips(vec![index])),
+            ContextMenuItem::ui(l.tr("item-cut"), UiEvent::CutClips(vec![index])),
+            ContextMenuItem::ui(l.tr("item-remove"), UiEvent::RemoveClips(vec![index])),
+        ]);
+        Some(items)
+    }
+}
+
+impl View for ClipEntry {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, meta| match window_event {
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                cx.emit(UiEvent::InspectClip(self.clip_index));
+                cx.focus();
+            }
+
+            WindowEvent::MouseDown(MouseButton::Right) => {
+                if let Some(items) = self.context_menu(cx) {
+                    open_context_menu(cx, items);
+                }
+            }
+
+            WindowEvent::KeyDown(code, _) if is_context_menu_key(cx, *code) => {
+                if let Some(items) = self.context_menu(cx) {
+                    open_context_menu_below(cx, items);
+                }
+                meta.consume();
+            }
+
+            // M => Toggle whether the focused clip is muted.
+            WindowEvent::KeyDown(Code::KeyM, _) => {
+                cx.emit(UiEvent::ToggleClipMute(self.clip_index));
+                meta.consume();
+            }
+
+            // [ / ] => Start the focused container one beat earlier/later into
+            // its content.
+            WindowEvent::KeyDown(code @ (Code::BracketLeft | Code::BracketRight), _) => {
+                let offset = cx.data::<UiData>().and_then(|ui_data| {
+                    match &ui_data.state.clips.get(self.clip_index)?.type_ {
+                        ClipType::Container(container) => {
+                            Some(container.content_offset.get().as_beats_f64())
+                        }
+                        _ => None,
+                    }
+                });
+                if let Some(offset) = offset {
+                    let step = if *code == Code::BracketLeft { -1.0 } else { 1.0 };
+                    cx.emit(UiEvent::SetContainerOffset(self.clip_index, offset + step));
+                    meta.consume();
+                }
+            }
+
+            // Delete => Remove the focused clip.
+            WindowEvent::KeyDown(Code::Delete, _) => {
+                cx.emit(UiEvent::RemoveClips(vec![self.clip_index]));
+                meta.consume();
+            }
+
+            _ => {}
+        });
+    }
+}
+
+/// The context menu of a channel in the channel rack.
+fn channel_context_menu(index: usize, state: &UiState, l: &Localization) -> Vec<ContextMenuItem> {
+    let channel = &state.channels[index];
+    let mut items = vec![ContextMenuItem::new(
+        l.tr("item-rename"),
+        ContextMenuAction::Rename {
+            target: RenameTarget::Channel(index),
+            current: channel.name.clone(),
+        },
+    )];
+    items.extend(ContextMenuItem::colors(l, |color| {
+        ContextMenuAction::Channel(ChannelEvent::SetChannelColor(index, color))
+    }));
+    items.extend([
+        ContextMenuItem::channel(l.tr("item-duplicate"), ChannelEvent::DuplicateChannel(index)),
+        ContextMenuItem::ui(l.tr("item-freeze"), UiEvent::FreezeChannel(index)),
+        ContextMenuItem::ui(l.tr("item-copy-inserts"), UiEvent::CopyEffects(index)),
+        ContextMenuItem::ui(l.tr("item-paste-inserts"), UiEvent::PasteEffects),
+    ]);
+
+    // The master channel doesn't record.
+    if index != 0 {
+        items.push(ContextMenuItem::channel(
+            l.tr(if channel.exclusive_arm {
+                "item-exclusive-arm-off"
+            } else {
+                "item-exclusive-arm-on"
+            }),
+            ChannelEvent::SetExclusiveArm(index, !channel.exclusive_arm),
+        ));
+        if !channel.subchannels.is_empty() {
+            items.push(ContextMenuItem::channel(
+                l.tr("item-link-arm-subchannels"),
+                ChannelEvent::LinkArm(channel.subchannels.clone()),
+            ));
+        }
+        items.extend(state.arm_groups().into_iter().filter(|g| channel.arm_group != Some(*g)).map(
+            |group| {
+                ContextMenuItem::channel(
+                    l.tr_args("item-arm-group", &[("group", &group)]),
+                    ChannelEvent::SetArmGroup(index, Some(group)),
+                )
+            },
+        ));
+        items.push(match channel.arm_group {
+            Some(_) => ContextMenuItem::channel(
+                l.tr("item-leave-arm-group"),
+                ChannelEvent::SetArmGroup(index, None),
+            ),
+            None => ContextMenuItem::channel(
+                l.tr("item-new-arm-group"),
+                ChannelEvent::SetArmGroup(index, Some(state.new_arm_group())),
+            ),
+        });
+
+        if channel.record_input != RecordInput::default() {
+            items.push(ContextMenuItem::channel(
+                l.tr("item-record-device-input"),
+                ChannelEvent::SetRecordInput(index, RecordInput::default()),
+            ));
+        }
+        items.extend(
+            state
+                .buses()
+                .into_iter()
+                .filter(|bus| *bus != index && channel.record_input != RecordInput::Bus(*bus))
+                .map(|bus| {
+                    ContextMenuItem::channel(
+                        l.tr_args("item-record-bus", &[("channel", &state.channels[bus].name)]),
+                        ChannelEvent::SetRecordInput(index, RecordInput::Bus(bus)),
+                    )
+                }),
+        );
+
+        if channel.hardware_output.is_some() {
+            items.push(ContextMenuItem::channel(
+                l.tr("item-hardware-output-none"),
+                ChannelEvent::SetHardwareOutput(index, None),
+            ));
+        }
+        items.extend(
+            (0..state.audio_device_outputs / 2)
+                .map(|pair| HardwareOutput { left: pair * 2, right: pair * 2 + 1 })
+                .filter(|output| channel.hardware_output != Some(*output))
+                .map(|output| {
+                    ContextMenuItem::channel(
+                        l.tr_args(
+                            "item-hardware-output",
+                            &[("left", &(output.left + 1)), ("right", &(output.right + 1))],
+                        ),
+                        ChannelEvent::SetHardwareOutput(index, Some(output)),
+                    )
+                }),
+        );
+    }
+
+    items.push(ContextMenuItem::channel(l.tr("item-remove"), ChannelEvent::RemoveChannel));
+    items
+}
+
+/// Arms the channel and its arm group. The number of the arm group is shown
+/// next to it.
+fn arm_button(cx: &mut Context, index: usize, channel: &ChannelState) {
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            move |cx| cx.emit(ChannelEvent::ToggleArm(index)),
+            |cx| Icon::new(cx, IconCode::Record, 20.0, 14.0),
+        )
+        .class("arm_button")
+        .toggle_class("armed", channel.armed)
+        .toggle_class("exclusive", channel.exclusive_arm);
+        if let Some(group) = channel.arm_group {
+            Label::new(cx, group.to_string()).class("arm_group");
+        }
+    })
+    .class("arm_row");
+}
+
 pub struct Channel {
     channel_index: usize,
 }
@@ -217,12 +458,30 @@ impl Channel {
 
                         VStack::new(cx, |cx| {
                             Label::new(cx, chnl.then(ChannelState::name));
+                            // The master channel doesn't record.
+                            if index != 0 {
+                                arm_button(cx, index, &data);
+                            }
+                            // The bus the channel records, if it records one.
+                            if let RecordInput::Bus(bus) = data.record_input {
+                                Label::new(
+                                    cx,
+                                    new_root.clone().map(move |channels| {
+                                        channels
+                                            .get(bus)
+                                            .map(|channel| channel.name.clone())
+                                            .unwrap_or_default()
+                                    }),
+                                )
+                                .class("record_input");
+                            }
                         });
                     })
                     .class("channel")
                     .toggle_class("selected", data.selected)
                     .on_press(move |cx| {
                         cx.emit(ChannelEvent::SelectChannel(index));
+                        cx.focus();
                         // println!("Start Drag: {}", index);
                         // cx.emit(ChannelEvent::DragChannel(index));
                     });
@@ -244,6 +503,12 @@ impl Channel {
             })
             .height(Auto);
     }
+
+    fn context_menu(&self, cx: &EventContext) -> Option<Vec<ContextMenuItem>> {
+        let ui_data = cx.data::<UiData>()?;
+        ui_data.state.channels.get(self.channel_index)?;
+        Some(channel_context_menu(self.channel_index, &ui_data.state, &ui_data.localization))
+    }
 }
 
 impl View for Channel {
@@ -254,6 +519,22 @@ impl View for Channel {
                 cx.emit(ChannelEvent::SelectChannelGroup(self.channel_index));
             }
 
+            WindowEvent::MouseDown(MouseButton::Right) => {
+                if let Some(items) = self.context_menu(cx) {
+                    // The menu acts on the channel that was clicked, so select it.
+                    cx.emit(ChannelEvent::SelectChannel(self.channel_index));
+                    open_context_menu(cx, items);
+                }
+                meta.consume();
+            }
+
+            WindowEvent::KeyDown(code, _) if is_context_menu_key(cx, *code) => {
+                if let Some(items) = self.context_menu(cx) {
+                    open_context_menu_below(cx, items);
+                }
+                meta.consume();
+            }
+
             _ => {}
         });
     }
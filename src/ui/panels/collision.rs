@@ -0,0 +1,130 @@
+use vizia::{
+    prelude::*,
+    vg::{Paint, Path},
+};
+
+use crate::backend::collision::NUM_COLLISION_BANDS;
+use crate::ui::state::{localized, CollisionState, PanelState, UiData, UiEvent, UiState};
+use crate::ui::Panel;
+
+/// The width of the column showing the average collision of each band.
+const TOTALS_WIDTH: f32 = 40.0;
+
+pub fn collision(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Panel::new(
+            cx,
+            |cx| {
+                Label::new(cx, localized("panel-collisions")).class("small");
+                Label::new(
+                    cx,
+                    UiData::state.then(UiState::collision.then(CollisionState::description)),
+                )
+                .class("small")
+                .left(Pixels(10.0));
+            },
+            |cx| {
+                HStack::new(cx, |cx| {
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(UiEvent::AnalyzeCollisions),
+                        |cx| Label::new(cx, localized("button-analyze-selected")).class("small"),
+                    );
+                })
+                .class("collision_toolbar");
+
+                CollisionHeatMap::new(cx).class("collision_heat_map");
+            },
+        );
+    })
+    .class("collision")
+    .toggle_class("hidden", UiData::state.then(UiState::panels.then(PanelState::hide_collisions)));
+}
+
+/// Draws where in time and frequency two channels mask each other. Time runs
+/// from left to right and frequency from bottom to top. The column on the left
+/// shows the average over the whole analysis.
+pub struct CollisionHeatMap {}
+
+impl CollisionHeatMap {
+    pub fn new(cx: &mut Context) -> Handle<Self> {
+        Self {}.build(cx, |_| {})
+    }
+}
+
+fn heat_color(heat: f32) -> vizia::vg::Color {
+    let heat = heat.clamp(0.0, 1.0);
+    vizia::vg::Color::rgba(
+        (120.0 + 135.0 * heat) as u8,
+        (200.0 - 150.0 * heat) as u8,
+        40,
+        (255.0 * heat.sqrt()) as u8,
+    )
+}
+
+impl View for CollisionHeatMap {
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+        if bounds.w < 1.0 || bounds.h < 1.0 {
+            return;
+        }
+
+        if let Some(ui_data) = cx.data::<UiData>() {
+            let map = match &ui_data.state.collision.map {
+                Some(map) if map.num_slices > 0 => map,
+                _ => return,
+            };
+
+            canvas.save();
+            canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
+
+            let totals_w = cx.logical_to_physical(TOTALS_WIDTH).min(bounds.w);
+            let map_x = bounds.x + totals_w;
+            let map_w = bounds.w - totals_w;
+
+            let band_h = bounds.h / NUM_COLLISION_BANDS as f32;
+            let slice_w = map_w / map.num_slices as f32;
+            let band_y = |band: usize| bounds.y + bounds.h - (band + 1) as f32 * band_h;
+
+            // Averages
+            for (band, total) in map.band_totals().iter().enumerate() {
+                let mut path = Path::new();
+                path.rect(bounds.x, band_y(band), totals_w * total.clamp(0.0, 1.0), band_h);
+                canvas.fill_path(&mut path, Paint::color(heat_color(*total)));
+            }
+
+            // Heat map
+            for slice in 0..map.num_slices {
+                for band in 0..NUM_COLLISION_BANDS {
+                    let heat = map.get(slice, band);
+                    if heat <= 0.0 {
+                        continue;
+                    }
+
+                    let mut path = Path::new();
+                    // Overlap by a pixel so there are no seams between cells.
+                    path.rect(map_x + slice as f32 * slice_w, band_y(band), slice_w + 1.0, band_h);
+                    canvas.fill_path(&mut path, Paint::color(heat_color(heat)));
+                }
+            }
+
+            // Grid lines at each decade.
+            let grid_paint = Paint::color(vizia::vg::Color::rgb(60, 60, 60));
+            for freq in [100.0, 1_000.0, 10_000.0] {
+                if let Some(band) = map.band_edges.iter().position(|edge| *edge >= freq) {
+                    let y = bounds.y + bounds.h - band as f32 * band_h;
+                    let mut path = Path::new();
+                    path.move_to(bounds.x, y);
+                    path.line_to(bounds.x + bounds.w, y);
+                    canvas.stroke_path(&mut path, grid_paint);
+                }
+            }
+            let mut path = Path::new();
+            path.move_to(map_x, bounds.y);
+            path.line_to(map_x, bounds.y + bounds.h);
+            canvas.stroke_path(&mut path, grid_paint);
+
+            canvas.restore();
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use vizia::prelude::*;
+
+use crate::ui::state::{
+    localized, ConsoleLine, ConsoleLineKind, ConsoleState, Macro, MacroState, PanelState, UiData,
+    UiEvent, UiState,
+};
+use crate::ui::Panel;
+
+/// A console for running scripts against the project. Type `help` for a list
+/// of commands. The commands that match what is being typed are shown below
+/// the input, and clicking one fills it in.
+///
+/// The macros are listed next to it. Clicking one shows its lines, one per
+/// text box, so they can be edited.
+pub fn console(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Panel::new(
+            cx,
+            |cx| {
+                Label::new(cx, localized("panel-console")).class("small");
+            },
+            |cx| {
+                HStack::new(cx, |cx| {
+                    VStack::new(cx, script_console).class("console_script");
+                    VStack::new(cx, macro_list).class("console_macros");
+                });
+            },
+        );
+    })
+    .class("console")
+    .toggle_class("hidden", UiData::state.then(UiState::panels.then(PanelState::hide_console)));
+}
+
+fn script_console(cx: &mut Context) {
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            |cx| cx.emit(UiEvent::ClearConsole),
+            |cx| Label::new(cx, localized("button-clear")).class("small"),
+        );
+        Binding::new(
+            cx,
+            UiData::state.then(UiState::macros.then(MacroState::recording)),
+            |cx, recording| {
+                let recording = recording.get(cx);
+                Button::new(
+                    cx,
+                    move |cx| {
+                        cx.emit(if recording {
+                            UiEvent::StopMacroRecording
+                        } else {
+                            UiEvent::StartMacroRecording
+                        })
+                    },
+                    move |cx| {
+                        Label::new(
+                            cx,
+                            localized(if recording {
+                                "button-stop-recording"
+                            } else {
+                                "button-record-macro"
+                            }),
+                        )
+                        .class("small")
+                    },
+                )
+                .toggle_class("recording", recording);
+            },
+        );
+    })
+    .class("console_toolbar");
+
+    ScrollView::new(cx, 0.0, 1.0, false, true, |cx| {
+        List::new(
+            cx,
+            UiData::state.then(UiState::console.then(ConsoleState::lines)),
+            |cx, _, line| {
+                Label::new(cx, line.then(ConsoleLine::text))
+                    .class("small")
+                    .class("console_line")
+                    .toggle_class(
+                        "input",
+                        line.then(ConsoleLine::kind).map(|kind| *kind == ConsoleLineKind::Input),
+                    )
+                    .toggle_class(
+                        "error",
+                        line.then(ConsoleLine::kind).map(|kind| *kind == ConsoleLineKind::Error),
+                    );
+            },
+        );
+    })
+    .class("console_output");
+
+    Textbox::new(cx, UiData::state.then(UiState::console.then(ConsoleState::input)))
+        .on_edit(|cx, text| cx.emit(UiEvent::SetConsoleInput(text)))
+        .on_submit(|cx, text| cx.emit(UiEvent::RunScript(text)))
+        .class("console_input");
+
+    List::new(
+        cx,
+        UiData::state.then(UiState::console.then(ConsoleState::completions)),
+        |cx, index, signature| {
+            Label::new(cx, signature).class("small").class("console_completion").on_press(
+                move |cx| {
+                    let input = cx.data::<UiData>().and_then(|ui_data| {
+                        let signature = ui_data.state.console.completions.get(index)?;
+                        Some(format!("{} ", signature.split(' ').next()?))
+                    });
+                    if let Some(input) = input {
+                        cx.emit(UiEvent::SetConsoleInput(input));
+                    }
+                },
+            );
+        },
+    )
+    .class("console_completions");
+}
+
+fn macro_list(cx: &mut Context) {
+    let macros = UiData::state.then(UiState::macros.then(MacroState::macros));
+    let editing = UiData::state.then(UiState::macros.then(MacroState::editing));
+
+    Label::new(cx, localized("macros-title")).class("small");
+    Label::new(cx, localized("macro-no-macros"))
+        .class("small")
+        .class("console_macros_empty")
+        .toggle_class("hidden", macros.map(|macros| !macros.is_empty()));
+    List::new(cx, macros, move |cx, index, item| {
+        HStack::new(cx, |cx| {
+            Label::new(cx, item.then(Macro::name)).class("small");
+            Label::new(
+                cx,
+                item.then(Macro::shortcut).map(|shortcut| {
+                    shortcut.map(|n| format!("Ctrl+Alt+{}", n)).unwrap_or_default()
+                }),
+            )
+            .class("small")
+            .class("console_macro_shortcut");
+        })
+        .class("console_macro")
+        .toggle_class("selected", editing.map(move |editing| *editing == Some(index)))
+        .on_press(move |cx| cx.emit(UiEvent::EditMacro(Some(index))));
+    });
+
+    Binding::new(cx, editing, move |cx, editing| {
+        if let Some(index) = editing.get(cx) {
+            macro_editor(cx, index);
+        }
+    });
+}
+
+/// The name and lines of a macro. Emptying a line removes it, and typing in
+/// the empty box at the end adds one.
+fn macro_editor(cx: &mut Context, index: usize) {
+    let this_macro = UiData::state
+        .then(UiState::macros.then(MacroState::macros))
+        .map(move |macros| macros.get(index).cloned());
+
+    Textbox::new(cx, this_macro.map(|m| m.as_ref().map(|m| m.name.clone()).unwrap_or_default()))
+        .on_submit(move |cx, name| cx.emit(UiEvent::RenameMacro { index, name }))
+        .class("console_macro_name");
+    List::new(
+        cx,
+        this_macro.map(|m| m.as_ref().map(|m| m.lines.clone()).unwrap_or_default()),
+        |cx, line, text| {
+            Textbox::new(cx, text)
+                .on_submit(move |cx, text| cx.emit(UiEvent::SetMacroLine { line, text }))
+                .class("console_macro_line");
+        },
+    );
+    Textbox::new(cx, this_macro.map(|_| String::new()))
+        .on_submit(|cx, text| cx.emit(UiEvent::SetMacroLine { line: usize::MAX, text }))
+        .class("console_macro_line");
+
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::RunMacro { index, args: Vec::new() }),
+            |cx| Label::new(cx, localized("button-run")).class("small"),
+        );
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::DeleteMacro(index)),
+            |cx| Label::new(cx, localized("button-delete")).class("small"),
+        );
+    })
+    .class("console_toolbar");
+}
+
+/// CTRL + ALT + 1 to 9 => Runs the macro bound to that number. This is added to
+/// the root of the UI so the shortcuts work wherever the focus is.
+pub fn macros_keymap(cx: &mut Context) {
+    Keymap::from(vec![
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit1),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(1), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(1));
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit2),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(2), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(2));
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit3),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(3), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(3));
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit4),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(4), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(4));
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit5),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(5), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(5));
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit6),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(6), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(6));
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit7),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(7), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(7));
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit8),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(8), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(8));
+            }),
+        ),
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::ALT, Code::Digit9),
+            KeymapEntry::new(UiEvent::RunMacroShortcut(9), |cx| {
+                cx.emit(UiEvent::RunMacroShortcut(9));
+            }),
+        ),
+    ])
+    .build(cx);
+}
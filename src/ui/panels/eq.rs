@@ -0,0 +1,371 @@
+use vizia::{
+    prelude::*,
+    vg::{Baseline, Paint, Path},
+};
+
+use crate::backend::eq_plug::{
+    eq_response_db, EqBand, EqBandType, MAX_FREQ, MAX_GAIN_DB, MIN_FREQ,
+};
+use crate::ui::state::{localized, EqState, PanelState, UiData, UiEvent, UiState};
+use crate::ui::Panel;
+use crate::util::Unit;
+
+/// The range of the spectrum display in dB.
+const SPECTRUM_MIN_DB: f32 = -84.0;
+const SPECTRUM_MAX_DB: f32 = 0.0;
+
+/// The radius of the band handles in logical pixels.
+const HANDLE_RADIUS: f32 = 5.0;
+
+/// How much Q changes with each step of the scroll wheel.
+const Q_SCROLL_FACTOR: f32 = 1.1;
+
+const Q_UNIT: Unit = Unit::Plain { decimals: 2 };
+
+pub fn eq(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Panel::new(
+            cx,
+            |cx| {
+                Label::new(cx, localized("panel-eq")).class("small");
+            },
+            |cx| {
+                HStack::new(cx, |cx| {
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(UiEvent::ToggleEqBypass),
+                        |cx| Label::new(cx, localized("button-bypass")).class("small"),
+                    )
+                    .toggle_class(
+                        "active",
+                        UiData::state.then(UiState::eq.then(EqState::bypassed)),
+                    );
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(UiEvent::ToggleEqAutoGain),
+                        |cx| Label::new(cx, localized("button-auto-gain")).class("small"),
+                    )
+                    .toggle_class(
+                        "active",
+                        UiData::state.then(UiState::eq.then(EqState::auto_gain)),
+                    );
+                    Label::new(
+                        cx,
+                        UiData::state.then(UiState::eq).map(|s| {
+                            if s.auto_gain {
+                                Unit::Decibels.format(s.compensation_db.into())
+                            } else {
+                                String::new()
+                            }
+                        }),
+                    )
+                    .class("small");
+
+                    // The values of the selected band, which can also be typed in.
+                    Label::new(
+                        cx,
+                        UiData::state
+                            .then(UiState::eq.then(EqState::selected_band))
+                            .map(|band| format!("{}", band + 1)),
+                    )
+                    .class("small")
+                    .class("eq_band_number");
+                    band_value_entry(cx, Unit::Hertz, |band| band.freq, |band, v| band.freq = v);
+                    band_value_entry(
+                        cx,
+                        Unit::Decibels,
+                        |band| band.gain_db,
+                        |band, v| band.gain_db = v,
+                    );
+                    band_value_entry(cx, Q_UNIT, |band| band.q, |band, v| band.q = v);
+                })
+                .class("eq_toolbar");
+
+                EqView::new(cx).class("eq_view");
+            },
+        );
+    })
+    .class("eq")
+    .toggle_class("hidden", UiData::state.then(UiState::panels.then(PanelState::hide_eq)));
+}
+
+/// A text box showing one value of the selected band. Text typed into it is
+/// read with the given unit.
+fn band_value_entry(
+    cx: &mut Context,
+    unit: Unit,
+    get: impl 'static + Fn(&EqBand) -> f32,
+    set: impl 'static + Fn(&mut EqBand, f32),
+) {
+    Textbox::new(
+        cx,
+        UiData::state.then(UiState::eq).map(move |s| unit.format(get(s.selected_band()).into())),
+    )
+    .on_submit(move |cx, text| {
+        let (index, mut band) = match cx.data::<UiData>() {
+            Some(ui_data) => {
+                let eq = &ui_data.state.eq;
+                (eq.selected_band, *eq.selected_band())
+            }
+            None => return,
+        };
+        if let Some(value) = unit.parse(&text) {
+            set(&mut band, value as f32);
+            cx.emit(UiEvent::SetEqBand(index, band.clamped()));
+        }
+    })
+    .class("eq_band_value");
+}
+
+/// Draws the live input and output spectrums behind the editable EQ curve.
+///
+/// Drag a band's handle to change its frequency and gain, scroll over it to
+/// change its Q, and right click it to toggle it on or off. Hovering over a
+/// handle shows the values of its band.
+pub struct EqView {
+    dragging_band: Option<usize>,
+    hovered_band: Option<usize>,
+}
+
+impl EqView {
+    pub fn new(cx: &mut Context) -> Handle<Self> {
+        Self { dragging_band: None, hovered_band: None }.build(cx, |_| {})
+    }
+}
+
+struct Mapping {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+impl Mapping {
+    fn freq_to_x(&self, freq: f32) -> f32 {
+        self.x + self.w * (freq / MIN_FREQ).ln() / (MAX_FREQ / MIN_FREQ).ln()
+    }
+
+    fn x_to_freq(&self, x: f32) -> f32 {
+        MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(((x - self.x) / self.w).clamp(0.0, 1.0))
+    }
+
+    fn gain_to_y(&self, db: f32) -> f32 {
+        self.y + self.h * (0.5 - db / (MAX_GAIN_DB * 2.0))
+    }
+
+    fn y_to_gain(&self, y: f32) -> f32 {
+        (0.5 - (y - self.y) / self.h) * MAX_GAIN_DB * 2.0
+    }
+
+    fn spectrum_to_y(&self, db: f32) -> f32 {
+        let normal = ((db - SPECTRUM_MIN_DB) / (SPECTRUM_MAX_DB - SPECTRUM_MIN_DB)).clamp(0.0, 1.0);
+        self.y + self.h * (1.0 - normal)
+    }
+}
+
+fn band_handle_gain(state: &EqState, band: usize) -> f32 {
+    match state.bands[band].type_ {
+        EqBandType::LowCut | EqBandType::HighCut => 0.0,
+        _ => state.bands[band].gain_db,
+    }
+}
+
+impl View for EqView {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        let current = cx.current();
+        let map = Mapping {
+            x: cx.cache.get_posx(current),
+            y: cx.cache.get_posy(current),
+            w: cx.cache.get_width(current),
+            h: cx.cache.get_height(current),
+        };
+        let (mouse_x, mouse_y) = (cx.mouse.cursorx, cx.mouse.cursory);
+        let radius = HANDLE_RADIUS * 2.0 * cx.scale_factor();
+
+        let state = match cx.data::<UiData>() {
+            Some(ui_data) => ui_data.state.eq.clone(),
+            None => return,
+        };
+
+        let band_under_mouse = (0..state.bands.len()).find(|&i| {
+            let x = map.freq_to_x(state.bands[i].freq);
+            let y = map.gain_to_y(band_handle_gain(&state, i));
+            (x - mouse_x).abs() <= radius && (y - mouse_y).abs() <= radius
+        });
+
+        event.map(|window_event, _| match window_event {
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                if let Some(band) = band_under_mouse {
+                    self.dragging_band = Some(band);
+                    cx.emit(UiEvent::SelectEqBand(band));
+                    cx.capture();
+                }
+            }
+            WindowEvent::MouseDown(MouseButton::Right) => {
+                if let Some(band) = band_under_mouse {
+                    let mut new_band = state.bands[band];
+                    new_band.enabled ^= true;
+                    cx.emit(UiEvent::SetEqBand(band, new_band));
+                }
+            }
+            WindowEvent::MouseMove(x, y) => {
+                let hovered_band = self.dragging_band.or(band_under_mouse);
+                if hovered_band != self.hovered_band {
+                    self.hovered_band = hovered_band;
+                    cx.needs_redraw();
+                }
+
+                if let Some(band) = self.dragging_band {
+                    let mut new_band = state.bands[band];
+                    new_band.freq = map.x_to_freq(*x);
+                    if !matches!(new_band.type_, EqBandType::LowCut | EqBandType::HighCut) {
+                        new_band.gain_db = map.y_to_gain(*y);
+                    }
+                    cx.emit(UiEvent::SetEqBand(band, new_band));
+                }
+            }
+            WindowEvent::MouseUp(MouseButton::Left) => {
+                if self.dragging_band.take().is_some() {
+                    cx.release();
+                }
+            }
+            WindowEvent::MouseLeave => {
+                if self.dragging_band.is_none() && self.hovered_band.take().is_some() {
+                    cx.needs_redraw();
+                }
+            }
+            WindowEvent::MouseScroll(_, y) => {
+                if let Some(band) = self.dragging_band.or(band_under_mouse) {
+                    let mut new_band = state.bands[band];
+                    new_band.q *= Q_SCROLL_FACTOR.powf(*y);
+                    cx.emit(UiEvent::SetEqBand(band, new_band));
+                }
+            }
+            _ => {}
+        });
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+        if bounds.w < 1.0 || bounds.h < 1.0 {
+            return;
+        }
+
+        if let Some(ui_data) = cx.data::<UiData>() {
+            let state = &ui_data.state.eq;
+            let map = Mapping { x: bounds.x, y: bounds.y, w: bounds.w, h: bounds.h };
+
+            canvas.save();
+            canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
+
+            // Grid lines at each decade and every 6dB.
+            let grid_paint = Paint::color(vizia::vg::Color::rgb(44, 44, 44));
+            for freq in [100.0, 1_000.0, 10_000.0] {
+                let mut path = Path::new();
+                path.move_to(map.freq_to_x(freq), bounds.y);
+                path.line_to(map.freq_to_x(freq), bounds.y + bounds.h);
+                canvas.stroke_path(&mut path, grid_paint);
+            }
+            let mut db = -MAX_GAIN_DB + 6.0;
+            while db < MAX_GAIN_DB {
+                let mut path = Path::new();
+                path.move_to(bounds.x, map.gain_to_y(db));
+                path.line_to(bounds.x + bounds.w, map.gain_to_y(db));
+                canvas.stroke_path(&mut path, grid_paint);
+                db += 6.0;
+            }
+
+            // Input spectrum as a filled area.
+            let mut path = Path::new();
+            path.move_to(bounds.x, bounds.y + bounds.h);
+            for (i, db) in state.pre_spectrum.iter().enumerate() {
+                path.line_to(map.freq_to_x(EqState::point_freq(i)), map.spectrum_to_y(*db));
+            }
+            path.line_to(bounds.x + bounds.w, bounds.y + bounds.h);
+            path.close();
+            canvas.fill_path(&mut path, Paint::color(vizia::vg::Color::rgba(120, 120, 120, 70)));
+
+            // Output spectrum as a line.
+            let mut path = Path::new();
+            for (i, db) in state.post_spectrum.iter().enumerate() {
+                let (x, y) = (map.freq_to_x(EqState::point_freq(i)), map.spectrum_to_y(*db));
+                if i == 0 {
+                    path.move_to(x, y);
+                } else {
+                    path.line_to(x, y);
+                }
+            }
+            canvas.stroke_path(&mut path, Paint::color(vizia::vg::Color::rgba(90, 140, 200, 160)));
+
+            // The EQ curve, evaluated at every pixel.
+            let mut path = Path::new();
+            for px in 0..=(bounds.w as usize) {
+                let x = bounds.x + px as f32;
+                let db = eq_response_db(&state.bands, state.sample_rate, map.x_to_freq(x));
+                let y = map.gain_to_y(db.clamp(-MAX_GAIN_DB * 2.0, MAX_GAIN_DB * 2.0));
+                if px == 0 {
+                    path.move_to(x, y);
+                } else {
+                    path.line_to(x, y);
+                }
+            }
+            let mut curve_paint = Paint::color(vizia::vg::Color::rgb(237, 225, 113));
+            curve_paint.set_line_width(cx.logical_to_physical(1.5));
+            canvas.stroke_path(&mut path, curve_paint);
+
+            // Band handles
+            let radius = cx.logical_to_physical(HANDLE_RADIUS);
+            for (i, band) in state.bands.iter().enumerate() {
+                let mut path = Path::new();
+                path.circle(
+                    map.freq_to_x(band.freq),
+                    map.gain_to_y(band_handle_gain(state, i)),
+                    radius,
+                );
+
+                let color = if band.enabled {
+                    vizia::vg::Color::rgb(237, 225, 113)
+                } else {
+                    vizia::vg::Color::rgb(82, 82, 82)
+                };
+                canvas.fill_path(&mut path, Paint::color(color));
+            }
+
+            // Tooltip with the values of the band under the mouse.
+            if let Some(i) = self.hovered_band {
+                let band = &state.bands[i];
+                let text = format!(
+                    "{}  {}  Q {}",
+                    Unit::Hertz.format(band.freq.into()),
+                    Unit::Decibels.format(band_handle_gain(state, i).into()),
+                    Q_UNIT.format(band.q.into())
+                );
+
+                let mut text_paint = Paint::color(vizia::vg::Color::rgb(220, 220, 220));
+                text_paint.set_font_size(cx.logical_to_physical(11.0));
+                text_paint.set_text_baseline(Baseline::Middle);
+
+                let padding = cx.logical_to_physical(4.0);
+                let height = cx.logical_to_physical(18.0);
+                let width = canvas
+                    .measure_text(0.0, 0.0, &text, text_paint)
+                    .map(|metrics| metrics.width())
+                    .unwrap_or(0.0)
+                    + padding * 2.0;
+
+                // Next to the handle, kept inside the view.
+                let x = (map.freq_to_x(band.freq) + radius * 2.0)
+                    .min(bounds.x + bounds.w - width)
+                    .max(bounds.x);
+                let y = (map.gain_to_y(band_handle_gain(state, i)) - height - radius).max(bounds.y);
+
+                let mut path = Path::new();
+                path.rounded_rect(x, y, width, height, cx.logical_to_physical(2.0));
+                canvas.fill_path(&mut path, Paint::color(vizia::vg::Color::rgba(20, 20, 20, 230)));
+                let _ = canvas.fill_text(x + padding, y + height / 2.0, &text, text_paint);
+            }
+
+            canvas.restore();
+        }
+    }
+}
@@ -0,0 +1,1558 @@
+use vizia::prelude::*;
+
+use crate::backend::drum_rack::DrumPad;
+use crate::backend::macro_knob::MacroCurve;
+use crate::backend::midi_transform::{VelocityCurve, ALL_CHANNELS};
+use crate::ui::state::{
+    localized, AudioClipState, ChannelEvent, ChannelMixEdit, ChannelState, ClipStart, ClipState,
+    ClipType, DrumPadEdit, EffectSlot, ExternalEffectState, FxRackEdit, FxRackState,
+    HRackEffectState, Inspected, MacroKnobEdit, MidiInputEdit, PanelState, ParameterRange,
+    ParameterState, RackEdit, RecordingInfo, SendEdit, UiData, UiEvent, UiState, BEATS_PER_BAR,
+};
+use crate::ui::{open_context_menu, ContextMenuItem, Panel};
+use crate::util::Unit;
+
+const POSITION_UNIT: Unit = Unit::BarsBeats { beats_per_bar: BEATS_PER_BAR };
+
+/// The velocity curves that can be picked, with the keys of their names.
+const VELOCITY_CURVES: [(&str, VelocityCurve); 4] = [
+    ("velocity-curve-linear", VelocityCurve::Linear),
+    ("velocity-curve-soft", VelocityCurve::Soft),
+    ("velocity-curve-hard", VelocityCurve::Hard),
+    ("velocity-curve-fixed", VelocityCurve::Fixed(100.0 / 127.0)),
+];
+
+/// The curves a parameter can follow a macro knob along, with the keys of
+/// their names.
+const MACRO_CURVES: [(&str, MacroCurve); 4] = [
+    ("macro-curve-linear", MacroCurve::Linear),
+    ("macro-curve-exponential", MacroCurve::Exponential),
+    ("macro-curve-logarithmic", MacroCurve::Logarithmic),
+    ("macro-curve-s-curve", MacroCurve::SCurve),
+];
+
+/// Shows the properties of whatever was selected last (a clip, a channel or a
+/// section of the arranger) and lets them be edited.
+pub fn inspector(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Panel::new(
+            cx,
+            |cx| {
+                Label::new(cx, localized("panel-inspector")).class("small");
+            },
+            |cx| {
+                Binding::new(cx, UiData::state.then(UiState::inspected), |cx, inspected| {
+                    VStack::new(cx, |cx| match inspected.get(cx) {
+                        Some(Inspected::Clip(index)) => clip_properties(cx, index),
+                        Some(Inspected::Channel(index)) => channel_properties(cx, index),
+                        Some(Inspected::Section(index)) => section_properties(cx, index),
+                        None => {
+                            Label::new(cx, localized("inspector-nothing-selected")).class("small");
+                        }
+                    })
+                    .class("inspector_content");
+                });
+            },
+        )
+        .class("inspector");
+    })
+    .class("inspector")
+    .toggle_class("hidden", UiData::state.then(UiState::panels.then(PanelState::hide_inspector)));
+}
+
+fn clip_properties(cx: &mut Context, index: usize) {
+    let clips = UiData::state.then(UiState::clips);
+
+    property(
+        cx,
+        "inspector-name",
+        clips.map(move |clips| clips.get(index).map(|clip| clip.name.clone()).unwrap_or_default()),
+    );
+    value_entry(
+        cx,
+        "inspector-start",
+        POSITION_UNIT,
+        move |state| match &state.clips.get(index)?.timeline_start {
+            ClipStart::OnLane(on_lane) => Some(on_lane.timeline_start.get().as_beats_f64()),
+            ClipStart::NotInTimeline => None,
+        },
+        move |beats| UiEvent::SetClipStart(index, beats),
+    );
+    value_entry(
+        cx,
+        "inspector-length",
+        Unit::Beats,
+        move |state| Some(state.clips.get(index)?.length.get().as_beats_f64()),
+        move |beats| UiEvent::SetClipLength(index, beats),
+    );
+
+    let is_audio =
+        clips.map(move |clips| clips.get(index).map_or(false, |clip| audio(clip).is_some()));
+    Binding::new(cx, is_audio, move |cx, is_audio| {
+        if is_audio.get(cx) {
+            value_entry(
+                cx,
+                "inspector-gain",
+                Unit::Decibels,
+                move |state| Some(f64::from(audio(state.clips.get(index)?)?.gain_db)),
+                move |db| UiEvent::SetClipGain(index, db as f32),
+            );
+            value_entry(
+                cx,
+                "inspector-fade-in",
+                Unit::Milliseconds,
+                move |state| Some(audio(state.clips.get(index)?)?.fade_in_secs.get().0 * 1_000.0),
+                move |ms| UiEvent::SetClipFade { clip: index, fade_out: false, secs: ms / 1_000.0 },
+            );
+            value_entry(
+                cx,
+                "inspector-fade-out",
+                Unit::Milliseconds,
+                move |state| Some(audio(state.clips.get(index)?)?.fade_out_secs.get().0 * 1_000.0),
+                move |ms| UiEvent::SetClipFade { clip: index, fade_out: true, secs: ms / 1_000.0 },
+            );
+        }
+    });
+
+    // Recorded notes that were moved by input quantize can be quantized again
+    // from where they were played.
+    let quantized = clips.map(move |clips| match clips.get(index).map(|clip| &clip.type_) {
+        Some(ClipType::PianoRoll(piano_roll)) => {
+            piano_roll.notes.iter().any(|note| note.recorded_start.is_some())
+        }
+        _ => false,
+    });
+    Binding::new(cx, quantized, move |cx, quantized| {
+        if quantized.get(cx) {
+            Button::new(
+                cx,
+                move |cx| cx.emit(UiEvent::RequantizeClip(index)),
+                |cx| Label::new(cx, localized("button-requantize")).class("small"),
+            );
+        }
+    });
+
+    let recording = move |f: fn(&RecordingInfo) -> String| {
+        clips.map(move |clips| {
+            clips.get(index).and_then(|clip| clip.recording.as_ref()).map(f).unwrap_or_default()
+        })
+    };
+    let recorded =
+        clips.map(move |clips| clips.get(index).map_or(false, |clip| clip.recording.is_some()));
+    Binding::new(cx, recorded, move |cx, recorded| {
+        if recorded.get(cx) {
+            property(cx, "inspector-source", recording(|r| r.source.clone()));
+            property(cx, "inspector-take", recording(|r| r.take.to_string()));
+            property(cx, "inspector-tempo", recording(|r| format!("{:.2} BPM", r.bpm)));
+            property(cx, "inspector-date", recording(|r| r.date.clone()));
+        } else {
+            Label::new(cx, localized("inspector-not-recorded")).class("small");
+        }
+    });
+}
+
+fn channel_properties(cx: &mut Context, index: usize) {
+    let channels = UiData::state.then(UiState::channels);
+
+    property(
+        cx,
+        "inspector-name",
+        channels.map(move |channels| {
+            channels.get(index).map(|channel| channel.name.clone()).unwrap_or_default()
+        }),
+    );
+
+    channel_mix(cx, index);
+
+    // The master channel isn't routed anywhere.
+    if index != 0 {
+        channel_output(cx, index);
+        channel_sends(cx, index);
+        midi_input(cx, index);
+        instrument_rack(cx, index);
+        drum_rack(cx, index);
+        midi_output(cx, index);
+    }
+    effect_tools(cx, index);
+    macro_knobs(cx, index);
+}
+
+/// The fader of the channel.
+fn channel_mix(cx: &mut Context, index: usize) {
+    let channels = UiData::state.then(UiState::channels);
+    let edit = move |edit: ChannelMixEdit| UiEvent::EditChannelMix { channel: index, edit };
+    let mute_solo = channels
+        .map(move |channels| channels.get(index).map_or((false, false), |c| (c.muted, c.soloed)));
+
+    value_entry(
+        cx,
+        "inspector-channel-gain",
+        Unit::Decibels,
+        move |state| Some(f64::from(state.channel_fader(index)?.0)),
+        move |db| edit(ChannelMixEdit::SetGain(db as f32)),
+    );
+    value_entry(
+        cx,
+        "inspector-channel-pan",
+        Unit::Plain { decimals: 2 },
+        move |state| Some(f64::from(state.channel_fader(index)?.1)),
+        move |pan| edit(ChannelMixEdit::SetPan(pan as f32)),
+    );
+    Binding::new(cx, mute_solo, move |cx, mute_solo| {
+        let (muted, soloed) = mute_solo.get(cx);
+        HStack::new(cx, |cx| {
+            Button::new(
+                cx,
+                move |cx| cx.emit(edit(ChannelMixEdit::SetMuted(!muted))),
+                |cx| Label::new(cx, localized("button-mute")).class("small"),
+            )
+            .checked(muted);
+            Button::new(
+                cx,
+                move |cx| cx.emit(edit(ChannelMixEdit::SetSoloed(!soloed))),
+                |cx| Label::new(cx, localized("button-solo")).class("small"),
+            )
+            .checked(soloed);
+        });
+    });
+}
+
+fn channel_output(cx: &mut Context, index: usize) {
+    let channels = UiData::state.then(UiState::channels);
+
+    HStack::new(cx, |cx| {
+        Label::new(cx, localized("inspector-output"))
+            .class("small")
+            .class("inspector_property_name");
+        Button::new(
+            cx,
+            move |cx| {
+                if let Some(ui_data) = cx.data::<UiData>() {
+                    let items = ui_data
+                        .state
+                        .channels
+                        .iter()
+                        .enumerate()
+                        .filter(|(to, _)| *to != index)
+                        .map(|(to, channel)| {
+                            ContextMenuItem::channel(
+                                channel.name.clone(),
+                                ChannelEvent::RouteChannel(index, to),
+                            )
+                        })
+                        .collect();
+                    open_context_menu(cx, items);
+                }
+            },
+            move |cx| {
+                Label::new(
+                    cx,
+                    channels.map(move |channels| {
+                        channels
+                            .get(index)
+                            .and_then(|channel| channels.get(channel.routed_to))
+                            .map(|output| output.name.clone())
+                            .unwrap_or_default()
+                    }),
+                )
+                .class("small")
+            },
+        )
+        .class("inspector_value");
+    })
+    .class("inspector_property");
+}
+
+/// The other channels the channel sends to, with the level of each send and
+/// whether it is taken before the fader.
+fn channel_sends(cx: &mut Context, index: usize) {
+    let channels = UiData::state.then(UiState::channels);
+    let edit = move |send: usize, edit: SendEdit| UiEvent::EditSend { channel: index, send, edit };
+    let count = channels.map(move |channels| channels.get(index).map_or(0, |c| c.sends.len()));
+
+    Binding::new(cx, count, move |cx, count| {
+        for send in 0..count.get(cx) {
+            let send_of =
+                move |state: &UiState| state.channels.get(index)?.sends.get(send).cloned();
+            let pre_fader = channels.map(move |channels| {
+                channels.get(index).and_then(|c| c.sends.get(send)).map_or(false, |s| s.pre_fader)
+            });
+
+            HStack::new(cx, |cx| {
+                Label::new(cx, localized("inspector-send"))
+                    .class("small")
+                    .class("inspector_property_name");
+                Label::new(
+                    cx,
+                    channels.map(move |channels| {
+                        channels
+                            .get(index)
+                            .and_then(|channel| channel.sends.get(send))
+                            .and_then(|send| channels.get(send.to))
+                            .map(|to| to.name.clone())
+                            .unwrap_or_default()
+                    }),
+                )
+                .class("small");
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(send, SendEdit::Remove)),
+                    |cx| Label::new(cx, localized("button-remove")).class("small"),
+                );
+            })
+            .class("inspector_property");
+            value_entry(
+                cx,
+                "inspector-send-level",
+                Unit::Decibels,
+                move |state| Some(f64::from(send_of(state)?.level_db)),
+                move |db| edit(send, SendEdit::SetLevel(db as f32)),
+            );
+            Binding::new(cx, pre_fader, move |cx, pre_fader| {
+                let pre_fader = pre_fader.get(cx);
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(send, SendEdit::SetPreFader(!pre_fader))),
+                    move |cx| {
+                        let key = if pre_fader { "button-pre-fader" } else { "button-post-fader" };
+                        Label::new(cx, localized(key)).class("small")
+                    },
+                );
+            });
+        }
+    });
+
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            move |cx| {
+                if let Some(ui_data) = cx.data::<UiData>() {
+                    let state = &ui_data.state;
+                    let items = state
+                        .channels
+                        .iter()
+                        .enumerate()
+                        .filter(|(to, _)| {
+                            *to != index
+                                && !state.channels[index].sends.iter().any(|s| s.to == *to)
+                                && !state.feeds(*to, index)
+                        })
+                        .map(|(to, channel)| {
+                            ContextMenuItem::ui(
+                                channel.name.clone(),
+                                UiEvent::AddSend { channel: index, to },
+                            )
+                        })
+                        .collect();
+                    open_context_menu(cx, items);
+                }
+            },
+            |cx| Label::new(cx, localized("button-add-send")).class("small"),
+        );
+        Button::new(
+            cx,
+            |cx| cx.emit(UiEvent::AddReturnBus),
+            |cx| Label::new(cx, localized("button-add-return-bus")).class("small"),
+        );
+    });
+}
+
+/// What the channel does to incoming notes before its instrument. A MIDI
+/// channel of 0 lets every channel through. Zones split the keyboard, or
+/// layer it where they overlap.
+fn midi_input(cx: &mut Context, index: usize) {
+    let edit = move |edit: MidiInputEdit| UiEvent::EditMidiInput { channel: index, edit };
+    let transform = move |state: &UiState| state.channels.get(index).map(|c| c.midi_input.clone());
+    let key = Unit::Plain { decimals: 0 };
+
+    value_entry(
+        cx,
+        "inspector-midi-in-channel",
+        Unit::Plain { decimals: 0 },
+        move |state| {
+            let channels = transform(state)?.channels;
+            Some(if channels == ALL_CHANNELS {
+                0.0
+            } else {
+                f64::from(channels.trailing_zeros() + 1)
+            })
+        },
+        move |n| {
+            let n = n.round().clamp(0.0, 16.0) as i16;
+            edit(MidiInputEdit::SetChannel((n > 0).then(|| n - 1)))
+        },
+    );
+    value_entry(
+        cx,
+        "inspector-low-key",
+        key,
+        move |state| Some(f64::from(transform(state)?.low_key)),
+        move |n| edit(MidiInputEdit::SetLowKey(n.round() as i16)),
+    );
+    value_entry(
+        cx,
+        "inspector-high-key",
+        key,
+        move |state| Some(f64::from(transform(state)?.high_key)),
+        move |n| edit(MidiInputEdit::SetHighKey(n.round() as i16)),
+    );
+    value_entry(
+        cx,
+        "inspector-min-velocity",
+        Unit::Percent,
+        move |state| Some(f64::from(transform(state)?.min_velocity)),
+        move |v| edit(MidiInputEdit::SetMinVelocity(v as f32)),
+    );
+    value_entry(
+        cx,
+        "inspector-max-velocity",
+        Unit::Percent,
+        move |state| Some(f64::from(transform(state)?.max_velocity)),
+        move |v| edit(MidiInputEdit::SetMaxVelocity(v as f32)),
+    );
+    value_entry(
+        cx,
+        "inspector-transpose",
+        Unit::Semitones,
+        move |state| Some(f64::from(transform(state)?.transpose)),
+        move |semitones| edit(MidiInputEdit::SetTranspose(semitones.round() as i16)),
+    );
+
+    HStack::new(cx, |cx| {
+        Label::new(cx, localized("inspector-velocity-curve"))
+            .class("small")
+            .class("inspector_property_name");
+        Button::new(
+            cx,
+            move |cx| {
+                if let Some(ui_data) = cx.data::<UiData>() {
+                    let items = VELOCITY_CURVES
+                        .iter()
+                        .map(|(key, curve)| {
+                            ContextMenuItem::ui(
+                                ui_data.localization.tr(key),
+                                edit(MidiInputEdit::SetVelocityCurve(*curve)),
+                            )
+                        })
+                        .collect();
+                    open_context_menu(cx, items);
+                }
+            },
+            move |cx| {
+                let curve = UiData::state.then(UiState::channels).map(move |channels| {
+                    let curve = channels.get(index)?.midi_input.velocity_curve;
+                    VELOCITY_CURVES.iter().position(|(_, c)| *c == curve)
+                });
+                Binding::new(cx, curve, |cx, curve| {
+                    if let Some(curve) = curve.get(cx) {
+                        Label::new(cx, localized(VELOCITY_CURVES[curve].0)).class("small");
+                    }
+                });
+            },
+        )
+        .class("inspector_value");
+    })
+    .class("inspector_property");
+
+    let zones = UiData::state
+        .then(UiState::channels)
+        .map(move |channels| channels.get(index).map_or(0, |c| c.midi_input.zones.len()));
+    Binding::new(cx, zones, move |cx, zones| {
+        for zone in 0..zones.get(cx) {
+            let zone_of = move |state: &UiState| transform(state)?.zones.get(zone).copied();
+
+            HStack::new(cx, |cx| {
+                Label::new(cx, localized("inspector-zone")).class("small");
+                Label::new(cx, (zone + 1).to_string()).class("small");
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(MidiInputEdit::RemoveZone(zone))),
+                    |cx| Label::new(cx, localized("button-remove")).class("small"),
+                );
+            })
+            .class("inspector_property");
+            value_entry(
+                cx,
+                "inspector-low-key",
+                key,
+                move |state| Some(f64::from(zone_of(state)?.low_key)),
+                move |n| edit(MidiInputEdit::SetZoneLowKey(zone, n.round() as i16)),
+            );
+            value_entry(
+                cx,
+                "inspector-high-key",
+                key,
+                move |state| Some(f64::from(zone_of(state)?.high_key)),
+                move |n| edit(MidiInputEdit::SetZoneHighKey(zone, n.round() as i16)),
+            );
+            value_entry(
+                cx,
+                "inspector-transpose",
+                Unit::Semitones,
+                move |state| Some(f64::from(zone_of(state)?.transpose)),
+                move |semitones| {
+                    edit(MidiInputEdit::SetZoneTranspose(zone, semitones.round() as i16))
+                },
+            );
+        }
+    });
+
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            move |cx| cx.emit(edit(MidiInputEdit::AddZone)),
+            |cx| Label::new(cx, localized("button-add-zone")).class("small"),
+        );
+        Button::new(
+            cx,
+            move |cx| cx.emit(edit(MidiInputEdit::Reset)),
+            |cx| Label::new(cx, localized("button-reset")).class("small"),
+        );
+    });
+}
+
+/// The chains of the instrument rack of the channel, each with the keys and
+/// velocities it plays and its place in the mix.
+fn instrument_rack(cx: &mut Context, index: usize) {
+    let channels = UiData::state.then(UiState::channels);
+    let edit =
+        move |chain: usize, edit: RackEdit| UiEvent::EditRackChain { channel: index, chain, edit };
+    let count = channels
+        .map(move |channels| channels.get(index).map_or(0, |c| c.instrument_rack.chains.len()));
+    let key = Unit::Plain { decimals: 0 };
+
+    Binding::new(cx, count, move |cx, count| {
+        for chain in 0..count.get(cx) {
+            let chain_of = move |state: &UiState| {
+                state.channels.get(index)?.instrument_rack.chains.get(chain).cloned()
+            };
+            let mute_solo = channels.map(move |channels| {
+                channels
+                    .get(index)
+                    .and_then(|c| c.instrument_rack.chains.get(chain))
+                    .map_or((false, false), |c| (c.muted, c.soloed))
+            });
+
+            HStack::new(cx, |cx| {
+                Label::new(cx, localized("inspector-chain"))
+                    .class("small")
+                    .class("inspector_property_name");
+                Label::new(
+                    cx,
+                    channels.map(move |channels| {
+                        channels
+                            .get(index)
+                            .and_then(|c| c.instrument_rack.chains.get(chain))
+                            .map(|c| c.name.clone())
+                            .unwrap_or_default()
+                    }),
+                )
+                .class("small");
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(chain, RackEdit::Remove)),
+                    |cx| Label::new(cx, localized("button-remove")).class("small"),
+                );
+            })
+            .class("inspector_property");
+            value_entry(
+                cx,
+                "inspector-low-key",
+                key,
+                move |state| Some(f64::from(chain_of(state)?.zone.low_key)),
+                move |n| edit(chain, RackEdit::SetLowKey(n.round() as i16)),
+            );
+            value_entry(
+                cx,
+                "inspector-high-key",
+                key,
+                move |state| Some(f64::from(chain_of(state)?.zone.high_key)),
+                move |n| edit(chain, RackEdit::SetHighKey(n.round() as i16)),
+            );
+            value_entry(
+                cx,
+                "inspector-min-velocity",
+                Unit::Percent,
+                move |state| Some(f64::from(chain_of(state)?.zone.min_velocity)),
+                move |v| edit(chain, RackEdit::SetMinVelocity(v as f32)),
+            );
+            value_entry(
+                cx,
+                "inspector-max-velocity",
+                Unit::Percent,
+                move |state| Some(f64::from(chain_of(state)?.zone.max_velocity)),
+                move |v| edit(chain, RackEdit::SetMaxVelocity(v as f32)),
+            );
+            value_entry(
+                cx,
+                "inspector-chain-gain",
+                Unit::Decibels,
+                move |state| Some(f64::from(chain_of(state)?.gain_db)),
+                move |db| edit(chain, RackEdit::SetGain(db as f32)),
+            );
+            value_entry(
+                cx,
+                "inspector-chain-pan",
+                Unit::Plain { decimals: 2 },
+                move |state| Some(f64::from(chain_of(state)?.pan)),
+                move |pan| edit(chain, RackEdit::SetPan(pan as f32)),
+            );
+            Binding::new(cx, mute_solo, move |cx, mute_solo| {
+                let (muted, soloed) = mute_solo.get(cx);
+                HStack::new(cx, |cx| {
+                    Button::new(
+                        cx,
+                        move |cx| cx.emit(edit(chain, RackEdit::SetMuted(!muted))),
+                        |cx| Label::new(cx, localized("button-mute")).class("small"),
+                    )
+                    .checked(muted);
+                    Button::new(
+                        cx,
+                        move |cx| cx.emit(edit(chain, RackEdit::SetSoloed(!soloed))),
+                        |cx| Label::new(cx, localized("button-solo")).class("small"),
+                    )
+                    .checked(soloed);
+                });
+            });
+        }
+    });
+
+    Button::new(
+        cx,
+        move |cx| cx.emit(UiEvent::AddRackChain(index)),
+        |cx| Label::new(cx, localized("button-add-chain")).class("small"),
+    );
+}
+
+/// The pads of the drum rack of the channel, each with its key, sample,
+/// sound and place in the mix.
+fn drum_rack(cx: &mut Context, index: usize) {
+    let channels = UiData::state.then(UiState::channels);
+    let edit =
+        move |pad: usize, edit: DrumPadEdit| UiEvent::EditDrumPad { channel: index, pad, edit };
+    let count =
+        channels.map(move |channels| channels.get(index).map_or(0, |c| c.drum_rack.pads.len()));
+    let plain = Unit::Plain { decimals: 0 };
+
+    Binding::new(cx, count, move |cx, count| {
+        for pad in 0..count.get(cx) {
+            let pad_of =
+                move |state: &UiState| state.channels.get(index)?.drum_rack.pads.get(pad).cloned();
+            let pad_field = move |f: fn(&DrumPad) -> String| {
+                channels.map(move |channels| {
+                    channels
+                        .get(index)
+                        .and_then(|c| c.drum_rack.pads.get(pad))
+                        .map(f)
+                        .unwrap_or_default()
+                })
+            };
+
+            HStack::new(cx, |cx| {
+                Label::new(cx, localized("inspector-pad"))
+                    .class("small")
+                    .class("inspector_property_name");
+                Label::new(cx, pad_field(|p| p.name.clone())).class("small");
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(pad, DrumPadEdit::Remove)),
+                    |cx| Label::new(cx, localized("button-remove")).class("small"),
+                );
+            })
+            .class("inspector_property");
+            HStack::new(cx, |cx| {
+                Label::new(cx, localized("inspector-sample"))
+                    .class("small")
+                    .class("inspector_property_name");
+                Label::new(
+                    cx,
+                    pad_field(|p| {
+                        p.sample
+                            .as_ref()
+                            .and_then(|sample| sample.file_name())
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default()
+                    }),
+                )
+                .class("small");
+                Button::new(
+                    cx,
+                    move |cx| {
+                        if let Some(path) = rfd::FileDialog::new().pick_file() {
+                            cx.emit(edit(pad, DrumPadEdit::SetSample(Some(path))));
+                        }
+                    },
+                    |cx| Label::new(cx, localized("button-choose-sample")).class("small"),
+                );
+            })
+            .class("inspector_property");
+            value_entry(
+                cx,
+                "inspector-pad-key",
+                plain,
+                move |state| Some(f64::from(pad_of(state)?.key)),
+                move |n| edit(pad, DrumPadEdit::SetKey(n.round() as i16)),
+            );
+            value_entry(
+                cx,
+                "inspector-pad-tune",
+                Unit::Semitones,
+                move |state| Some(f64::from(pad_of(state)?.tune)),
+                move |st| edit(pad, DrumPadEdit::SetTune(st as f32)),
+            );
+            value_entry(
+                cx,
+                "inspector-pad-cutoff",
+                Unit::Hertz,
+                move |state| Some(f64::from(pad_of(state)?.cutoff_hz)),
+                move |hz| edit(pad, DrumPadEdit::SetCutoff(hz as f32)),
+            );
+            value_entry(
+                cx,
+                "inspector-chain-gain",
+                Unit::Decibels,
+                move |state| Some(f64::from(pad_of(state)?.gain_db)),
+                move |db| edit(pad, DrumPadEdit::SetGain(db as f32)),
+            );
+            value_entry(
+                cx,
+                "inspector-chain-pan",
+                Unit::Plain { decimals: 2 },
+                move |state| Some(f64::from(pad_of(state)?.pan)),
+                move |pan| edit(pad, DrumPadEdit::SetPan(pan as f32)),
+            );
+            // A choke group of 0 is no group.
+            value_entry(
+                cx,
+                "inspector-choke-group",
+                plain,
+                move |state| Some(f64::from(pad_of(state)?.choke_group.unwrap_or(0))),
+                move |n| {
+                    let group = n.round().clamp(0.0, f64::from(u8::MAX)) as u8;
+                    edit(pad, DrumPadEdit::SetChokeGroup(Some(group).filter(|g| *g != 0)))
+                },
+            );
+            let separate = channels.map(move |channels| {
+                channels
+                    .get(index)
+                    .and_then(|c| c.drum_rack.pads.get(pad))
+                    .map_or(false, |p| p.separate_output)
+            });
+            Binding::new(cx, separate, move |cx, separate| {
+                let separate = separate.get(cx);
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(pad, DrumPadEdit::SetSeparateOutput(!separate))),
+                    |cx| Label::new(cx, localized("button-separate-output")).class("small"),
+                )
+                .checked(separate);
+                if separate {
+                    drum_pad_output(cx, index, pad);
+                }
+            });
+        }
+    });
+
+    Button::new(
+        cx,
+        move |cx| cx.emit(UiEvent::AddDrumPads(index)),
+        |cx| Label::new(cx, localized("button-add-drum-pads")).class("small"),
+    );
+}
+
+/// The channel the separate output of a drum pad plays into.
+fn drum_pad_output(cx: &mut Context, index: usize, pad: usize) {
+    let channels = UiData::state.then(UiState::channels);
+    HStack::new(cx, |cx| {
+        Label::new(cx, localized("inspector-pad-output"))
+            .class("small")
+            .class("inspector_property_name");
+        Button::new(
+            cx,
+            move |cx| {
+                if let Some(ui_data) = cx.data::<UiData>() {
+                    let items = ui_data
+                        .state
+                        .channels
+                        .iter()
+                        .enumerate()
+                        .map(|(to, channel)| {
+                            ContextMenuItem::ui(
+                                channel.name.clone(),
+                                UiEvent::EditDrumPad {
+                                    channel: index,
+                                    pad,
+                                    edit: DrumPadEdit::SetOutputChannel(to),
+                                },
+                            )
+                        })
+                        .collect();
+                    open_context_menu(cx, items);
+                }
+            },
+            move |cx| {
+                Label::new(
+                    cx,
+                    channels.map(move |channels| {
+                        channels
+                            .get(index)
+                            .and_then(|channel| channel.drum_rack.pads.get(pad))
+                            .and_then(|pad| channels.get(pad.output_channel))
+                            .map(|output| output.name.clone())
+                            .unwrap_or_default()
+                    }),
+                )
+                .class("small")
+            },
+        )
+        .class("inspector_value");
+    })
+    .class("inspector_property");
+}
+
+/// The macro knobs of a channel, each with the parameters mapped to it and
+/// the range and curve each of them follows the knob with.
+fn macro_knobs(cx: &mut Context, index: usize) {
+    let channels = UiData::state.then(UiState::channels);
+    let edit = move |edit: MacroKnobEdit| UiEvent::EditMacroKnobs { channel: index, edit };
+    let count =
+        channels.map(move |channels| channels.get(index).map_or(0, |c| c.macro_knobs.len()));
+
+    Binding::new(cx, count, move |cx, count| {
+        for knob in 0..count.get(cx) {
+            let knob_lens =
+                channels.map(move |channels| channels.get(index)?.macro_knobs.get(knob).cloned());
+
+            HStack::new(cx, |cx| {
+                Label::new(cx, localized("inspector-macro-knob"))
+                    .class("small")
+                    .class("inspector_property_name");
+                Label::new(
+                    cx,
+                    knob_lens.map(|k| k.as_ref().map(|k| k.name.clone()).unwrap_or_default()),
+                )
+                .class("small");
+                Knob::new(
+                    cx,
+                    0.0,
+                    knob_lens.map(|k| k.as_ref().map_or(0.0, |k| k.value as f32)),
+                    false,
+                )
+                .on_changing(move |cx, value| {
+                    cx.emit(edit(MacroKnobEdit::SetValue(knob, f64::from(value))))
+                })
+                .class("inspector_morph");
+                Button::new(
+                    cx,
+                    move |cx| {
+                        // Any parameter the UI knows of on a plugin of the
+                        // channel can be mapped.
+                        if let Some(ui_data) = cx.data::<UiData>() {
+                            let effects = ui_data
+                                .state
+                                .channels
+                                .get(index)
+                                .map(|c| c.effects.as_slice())
+                                .unwrap_or_default();
+                            let items = effects
+                                .iter()
+                                .enumerate()
+                                .filter_map(|(effect, state)| match state {
+                                    HRackEffectState::External(plugin) => Some((effect, plugin)),
+                                    _ => None,
+                                })
+                                .flat_map(|(effect, plugin)| {
+                                    plugin.parameters().into_iter().map(move |parameter| {
+                                        ContextMenuItem::ui(
+                                            format!("{}: {}", plugin.name, parameter.name),
+                                            edit(MacroKnobEdit::MapParameter {
+                                                knob,
+                                                effect,
+                                                id: parameter.id,
+                                            }),
+                                        )
+                                    })
+                                })
+                                .collect();
+                            open_context_menu(cx, items);
+                        }
+                    },
+                    |cx| Label::new(cx, localized("button-map-parameter")).class("small"),
+                );
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(MacroKnobEdit::RemoveKnob(knob))),
+                    |cx| Label::new(cx, localized("button-remove")).class("small"),
+                );
+            })
+            .class("inspector_property");
+
+            let targets = knob_lens.map(|k| k.as_ref().map_or(0, |k| k.targets.len()));
+            Binding::new(cx, targets, move |cx, targets| {
+                for target in 0..targets.get(cx) {
+                    macro_knob_target(cx, index, knob, target);
+                }
+            });
+        }
+    });
+
+    Button::new(
+        cx,
+        move |cx| cx.emit(UiEvent::AddMacroKnob(index)),
+        |cx| Label::new(cx, localized("button-add-macro-knob")).class("small"),
+    );
+}
+
+fn macro_knob_target(cx: &mut Context, index: usize, knob: usize, target: usize) {
+    let edit = move |edit: MacroKnobEdit| UiEvent::EditMacroKnobs { channel: index, edit };
+    let target_of = move |state: &UiState| {
+        state.channels.get(index)?.macro_knobs.get(knob)?.targets.get(target).copied()
+    };
+    let target_lens = UiData::state.map(move |state| target_of(state));
+
+    HStack::new(cx, |cx| {
+        Label::new(
+            cx,
+            UiData::state.map(move |state| {
+                let target = match target_of(state) {
+                    Some(target) => target,
+                    None => return String::new(),
+                };
+                let slot = EffectSlot { channel: index, effect: target.effect };
+                plugin(&state.channels, slot)
+                    .and_then(|p| {
+                        let parameter = p.parameters().into_iter().find(|p| p.id == target.id)?;
+                        Some(format!("{}: {}", p.name, parameter.name))
+                    })
+                    .unwrap_or_default()
+            }),
+        )
+        .class("small")
+        .class("inspector_property_name");
+        Button::new(
+            cx,
+            move |cx| {
+                if let Some(ui_data) = cx.data::<UiData>() {
+                    let items = MACRO_CURVES
+                        .iter()
+                        .map(|(key, curve)| {
+                            ContextMenuItem::ui(
+                                ui_data.localization.tr(key),
+                                edit(MacroKnobEdit::SetCurve { knob, target, curve: *curve }),
+                            )
+                        })
+                        .collect();
+                    open_context_menu(cx, items);
+                }
+            },
+            move |cx| {
+                let curve = target_lens.map(|t| {
+                    let curve = t.as_ref()?.mapping.curve;
+                    MACRO_CURVES.iter().position(|(_, c)| *c == curve)
+                });
+                Binding::new(cx, curve, |cx, curve| {
+                    if let Some(curve) = curve.get(cx) {
+                        Label::new(cx, localized(MACRO_CURVES[curve].0)).class("small");
+                    }
+                });
+            },
+        )
+        .class("inspector_value");
+        Button::new(
+            cx,
+            move |cx| cx.emit(edit(MacroKnobEdit::UnmapParameter { knob, target })),
+            |cx| Label::new(cx, localized("button-remove")).class("small"),
+        );
+    })
+    .class("inspector_property");
+
+    value_entry(
+        cx,
+        "inspector-macro-min",
+        Unit::Percent,
+        move |state| Some(target_of(state)?.mapping.min),
+        move |min| edit(MacroKnobEdit::SetMin { knob, target, min }),
+    );
+    value_entry(
+        cx,
+        "inspector-macro-max",
+        Unit::Percent,
+        move |state| Some(target_of(state)?.mapping.max),
+        move |max| edit(MacroKnobEdit::SetMax { knob, target, max }),
+    );
+}
+
+/// The hardware synth the channel sends its MIDI to: the device, the MIDI
+/// channel, and the program and bank selected on it. Programs and channels are
+/// numbered from 1 like on most hardware.
+fn midi_output(cx: &mut Context, index: usize) {
+    let device = move || {
+        UiData::state.then(UiState::channels).map(move |channels| {
+            let route = channels.get(index).and_then(|c| c.midi_output.as_ref());
+            route.map(|route| route.device.clone())
+        })
+    };
+
+    HStack::new(cx, |cx| {
+        Label::new(cx, localized("inspector-midi-output"))
+            .class("small")
+            .class("inspector_property_name");
+        Button::new(
+            cx,
+            move |cx| {
+                if let Some(ui_data) = cx.data::<UiData>() {
+                    let l = &ui_data.localization;
+                    let mut items = vec![ContextMenuItem::ui(
+                        l.tr("item-no-midi-output"),
+                        UiEvent::SetMidiOutput { channel: index, device: None },
+                    )];
+                    items.extend(ui_data.state.midi_out_devices.iter().map(|device| {
+                        ContextMenuItem::ui(
+                            device.name.clone(),
+                            UiEvent::SetMidiOutput {
+                                channel: index,
+                                device: Some(device.name.clone()),
+                            },
+                        )
+                    }));
+                    items.push(ContextMenuItem::ui(
+                        l.tr("item-refresh-midi-devices"),
+                        UiEvent::RefreshMidiOutDevices,
+                    ));
+                    open_context_menu(cx, items);
+                }
+            },
+            move |cx| {
+                Label::new(cx, device().map(|device| device.clone().unwrap_or_else(|| "-".into())))
+                    .class("small")
+            },
+        )
+        .class("inspector_value");
+    })
+    .class("inspector_property");
+
+    Binding::new(cx, device(), move |cx, device| {
+        let device = match device.get(cx) {
+            Some(device) => device,
+            None => return,
+        };
+        let route = move |state: &UiState| state.channels.get(index)?.midi_output.clone();
+
+        value_entry(
+            cx,
+            "inspector-midi-channel",
+            Unit::Plain { decimals: 0 },
+            move |state| Some(f64::from(route(state)?.channel) + 1.0),
+            move |n| UiEvent::SetMidiOutChannel {
+                channel: index,
+                midi_channel: n.round().clamp(1.0, 16.0) as u8 - 1,
+            },
+        );
+        value_entry(
+            cx,
+            "inspector-midi-program",
+            Unit::Plain { decimals: 0 },
+            move |state| Some(f64::from(route(state)?.program?) + 1.0),
+            move |n| UiEvent::SetMidiProgram {
+                channel: index,
+                program: Some(n.round().clamp(1.0, 128.0) as u8 - 1),
+            },
+        );
+        value_entry(
+            cx,
+            "inspector-midi-bank",
+            Unit::Plain { decimals: 0 },
+            move |state| Some(f64::from(route(state)?.bank?)),
+            move |n| UiEvent::SetMidiBank {
+                channel: index,
+                bank: Some(n.round().clamp(0.0, 16383.0) as u16),
+            },
+        );
+
+        let name = device.clone();
+        value_entry(
+            cx,
+            "inspector-midi-latency",
+            Unit::Milliseconds,
+            move |state| {
+                Some(state.midi_out_devices.iter().find(|d| d.name == name)?.latency_offset_ms)
+            },
+            move |ms| UiEvent::SetMidiOutLatency { device: device.clone(), ms },
+        );
+
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::AddMidiReturnChannel(index)),
+            |cx| Label::new(cx, localized("button-add-return-channel")).class("small"),
+        );
+    });
+}
+
+/// Sound design tools for each plugin of a channel: giving chosen parameters
+/// random values within their ranges, and morphing between two snapshots of
+/// the parameters with one knob.
+fn effect_tools(cx: &mut Context, channel: usize) {
+    let channels = UiData::state.then(UiState::channels);
+    let count = channels.map(move |channels| channels.get(channel).map_or(0, |c| c.effects.len()));
+
+    Binding::new(cx, count, move |cx, count| {
+        for effect in 0..count.get(cx) {
+            let slot = EffectSlot { channel, effect };
+            let kind = channels.map(move |channels| {
+                (plugin(channels, slot).is_some(), fx_rack(channels, slot).is_some())
+            });
+            Binding::new(cx, kind, move |cx, kind| match kind.get(cx) {
+                (true, _) => plugin_tools(cx, slot),
+                (_, true) => fx_rack_tools(cx, slot),
+                _ => {}
+            });
+        }
+    });
+
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            move |cx| {
+                if let Some(ui_data) = cx.data::<UiData>() {
+                    let items = ui_data
+                        .state
+                        .available_plugins
+                        .iter()
+                        .map(|plugin| {
+                            ContextMenuItem::ui(
+                                format!("{} ({})", plugin.name, plugin.vendor),
+                                UiEvent::AddPlugin { channel, rdn: plugin.rdn.clone() },
+                            )
+                        })
+                        .collect();
+                    open_context_menu(cx, items);
+                }
+            },
+            |cx| Label::new(cx, localized("button-add-plugin")).class("small"),
+        );
+        Button::new(
+            cx,
+            |cx| cx.emit(UiEvent::RescanPlugins),
+            |cx| Label::new(cx, localized("button-rescan-plugins")).class("small"),
+        );
+    })
+    .class("inspector_property");
+
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::AddFxRack(channel)),
+            |cx| Label::new(cx, localized("button-add-fx-rack")).class("small"),
+        );
+        Button::new(
+            cx,
+            move |cx| {
+                if let Some(ui_data) = cx.data::<UiData>() {
+                    let items = ui_data
+                        .state
+                        .fx_rack_presets
+                        .iter()
+                        .enumerate()
+                        .map(|(preset, rack)| {
+                            ContextMenuItem::ui(
+                                rack.name.clone(),
+                                UiEvent::LoadFxRackPreset { channel, preset },
+                            )
+                        })
+                        .collect();
+                    open_context_menu(cx, items);
+                }
+            },
+            |cx| Label::new(cx, localized("button-load-fx-rack")).class("small"),
+        );
+    })
+    .class("inspector_property");
+}
+
+/// The chains of a rack of parallel effects, with their mix and the effects
+/// in them. Effects are moved in from the channel the rack is on.
+fn fx_rack_tools(cx: &mut Context, slot: EffectSlot) {
+    let channels = UiData::state.then(UiState::channels);
+    let edit = move |edit: FxRackEdit| UiEvent::EditFxRack { slot, edit };
+    let count = channels.map(move |channels| fx_rack(channels, slot).map_or(0, |r| r.chains.len()));
+
+    Label::new(
+        cx,
+        channels.map(move |channels| {
+            fx_rack(channels, slot).map(|r| r.name.clone()).unwrap_or_default()
+        }),
+    )
+    .class("small")
+    .class("inspector_heading");
+
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            move |cx| cx.emit(edit(FxRackEdit::AddChain(String::new()))),
+            |cx| Label::new(cx, localized("button-add-chain")).class("small"),
+        );
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::SaveFxRackPreset(slot)),
+            |cx| Label::new(cx, localized("button-save-fx-rack")).class("small"),
+        );
+        move_effect_buttons(cx, slot);
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::RemoveEffect(slot)),
+            |cx| Label::new(cx, localized("button-remove")).class("small"),
+        );
+    })
+    .class("inspector_property");
+
+    Binding::new(cx, count, move |cx, count| {
+        for chain in 0..count.get(cx) {
+            let chain_of = move |state: &UiState| {
+                fx_rack(&state.channels, slot)?.chains.get(chain).map(|c| c.mix)
+            };
+            let chain_lens =
+                channels.map(move |channels| fx_rack(channels, slot)?.chains.get(chain).cloned());
+
+            HStack::new(cx, |cx| {
+                Label::new(cx, localized("inspector-chain"))
+                    .class("small")
+                    .class("inspector_property_name");
+                Label::new(
+                    cx,
+                    chain_lens.map(|c| c.as_ref().map(|c| c.name.clone()).unwrap_or_default()),
+                )
+                .class("small");
+                Button::new(
+                    cx,
+                    move |cx| {
+                        // Any other effect of the channel can be moved in.
+                        if let Some(ui_data) = cx.data::<UiData>() {
+                            let effects = ui_data
+                                .state
+                                .channels
+                                .get(slot.channel)
+                                .map(|c| c.effects.as_slice())
+                                .unwrap_or_default();
+                            let items = effects
+                                .iter()
+                                .enumerate()
+                                .filter(|(effect, _)| *effect != slot.effect)
+                                .map(|(effect, state)| {
+                                    ContextMenuItem::ui(
+                                        effect_name(state),
+                                        edit(FxRackEdit::MoveEffectIn { effect, chain }),
+                                    )
+                                })
+                                .collect();
+                            open_context_menu(cx, items);
+                        }
+                    },
+                    |cx| Label::new(cx, localized("button-move-effect-in")).class("small"),
+                );
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(FxRackEdit::RemoveChain(chain))),
+                    |cx| Label::new(cx, localized("button-remove")).class("small"),
+                );
+            })
+            .class("inspector_property");
+            value_entry(
+                cx,
+                "inspector-chain-gain",
+                Unit::Decibels,
+                move |state| Some(f64::from(chain_of(state)?.gain_db)),
+                move |db| edit(FxRackEdit::SetChainGain(chain, db as f32)),
+            );
+            value_entry(
+                cx,
+                "inspector-chain-pan",
+                Unit::Plain { decimals: 2 },
+                move |state| Some(f64::from(chain_of(state)?.pan)),
+                move |pan| edit(FxRackEdit::SetChainPan(chain, pan as f32)),
+            );
+            let muted = chain_lens.map(|c| c.as_ref().map_or(false, |c| c.mix.muted));
+            Binding::new(cx, muted, move |cx, muted| {
+                let muted = muted.get(cx);
+                Button::new(
+                    cx,
+                    move |cx| cx.emit(edit(FxRackEdit::SetChainMuted(chain, !muted))),
+                    |cx| Label::new(cx, localized("button-mute")).class("small"),
+                )
+                .checked(muted);
+            });
+
+            let names = chain_lens.map(|c| {
+                c.as_ref()
+                    .map(|c| c.effects.iter().map(effect_name).collect::<Vec<_>>())
+                    .unwrap_or_default()
+            });
+            List::new(cx, names, move |cx, effect, name| {
+                HStack::new(cx, |cx| {
+                    Label::new(cx, name).class("small").class("inspector_property_name");
+                    Button::new(
+                        cx,
+                        move |cx| cx.emit(edit(FxRackEdit::MoveEffectOut { chain, effect })),
+                        |cx| Label::new(cx, localized("button-move-effect-out")).class("small"),
+                    );
+                })
+                .class("inspector_property");
+            });
+        }
+    });
+}
+
+/// Buttons that move an effect one place earlier or later in its channel.
+fn move_effect_buttons(cx: &mut Context, slot: EffectSlot) {
+    Button::new(
+        cx,
+        move |cx| {
+            if let Some(to) = slot.effect.checked_sub(1) {
+                cx.emit(UiEvent::MoveEffect { slot, to });
+            }
+        },
+        |cx| Label::new(cx, localized("button-move-effect-up")).class("small"),
+    );
+    Button::new(
+        cx,
+        move |cx| cx.emit(UiEvent::MoveEffect { slot, to: slot.effect + 1 }),
+        |cx| Label::new(cx, localized("button-move-effect-down")).class("small"),
+    );
+}
+
+fn plugin_tools(cx: &mut Context, slot: EffectSlot) {
+    let channels = UiData::state.then(UiState::channels);
+
+    Label::new(
+        cx,
+        channels.map(move |channels| {
+            plugin(channels, slot).map(|p| p.name.clone()).unwrap_or_default()
+        }),
+    )
+    .class("small")
+    .class("inspector_heading");
+
+    HStack::new(cx, |cx| {
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::RandomizeParameters(slot)),
+            |cx| Label::new(cx, localized("button-randomize")).class("small"),
+        );
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::StoreMorphPreset { slot, b: false }),
+            |cx| Label::new(cx, localized("button-store-a")).class("small"),
+        );
+        Knob::new(
+            cx,
+            0.0,
+            channels
+                .map(move |channels| plugin(channels, slot).map_or(0.0, |p| p.morph.amount as f32)),
+            false,
+        )
+        .on_changing(move |cx, amount| {
+            cx.emit(UiEvent::SetMorphAmount { slot, amount: f64::from(amount) })
+        })
+        .class("inspector_morph");
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::StoreMorphPreset { slot, b: true }),
+            |cx| Label::new(cx, localized("button-store-b")).class("small"),
+        );
+        let bypassed =
+            channels.map(move |channels| plugin(channels, slot).map_or(false, |p| p.bypassed));
+        Binding::new(cx, bypassed, move |cx, bypassed| {
+            let bypassed = bypassed.get(cx);
+            Button::new(
+                cx,
+                move |cx| cx.emit(UiEvent::SetEffectBypassed { slot, bypassed: !bypassed }),
+                |cx| Label::new(cx, localized("button-bypass")).class("small"),
+            )
+            .checked(bypassed);
+        });
+        move_effect_buttons(cx, slot);
+        Button::new(
+            cx,
+            move |cx| cx.emit(UiEvent::RemoveEffect(slot)),
+            |cx| Label::new(cx, localized("button-remove-plugin")).class("small"),
+        );
+    })
+    .class("inspector_property");
+
+    List::new(
+        cx,
+        channels.map(move |channels| {
+            plugin(channels, slot).map(|p| p.parameters()).unwrap_or_default()
+        }),
+        move |cx, _, parameter| {
+            let id = parameter.get(cx).id;
+            let range = channels.map(move |channels| {
+                plugin(channels, slot)
+                    .and_then(|p| p.randomize_ranges.iter().find(|r| r.id == id).copied())
+            });
+
+            HStack::new(cx, |cx| {
+                Button::new(
+                    cx,
+                    move |cx| {
+                        let range = cx.data::<UiData>().and_then(|ui_data| {
+                            let p = plugin(&ui_data.state.channels, slot)?;
+                            p.randomize_ranges.iter().find(|r| r.id == id).copied()
+                        });
+                        cx.emit(UiEvent::SetRandomizeRange {
+                            slot,
+                            parameter: id,
+                            range: match range {
+                                Some(_) => None,
+                                None => Some((0.0, 1.0)),
+                            },
+                        });
+                    },
+                    |cx| Label::new(cx, localized("inspector-randomize")).class("small"),
+                )
+                .toggle_class("selected", range.map(Option::is_some));
+                Label::new(cx, parameter.then(ParameterState::name))
+                    .class("small")
+                    .class("inspector_property_name");
+                Label::new(cx, parameter.then(ParameterState::display_value))
+                    .class("small")
+                    .text_wrap(false);
+            })
+            .class("inspector_property");
+
+            Binding::new(cx, range.map(Option::is_some), move |cx, randomized| {
+                if randomized.get(cx) {
+                    HStack::new(cx, |cx| {
+                        Label::new(cx, localized("inspector-random-range"))
+                            .class("small")
+                            .class("inspector_property_name");
+                        range_entry(cx, slot, id, range, false);
+                        range_entry(cx, slot, id, range, true);
+                    })
+                    .class("inspector_property");
+                }
+            });
+        },
+    );
+}
+
+/// A text box for the minimum (`max == false`) or maximum of the range that
+/// randomizing picks values of a parameter from.
+fn range_entry(
+    cx: &mut Context,
+    slot: EffectSlot,
+    id: u32,
+    range: impl Lens<Target = Option<ParameterRange>>,
+    max: bool,
+) {
+    Textbox::new(
+        cx,
+        range.map(move |range| {
+            range.map(|r| Unit::Percent.format(if max { r.max } else { r.min })).unwrap_or_default()
+        }),
+    )
+    .on_submit(move |cx, text| {
+        let range = cx.data::<UiData>().and_then(|ui_data| {
+            let p = plugin(&ui_data.state.channels, slot)?;
+            p.randomize_ranges.iter().find(|r| r.id == id).copied()
+        });
+        if let (Some(range), Some(value)) = (range, Unit::Percent.parse(&text)) {
+            let range = if max { (range.min, value) } else { (value, range.max) };
+            cx.emit(UiEvent::SetRandomizeRange { slot, parameter: id, range: Some(range) });
+        }
+    })
+    .class("inspector_value");
+}
+
+fn fx_rack(channels: &[ChannelState], slot: EffectSlot) -> Option<&FxRackState> {
+    match channels.get(slot.channel)?.effects.get(slot.effect)? {
+        HRackEffectState::Rack(rack) => Some(rack),
+        _ => None,
+    }
+}
+
+fn effect_name(effect: &HRackEffectState) -> String {
+    match effect {
+        HRackEffectState::External(plugin) => plugin.name.clone(),
+        HRackEffectState::Rack(rack) => rack.name.clone(),
+        HRackEffectState::Internal(_) => String::new(),
+    }
+}
+
+fn plugin(channels: &[ChannelState], slot: EffectSlot) -> Option<&ExternalEffectState> {
+    match channels.get(slot.channel)?.effects.get(slot.effect)? {
+        HRackEffectState::External(plugin) => Some(plugin),
+        _ => None,
+    }
+}
+
+fn section_properties(cx: &mut Context, index: usize) {
+    let arranger = UiData::state.then(UiState::arranger);
+
+    property(
+        cx,
+        "inspector-name",
+        arranger.map(move |arranger| {
+            arranger.sections.get(index).map(|section| section.name.clone()).unwrap_or_default()
+        }),
+    );
+    property(
+        cx,
+        "inspector-start",
+        arranger.map(move |arranger| {
+            arranger
+                .section_ranges()
+                .get(index)
+                .map(|range| POSITION_UNIT.format(range.start))
+                .unwrap_or_default()
+        }),
+    );
+    value_entry(
+        cx,
+        "inspector-length",
+        Unit::Beats,
+        move |state| Some(state.arranger.sections.get(index)?.length.get().as_beats_f64()),
+        move |beats| UiEvent::SetSectionLength(index, beats),
+    );
+}
+
+fn audio(clip: &ClipState) -> Option<&AudioClipState> {
+    match &clip.type_ {
+        ClipType::Audio(audio) => Some(audio),
+        _ => None,
+    }
+}
+
+/// A row with the name of a property and its value.
+fn property(cx: &mut Context, name: &'static str, value: impl Lens<Target = String>) {
+    HStack::new(cx, |cx| {
+        Label::new(cx, localized(name)).class("small").class("inspector_property_name");
+        Label::new(cx, value).class("small").text_wrap(false);
+    })
+    .class("inspector_property");
+}
+
+/// A row with the name of a property and a text box showing its value in the
+/// given unit. Text typed into it is read with the same unit and sent with the
+/// same event that editing the value anywhere else sends.
+fn value_entry(
+    cx: &mut Context,
+    name: &'static str,
+    unit: Unit,
+    get: impl 'static + Fn(&UiState) -> Option<f64>,
+    set: impl 'static + Fn(f64) -> UiEvent,
+) {
+    HStack::new(cx, |cx| {
+        Label::new(cx, localized(name)).class("small").class("inspector_property_name");
+        Textbox::new(
+            cx,
+            UiData::state.map(move |state| get(state).map(|v| unit.format(v)).unwrap_or_default()),
+        )
+        .on_submit(move |cx, text| {
+            if let Some(value) = unit.parse(&text) {
+                cx.emit(set(value));
+            }
+        })
+        .class("inspector_value");
+    })
+    .class("inspector_property");
+}
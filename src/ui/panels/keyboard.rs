@@ -0,0 +1,93 @@
+use vizia::prelude::*;
+
+use crate::ui::state::{
+    is_black_key, key_name, keyboard_keys, localized, OnScreenInstrumentsState, PanelState, UiData,
+    UiEvent, UiState,
+};
+use crate::ui::{NotePad, Panel};
+
+/// The width of a black key, relative to a white key.
+const BLACK_KEY_WIDTH: f32 = 0.6;
+
+/// A piano keyboard to play notes with the mouse on the selected channel.
+/// The keys of notes held on the MIDI input light up.
+pub fn keyboard(cx: &mut Context) {
+    let octave = UiData::state
+        .then(UiState::onscreen_instruments.then(OnScreenInstrumentsState::keyboard_octave));
+
+    VStack::new(cx, |cx| {
+        Panel::new(
+            cx,
+            |cx| {
+                Label::new(cx, localized("panel-keyboard")).class("small");
+            },
+            |cx| {
+                HStack::new(cx, |cx| {
+                    Button::new(
+                        cx,
+                        move |cx| {
+                            let octave = octave.get(cx);
+                            cx.emit(UiEvent::SetKeyboardOctave(octave - 1));
+                        },
+                        |cx| Label::new(cx, "-").class("small"),
+                    );
+                    Label::new(cx, octave.map(|octave| format!("C{}", octave))).class("small");
+                    Button::new(
+                        cx,
+                        move |cx| {
+                            let octave = octave.get(cx);
+                            cx.emit(UiEvent::SetKeyboardOctave(octave + 1));
+                        },
+                        |cx| Label::new(cx, "+").class("small"),
+                    );
+                })
+                .class("onscreen_toolbar");
+
+                Binding::new(cx, octave, |cx, octave| {
+                    let keys = keyboard_keys(octave.get(cx));
+                    let white_keys = keys.iter().filter(|key| !is_black_key(**key)).count();
+                    let white_width = 100.0 / white_keys.max(1) as f32;
+
+                    ZStack::new(cx, |cx| {
+                        HStack::new(cx, |cx| {
+                            for key in keys.iter().copied().filter(|key| !is_black_key(*key)) {
+                                NotePad::new(cx, key).class("white_key").width(Stretch(1.0));
+                            }
+                        });
+
+                        // Each black key sits on the line between two white keys.
+                        let mut whites_before = 0;
+                        for key in keys.iter().copied() {
+                            if is_black_key(key) {
+                                let left = whites_before as f32 * white_width
+                                    - white_width * BLACK_KEY_WIDTH / 2.0;
+                                NotePad::new(cx, key)
+                                    .class("black_key")
+                                    .left(Percentage(left))
+                                    .width(Percentage(white_width * BLACK_KEY_WIDTH));
+                            } else {
+                                whites_before += 1;
+                            }
+                        }
+
+                        // Label the Cs, so the octaves can be told apart.
+                        HStack::new(cx, |cx| {
+                            for key in keys.iter().copied().filter(|key| !is_black_key(*key)) {
+                                let name =
+                                    if key % 12 == 0 { key_name(key) } else { String::new() };
+                                Label::new(cx, name.as_str())
+                                    .class("small")
+                                    .class("key_name")
+                                    .width(Stretch(1.0));
+                            }
+                        })
+                        .hoverable(false);
+                    })
+                    .class("keyboard_keys");
+                });
+            },
+        );
+    })
+    .class("keyboard")
+    .toggle_class("hidden", UiData::state.then(UiState::panels.then(PanelState::hide_keyboard)));
+}
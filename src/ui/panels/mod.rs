@@ -21,3 +21,27 @@ pub use timeline::*;
 
 pub mod piano_roll;
 pub use piano_roll::*;
+
+pub mod tuner;
+pub use tuner::*;
+
+pub mod inspector;
+pub use inspector::*;
+
+pub mod sample_editor;
+pub use sample_editor::*;
+
+pub mod eq;
+pub use eq::*;
+
+pub mod collision;
+pub use collision::*;
+
+pub mod console;
+pub use console::*;
+
+pub mod keyboard;
+pub use keyboard::*;
+
+pub mod pads;
+pub use pads::*;
@@ -0,0 +1,66 @@
+use vizia::prelude::*;
+
+use crate::ui::state::{
+    key_name, localized, pad_key, OnScreenInstrumentsState, PanelState, UiData, UiEvent, UiState,
+    PAD_COLUMNS, PAD_ROWS,
+};
+use crate::ui::{NotePad, Panel};
+
+/// A grid of drum pads to play notes with the mouse on the selected channel.
+/// The pads count up from the bottom left, starting at the kick drum of the
+/// General MIDI drum map. The pads of notes held on the MIDI input light up.
+pub fn pads(cx: &mut Context) {
+    let first_pad_key = UiData::state
+        .then(UiState::onscreen_instruments.then(OnScreenInstrumentsState::first_pad_key));
+
+    VStack::new(cx, |cx| {
+        Panel::new(
+            cx,
+            |cx| {
+                Label::new(cx, localized("panel-pads")).class("small");
+            },
+            |cx| {
+                // Switch between banks of pads.
+                HStack::new(cx, |cx| {
+                    Button::new(
+                        cx,
+                        move |cx| {
+                            let first = first_pad_key.get(cx);
+                            cx.emit(UiEvent::SetFirstPadKey(first - PAD_ROWS * PAD_COLUMNS));
+                        },
+                        |cx| Label::new(cx, "-").class("small"),
+                    );
+                    Label::new(cx, first_pad_key.map(|key| key_name(*key))).class("small");
+                    Button::new(
+                        cx,
+                        move |cx| {
+                            let first = first_pad_key.get(cx);
+                            cx.emit(UiEvent::SetFirstPadKey(first + PAD_ROWS * PAD_COLUMNS));
+                        },
+                        |cx| Label::new(cx, "+").class("small"),
+                    );
+                })
+                .class("onscreen_toolbar");
+
+                Binding::new(cx, first_pad_key, |cx, first_pad_key| {
+                    let first_pad_key = first_pad_key.get(cx);
+                    VStack::new(cx, |cx| {
+                        for row in (0..PAD_ROWS).rev() {
+                            HStack::new(cx, |cx| {
+                                for column in 0..PAD_COLUMNS {
+                                    if let Some(key) = pad_key(first_pad_key, row, column) {
+                                        NotePad::new(cx, key).class("drum_pad");
+                                    }
+                                }
+                            })
+                            .class("pad_row");
+                        }
+                    })
+                    .class("pad_grid");
+                });
+            },
+        );
+    })
+    .class("pads")
+    .toggle_class("hidden", UiData::state.then(UiState::panels.then(PanelState::hide_pads)));
+}
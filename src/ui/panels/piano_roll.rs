@@ -1,13 +1,13 @@
 use vizia::prelude::*;
 
-use crate::ui::Panel;
+use crate::ui::{localized, Panel};
 
 pub fn piano_roll(cx: &mut Context) {
     VStack::new(cx, |cx| {
         Panel::new(
             cx,
             |cx| {
-                Label::new(cx, "PIANO ROLL").class("small");
+                Label::new(cx, localized("panel-piano-roll")).class("small");
             },
             |_| {},
         )
@@ -0,0 +1,203 @@
+use vizia::{
+    prelude::*,
+    vg::{Paint, Path},
+};
+
+use crate::backend::sample_edit::SampleEditOp;
+use crate::ui::state::{
+    PanelState, SampleEditorEvent, SampleEditorState, UiData, UiEvent, UiState,
+};
+use crate::ui::{localized, CachedLayer, Panel};
+
+/// The level that "normalize" brings the peak of the selection to.
+const NORMALIZE_TARGET_DB: f32 = -0.3;
+
+pub fn sample_editor(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Panel::new(
+            cx,
+            |cx| {
+                Label::new(cx, localized("panel-sample-editor")).class("small");
+                Label::new(
+                    cx,
+                    UiData::state.then(UiState::sample_editor).map(|s| {
+                        if s.dirty {
+                            format!("{} *", s.name)
+                        } else {
+                            s.name.clone()
+                        }
+                    }),
+                )
+                .class("small")
+                .left(Pixels(10.0));
+            },
+            |cx| {
+                HStack::new(cx, |cx| {
+                    edit_button(cx, "button-cut", |cx| cx.emit(SampleEditorEvent::Cut));
+                    edit_button(cx, "button-copy", |cx| cx.emit(SampleEditorEvent::Copy));
+                    edit_button(cx, "button-paste", |cx| cx.emit(SampleEditorEvent::Paste));
+                    edit_button(cx, "button-silence", |cx| {
+                        cx.emit(SampleEditorEvent::Apply(SampleEditOp::Silence))
+                    });
+                    edit_button(cx, "button-fade-in", |cx| {
+                        cx.emit(SampleEditorEvent::Apply(SampleEditOp::FadeIn))
+                    });
+                    edit_button(cx, "button-fade-out", |cx| {
+                        cx.emit(SampleEditorEvent::Apply(SampleEditOp::FadeOut))
+                    });
+                    edit_button(cx, "button-normalize", |cx| {
+                        cx.emit(SampleEditorEvent::Apply(SampleEditOp::Normalize(
+                            NORMALIZE_TARGET_DB,
+                        )))
+                    });
+                    edit_button(cx, "button-dc", |cx| {
+                        cx.emit(SampleEditorEvent::Apply(SampleEditOp::RemoveDC))
+                    });
+                    edit_button(cx, "button-undo", |cx| cx.emit(SampleEditorEvent::Undo)).disabled(
+                        UiData::state
+                            .then(UiState::sample_editor.then(SampleEditorState::can_undo))
+                            .map(|b| !*b),
+                    );
+                    edit_button(cx, "button-redo", |cx| cx.emit(SampleEditorEvent::Redo)).disabled(
+                        UiData::state
+                            .then(UiState::sample_editor.then(SampleEditorState::can_redo))
+                            .map(|b| !*b),
+                    );
+                    edit_button(cx, "button-save", |cx| cx.emit(UiEvent::SaveSampleEditor))
+                        .disabled(
+                            UiData::state
+                                .then(UiState::sample_editor.then(SampleEditorState::dirty))
+                                .map(|b| !*b),
+                        );
+                })
+                .class("sample_editor_toolbar");
+
+                SampleEditorWaveform::new(cx).class("sample_editor_waveform");
+            },
+        );
+    })
+    .class("sample_editor")
+    .toggle_class(
+        "hidden",
+        UiData::state.then(UiState::panels.then(PanelState::hide_sample_editor)),
+    );
+}
+
+fn edit_button(
+    cx: &mut Context,
+    key: &'static str,
+    action: impl Fn(&mut EventContext) + 'static,
+) -> Handle<Button> {
+    Button::new(cx, action, move |cx| Label::new(cx, localized(key)).class("small"))
+}
+
+/// Draws the waveform of the buffer being edited along with the selection.
+/// Click and drag to select.
+pub struct SampleEditorWaveform {
+    /// The normalized position where the current drag started.
+    drag_start: Option<f32>,
+
+    waveform: CachedLayer,
+}
+
+impl SampleEditorWaveform {
+    pub fn new(cx: &mut Context) -> Handle<Self> {
+        Self { drag_start: None, waveform: CachedLayer::new() }.build(cx, |_| {})
+    }
+
+    fn normalized_x(cx: &EventContext, x: f32) -> f32 {
+        let current = cx.current();
+        let posx = cx.cache.get_posx(current);
+        let width = cx.cache.get_width(current);
+
+        if width > 0.0 {
+            ((x - posx) / width).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl View for SampleEditorWaveform {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, _| match window_event {
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                let x = Self::normalized_x(cx, cx.mouse.cursorx);
+                self.drag_start = Some(x);
+                cx.capture();
+                cx.emit(SampleEditorEvent::SetSelectionNormalized(x, x));
+            }
+            WindowEvent::MouseMove(x, _) => {
+                if let Some(start) = self.drag_start {
+                    let x = Self::normalized_x(cx, *x);
+                    cx.emit(SampleEditorEvent::SetSelectionNormalized(start, x));
+                }
+            }
+            WindowEvent::MouseUp(MouseButton::Left) => {
+                self.drag_start = None;
+                cx.release();
+            }
+            _ => {}
+        });
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+
+        if let Some(ui_data) = cx.data::<UiData>() {
+            let state = &ui_data.state.sample_editor;
+            let (editor, peaks) = match (&state.editor, &state.peaks) {
+                (Some(editor), Some(peaks)) => (editor, peaks),
+                _ => return,
+            };
+            let buffer = editor.buffer();
+            let len = buffer.len_frames();
+            if len == 0 || bounds.w < 1.0 {
+                return;
+            }
+
+            canvas.save();
+            canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
+
+            // Selection
+            let frame_to_x = |frame: usize| bounds.x + bounds.w * frame as f32 / len as f32;
+            let sel_x = frame_to_x(state.selection_start);
+            let sel_w = (frame_to_x(state.selection_end) - sel_x).max(1.0);
+            let mut path = Path::new();
+            path.rect(sel_x, bounds.y, sel_w, bounds.h);
+            canvas.fill_path(&mut path, Paint::color(vizia::vg::Color::rgba(255, 255, 255, 30)));
+
+            // Waveform, drawn as the min and max of each column of pixels. The
+            // left channel is drawn in the top half and the right in the bottom.
+            // It is only drawn again when the buffer or the size changes.
+            self.waveform.draw(
+                canvas,
+                (bounds.x, bounds.y, bounds.w, bounds.h),
+                state.buffer_generation,
+                |canvas, w, h| {
+                    let half_h = h / 2.0;
+                    let channels = [(&peaks[0], &buffer.left), (&peaks[1], &buffer.right)];
+
+                    for (channel, (peaks, samples)) in channels.iter().enumerate() {
+                        let center_y = half_h * (channel as f32 + 0.5);
+
+                        let mut path = Path::new();
+                        for (column, (min, max)) in
+                            peaks.columns(samples, 0..len, w as usize).into_iter().enumerate()
+                        {
+                            let x = column as f32 + 0.5;
+                            path.move_to(x, center_y - max.min(1.0) * half_h * 0.5);
+                            path.line_to(x, center_y - min.max(-1.0) * half_h * 0.5);
+                        }
+                        canvas.stroke_path(
+                            &mut path,
+                            Paint::color(vizia::vg::Color::rgb(140, 190, 240)),
+                        );
+                    }
+                },
+            );
+
+            canvas.restore();
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use super::grid::TIMELINE_DEFAULT_OFFSET;
+use crate::ui::state::{localized, Localization, PanelState, UiData, UiEvent, UiState};
+use crate::ui::{
+    is_context_menu_key, open_context_menu, open_context_menu_below, ContextMenuAction,
+    ContextMenuItem, RenameTarget,
+};
+use vizia::{
+    prelude::*,
+    vg::{Baseline, Paint, Path},
+};
+
+/// A strip above the lanes with the sections of the song. Sections can be
+/// dragged to reorder them, which moves the clips on every lane along with
+/// them.
+pub fn arranger_track(cx: &mut Context) {
+    HStack::new(cx, |cx| {
+        HStack::new(cx, |cx| {
+            Label::new(cx, localized("panel-arranger")).class("small");
+            Button::new(cx, |cx| cx.emit(UiEvent::AddSection), |cx| Label::new(cx, "+"))
+                .class("arranger_add");
+        })
+        .width(
+            UiData::state
+                .then(UiState::panels.then(PanelState::lane_header_width))
+                .map(|w| Pixels(*w)),
+        )
+        .class("arranger_header");
+
+        ArrangerTrack::new(cx);
+    })
+    .class("arranger");
+}
+
+pub struct ArrangerTrack {
+    /// The section that is being dragged.
+    dragging: Option<usize>,
+}
+
+impl ArrangerTrack {
+    pub fn new(cx: &mut Context) -> Handle<Self> {
+        Self { dragging: None }.build(cx, |_| {}).width(Stretch(1.0)).height(Stretch(1.0))
+    }
+
+    /// The section under the mouse.
+    fn section_under_mouse(&self, cx: &EventContext) -> Option<usize> {
+        let ui_data = cx.data::<UiData>()?;
+        let current = cx.current();
+        let x = (cx.mouse.cursorx - cx.cache.get_posx(current)) / cx.scale_factor();
+
+        let timeline_grid = &ui_data.state.timeline_grid;
+        let beats = timeline_grid.left_start.get().as_beats_f64()
+            + f64::from((x - TIMELINE_DEFAULT_OFFSET) / timeline_grid.beat_width());
+        ui_data.state.arranger.section_at(beats)
+    }
+
+    fn selected(&self, cx: &EventContext) -> Option<(usize, usize)> {
+        let arranger = &cx.data::<UiData>()?.state.arranger;
+        Some((arranger.selected?, arranger.sections.len()))
+    }
+
+    fn context_menu(&self, cx: &EventContext, index: usize) -> Option<Vec<ContextMenuItem>> {
+        let ui_data = cx.data::<UiData>()?;
+        let section = ui_data.state.arranger.sections.get(index)?;
+
+        Some(section_context_menu(
+            index,
+            ui_data.state.arranger.sections.len(),
+            section.name.clone(),
+            &ui_data.localization,
+        ))
+    }
+}
+
+fn section_context_menu(
+    index: usize,
+    len: usize,
+    name: String,
+    l: &Localization,
+) -> Vec<ContextMenuItem> {
+    let mut items = vec![ContextMenuItem::new(
+        l.tr("item-rename"),
+        ContextMenuAction::Rename { target: RenameTarget::Section(index), current: name },
+    )];
+    if index > 0 {
+        items.push(ContextMenuItem::ui(
+            l.tr("item-move-earlier"),
+            UiEvent::MoveSection { from: index, to: index - 1 },
+        ));
+    }
+    if index + 1 < len {
+        items.push(ContextMenuItem::ui(
+            l.tr("item-move-later"),
+            UiEvent::MoveSection { from: index, to: index + 1 },
+        ));
+    }
+    items.extend([
+        ContextMenuItem::ui(l.tr("item-duplicate"), UiEvent::DuplicateSection(index)),
+        ContextMenuItem::ui(l.tr("item-remove"), UiEvent::RemoveSection(index)),
+    ]);
+    items
+}
+
+impl View for ArrangerTrack {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, meta| match window_event {
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                if let Some(index) = self.section_under_mouse(cx) {
+                    cx.emit(UiEvent::SelectSection(index));
+                    self.dragging = Some(index);
+                    cx.capture();
+                }
+                cx.focus();
+            }
+
+            WindowEvent::MouseUp(MouseButton::Left) => {
+                if let Some(from) = self.dragging.take() {
+                    cx.release();
+                    if let Some(to) = self.section_under_mouse(cx) {
+                        if to != from {
+                            cx.emit(UiEvent::MoveSection { from, to });
+                        }
+                    }
+                }
+            }
+
+            WindowEvent::MouseDown(MouseButton::Right) => {
+                if let Some(index) = self.section_under_mouse(cx) {
+                    cx.emit(UiEvent::SelectSection(index));
+                    if let Some(items) = self.context_menu(cx, index) {
+                        open_context_menu(cx, items);
+                    }
+                }
+            }
+
+            WindowEvent::KeyDown(code, _) if is_context_menu_key(cx, *code) => {
+                let items = self.selected(cx).and_then(|(index, _)| self.context_menu(cx, index));
+                if let Some(items) = items {
+                    open_context_menu_below(cx, items);
+                }
+            }
+
+            // Left/Right selects the previous/next section, and with CTRL held
+            // moves the selected section.
+            WindowEvent::KeyDown(code @ (Code::ArrowLeft | Code::ArrowRight), _) => {
+                let (selected, len) = match self.selected(cx) {
+                    Some(selected) => selected,
+                    None => return,
+                };
+                let target = match code {
+                    Code::ArrowLeft => selected.checked_sub(1),
+                    _ => Some(selected + 1).filter(|i| *i < len),
+                };
+
+                if let Some(target) = target {
+                    if cx.modifiers.contains(Modifiers::CTRL) {
+                        cx.emit(UiEvent::MoveSection { from: selected, to: target });
+                    } else {
+                        cx.emit(UiEvent::SelectSection(target));
+                    }
+                }
+            }
+
+            // These are handled here so the timeline doesn't also act on the
+            // selected lanes.
+            WindowEvent::KeyDown(Code::KeyD, _) if cx.modifiers.contains(Modifiers::CTRL) => {
+                if let Some((selected, _)) = self.selected(cx) {
+                    cx.emit(UiEvent::DuplicateSection(selected));
+                }
+                meta.consume();
+            }
+
+            WindowEvent::KeyDown(Code::Delete, _) => {
+                if let Some((selected, _)) = self.selected(cx) {
+                    cx.emit(UiEvent::RemoveSection(selected));
+                }
+                meta.consume();
+            }
+
+            _ => {}
+        });
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+
+        if let Some(ui_data) = cx.data::<UiData>() {
+            let arranger = &ui_data.state.arranger;
+            let left_start = ui_data.state.timeline_grid.left_start.get().as_beats_f64();
+            let beat_width = ui_data.state.timeline_grid.beat_width();
+
+            let x_of = |beats: f64| {
+                bounds.x
+                    + cx.logical_to_physical(
+                        TIMELINE_DEFAULT_OFFSET + (beats - left_start) as f32 * beat_width,
+                    )
+            };
+
+            canvas.save();
+            canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
+
+            let margin = cx.logical_to_physical(2.0);
+            for (index, range) in arranger.section_ranges().into_iter().enumerate() {
+                let x = x_of(range.start);
+                let w = x_of(range.end) - x - margin;
+
+                let color = if arranger.selected == Some(index) {
+                    vizia::vg::Color::rgb(110, 140, 190)
+                } else {
+                    vizia::vg::Color::rgb(70, 90, 120)
+                };
+                let mut path = Path::new();
+                path.rounded_rect(
+                    x,
+                    bounds.y + margin,
+                    w,
+                    bounds.h - margin * 2.0,
+                    cx.logical_to_physical(3.0),
+                );
+                canvas.fill_path(&mut path, Paint::color(color));
+
+                let mut text_paint = Paint::color(vizia::vg::Color::rgb(230, 230, 230));
+                text_paint.set_font_size(cx.logical_to_physical(11.0));
+                text_paint.set_text_baseline(Baseline::Middle);
+
+                canvas.save();
+                canvas.intersect_scissor(x, bounds.y, w, bounds.h);
+                let _ = canvas.fill_text(
+                    x + cx.logical_to_physical(4.0),
+                    bounds.y + bounds.h / 2.0,
+                    &arranger.sections[index].name,
+                    text_paint,
+                );
+                canvas.restore();
+            }
+
+            canvas.restore();
+        }
+    }
+}
@@ -1,12 +1,16 @@
-use super::lanes::DEFAULT_LANE_HEIGHT_PX;
-use crate::ui::state::UiData;
+use crate::ui::state::{UiData, UiEvent, DEFAULT_LANE_HEIGHT_PX, TIMELINE_GAP_BETWEEN_LANES};
 use vizia::{
     prelude::*,
     vg::{Align, Baseline, Paint, Path},
 };
 
 pub const TIMELINE_DEFAULT_OFFSET: f32 = 10.0;
-pub const TIMELINE_GAP_BETWEEN_LANES: f32 = 1.0;
+/// Labels on the ruler are left out so they are at least this far apart (in
+/// logical pixels).
+const MIN_BAR_LABEL_SPACING: f32 = 30.0;
+/// Ticks on the beats are left out once the beats are closer together than
+/// this (in logical pixels).
+const MIN_BEAT_TICKS_WIDTH: f32 = 40.0;
 
 pub struct TimelineGrid;
 
@@ -26,8 +30,6 @@ impl View for TimelineGrid {
             let start = timeline_grid.left_start.get().as_beats_f64();
             let end = timeline_grid.left_start.get().as_beats_f64()
                 + timeline_grid.project_length.get().as_beats_f64();
-            // TODO: Horizontal zoom
-            // let zoom_x = timeline_grid.horizontal_zoom_level;
             let zoom_y = timeline_grid.vertical_zoom_level;
 
             canvas.save();
@@ -61,8 +63,9 @@ impl View for TimelineGrid {
             }
 
             // Vertical lines
-            let beat_width = 100.0;
-            let mut lane_x = cx.logical_to_physical(TIMELINE_DEFAULT_OFFSET);
+            let beat_width = timeline_grid.beat_width();
+            let mut lane_x =
+                cx.logical_to_physical(TIMELINE_DEFAULT_OFFSET - start.fract() as f32 * beat_width);
             for index in (start as usize)..=(end as usize) {
                 let mut path = Path::new();
                 path.move_to(bounds.x + lane_x, clip_region.y);
@@ -84,6 +87,16 @@ impl TimelineGridHeader {
 }
 
 impl View for TimelineGridHeader {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, _| {
+            // The header is as wide as the visible part of the lanes.
+            if let WindowEvent::GeometryChanged(_) = window_event {
+                let width = cx.cache.get_width(cx.current()) / cx.scale_factor();
+                cx.emit(UiEvent::SetTimelineViewWidth(width));
+            }
+        });
+    }
+
     fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
         let bounds = cx.bounds();
 
@@ -111,47 +124,55 @@ impl View for TimelineGridHeader {
             canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
 
             // Vertical lines
-            let beat_width = 100.0;
-            let mut lane_x = cx.logical_to_physical(TIMELINE_DEFAULT_OFFSET);
-            for index in (start as usize)..=(end as usize) {
-                // Line per bar
-                let mut path = Path::new();
-                path.move_to(bounds.x + lane_x, bounds.y + bounds.h);
-                path.line_to(bounds.x + lane_x, bounds.y + bounds.h - cx.logical_to_physical(10.0));
-                canvas.stroke_path(&mut path, Paint::color(vizia::vg::Color::rgb(82, 82, 82)));
-
-                // Number per bar
-                let mut text_paint = Paint::color(vizia::vg::Color::rgb(82, 82, 82));
-                // text_paint.set_font(&[font_id.clone()]);
-                text_paint.set_text_align(Align::Center);
-                text_paint.set_text_baseline(Baseline::Top);
-                let _ = canvas.fill_text(
-                    bounds.x + lane_x,
-                    bounds.y,
-                    &format!("{}", index + 1),
-                    text_paint,
-                );
+            let beat_width = timeline_grid.beat_width();
+            let time_signatures = &timeline_grid.time_signatures;
+            let x_of = |beats: f64| {
+                bounds.x
+                    + cx.logical_to_physical(
+                        TIMELINE_DEFAULT_OFFSET + (beats - start) as f32 * beat_width,
+                    )
+            };
+            let color = vizia::vg::Color::rgb(82, 82, 82);
+
+            let mut bar = time_signatures.position_at(start).bar;
+            loop {
+                let bar_start = time_signatures.beats_at(bar, 0.0);
+                if bar_start > end {
+                    break;
+                }
+                let signature = time_signatures.signature_at(bar_start);
+                let bar_width = beat_width * signature.bar_length() as f32;
+                let label_every = (MIN_BAR_LABEL_SPACING / bar_width).ceil().max(1.0) as u32;
+                let x = x_of(bar_start);
 
-                // Line per beat
-                if index != end as usize {
+                if bar % label_every == 0 {
                     // Line per bar
-                    for index in 1..4 {
-                        let lane_bar_x =
-                            lane_x + cx.logical_to_physical(index as f32 * beat_width / 4.0);
+                    let mut path = Path::new();
+                    path.move_to(x, bounds.y + bounds.h);
+                    path.line_to(x, bounds.y + bounds.h - cx.logical_to_physical(10.0));
+                    canvas.stroke_path(&mut path, Paint::color(color));
+
+                    // Number per bar
+                    let mut text_paint = Paint::color(color);
+                    // text_paint.set_font(&[font_id.clone()]);
+                    text_paint.set_text_align(Align::Center);
+                    text_paint.set_text_baseline(Baseline::Top);
+                    let _ = canvas.fill_text(x, bounds.y, &format!("{}", bar + 1), text_paint);
+                }
 
+                // Line per beat
+                let beat_length = signature.beat_length();
+                if beat_width * beat_length as f32 >= MIN_BEAT_TICKS_WIDTH {
+                    for beat in 1..signature.numerator {
+                        let x = x_of(bar_start + f64::from(beat) * beat_length);
                         let mut path = Path::new();
-                        let length = cx.logical_to_physical(if index == 2 { 8.0 } else { 5.0 });
-
-                        path.move_to(bounds.x + lane_bar_x, bounds.y + bounds.h);
-                        path.line_to(bounds.x + lane_bar_x, bounds.y + bounds.h - length);
-                        canvas.stroke_path(
-                            &mut path,
-                            Paint::color(vizia::vg::Color::rgb(82, 82, 82)),
-                        );
+                        path.move_to(x, bounds.y + bounds.h);
+                        path.line_to(x, bounds.y + bounds.h - cx.logical_to_physical(5.0));
+                        canvas.stroke_path(&mut path, Paint::color(color));
                     }
                 }
 
-                lane_x += cx.logical_to_physical(beat_width);
+                bar += 1;
             }
             canvas.restore();
         }
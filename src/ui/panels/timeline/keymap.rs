@@ -19,6 +19,20 @@ pub fn timeline_keymap(cx: &mut Context) {
                 cx.focus();
             }),
         ),
+        // CTRL + SHIFT + R => Turns the last performance on the MIDI input into a clip.
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::SHIFT, Code::KeyR),
+            KeymapEntry::new(UiEvent::CaptureLastPerformance, |cx| {
+                cx.emit(UiEvent::CaptureLastPerformance);
+            }),
+        ),
+        // CTRL + SHIFT + K => Turns playing notes with the computer keyboard on or off.
+        (
+            KeyChord::new(Modifiers::CTRL | Modifiers::SHIFT, Code::KeyK),
+            KeymapEntry::new(UiEvent::ToggleMusicalTyping, |cx| {
+                cx.emit(UiEvent::ToggleMusicalTyping);
+            }),
+        ),
         // CTRL + D => Duplicates the selected lanes.
         (
             KeyChord::new(Modifiers::CTRL, Code::KeyD),
@@ -90,6 +104,41 @@ pub fn timeline_keymap(cx: &mut Context) {
                 cx.emit(UiEvent::ZoomOutVertically);
             }),
         ),
+        // F => Zooms to fit the whole project.
+        (
+            KeyChord::new(Modifiers::empty(), Code::KeyF),
+            KeymapEntry::new(UiEvent::ZoomToFit, |cx| {
+                cx.emit(UiEvent::ZoomToFit);
+            }),
+        ),
+        // Z => Zooms to the clips on the selected lanes.
+        (
+            KeyChord::new(Modifiers::empty(), Code::KeyZ),
+            KeymapEntry::new(UiEvent::ZoomToSelection, |cx| {
+                cx.emit(UiEvent::ZoomToSelection);
+            }),
+        ),
+        // ALT + ArrowLeft => Goes back to the view before the last zoom.
+        (
+            KeyChord::new(Modifiers::ALT, Code::ArrowLeft),
+            KeymapEntry::new(UiEvent::ViewBack, |cx| {
+                cx.emit(UiEvent::ViewBack);
+            }),
+        ),
+        // ALT + ArrowRight => Goes forward again after going back.
+        (
+            KeyChord::new(Modifiers::ALT, Code::ArrowRight),
+            KeymapEntry::new(UiEvent::ViewForward, |cx| {
+                cx.emit(UiEvent::ViewForward);
+            }),
+        ),
+        // SHIFT + F => Fits the height of all lanes into the view.
+        (
+            KeyChord::new(Modifiers::SHIFT, Code::KeyF),
+            KeymapEntry::new(UiEvent::FitAllLanes, |cx| {
+                cx.emit(UiEvent::FitAllLanes);
+            }),
+        ),
         // SHIFT + ArrowUp => Decreases the size of the selected lanes.
         (
             KeyChord::new(Modifiers::SHIFT, Code::ArrowUp),
@@ -111,6 +160,27 @@ pub fn timeline_keymap(cx: &mut Context) {
                 cx.emit(UiEvent::SelectAllLanes);
             }),
         ),
+        // CTRL + C => Copies the selected lanes.
+        (
+            KeyChord::new(Modifiers::CTRL, Code::KeyC),
+            KeymapEntry::new(UiEvent::CopySelectedLanes, |cx| {
+                cx.emit(UiEvent::CopySelectedLanes);
+            }),
+        ),
+        // CTRL + X => Cuts the selected lanes.
+        (
+            KeyChord::new(Modifiers::CTRL, Code::KeyX),
+            KeymapEntry::new(UiEvent::CutSelectedLanes, |cx| {
+                cx.emit(UiEvent::CutSelectedLanes);
+            }),
+        ),
+        // CTRL + V => Pastes lanes below the last selected lane.
+        (
+            KeyChord::new(Modifiers::CTRL, Code::KeyV),
+            KeymapEntry::new(UiEvent::PasteLanes, |cx| {
+                cx.emit(UiEvent::PasteLanes);
+            }),
+        ),
     ])
     .build(cx);
 }
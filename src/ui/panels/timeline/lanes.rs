@@ -1,10 +1,22 @@
+use super::grid::TIMELINE_DEFAULT_OFFSET;
+use crate::backend::resource_loader::PcmKey;
+use crate::backend::waveform::WaveformPeaks;
 use crate::ui::{
-    state::{LaneState, LaneStates, TimelineGridState},
-    PanelEvent, PanelState, ResizableStack, UiData, UiEvent, UiState,
+    is_context_menu_key, open_context_menu, open_context_menu_below,
+    state::{
+        ClipStart, ClipType, LaneState, LaneStates, Localization, TimelineGridState,
+        DEFAULT_LANE_HEIGHT_PX, TIMELINE_GAP_BETWEEN_LANES,
+    },
+    ContextMenuAction, ContextMenuItem, PanelEvent, PanelState, RenameTarget, ResizableStack,
+    UiData, UiEvent, UiState,
 };
+use std::ops::Range;
+use std::time::{Duration, Instant};
 use vizia::prelude::*;
 
-pub const DEFAULT_LANE_HEIGHT_PX: f32 = 100.0;
+/// Files dropped at the same spot within this amount of time are treated as a
+/// single multi-file drop.
+const MULTI_FILE_DROP_WINDOW: Duration = Duration::from_millis(250);
 
 #[derive(Lens)]
 pub struct LaneHeader {
@@ -88,6 +100,13 @@ impl LaneHeader {
         .toggle_class("selected", item.then(LaneState::selected))
         .toggle_class("disabled", item.then(LaneState::disabled))
     }
+
+    fn context_menu(&self, cx: &EventContext) -> Option<Vec<ContextMenuItem>> {
+        let ui_data = cx.data::<UiData>()?;
+        let lane = ui_data.state.timeline_grid.lane_states.lanes.get(self.index)?;
+
+        Some(lane_context_menu(self.index, lane, &ui_data.localization))
+    }
 }
 
 pub(crate) enum LaneHeaderEvent {
@@ -124,6 +143,21 @@ impl View for LaneHeader {
                 }
             }
 
+            WindowEvent::MouseDown(MouseButton::Right) => {
+                if !cx.modifiers.contains(Modifiers::CTRL) {
+                    cx.emit(UiEvent::SelectLane(self.index));
+                }
+                if let Some(items) = self.context_menu(cx) {
+                    open_context_menu(cx, items);
+                }
+            }
+
+            WindowEvent::KeyDown(code, _) if is_context_menu_key(cx, *code) => {
+                if let Some(items) = self.context_menu(cx) {
+                    open_context_menu_below(cx, items);
+                }
+            }
+
             WindowEvent::MouseMove(_, y) => {
                 if self.is_dragging {
                     let current = cx.current();
@@ -143,6 +177,53 @@ impl View for LaneHeader {
     }
 }
 
+/// The context menu of a lane. The actions apply to all selected lanes except
+/// for renaming, changing the color and the playlists.
+fn lane_context_menu(index: usize, lane: &LaneState, l: &Localization) -> Vec<ContextMenuItem> {
+    let name = lane.name.clone().unwrap_or_else(|| format!("lane {}", index));
+    let mut items = vec![ContextMenuItem::new(
+        l.tr("item-rename"),
+        ContextMenuAction::Rename { target: RenameTarget::Lane(index), current: name },
+    )];
+    items.extend(ContextMenuItem::colors(l, |color| {
+        ContextMenuAction::Ui(UiEvent::SetLaneColor(index, color))
+    }));
+    items.extend([
+        ContextMenuItem::ui(l.tr("item-duplicate"), UiEvent::DuplicateSelectedLanes),
+        ContextMenuItem::ui(l.tr("item-toggle-active"), UiEvent::ToggleSelectedLaneActivation),
+        ContextMenuItem::ui(l.tr("item-group-clips"), UiEvent::GroupSelectedLaneClips),
+        ContextMenuItem::ui(l.tr("item-copy"), UiEvent::CopySelectedLanes),
+        ContextMenuItem::ui(l.tr("item-cut"), UiEvent::CutSelectedLanes),
+        ContextMenuItem::ui(l.tr("item-paste"), UiEvent::PasteLanes),
+        ContextMenuItem::ui(l.tr("item-remove"), UiEvent::DeleteSelectedLanes),
+    ]);
+    items.extend([
+        ContextMenuItem::ui(
+            l.tr("item-new-playlist"),
+            UiEvent::AddLanePlaylist { lane: index, copy: false },
+        ),
+        ContextMenuItem::ui(
+            l.tr("item-duplicate-playlist"),
+            UiEvent::AddLanePlaylist { lane: index, copy: true },
+        ),
+    ]);
+    for (i, playlist) in lane.playlists.iter().enumerate() {
+        if i != lane.active_playlist {
+            items.push(ContextMenuItem::ui(
+                l.tr_args("item-switch-playlist", &[("playlist", &playlist.display_name(i, l))]),
+                UiEvent::SwitchLanePlaylist { lane: index, playlist: i },
+            ));
+        }
+    }
+    if lane.playlists.len() > 1 {
+        items.push(ContextMenuItem::ui(
+            l.tr("item-remove-playlist"),
+            UiEvent::RemoveLanePlaylist(index),
+        ));
+    }
+    items
+}
+
 pub fn lane_header(cx: &mut Context) {
     ResizableStack::new(
         cx,
@@ -166,6 +247,257 @@ pub fn lane_header(cx: &mut Context) {
     );
 }
 
+/// The scrollable area with the lanes, which tells the timeline how much of
+/// the lanes is visible.
+pub struct LanesViewport;
+
+impl LanesViewport {
+    pub fn new(cx: &mut Context, content: impl FnOnce(&mut Context)) -> Handle<Self> {
+        Self {}.build(cx, content).height(Stretch(1.0))
+    }
+}
+
+impl View for LanesViewport {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, _| {
+            if let WindowEvent::GeometryChanged(_) = window_event {
+                let height = cx.cache.get_height(cx.current()) / cx.scale_factor();
+                cx.emit(UiEvent::SetTimelineViewHeight(height));
+            }
+        });
+    }
+}
+
 pub fn lane_content(cx: &mut Context) {
-    // TODO: Implement
+    LaneContent::new(cx);
+}
+
+/// The area of the timeline that holds the clips, and draws them.
+///
+/// Audio files dropped onto it from the file manager are turned into clips at
+/// the drop location. When several files are dropped at once, each one goes
+/// on the lane below the previous one.
+pub struct LaneContent {
+    /// When the last file was dropped, where (in physical pixels), and the
+    /// lane the next file from the same drop should go to.
+    last_drop: Option<(Instant, (f32, f32), usize)>,
+}
+
+impl LaneContent {
+    pub fn new(cx: &mut Context) -> Handle<Self> {
+        Self { last_drop: None }.build(cx, |_| {}).width(Stretch(1.0)).height(Stretch(1.0))
+    }
+}
+
+/// Draw the waveform of a clip as the min and max of both channels for each
+/// column of pixels in the bounds.
+fn draw_waveform(
+    canvas: &mut Canvas,
+    peaks: &[WaveformPeaks; 2],
+    frames: Range<usize>,
+    (x, y, w, h): (f32, f32, f32, f32),
+) {
+    let columns = w.ceil().max(0.0) as usize;
+    let left = peaks[0].columns(&[], frames.clone(), columns);
+    let right = peaks[1].columns(&[], frames, columns);
+
+    let center_y = y + h / 2.0;
+    let half_h = h / 2.0;
+    let mut path = Path::new();
+    for (column, ((l_min, l_max), (r_min, r_max))) in left.iter().zip(right.iter()).enumerate() {
+        let column_x = x + column as f32 + 0.5;
+        let min = l_min.min(*r_min).clamp(-1.0, 1.0);
+        let max = l_max.max(*r_max).clamp(-1.0, 1.0);
+        path.move_to(column_x, center_y - max * half_h);
+        path.line_to(column_x, center_y - min * half_h + 1.0);
+    }
+    canvas.stroke_path(&mut path, Paint::color(vizia::vg::Color::rgba(20, 20, 20, 180)));
+}
+
+/// The logical height of a lane, including the gap below it.
+fn lane_px(timeline_grid: &TimelineGridState, height: Option<f64>) -> f32 {
+    (DEFAULT_LANE_HEIGHT_PX * height.unwrap_or(timeline_grid.lane_height) as f32
+        + TIMELINE_GAP_BETWEEN_LANES)
+        * timeline_grid.vertical_zoom_level as f32
+}
+
+/// The index of the lane at the given logical y position, measured from the
+/// top of the first lane. Positions below the last lane continue with lanes of
+/// the default height, so this can be past the end of the existing lanes.
+fn lane_at_y(timeline_grid: &TimelineGridState, y: f32) -> usize {
+    let mut lane_y = 0.0;
+    for (index, lane) in timeline_grid.lane_states.lanes.iter().enumerate() {
+        lane_y += lane_px(timeline_grid, lane.height);
+        if y < lane_y {
+            return index;
+        }
+    }
+
+    let num_lanes = timeline_grid.lane_states.lanes.len();
+    num_lanes + ((y - lane_y).max(0.0) / lane_px(timeline_grid, None)) as usize
+}
+
+/// The logical y position of the top of each lane, measured from the top of
+/// the first lane, and its height without the gap below it.
+fn lane_rows(timeline_grid: &TimelineGridState) -> Vec<(f32, f32)> {
+    let gap = TIMELINE_GAP_BETWEEN_LANES * timeline_grid.vertical_zoom_level as f32;
+    let mut lane_y = 0.0;
+    timeline_grid
+        .lane_states
+        .lanes
+        .iter()
+        .map(|lane| {
+            let height = lane_px(timeline_grid, lane.height);
+            let row = (lane_y, height - gap);
+            lane_y += height;
+            row
+        })
+        .collect()
+}
+
+impl View for LaneContent {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, _| match window_event {
+            WindowEvent::DroppedFile(path) => {
+                let timeline_grid = match cx.data::<UiData>() {
+                    Some(ui_data) => &ui_data.state.timeline_grid,
+                    None => return,
+                };
+
+                let cursor = (cx.mouse.cursorx, cx.mouse.cursory);
+                let current = cx.current();
+                let dpi = cx.scale_factor();
+                let x = (cursor.0 - cx.cache.get_posx(current)) / dpi;
+                let y = (cursor.1 - cx.cache.get_posy(current)) / dpi;
+
+                let start_beats = timeline_grid.left_start.get().as_beats_f64()
+                    + f64::from((x - TIMELINE_DEFAULT_OFFSET) / timeline_grid.beat_width());
+
+                let lane = match self.last_drop {
+                    Some((time, pos, next_lane))
+                        if pos == cursor && time.elapsed() < MULTI_FILE_DROP_WINDOW =>
+                    {
+                        next_lane
+                    }
+                    _ => lane_at_y(timeline_grid, y),
+                };
+                self.last_drop = Some((Instant::now(), cursor, lane + 1));
+
+                cx.emit(UiEvent::ImportAudioFile { path: path.clone(), lane, start_beats });
+            }
+
+            _ => {}
+        });
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+        let clip_region = cx.clip_region();
+
+        let ui_data = match cx.data::<UiData>() {
+            Some(ui_data) => ui_data,
+            None => return,
+        };
+        let state = &ui_data.state;
+        let sample_rate = ui_data.resource_loader.project_sr();
+        let timeline_grid = &state.timeline_grid;
+        let left_start = timeline_grid.left_start.get().as_beats_f64();
+        let beat_width = timeline_grid.beat_width();
+        let x_of = |beats: f64| {
+            bounds.x
+                + cx.logical_to_physical(
+                    TIMELINE_DEFAULT_OFFSET + (beats - left_start) as f32 * beat_width,
+                )
+        };
+        let rows = lane_rows(timeline_grid);
+
+        canvas.save();
+        canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
+
+        let margin = cx.logical_to_physical(1.0);
+        for clip in state.clips.iter() {
+            let on_lane = match &clip.timeline_start {
+                ClipStart::OnLane(on_lane) => on_lane,
+                ClipStart::NotInTimeline => continue,
+            };
+            let (lane_y, lane_h) = match rows.get(on_lane.lane_index as usize) {
+                Some(row) => *row,
+                None => continue,
+            };
+
+            let start = on_lane.timeline_start.get().as_beats_f64();
+            let x = x_of(start);
+            let w = x_of(start + clip.length.get().as_beats_f64()) - x;
+            let y = bounds.y + cx.logical_to_physical(lane_y) + margin;
+            let h = cx.logical_to_physical(lane_h) - margin * 2.0;
+            // Only draw the clips that can be seen.
+            if x + w < clip_region.x
+                || x > clip_region.x + clip_region.w
+                || y + h < clip_region.y
+                || y > clip_region.y + clip_region.h
+                || w < 1.0
+                || h < 1.0
+            {
+                continue;
+            }
+
+            let color = state
+                .channels
+                .get(clip.channel)
+                .map_or(Color::rgb(128, 128, 128), |channel| Color::from(channel.color.clone()));
+            let alpha = if clip.muted { 80 } else { 200 };
+            let mut path = Path::new();
+            path.rounded_rect(x, y, w, h, cx.logical_to_physical(3.0));
+            canvas.fill_path(
+                &mut path,
+                Paint::color(vizia::vg::Color::rgba(color.r(), color.g(), color.b(), alpha)),
+            );
+
+            // The waveform is drawn once its peaks were computed, and only for
+            // the part of the clip that can be seen.
+            if let ClipType::Audio(audio) = &clip.type_ {
+                let key = PcmKey {
+                    path: audio.active_pcm_path().clone(),
+                    resample_to_project_sr: true,
+                    resample_quality: ui_data.resample_quality,
+                };
+                if let Some(peaks) = ui_data.resource_loader.cached_waveform_peaks(&key) {
+                    let first_frame =
+                        audio.clip_start_offset.get().to_nearest_frame_round(sample_rate).0;
+                    let secs = timeline_grid
+                        .beats_to_seconds(start + clip.length.get().as_beats_f64())
+                        - timeline_grid.beats_to_seconds(start);
+                    let frames = secs * sample_rate.0;
+
+                    let from = x.max(clip_region.x);
+                    let to = (x + w).min(clip_region.x + clip_region.w);
+                    let frame_at = |at: f32| {
+                        first_frame as usize + (f64::from((at - x) / w) * frames).max(0.0) as usize
+                    };
+                    draw_waveform(
+                        canvas,
+                        peaks,
+                        frame_at(from)..frame_at(to),
+                        (from, y, to - from, h),
+                    );
+                }
+            }
+
+            let mut text_paint = Paint::color(vizia::vg::Color::rgb(20, 20, 20));
+            text_paint.set_font_size(cx.logical_to_physical(11.0));
+            text_paint.set_text_baseline(Baseline::Top);
+
+            canvas.save();
+            canvas.intersect_scissor(x, y, w, h);
+            let _ = canvas.fill_text(
+                x + cx.logical_to_physical(4.0),
+                y + cx.logical_to_physical(2.0),
+                &clip.name,
+                text_paint,
+            );
+            canvas.restore();
+        }
+
+        canvas.restore();
+    }
 }
@@ -0,0 +1,120 @@
+use super::grid::TIMELINE_DEFAULT_OFFSET;
+use crate::ui::state::{localized, LoudnessHistoryState, PanelState, UiData, UiEvent, UiState};
+use vizia::{
+    prelude::*,
+    vg::{Paint, Path},
+};
+
+/// The loudest value shown on the graph in LUFS.
+const GRAPH_MAX_LUFS: f32 = 0.0;
+/// The quietest value shown on the graph in LUFS.
+const GRAPH_MIN_LUFS: f32 = -48.0;
+
+/// A strip above the lanes which shows the loudness of the master bus over
+/// the course of the timeline.
+pub fn loudness_history(cx: &mut Context) {
+    HStack::new(cx, |cx| {
+        VStack::new(cx, |cx| {
+            Label::new(cx, localized("panel-loudness")).class("small");
+            Label::new(
+                cx,
+                UiData::state
+                    .then(UiState::loudness_history.then(LoudnessHistoryState::short_term))
+                    .map(|lufs| format!("S {:.1}", lufs)),
+            )
+            .class("small");
+            Label::new(
+                cx,
+                UiData::state
+                    .then(UiState::loudness_history.then(LoudnessHistoryState::momentary))
+                    .map(|lufs| format!("M {:.1}", lufs)),
+            )
+            .class("small");
+            Button::new(
+                cx,
+                |cx| cx.emit(UiEvent::ClearLoudnessHistory),
+                |cx| Label::new(cx, localized("button-clear")).class("small"),
+            );
+        })
+        .width(
+            UiData::state
+                .then(UiState::panels.then(PanelState::lane_header_width))
+                .map(|w| Pixels(*w)),
+        )
+        .class("loudness_history_header");
+
+        LoudnessGraph::new(cx);
+    })
+    .class("loudness_history")
+    .toggle_class(
+        "hidden",
+        UiData::state.then(UiState::panels.then(PanelState::hide_loudness_history)),
+    );
+}
+
+pub struct LoudnessGraph;
+
+impl LoudnessGraph {
+    pub fn new(cx: &mut Context) -> Handle<Self> {
+        Self {}.build(cx, |_| {}).focusable(false).hoverable(false)
+    }
+}
+
+impl View for LoudnessGraph {
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+
+        if let Some(ui_data) = cx.data::<UiData>() {
+            let points = &ui_data.state.loudness_history.points;
+            let left_start = ui_data.state.timeline_grid.left_start.get().as_beats_f64();
+            let beat_width = ui_data.state.timeline_grid.beat_width();
+
+            let x_of = |beats: f64| {
+                bounds.x
+                    + cx.logical_to_physical(
+                        TIMELINE_DEFAULT_OFFSET + (beats - left_start) as f32 * beat_width,
+                    )
+            };
+            let y_of = |lufs: f32| {
+                let normal =
+                    ((lufs - GRAPH_MIN_LUFS) / (GRAPH_MAX_LUFS - GRAPH_MIN_LUFS)).clamp(0.0, 1.0);
+                bounds.y + bounds.h * (1.0 - normal)
+            };
+
+            canvas.save();
+            canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
+
+            // Reference lines every 12 LUFS.
+            let mut lufs = GRAPH_MIN_LUFS + 12.0;
+            while lufs < GRAPH_MAX_LUFS {
+                let mut path = Path::new();
+                path.move_to(bounds.x, y_of(lufs));
+                path.line_to(bounds.x + bounds.w, y_of(lufs));
+                canvas.stroke_path(&mut path, Paint::color(vizia::vg::Color::rgb(44, 44, 44)));
+                lufs += 12.0;
+            }
+
+            if points.len() >= 2 {
+                // Short-term loudness as a filled area.
+                let mut path = Path::new();
+                path.move_to(x_of(points[0].beats), bounds.y + bounds.h);
+                for p in points.iter() {
+                    path.line_to(x_of(p.beats), y_of(p.short_term));
+                }
+                path.line_to(x_of(points[points.len() - 1].beats), bounds.y + bounds.h);
+                path.close();
+                canvas.fill_path(&mut path, Paint::color(vizia::vg::Color::rgba(90, 140, 200, 90)));
+
+                // Momentary loudness as a line on top.
+                let mut path = Path::new();
+                path.move_to(x_of(points[0].beats), y_of(points[0].momentary));
+                for p in points.iter().skip(1) {
+                    path.line_to(x_of(p.beats), y_of(p.momentary));
+                }
+                canvas.stroke_path(&mut path, Paint::color(vizia::vg::Color::rgb(140, 190, 240)));
+            }
+
+            canvas.restore();
+        }
+    }
+}
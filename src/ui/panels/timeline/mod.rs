@@ -1,12 +1,19 @@
+mod arranger;
 mod grid;
 mod keymap;
 pub(crate) mod lanes;
+mod loudness;
+mod overview;
+mod time_ruler;
 
-use self::{grid::TimelineGridHeader, lanes::lane_content};
-use crate::ui::{Panel, PanelState, UiData, UiState};
+use self::{arranger::arranger_track, grid::TimelineGridHeader, lanes::lane_content};
+use crate::ui::{localized, Panel, PanelState, UiData, UiState};
 use grid::TimelineGrid;
 use keymap::timeline_keymap;
-use lanes::lane_header;
+use lanes::{lane_header, LanesViewport};
+use loudness::loudness_history;
+use overview::overview;
+use time_ruler::time_ruler;
 use vizia::prelude::*;
 
 pub fn timeline(cx: &mut Context) {
@@ -16,11 +23,13 @@ pub fn timeline(cx: &mut Context) {
         Panel::new(
             cx,
             |cx| {
-                Label::new(cx, "TIMELINE").class("small");
+                Label::new(cx, localized("panel-timeline")).class("small");
             },
             |cx| {
                 // Timeline content
                 VStack::new(cx, |cx| {
+                    overview(cx);
+
                     // Left area of the timeline content
                     HStack::new(cx, |cx| {
                         // Above the lane headers
@@ -39,17 +48,25 @@ pub fn timeline(cx: &mut Context) {
                     })
                     .class("timeline_content_header");
 
+                    time_ruler(cx);
+
+                    arranger_track(cx);
+
+                    loudness_history(cx);
+
                     // Right area of the timeline content
-                    ScrollView::new(cx, 0.0, 0.0, true, true, |cx| {
-                        HStack::new(cx, |cx| {
-                            lane_header(cx);
-                            ZStack::new(cx, |cx| {
-                                TimelineGrid::new(cx);
-                                lane_content(cx);
+                    LanesViewport::new(cx, |cx| {
+                        ScrollView::new(cx, 0.0, 0.0, true, true, |cx| {
+                            HStack::new(cx, |cx| {
+                                lane_header(cx);
+                                ZStack::new(cx, |cx| {
+                                    TimelineGrid::new(cx);
+                                    lane_content(cx);
+                                });
                             });
-                        });
-                    })
-                    .class("timeline_content");
+                        })
+                        .class("timeline_content");
+                    });
                 });
             },
         )
@@ -0,0 +1,156 @@
+use crate::ui::state::{ClipStart, PanelState, TimelineGridState, UiData, UiEvent, UiState};
+use vizia::{
+    prelude::*,
+    vg::{Paint, Path},
+};
+
+/// A strip above the timeline that shows the whole project at once, with the
+/// visible part of it as a window that can be dragged around.
+pub fn overview(cx: &mut Context) {
+    HStack::new(cx, |cx| {
+        Element::new(cx)
+            .width(
+                UiData::state
+                    .then(UiState::panels.then(PanelState::lane_header_width))
+                    .map(|w| Pixels(*w)),
+            )
+            .class("overview_header");
+
+        Overview::new(cx);
+    })
+    .class("overview");
+}
+
+pub struct Overview {
+    /// Where in the viewport window it was grabbed (in beats from its start).
+    dragging: Option<f64>,
+}
+
+impl Overview {
+    pub fn new(cx: &mut Context) -> Handle<Self> {
+        Self { dragging: None }.build(cx, |_| {}).width(Stretch(1.0)).height(Stretch(1.0))
+    }
+
+    /// The position under the mouse in beats.
+    fn beats_under_mouse(&self, cx: &EventContext) -> Option<f64> {
+        let timeline_grid = &cx.data::<UiData>()?.state.timeline_grid;
+        let current = cx.current();
+        let x = (cx.mouse.cursorx - cx.cache.get_posx(current)) / cx.scale_factor();
+        let width = cx.cache.get_width(current) / cx.scale_factor();
+
+        Some(f64::from(x / width) * overview_length(timeline_grid))
+    }
+}
+
+/// The number of beats the overview spans, which is the whole project and the
+/// visible part of the timeline if it goes past the end.
+fn overview_length(timeline_grid: &TimelineGridState) -> f64 {
+    let (start, end) = visible_range(timeline_grid);
+    timeline_grid.project_length.get().as_beats_f64().max(end).max(end - start).max(1.0)
+}
+
+/// The visible part of the timeline in beats.
+fn visible_range(timeline_grid: &TimelineGridState) -> (f64, f64) {
+    let start = timeline_grid.left_start.get().as_beats_f64();
+    (start, start + f64::from(timeline_grid.view_width / timeline_grid.beat_width()))
+}
+
+impl View for Overview {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, _| match window_event {
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                let (beats, (start, end)) = match cx.data::<UiData>() {
+                    Some(ui_data) => match self.beats_under_mouse(cx) {
+                        Some(beats) => (beats, visible_range(&ui_data.state.timeline_grid)),
+                        None => return,
+                    },
+                    None => return,
+                };
+
+                // Clicking outside of the window centers it on the click.
+                let grab = if (start..end).contains(&beats) {
+                    beats - start
+                } else {
+                    let grab = (end - start) / 2.0;
+                    cx.emit(UiEvent::ScrollTimelineTo(beats - grab));
+                    grab
+                };
+                self.dragging = Some(grab);
+                cx.capture();
+            }
+
+            WindowEvent::MouseMove(_, _) => {
+                if let Some(grab) = self.dragging {
+                    if let Some(beats) = self.beats_under_mouse(cx) {
+                        cx.emit(UiEvent::ScrollTimelineTo(beats - grab));
+                    }
+                }
+            }
+
+            WindowEvent::MouseUp(MouseButton::Left) => {
+                if self.dragging.take().is_some() {
+                    cx.release();
+                }
+            }
+
+            _ => {}
+        });
+    }
+
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+
+        if let Some(ui_data) = cx.data::<UiData>() {
+            let timeline_grid = &ui_data.state.timeline_grid;
+            let length = overview_length(timeline_grid);
+            let x_of = |beats: f64| bounds.x + bounds.w * (beats / length) as f32;
+
+            canvas.save();
+            canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
+
+            // One row per lane, with a block for every clip on it.
+            let lanes = &timeline_grid.lane_states.lanes;
+            let row_height = bounds.h / lanes.len().max(1) as f32;
+            for clip in ui_data.state.clips.iter() {
+                let on_lane = match &clip.timeline_start {
+                    ClipStart::OnLane(on_lane) => on_lane,
+                    ClipStart::NotInTimeline => continue,
+                };
+                let lane = on_lane.lane_index as usize;
+                if lane >= lanes.len() {
+                    continue;
+                }
+
+                let start = on_lane.timeline_start.get().as_beats_f64();
+                let x = x_of(start);
+                let w = (x_of(start + clip.length.get().as_beats_f64()) - x).max(1.0);
+                let color = match &lanes[lane].color {
+                    Some(color) => Color::from(color.clone()),
+                    None => Color::rgb(120, 120, 120),
+                };
+
+                let mut path = Path::new();
+                path.rect(x, bounds.y + row_height * lane as f32, w, row_height.max(1.0));
+                canvas.fill_path(
+                    &mut path,
+                    Paint::color(vizia::vg::Color::rgba(
+                        color.r(),
+                        color.g(),
+                        color.b(),
+                        if clip.muted { 80 } else { 200 },
+                    )),
+                );
+            }
+
+            // The visible part of the timeline.
+            let (start, end) = visible_range(timeline_grid);
+            let x = x_of(start);
+            let mut path = Path::new();
+            path.rect(x, bounds.y, x_of(end) - x, bounds.h);
+            canvas.fill_path(&mut path, Paint::color(vizia::vg::Color::rgba(255, 255, 255, 30)));
+            canvas.stroke_path(&mut path, Paint::color(vizia::vg::Color::rgb(200, 200, 200)));
+
+            canvas.restore();
+        }
+    }
+}
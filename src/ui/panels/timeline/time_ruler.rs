@@ -0,0 +1,190 @@
+use super::grid::TIMELINE_DEFAULT_OFFSET;
+use crate::ui::state::{PanelState, TimeRulerFormat, TimelineGridState, UiData, UiEvent, UiState};
+use vizia::{
+    prelude::*,
+    vg::{Align, Baseline, Paint, Path},
+};
+
+/// The least amount of space between two labels in logical pixels.
+const MIN_LABEL_SPACING: f32 = 80.0;
+
+/// The times between labels that may be picked in seconds, along with the
+/// number of ticks from one label to the next.
+const TIME_STEPS: [(f64, u32); 15] = [
+    (0.01, 5),
+    (0.02, 2),
+    (0.05, 5),
+    (0.1, 5),
+    (0.2, 2),
+    (0.5, 5),
+    (1.0, 5),
+    (2.0, 2),
+    (5.0, 5),
+    (10.0, 5),
+    (15.0, 3),
+    (30.0, 3),
+    (60.0, 4),
+    (300.0, 5),
+    (600.0, 5),
+];
+
+/// The numbers of frames between labels that may be picked when showing
+/// timecode, along with the number of ticks from one label to the next.
+/// Labels further apart than a second use `TIME_STEPS`.
+const FRAME_STEPS: [(u32, u32); 4] = [(1, 1), (2, 2), (5, 5), (10, 5)];
+
+/// A second ruler below the musical one, which shows the absolute time of the
+/// timeline. Clicking the format on the left switches between plain time and
+/// timecode.
+pub fn time_ruler(cx: &mut Context) {
+    HStack::new(cx, |cx| {
+        HStack::new(cx, |cx| {
+            Button::new(
+                cx,
+                |cx| cx.emit(UiEvent::CycleTimeRulerFormat),
+                |cx| {
+                    Label::new(
+                        cx,
+                        UiData::state
+                            .then(UiState::timeline_grid.then(TimelineGridState::time_ruler_format))
+                            .map(|format| match format {
+                                TimeRulerFormat::Time => String::from("m:s.ms"),
+                                TimeRulerFormat::Timecode { fps } => format!("SMPTE {}", fps),
+                            }),
+                    )
+                    .class("small")
+                },
+            )
+            .class("time_ruler_format");
+        })
+        .width(
+            UiData::state
+                .then(UiState::panels.then(PanelState::lane_header_width))
+                .map(|w| Pixels(*w)),
+        )
+        .class("time_ruler_header");
+
+        TimeRuler::new(cx);
+    })
+    .class("time_ruler");
+}
+
+pub struct TimeRuler;
+
+impl TimeRuler {
+    pub fn new(cx: &mut Context) -> Handle<Self> {
+        Self {}.build(cx, |_| {}).focusable(false).hoverable(false)
+    }
+}
+
+/// The time between labels and the number of ticks from one label to the
+/// next, so that the labels are at least `MIN_LABEL_SPACING` apart at the
+/// given number of pixels per second.
+fn label_step(format: TimeRulerFormat, px_per_sec: f32) -> (f64, u32) {
+    let min_step = f64::from(MIN_LABEL_SPACING / px_per_sec);
+
+    if let TimeRulerFormat::Timecode { fps } = format {
+        let frame = 1.0 / f64::from(fps);
+        let step = FRAME_STEPS
+            .iter()
+            .map(|(frames, ticks)| (f64::from(*frames) * frame, *ticks))
+            .find(|(step, _)| *step >= min_step && *step < 1.0);
+        if let Some(step) = step {
+            return step;
+        }
+    }
+
+    TIME_STEPS.iter().copied().find(|(step, _)| *step >= min_step).unwrap_or((3600.0, 4))
+}
+
+fn format_time(secs: f64, format: TimeRulerFormat, step: f64) -> String {
+    match format {
+        TimeRulerFormat::Time => {
+            let minutes = (secs / 60.0).floor();
+            let rest = secs - minutes * 60.0;
+            if step < 1.0 {
+                format!("{}:{:06.3}", minutes, rest)
+            } else {
+                format!("{}:{:02.0}", minutes, rest.floor())
+            }
+        }
+        TimeRulerFormat::Timecode { fps } => {
+            let total_frames = (secs * f64::from(fps)).round() as u64;
+            let fps = u64::from(fps);
+            let frames = total_frames % fps;
+            let total_secs = total_frames / fps;
+            format!(
+                "{:02}:{:02}:{:02}:{:02}",
+                total_secs / 3600,
+                (total_secs / 60) % 60,
+                total_secs % 60,
+                frames
+            )
+        }
+    }
+}
+
+impl View for TimeRuler {
+    fn draw(&self, cx: &mut DrawContext, canvas: &mut Canvas) {
+        let bounds = cx.bounds();
+
+        if let Some(ui_data) = cx.data::<UiData>() {
+            let timeline_grid = &ui_data.state.timeline_grid;
+            let format = timeline_grid.time_ruler_format;
+            let left_start = timeline_grid.left_start.get().as_beats_f64();
+            let bpm = timeline_grid.bpm_at(left_start);
+
+            let beat_width = timeline_grid.beat_width();
+            let px_per_sec = beat_width * (bpm / 60.0) as f32;
+            let x_of = |secs: f64| {
+                bounds.x
+                    + cx.logical_to_physical(
+                        TIMELINE_DEFAULT_OFFSET
+                            + (timeline_grid.seconds_to_beats(secs) - left_start) as f32
+                                * beat_width,
+                    )
+            };
+            let right_end = left_start + f64::from(bounds.w / cx.logical_to_physical(beat_width));
+            let start_secs = timeline_grid.beats_to_seconds(left_start.max(0.0));
+            let end_secs = timeline_grid.beats_to_seconds(right_end) + 1.0;
+
+            let (step, ticks) = label_step(format, px_per_sec);
+            let color = vizia::vg::Color::rgb(82, 82, 82);
+
+            canvas.save();
+            canvas.scissor(bounds.x, bounds.y, bounds.w, bounds.h);
+
+            let first = (start_secs / step).floor() as u64;
+            let last = (end_secs / step).ceil() as u64;
+            for i in first..=last {
+                let secs = i as f64 * step;
+                let x = x_of(secs);
+
+                let mut path = Path::new();
+                path.move_to(x, bounds.y);
+                path.line_to(x, bounds.y + cx.logical_to_physical(10.0));
+                canvas.stroke_path(&mut path, Paint::color(color));
+
+                for tick in 1..ticks {
+                    let x = x_of(secs + step * f64::from(tick) / f64::from(ticks));
+                    let mut path = Path::new();
+                    path.move_to(x, bounds.y);
+                    path.line_to(x, bounds.y + cx.logical_to_physical(5.0));
+                    canvas.stroke_path(&mut path, Paint::color(color));
+                }
+
+                let mut text_paint = Paint::color(color);
+                text_paint.set_text_align(Align::Center);
+                text_paint.set_text_baseline(Baseline::Bottom);
+                let _ = canvas.fill_text(
+                    x,
+                    bounds.y + bounds.h,
+                    &format_time(secs, format, step),
+                    text_paint,
+                );
+            }
+
+            canvas.restore();
+        }
+    }
+}
@@ -1,28 +1,45 @@
 use vizia::prelude::*;
 
 use crate::ui::icons::IconCode;
-use crate::ui::state::PanelEvent;
-use crate::ui::{Icon, Meter, MeterHandle};
-
-#[derive(Lens)]
-pub struct Data {
-    input_l: f32,
-    input_r: f32,
-}
-
-impl Model for Data {}
+use crate::ui::state::{
+    localized, InputMeterState, InputQuantize, MusicalTypingState, PanelEvent, TimelineGridState,
+    UiData, UiEvent, UiState, INPUT_QUANTIZE_GRIDS, TEMPO_NUDGE_STEP,
+};
+use crate::ui::{open_context_menu, ContextMenuItem, Icon, Meter, MeterHandle};
 
 pub fn top_bar(cx: &mut Context) {
     HStack::new(cx, |cx| {
         Button::new(cx, |_| {}, |cx| Icon::new(cx, IconCode::Menu, 24.0, 16.0))
             .class("top_bar_menu");
 
-        // This is all just dummy content and it doesn't do anything
+        // This is mostly just dummy content and it doesn't do anything
         HStack::new(cx, |cx| {
             VStack::new(cx, |cx| {
                 HStack::new(cx, |cx| {
-                    Label::new(cx, "130.00");
-                    Label::new(cx, "TAP");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(UiEvent::NudgeTempo(-TEMPO_NUDGE_STEP)),
+                        |cx| Label::new(cx, "-"),
+                    )
+                    .class("tempo_nudge");
+                    Label::new(
+                        cx,
+                        UiData::state
+                            .then(UiState::timeline_grid.then(TimelineGridState::bpm))
+                            .map(|bpm| format!("{:.2}", bpm)),
+                    );
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(UiEvent::NudgeTempo(TEMPO_NUDGE_STEP)),
+                        |cx| Label::new(cx, "+"),
+                    )
+                    .class("tempo_nudge");
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(UiEvent::TapTempo),
+                        |cx| Label::new(cx, localized("button-tap")),
+                    )
+                    .class("tempo_tap");
                 });
                 HStack::new(cx, |cx| {
                     Label::new(cx, "4/4");
@@ -35,6 +52,54 @@ pub fn top_bar(cx: &mut Context) {
                 Button::new(cx, |_| {}, |cx| Icon::new(cx, IconCode::Play, 24.0, 23.0));
                 Button::new(cx, |_| {}, |cx| Icon::new(cx, IconCode::Stop, 24.0, 23.0));
                 Button::new(cx, |_| {}, |cx| Icon::new(cx, IconCode::Record, 24.0, 23.0));
+                Button::new(
+                    cx,
+                    |cx| cx.emit(UiEvent::CaptureLastPerformance),
+                    |cx| Label::new(cx, localized("button-capture")),
+                )
+                .class("capture_performance");
+                Button::new(
+                    cx,
+                    |cx| {
+                        if let Some(ui_data) = cx.data::<UiData>() {
+                            let items = input_quantize_menu(ui_data);
+                            open_context_menu(cx, items);
+                        }
+                    },
+                    |cx| Label::new(cx, localized("button-input-quantize")),
+                )
+                .class("input_quantize")
+                .toggle_class(
+                    "active",
+                    UiData::state.then(UiState::input_quantize.then(InputQuantize::enabled)),
+                );
+                // Shows the octave and velocity of musical typing while it is on.
+                let typing = UiData::state.then(UiState::musical_typing);
+                Button::new(
+                    cx,
+                    |cx| cx.emit(UiEvent::ToggleMusicalTyping),
+                    move |cx| {
+                        HStack::new(cx, |cx| {
+                            Label::new(cx, localized("button-musical-typing"));
+                            Label::new(
+                                cx,
+                                typing.map(|typing| {
+                                    if typing.enabled {
+                                        format!(
+                                            "{} {}",
+                                            typing.octave_name(),
+                                            (typing.velocity * 127.0).round()
+                                        )
+                                    } else {
+                                        String::new()
+                                    }
+                                }),
+                            );
+                        })
+                    },
+                )
+                .class("musical_typing")
+                .toggle_class("active", typing.then(MusicalTypingState::enabled));
             })
             .class("top_play_center")
             .top(Stretch(1.0))
@@ -75,22 +140,28 @@ pub fn top_bar(cx: &mut Context) {
 
             HStack::new(cx, |cx| {
                 VStack::new(cx, |cx| {
-                    Label::new(cx, "Oscilloscope");
+                    Label::new(cx, localized("panel-oscilloscope"));
+                    // The inputs of the audio device, metered even when nothing is
+                    // recording. Clicking turns off the clip indicator.
+                    let input_meter = UiData::state.then(UiState::input_meter);
                     VStack::new(cx, |cx| {
-                        Data { input_l: 0.42, input_r: 0.69 }.build(cx);
-                        Meter::new(cx, Data::input_l)
+                        Meter::new(cx, input_meter.map(|meter| meter.level(0)))
                             .line_color(Color::rgb(245, 78, 71))
                             .class("top_bar_peak");
-                        Meter::new(cx, Data::input_r)
+                        Meter::new(cx, input_meter.map(|meter| meter.level(1)))
                             .line_color(Color::rgb(245, 78, 71))
                             .class("top_bar_peak");
                     })
-                    .class("top_bar_peak_container");
+                    .class("top_bar_peak_container")
+                    .toggle_class("clipped", input_meter.map(InputMeterState::any_clipped))
+                    .on_press(|cx| cx.emit(UiEvent::ResetInputClip));
                 })
                 .class("top_bar_audio_graph_container");
 
                 VStack::new(cx, |cx| {
-                    Label::new(cx, "Usage Graph").top(Stretch(1.0)).bottom(Stretch(1.0));
+                    Label::new(cx, localized("panel-usage-graph"))
+                        .top(Stretch(1.0))
+                        .bottom(Stretch(1.0));
                 })
                 .class("top_bar_usage_graph_container");
             })
@@ -100,3 +171,32 @@ pub fn top_bar(cx: &mut Context) {
     })
     .class("top_bar");
 }
+
+/// Turning input quantize on or off, and picking its grid and strength.
+fn input_quantize_menu(ui_data: &UiData) -> Vec<ContextMenuItem> {
+    let l = &ui_data.localization;
+    let quantize = ui_data.state.input_quantize;
+    let set = |quantize: InputQuantize| UiEvent::SetInputQuantize(quantize);
+
+    let toggle =
+        if quantize.enabled { "item-input-quantize-off" } else { "item-input-quantize-on" };
+    let mut items = vec![ContextMenuItem::ui(
+        l.tr(toggle),
+        set(InputQuantize { enabled: !quantize.enabled, ..quantize }),
+    )];
+    // Picking a grid turns input quantize on.
+    items.extend(INPUT_QUANTIZE_GRIDS.iter().map(|(key, grid_beats)| {
+        ContextMenuItem::ui(
+            l.tr(key),
+            set(InputQuantize { enabled: true, grid_beats: *grid_beats, ..quantize }),
+        )
+    }));
+    items.extend([1.0, 0.75, 0.5, 0.25].iter().map(|strength: &f64| {
+        ContextMenuItem::ui(
+            l.tr_args("item-input-quantize-strength", &[("percent", &(strength * 100.0))]),
+            set(InputQuantize { strength: *strength, ..quantize }),
+        )
+    }));
+
+    items
+}
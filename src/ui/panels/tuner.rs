@@ -0,0 +1,98 @@
+use vizia::prelude::*;
+
+use crate::ui::state::{localized, PanelState, TunerState, UiData, UiEvent, UiState};
+use crate::ui::Panel;
+use crate::util::Unit;
+
+/// How much the reference pitch changes with each press of the +/- buttons.
+const REFERENCE_PITCH_STEP: f32 = 1.0;
+
+pub fn tuner(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Panel::new(
+            cx,
+            |cx| {
+                Label::new(cx, localized("panel-tuner")).class("small");
+            },
+            |cx| {
+                VStack::new(cx, |cx| {
+                    // Detected note
+                    Label::new(cx, UiData::state.then(UiState::tuner.then(TunerState::note_name)))
+                        .class("tuner_note")
+                        .toggle_class(
+                            "in_tune",
+                            UiData::state.then(
+                                UiState::tuner.then(TunerState::cents).map(|c| c.abs() < 3.0),
+                            ),
+                        );
+
+                    // Cents offset
+                    ZStack::new(cx, |cx| {
+                        Element::new(cx).class("tuner_center_line");
+                        Element::new(cx)
+                            .class("tuner_needle")
+                            .left(
+                                UiData::state.then(
+                                    UiState::tuner
+                                        .then(TunerState::cents)
+                                        .map(|c| Percentage(c.clamp(-50.0, 50.0) + 50.0)),
+                                ),
+                            )
+                            .visibility(
+                                UiData::state.then(UiState::tuner.then(TunerState::has_pitch)),
+                            );
+                    })
+                    .class("tuner_meter");
+
+                    Label::new(
+                        cx,
+                        UiData::state.then(
+                            UiState::tuner
+                                .then(TunerState::cents)
+                                .map(|c| Unit::Cents.format((*c).into())),
+                        ),
+                    )
+                    .class("small");
+
+                    Label::new(
+                        cx,
+                        UiData::state.then(
+                            UiState::tuner
+                                .then(TunerState::freq)
+                                .map(|f| Unit::Hertz.format((*f).into())),
+                        ),
+                    )
+                    .class("small");
+
+                    // Reference pitch
+                    HStack::new(cx, |cx| {
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(UiEvent::NudgeTunerReferencePitch(-REFERENCE_PITCH_STEP)),
+                            |cx| Label::new(cx, "-"),
+                        );
+                        Label::new(
+                            cx,
+                            UiData::state.then(
+                                UiState::tuner
+                                    .then(TunerState::reference_pitch)
+                                    .map(|f| format!("A4 = {}", Unit::Hertz.format((*f).into()))),
+                            ),
+                        )
+                        .class("small");
+                        Button::new(
+                            cx,
+                            |cx| cx.emit(UiEvent::NudgeTunerReferencePitch(REFERENCE_PITCH_STEP)),
+                            |cx| Label::new(cx, "+"),
+                        );
+                    })
+                    .class("tuner_reference");
+                })
+                .class("tuner_content");
+            },
+        )
+        .class("tuner");
+    })
+    .class("tuner")
+    .toggle_class("hidden", UiData::state.then(UiState::panels.then(PanelState::hide_tuner)));
+}
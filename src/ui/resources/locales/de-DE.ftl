@@ -0,0 +1,409 @@
+# German translations. Messages that are missing here are shown in English.
+
+## Panels
+
+panel-browser = BROWSER
+panel-channel-rack = KANALRACK
+panel-main = HAUPTBEREICH
+panel-clips = CLIPS
+panel-timeline = ZEITLEISTE
+panel-piano-roll = PIANOROLLE
+panel-sample-editor = SAMPLE-EDITOR
+panel-eq = EQ
+panel-collisions = KOLLISIONEN
+panel-console = KONSOLE
+panel-keyboard = KLAVIATUR
+panel-pads = PADS
+panel-tuner = STIMMGERÄT
+panel-inspector = INSPEKTOR
+panel-loudness = LUFS
+panel-arranger = ARRANGER
+panel-oscilloscope = Oszilloskop
+panel-usage-graph = Auslastung
+
+## Project tabs
+
+tab-meadowlark-project = MEADOWLARK-PROJEKT
+tab-other-project = ANDERES PROJEKT
+
+## Menu bar
+
+menu-save = SPEICHERN
+menu-load = LADEN
+menu-export = EXPORTIEREN
+menu-export-stems = STEMS
+menu-record = REC
+menu-metronome = KLICK
+menu-loop = LOOP
+menu-tuner = STIMMGERÄT
+menu-inspector = INSPEKTOR
+menu-loudness = LAUTHEIT
+menu-editor = EDITOR
+menu-eq = EQ
+menu-collisions = KOLLISIONEN
+menu-console = KONSOLE
+menu-keyboard = KLAVIATUR
+menu-pads = PADS
+menu-file = Datei
+menu-edit = Bearbeiten
+menu-view = Ansicht
+menu-help = Hilfe
+
+## Buttons
+
+button-rename = UMBENENNEN
+button-clear = LEEREN
+button-bypass = BYPASS
+button-auto-gain = AUTO-GAIN
+button-analyze-selected = AUSWAHL ANALYSIEREN
+button-cut = AUSSCHNEIDEN
+button-copy = KOPIEREN
+button-paste = EINFÜGEN
+button-silence = STILLE
+button-fade-in = EINBLENDEN
+button-fade-out = AUSBLENDEN
+button-normalize = NORMALISIEREN
+button-dc = DC
+button-undo = RÜCKGÄNGIG
+button-redo = WIEDERHOLEN
+button-tap = TAP
+button-capture = ERFASSEN
+button-input-quantize = EINGABE-Q
+button-musical-typing = TASTEN
+button-requantize = NEU QUANTISIEREN
+button-save = SPEICHERN
+button-record-macro = MAKRO AUFNEHMEN
+button-stop-recording = AUFNAHME BEENDEN
+button-run = AUSFÜHREN
+button-delete = LÖSCHEN
+button-randomize = ZUFALL
+button-store-a = A SPEICHERN
+button-store-b = B SPEICHERN
+button-add-return-channel = RÜCKKANAL HINZUFÜGEN
+button-add-send = SEND HINZUFÜGEN
+button-add-plugin = PLUGIN HINZUFÜGEN
+button-remove-plugin = ENTFERNEN
+button-rescan-plugins = NEU SUCHEN
+button-add-return-bus = RETURN-BUS HINZUFÜGEN
+button-pre-fader = PRE-FADER
+button-post-fader = POST-FADER
+button-add-zone = ZONE HINZUFÜGEN
+button-add-chain = KETTE HINZUFÜGEN
+button-add-drum-pads = PADS HINZUFÜGEN
+button-choose-sample = WÄHLEN
+button-separate-output = EIGENER AUSGANG
+button-add-macro-knob = MAKRO HINZUFÜGEN
+button-map-parameter = ZUWEISEN
+button-add-fx-rack = RACK HINZUFÜGEN
+button-load-fx-rack = RACK LADEN
+button-save-fx-rack = RACK SPEICHERN
+button-move-effect-in = HINEIN
+button-move-effect-out = HERAUS
+button-move-effect-up = NACH OBEN
+button-move-effect-down = NACH UNTEN
+button-mute = STUMM
+button-solo = SOLO
+button-remove = ENTFERNEN
+button-reset = ZURÜCKSETZEN
+
+## Context menus
+
+item-rename = Umbenennen
+item-duplicate = Duplizieren
+item-duplicate-alias = Als Alias duplizieren
+item-unlink = Verknüpfung lösen
+item-unpack = Entpacken
+item-mute = Stummschalten
+item-unmute = Stummschaltung aufheben
+item-copy = Kopieren
+item-cut = Ausschneiden
+item-paste = Einfügen
+item-remove = Entfernen
+item-toggle-active = Aktivieren/Deaktivieren
+item-group-clips = Clips gruppieren
+item-new-playlist = Neue Playlist
+item-duplicate-playlist = Playlist duplizieren
+item-switch-playlist = Zu { $playlist } wechseln
+item-remove-playlist = Playlist entfernen
+item-freeze = Einfrieren
+item-copy-inserts = Inserts kopieren
+item-paste-inserts = Inserts einfügen
+item-clear-inserts = Inserts entfernen
+item-exclusive-arm-on = Exklusiv scharf schalten
+item-exclusive-arm-off = Nicht exklusiv scharf schalten
+item-link-arm-subchannels = Aufnahmebereitschaft der Gruppe koppeln
+item-arm-group = Aufnahmegruppe { $group } beitreten
+item-new-arm-group = Neue Aufnahmegruppe
+item-leave-arm-group = Aufnahmegruppe verlassen
+item-record-device-input = Geräteeingang aufnehmen
+item-record-bus = { $channel } aufnehmen
+item-hardware-output = Auf Ausgängen { $left }/{ $right } abspielen
+item-hardware-output-none = Über Routing abspielen
+item-no-midi-output = Kein MIDI-Ausgang
+item-refresh-midi-devices = MIDI-Geräte aktualisieren
+item-color = Farbe: { $color }
+item-undo = { $action } rückgängig machen
+item-redo = { $action } wiederherstellen
+item-save-workspace = Arbeitsbereich speichern...
+item-save-project-workspace = Projekt-Arbeitsbereich speichern...
+item-workspace = Arbeitsbereich: { $name }
+item-project-workspace = Arbeitsbereich: { $name } (Projekt)
+item-move-left = Nach links: { $panel }
+item-move-right = Nach rechts: { $panel }
+item-move-up = Nach oben: { $panel }
+item-move-down = Nach unten: { $panel }
+item-move-earlier = Nach vorne
+item-move-later = Nach hinten
+item-language = Sprache: { $language }
+item-input-quantize-on = Aufgenommene Noten quantisieren
+item-input-quantize-off = Aufgenommene Noten nicht quantisieren
+item-input-quantize-strength = Stärke { $percent } %
+
+color-red = Rot
+color-orange = Orange
+color-yellow = Gelb
+color-green = Grün
+color-blue = Blau
+color-purple = Lila
+
+## Clips
+
+clip-captured-performance = Erfasste Performance { $take }
+clip-container = Container
+
+section-default-name = Abschnitt { $number }
+playlist-default-name = Playlist { $number }
+chain-default-name = Kette { $number }
+drum-pad-default-name = Pad { $number }
+macro-knob-default-name = Makro { $number }
+fx-rack-default-name = Rack
+
+recording-source-note-input = Noteneingang
+recording-source-audio-input = Audioeingang
+
+## Inspector
+
+inspector-nothing-selected = Wähle einen Clip, Kanal oder Abschnitt aus, um ihn anzusehen.
+inspector-not-recorded = Nicht aufgenommen
+inspector-name = Name
+inspector-start = Anfang
+inspector-length = Länge
+inspector-gain = Pegel
+inspector-fade-in = Einblenden
+inspector-fade-out = Ausblenden
+inspector-output = Ausgang
+inspector-send = Send
+inspector-send-level = Send-Pegel
+inspector-midi-output = MIDI-Ausgang
+inspector-midi-channel = MIDI-Kanal
+inspector-midi-program = Programm
+inspector-midi-bank = Bank
+inspector-midi-latency = Latenzausgleich
+inspector-midi-in-channel = MIDI-Eingangskanal
+inspector-low-key = Tiefste Taste
+inspector-high-key = Höchste Taste
+inspector-min-velocity = Min. Anschlag
+inspector-max-velocity = Max. Anschlag
+inspector-transpose = Transponieren
+inspector-velocity-curve = Anschlagkurve
+inspector-zone = Zone
+inspector-chain = Kette
+inspector-chain-gain = Pegel
+inspector-chain-pan = Panorama
+inspector-channel-gain = Lautstärke
+inspector-channel-pan = Panorama
+inspector-pad = Pad
+inspector-sample = Sample
+inspector-pad-key = Taste
+inspector-pad-tune = Stimmung
+inspector-pad-cutoff = Grenzfrequenz
+inspector-choke-group = Choke-Gruppe
+inspector-pad-output = Ausgang nach
+inspector-macro-knob = Makro
+inspector-macro-min = Min
+inspector-macro-max = Max
+inspector-macro-curve = Kurve
+macro-curve-linear = Linear
+macro-curve-exponential = Exponentiell
+macro-curve-logarithmic = Logarithmisch
+macro-curve-s-curve = S-Kurve
+inspector-randomize = ZUF
+inspector-random-range = Bereich
+inspector-source = Quelle
+inspector-take = Take
+inspector-tempo = Tempo
+inspector-date = Aufgenommen
+
+velocity-curve-linear = Linear
+velocity-curve-soft = Weich
+velocity-curve-hard = Hart
+velocity-curve-fixed = Fest
+
+quantize-grid-quarter = 1/4
+quantize-grid-eighth = 1/8
+quantize-grid-eighth-triplet = 1/8-Triolen
+quantize-grid-sixteenth = 1/16
+quantize-grid-thirty-second = 1/32
+
+## Macros
+
+macros-title = MAKROS
+macro-default-name = Makro
+macro-no-macros = Nimm ein Makro auf, um deine Schritte mit einem Klick zu wiederholen.
+
+## Announcements
+
+announce-lane-selected = { $lane } ausgewählt
+announce-lane-selected-disabled = { $lane } ausgewählt, deaktiviert
+announce-playlist-active = { $lane } spielt jetzt { $playlist }
+announce-channel-selected = Kanal { $channel } ausgewählt
+announce-plugin-added = { $plugin } hinzugefügt
+announce-clip-muted = { $clip } stummgeschaltet
+announce-clip-unmuted = { $clip } nicht mehr stummgeschaltet
+announce-eq-band = EQ-Band { $band }: { $freq }, { $gain }, Q { $q }
+announce-eq-band-off = EQ-Band { $band }: { $freq }, { $gain }, Q { $q }, aus
+announce-eq-bypassed = EQ umgangen
+announce-eq-active = EQ aktiv
+announce-eq-auto-gain-on = EQ-Auto-Gain an
+announce-eq-auto-gain-off = EQ-Auto-Gain aus
+announce-tempo = Tempo { $bpm } BPM
+announce-performance-captured = Letzte Performance erfasst
+announce-musical-typing-on = Tastatur-Klavier an: Noten mit den Buchstabentasten spielen, Z und X ändern die Oktave, C und V die Anschlagstärke
+announce-musical-typing-off = Tastatur-Klavier aus
+announce-typing-octave = Oktave { $octave }
+announce-typing-velocity = Anschlagstärke { $velocity }
+announce-section-selected = Abschnitt { $section } ausgewählt
+announce-reference-pitch = Kammerton { $pitch }
+announce-undo = { $action } rückgängig gemacht
+announce-redo = { $action } wiederhergestellt
+
+## Undo history (the edits that can be undone)
+
+history-tempo = Tempoänderung
+history-time-signature = Taktartänderung
+history-loop = Loop-Änderung
+history-effects = Effektänderung
+history-strip-silence = Stille entfernen
+history-take = Take-Wechsel
+history-rename-clip = Clip umbenennen
+history-duplicate-clip = Clip duplizieren
+history-clip-notes = Notenbearbeitung
+history-mute-clip = Clip stummschalten
+history-remove-clips = Clips entfernen
+history-container = Container-Bearbeitung
+history-move-clip = Clip verschieben
+history-clip-length = Cliplänge
+history-clip-gain = Clip-Pegel
+history-clip-fade = Clip-Blende
+history-paste = Einfügen
+history-sections = Arranger-Bearbeitung
+history-capture = Performance aufnehmen
+history-input-quantize = Eingabequantisierung
+history-import = Import
+history-lanes = Spurbearbeitung
+history-midi = MIDI-Einstellungen
+history-instrument-rack = Instrument-Rack
+history-drum-rack = Drum-Rack
+history-channels = Kanalbearbeitung
+history-channel-mix = Mixer
+history-routing = Routing
+history-automation = Automation
+history-macro-knobs = Makroregler
+history-record-audio = Audio aufnehmen
+history-arm = Aufnahmebereitschaft
+history-script = Skript
+
+## Notifications
+
+# These complete "Der Clip … konnte nicht … werden" in error-load-clip.
+action-strip-silence = von Stille befreit
+action-learn-noise-profile = zum Lernen eines Rauschprofils verwendet
+action-denoise = entrauscht
+action-analyze = analysiert
+
+info-learned-noise-profile = Rauschprofil gelernt
+info-export-finished = Projekt nach "{ $path }" exportiert
+info-switched-audio-device = Zum Audiogerät „{ $device }“ gewechselt
+info-no-clips-to-freeze = Auf dem Kanal „{ $channel }“ gibt es keine Audio-Clips zum Einfrieren
+info-no-performance-to-capture = Auf dem MIDI-Eingang wurde noch nichts gespielt
+info-no-clips-to-group = Auf den ausgewählten Spuren gibt es keine Clips
+info-macro-recorded = { $lines } Zeilen als Makro „{ $name }“ aufgenommen
+info-nothing-armed = Schalte einen Kanal scharf, um darauf aufzunehmen
+info-nothing-to-zoom-to = Wähle Spuren mit Clips oder einen Abschnitt zum Zoomen aus
+info-master-outputs-remapped =
+    Das Audiogerät hat nur { $outputs } Ausgänge, deshalb spielt der Master stattdessen
+    auf den Ausgängen { $left } und { $right }
+
+error-noise-profile-too-short = Die Auswahl ist zu kurz, um daraus ein Rauschprofil zu lernen
+error-no-noise-profile = Lerne ein Rauschprofil, bevor du einen Clip entrauschst
+error-write-denoised-take = Der entrauschte Take „{ $path }“ konnte nicht geschrieben werden: { $error }
+error-collision-needs-two-channels = Wähle zwei Kanäle aus, um sie auf Kollisionen zu analysieren
+error-collision-no-clips = Die ausgewählten Kanäle haben keine Audio-Clips in der Zeitleiste
+error-audio-device-disconnected = Die Verbindung zum Audiogerät „{ $device }“ wurde getrennt
+error-save-sample = Das bearbeitete Sample „{ $path }“ konnte nicht gespeichert werden: { $error }
+error-save-workspace = Der Arbeitsbereich „{ $name }“ konnte nicht gespeichert werden: { $error }
+error-save-project = Das Projekt konnte nicht gespeichert werden: { $error }
+error-load-project = Das Projekt konnte nicht geladen werden: { $error }
+error-export = Export des Projekts fehlgeschlagen: { $error }
+error-start-recording = Aufnahme konnte nicht gestartet werden: { $error }
+error-load-click = Klick-Sample konnte nicht geladen werden: { $error }
+error-recording-dropped = { $frames } Frames der Aufnahme von { $channel } gingen verloren, weil die Festplatte nicht mithalten konnte
+error-recording-failed = Aufnahme von { $channel } konnte nicht nach { $path } geschrieben werden
+error-save-ui-state = Ansicht und Layout des Projekts konnten nicht gespeichert werden: { $error }
+error-delete-workspace = Der Arbeitsbereich „{ $name }“ konnte nicht gelöscht werden: { $error }
+error-save-macro = Das Makro „{ $name }“ konnte nicht gespeichert werden: { $error }
+error-save-fx-rack = Das Rack „{ $name }“ konnte nicht gespeichert werden: { $error }
+error-delete-macro = Das Makro „{ $name }“ konnte nicht gelöscht werden: { $error }
+error-freeze-channel = Der Kanal „{ $channel }“ konnte nicht eingefroren werden: { $error }
+error-import-file = „{ $path }“ konnte nicht importiert werden: { $error }
+error-open-sample-editor = „{ $path }“ konnte nicht im Sample-Editor geöffnet werden: { $error }
+error-load-clip = Der Clip „{ $clip }“ konnte nicht { $action } werden: { $error }
+error-load-internal-plugin = Internes Plugin konnte nicht geladen werden: { $error }
+error-internal-plugin-missing = Der Audiograph konnte nicht aufgebaut werden, weil ein internes Plugin fehlt
+error-load-plugin = Das Plugin „{ $plugin }“ konnte nicht geladen werden: { $error }
+error-activate-plugin = Das Plugin „{ $plugin }“ konnte nicht aktiviert werden: { $error }
+
+## Errors from editing, recording and exporting
+
+error-system = { $error }
+error-engine-not-running = Die Audio-Engine läuft nicht
+error-export-running = Es läuft bereits ein Export
+error-export-sample-rate = Die Abtastrate des Exports muss größer als null sein
+error-export-stem-count = Es gibt zu viele Kanäle, um sie als Stems zu exportieren
+error-recording-running = Es läuft bereits eine Aufnahme
+error-channel-missing = Kanal { $channel } existiert nicht
+error-remove-master = Der Master-Kanal kann nicht entfernt werden
+error-route-master = Der Master-Kanal kann nicht geroutet werden
+error-route-channel = Kanal { $channel } kann nicht zu Kanal { $to } geroutet werden
+error-route-loop = Kanal { $channel } zu { $to } zu routen ergibt eine Schleife
+error-send-master = Der Master-Kanal hat keine Sends
+error-send-channel = Kanal { $channel } kann nicht an Kanal { $to } senden
+error-send-exists = Kanal { $channel } sendet bereits an Kanal { $to }
+error-send-loop = Kanal { $channel } an { $to } zu senden ergibt eine Schleife
+error-midi-master = Der Master-Kanal hat keinen MIDI-Ausgang
+error-record-channel = Kanal { $channel } kann nicht aufnehmen
+error-record-bus = Kanal { $channel } kann Kanal { $bus } nicht aufnehmen
+error-hardware-output-master = Der Master spielt auf den Ausgängen, die für das Audiogerät eingestellt sind
+error-clip-missing = Clip { $clip } existiert nicht
+error-lane-missing = Spur { $lane } existiert nicht
+error-section-missing = Abschnitt { $section } existiert nicht
+error-tempo-change-missing = Tempowechsel { $index } existiert nicht
+error-time-signature-change-missing = Taktartwechsel { $index } existiert nicht
+error-invalid-time-signature = { $signature } ist keine gültige Taktart
+error-macro-arguments = Makro `{ $name }` braucht { $parameters } Argumente, nicht { $args }
+
+## Console
+
+error-script-unknown-command = Unbekannter Befehl `{ $command }`. Gib `help` ein, um alle Befehle zu sehen.
+error-script-usage = Aufruf: { $usage }
+error-script-no-macro = Es gibt kein Makro namens `{ $name }`
+error-script-no-channel = Es gibt keinen Kanal namens `{ $name }`
+error-script-expected-position = Erwartet eine Position wie 3:1, nicht `{ $text }`
+error-script-expected-ramp = Erwartet `ramp`, nicht `{ $text }`
+error-script-expected-bar = Erwartet eine Taktnummer ab 1, nicht `{ $text }`
+error-script-expected-time-signature = Erwartet eine Taktart wie 3/4, nicht `{ $text }`
+error-script-expected-beats = Erwartet eine Anzahl Schläge, nicht `{ $text }`
+error-script-expected-number = Erwartet eine Zahl, nicht `{ $text }`
+error-script-repeat-count = Mehr als { $count } Wiederholungen sind nicht möglich
+error-script-missing-quote = Ein schließendes Anführungszeichen fehlt
+error-script-depth = Skripte können Skripte nicht tiefer als { $depth } Ebenen ausführen
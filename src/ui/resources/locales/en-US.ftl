@@ -0,0 +1,409 @@
+# The translations that every other locale falls back to. Every message that
+# the UI uses must be in this file.
+
+## Panels
+
+panel-browser = BROWSER
+panel-channel-rack = CHANNEL RACK
+panel-main = MAIN
+panel-clips = CLIPS
+panel-timeline = TIMELINE
+panel-piano-roll = PIANO ROLL
+panel-sample-editor = SAMPLE EDITOR
+panel-eq = EQ
+panel-collisions = COLLISIONS
+panel-console = CONSOLE
+panel-keyboard = KEYBOARD
+panel-pads = PADS
+panel-tuner = TUNER
+panel-inspector = INSPECTOR
+panel-loudness = LUFS
+panel-arranger = ARRANGER
+panel-oscilloscope = Oscilloscope
+panel-usage-graph = Usage Graph
+
+## Project tabs
+
+tab-meadowlark-project = MEADOWLARK PROJECT
+tab-other-project = OTHER PROJECT
+
+## Menu bar
+
+menu-save = SAVE
+menu-load = LOAD
+menu-export = EXPORT
+menu-export-stems = STEMS
+menu-record = REC
+menu-metronome = CLICK
+menu-loop = LOOP
+menu-tuner = TUNER
+menu-inspector = INSPECTOR
+menu-loudness = LOUDNESS
+menu-editor = EDITOR
+menu-eq = EQ
+menu-collisions = COLLISIONS
+menu-console = CONSOLE
+menu-keyboard = KEYBOARD
+menu-pads = PADS
+menu-file = File
+menu-edit = Edit
+menu-view = View
+menu-help = Help
+
+## Buttons
+
+button-rename = RENAME
+button-clear = CLEAR
+button-bypass = BYPASS
+button-auto-gain = AUTO GAIN
+button-analyze-selected = ANALYZE SELECTED
+button-cut = CUT
+button-copy = COPY
+button-paste = PASTE
+button-silence = SILENCE
+button-fade-in = FADE IN
+button-fade-out = FADE OUT
+button-normalize = NORMALIZE
+button-dc = DC
+button-undo = UNDO
+button-redo = REDO
+button-tap = TAP
+button-capture = CAPTURE
+button-input-quantize = INPUT Q
+button-musical-typing = KEYS
+button-requantize = REQUANTIZE
+button-save = SAVE
+button-record-macro = RECORD MACRO
+button-stop-recording = STOP RECORDING
+button-run = RUN
+button-delete = DELETE
+button-randomize = RANDOMIZE
+button-store-a = STORE A
+button-store-b = STORE B
+button-add-return-channel = ADD RETURN CHANNEL
+button-add-send = ADD SEND
+button-add-plugin = ADD PLUGIN
+button-remove-plugin = REMOVE
+button-rescan-plugins = RESCAN
+button-add-return-bus = ADD RETURN BUS
+button-pre-fader = PRE FADER
+button-post-fader = POST FADER
+button-add-zone = ADD ZONE
+button-add-chain = ADD CHAIN
+button-add-drum-pads = ADD PADS
+button-choose-sample = CHOOSE
+button-separate-output = OWN OUTPUT
+button-add-macro-knob = ADD MACRO
+button-map-parameter = MAP
+button-add-fx-rack = ADD RACK
+button-load-fx-rack = LOAD RACK
+button-save-fx-rack = SAVE RACK
+button-move-effect-in = MOVE IN
+button-move-effect-out = MOVE OUT
+button-move-effect-up = MOVE UP
+button-move-effect-down = MOVE DOWN
+button-mute = MUTE
+button-solo = SOLO
+button-remove = REMOVE
+button-reset = RESET
+
+## Context menus
+
+item-rename = Rename
+item-duplicate = Duplicate
+item-duplicate-alias = Duplicate as Alias
+item-unlink = Unlink
+item-unpack = Unpack
+item-mute = Mute
+item-unmute = Unmute
+item-copy = Copy
+item-cut = Cut
+item-paste = Paste
+item-remove = Remove
+item-toggle-active = Toggle Active
+item-group-clips = Group Clips
+item-new-playlist = New Playlist
+item-duplicate-playlist = Duplicate Playlist
+item-switch-playlist = Switch to { $playlist }
+item-remove-playlist = Remove Playlist
+item-freeze = Freeze
+item-copy-inserts = Copy Inserts
+item-paste-inserts = Paste Inserts
+item-clear-inserts = Clear Inserts
+item-exclusive-arm-on = Exclusive Arm
+item-exclusive-arm-off = Non-Exclusive Arm
+item-link-arm-subchannels = Link Arms of Group
+item-arm-group = Join Arm Group { $group }
+item-new-arm-group = New Arm Group
+item-leave-arm-group = Leave Arm Group
+item-record-device-input = Record Device Input
+item-record-bus = Record { $channel }
+item-hardware-output = Play on Outputs { $left }/{ $right }
+item-hardware-output-none = Play Through Routing
+item-no-midi-output = No MIDI Output
+item-refresh-midi-devices = Refresh MIDI Devices
+item-color = Color: { $color }
+item-undo = Undo { $action }
+item-redo = Redo { $action }
+item-save-workspace = Save Workspace...
+item-save-project-workspace = Save Project Workspace...
+item-workspace = Workspace: { $name }
+item-project-workspace = Workspace: { $name } (project)
+item-move-left = Move Left: { $panel }
+item-move-right = Move Right: { $panel }
+item-move-up = Move Up: { $panel }
+item-move-down = Move Down: { $panel }
+item-move-earlier = Move Earlier
+item-move-later = Move Later
+item-language = Language: { $language }
+item-input-quantize-on = Quantize Recorded Notes
+item-input-quantize-off = Don't Quantize Recorded Notes
+item-input-quantize-strength = Strength { $percent }%
+
+color-red = Red
+color-orange = Orange
+color-yellow = Yellow
+color-green = Green
+color-blue = Blue
+color-purple = Purple
+
+## Clips
+
+clip-captured-performance = Captured Performance { $take }
+clip-container = Container
+
+section-default-name = Section { $number }
+playlist-default-name = Playlist { $number }
+chain-default-name = Chain { $number }
+drum-pad-default-name = Pad { $number }
+macro-knob-default-name = Macro { $number }
+fx-rack-default-name = Rack
+
+recording-source-note-input = Note input
+recording-source-audio-input = Audio input
+
+## Inspector
+
+inspector-nothing-selected = Select a clip, channel or section to inspect it.
+inspector-not-recorded = Not recorded
+inspector-name = Name
+inspector-start = Start
+inspector-length = Length
+inspector-gain = Gain
+inspector-fade-in = Fade in
+inspector-fade-out = Fade out
+inspector-output = Output
+inspector-send = Send
+inspector-send-level = Send level
+inspector-midi-output = MIDI out
+inspector-midi-channel = MIDI channel
+inspector-midi-program = Program
+inspector-midi-bank = Bank
+inspector-midi-latency = Latency offset
+inspector-midi-in-channel = MIDI in channel
+inspector-low-key = Lowest key
+inspector-high-key = Highest key
+inspector-min-velocity = Min velocity
+inspector-max-velocity = Max velocity
+inspector-transpose = Transpose
+inspector-velocity-curve = Velocity curve
+inspector-zone = Zone
+inspector-chain = Chain
+inspector-chain-gain = Gain
+inspector-chain-pan = Pan
+inspector-channel-gain = Volume
+inspector-channel-pan = Pan
+inspector-pad = Pad
+inspector-sample = Sample
+inspector-pad-key = Key
+inspector-pad-tune = Tune
+inspector-pad-cutoff = Cutoff
+inspector-choke-group = Choke group
+inspector-pad-output = Output to
+inspector-macro-knob = Macro
+inspector-macro-min = Min
+inspector-macro-max = Max
+inspector-macro-curve = Curve
+macro-curve-linear = Linear
+macro-curve-exponential = Exponential
+macro-curve-logarithmic = Logarithmic
+macro-curve-s-curve = S-Curve
+inspector-randomize = RND
+inspector-random-range = Range
+inspector-source = Source
+inspector-take = Take
+inspector-tempo = Tempo
+inspector-date = Recorded
+
+velocity-curve-linear = Linear
+velocity-curve-soft = Soft
+velocity-curve-hard = Hard
+velocity-curve-fixed = Fixed
+
+quantize-grid-quarter = 1/4
+quantize-grid-eighth = 1/8
+quantize-grid-eighth-triplet = 1/8 Triplets
+quantize-grid-sixteenth = 1/16
+quantize-grid-thirty-second = 1/32
+
+## Macros
+
+macros-title = MACROS
+macro-default-name = Macro
+macro-no-macros = Record a macro to repeat what you did with one click.
+
+## Announcements
+
+announce-lane-selected = { $lane } selected
+announce-lane-selected-disabled = { $lane } selected, disabled
+announce-playlist-active = { $lane } now plays { $playlist }
+announce-channel-selected = Channel { $channel } selected
+announce-plugin-added = { $plugin } added
+announce-clip-muted = { $clip } muted
+announce-clip-unmuted = { $clip } unmuted
+announce-eq-band = EQ band { $band }: { $freq }, { $gain }, Q { $q }
+announce-eq-band-off = EQ band { $band }: { $freq }, { $gain }, Q { $q }, off
+announce-eq-bypassed = EQ bypassed
+announce-eq-active = EQ active
+announce-eq-auto-gain-on = EQ auto gain on
+announce-eq-auto-gain-off = EQ auto gain off
+announce-tempo = Tempo { $bpm } BPM
+announce-performance-captured = Captured the last performance
+announce-musical-typing-on = Musical typing on: play notes with the letter keys, Z and X change the octave, C and V the velocity
+announce-musical-typing-off = Musical typing off
+announce-typing-octave = Octave { $octave }
+announce-typing-velocity = Velocity { $velocity }
+announce-section-selected = Section { $section } selected
+announce-reference-pitch = Reference pitch { $pitch }
+announce-undo = Undid { $action }
+announce-redo = Redid { $action }
+
+## Undo history (the edits that can be undone)
+
+history-tempo = Tempo Change
+history-time-signature = Time Signature Change
+history-loop = Loop Change
+history-effects = Effect Change
+history-strip-silence = Strip Silence
+history-take = Take Change
+history-rename-clip = Rename Clip
+history-duplicate-clip = Duplicate Clip
+history-clip-notes = Note Edit
+history-mute-clip = Mute Clip
+history-remove-clips = Remove Clips
+history-container = Container Edit
+history-move-clip = Move Clip
+history-clip-length = Clip Length
+history-clip-gain = Clip Gain
+history-clip-fade = Clip Fade
+history-paste = Paste
+history-sections = Arranger Edit
+history-capture = Capture Performance
+history-input-quantize = Input Quantize
+history-import = Import
+history-lanes = Lane Edit
+history-midi = MIDI Settings
+history-instrument-rack = Instrument Rack
+history-drum-rack = Drum Rack
+history-channels = Channel Edit
+history-channel-mix = Mixer
+history-routing = Routing
+history-automation = Automation
+history-macro-knobs = Macro Knobs
+history-record-audio = Record Audio
+history-arm = Arm Settings
+history-script = Script
+
+## Notifications
+
+action-strip-silence = strip silence from
+action-learn-noise-profile = learn a noise profile from
+action-denoise = denoise
+action-analyze = analyze
+
+info-learned-noise-profile = Learned noise profile
+info-export-finished = Exported the project to "{ $path }"
+info-switched-audio-device = Switched to audio device "{ $device }"
+info-no-clips-to-freeze = There are no audio clips to freeze on channel "{ $channel }"
+info-no-performance-to-capture = Nothing has been played on the MIDI input yet
+info-no-clips-to-group = There are no clips on the selected lanes
+info-nothing-to-zoom-to = Select lanes with clips or a section to zoom to
+info-macro-recorded = Recorded { $lines } lines as macro "{ $name }"
+info-nothing-armed = Arm a channel to record onto
+info-master-outputs-remapped =
+    The audio device only has { $outputs } outputs, so the master is playing on
+    outputs { $left } and { $right } instead
+
+error-noise-profile-too-short = The selection is too short to learn a noise profile from
+error-no-noise-profile = Learn a noise profile before denoising a clip
+error-write-denoised-take = Failed to write denoised take "{ $path }": { $error }
+error-collision-needs-two-channels = Select two channels to analyze for collisions
+error-collision-no-clips = The selected channels have no audio clips on the timeline
+error-audio-device-disconnected = The audio device "{ $device }" was disconnected
+error-save-sample = Failed to save edited sample "{ $path }": { $error }
+error-save-workspace = Failed to save workspace "{ $name }": { $error }
+error-save-project = Failed to save the project: { $error }
+error-load-project = Failed to load the project: { $error }
+error-export = Failed to export the project: { $error }
+error-start-recording = Failed to start recording: { $error }
+error-load-click = Failed to load the click sample: { $error }
+error-recording-dropped = { $frames } frames of the recording of { $channel } were lost because the disk couldn't keep up
+error-recording-failed = Failed to write the recording of { $channel } to { $path }
+error-save-ui-state = Failed to save the view and layout of the project: { $error }
+error-delete-workspace = Failed to delete workspace "{ $name }": { $error }
+error-save-macro = Failed to save macro "{ $name }": { $error }
+error-save-fx-rack = Failed to save rack "{ $name }": { $error }
+error-delete-macro = Failed to delete macro "{ $name }": { $error }
+error-freeze-channel = Failed to freeze channel "{ $channel }": { $error }
+error-import-file = Failed to import "{ $path }": { $error }
+error-load-clip = Failed to { $action } clip "{ $clip }": { $error }
+error-open-sample-editor = Failed to open "{ $path }" in the sample editor: { $error }
+error-load-internal-plugin = Failed to load internal plugin: { $error }
+error-internal-plugin-missing = Could not build the audio graph because an internal plugin is missing
+error-load-plugin = Failed to load plugin "{ $plugin }": { $error }
+error-activate-plugin = Failed to activate plugin "{ $plugin }": { $error }
+
+## Errors from editing, recording and exporting
+
+error-system = { $error }
+error-engine-not-running = The engine is not running
+error-export-running = An export is already running
+error-export-sample-rate = The sample rate of the export must be above zero
+error-export-stem-count = There are too many channels to export as stems
+error-recording-running = A recording is already running
+error-channel-missing = Channel { $channel } does not exist
+error-remove-master = The master channel can't be removed
+error-route-master = The master channel can't be routed
+error-route-channel = Can't route channel { $channel } to channel { $to }
+error-route-loop = Routing channel { $channel } to { $to } makes a loop
+error-send-master = The master channel has no sends
+error-send-channel = Can't send channel { $channel } to channel { $to }
+error-send-exists = Channel { $channel } already sends to channel { $to }
+error-send-loop = Sending channel { $channel } to { $to } makes a loop
+error-midi-master = The master channel has no MIDI output
+error-record-channel = Channel { $channel } can't record
+error-record-bus = Channel { $channel } can't record channel { $bus }
+error-hardware-output-master = The master plays on the outputs the audio device is set up with
+error-clip-missing = Clip { $clip } does not exist
+error-lane-missing = Lane { $lane } does not exist
+error-section-missing = Section { $section } does not exist
+error-tempo-change-missing = Tempo change { $index } does not exist
+error-time-signature-change-missing = Time signature change { $index } does not exist
+error-invalid-time-signature = { $signature } is not a valid time signature
+error-macro-arguments = Macro `{ $name }` needs { $parameters } arguments, got { $args }
+
+## Console
+
+error-script-unknown-command = Unknown command `{ $command }`. Type `help` for a list.
+error-script-usage = Usage: { $usage }
+error-script-no-macro = There is no macro called `{ $name }`
+error-script-no-channel = There is no channel called `{ $name }`
+error-script-expected-position = Expected a position like 3:1, got `{ $text }`
+error-script-expected-ramp = Expected `ramp`, got `{ $text }`
+error-script-expected-bar = Expected a bar number from 1, got `{ $text }`
+error-script-expected-time-signature = Expected a time signature like 3/4, got `{ $text }`
+error-script-expected-beats = Expected a number of beats, got `{ $text }`
+error-script-expected-number = Expected a number, got `{ $text }`
+error-script-repeat-count = Can't repeat more than { $count } times
+error-script-missing-quote = Missing a closing quote
+error-script-depth = Scripts can't run scripts more than { $depth } deep
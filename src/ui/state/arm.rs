@@ -0,0 +1,132 @@
+use super::{LocalizedMessage, RecordInput, UiState};
+
+impl UiState {
+    /// The channels that are armed together with the given one: the channels
+    /// in its arm group, or just itself.
+    pub fn arm_linked(&self, index: usize) -> Vec<usize> {
+        match self.channels.get(index).and_then(|channel| channel.arm_group) {
+            Some(group) => (0..self.channels.len())
+                .filter(|i| self.channels[*i].arm_group == Some(group))
+                .collect(),
+            None => vec![index],
+        }
+    }
+
+    /// Arm or disarm a channel along with its arm group.
+    ///
+    /// Arming a channel with exclusive arm disarms every other channel, and
+    /// arming any channel disarms the channels with exclusive arm. The master
+    /// channel can't be armed.
+    pub fn set_armed(&mut self, index: usize, armed: bool) {
+        if index == 0 || index >= self.channels.len() {
+            return;
+        }
+        let linked = self.arm_linked(index);
+        let exclusive = linked.iter().any(|i| self.channels[*i].exclusive_arm);
+
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            if linked.contains(&i) {
+                channel.armed = armed;
+            } else if armed && (exclusive || channel.exclusive_arm) {
+                channel.armed = false;
+            }
+        }
+    }
+
+    /// Turn exclusive arm on or off for a channel and its arm group. If they
+    /// are armed, the other channels are disarmed right away.
+    pub fn set_exclusive_arm(&mut self, index: usize, exclusive: bool) {
+        if index == 0 || index >= self.channels.len() {
+            return;
+        }
+        for i in self.arm_linked(index) {
+            self.channels[i].exclusive_arm = exclusive;
+        }
+        if exclusive && self.channels[index].armed {
+            self.set_armed(index, true);
+        }
+    }
+
+    /// Put a channel in an arm group, or take it out of its group with
+    /// `None`. A channel that joins a group takes on whether the group is
+    /// armed and exclusive.
+    pub fn set_arm_group(&mut self, index: usize, group: Option<u32>) {
+        if index == 0 || index >= self.channels.len() {
+            return;
+        }
+        let member = group.and_then(|group| {
+            self.channels.iter().find(|channel| channel.arm_group == Some(group)).cloned()
+        });
+
+        let channel = &mut self.channels[index];
+        channel.arm_group = group;
+        if let Some(member) = member {
+            channel.armed = member.armed;
+            channel.exclusive_arm = member.exclusive_arm;
+        }
+    }
+
+    /// Put the given channels in a new arm group, e.g. the microphones of a
+    /// drum kit.
+    pub fn link_arm(&mut self, channels: &[usize]) {
+        let group = self.new_arm_group();
+        for index in channels.iter().copied().filter(|i| *i != 0 && *i < self.channels.len()) {
+            self.channels[index].arm_group = Some(group);
+        }
+        // Arm and make exclusive all of them if any one of them was.
+        if let Some(first) = channels.iter().copied().find(|i| *i != 0 && *i < self.channels.len())
+        {
+            let armed = channels.iter().any(|i| self.channels.get(*i).map_or(false, |c| c.armed));
+            let exclusive =
+                channels.iter().any(|i| self.channels.get(*i).map_or(false, |c| c.exclusive_arm));
+            self.set_exclusive_arm(first, exclusive);
+            self.set_armed(first, armed);
+        }
+    }
+
+    /// The arm groups that have channels in them, in order.
+    pub fn arm_groups(&self) -> Vec<u32> {
+        let mut groups: Vec<u32> =
+            self.channels.iter().filter_map(|channel| channel.arm_group).collect();
+        groups.sort_unstable();
+        groups.dedup();
+        groups
+    }
+
+    /// A number for an arm group that no channel is in.
+    pub fn new_arm_group(&self) -> u32 {
+        self.arm_groups().last().map_or(1, |group| group + 1)
+    }
+
+    /// The channels that other channels are routed to, i.e. the master and
+    /// the groups. These can be recorded by another channel.
+    pub fn buses(&self) -> Vec<usize> {
+        (0..self.channels.len())
+            .filter(|i| *i == 0 || self.channels.iter().any(|channel| channel.routed_to == *i))
+            .collect()
+    }
+
+    /// Set what a channel records. A channel can record any bus except
+    /// itself, including one it is routed to, e.g. the master for resampling.
+    /// The bus is recorded after its fader. What a channel records isn't
+    /// played back through it while recording, so this doesn't feed back.
+    pub fn set_record_input(
+        &mut self,
+        index: usize,
+        input: RecordInput,
+    ) -> Result<(), LocalizedMessage> {
+        if index == 0 || index >= self.channels.len() {
+            return Err(LocalizedMessage::new("error-record-channel").arg("channel", index));
+        }
+        if let RecordInput::Bus(bus) = input {
+            if bus == index || bus >= self.channels.len() {
+                return Err(LocalizedMessage::new("error-record-bus")
+                    .arg("channel", index)
+                    .arg("bus", bus));
+            }
+        }
+
+        self.channels[index].record_input = input;
+        Ok(())
+    }
+}
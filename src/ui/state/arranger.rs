@@ -0,0 +1,348 @@
+use meadowlark_core_types::time::MusicalTime;
+use std::ops::Range;
+use vizia::prelude::*;
+
+use super::core_types::{musical_from_beats, WMusicalTime};
+use super::{ClipStart, ClipState, Inspected, UiState};
+
+/// The length of a new section in bars.
+pub const DEFAULT_SECTION_BARS: u32 = 8;
+
+/// A section of the song (i.e. "Verse" or "Chorus") on the arranger track.
+#[derive(Debug, Lens, Clone, Data)]
+pub struct ArrangerSection {
+    pub name: String,
+    pub length: WMusicalTime,
+}
+
+/// The arranger track, which splits the song into sections that can be moved
+/// around together with everything in them.
+///
+/// The sections follow each other without gaps, so the start of every section
+/// is the end of the one before it.
+#[derive(Debug, Lens, Clone)]
+pub struct ArrangerState {
+    /// Where the first section starts.
+    pub start: WMusicalTime,
+
+    pub sections: Vec<ArrangerSection>,
+
+    pub selected: Option<usize>,
+}
+
+impl Default for ArrangerState {
+    fn default() -> Self {
+        Self { start: MusicalTime::from_beats(0).into(), sections: Vec::new(), selected: None }
+    }
+}
+
+impl ArrangerState {
+    /// The start and end of every section in beats.
+    pub fn section_ranges(&self) -> Vec<Range<f64>> {
+        let mut start = self.start.get().as_beats_f64();
+        self.sections
+            .iter()
+            .map(|section| {
+                let end = start + section.length.get().as_beats_f64();
+                let range = start..end;
+                start = end;
+                range
+            })
+            .collect()
+    }
+
+    /// The section that contains the given position in beats.
+    pub fn section_at(&self, beats: f64) -> Option<usize> {
+        self.section_ranges().iter().position(|range| range.contains(&beats))
+    }
+}
+
+impl UiState {
+    /// Add a section after the last one.
+    pub fn add_section(&mut self, name: String) {
+        let start = self
+            .arranger
+            .section_ranges()
+            .last()
+            .map_or_else(|| self.arranger.start.get().as_beats_f64(), |range| range.end);
+        let time_signatures = &self.timeline_grid.time_signatures;
+        let position = time_signatures.position_at(start);
+        let end = time_signatures
+            .beats_at(position.bar.saturating_add(DEFAULT_SECTION_BARS), position.beat);
+        let beats = end - start;
+
+        self.arranger
+            .sections
+            .push(ArrangerSection { name, length: musical_from_beats(beats).into() });
+        self.arranger.selected = Some(self.arranger.sections.len() - 1);
+        self.extend_project_to_sections();
+    }
+
+    /// Move a section so it becomes the section at index `to`. The sections in
+    /// between make room for it, and every clip on every lane moves along with
+    /// the section it starts in.
+    pub fn move_section(&mut self, from: usize, to: usize) {
+        let len = self.arranger.sections.len();
+        if from >= len || to >= len || from == to {
+            return;
+        }
+
+        let old_ranges = self.arranger.section_ranges();
+        let section = self.arranger.sections.remove(from);
+        self.arranger.sections.insert(to, section);
+
+        // The new index of every section.
+        let mut order: Vec<usize> = (0..len).collect();
+        let moved = order.remove(from);
+        order.insert(to, moved);
+        let new_ranges = self.arranger.section_ranges();
+
+        let mut offsets = vec![0.0; len];
+        for (new_index, old_index) in order.iter().enumerate() {
+            offsets[*old_index] = new_ranges[new_index].start - old_ranges[*old_index].start;
+        }
+
+        self.shift_clips(|beats| {
+            old_ranges.iter().position(|range| range.contains(&beats)).map(|i| offsets[i])
+        });
+        self.arranger.selected = Some(to);
+        if self.inspected == Some(Inspected::Section(from)) {
+            self.inspected = Some(Inspected::Section(to));
+        }
+    }
+
+    /// Add a copy of a section right after it, along with a copy of every clip
+    /// that starts in it. Everything after the section moves back to make room.
+    pub fn duplicate_section(&mut self, index: usize) {
+        let ranges = self.arranger.section_ranges();
+        let range = match ranges.get(index) {
+            Some(range) => range.clone(),
+            None => return,
+        };
+        let length = range.end - range.start;
+
+        let copies: Vec<_> = self
+            .clips
+            .iter()
+            .filter(|clip| clip_start_beats(clip).map_or(false, |beats| range.contains(&beats)))
+            .cloned()
+            .collect();
+
+        self.shift_clips(|beats| if beats >= range.end { Some(length) } else { None });
+        for mut clip in copies {
+            shift_clip(&mut clip, length);
+            self.clips.push(clip);
+        }
+
+        let section = self.arranger.sections[index].clone();
+        self.arranger.sections.insert(index + 1, section);
+        self.arranger.selected = Some(index + 1);
+        self.extend_project_to_sections();
+    }
+
+    /// Set the length of a section in beats. Everything after the section
+    /// moves along with its end.
+    pub fn set_section_length(&mut self, index: usize, beats: f64) {
+        let range = match self.arranger.section_ranges().get(index) {
+            Some(range) => range.clone(),
+            None => return,
+        };
+        let beats = beats.max(1.0);
+
+        self.shift_clips(|start| {
+            if start >= range.end {
+                Some(beats - (range.end - range.start))
+            } else {
+                None
+            }
+        });
+        self.arranger.sections[index].length = musical_from_beats(beats).into();
+        self.extend_project_to_sections();
+    }
+
+    /// Remove a section along with every clip that starts in it. Everything
+    /// after the section moves forward to close the gap.
+    pub fn remove_section(&mut self, index: usize) {
+        let ranges = self.arranger.section_ranges();
+        let range = match ranges.get(index) {
+            Some(range) => range.clone(),
+            None => return,
+        };
+        let length = range.end - range.start;
+
+        self.clips
+            .retain(|clip| clip_start_beats(clip).map_or(true, |beats| !range.contains(&beats)));
+        self.shift_clips(|beats| if beats >= range.end { Some(-length) } else { None });
+
+        self.arranger.sections.remove(index);
+        self.arranger.selected = None;
+        if matches!(self.inspected, Some(Inspected::Section(_))) {
+            self.inspected = None;
+        }
+    }
+
+    /// Move every clip on the timeline by the number of beats returned for its
+    /// start, if any.
+    fn shift_clips(&mut self, offset: impl Fn(f64) -> Option<f64>) {
+        for clip in self.clips.iter_mut() {
+            if let Some(offset) = clip_start_beats(clip).and_then(&offset) {
+                shift_clip(clip, offset);
+            }
+        }
+    }
+
+    fn extend_project_to_sections(&mut self) {
+        if let Some(end) = self.arranger.section_ranges().last().map(|range| range.end) {
+            if end > self.timeline_grid.project_length.get().as_beats_f64() {
+                self.timeline_grid.project_length = musical_from_beats(end).into();
+            }
+        }
+    }
+}
+
+/// The start of a clip in beats, or `None` if it is not on the timeline.
+fn clip_start_beats(clip: &ClipState) -> Option<f64> {
+    match &clip.timeline_start {
+        ClipStart::OnLane(on_lane) => Some(on_lane.timeline_start.get().as_beats_f64()),
+        ClipStart::NotInTimeline => None,
+    }
+}
+
+fn shift_clip(clip: &mut ClipState, beats: f64) {
+    if let ClipStart::OnLane(on_lane) = &mut clip.timeline_start {
+        let start = on_lane.timeline_start.get().as_beats_f64() + beats;
+        on_lane.timeline_start = musical_from_beats(start).into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::time_signature::{TimeSignature, TimeSignatureChange};
+    use crate::ui::state::{AutomationClipState, ClipType, OnLane};
+
+    fn clip(beats: Option<f64>) -> ClipState {
+        ClipState {
+            name: String::new(),
+            timeline_start: match beats {
+                Some(beats) => ClipStart::OnLane(OnLane {
+                    lane_index: 0,
+                    timeline_start: musical_from_beats(beats).into(),
+                }),
+                None => ClipStart::NotInTimeline,
+            },
+            length: MusicalTime::from_beats(1).into(),
+            channel: 0,
+            muted: false,
+            effects: Vec::new(),
+            recording: None,
+            player_host_id: None,
+            type_: ClipType::Automation(AutomationClipState {}),
+        }
+    }
+
+    /// Three sections of 32 beats, "A", "B" and "C", with a clip in each and
+    /// one that isn't on the timeline.
+    fn state() -> UiState {
+        let mut state = UiState::for_tests(1, 1);
+        for name in ["A", "B", "C"] {
+            state.add_section(name.into());
+        }
+        state.clips = vec![clip(Some(4.0)), clip(Some(40.0)), clip(Some(70.0)), clip(None)];
+        state
+    }
+
+    fn names(state: &UiState) -> Vec<&str> {
+        state.arranger.sections.iter().map(|section| section.name.as_str()).collect()
+    }
+
+    fn starts(state: &UiState) -> Vec<Option<f64>> {
+        state.clips.iter().map(clip_start_beats).collect()
+    }
+
+    fn project_length(state: &UiState) -> f64 {
+        state.timeline_grid.project_length.get().as_beats_f64()
+    }
+
+    #[test]
+    fn sections_follow_each_other() {
+        let mut state = state();
+        assert_eq!(state.arranger.section_ranges(), [0.0..32.0, 32.0..64.0, 64.0..96.0]);
+        assert_eq!(state.arranger.selected, Some(2));
+        assert_eq!(project_length(&state), 96.0);
+
+        assert_eq!(state.arranger.section_at(31.9), Some(0));
+        assert_eq!(state.arranger.section_at(32.0), Some(1));
+        assert_eq!(state.arranger.section_at(96.0), None);
+
+        state.arranger.start = MusicalTime::from_beats(4).into();
+        assert_eq!(state.arranger.section_at(2.0), None);
+        assert_eq!(state.arranger.section_ranges()[0], 4.0..36.0);
+    }
+
+    #[test]
+    fn new_sections_are_bars_of_the_signature_they_start_in() {
+        let mut state = UiState::for_tests(1, 1);
+        state
+            .timeline_grid
+            .time_signatures
+            .insert(TimeSignatureChange { bar: 0, signature: TimeSignature::new(3, 4) });
+        state.add_section("A".into());
+        assert_eq!(state.arranger.section_ranges(), [0.0..24.0]);
+    }
+
+    #[test]
+    fn moved_sections_take_their_clips_along() {
+        let mut state = state();
+        state.inspected = Some(Inspected::Section(0));
+        state.move_section(0, 2);
+
+        assert_eq!(names(&state), ["B", "C", "A"]);
+        assert_eq!(starts(&state), [Some(68.0), Some(8.0), Some(38.0), None]);
+        assert_eq!(state.arranger.selected, Some(2));
+        assert_eq!(state.inspected, Some(Inspected::Section(2)));
+
+        // Out of range, or in place.
+        state.move_section(0, 3);
+        state.move_section(1, 1);
+        assert_eq!(names(&state), ["B", "C", "A"]);
+    }
+
+    #[test]
+    fn duplicated_sections_copy_their_clips() {
+        let mut state = state();
+        state.duplicate_section(0);
+
+        assert_eq!(names(&state), ["A", "A", "B", "C"]);
+        assert_eq!(starts(&state), [Some(4.0), Some(72.0), Some(102.0), None, Some(36.0)]);
+        assert_eq!(state.arranger.selected, Some(1));
+        assert_eq!(project_length(&state), 128.0);
+    }
+
+    #[test]
+    fn resized_sections_move_what_comes_after() {
+        let mut state = state();
+        state.set_section_length(0, 16.0);
+        assert_eq!(state.arranger.section_ranges(), [0.0..16.0, 16.0..48.0, 48.0..80.0]);
+        assert_eq!(starts(&state), [Some(4.0), Some(24.0), Some(54.0), None]);
+
+        // A section is at least a beat long.
+        state.set_section_length(2, 0.0);
+        assert_eq!(state.arranger.section_ranges()[2], 48.0..49.0);
+    }
+
+    #[test]
+    fn removed_sections_take_their_clips_with_them() {
+        let mut state = state();
+        state.inspected = Some(Inspected::Section(1));
+        state.remove_section(1);
+
+        assert_eq!(names(&state), ["A", "C"]);
+        assert_eq!(starts(&state), [Some(4.0), Some(38.0), None]);
+        assert_eq!(state.arranger.selected, None);
+        assert_eq!(state.inspected, None);
+
+        state.remove_section(5);
+        assert_eq!(names(&state), ["A", "C"]);
+    }
+}
@@ -0,0 +1,124 @@
+use meadowlark_core_types::time::{Seconds, SuperFrames};
+use std::path::{Path, PathBuf};
+
+use super::{
+    musical_from_beats, AudioClipState, ClipStart, ClipState, ClipType, LaneState, OnLane,
+    RecordingInfo, UiState,
+};
+use crate::backend::disk_writer::RecordingTrackId;
+
+/// The directory that recordings are written to.
+pub const RECORDINGS_DIR: &str = "recordings";
+
+/// A recording of the input onto the armed channels. Each channel is written
+/// to a file of its own, which becomes a clip once it is closed.
+pub struct AudioRecording {
+    pub tracks: Vec<RecordedTrack>,
+    /// The position of the playhead in seconds when the recording started,
+    /// which is where the clips go. `None` if the transport never played.
+    pub start_secs: Option<f64>,
+    /// Set once the recording was stopped. The clips are added once every
+    /// file is closed.
+    pub stopped: bool,
+}
+
+/// A channel that is being recorded.
+pub struct RecordedTrack {
+    pub id: RecordingTrackId,
+    pub channel: usize,
+    /// The lane the clip goes on.
+    pub lane: usize,
+    pub path: PathBuf,
+}
+
+impl AudioRecording {
+    pub fn new() -> Self {
+        Self { tracks: Vec::new(), start_secs: None, stopped: false }
+    }
+
+    /// A file for a new recording of a channel that doesn't exist yet.
+    pub fn new_path(channel_name: &str) -> PathBuf {
+        let name: String = channel_name
+            .chars()
+            .map(|c| if c.is_alphanumeric() || " -_".contains(c) { c } else { '_' })
+            .collect();
+        let date = chrono::Local::now().format("%Y-%m-%d %H-%M-%S");
+
+        let mut n = 1;
+        loop {
+            let path =
+                Path::new(RECORDINGS_DIR).join(format!("{} {} {}.wav", name.trim(), date, n));
+            if !path.exists() {
+                return path;
+            }
+            n += 1;
+        }
+    }
+}
+
+impl Default for AudioRecording {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UiState {
+    /// Place a recorded file on the timeline as a clip of the channel it was
+    /// recorded onto, starting where the recording started.
+    pub fn add_recorded_clip(
+        &mut self,
+        track: &RecordedTrack,
+        start_secs: f64,
+        length_secs: f64,
+        source: String,
+    ) {
+        let start_beats = self.timeline_grid.seconds_to_beats(start_secs);
+        let end_beats = self.timeline_grid.seconds_to_beats(start_secs + length_secs);
+        let bpm = self.timeline_grid.bpm_at(start_beats);
+        let lane_states = &mut self.timeline_grid.lane_states;
+        while lane_states.lanes.len() <= track.lane {
+            lane_states.push_lane(LaneState::default());
+        }
+
+        let recording = RecordingInfo {
+            source,
+            take: self.next_take(track.lane),
+            bpm,
+            date: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+        };
+        let name = track
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("Audio"));
+
+        self.clips.push(ClipState {
+            name,
+            timeline_start: ClipStart::OnLane(OnLane {
+                lane_index: track.lane as u32,
+                timeline_start: musical_from_beats(start_beats).into(),
+            }),
+            length: musical_from_beats(end_beats - start_beats).into(),
+            channel: track.channel,
+            muted: false,
+            effects: Vec::new(),
+            recording: Some(recording),
+            player_host_id: None,
+            type_: ClipType::Audio(AudioClipState {
+                gain_db: 0.0,
+                fade_in_secs: Seconds(0.0).into(),
+                fade_out_secs: Seconds(0.0).into(),
+                clip_start_offset: SuperFrames(0).into(),
+                pcm_path: track.path.clone(),
+                alternate_takes: Vec::new(),
+                active_take: None,
+            }),
+        });
+
+        let timeline_grid = &mut self.timeline_grid;
+        if end_beats > timeline_grid.project_length.get().as_beats_f64() {
+            timeline_grid.project_length = musical_from_beats(end_beats).into();
+        }
+        timeline_grid.used_lanes = timeline_grid.used_lanes.max(track.lane as u32);
+    }
+}
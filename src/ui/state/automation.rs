@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use vizia::prelude::*;
+
+use super::{moved_effect_index, HRackEffectState, UiState};
+use crate::backend::automation::{AutomationCurve, AutomationLane, AutomationPoint};
+use crate::backend::automation_plug::{AutomatedParam, AutomationPlugHandle};
+use crate::backend::fader_plug::{FADER_PARAM_GAIN, FADER_PARAM_PAN};
+use crate::backend::graph_interface::PluginHost;
+
+impl Data for AutomationLane {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Data for AutomationPoint {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// What an automation lane of a channel changes.
+#[derive(Debug, Clone, Copy, PartialEq, Data, Serialize, Deserialize)]
+pub enum AutomationTarget {
+    /// The output gain of the channel.
+    Gain,
+    /// The output pan of the channel.
+    Pan,
+    /// A parameter of one of the effects of the channel.
+    Parameter { effect: usize, id: u32 },
+    /// One of the macro knobs of the channel.
+    Macro(usize),
+}
+
+/// The changes of one target of a channel over time. Values are normalized,
+/// like `out_gain_normalized` and the normalized values of parameters.
+///
+/// The lanes are played by an `AutomationPlug` in front of the fader or the
+/// effect whose parameters they move.
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct AutomationLaneState {
+    pub target: AutomationTarget,
+    pub lane: AutomationLane,
+}
+
+/// The plugin of a channel whose parameters automation lanes move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutomatedPlugin {
+    Fader,
+    Effect(usize),
+}
+
+/// A change to the automation lanes of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AutomationEdit {
+    /// Add a lane for a target, unless the channel already has one.
+    AddLane(AutomationTarget),
+    RemoveLane(usize),
+    AddPoint {
+        lane: usize,
+        point: AutomationPoint,
+    },
+    MovePoint {
+        lane: usize,
+        index: usize,
+        to: AutomationPoint,
+    },
+    RemovePoint {
+        lane: usize,
+        index: usize,
+    },
+    /// Change the shape of the ramp from a point to the next one.
+    SetCurve {
+        lane: usize,
+        index: usize,
+        curve: AutomationCurve,
+    },
+}
+
+impl UiState {
+    pub fn edit_automation(&mut self, index: usize, edit: &AutomationEdit) {
+        let lanes = match self.channels.get_mut(index) {
+            Some(channel) => &mut channel.automation_lanes,
+            None => return,
+        };
+
+        match edit {
+            AutomationEdit::AddLane(target) => {
+                if !lanes.iter().any(|lane| lane.target == *target) {
+                    lanes
+                        .push(AutomationLaneState { target: *target, lane: AutomationLane::new() });
+                }
+            }
+            AutomationEdit::RemoveLane(lane) => {
+                if *lane < lanes.len() {
+                    lanes.remove(*lane);
+                }
+            }
+            AutomationEdit::AddPoint { lane, point } => {
+                if let Some(lane) = lanes.get_mut(*lane) {
+                    lane.lane.add_point(*point);
+                }
+            }
+            AutomationEdit::MovePoint { lane, index, to } => {
+                if let Some(lane) = lanes.get_mut(*lane) {
+                    lane.lane.move_point(*index, *to);
+                }
+            }
+            AutomationEdit::RemovePoint { lane, index } => {
+                if let Some(lane) = lanes.get_mut(*lane) {
+                    lane.lane.remove_point(*index);
+                }
+            }
+            AutomationEdit::SetCurve { lane, index, curve } => {
+                if let Some(lane) = lanes.get_mut(*lane) {
+                    lane.lane.set_curve(*index, *curve);
+                }
+            }
+        }
+    }
+
+    /// Follow the effects of a channel to their new slots after one of them
+    /// was moved.
+    pub fn on_effect_moved_automation(&mut self, channel: usize, from: usize, to: usize) {
+        if let Some(channel) = self.channels.get_mut(channel) {
+            for lane in channel.automation_lanes.iter_mut() {
+                if let AutomationTarget::Parameter { effect, .. } = &mut lane.target {
+                    *effect = moved_effect_index(*effect, from, to);
+                }
+            }
+        }
+    }
+
+    /// Forget the lanes of the parameters of an effect that was removed, and
+    /// follow the effects after it to their new slots.
+    pub fn on_effect_removed_automation(&mut self, channel: usize, effect: usize) {
+        let lanes = match self.channels.get_mut(channel) {
+            Some(channel) => &mut channel.automation_lanes,
+            None => return,
+        };
+        lanes.retain(|lane| match lane.target {
+            AutomationTarget::Parameter { effect: e, .. } => e != effect,
+            _ => true,
+        });
+        for lane in lanes.iter_mut() {
+            if let AutomationTarget::Parameter { effect: e, .. } = &mut lane.target {
+                if *e > effect {
+                    *e -= 1;
+                }
+            }
+        }
+    }
+
+    /// The parameters the automation lanes of a channel move, with the
+    /// plugin each one is on. Lanes without points are left out, and a lane
+    /// of a macro knob moves every parameter mapped to the knob.
+    pub fn automated_params(&self, channel: usize) -> Vec<(AutomatedPlugin, AutomatedParam)> {
+        let channel = match self.channels.get(channel) {
+            Some(channel) => channel,
+            None => return Vec::new(),
+        };
+
+        let mut params = Vec::new();
+        for lane in channel.automation_lanes.iter().filter(|l| !l.lane.points().is_empty()) {
+            let param =
+                |param_id, mapping| AutomatedParam { param_id, lane: lane.lane.clone(), mapping };
+            match lane.target {
+                AutomationTarget::Gain => {
+                    params.push((AutomatedPlugin::Fader, param(FADER_PARAM_GAIN, None)))
+                }
+                AutomationTarget::Pan => {
+                    params.push((AutomatedPlugin::Fader, param(FADER_PARAM_PAN, None)))
+                }
+                AutomationTarget::Parameter { effect, id } => {
+                    params.push((AutomatedPlugin::Effect(effect), param(id, None)))
+                }
+                AutomationTarget::Macro(knob) => {
+                    for target in channel.macro_knobs.get(knob).iter().flat_map(|k| &k.targets) {
+                        params.push((
+                            AutomatedPlugin::Effect(target.effect),
+                            param(target.id, Some(target.mapping)),
+                        ));
+                    }
+                }
+            }
+        }
+        params
+    }
+
+    /// The plugins of a channel that have automated parameters. Only the
+    /// fader and external effects can be automated.
+    pub fn automated_plugins(&self, channel: usize) -> Vec<AutomatedPlugin> {
+        let mut plugins = Vec::new();
+        for (plugin, _) in self.automated_params(channel) {
+            if !plugins.contains(&plugin) && self.automation_host_id(channel, plugin).is_some() {
+                plugins.push(plugin);
+            }
+        }
+        plugins
+    }
+
+    /// The host ID of the automation plugin of a plugin of a channel. `None`
+    /// if the plugin can't be automated, `Some(None)` if its automation
+    /// plugin hasn't been added to the graph.
+    pub fn automation_host_id(
+        &self,
+        channel: usize,
+        plugin: AutomatedPlugin,
+    ) -> Option<Option<u64>> {
+        let channel = self.channels.get(channel)?;
+        match plugin {
+            AutomatedPlugin::Fader => Some(channel.fader_automation_host_id),
+            AutomatedPlugin::Effect(effect) => match channel.effects.get(effect)? {
+                HRackEffectState::External(effect) => Some(effect.automation_host_id),
+                _ => None,
+            },
+        }
+    }
+
+    /// The host ID of a plugin of a channel that can be automated.
+    pub fn automated_plugin_host_id(&self, channel: usize, plugin: AutomatedPlugin) -> Option<u64> {
+        let channel = self.channels.get(channel)?;
+        match plugin {
+            AutomatedPlugin::Fader => channel.fader_host_id,
+            AutomatedPlugin::Effect(effect) => match channel.effects.get(effect)? {
+                HRackEffectState::External(effect) => effect.host_id,
+                _ => None,
+            },
+        }
+    }
+
+    pub fn set_automation_host_id(&mut self, channel: usize, plugin: AutomatedPlugin, id: u64) {
+        let channel = match self.channels.get_mut(channel) {
+            Some(channel) => channel,
+            None => return,
+        };
+        match plugin {
+            AutomatedPlugin::Fader => channel.fader_automation_host_id = Some(id),
+            AutomatedPlugin::Effect(effect) => {
+                if let Some(HRackEffectState::External(effect)) = channel.effects.get_mut(effect) {
+                    effect.automation_host_id = Some(id);
+                }
+            }
+        }
+    }
+
+    /// The host IDs of the automation plugins of the plugins that have
+    /// automated parameters.
+    pub fn automation_host_ids(&self) -> Vec<u64> {
+        (0..self.channels.len())
+            .flat_map(|channel| {
+                self.automated_plugins(channel)
+                    .into_iter()
+                    .filter_map(move |plugin| self.automation_host_id(channel, plugin).flatten())
+            })
+            .collect()
+    }
+
+    /// The automated plugins whose automation plugin isn't in the audio
+    /// graph. Like faders, a copy of a channel or an effect needs one of its
+    /// own.
+    pub fn unhosted_automation(&self, hosted: &[u64]) -> Vec<(usize, AutomatedPlugin)> {
+        let mut seen = Vec::new();
+        let mut unhosted = Vec::new();
+        for channel in 0..self.channels.len() {
+            for plugin in self.automated_plugins(channel) {
+                match self.automation_host_id(channel, plugin).flatten() {
+                    Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                    _ => unhosted.push((channel, plugin)),
+                }
+            }
+        }
+        unhosted
+    }
+
+    /// Send the automated parameters of each plugin to its automation plugin.
+    pub fn sync_automation(&self, plugin_host: &mut PluginHost) {
+        for channel in 0..self.channels.len() {
+            let params = self.automated_params(channel);
+            for plugin in self.automated_plugins(channel) {
+                let host_id = self.automation_host_id(channel, plugin).flatten();
+                let handle = match host_id.and_then(|id| plugin_host.handle_mut(id)) {
+                    Some(handle) => handle,
+                    None => continue,
+                };
+                if let Some(automation) =
+                    handle.internal.as_mut().and_then(|h| h.downcast_mut::<AutomationPlugHandle>())
+                {
+                    automation.set_params(
+                        params
+                            .iter()
+                            .filter(|(p, _)| *p == plugin)
+                            .map(|(_, param)| param.clone())
+                            .collect(),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::path::{Path, PathBuf};
 
 use super::UiEvent;
+use crate::backend::waveform::PEAK_FILE_EXTENSION;
 use vizia::prelude::*;
 
 #[derive(Debug, Lens, Clone, Data)]
@@ -19,6 +20,7 @@ pub enum BrowserEvent {
     ToggleOpen,
     PlaySelected,
     StopSelected,
+    EditSelected,
 }
 
 #[derive(Debug, Clone, Data, Lens)]
@@ -78,6 +80,15 @@ impl Model for BrowserState {
                 cx.emit(UiEvent::BrowserFileStop());
             }
 
+            // Open the selected file in the sample editor
+            BrowserEvent::EditSelected => {
+                if let Some(path) = &self.selected {
+                    if path.is_file() {
+                        cx.emit(UiEvent::OpenSampleEditor(path.clone()));
+                    }
+                }
+            }
+
             BrowserEvent::ToggleOpen => {
                 if let Some(path) = &self.selected {
                     toggle_open(&mut self.root_file, path);
@@ -189,6 +200,9 @@ fn visit_dirs(dir: &Path) -> Option<File> {
             let path = entry.path();
             if path.is_dir() {
                 children.push(visit_dirs(&path)?);
+            } else if path.extension().map_or(false, |ext| ext == PEAK_FILE_EXTENSION) {
+                // Peak files are a cache of the audio file next to them.
+                continue;
             } else {
                 children.push(File {
                     name: format!("{}", entry.path().file_name()?.to_str()?),
@@ -1,7 +1,13 @@
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 use super::clip::{AudioClipState, AutomationClipState, PianoRollClipState};
 use super::hrack_effect::HRackEffectState;
+use super::{AutomationLaneState, MacroKnobState};
+use crate::backend::drum_rack::DrumRack;
+use crate::backend::instrument_rack::InstrumentRack;
+use crate::backend::midi_transform::MidiTransform;
+use crate::util::Unit;
 use vizia::prelude::*;
 
 #[derive(Debug, Lens, Clone, Data)]
@@ -27,6 +33,74 @@ impl From<Color> for ChannelBaseColor {
     }
 }
 
+/// Where a channel records from when it is armed.
+#[derive(Debug, Clone, Copy, PartialEq, Data, Serialize, Deserialize)]
+pub enum RecordInput {
+    /// A pair of inputs of the audio device.
+    Device { left: usize, right: usize },
+    /// The output of another channel, e.g. the master or a group. This is for
+    /// bouncing and resampling without routing the audio out and back in.
+    Bus(usize),
+}
+
+impl Default for RecordInput {
+    fn default() -> Self {
+        RecordInput::Device { left: 0, right: 1 }
+    }
+}
+
+/// A pair of outputs of the audio device a channel plays on instead of the
+/// channel it is routed to, e.g. for a headphone mix or outboard gear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data, Serialize, Deserialize)]
+pub struct HardwareOutput {
+    pub left: usize,
+    pub right: usize,
+}
+
+/// Where the MIDI of a channel is sent to drive a hardware synth. The program
+/// and bank are sent when they change, and the notes the channel's MIDI track
+/// plays as they are played.
+#[derive(Debug, Lens, Clone, PartialEq, Data, Serialize, Deserialize)]
+pub struct MidiOutputRoute {
+    /// The name of the MIDI output device.
+    pub device: String,
+    /// The MIDI channel, from 0 to 15.
+    pub channel: u8,
+    /// The program that is selected when the route is set up, from 0 to 127.
+    pub program: Option<u8>,
+    /// The bank that is selected before the program, from 0 to 16383.
+    pub bank: Option<u16>,
+}
+
+impl MidiOutputRoute {
+    pub fn new(device: String) -> Self {
+        Self { device, channel: 0, program: None, bank: None }
+    }
+}
+
+/// A copy of the output of a channel that is sent to another channel on top
+/// of its main output, e.g. to share one reverb between many channels.
+#[derive(Debug, Lens, Clone, PartialEq, Data, Serialize, Deserialize)]
+pub struct ChannelSend {
+    /// The index of the channel the send goes to.
+    pub to: usize,
+    /// The level of the send in decibels.
+    pub level_db: f32,
+    /// If true, the send is taken before the gain of the channel, so turning
+    /// the channel down doesn't turn down what it sends.
+    pub pre_fader: bool,
+    /// The fader plugin that sets the level of the send in the audio graph,
+    /// or `None` if it hasn't been added to the graph.
+    #[serde(skip)]
+    pub host_id: Option<u64>,
+}
+
+impl ChannelSend {
+    pub fn new(to: usize) -> Self {
+        Self { to, level_db: 0.0, pre_fader: false, host_id: None }
+    }
+}
+
 /// A "channel" refers to a mixer channel.
 #[derive(Debug, Lens, Clone, Data)]
 pub struct ChannelState {
@@ -53,10 +127,16 @@ pub struct ChannelState {
     /// The audio clips assigned to this channel.
     pub automation_clips: Vec<AutomationClipState>,
 
-    // TODO: Use some kind of tree structure instead of a Vec once we
-    // implement container effects.
+    /// The parameters of the channel that change over time, at most one lane
+    /// for each.
+    pub automation_lanes: Vec<AutomationLaneState>,
+
+    /// The effects of the channel, in order. Racks hold effects of their own.
     pub effects: Vec<HRackEffectState>,
 
+    /// Knobs that each turn several parameters of the effects at once.
+    pub macro_knobs: Vec<MacroKnobState>,
+
     /// The index to the channel that this channel is routed to.
     ///
     /// The master channel is always at index 0.
@@ -79,7 +159,70 @@ pub struct ChannelState {
 
     /// True if this channel is currently being muted.
     pub muted: bool,
-    // TODO: Sends
+
+    /// True if this channel records when recording starts.
+    pub armed: bool,
+
+    /// If true, arming this channel disarms every channel outside its arm
+    /// group.
+    pub exclusive_arm: bool,
+
+    /// Channels in the same arm group are armed and disarmed together, e.g.
+    /// the microphones of a drum kit.
+    pub arm_group: Option<u32>,
+
+    /// What the channel records when it is armed.
+    pub record_input: RecordInput,
+
+    /// The outputs of the audio device the channel plays on instead of the
+    /// channel it is routed to, if any. The master plays on the outputs the
+    /// audio device is set up with.
+    pub hardware_output: Option<HardwareOutput>,
+
+    /// The hardware device this channel sends its MIDI to, if any.
+    pub midi_output: Option<MidiOutputRoute>,
+
+    /// What is done to incoming notes before they reach the instrument of
+    /// the channel, by its MIDI track plugin. It is also applied to
+    /// performances captured onto the channel.
+    pub midi_input: MidiTransform,
+
+    /// The chains of instruments of the channel, each with its own zone of
+    /// keys and velocities and its own gain and pan. Only the model for now,
+    /// see `instrument_rack`.
+    pub instrument_rack: InstrumentRack,
+
+    /// The drum pads the channel plays, each with a sample on a key of its
+    /// own.
+    pub drum_rack: DrumRack,
+
+    /// The other channels this channel sends to, on top of the channel it is
+    /// routed to.
+    pub sends: Vec<ChannelSend>,
+
+    /// The fader plugin at the end of the channel in the audio graph, or
+    /// `None` if it hasn't been added to the graph.
+    pub fader_host_id: Option<u64>,
+
+    /// The MIDI track plugin that plays the MIDI clips of the channel, or
+    /// `None` if it hasn't been added to the graph. Only channels with MIDI
+    /// clips or MIDI input processing have one.
+    pub midi_track_host_id: Option<u64>,
+
+    /// The timeline track plugin that plays the audio clips of the channel,
+    /// or `None` if it hasn't been added to the graph. Only channels with
+    /// audio clips without effects of their own have one.
+    pub timeline_track_host_id: Option<u64>,
+
+    /// The drum rack plugin that plays the pads of the channel, or `None` if
+    /// it hasn't been added to the graph. Only channels with pads that have
+    /// a sample have one.
+    pub drum_rack_host_id: Option<u64>,
+
+    /// The automation plugin that moves the gain and pan of the fader, or
+    /// `None` if it hasn't been added to the graph. Only channels with gain
+    /// or pan automation have one.
+    pub fader_automation_host_id: Option<u64>,
 }
 
 impl Default for ChannelState {
@@ -94,24 +237,60 @@ impl Default for ChannelState {
             audio_clips: vec![],
             piano_roll_clips: vec![],
             automation_clips: vec![],
+            automation_lanes: vec![],
             effects: vec![],
+            macro_knobs: vec![],
             routed_to: 0,
             out_gain_normalized: 1.0,
             out_pan_normalized: 0.5,
-            out_gain_display: String::from("0dB"),
+            out_gain_display: Unit::Decibels.format(0.0),
             out_pan_display: String::from("0"),
             soloed: false,
             muted: false,
+            armed: false,
+            exclusive_arm: false,
+            arm_group: None,
+            record_input: RecordInput::default(),
+            hardware_output: None,
+            midi_output: None,
+            midi_input: MidiTransform::default(),
+            instrument_rack: InstrumentRack::default(),
+            drum_rack: DrumRack::default(),
+            sends: vec![],
+            fader_host_id: None,
+            midi_track_host_id: None,
+            timeline_track_host_id: None,
+            drum_rack_host_id: None,
+            fader_automation_host_id: None,
         }
     }
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ChannelEvent {
     SelectChannel(usize),
     SelectChannelGroup(usize),
+    SelectPreviousChannel,
+    SelectNextChannel,
     AddChannel,
     RemoveChannel,
+    RenameChannel(usize, String),
+    SetChannelColor(usize, Color),
+    /// Route the output of the first channel to the second one.
+    RouteChannel(usize, usize),
+    /// Add a copy of the channel (without its clips) next to it.
+    DuplicateChannel(usize),
+    /// Arm the channel and its arm group, or disarm them if it is armed.
+    ToggleArm(usize),
+    SetExclusiveArm(usize, bool),
+    /// Put the channel in an arm group, or take it out with `None`.
+    SetArmGroup(usize, Option<u32>),
+    /// Put the channels in a new arm group.
+    LinkArm(Vec<usize>),
+    SetRecordInput(usize, RecordInput),
+    /// Play the channel on outputs of the audio device, or through the
+    /// channel it is routed to again with `None`.
+    SetHardwareOutput(usize, Option<HardwareOutput>),
     // DragChannel(usize),
     // DropChannel(usize),
 }
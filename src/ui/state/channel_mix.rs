@@ -0,0 +1,297 @@
+use dropseed::plugin::PluginInstanceID;
+
+use super::{HRackEffectState, RecordInput, UiState};
+use crate::backend::channel_graph::{
+    ChannelNodes, ClipChain, DrumRackNodes, EffectNodes, FxChainNodes, RecordSource, SendNodes,
+};
+use crate::backend::dsp::{db_to_gain, gain_to_db, pan_gains, MIN_DB};
+use crate::backend::fader::{audible_channels, fader_db, fader_pan, SoloState};
+use crate::backend::fader_plug::FaderPlugHandle;
+use crate::backend::graph_interface::PluginHost;
+use crate::util::Unit;
+
+/// A change to the fader of a channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelMixEdit {
+    /// The gain in decibels. The top of the fader is unity gain.
+    SetGain(f32),
+    /// From -1.0 (left) to 1.0 (right).
+    SetPan(f32),
+    SetMuted(bool),
+    SetSoloed(bool),
+}
+
+/// The text of a pan from -1.0 (left) to 1.0 (right), i.e. "75R".
+pub fn pan_display(pan: f32) -> String {
+    let percent = (pan.abs() * 100.0).round();
+    if percent == 0.0 {
+        String::from("0")
+    } else if pan < 0.0 {
+        format!("{}L", percent)
+    } else {
+        format!("{}R", percent)
+    }
+}
+
+/// The normalized gain of the fader of a send at a level in decibels. The
+/// send is centered, and the pan law of the fader turns a centered signal
+/// down, which a send shouldn't do.
+pub fn send_gain_normalized(level_db: f32) -> f64 {
+    let pan_law_db = gain_to_db(pan_gains(0.0).0);
+    f64::from(db_to_gain(level_db - pan_law_db).cbrt())
+}
+
+impl UiState {
+    pub fn edit_channel_mix(&mut self, index: usize, edit: ChannelMixEdit) {
+        let channel = match self.channels.get_mut(index) {
+            Some(channel) => channel,
+            None => return,
+        };
+        match edit {
+            ChannelMixEdit::SetGain(db) => {
+                let db = db.clamp(MIN_DB, 0.0);
+                channel.out_gain_normalized = f64::from(db_to_gain(db).cbrt());
+                channel.out_gain_display = Unit::Decibels.format(f64::from(db));
+            }
+            ChannelMixEdit::SetPan(pan) => {
+                let pan = pan.clamp(-1.0, 1.0);
+                channel.out_pan_normalized = f64::from(pan + 1.0) * 0.5;
+                channel.out_pan_display = pan_display(pan);
+            }
+            ChannelMixEdit::SetMuted(muted) => channel.muted = muted,
+            ChannelMixEdit::SetSoloed(soloed) => channel.soloed = soloed,
+        }
+    }
+
+    /// The gain in decibels and the pan of the fader of a channel.
+    pub fn channel_fader(&self, index: usize) -> Option<(f32, f32)> {
+        let channel = self.channels.get(index)?;
+        Some((fader_db(channel.out_gain_normalized), fader_pan(channel.out_pan_normalized)))
+    }
+
+    /// Whether each channel is heard, taking mute and solo into account.
+    pub fn audible_channels(&self) -> Vec<bool> {
+        let solo_states: Vec<SoloState> = self
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(i, channel)| SoloState {
+                muted: channel.muted,
+                soloed: channel.soloed,
+                outputs: std::iter::once(channel.routed_to)
+                    .chain(channel.sends.iter().map(|send| send.to))
+                    .filter(|to| *to != i)
+                    .collect(),
+            })
+            .collect();
+        audible_channels(&solo_states)
+    }
+
+    /// The host IDs of the faders of the channels.
+    pub fn fader_host_ids(&self) -> Vec<u64> {
+        self.channels.iter().filter_map(|channel| channel.fader_host_id).collect()
+    }
+
+    /// The channels whose fader isn't in the audio graph. Like effects, a
+    /// copy of a channel needs a fader of its own, so only the first channel
+    /// with a host ID counts as hosted.
+    pub fn unhosted_faders(&self, hosted: &[u64]) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut channels = Vec::new();
+        for (index, channel) in self.channels.iter().enumerate() {
+            match channel.fader_host_id {
+                Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                _ => channels.push(index),
+            }
+        }
+        channels
+    }
+
+    /// The host IDs of the faders of the sends of the channels.
+    pub fn send_host_ids(&self) -> Vec<u64> {
+        self.channels
+            .iter()
+            .flat_map(|channel| channel.sends.iter().filter_map(|send| send.host_id))
+            .collect()
+    }
+
+    /// The sends whose fader isn't in the audio graph, as the index of the
+    /// channel and the index of the send. See `unhosted_faders()`.
+    pub fn unhosted_sends(&self, hosted: &[u64]) -> Vec<(usize, usize)> {
+        let mut seen = Vec::new();
+        let mut sends = Vec::new();
+        for (index, channel) in self.channels.iter().enumerate() {
+            for (send_index, send) in channel.sends.iter().enumerate() {
+                match send.host_id {
+                    Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                    _ => sends.push((index, send_index)),
+                }
+            }
+        }
+        sends
+    }
+
+    /// The plugins of each channel that are in the audio graph, to connect
+    /// them with. Bypassed effects are left out of the chain, but are still
+    /// automated. While stems are exported, each channel other than the
+    /// master has a pair of the `out_channels` of the graph of its own.
+    /// Otherwise the channels with hardware outputs play on theirs.
+    /// Clips with effects of their own play through them on their channel.
+    /// Armed channels play their record input into the record plugin.
+    pub fn channel_nodes(
+        &self,
+        plugin_host: &PluginHost,
+        out_channels: u16,
+    ) -> Vec<ChannelNodes<PluginInstanceID>> {
+        let plugin_id = |id: Option<u64>| id.and_then(|id| plugin_host.plugin_id(id)).cloned();
+        let effect_ids = |effects: &[HRackEffectState]| -> Vec<PluginInstanceID> {
+            effects
+                .iter()
+                .filter_map(|effect| match effect {
+                    HRackEffectState::External(effect) if !effect.bypassed => {
+                        plugin_id(effect.host_id)
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        // Racks get the chains whose fader is in the graph, and racks in
+        // racks are left out.
+        let channel_effects = |effects: &[HRackEffectState]| -> Vec<EffectNodes<PluginInstanceID>> {
+            effects
+                .iter()
+                .filter_map(|effect| match effect {
+                    HRackEffectState::External(effect) if !effect.bypassed => {
+                        plugin_id(effect.host_id).map(EffectNodes::Plugin)
+                    }
+                    HRackEffectState::Rack(rack) => {
+                        let chains: Vec<FxChainNodes<PluginInstanceID>> = rack
+                            .chains
+                            .iter()
+                            .filter_map(|chain| {
+                                Some(FxChainNodes {
+                                    effects: effect_ids(&chain.effects),
+                                    mix: plugin_id(chain.mix_host_id)?,
+                                })
+                            })
+                            .collect();
+                        (!chains.is_empty()).then(|| EffectNodes::Rack(chains))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        let clip_chains = self.clip_chains();
+        let exporting = self.export_progress.is_some();
+        let hardware_out_pairs = self.hardware_out_pairs();
+
+        self.channels
+            .iter()
+            .zip(self.midi_channels())
+            .zip(self.audio_channels())
+            .zip(self.drum_channels())
+            .enumerate()
+            .map(|(index, (((channel, midi), audio), drums))| {
+                let stem = (index * 2) as u16;
+                ChannelNodes {
+                    fader: plugin_id(channel.fader_host_id),
+                    notes: plugin_id(channel.midi_track_host_id.filter(|_| midi)),
+                    clips: plugin_id(channel.timeline_track_host_id.filter(|_| audio)),
+                    clip_chains: clip_chains
+                        .iter()
+                        .map(|clip| &self.clips[*clip])
+                        .filter(|clip| clip.channel == index)
+                        .filter_map(|clip| {
+                            Some(ClipChain {
+                                player: plugin_id(clip.player_host_id)?,
+                                effects: effect_ids(&clip.effects),
+                            })
+                        })
+                        .collect(),
+                    drums: plugin_id(channel.drum_rack_host_id.filter(|_| drums)).map(|plugin| {
+                        DrumRackNodes { plugin, outputs: channel.drum_rack.output_channels() }
+                    }),
+                    effects: channel_effects(&channel.effects),
+                    routed_to: channel.routed_to,
+                    sends: channel
+                        .sends
+                        .iter()
+                        .filter_map(|send| {
+                            Some(SendNodes {
+                                node: plugin_id(send.host_id)?,
+                                to: send.to,
+                                pre_fader: send.pre_fader,
+                            })
+                        })
+                        .collect(),
+                    stem: (exporting && index != 0 && stem + 2 <= out_channels).then(|| stem),
+                    hardware_out: hardware_out_pairs[index]
+                        .filter(|first| !exporting && first + 2 <= out_channels),
+                    automation: self
+                        .automated_plugins(index)
+                        .into_iter()
+                        .filter_map(|plugin| {
+                            let automation = self.automation_host_id(index, plugin).flatten();
+                            let target = self.automated_plugin_host_id(index, plugin);
+                            Some((plugin_id(automation)?, plugin_id(target)?))
+                        })
+                        .collect(),
+                    record: channel.armed.then(|| match channel.record_input {
+                        RecordInput::Device { left, right } => RecordSource::Device {
+                            left: u16::try_from(left).unwrap_or(u16::MAX),
+                            right: u16::try_from(right).unwrap_or(u16::MAX),
+                        },
+                        RecordInput::Bus(bus) => RecordSource::Bus(bus),
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    /// The fader at the end of the master channel, if it is in the graph.
+    pub fn master_fader<'a>(
+        &self,
+        plugin_host: &'a mut PluginHost,
+    ) -> Option<&'a mut FaderPlugHandle> {
+        let id = self.channels.first()?.fader_host_id?;
+        plugin_host.handle_mut(id)?.internal.as_mut()?.downcast_mut::<FaderPlugHandle>()
+    }
+
+    /// Send the gain and pan of each channel to its fader, and whether it is
+    /// heard. The faders of the sends get the level of the send, and those of
+    /// the chains of racks the mix of the chain.
+    pub fn sync_faders(&self, plugin_host: &mut PluginHost) {
+        let audible = self.audible_channels();
+        for (index, channel) in self.channels.iter().enumerate() {
+            let mut set = |host_id: Option<u64>, gain: f64, pan: f64, audible: bool| {
+                let handle = match host_id.and_then(|id| plugin_host.handle_mut(id)) {
+                    Some(handle) => handle,
+                    None => return,
+                };
+                if let Some(fader) =
+                    handle.internal.as_mut().and_then(|h| h.downcast_mut::<FaderPlugHandle>())
+                {
+                    fader.set(gain, pan, audible);
+                }
+            };
+            set(
+                channel.fader_host_id,
+                channel.out_gain_normalized,
+                channel.out_pan_normalized,
+                audible[index],
+            );
+            for send in channel.sends.iter() {
+                set(send.host_id, send_gain_normalized(send.level_db), 0.5, audible[index]);
+            }
+            let racks = channel.effects.iter().filter_map(|effect| match effect {
+                HRackEffectState::Rack(rack) => Some(rack),
+                _ => None,
+            });
+            for chain in racks.flat_map(|rack| rack.chains.iter()) {
+                let gain = f64::from(db_to_gain(chain.mix.gain_db).cbrt());
+                let pan = f64::from(chain.mix.pan + 1.0) * 0.5;
+                set(chain.mix_host_id, gain, pan, !chain.mix.muted);
+            }
+        }
+    }
+}
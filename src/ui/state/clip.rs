@@ -1,4 +1,14 @@
-use super::core_types::{WMusicalTime, WSeconds, WSuperFrames};
+use super::core_types::{musical_from_beats, WMusicalTime, WSeconds, WSuperFrames};
+use super::{HRackEffectState, TimelineGridState, UiState};
+use crate::backend::dsp::db_to_gain;
+use crate::backend::graph_interface::PluginHost;
+use crate::backend::midi_track_plug::{MidiTrackPlugHandle, TrackNote};
+use crate::backend::resource_loader::{PcmKey, ResampleQuality, ResourceLoader};
+use crate::backend::timeline_track::{TimelineTrackPlugHandle, TrackClip};
+use meadowlark_core_types::time::{Frames, SampleRate};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use std::path::PathBuf;
 use vizia::prelude::*;
 
 #[derive(Debug, Lens, Clone, Data)]
@@ -9,18 +19,234 @@ pub struct ClipState {
 
     pub channel: usize,
 
+    /// A muted clip stays on the timeline but is not played.
+    pub muted: bool,
+
+    /// Effects that only process this clip while it plays, before the inserts
+    /// of its channel.
+    pub effects: Vec<HRackEffectState>,
+
+    /// How the clip was recorded, or `None` if it wasn't.
+    pub recording: Option<RecordingInfo>,
+
+    /// The timeline track plugin that only plays this clip, through its
+    /// effects, or `None` if it hasn't been added to the graph. Only audio
+    /// clips with effects have one.
+    pub player_host_id: Option<u64>,
+
     pub type_: ClipType,
 }
 
+/// The context a clip was recorded in, stamped on it when it is created so
+/// takes can be told apart and sorted out after the session.
+#[derive(Debug, Lens, Clone, PartialEq, Data, Serialize, Deserialize)]
+pub struct RecordingInfo {
+    /// The input that was recorded.
+    pub source: String,
+    /// The number of the take on its lane, starting at 1.
+    pub take: u32,
+    /// The tempo of the project at the time of recording.
+    pub bpm: f64,
+    /// The local date and time of the recording.
+    pub date: String,
+}
+
+/// What to do with the silent parts of a clip when stripping silence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripSilenceMode {
+    /// The silent parts are removed from the timeline.
+    Delete,
+    /// The silent parts are split into their own muted clips.
+    Mute,
+}
+
+impl ClipState {
+    /// This clip after channels were removed, given the new index of each
+    /// old channel, or `None` if its channel was removed. The clips inside a
+    /// container whose channel was removed move to the channel of the
+    /// container, which they play on anyway.
+    pub fn with_new_channels(&self, new_index: &[Option<usize>]) -> Option<ClipState> {
+        let channel = new_index.get(self.channel).copied().flatten()?;
+        let mut clip = self.clone();
+        clip.move_to_new_channels(new_index, channel);
+        Some(clip)
+    }
+
+    fn move_to_new_channels(&mut self, new_index: &[Option<usize>], fallback: usize) {
+        self.channel = new_index.get(self.channel).copied().flatten().unwrap_or(fallback);
+        let channel = self.channel;
+        if let ClipType::Container(container) = &mut self.type_ {
+            for clip in container.clips.iter_mut() {
+                clip.move_to_new_channels(new_index, channel);
+            }
+        }
+    }
+
+    /// The clips that play on the timeline for this clip. This is the clip
+    /// itself, or for a container the clips inside it (and inside containers
+    /// within it) placed on the timeline. These play on the channel of the
+    /// container.
+    pub fn resolve(&self) -> Vec<ClipState> {
+        if !matches!(self.type_, ClipType::Container(_)) {
+            return vec![self.clone()];
+        }
+
+        self.container_contents()
+            .into_iter()
+            .flat_map(|mut clip| {
+                clip.channel = self.channel;
+                clip.muted |= self.muted;
+                clip.resolve()
+            })
+            .collect()
+    }
+
+    /// The clips inside this container, placed on the timeline the container
+    /// is on. Clips that start before the content offset or after the end of
+    /// the container are left out, and clips that run past the end are cut
+    /// short.
+    ///
+    /// Returns an empty list if this is not a container or it is not on the
+    /// timeline.
+    pub fn container_contents(&self) -> Vec<ClipState> {
+        let (container, on_lane) = match (&self.type_, &self.timeline_start) {
+            (ClipType::Container(container), ClipStart::OnLane(on_lane)) => (container, on_lane),
+            _ => return Vec::new(),
+        };
+
+        let start = on_lane.timeline_start.get().as_beats_f64();
+        let offset = container.content_offset.get().as_beats_f64();
+        let length = self.length.get().as_beats_f64();
+
+        container
+            .clips
+            .iter()
+            .filter_map(|clip| {
+                let inner = match &clip.timeline_start {
+                    ClipStart::OnLane(inner) => inner,
+                    ClipStart::NotInTimeline => return None,
+                };
+                let inner_start = inner.timeline_start.get().as_beats_f64() - offset;
+                if inner_start < 0.0 || inner_start >= length {
+                    return None;
+                }
+
+                let mut clip = clip.clone();
+                clip.timeline_start = ClipStart::OnLane(OnLane {
+                    lane_index: on_lane.lane_index + inner.lane_index,
+                    timeline_start: musical_from_beats(start + inner_start).into(),
+                });
+                clip.length =
+                    musical_from_beats(clip.length.get().as_beats_f64().min(length - inner_start))
+                        .into();
+                Some(clip)
+            })
+            .collect()
+    }
+
+    /// Split this audio clip at the boundaries of the given non-silent regions
+    /// of its PCM resource (in frames).
+    ///
+    /// Returns `None` if this is not an audio clip.
+    pub fn strip_silence(
+        &self,
+        regions: &[Range<usize>],
+        mode: StripSilenceMode,
+        sample_rate: SampleRate,
+        timeline_grid: &TimelineGridState,
+    ) -> Option<Vec<ClipState>> {
+        let audio = match &self.type_ {
+            ClipType::Audio(audio) => audio,
+            _ => return None,
+        };
+
+        // A clip that isn't on the timeline is measured as if it started at
+        // the start of the project.
+        let start_beats = match &self.timeline_start {
+            ClipStart::OnLane(on_lane) => on_lane.timeline_start.get().as_beats_f64(),
+            ClipStart::NotInTimeline => 0.0,
+        };
+        let start_secs = timeline_grid.beats_to_seconds(start_beats);
+        let length_secs =
+            timeline_grid.length_to_seconds(start_beats, self.length.get().as_beats_f64());
+
+        let clip_start =
+            audio.clip_start_offset.get().to_nearest_frame_round(sample_rate).0 as usize;
+        let clip_end = clip_start + (length_secs * sample_rate.0).round() as usize;
+
+        // The pieces of the clip that will remain, and whether or not they are muted.
+        let mut pieces: Vec<(Range<usize>, bool)> = Vec::new();
+        let mut pos = clip_start;
+        for r in regions {
+            let r = r.start.max(clip_start)..r.end.min(clip_end);
+            if r.start >= r.end {
+                continue;
+            }
+
+            if mode == StripSilenceMode::Mute && r.start > pos {
+                pieces.push((pos..r.start, true));
+            }
+            pos = r.end;
+            pieces.push((r, false));
+        }
+        if mode == StripSilenceMode::Mute && pos < clip_end {
+            pieces.push((pos..clip_end, true));
+        }
+
+        let frames_to_secs = |frames: usize| Frames(frames as u64).to_seconds(sample_rate).0;
+
+        let new_clips = pieces
+            .into_iter()
+            .map(|(piece, muted)| {
+                let piece_start_secs = start_secs + frames_to_secs(piece.start - clip_start);
+                let piece_start_beats = timeline_grid.seconds_to_beats(piece_start_secs);
+                let piece_length_beats = timeline_grid
+                    .length_to_beats(piece_start_beats, frames_to_secs(piece.end - piece.start));
+
+                let timeline_start = match &self.timeline_start {
+                    ClipStart::OnLane(on_lane) => ClipStart::OnLane(OnLane {
+                        lane_index: on_lane.lane_index,
+                        timeline_start: musical_from_beats(piece_start_beats).into(),
+                    }),
+                    ClipStart::NotInTimeline => ClipStart::NotInTimeline,
+                };
+
+                ClipState {
+                    name: self.name.clone(),
+                    timeline_start,
+                    length: musical_from_beats(piece_length_beats).into(),
+                    channel: self.channel,
+                    muted: muted || self.muted,
+                    effects: self.effects.clone(),
+                    recording: self.recording.clone(),
+                    player_host_id: None,
+                    type_: ClipType::Audio(AudioClipState {
+                        clip_start_offset: Frames(piece.start as u64)
+                            .to_super_frames(sample_rate)
+                            .into(),
+                        ..audio.clone()
+                    }),
+                }
+            })
+            .collect();
+
+        Some(new_clips)
+    }
+}
+
 #[derive(Debug, Lens, Clone, Data)]
 pub enum ClipType {
     Audio(AudioClipState),
     PianoRoll(PianoRollClipState),
     Automation(AutomationClipState),
+    Container(ContainerClipState),
 }
 
 #[derive(Debug, Lens, Clone, Data)]
 pub struct AudioClipState {
+    /// The gain of the clip in decibels.
+    pub gain_db: f32,
+
     pub fade_in_secs: WSeconds,
 
     pub fade_out_secs: WSeconds,
@@ -30,12 +256,48 @@ pub struct AudioClipState {
     ///
     /// TODO
     pub clip_start_offset: WSuperFrames,
-    // TODO: pointer to waveform data
+
+    /// The path to the original audio file of this clip.
+    pub pcm_path: PathBuf,
+
+    /// Rendered versions of the original audio file (i.e. denoised). The
+    /// original file is never modified.
+    pub alternate_takes: Vec<PathBuf>,
+
+    /// The index into `alternate_takes` of the take that is played, or `None`
+    /// to play the original file.
+    pub active_take: Option<usize>,
+}
+
+impl AudioClipState {
+    /// The path to the audio file that is played for this clip.
+    pub fn active_pcm_path(&self) -> &PathBuf {
+        self.active_take.and_then(|i| self.alternate_takes.get(i)).unwrap_or(&self.pcm_path)
+    }
 }
 
 #[derive(Debug, Lens, Clone, Data)]
 pub struct PianoRollClipState {
-    // TODO
+    /// The notes of the clip, sorted by their start.
+    pub notes: Vec<PianoRollNote>,
+
+    /// Clips with the same link are aliases of each other and always have the
+    /// same notes. See `UiState::set_clip_notes()`.
+    pub link: Option<u32>,
+}
+
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct PianoRollNote {
+    /// The start of the note relative to the start of the clip.
+    pub start: WMusicalTime,
+    pub length: WMusicalTime,
+    /// The MIDI note number.
+    pub key: i16,
+    /// The velocity in the range `[0.0, 1.0]`.
+    pub velocity: f32,
+    /// Where the note was played, if it was moved by input quantize. See
+    /// `InputQuantize`.
+    pub recorded_start: Option<WMusicalTime>,
 }
 
 #[derive(Debug, Lens, Clone, Data)]
@@ -43,6 +305,18 @@ pub struct AutomationClipState {
     // TODO
 }
 
+/// A clip that holds its own small timeline of clips, which play together as
+/// a single clip on the timeline it is placed on.
+#[derive(Debug, Lens, Clone, Data)]
+pub struct ContainerClipState {
+    /// The clips inside the container. Their lanes and starts are relative to
+    /// the first lane and the start of the container's content.
+    pub clips: Vec<ClipState>,
+
+    /// How far into its content this instance of the container starts.
+    pub content_offset: WMusicalTime,
+}
+
 #[derive(Debug, Lens, Clone, Data)]
 pub enum ClipStart {
     OnLane(OnLane),
@@ -53,6 +327,530 @@ pub enum ClipStart {
 
 #[derive(Debug, Lens, Clone, Data)]
 pub struct OnLane {
-    lane_index: u32,
-    timeline_start: WMusicalTime,
+    pub lane_index: u32,
+    pub timeline_start: WMusicalTime,
+}
+
+impl UiState {
+    /// The range (in beats) from the start of the first to the end of the last
+    /// clip on the selected lanes. If there are none, this is the range of the
+    /// selected section of the arranger instead.
+    pub fn selection_range(&self) -> Option<Range<f64>> {
+        let lanes = self.timeline_grid.lane_states.lane_indices(|lane| lane.selected);
+        let range = self
+            .clips
+            .iter()
+            .filter_map(|clip| match &clip.timeline_start {
+                ClipStart::OnLane(on_lane) if lanes.contains(&(on_lane.lane_index as usize)) => {
+                    let start = on_lane.timeline_start.get().as_beats_f64();
+                    Some(start..start + clip.length.get().as_beats_f64())
+                }
+                _ => None,
+            })
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end));
+
+        range.or_else(|| {
+            let selected = self.arranger.selected?;
+            self.arranger.section_ranges().get(selected).cloned()
+        })
+    }
+
+    /// The number of the next take recorded on a lane, counting the recorded
+    /// clips on all of its playlists.
+    pub fn next_take(&self, lane: usize) -> u32 {
+        let on_lane = self.clips.iter().filter(|clip| match &clip.timeline_start {
+            ClipStart::OnLane(on_lane) => on_lane.lane_index as usize == lane,
+            ClipStart::NotInTimeline => false,
+        });
+        let in_playlists = self
+            .timeline_grid
+            .lane_states
+            .lanes
+            .get(lane)
+            .into_iter()
+            .flat_map(|lane| lane.playlists.iter().flat_map(|playlist| playlist.clips.iter()));
+
+        on_lane
+            .chain(in_playlists)
+            .filter_map(|clip| clip.recording.as_ref().map(|recording| recording.take))
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// Put the given clips into a new container clip in their place. The
+    /// container starts at the earliest of the clips, on the top-most lane of
+    /// them, and plays on the channel of the first one.
+    ///
+    /// Clips that are not on the timeline are left alone.
+    pub fn group_clips(&mut self, indices: &[usize], name: String) {
+        let grouped: Vec<(usize, u32, f64)> = indices
+            .iter()
+            .filter_map(|i| match &self.clips.get(*i)?.timeline_start {
+                ClipStart::OnLane(on_lane) => {
+                    Some((*i, on_lane.lane_index, on_lane.timeline_start.get().as_beats_f64()))
+                }
+                ClipStart::NotInTimeline => None,
+            })
+            .collect();
+        let first_lane = match grouped.iter().map(|(_, lane, _)| *lane).min() {
+            Some(lane) => lane,
+            None => return,
+        };
+        let start = grouped.iter().map(|(_, _, start)| *start).fold(f64::MAX, f64::min);
+        let end = grouped
+            .iter()
+            .map(|(i, _, start)| start + self.clips[*i].length.get().as_beats_f64())
+            .fold(start, f64::max);
+
+        let clips = grouped
+            .iter()
+            .map(|(i, lane, clip_start)| {
+                let mut clip = self.clips[*i].clone();
+                clip.timeline_start = ClipStart::OnLane(OnLane {
+                    lane_index: lane - first_lane,
+                    timeline_start: musical_from_beats(clip_start - start).into(),
+                });
+                clip
+            })
+            .collect();
+        let channel = self.clips[grouped[0].0].channel;
+
+        let grouped: Vec<usize> = grouped.iter().map(|(i, ..)| *i).collect();
+        self.remove_clips(&grouped);
+
+        self.clips.push(ClipState {
+            name,
+            timeline_start: ClipStart::OnLane(OnLane {
+                lane_index: first_lane,
+                timeline_start: musical_from_beats(start).into(),
+            }),
+            length: musical_from_beats(end - start).into(),
+            channel,
+            muted: false,
+            effects: Vec::new(),
+            recording: None,
+            player_host_id: None,
+            type_: ClipType::Container(ContainerClipState {
+                clips,
+                content_offset: musical_from_beats(0.0).into(),
+            }),
+        });
+    }
+
+    /// Replace a container with the clips inside it, placed where they play.
+    pub fn unpack_container(&mut self, index: usize) {
+        let contents = match self.clips.get(index) {
+            Some(clip @ ClipState { type_: ClipType::Container(_), .. }) => {
+                clip.container_contents()
+            }
+            _ => return,
+        };
+
+        self.remove_clips(&[index]);
+        self.clips.extend(contents);
+    }
+
+    /// Set how far into its content a container starts (in beats).
+    pub fn set_container_offset(&mut self, index: usize, beats: f64) {
+        if let Some(ClipType::Container(container)) =
+            self.clips.get_mut(index).map(|clip| &mut clip.type_)
+        {
+            container.content_offset = musical_from_beats(beats).into();
+        }
+    }
+
+    /// Make sure a piano roll clip has a link, so copies of it become aliases.
+    ///
+    /// Returns `false` if the clip is not a piano roll clip.
+    pub fn link_clip(&mut self, index: usize) -> bool {
+        let next_link = self
+            .clips
+            .iter()
+            .filter_map(|clip| match &clip.type_ {
+                ClipType::PianoRoll(piano_roll) => piano_roll.link,
+                _ => None,
+            })
+            .max()
+            .map_or(0, |link| link + 1);
+
+        match self.clips.get_mut(index).map(|clip| &mut clip.type_) {
+            Some(ClipType::PianoRoll(piano_roll)) => {
+                piano_roll.link.get_or_insert(next_link);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove a clip from its link. If only one other clip is left with the
+    /// same link, that one is unlinked too.
+    pub fn unlink_clip(&mut self, index: usize) {
+        let link = match self.clips.get_mut(index).map(|clip| &mut clip.type_) {
+            Some(ClipType::PianoRoll(piano_roll)) => match piano_roll.link.take() {
+                Some(link) => link,
+                None => return,
+            },
+            _ => return,
+        };
+
+        let mut others = self.linked_clips_mut(link);
+        if let (Some(other), None) = (others.next(), others.next()) {
+            other.link = None;
+        }
+    }
+
+    /// Replace the notes of a piano roll clip and every clip linked to it.
+    pub fn set_clip_notes(&mut self, index: usize, notes: Vec<PianoRollNote>) {
+        let link = match self.clips.get_mut(index).map(|clip| &mut clip.type_) {
+            Some(ClipType::PianoRoll(piano_roll)) => {
+                piano_roll.notes = notes.clone();
+                piano_roll.link
+            }
+            _ => return,
+        };
+
+        if let Some(link) = link {
+            for piano_roll in self.linked_clips_mut(link) {
+                piano_roll.notes = notes.clone();
+            }
+        }
+    }
+
+    /// Whether each channel needs a `MidiTrackPlug`, to play its MIDI clips
+    /// or to process the notes coming in.
+    pub fn midi_channels(&self) -> Vec<bool> {
+        let mut midi: Vec<bool> =
+            self.channels.iter().map(|channel| !channel.midi_input.is_identity()).collect();
+        for clip in self.clips.iter().flat_map(|clip| clip.resolve()) {
+            if let (ClipType::PianoRoll(_), Some(midi)) = (&clip.type_, midi.get_mut(clip.channel))
+            {
+                *midi = true;
+            }
+        }
+        midi
+    }
+
+    /// The host IDs of the MIDI track plugins of the channels that need one.
+    pub fn midi_track_host_ids(&self) -> Vec<u64> {
+        self.channels
+            .iter()
+            .zip(self.midi_channels())
+            .filter_map(|(channel, midi)| channel.midi_track_host_id.filter(|_| midi))
+            .collect()
+    }
+
+    /// The channels that need a MIDI track plugin that isn't in the audio
+    /// graph. Like faders, a copy of a channel needs one of its own.
+    pub fn unhosted_midi_tracks(&self, hosted: &[u64]) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut channels = Vec::new();
+        for (index, midi) in self.midi_channels().into_iter().enumerate() {
+            if !midi {
+                continue;
+            }
+            match self.channels[index].midi_track_host_id {
+                Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                _ => channels.push(index),
+            }
+        }
+        channels
+    }
+
+    /// Send the notes and the MIDI input processing of each channel to its
+    /// MIDI track plugin.
+    pub fn sync_midi_tracks(&self, plugin_host: &mut PluginHost) {
+        for (index, midi) in self.midi_channels().into_iter().enumerate() {
+            let host_id = self.channels[index].midi_track_host_id.filter(|_| midi);
+            let handle = match host_id.and_then(|id| plugin_host.handle_mut(id)) {
+                Some(handle) => handle,
+                None => continue,
+            };
+            if let Some(track) =
+                handle.internal.as_mut().and_then(|h| h.downcast_mut::<MidiTrackPlugHandle>())
+            {
+                track.set_notes(self.midi_track_notes(index));
+                track.set_midi_input(&self.channels[index].midi_input);
+            }
+        }
+    }
+
+    /// The notes a channel plays on the timeline, for its `MidiTrackPlug`.
+    /// Notes of muted clips, of clips on disabled lanes and past the end of
+    /// their clip are left out.
+    pub fn midi_track_notes(&self, channel: usize) -> Vec<TrackNote> {
+        let lanes = &self.timeline_grid.lane_states.lanes;
+
+        let mut notes = Vec::new();
+        for clip in self.clips.iter().flat_map(ClipState::resolve) {
+            let (piano_roll, on_lane) = match (&clip.type_, &clip.timeline_start) {
+                (ClipType::PianoRoll(piano_roll), ClipStart::OnLane(on_lane)) => {
+                    (piano_roll, on_lane)
+                }
+                _ => continue,
+            };
+            let disabled = lanes.get(on_lane.lane_index as usize).map_or(false, |l| l.disabled);
+            if clip.channel != channel || clip.muted || disabled {
+                continue;
+            }
+
+            let start = on_lane.timeline_start.get().as_beats_f64();
+            let length = clip.length.get().as_beats_f64();
+            notes.extend(piano_roll.notes.iter().filter_map(|note| {
+                let note_start = note.start.get().as_beats_f64();
+                if note_start >= length {
+                    return None;
+                }
+                let note_end = (note_start + note.length.get().as_beats_f64()).min(length);
+                Some(TrackNote {
+                    start_beats: start + note_start,
+                    end_beats: start + note_end,
+                    channel: 0,
+                    key: note.key,
+                    velocity: note.velocity,
+                })
+            }));
+        }
+        notes
+    }
+
+    /// The clips that play through effects of their own. Those are the clips
+    /// with effects that play audio. The effects of a MIDI clip would have to
+    /// go before the instrument that plays it, which is an effect of the
+    /// channel, so they are left out.
+    pub fn clip_chains(&self) -> Vec<usize> {
+        (0..self.clips.len())
+            .filter(|index| {
+                let clip = &self.clips[*index];
+                !clip.effects.is_empty()
+                    && clip.resolve().iter().any(|clip| matches!(clip.type_, ClipType::Audio(_)))
+            })
+            .collect()
+    }
+
+    /// Whether each channel has audio clips that play through its
+    /// `TimelineTrackPlug`, i.e. audio clips without effects of their own.
+    pub fn audio_channels(&self) -> Vec<bool> {
+        let chains = self.clip_chains();
+        let mut audio = vec![false; self.channels.len()];
+        for (index, clip) in self.clips.iter().enumerate() {
+            if chains.contains(&index) {
+                continue;
+            }
+            for clip in clip.resolve() {
+                if let (ClipType::Audio(_), Some(audio)) =
+                    (&clip.type_, audio.get_mut(clip.channel))
+                {
+                    *audio = true;
+                }
+            }
+        }
+        audio
+    }
+
+    /// The host IDs of the timeline track plugins of the channels that have
+    /// audio clips, and of the clips with effects of their own.
+    pub fn timeline_track_host_ids(&self) -> Vec<u64> {
+        let mut ids: Vec<u64> = self
+            .channels
+            .iter()
+            .zip(self.audio_channels())
+            .filter_map(|(channel, audio)| channel.timeline_track_host_id.filter(|_| audio))
+            .collect();
+        ids.extend(self.clip_chains().into_iter().filter_map(|i| self.clips[i].player_host_id));
+        ids
+    }
+
+    /// The channels with audio clips whose timeline track plugin isn't in the
+    /// audio graph. See `unhosted_midi_tracks()`.
+    pub fn unhosted_timeline_tracks(&self, hosted: &[u64]) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut channels = Vec::new();
+        for (index, audio) in self.audio_channels().into_iter().enumerate() {
+            if !audio {
+                continue;
+            }
+            match self.channels[index].timeline_track_host_id {
+                Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                _ => channels.push(index),
+            }
+        }
+        channels
+    }
+
+    /// The clips with effects of their own whose timeline track plugin isn't
+    /// in the audio graph. A copy of a clip needs one of its own.
+    pub fn unhosted_clip_players(&self, hosted: &[u64]) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut clips = Vec::new();
+        for index in self.clip_chains() {
+            match self.clips[index].player_host_id {
+                Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                _ => clips.push(index),
+            }
+        }
+        clips
+    }
+
+    /// Send the audio clips of each channel to its timeline track plugin, and
+    /// each clip with effects of its own to its own plugin. The audio of the
+    /// clips is loaded if it wasn't already.
+    pub fn sync_timeline_tracks(
+        &self,
+        plugin_host: &mut PluginHost,
+        resource_loader: &mut ResourceLoader,
+        resample_quality: ResampleQuality,
+    ) {
+        let mut set_clips = |host_id: Option<u64>, clips: &[ClipState]| {
+            let handle = match host_id.and_then(|id| plugin_host.handle_mut(id)) {
+                Some(handle) => handle,
+                None => return,
+            };
+            if let Some(track) =
+                handle.internal.as_mut().and_then(|h| h.downcast_mut::<TimelineTrackPlugHandle>())
+            {
+                track.set_clips(self.track_clips(clips, resource_loader, resample_quality));
+            }
+        };
+
+        let chains = self.clip_chains();
+        for (index, audio) in self.audio_channels().into_iter().enumerate() {
+            if !audio {
+                continue;
+            }
+            let clips: Vec<ClipState> = self
+                .clips
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !chains.contains(i))
+                .flat_map(|(_, clip)| clip.resolve())
+                .filter(|clip| clip.channel == index)
+                .collect();
+            set_clips(self.channels[index].timeline_track_host_id, &clips);
+        }
+        for index in chains {
+            let clip = &self.clips[index];
+            set_clips(clip.player_host_id, &clip.resolve());
+        }
+    }
+
+    /// The audio clips that play on the timeline for a `TimelineTrackPlug`.
+    /// Muted clips and clips on disabled lanes are left out.
+    fn track_clips(
+        &self,
+        clips: &[ClipState],
+        resource_loader: &mut ResourceLoader,
+        resample_quality: ResampleQuality,
+    ) -> Vec<TrackClip> {
+        let lanes = &self.timeline_grid.lane_states.lanes;
+        let sample_rate = resource_loader.project_sr();
+        let to_frame = |secs: f64| (secs * sample_rate.0).round().max(0.0) as u64;
+
+        let mut track_clips = Vec::new();
+        for clip in clips.iter() {
+            let (audio, on_lane) = match (&clip.type_, &clip.timeline_start) {
+                (ClipType::Audio(audio), ClipStart::OnLane(on_lane)) => (audio, on_lane),
+                _ => continue,
+            };
+            let key = PcmKey {
+                path: audio.active_pcm_path().clone(),
+                resample_to_project_sr: true,
+                resample_quality,
+            };
+            let (pcm, res) = resource_loader.load_pcm(&key);
+            if res.is_err() {
+                continue;
+            }
+            // For drawing the clip on the timeline, which muted clips are too.
+            resource_loader.request_waveform_peaks(&key);
+            let disabled = lanes.get(on_lane.lane_index as usize).map_or(false, |l| l.disabled);
+            if clip.muted || disabled {
+                continue;
+            }
+
+            let start_beats = on_lane.timeline_start.get().as_beats_f64();
+            let end_beats = start_beats + clip.length.get().as_beats_f64();
+            track_clips.push(TrackClip {
+                start_frame: to_frame(self.timeline_grid.beats_to_seconds(start_beats)),
+                end_frame: to_frame(self.timeline_grid.beats_to_seconds(end_beats)),
+                pcm_start_frame: audio
+                    .clip_start_offset
+                    .get()
+                    .to_nearest_frame_round(sample_rate)
+                    .0,
+                gain: db_to_gain(audio.gain_db),
+                fade_in_frames: to_frame(audio.fade_in_secs.get().0),
+                fade_out_frames: to_frame(audio.fade_out_secs.get().0),
+                pcm,
+            });
+        }
+        track_clips
+    }
+
+    fn linked_clips_mut(&mut self, link: u32) -> impl Iterator<Item = &mut PianoRollClipState> {
+        self.clips.iter_mut().filter_map(move |clip| match &mut clip.type_ {
+            ClipType::PianoRoll(piano_roll) if piano_roll.link == Some(link) => Some(piano_roll),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meadowlark_core_types::time::MusicalTime;
+
+    fn clip(channel: usize, type_: ClipType) -> ClipState {
+        ClipState {
+            name: String::new(),
+            timeline_start: ClipStart::NotInTimeline,
+            length: MusicalTime::new(1, 0).into(),
+            channel,
+            muted: false,
+            effects: Vec::new(),
+            recording: None,
+            player_host_id: None,
+            type_,
+        }
+    }
+
+    fn automation(channel: usize) -> ClipState {
+        clip(channel, ClipType::Automation(AutomationClipState {}))
+    }
+
+    fn container(channel: usize, clips: Vec<ClipState>) -> ClipState {
+        clip(
+            channel,
+            ClipType::Container(ContainerClipState {
+                clips,
+                content_offset: MusicalTime::new(0, 0).into(),
+            }),
+        )
+    }
+
+    fn channels(clip: &ClipState) -> Vec<usize> {
+        let mut channels = vec![clip.channel];
+        if let ClipType::Container(container) = &clip.type_ {
+            channels.extend(container.clips.iter().flat_map(channels));
+        }
+        channels
+    }
+
+    #[test]
+    fn clips_inside_containers_follow_their_channels() {
+        // Channel 1 of 4 is removed.
+        let new_index = [Some(0), None, Some(1), Some(2)];
+
+        assert!(automation(1).with_new_channels(&new_index).is_none());
+        assert_eq!(channels(&automation(3).with_new_channels(&new_index).unwrap()), vec![2]);
+
+        let nested =
+            container(3, vec![automation(2), automation(1), container(1, vec![automation(3)])]);
+        let moved = nested.with_new_channels(&new_index).unwrap();
+        // Clips on the removed channel move to the channel of their
+        // container.
+        assert_eq!(channels(&moved), vec![2, 1, 2, 2, 2]);
+
+        assert!(container(1, vec![automation(2)]).with_new_channels(&new_index).is_none());
+    }
 }
@@ -0,0 +1,410 @@
+use super::{
+    musical_from_beats, ClipStart, ClipState, HRackEffectState, Inspected, LaneState, OnLane,
+    UiState,
+};
+
+/// Something that was copied or cut inside the application.
+///
+/// This lives outside of the project state, so it is kept when another
+/// project is loaded and can be used to move things between projects.
+#[derive(Debug, Clone)]
+pub enum ClipboardContents {
+    /// Clips, with their positions relative to the top-most lane and the
+    /// earliest clip.
+    Clips(Vec<CopiedClip>),
+    /// Whole lanes along with the clips on them. The positions of the clips
+    /// are relative to the first lane and to the start of the timeline.
+    Lanes { lanes: Vec<LaneState>, clips: Vec<CopiedClip> },
+    /// The effects on a channel's effect rack.
+    Effects(Vec<HRackEffectState>),
+}
+
+#[derive(Debug, Clone)]
+pub struct CopiedClip {
+    pub clip: ClipState,
+
+    /// The lane of the clip relative to the first copied lane, or `None` if
+    /// the clip is not on the timeline.
+    pub lane_offset: Option<usize>,
+
+    /// The start of the clip in beats relative to the copied position.
+    pub beats_offset: f64,
+}
+
+/// The lane and start (in beats) of a clip, or `None` if it is not on the
+/// timeline.
+fn clip_position(clip: &ClipState) -> Option<(usize, f64)> {
+    match &clip.timeline_start {
+        ClipStart::OnLane(on_lane) => {
+            Some((on_lane.lane_index as usize, on_lane.timeline_start.get().as_beats_f64()))
+        }
+        ClipStart::NotInTimeline => None,
+    }
+}
+
+impl UiState {
+    /// Copy the given clips. Invalid indices are ignored.
+    pub fn copy_clips(&self, indices: &[usize]) -> Option<ClipboardContents> {
+        let clips: Vec<&ClipState> = indices.iter().filter_map(|i| self.clips.get(*i)).collect();
+        if clips.is_empty() {
+            return None;
+        }
+
+        let positions: Vec<(usize, f64)> =
+            clips.iter().filter_map(|clip| clip_position(clip)).collect();
+        let first_lane = positions.iter().map(|(lane, _)| *lane).min().unwrap_or(0);
+        let first_beats = positions.iter().map(|(_, beats)| *beats).fold(f64::MAX, f64::min);
+
+        Some(ClipboardContents::Clips(
+            clips
+                .into_iter()
+                .map(|clip| {
+                    let position = clip_position(clip);
+                    CopiedClip {
+                        clip: clip.clone(),
+                        lane_offset: position.map(|(lane, _)| lane - first_lane),
+                        beats_offset: position.map(|(_, beats)| beats - first_beats).unwrap_or(0.0),
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    /// Remove the given clips. Invalid indices are ignored.
+    pub fn remove_clips(&mut self, indices: &[usize]) {
+        let mut i = 0;
+        self.clips.retain(|_| {
+            i += 1;
+            !indices.contains(&(i - 1))
+        });
+
+        // Keep inspecting the same clip now that the ones before it moved.
+        if let Some(Inspected::Clip(clip)) = self.inspected {
+            self.inspected = if indices.contains(&clip) {
+                None
+            } else {
+                Some(Inspected::Clip(clip - indices.iter().filter(|i| **i < clip).count()))
+            };
+        }
+    }
+
+    /// Paste clips so that the first lane and the earliest clip line up with
+    /// the given lane and position (in beats). New lanes are added if needed.
+    pub fn paste_clips(&mut self, clips: &[CopiedClip], lane: usize, start_beats: f64) {
+        for copied in clips {
+            self.paste_clip(copied, lane, start_beats);
+        }
+    }
+
+    fn paste_clip(&mut self, copied: &CopiedClip, lane: usize, start_beats: f64) {
+        let mut clip = copied.clip.clone();
+
+        // The clip may come from another project with a different set of channels.
+        if clip.channel >= self.channels.len() {
+            clip.channel = 0;
+        }
+
+        if let Some(lane_offset) = copied.lane_offset {
+            let lane_index = lane + lane_offset;
+            let lane_states = &mut self.timeline_grid.lane_states;
+            while lane_states.lanes.len() <= lane_index {
+                lane_states.push_lane(LaneState::default());
+            }
+
+            clip.timeline_start = ClipStart::OnLane(OnLane {
+                lane_index: lane_index as u32,
+                timeline_start: musical_from_beats(start_beats + copied.beats_offset).into(),
+            });
+
+            let end_beats = start_beats + copied.beats_offset + clip.length.get().as_beats_f64();
+            if end_beats > self.timeline_grid.project_length.get().as_beats_f64() {
+                self.timeline_grid.project_length = musical_from_beats(end_beats).into();
+            }
+            self.timeline_grid.used_lanes = self.timeline_grid.used_lanes.max(lane_index as u32);
+        }
+
+        self.clips.push(clip);
+    }
+
+    /// Copy the selected lanes along with the clips on them.
+    pub fn copy_selected_lanes(&self) -> Option<ClipboardContents> {
+        let lane_states = &self.timeline_grid.lane_states;
+        let selected = lane_states.lane_indices(|lane| lane.selected);
+        if selected.is_empty() {
+            return None;
+        }
+
+        let lanes = selected.iter().map(|i| lane_states.lanes[*i].clone()).collect();
+        let clips = self
+            .clips
+            .iter()
+            .filter_map(|clip| {
+                let (lane, beats) = clip_position(clip)?;
+                // Lanes in between the selected ones are left out, so the
+                // offset is the position among the selected lanes.
+                let lane_offset = selected.iter().position(|i| *i == lane)?;
+                Some(CopiedClip {
+                    clip: clip.clone(),
+                    lane_offset: Some(lane_offset),
+                    beats_offset: beats,
+                })
+            })
+            .collect();
+
+        Some(ClipboardContents::Lanes { lanes, clips })
+    }
+
+    /// Remove the selected lanes along with the clips on them. Clips on the
+    /// lanes below are moved up to stay on the same lanes.
+    pub fn remove_selected_lanes(&mut self) {
+        let selected = self.timeline_grid.lane_states.lane_indices(|lane| lane.selected);
+
+        if matches!(self.inspected, Some(Inspected::Clip(_))) {
+            self.inspected = None;
+        }
+        self.clips.retain(|clip| match &clip.timeline_start {
+            ClipStart::OnLane(on_lane) => !selected.contains(&(on_lane.lane_index as usize)),
+            ClipStart::NotInTimeline => true,
+        });
+        for clip in self.clips.iter_mut() {
+            if let ClipStart::OnLane(on_lane) = &mut clip.timeline_start {
+                let removed_above =
+                    selected.iter().filter(|i| **i < on_lane.lane_index as usize).count();
+                on_lane.lane_index -= removed_above as u32;
+            }
+        }
+
+        self.timeline_grid.lane_states.remove_lanes_in_vec(selected);
+    }
+
+    /// Insert copied lanes and their clips below the last selected lane (or at
+    /// the top if no lane is selected). Clips on the lanes below are moved down
+    /// to stay on the same lanes.
+    pub fn paste_lanes(&mut self, lanes: &[LaneState], clips: &[CopiedClip]) {
+        let lane_states = &mut self.timeline_grid.lane_states;
+        let index = lane_states.last_selected_index().map(|i| i + 1).unwrap_or(0);
+
+        for clip in self.clips.iter_mut() {
+            if let ClipStart::OnLane(on_lane) = &mut clip.timeline_start {
+                if on_lane.lane_index as usize >= index {
+                    on_lane.lane_index += lanes.len() as u32;
+                }
+            }
+        }
+
+        lane_states.unselect_all_lanes();
+        lane_states.insert_lanes(index, lanes.to_vec());
+        for i in index..index + lanes.len() {
+            lane_states.select_lane(i);
+        }
+
+        self.paste_clips(clips, index, 0.0);
+    }
+
+    /// Copy the effects of the given channel.
+    pub fn copy_effects(&self, channel: usize) -> Option<ClipboardContents> {
+        let channel = self.channels.get(channel)?;
+        Some(ClipboardContents::Effects(channel.effects.clone()))
+    }
+
+    /// Append copied effects to the effect rack of every selected channel.
+    /// The pasted plugins get plugin instances of their own, so they start
+    /// from their saved state rather than the current state of the copies.
+    pub fn paste_effects(&mut self, effects: &[HRackEffectState]) {
+        for channel in self.channels.iter_mut().filter(|channel| channel.selected) {
+            channel.effects.extend(effects.iter().cloned().map(|mut effect| {
+                effect.clear_host_ids();
+                effect
+            }));
+        }
+    }
+
+    /// Copy the effects of the given clip.
+    pub fn copy_clip_effects(&self, clip: usize) -> Option<ClipboardContents> {
+        let clip = self.clips.get(clip)?;
+        Some(ClipboardContents::Effects(clip.effects.clone()))
+    }
+
+    /// Append copied effects to the effects of the given clip. Like pasting
+    /// them on a channel, they get plugin instances of their own.
+    pub fn paste_clip_effects(&mut self, clip: usize, effects: &[HRackEffectState]) {
+        if let Some(clip) = self.clips.get_mut(clip) {
+            clip.effects.extend(effects.iter().cloned().map(|mut effect| {
+                effect.clear_host_ids();
+                effect
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::state::{AutomationClipState, ClipType, ExternalEffectState};
+    use meadowlark_core_types::time::MusicalTime;
+
+    fn clip(name: &str, channel: usize, position: Option<(u32, f64)>) -> ClipState {
+        ClipState {
+            name: name.into(),
+            timeline_start: match position {
+                Some((lane_index, beats)) => ClipStart::OnLane(OnLane {
+                    lane_index,
+                    timeline_start: musical_from_beats(beats).into(),
+                }),
+                None => ClipStart::NotInTimeline,
+            },
+            length: MusicalTime::from_beats(2).into(),
+            channel,
+            muted: false,
+            effects: Vec::new(),
+            recording: None,
+            player_host_id: None,
+            type_: ClipType::Automation(AutomationClipState {}),
+        }
+    }
+
+    fn positions(state: &UiState) -> Vec<(&str, Option<(usize, f64)>)> {
+        state.clips.iter().map(|clip| (clip.name.as_str(), clip_position(clip))).collect()
+    }
+
+    fn effect(host_id: u64) -> HRackEffectState {
+        let mut effect = ExternalEffectState::new("Delay".into(), "org.delay".into(), "1".into());
+        effect.host_id = Some(host_id);
+        effect.automation_host_id = Some(host_id + 1);
+        HRackEffectState::External(effect)
+    }
+
+    fn host_ids(effects: &[HRackEffectState]) -> Vec<Option<u64>> {
+        effects
+            .iter()
+            .map(|effect| match effect {
+                HRackEffectState::External(effect) => effect.host_id,
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pasted_clips_keep_their_layout() {
+        let mut state = UiState::for_tests(2, 2);
+        state.clips = vec![
+            clip("a", 1, Some((1, 4.0))),
+            clip("b", 1, Some((0, 6.0))),
+            clip("c", 0, None),
+            clip("d", 0, Some((1, 10.0))),
+        ];
+        let copied = match state.copy_clips(&[0, 1, 2, 7]) {
+            Some(ClipboardContents::Clips(copied)) => copied,
+            _ => panic!("nothing was copied"),
+        };
+        let offsets: Vec<_> = copied.iter().map(|c| (c.lane_offset, c.beats_offset)).collect();
+        assert_eq!(offsets, [(Some(1), 0.0), (Some(0), 2.0), (None, 0.0)]);
+
+        // Lanes are added for the clips that go past the last one, and the
+        // project grows to fit them.
+        state.paste_clips(&copied, 2, 15.0);
+        assert_eq!(
+            positions(&state)[4..],
+            [("a", Some((3, 15.0))), ("b", Some((2, 17.0))), ("c", None)]
+        );
+        assert_eq!(state.timeline_grid.lane_states.lanes.len(), 4);
+        assert_eq!(state.timeline_grid.used_lanes, 3);
+        assert_eq!(state.timeline_grid.project_length.get().as_beats_f64(), 19.0);
+
+        assert!(state.copy_clips(&[9]).is_none());
+    }
+
+    #[test]
+    fn clips_from_projects_with_more_channels_go_to_the_first() {
+        let mut source = UiState::for_tests(4, 1);
+        source.clips = vec![clip("a", 3, Some((0, 0.0)))];
+        let copied = match source.copy_clips(&[0]) {
+            Some(ClipboardContents::Clips(copied)) => copied,
+            _ => panic!("nothing was copied"),
+        };
+
+        let mut state = UiState::for_tests(2, 1);
+        state.paste_clips(&copied, 0, 0.0);
+        assert_eq!(state.clips[0].channel, 0);
+    }
+
+    #[test]
+    fn removing_clips_keeps_the_inspected_one() {
+        let mut state = UiState::for_tests(1, 1);
+        state.clips = ["a", "b", "c", "d"].iter().map(|name| clip(name, 0, None)).collect();
+        state.inspected = Some(Inspected::Clip(3));
+        state.remove_clips(&[0, 2]);
+        assert_eq!(positions(&state), [("b", None), ("d", None)]);
+        assert!(matches!(state.inspected, Some(Inspected::Clip(1))));
+
+        state.remove_clips(&[1]);
+        assert!(state.inspected.is_none());
+    }
+
+    #[test]
+    fn cut_lanes_take_their_clips_with_them() {
+        let mut state = UiState::for_tests(1, 4);
+        state.clips = vec![
+            clip("a", 0, Some((0, 1.0))),
+            clip("b", 0, Some((1, 2.0))),
+            clip("c", 0, Some((2, 3.0))),
+            clip("d", 0, Some((3, 4.0))),
+        ];
+        state.timeline_grid.lane_states.select_lane(1);
+        state.timeline_grid.lane_states.select_lane(3);
+
+        let (lanes, clips) = match state.copy_selected_lanes() {
+            Some(ClipboardContents::Lanes { lanes, clips }) => (lanes, clips),
+            _ => panic!("nothing was copied"),
+        };
+        assert_eq!(lanes.len(), 2);
+        // The lanes between the selected ones are left out.
+        let offsets: Vec<_> = clips.iter().map(|c| (c.lane_offset, c.beats_offset)).collect();
+        assert_eq!(offsets, [(Some(0), 2.0), (Some(1), 4.0)]);
+
+        state.remove_selected_lanes();
+        assert_eq!(positions(&state), [("a", Some((0, 1.0))), ("c", Some((1, 3.0)))]);
+        assert_eq!(state.timeline_grid.lane_states.lanes.len(), 2);
+
+        // Pasted below the selected lane, pushing the lanes below down.
+        state.timeline_grid.lane_states.select_lane(0);
+        state.paste_lanes(&lanes, &clips);
+        assert_eq!(
+            positions(&state),
+            [
+                ("a", Some((0, 1.0))),
+                ("c", Some((3, 3.0))),
+                ("b", Some((1, 2.0))),
+                ("d", Some((2, 4.0))),
+            ]
+        );
+        let lane_states = &state.timeline_grid.lane_states;
+        assert_eq!(lane_states.lanes.len(), 4);
+        assert_eq!(lane_states.lane_indices(|lane| lane.selected), [1, 2]);
+    }
+
+    #[test]
+    fn pasted_effects_get_plugins_of_their_own() {
+        let mut state = UiState::for_tests(3, 1);
+        state.channels[0].effects = vec![effect(10), effect(20)];
+        state.channels[1].selected = true;
+        state.channels[2].selected = true;
+        state.clips = vec![clip("a", 0, None)];
+
+        let effects = match state.copy_effects(0) {
+            Some(ClipboardContents::Effects(effects)) => effects,
+            _ => panic!("nothing was copied"),
+        };
+        state.paste_effects(&effects);
+        state.paste_clip_effects(0, &effects);
+
+        assert_eq!(host_ids(&state.channels[0].effects), [Some(10), Some(20)]);
+        for effects in
+            [&state.channels[1].effects, &state.channels[2].effects, &state.clips[0].effects]
+        {
+            assert_eq!(host_ids(effects), [None, None]);
+        }
+        assert!(state.copy_effects(3).is_none());
+        assert!(state.copy_clip_effects(1).is_none());
+    }
+}
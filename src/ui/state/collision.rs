@@ -0,0 +1,24 @@
+use vizia::prelude::*;
+
+use crate::backend::collision::{CollisionMap, CollisionSettings};
+
+/// The state of the collision analysis panel.
+#[derive(Debug, Lens, Clone)]
+pub struct CollisionState {
+    /// The names of the two channels that were compared, or an empty string
+    /// if nothing has been analyzed yet.
+    pub description: String,
+
+    #[lens(ignore)]
+    pub settings: CollisionSettings,
+
+    /// The result of the last analysis.
+    #[lens(ignore)]
+    pub map: Option<CollisionMap>,
+}
+
+impl Default for CollisionState {
+    fn default() -> Self {
+        Self { description: String::new(), settings: CollisionSettings::default(), map: None }
+    }
+}
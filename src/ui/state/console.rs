@@ -0,0 +1,47 @@
+use vizia::prelude::*;
+
+use super::script;
+
+/// The most lines the console keeps. The oldest lines are dropped first.
+pub const MAX_CONSOLE_LINES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+pub enum ConsoleLineKind {
+    /// A line of a script that was run.
+    Input,
+    Output,
+    Error,
+}
+
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct ConsoleLine {
+    pub text: String,
+    pub kind: ConsoleLineKind,
+}
+
+/// The state of the script console panel.
+#[derive(Debug, Lens, Clone, Default)]
+pub struct ConsoleState {
+    pub lines: Vec<ConsoleLine>,
+
+    /// The script that is being typed.
+    pub input: String,
+
+    /// The signatures of the commands that complete `input`.
+    pub completions: Vec<String>,
+}
+
+impl ConsoleState {
+    pub fn set_input(&mut self, input: String) {
+        self.completions =
+            script::completions(&input).iter().map(|command| command.signature()).collect();
+        self.input = input;
+    }
+
+    pub fn push(&mut self, text: String, kind: ConsoleLineKind) {
+        self.lines.push(ConsoleLine { text, kind });
+        if self.lines.len() > MAX_CONSOLE_LINES {
+            self.lines.drain(..self.lines.len() - MAX_CONSOLE_LINES);
+        }
+    }
+}
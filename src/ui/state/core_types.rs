@@ -59,6 +59,14 @@ impl From<WMusicalTime> for MusicalTime {
     }
 }
 
+/// A position or length in beats as a `MusicalTime`. Beats don't depend on
+/// the tempo, so this is the same wherever the tempo changes. Convert times
+/// in seconds with the tempo map in `TimelineGridState` instead.
+pub fn musical_from_beats(beats: f64) -> MusicalTime {
+    // At 60 BPM a beat lasts exactly one second.
+    Seconds(beats.max(0.0)).to_musical(60.0)
+}
+
 /// A wrapper around `meadowlark_core_types::Seconds` so we can derive
 /// `vizia::Data` on it.
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Data)]
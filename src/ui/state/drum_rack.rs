@@ -0,0 +1,167 @@
+use std::path::PathBuf;
+use vizia::prelude::*;
+
+use super::UiState;
+use crate::backend::drum_rack::{
+    DrumRack, MAX_CHOKE_GROUPS, MAX_DRUM_OUTPUTS, MAX_PAD_CUTOFF_HZ, MAX_PAD_DB, MAX_PAD_TUNE,
+    MIN_PAD_CUTOFF_HZ,
+};
+use crate::backend::drum_rack_plug::{DrumRackPlugHandle, PadPcm};
+use crate::backend::dsp::MIN_DB;
+use crate::backend::graph_interface::PluginHost;
+use crate::backend::midi_transform::{MAX_KEY, MIN_KEY};
+use crate::backend::resource_loader::{PcmKey, ResampleQuality, ResourceLoader};
+
+impl Data for DrumRack {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// A change to one of the pads of the drum rack of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DrumPadEdit {
+    Rename(String),
+    /// Play the pad with a MIDI note number.
+    SetKey(i16),
+    SetSample(Option<PathBuf>),
+    /// The tuning in semitones.
+    SetTune(f32),
+    SetCutoff(f32),
+    SetGain(f32),
+    SetPan(f32),
+    /// Put the pad in a choke group, numbered from 1, or in none.
+    SetChokeGroup(Option<u8>),
+    /// Play the pad on an output of its own. Nothing changes if the rack
+    /// has no outputs left.
+    SetSeparateOutput(bool),
+    /// The channel the separate output of the pad plays into.
+    SetOutputChannel(usize),
+    Remove,
+}
+
+impl UiState {
+    /// Add a bank of pads to the drum rack of a channel, named with
+    /// `name(number)`.
+    pub fn add_drum_pads(&mut self, index: usize, name: impl Fn(usize) -> String) {
+        if let Some(channel) = self.channels.get_mut(index) {
+            channel.drum_rack.add_bank(name);
+        }
+    }
+
+    pub fn edit_drum_rack(&mut self, index: usize, pad: usize, edit: &DrumPadEdit) {
+        let num_channels = self.channels.len();
+        let rack = match self.channels.get_mut(index) {
+            Some(channel) => &mut channel.drum_rack,
+            None => return,
+        };
+        let outputs_left = rack.num_outputs() < MAX_DRUM_OUTPUTS;
+        if let DrumPadEdit::Remove = edit {
+            if pad < rack.pads.len() {
+                rack.pads.remove(pad);
+            }
+            return;
+        }
+
+        let pad = match rack.pads.get_mut(pad) {
+            Some(pad) => pad,
+            None => return,
+        };
+        match edit {
+            DrumPadEdit::Rename(name) => pad.name = name.clone(),
+            DrumPadEdit::SetKey(key) => pad.key = (*key).clamp(MIN_KEY, MAX_KEY),
+            DrumPadEdit::SetSample(sample) => pad.sample = sample.clone(),
+            DrumPadEdit::SetTune(tune) => pad.tune = tune.clamp(-MAX_PAD_TUNE, MAX_PAD_TUNE),
+            DrumPadEdit::SetCutoff(hz) => {
+                pad.cutoff_hz = hz.clamp(MIN_PAD_CUTOFF_HZ, MAX_PAD_CUTOFF_HZ)
+            }
+            DrumPadEdit::SetGain(db) => pad.gain_db = db.clamp(MIN_DB, MAX_PAD_DB),
+            DrumPadEdit::SetPan(pan) => pad.pan = pan.clamp(-1.0, 1.0),
+            DrumPadEdit::SetChokeGroup(group) => {
+                pad.choke_group = group.filter(|g| (1..=MAX_CHOKE_GROUPS).contains(g))
+            }
+            DrumPadEdit::SetSeparateOutput(separate) => {
+                if !*separate || pad.separate_output || outputs_left {
+                    pad.separate_output = *separate;
+                }
+            }
+            DrumPadEdit::SetOutputChannel(to) => {
+                if *to < num_channels {
+                    pad.output_channel = *to;
+                }
+            }
+            DrumPadEdit::Remove => {}
+        }
+    }
+
+    /// Whether each channel needs a `DrumRackPlug`, i.e. has pads with a
+    /// sample.
+    pub fn drum_channels(&self) -> Vec<bool> {
+        self.channels
+            .iter()
+            .map(|channel| channel.drum_rack.pads.iter().any(|pad| pad.sample.is_some()))
+            .collect()
+    }
+
+    /// The host IDs of the drum rack plugins of the channels that need one.
+    pub fn drum_rack_host_ids(&self) -> Vec<u64> {
+        self.channels
+            .iter()
+            .zip(self.drum_channels())
+            .filter_map(|(channel, drums)| channel.drum_rack_host_id.filter(|_| drums))
+            .collect()
+    }
+
+    /// The channels that need a drum rack plugin that isn't in the audio
+    /// graph. See `unhosted_midi_tracks()`.
+    pub fn unhosted_drum_racks(&self, hosted: &[u64]) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut channels = Vec::new();
+        for (index, drums) in self.drum_channels().into_iter().enumerate() {
+            if !drums {
+                continue;
+            }
+            match self.channels[index].drum_rack_host_id {
+                Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                _ => channels.push(index),
+            }
+        }
+        channels
+    }
+
+    /// Send the pads of each drum rack to its plugin. The samples of the pads
+    /// are loaded if they weren't already.
+    pub fn sync_drum_racks(
+        &self,
+        plugin_host: &mut PluginHost,
+        resource_loader: &mut ResourceLoader,
+        resample_quality: ResampleQuality,
+    ) {
+        let sample_rate = resource_loader.project_sr().0 as f32;
+        for (index, drums) in self.drum_channels().into_iter().enumerate() {
+            let host_id = self.channels[index].drum_rack_host_id.filter(|_| drums);
+            let handle = match host_id.and_then(|id| plugin_host.handle_mut(id)) {
+                Some(handle) => handle,
+                None => continue,
+            };
+            let rack =
+                match handle.internal.as_mut().and_then(|h| h.downcast_mut::<DrumRackPlugHandle>())
+                {
+                    Some(rack) => rack,
+                    None => continue,
+                };
+            rack.set_kit(self.channels[index].drum_rack.kit(sample_rate, |path| {
+                let (pcm, res) = resource_loader.load_pcm(&PcmKey {
+                    path: path.to_path_buf(),
+                    resample_to_project_sr: true,
+                    resample_quality,
+                });
+                if res.is_ok() {
+                    Some(PadPcm(pcm))
+                } else {
+                    None
+                }
+            }));
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use vizia::prelude::*;
+
+use crate::backend::analysis::SpectrumAnalyzer;
+use crate::backend::dsp::MIN_DB;
+use crate::backend::eq_plug::{EqBand, EqPlugHandle, DEFAULT_BANDS, MAX_FREQ, MIN_FREQ, NUM_BANDS};
+
+/// The number of points the spectrums are reduced to for drawing.
+pub const SPECTRUM_POINTS: usize = 256;
+
+/// The state of the EQ panel.
+#[derive(Debug, Lens, Clone)]
+pub struct EqState {
+    #[lens(ignore)]
+    pub bands: [EqBand; NUM_BANDS],
+
+    /// The spectrum going into the EQ in dB, at `SPECTRUM_POINTS` logarithmically
+    /// spaced frequencies between `MIN_FREQ` and `MAX_FREQ`.
+    #[lens(ignore)]
+    pub pre_spectrum: Vec<f32>,
+
+    /// The spectrum coming out of the EQ, in the same format as `pre_spectrum`.
+    #[lens(ignore)]
+    pub post_spectrum: Vec<f32>,
+
+    #[lens(ignore)]
+    pub sample_rate: f32,
+
+    /// The band whose values are shown for text entry.
+    pub selected_band: usize,
+
+    pub bypassed: bool,
+
+    /// Whether the output of the EQ is matched to the loudness of its input.
+    pub auto_gain: bool,
+    /// The gain currently applied by auto gain in dB.
+    pub compensation_db: f32,
+}
+
+impl EqState {
+    /// The frequency of the given spectrum point.
+    pub fn point_freq(point: usize) -> f32 {
+        MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(point as f32 / (SPECTRUM_POINTS - 1) as f32)
+    }
+
+    pub fn selected_band(&self) -> &EqBand {
+        &self.bands[self.selected_band.min(NUM_BANDS - 1)]
+    }
+
+    pub fn update_from_handle(&mut self, handle: &EqPlugHandle) {
+        self.bands = *handle.bands();
+        self.sample_rate = handle.sample_rate();
+        self.bypassed = handle.bypassed();
+        self.auto_gain = handle.auto_gain();
+        self.compensation_db = handle.compensation_db();
+
+        reduce_spectrum(handle.pre_spectrum(), &mut self.pre_spectrum);
+        reduce_spectrum(handle.post_spectrum(), &mut self.post_spectrum);
+    }
+}
+
+impl Default for EqState {
+    fn default() -> Self {
+        Self {
+            bands: DEFAULT_BANDS,
+            pre_spectrum: vec![MIN_DB; SPECTRUM_POINTS],
+            post_spectrum: vec![MIN_DB; SPECTRUM_POINTS],
+            sample_rate: 44_100.0,
+            selected_band: 0,
+            bypassed: false,
+            auto_gain: false,
+            compensation_db: 0.0,
+        }
+    }
+}
+
+/// Reduce the linearly spaced bins of the analyzer to logarithmically spaced
+/// points, taking the loudest bin around each point.
+fn reduce_spectrum(analyzer: &SpectrumAnalyzer, out: &mut Vec<f32>) {
+    let spectrum = analyzer.spectrum_db();
+    let bin_width = analyzer.bin_freq(1);
+
+    out.clear();
+    for point in 0..SPECTRUM_POINTS {
+        let low = EqState::point_freq(point) / bin_width;
+        let high = EqState::point_freq(point + 1) / bin_width;
+
+        let start = (low.round() as usize).min(spectrum.len() - 1);
+        let end = (high.round() as usize).clamp(start + 1, spectrum.len());
+
+        out.push(spectrum[start..end].iter().copied().fold(MIN_DB, f32::max));
+    }
+}
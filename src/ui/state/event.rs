@@ -1,19 +1,336 @@
 use std::path::PathBuf;
 
+use super::{
+    AutomationEdit, ChannelMixEdit, DrumPadEdit, EffectSlot, FxRackEdit, InputQuantize,
+    MacroKnobEdit, MetronomeSound, MidiInputEdit, PianoRollNote, RackEdit, SendEdit,
+    StripSilenceMode,
+};
+use crate::backend::denoise::DenoiseSettings;
+use crate::backend::eq_plug::EqBand;
+use crate::backend::offline_render::ExportOptions;
+use crate::backend::resource_loader::ResampleQuality;
+use crate::backend::silence::StripSilenceSettings;
+use crate::backend::system_io::OutputBuffering;
+use std::ops::Range;
+use vizia::prelude::Color;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum UiEvent {
     // ----- General -----
     PollEngine,
+    /// Describe a change that was made, e.g. a new parameter value, so users
+    /// that can't see the control that changed still know what happened.
+    ///
+    /// `key` is the message to show, and `args` are its arguments.
+    Announce {
+        key: &'static str,
+        args: Vec<(&'static str, String)>,
+    },
+    /// Change the language of the UI, e.g. to `"de-DE"`.
+    SetLocale(String),
 
     // Project
     SaveProject,
     LoadProject,
+    /// Render the master output of the project to a WAV file.
+    ExportMaster(ExportOptions),
+    /// Render the output of every channel to a WAV file of its own.
+    ExportStems(ExportOptions),
+
+    // Recording
+    /// Record the audio input onto every armed channel, and start playing.
+    StartRecording,
+    /// Stop playing, and turn what was recorded into clips.
+    StopRecording,
+
+    // Tempo
+    /// Set the tempo from the time between this and the previous taps.
+    TapTempo,
+    /// Change the tempo by the given number of beats per minute.
+    NudgeTempo(f64),
+    SetTempo(f64),
+    /// Change the tempo at a position in beats, replacing the change that is
+    /// there. With `ramp` the tempo glides there from the change before.
+    InsertTempoChange {
+        beats: f64,
+        bpm: f64,
+        ramp: bool,
+    },
+    /// Remove a tempo change by its index in the tempo map.
+    RemoveTempoChange(usize),
+    /// Change the time signature from a bar on (counting from 0), replacing
+    /// the change that is there.
+    InsertTimeSignature {
+        bar: u32,
+        numerator: u32,
+        denominator: u32,
+    },
+    /// Remove a change of the time signature by its index.
+    RemoveTimeSignature(usize),
+
+    // Loop
+    /// Turn looping over the loop region on or off.
+    ToggleLoop,
+    /// Move the loop region. Regions that end before they start are ignored.
+    SetLoopRegion {
+        start_beats: f64,
+        end_beats: f64,
+    },
+
+    /// Free all loaded resources that are no longer used right away instead of
+    /// waiting for the next periodic collection.
+    CollectResources,
+
+    // Audio device
+    /// Send the left and right channels of the master bus to these channels of
+    /// the audio device.
+    SetMasterOutputChannels {
+        left: usize,
+        right: usize,
+    },
+    SetOutputBuffering(OutputBuffering),
+    /// Choose how audio files that are loaded from now on are converted to
+    /// the sample rate of the project.
+    SetResampleQuality(ResampleQuality),
+    /// Choose whether the audio graph of a loaded project is built a few
+    /// plugins at a time instead of all at once.
+    SetLazyGraph(bool),
+
+    // Metronome
+    /// Turn the click along with the transport on or off.
+    ToggleMetronome,
+    SetMetronomeSound(MetronomeSound),
+    /// The level of the clicks in decibels.
+    SetMetronomeLevel(f32),
+    /// Count in this many bars before a recording starts, or none with 0.
+    SetCountInBars(u32),
+    /// Turn off the clip indicators of the input meters.
+    ResetInputClip,
+
+    // Workspaces
+    /// Save the current arrangement of the panels under the given name, either
+    /// with the project or globally.
+    SaveWorkspace {
+        name: String,
+        global: bool,
+    },
+    LoadWorkspace(String),
+    DeleteWorkspace {
+        name: String,
+        global: bool,
+    },
+
+    // Edit transactions (see `UiData::begin_edit()`)
+    BeginEdit,
+    CommitEdit,
+    RollbackEdit,
+
+    // Undo history (see `EditHistory`)
+    Undo,
+    Redo,
+    /// Set how many edits can be undone.
+    SetHistoryDepth(usize),
 
     // ----- Channel Rack -----
     SelectChannel(usize),
+    /// Copy the effect rack of the given channel to the clipboard.
+    CopyEffects(usize),
+    /// Append the effects in the clipboard to every selected channel.
+    PasteEffects,
+    /// Append the CLAP plugin with the given ID to the effects of a channel.
+    AddPlugin {
+        channel: usize,
+        rdn: String,
+    },
+    RemoveEffect(EffectSlot),
+    /// Move an effect to another place in the effects of its channel, where
+    /// `to` is its index once it has moved.
+    MoveEffect {
+        slot: EffectSlot,
+        to: usize,
+    },
+    SetEffectBypassed {
+        slot: EffectSlot,
+        bypassed: bool,
+    },
+    /// Append an empty rack of parallel effect chains to a channel.
+    AddFxRack(usize),
+    EditFxRack {
+        slot: EffectSlot,
+        edit: FxRackEdit,
+    },
+    /// Save a rack as a preset that is available in every project.
+    SaveFxRackPreset(EffectSlot),
+    /// Append a copy of a rack preset to a channel.
+    LoadFxRackPreset {
+        channel: usize,
+        preset: usize,
+    },
+    /// Look for CLAP plugins again, e.g. after installing one.
+    RescanPlugins,
+    /// Give the parameters of an effect that have a randomize range random
+    /// values in their range.
+    RandomizeParameters(EffectSlot),
+    /// Include a parameter in randomizing with a normalized `(min, max)` range,
+    /// or leave it out with `None`.
+    SetRandomizeRange {
+        slot: EffectSlot,
+        parameter: u32,
+        range: Option<(f64, f64)>,
+    },
+    /// Store the current parameter values of an effect as snapshot `a` or `b`
+    /// of its morph.
+    StoreMorphPreset {
+        slot: EffectSlot,
+        b: bool,
+    },
+    /// Blend the parameters of an effect between its two snapshots, from 0.0
+    /// (`a`) to 1.0 (`b`).
+    SetMorphAmount {
+        slot: EffectSlot,
+        amount: f64,
+    },
+    /// Render the clips of the channel to a new audio file and replace them
+    /// with a single clip playing that file. The original clips are muted.
+    FreezeChannel(usize),
 
     // ----- Timeline -----
 
+    // Clip editing
+    StripSilence {
+        clip: usize,
+        settings: StripSilenceSettings,
+        mode: StripSilenceMode,
+    },
+    /// Learn a noise profile from the given selection (in seconds) of the
+    /// clip's original audio file.
+    LearnNoiseProfile {
+        clip: usize,
+        selection: Range<f64>,
+    },
+    /// Render a denoised take of the clip using the last learned noise profile.
+    DenoiseClip {
+        clip: usize,
+        settings: DenoiseSettings,
+    },
+    SetActiveTake {
+        clip: usize,
+        take: Option<usize>,
+    },
+
+    // Clip editing
+    RenameClip(usize, String),
+    /// Add a copy of the clip right after it on the same lane.
+    DuplicateClip(usize),
+    /// Add a linked copy of a piano roll clip right after it on the same lane.
+    /// Linked clips share their notes, so editing one edits all of them.
+    DuplicateClipAsAlias(usize),
+    /// Give a linked clip its own copy of the notes.
+    UnlinkClip(usize),
+    /// Replace the notes of a piano roll clip and every clip linked to it.
+    SetClipNotes(usize, Vec<PianoRollNote>),
+    ToggleClipMute(usize),
+    /// Show the given clip in the inspector panel.
+    InspectClip(usize),
+    /// Copy the effects of the given clip to the clipboard.
+    CopyClipEffects(usize),
+    /// Append the effects in the clipboard to the given clip.
+    PasteClipEffects(usize),
+    /// Remove every effect from the given clip.
+    ClearClipEffects(usize),
+    RemoveClips(Vec<usize>),
+    /// Put every clip on the selected lanes into a new container clip.
+    GroupSelectedLaneClips,
+    /// Replace a container clip with the clips inside it.
+    UnpackContainer(usize),
+    /// Set how far into its content a container clip starts (in beats).
+    SetContainerOffset(usize, f64),
+    /// Move a clip to start at the given position (in beats).
+    SetClipStart(usize, f64),
+    /// Set the length of a clip (in beats).
+    SetClipLength(usize, f64),
+    /// Set the gain of an audio clip (in decibels).
+    SetClipGain(usize, f32),
+    /// Set the fade in or fade out of an audio clip (in seconds).
+    SetClipFade {
+        clip: usize,
+        fade_out: bool,
+        secs: f64,
+    },
+
+    // Clipboard
+    CopyClips(Vec<usize>),
+    CutClips(Vec<usize>),
+    /// Paste the clips in the clipboard so the top-most one starts at the given
+    /// lane and position (in beats).
+    PasteClips {
+        lane: usize,
+        start_beats: f64,
+    },
+    CopySelectedLanes,
+    CutSelectedLanes,
+    /// Insert the lanes in the clipboard below the last selected lane.
+    PasteLanes,
+
+    // Arranger
+    /// Add a section to the end of the arranger track.
+    AddSection,
+    SelectSection(usize),
+    RenameSection(usize, String),
+    /// Set the length of a section (in beats). Everything after it moves
+    /// along with its end.
+    SetSectionLength(usize, f64),
+    /// Move a section to another place in the order of the sections, along
+    /// with every clip on every lane that starts in it.
+    MoveSection {
+        from: usize,
+        to: usize,
+    },
+    /// Add a copy of a section and its clips right after it.
+    DuplicateSection(usize),
+    /// Remove a section and its clips, and close the gap it leaves.
+    RemoveSection(usize),
+
+    // Recording
+    /// Turn the notes that were last played on the MIDI input into a clip,
+    /// even if nothing was recording.
+    CaptureLastPerformance,
+    /// Change how the notes of new recordings are snapped to the grid.
+    SetInputQuantize(InputQuantize),
+    /// Quantize the recorded notes of a piano roll clip again with the
+    /// current input quantize settings.
+    RequantizeClip(usize),
+    /// Turn playing notes with the computer keyboard on or off.
+    ToggleMusicalTyping,
+    SetTypingOctave(i16),
+    /// Set the velocity of the notes played with musical typing, from 0.0 to
+    /// 1.0.
+    SetTypingVelocity(f32),
+    /// Play a note clicked on the on-screen keyboard or pad grid on the
+    /// selected channel, with a velocity from 0.0 to 1.0.
+    PlayOnScreenNote {
+        key: i16,
+        velocity: f32,
+    },
+    ReleaseOnScreenNote(i16),
+    /// Set the octave of the lowest C of the on-screen keyboard.
+    SetKeyboardOctave(i16),
+    /// Set the key of the first pad of the pad grid.
+    SetFirstPadKey(i16),
+
+    // Import
+    /// Create a clip from an audio file at the given lane and position on the
+    /// timeline (in beats). New lanes are added if the lane doesn't exist yet.
+    ImportAudioFile {
+        path: PathBuf,
+        lane: usize,
+        start_beats: f64,
+    },
+
+    // Lane editing
+    RenameLane(usize, String),
+    SetLaneColor(usize, Color),
+
     // Insertion
     InsertLane,
     DuplicateSelectedLanes,
@@ -33,19 +350,201 @@ pub enum UiEvent {
     // Zoom
     ZoomInVertically,
     ZoomOutVertically,
+    /// Show the whole project.
+    ZoomToFit,
+    /// Show the clips on the selected lanes, or the selected section if no
+    /// lane has clips.
+    ZoomToSelection,
+    /// Go back to the view before the last zoom.
+    ViewBack,
+    /// Undo going back to an earlier view.
+    ViewForward,
+    /// Scroll the timeline so it starts at the given position (in beats).
+    ScrollTimelineTo(f64),
+    /// Sent by the timeline when the width of the visible part changes (in
+    /// logical pixels).
+    SetTimelineViewWidth(f32),
+
+    // Rulers
+    /// Switch the time ruler between plain time and timecode.
+    CycleTimeRulerFormat,
 
     // Height
     IncreaseSelectedLaneHeight,
     DecreaseSelectedLaneHeight,
+    /// Set the height of a lane as it is shown, in units of the default lane
+    /// height.
     SetSelectedLaneHeight(usize, f32),
+    /// Give every lane the same height so they all fit in the view.
+    FitAllLanes,
+    /// Sent by the timeline when the height of the visible part of the lanes
+    /// changes (in logical pixels).
+    SetTimelineViewHeight(f32),
 
     // Activation
     ActivateSelectedLanes,
     DeactivateSelectedLanes,
     ToggleSelectedLaneActivation,
 
+    // Playlists
+    /// Add a playlist to the lane and switch to it. The new playlist is either
+    /// empty or a copy of the active one.
+    AddLanePlaylist {
+        lane: usize,
+        copy: bool,
+    },
+    SwitchLanePlaylist {
+        lane: usize,
+        playlist: usize,
+    },
+    /// Remove the active playlist of the lane along with its clips.
+    RemoveLanePlaylist(usize),
+
     // ----- Browser -----
     SetBrowserWidth(f32),
     BrowserFileClicked(PathBuf),
     BrowserFileStop(),
+
+    // ----- Sample Editor -----
+    OpenSampleEditor(PathBuf),
+    /// Write the edited sample to a new file and point any clips that were
+    /// using the original file to it.
+    SaveSampleEditor,
+
+    // ----- EQ -----
+    SetEqBand(usize, EqBand),
+    /// Select the band whose values are shown for text entry.
+    SelectEqBand(usize),
+    ToggleEqBypass,
+    ToggleEqAutoGain,
+
+    // ----- Tuner -----
+    NudgeTunerReferencePitch(f32),
+
+    // ----- Loudness History -----
+    ClearLoudnessHistory,
+
+    // ----- Console -----
+    /// Run a script in the console, one line at a time.
+    RunScript(String),
+    SetConsoleInput(String),
+    ClearConsole,
+
+    // ----- Macros -----
+    /// Start recording what is done in the UI as the lines of a new macro.
+    StartMacroRecording,
+    /// Stop recording and save what was recorded as a new macro.
+    StopMacroRecording,
+    /// Run a macro with the given arguments. See `Macro::expand()`.
+    RunMacro {
+        index: usize,
+        args: Vec<String>,
+    },
+    /// Run the macro bound to Ctrl+Alt and the given number, if any.
+    RunMacroShortcut(u32),
+    /// Show the lines of a macro in the console so they can be edited.
+    EditMacro(Option<usize>),
+    RenameMacro {
+        index: usize,
+        name: String,
+    },
+    /// Replace a line of the macro being edited. An empty line is removed,
+    /// and a line past the end is added.
+    SetMacroLine {
+        line: usize,
+        text: String,
+    },
+    /// Bind a macro to Ctrl+Alt and a number, taking the number from any other
+    /// macro that had it.
+    BindMacro {
+        index: usize,
+        shortcut: Option<u32>,
+    },
+    DeleteMacro(usize),
+
+    // ----- Collisions -----
+    /// Compare the spectrums of the first two selected channels.
+    AnalyzeCollisions,
+
+    // ----- MIDI Output -----
+    /// Look for MIDI output devices again, e.g. after one was plugged in.
+    RefreshMidiOutDevices,
+    /// Send the MIDI of a channel to a hardware device, or stop with `None`.
+    SetMidiOutput {
+        channel: usize,
+        device: Option<String>,
+    },
+    /// Set the MIDI channel (0 to 15) a channel sends on.
+    SetMidiOutChannel {
+        channel: usize,
+        midi_channel: u8,
+    },
+    /// Select a program (0 to 127) on the device a channel sends to.
+    SetMidiProgram {
+        channel: usize,
+        program: Option<u8>,
+    },
+    /// Select a bank (0 to 16383) before the program of a channel.
+    SetMidiBank {
+        channel: usize,
+        bank: Option<u16>,
+    },
+    SetMidiOutLatency {
+        device: String,
+        ms: f64,
+    },
+    /// Change what is done to the notes coming into a channel.
+    EditMidiInput {
+        channel: usize,
+        edit: MidiInputEdit,
+    },
+    /// Add a chain to the instrument rack of a channel.
+    AddRackChain(usize),
+    EditRackChain {
+        channel: usize,
+        chain: usize,
+        edit: RackEdit,
+    },
+    /// Add a bank of pads to the drum rack of a channel.
+    AddDrumPads(usize),
+    EditDrumPad {
+        channel: usize,
+        pad: usize,
+        edit: DrumPadEdit,
+    },
+    /// Add a channel that records the audio coming back from the hardware
+    /// that a channel sends MIDI to.
+    AddMidiReturnChannel(usize),
+
+    // ----- Routing -----
+    /// Change the gain, pan, mute or solo of a channel.
+    EditChannelMix {
+        channel: usize,
+        edit: ChannelMixEdit,
+    },
+    /// Send a copy of the output of a channel to another channel.
+    AddSend {
+        channel: usize,
+        to: usize,
+    },
+    EditSend {
+        channel: usize,
+        send: usize,
+        edit: SendEdit,
+    },
+    /// Add a channel for other channels to send to.
+    AddReturnBus,
+
+    // ----- Automation -----
+    /// Change the automation lanes of a channel.
+    EditAutomation {
+        channel: usize,
+        edit: AutomationEdit,
+    },
+    /// Add a macro knob to a channel.
+    AddMacroKnob(usize),
+    EditMacroKnobs {
+        channel: usize,
+        edit: MacroKnobEdit,
+    },
 }
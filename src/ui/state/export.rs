@@ -0,0 +1,145 @@
+use std::path::{Path, PathBuf};
+
+use dropseed::DSEngineAudioThread;
+
+use super::{ClipStart, UiState};
+use crate::backend::offline_render::{ExportOptions, OfflineRender};
+
+/// How long the export goes on after the end of the last clip, so reverb and
+/// delay tails aren't cut off.
+pub const EXPORT_TAIL_SECS: f64 = 2.0;
+
+/// An export that is in progress, of the master output or of the stems of the
+/// channels. While it runs, the engine is activated at the sample rate of the
+/// export, without an audio device.
+pub struct ProjectExport {
+    /// The file or the directory the export is written to.
+    path: PathBuf,
+    /// The file of each pair of outputs of the engine.
+    files: Vec<PathBuf>,
+    options: ExportOptions,
+    frames: u64,
+    /// The audio thread of the engine, until the render starts.
+    audio_thread: Option<DSEngineAudioThread>,
+    /// Set once the engine has built the graph of the project.
+    graph_ready: bool,
+    render: Option<OfflineRender>,
+}
+
+impl ProjectExport {
+    /// Export the master output to a file.
+    pub fn master(path: &Path, options: ExportOptions, frames: u64) -> Self {
+        Self::new(path, vec![path.to_path_buf()], options, frames)
+    }
+
+    /// Export the output of every channel to a file of its own in a
+    /// directory, named after the channel. The master channel is the first
+    /// stem.
+    pub fn stems(dir: &Path, names: &[String], options: ExportOptions, frames: u64) -> Self {
+        let files = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                // Channels may share a name, so the number keeps the files
+                // apart.
+                let name: String = name
+                    .chars()
+                    .map(|c| if c.is_alphanumeric() || " -_".contains(c) { c } else { '_' })
+                    .collect();
+                dir.join(format!("{:02} {}.wav", index + 1, name.trim()))
+            })
+            .collect();
+        Self::new(dir, files, options, frames)
+    }
+
+    fn new(path: &Path, files: Vec<PathBuf>, options: ExportOptions, frames: u64) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            files,
+            options,
+            frames,
+            audio_thread: None,
+            graph_ready: false,
+            render: None,
+        }
+    }
+
+    pub fn options(&self) -> &ExportOptions {
+        &self.options
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of outputs the engine needs for the export, two for each
+    /// file.
+    pub fn out_channels(&self) -> u16 {
+        (self.files.len() * 2) as u16
+    }
+
+    /// Keep the audio thread of the engine, instead of giving it to the audio
+    /// device.
+    pub fn engine_activated(&mut self, audio_thread: DSEngineAudioThread) {
+        self.audio_thread = Some(audio_thread);
+    }
+
+    pub fn set_graph_ready(&mut self) {
+        self.graph_ready = true;
+    }
+
+    /// True once the engine is activated and has built the graph, so the
+    /// render can start.
+    pub fn is_ready(&self) -> bool {
+        self.graph_ready && self.audio_thread.is_some()
+    }
+
+    /// Start rendering on a thread of its own.
+    pub fn start(&mut self) -> std::io::Result<()> {
+        if let Some(audio_thread) = self.audio_thread.take() {
+            if let Some(dir) = self.files.first().and_then(|file| file.parent()) {
+                if !dir.as_os_str().is_empty() {
+                    std::fs::create_dir_all(dir)?;
+                }
+            }
+            self.render =
+                Some(OfflineRender::spawn(audio_thread, self.frames, &self.files, self.options)?);
+        }
+        Ok(())
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.render.as_ref().map_or(0.0, |render| render.progress())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.render.as_ref().map_or(false, |render| render.is_finished())
+    }
+
+    /// Wait for the render to finish, and return whether the file was written.
+    pub fn finish(self) -> std::io::Result<()> {
+        self.render.map_or(Ok(()), |render| render.finish())
+    }
+}
+
+impl UiState {
+    /// Where the last clip on the timeline ends, in beats.
+    pub fn project_end_beats(&self) -> f64 {
+        self.clips
+            .iter()
+            .filter_map(|clip| match &clip.timeline_start {
+                ClipStart::OnLane(on_lane) => Some(
+                    on_lane.timeline_start.get().as_beats_f64() + clip.length.get().as_beats_f64(),
+                ),
+                _ => None,
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// The number of frames an export of the whole project takes at the
+    /// given sample rate, including `EXPORT_TAIL_SECS`.
+    pub fn export_frames(&self, sample_rate: u32) -> u64 {
+        let secs = self.timeline_grid.beats_to_seconds(self.project_end_beats()) + EXPORT_TAIL_SECS;
+        (secs * f64::from(sample_rate)).ceil() as u64
+    }
+}
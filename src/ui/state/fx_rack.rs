@@ -0,0 +1,323 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use vizia::prelude::*;
+
+use super::project_file::{effects_from_ron, effects_to_ron};
+use super::{EffectSlot, ExternalEffectState, HRackEffectState, UiState};
+use crate::backend::dsp::MIN_DB;
+use crate::backend::fx_rack::{FxChainMix, MAX_FX_CHAINS, MAX_FX_CHAIN_DB};
+
+/// The directory that rack presets are saved in. Rack presets are available
+/// in every project.
+pub const GLOBAL_FX_RACKS_DIR: &str = "fx_racks";
+
+const FX_RACK_EXTENSION: &str = "fxrack";
+
+impl Data for FxChainMix {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// An effect that runs its input through parallel chains of effects and sums
+/// their outputs, e.g. for parallel compression. In the audio graph each
+/// chain ends in a fader plugin with its mix, see `channel_graph`. Racks
+/// can't hold other racks.
+#[derive(Debug, Lens, Clone, Data)]
+pub struct FxRackState {
+    pub name: String,
+    pub chains: Vec<FxChainState>,
+}
+
+#[derive(Debug, Lens, Clone, Data)]
+pub struct FxChainState {
+    pub name: String,
+    pub mix: FxChainMix,
+    pub effects: Vec<HRackEffectState>,
+    /// The fader plugin that sets the mix of the chain in the audio graph,
+    /// or `None` if it hasn't been added to the graph.
+    pub mix_host_id: Option<u64>,
+}
+
+/// A change to a rack on the effects of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FxRackEdit {
+    /// Add an empty chain with the given name.
+    AddChain(String),
+    RemoveChain(usize),
+    SetChainGain(usize, f32),
+    SetChainPan(usize, f32),
+    SetChainMuted(usize, bool),
+    /// Move another effect of the channel to the end of a chain.
+    MoveEffectIn {
+        effect: usize,
+        chain: usize,
+    },
+    /// Move an effect out of a chain to the channel, right after the rack.
+    MoveEffectOut {
+        chain: usize,
+        effect: usize,
+    },
+}
+
+impl FxRackState {
+    pub fn new(name: String) -> Self {
+        Self { name, chains: Vec::new() }
+    }
+
+    /// A rack preset is written like the effects of a channel in a project
+    /// file, with the rack as the only effect.
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        effects_to_ron(&[HRackEffectState::Rack(self.clone())])
+    }
+
+    pub fn from_ron(name: String, text: &str) -> Result<Self, Box<dyn Error>> {
+        let rack = effects_from_ron(text)?.into_iter().find_map(|effect| match effect {
+            HRackEffectState::Rack(rack) => Some(rack),
+            _ => None,
+        });
+        // The name of a preset is the name of its file.
+        Ok(Self { name, chains: rack.map(|rack| rack.chains).unwrap_or_default() })
+    }
+
+    /// Forget which plugins in the graph play the plugins in the rack, so a
+    /// copy of the rack gets plugins of its own.
+    pub fn clear_host_ids(&mut self) {
+        for chain in self.chains.iter_mut() {
+            chain.mix_host_id = None;
+            for effect in chain.effects.iter_mut() {
+                effect.clear_host_ids();
+            }
+        }
+    }
+
+    fn global_path(name: &str) -> PathBuf {
+        Path::new(GLOBAL_FX_RACKS_DIR).join(format!("{}.{}", name, FX_RACK_EXTENSION))
+    }
+
+    pub fn save_global(&self) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(GLOBAL_FX_RACKS_DIR)?;
+        std::fs::write(Self::global_path(&self.name), self.to_ron()?)?;
+        Ok(())
+    }
+
+    /// Load all rack presets in the rack presets directory, sorted by name.
+    /// Returns an empty list if the directory doesn't exist yet.
+    pub fn load_global() -> Vec<FxRackState> {
+        let entries = match std::fs::read_dir(GLOBAL_FX_RACKS_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut racks: Vec<FxRackState> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != FX_RACK_EXTENSION {
+                    return None;
+                }
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                match std::fs::read_to_string(&path)
+                    .map_err(Box::<dyn Error>::from)
+                    .and_then(|text| FxRackState::from_ron(name, &text))
+                {
+                    Ok(rack) => Some(rack),
+                    Err(e) => {
+                        log::error!("Failed to read rack preset {:?}: {}", &path, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        racks.sort_by(|a, b| a.name.cmp(&b.name));
+        racks
+    }
+}
+
+impl HRackEffectState {
+    /// Forget which plugins in the graph play this effect and the effects
+    /// inside it.
+    pub fn clear_host_ids(&mut self) {
+        match self {
+            HRackEffectState::External(effect) => {
+                effect.host_id = None;
+                effect.automation_host_id = None;
+            }
+            HRackEffectState::Rack(rack) => rack.clear_host_ids(),
+            HRackEffectState::Internal(_) => {}
+        }
+    }
+}
+
+impl UiState {
+    /// Append a rack to the effects of a channel. Returns the slot of the new
+    /// rack.
+    pub fn add_fx_rack(&mut self, channel: usize, mut rack: FxRackState) -> Option<EffectSlot> {
+        let effects = &mut self.channels.get_mut(channel)?.effects;
+        rack.clear_host_ids();
+        effects.push(HRackEffectState::Rack(rack));
+        Some(EffectSlot { channel, effect: effects.len() - 1 })
+    }
+
+    /// The host IDs of the faders of the chains of the racks.
+    pub fn rack_mix_host_ids(&self) -> Vec<u64> {
+        self.fx_racks()
+            .flat_map(|(_, rack)| rack.chains.iter().filter_map(|chain| chain.mix_host_id))
+            .collect()
+    }
+
+    /// The chains whose fader isn't in the audio graph, as the slot of the
+    /// rack and the index of the chain. Like the faders of channels, only
+    /// the first chain with a host ID counts as hosted.
+    pub fn unhosted_rack_mixes(&self, hosted: &[u64]) -> Vec<(EffectSlot, usize)> {
+        let mut seen = Vec::new();
+        let mut chains = Vec::new();
+        for (slot, rack) in self.fx_racks() {
+            for (index, chain) in rack.chains.iter().enumerate() {
+                match chain.mix_host_id {
+                    Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                    _ => chains.push((slot, index)),
+                }
+            }
+        }
+        chains
+    }
+
+    /// The plugin effects in the chains of racks that aren't played by a
+    /// plugin in the audio graph, as the slot of the rack, the index of the
+    /// chain and the index of the effect. See `unhosted_effects()`.
+    pub fn unhosted_rack_effects(&self, hosted: &[u64]) -> Vec<(EffectSlot, usize, usize)> {
+        let mut seen = Vec::new();
+        let mut effects = Vec::new();
+        for (slot, rack) in self.fx_racks() {
+            for (chain_index, chain) in rack.chains.iter().enumerate() {
+                for (index, effect) in chain.effects.iter().enumerate() {
+                    if let HRackEffectState::External(effect) = effect {
+                        match effect.host_id {
+                            Some(id) if hosted.contains(&id) && !seen.contains(&id) => {
+                                seen.push(id)
+                            }
+                            _ => effects.push((slot, chain_index, index)),
+                        }
+                    }
+                }
+            }
+        }
+        effects
+    }
+
+    /// Every rack on the effects of a channel, with its slot.
+    fn fx_racks(&self) -> impl Iterator<Item = (EffectSlot, &FxRackState)> {
+        self.channels.iter().enumerate().flat_map(|(channel, state)| {
+            state.effects.iter().enumerate().filter_map(move |(effect, state)| match state {
+                HRackEffectState::Rack(rack) => Some((EffectSlot { channel, effect }, rack)),
+                _ => None,
+            })
+        })
+    }
+
+    pub fn fx_rack(&self, slot: EffectSlot) -> Option<&FxRackState> {
+        match self.channels.get(slot.channel)?.effects.get(slot.effect)? {
+            HRackEffectState::Rack(rack) => Some(rack),
+            _ => None,
+        }
+    }
+
+    pub fn fx_rack_mut(&mut self, slot: EffectSlot) -> Option<&mut FxRackState> {
+        match self.channels.get_mut(slot.channel)?.effects.get_mut(slot.effect)? {
+            HRackEffectState::Rack(rack) => Some(rack),
+            _ => None,
+        }
+    }
+
+    /// A plugin effect in a chain of the rack in `slot`.
+    pub fn rack_effect_mut(
+        &mut self,
+        slot: EffectSlot,
+        chain: usize,
+        effect: usize,
+    ) -> Option<&mut ExternalEffectState> {
+        match self.fx_rack_mut(slot)?.chains.get_mut(chain)?.effects.get_mut(effect)? {
+            HRackEffectState::External(effect) => Some(effect),
+            _ => None,
+        }
+    }
+
+    pub fn edit_fx_rack(&mut self, slot: EffectSlot, edit: &FxRackEdit) {
+        let effects = match self.channels.get_mut(slot.channel) {
+            Some(channel) => &mut channel.effects,
+            None => return,
+        };
+
+        // Effects move between the channel and the rack, so take the rack out
+        // while it is edited.
+        if !matches!(effects.get(slot.effect), Some(HRackEffectState::Rack(_))) {
+            return;
+        }
+        let mut rack = match effects.remove(slot.effect) {
+            HRackEffectState::Rack(rack) => rack,
+            _ => unreachable!(),
+        };
+        let mut index = slot.effect;
+
+        match edit {
+            FxRackEdit::AddChain(name) => {
+                if rack.chains.len() < MAX_FX_CHAINS {
+                    rack.chains.push(FxChainState {
+                        name: name.clone(),
+                        mix: FxChainMix::default(),
+                        effects: Vec::new(),
+                        mix_host_id: None,
+                    });
+                }
+            }
+            FxRackEdit::RemoveChain(chain) => {
+                if *chain < rack.chains.len() {
+                    rack.chains.remove(*chain);
+                }
+            }
+            FxRackEdit::SetChainGain(chain, db) => {
+                if let Some(chain) = rack.chains.get_mut(*chain) {
+                    chain.mix.gain_db = db.clamp(MIN_DB, MAX_FX_CHAIN_DB);
+                }
+            }
+            FxRackEdit::SetChainPan(chain, pan) => {
+                if let Some(chain) = rack.chains.get_mut(*chain) {
+                    chain.mix.pan = pan.clamp(-1.0, 1.0);
+                }
+            }
+            FxRackEdit::SetChainMuted(chain, muted) => {
+                if let Some(chain) = rack.chains.get_mut(*chain) {
+                    chain.mix.muted = *muted;
+                }
+            }
+            FxRackEdit::MoveEffectIn { effect, chain } => {
+                // The rack itself was taken out, so the effects after it moved
+                // down by one.
+                let effect = if *effect > slot.effect { *effect - 1 } else { *effect };
+                let movable = matches!(effects.get(effect), Some(HRackEffectState::External(_)));
+                if *effect != slot.effect && movable && *chain < rack.chains.len() {
+                    // The plugin keeps playing in the chain, but only the
+                    // effects of the channel are automated.
+                    let mut moved = effects.remove(effect);
+                    if let HRackEffectState::External(moved) = &mut moved {
+                        moved.automation_host_id = None;
+                    }
+                    rack.chains[*chain].effects.push(moved);
+                    if effect < index {
+                        index -= 1;
+                    }
+                }
+            }
+            FxRackEdit::MoveEffectOut { chain, effect } => {
+                if let Some(chain) = rack.chains.get_mut(*chain) {
+                    if *effect < chain.effects.len() {
+                        let moved = chain.effects.remove(*effect);
+                        effects.insert(index, moved);
+                    }
+                }
+            }
+        }
+
+        effects.insert(index, HRackEffectState::Rack(rack));
+    }
+}
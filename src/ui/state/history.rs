@@ -0,0 +1,441 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::{ChannelEvent, ProjectSaveState, UiEvent, UiState};
+
+/// The number of edits that can be undone, unless changed with
+/// `UiEvent::SetHistoryDepth`.
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+/// Edits of the same kind to the same thing that follow each other faster
+/// than this are undone together, so e.g. dragging the gain of a clip is a
+/// single step.
+const COALESCE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What an edit changes, e.g. a clip by index or the sends of a channel, so
+/// that edits of the same kind to different things aren't undone together.
+/// `None` is the project as a whole.
+pub type EditTarget = Option<(usize, usize)>;
+
+/// A state of the project that can be gone back to.
+struct HistoryEntry {
+    /// The localization key that describes the edit that came after it.
+    label: &'static str,
+    project: ProjectSaveState,
+}
+
+/// The undo and redo stacks of the project.
+///
+/// A snapshot of the project is taken before every edit, so anything an edit
+/// changes can be undone without each edit having to know how to reverse
+/// itself. The view of the timeline is not part of the history.
+pub struct EditHistory {
+    /// The oldest entry is at the front.
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    depth: usize,
+    /// The kind, target and time of the last edit, for coalescing.
+    last_edit: Option<(&'static str, EditTarget, Instant)>,
+    /// The number of open groups. See `begin_group()`.
+    groups: usize,
+    /// While a transaction is open, the label of the first edit made in it.
+    /// See `begin_transaction()`.
+    transaction: Option<Option<&'static str>>,
+}
+
+impl EditHistory {
+    pub fn new() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth: DEFAULT_HISTORY_DEPTH,
+            last_edit: None,
+            groups: 0,
+            transaction: None,
+        }
+    }
+
+    /// The label of the edit that would be undone next.
+    pub fn undo_label(&self) -> Option<&'static str> {
+        self.undo.back().map(|entry| entry.label)
+    }
+
+    /// The label of the edit that would be redone next.
+    pub fn redo_label(&self) -> Option<&'static str> {
+        self.redo.last().map(|entry| entry.label)
+    }
+
+    /// Set how many edits can be undone. The oldest edits past this are
+    /// forgotten.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Forget every edit, e.g. when another project is loaded.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.last_edit = None;
+    }
+
+    /// Edits recorded while a group is open are part of the edit that was
+    /// recorded before it, e.g. the lines of a script are undone together
+    /// with running it.
+    pub fn begin_group(&mut self) {
+        self.groups += 1;
+    }
+
+    pub fn end_group(&mut self) {
+        self.groups = self.groups.saturating_sub(1);
+    }
+
+    /// Edits recorded while a transaction is open are undone together, as
+    /// the edit that was recorded first. The transaction keeps the project as
+    /// it was before it, so nothing is remembered until it is committed.
+    pub fn begin_transaction(&mut self) {
+        self.transaction = Some(None);
+    }
+
+    /// Remember the project as it was before the transaction, if anything was
+    /// edited while it was open.
+    pub fn commit_transaction(&mut self, project: ProjectSaveState) {
+        if let Some(Some(label)) = self.transaction.take() {
+            self.last_edit = None;
+            if self.depth > 0 {
+                self.push(label, project);
+            }
+        }
+    }
+
+    /// The edits of the transaction were undone, so there is nothing to
+    /// remember.
+    pub fn rollback_transaction(&mut self) {
+        self.transaction = None;
+    }
+
+    /// Remember the project as it is before an edit of the given kind. Does
+    /// nothing if the last edit was of the same kind to the same target and
+    /// just happened.
+    pub fn record(&mut self, label: &'static str, target: EditTarget, state: &UiState) {
+        self.record_project(label, target, Instant::now(), || ProjectSaveState::from_state(state));
+    }
+
+    fn record_project(
+        &mut self,
+        label: &'static str,
+        target: EditTarget,
+        now: Instant,
+        project: impl FnOnce() -> ProjectSaveState,
+    ) {
+        if self.groups > 0 {
+            return;
+        }
+        if let Some(transaction) = &mut self.transaction {
+            transaction.get_or_insert(label);
+            return;
+        }
+
+        let coalesce = self.last_edit.map_or(false, |(last_label, last_target, time)| {
+            last_label == label
+                && last_target == target
+                && now.saturating_duration_since(time) < COALESCE_INTERVAL
+        });
+        self.last_edit = Some((label, target, now));
+        if coalesce || self.depth == 0 {
+            return;
+        }
+
+        self.push(label, project());
+    }
+
+    fn push(&mut self, label: &'static str, project: ProjectSaveState) {
+        self.redo.clear();
+        self.undo.push_back(HistoryEntry { label, project });
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Go back to before the last edit. Returns the label of the edit that
+    /// was undone, or `None` if there is nothing to undo.
+    pub fn undo(&mut self, state: &mut UiState) -> Option<&'static str> {
+        let entry = self.step_back(|| ProjectSaveState::from_state(state))?;
+        entry.project.apply(state);
+        Some(entry.label)
+    }
+
+    /// Make the last undone edit again. Returns its label, or `None` if there
+    /// is nothing to redo.
+    pub fn redo(&mut self, state: &mut UiState) -> Option<&'static str> {
+        let entry = self.step_forward(|| ProjectSaveState::from_state(state))?;
+        entry.project.apply(state);
+        Some(entry.label)
+    }
+
+    /// Take the entry to undo to, and remember the `current` project to redo
+    /// to in its place.
+    fn step_back(&mut self, current: impl FnOnce() -> ProjectSaveState) -> Option<HistoryEntry> {
+        let entry = self.undo.pop_back()?;
+        self.redo.push(HistoryEntry { label: entry.label, project: current() });
+        self.last_edit = None;
+        Some(entry)
+    }
+
+    /// Like `step_back()`, the other way.
+    fn step_forward(&mut self, current: impl FnOnce() -> ProjectSaveState) -> Option<HistoryEntry> {
+        let entry = self.redo.pop()?;
+        self.undo.push_back(HistoryEntry { label: entry.label, project: current() });
+        self.last_edit = None;
+        Some(entry)
+    }
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The kind of edit an event makes to the project, or `None` if it doesn't
+/// change the project (i.e. it only changes the view or the selection).
+pub fn history_label(event: &UiEvent) -> Option<&'static str> {
+    Some(match event {
+        UiEvent::TapTempo
+        | UiEvent::NudgeTempo(_)
+        | UiEvent::SetTempo(_)
+        | UiEvent::InsertTempoChange { .. }
+        | UiEvent::RemoveTempoChange(_) => "history-tempo",
+        UiEvent::InsertTimeSignature { .. } | UiEvent::RemoveTimeSignature(_) => {
+            "history-time-signature"
+        }
+        UiEvent::ToggleLoop | UiEvent::SetLoopRegion { .. } => "history-loop",
+        UiEvent::PasteEffects
+        | UiEvent::AddPlugin { .. }
+        | UiEvent::RemoveEffect(_)
+        | UiEvent::MoveEffect { .. }
+        | UiEvent::SetEffectBypassed { .. }
+        | UiEvent::AddFxRack(_)
+        | UiEvent::EditFxRack { .. }
+        | UiEvent::LoadFxRackPreset { .. }
+        | UiEvent::PasteClipEffects(_)
+        | UiEvent::ClearClipEffects(_) => "history-effects",
+        UiEvent::StripSilence { .. } => "history-strip-silence",
+        UiEvent::DenoiseClip { .. } | UiEvent::SetActiveTake { .. } => "history-take",
+        UiEvent::RenameClip(..) => "history-rename-clip",
+        UiEvent::DuplicateClip(_) | UiEvent::DuplicateClipAsAlias(_) => "history-duplicate-clip",
+        UiEvent::UnlinkClip(_) | UiEvent::SetClipNotes(..) => "history-clip-notes",
+        UiEvent::ToggleClipMute(_) => "history-mute-clip",
+        UiEvent::RemoveClips(_) | UiEvent::CutClips(_) => "history-remove-clips",
+        UiEvent::GroupSelectedLaneClips
+        | UiEvent::UnpackContainer(_)
+        | UiEvent::SetContainerOffset(..) => "history-container",
+        UiEvent::SetClipStart(..) => "history-move-clip",
+        UiEvent::SetClipLength(..) => "history-clip-length",
+        UiEvent::SetClipGain(..) => "history-clip-gain",
+        UiEvent::SetClipFade { .. } => "history-clip-fade",
+        UiEvent::PasteClips { .. } | UiEvent::PasteLanes => "history-paste",
+        UiEvent::AddSection
+        | UiEvent::RenameSection(..)
+        | UiEvent::SetSectionLength(..)
+        | UiEvent::MoveSection { .. }
+        | UiEvent::DuplicateSection(_)
+        | UiEvent::RemoveSection(_) => "history-sections",
+        UiEvent::CaptureLastPerformance => "history-capture",
+        UiEvent::SetInputQuantize(_) => "history-input-quantize",
+        UiEvent::RequantizeClip(_) => "history-clip-notes",
+        UiEvent::ImportAudioFile { .. } => "history-import",
+        UiEvent::CutSelectedLanes
+        | UiEvent::RenameLane(..)
+        | UiEvent::SetLaneColor(..)
+        | UiEvent::InsertLane
+        | UiEvent::DuplicateSelectedLanes
+        | UiEvent::MoveSelectedLanesUp
+        | UiEvent::MoveSelectedLanesDown
+        | UiEvent::DeleteSelectedLanes
+        | UiEvent::ToggleLaneActivation
+        | UiEvent::ActivateSelectedLanes
+        | UiEvent::DeactivateSelectedLanes
+        | UiEvent::ToggleSelectedLaneActivation
+        | UiEvent::AddLanePlaylist { .. }
+        | UiEvent::SwitchLanePlaylist { .. }
+        | UiEvent::RemoveLanePlaylist(_) => "history-lanes",
+        UiEvent::SetMidiOutput { .. }
+        | UiEvent::SetMidiOutChannel { .. }
+        | UiEvent::SetMidiProgram { .. }
+        | UiEvent::SetMidiBank { .. }
+        | UiEvent::EditMidiInput { .. } => "history-midi",
+        UiEvent::AddRackChain(_) | UiEvent::EditRackChain { .. } => "history-instrument-rack",
+        UiEvent::AddDrumPads(_) | UiEvent::EditDrumPad { .. } => "history-drum-rack",
+        UiEvent::AddMidiReturnChannel(_) | UiEvent::AddReturnBus => "history-channels",
+        UiEvent::EditChannelMix { .. } => "history-channel-mix",
+        UiEvent::AddSend { .. } | UiEvent::EditSend { .. } => "history-routing",
+        UiEvent::EditAutomation { .. } => "history-automation",
+        UiEvent::AddMacroKnob(_) | UiEvent::EditMacroKnobs { .. } => "history-macro-knobs",
+        UiEvent::RunScript(_) | UiEvent::RunMacro { .. } | UiEvent::RunMacroShortcut(_) => {
+            "history-script"
+        }
+        _ => return None,
+    })
+}
+
+/// Like `history_label()`, for events of the channels panel.
+pub fn channel_history_label(event: &ChannelEvent) -> Option<&'static str> {
+    Some(match event {
+        ChannelEvent::AddChannel
+        | ChannelEvent::RemoveChannel
+        | ChannelEvent::RenameChannel(..)
+        | ChannelEvent::SetChannelColor(..)
+        | ChannelEvent::RouteChannel(..)
+        | ChannelEvent::SetHardwareOutput(..)
+        | ChannelEvent::DuplicateChannel(_) => "history-channels",
+        ChannelEvent::ToggleArm(_)
+        | ChannelEvent::SetExclusiveArm(..)
+        | ChannelEvent::SetArmGroup(..)
+        | ChannelEvent::LinkArm(_)
+        | ChannelEvent::SetRecordInput(..) => "history-arm",
+        _ => return None,
+    })
+}
+
+/// What an edit made by an event changes, for coalescing. See `EditTarget`.
+pub fn history_target(event: &UiEvent) -> EditTarget {
+    Some(match event {
+        UiEvent::RenameClip(clip, _)
+        | UiEvent::SetClipNotes(clip, _)
+        | UiEvent::SetContainerOffset(clip, _)
+        | UiEvent::SetClipStart(clip, _)
+        | UiEvent::SetClipLength(clip, _)
+        | UiEvent::SetClipGain(clip, _)
+        | UiEvent::SetClipFade { clip, .. } => (*clip, 0),
+        UiEvent::RenameSection(section, _) | UiEvent::SetSectionLength(section, _) => (*section, 0),
+        UiEvent::RenameLane(lane, _) | UiEvent::SetLaneColor(lane, _) => (*lane, 0),
+        UiEvent::SetEffectBypassed { slot, .. } | UiEvent::EditFxRack { slot, .. } => {
+            (slot.channel, slot.effect)
+        }
+        UiEvent::SetMidiOutput { channel, .. }
+        | UiEvent::SetMidiOutChannel { channel, .. }
+        | UiEvent::SetMidiProgram { channel, .. }
+        | UiEvent::SetMidiBank { channel, .. }
+        | UiEvent::EditMidiInput { channel, .. }
+        | UiEvent::EditChannelMix { channel, .. }
+        | UiEvent::EditAutomation { channel, .. }
+        | UiEvent::EditMacroKnobs { channel, .. } => (*channel, 0),
+        UiEvent::EditSend { channel, send, .. } => (*channel, *send),
+        UiEvent::EditRackChain { channel, chain, .. } => (*channel, *chain),
+        UiEvent::EditDrumPad { channel, pad, .. } => (*channel, *pad),
+        _ => return None,
+    })
+}
+
+/// Like `history_target()`, for events of the channels panel.
+pub fn channel_history_target(event: &ChannelEvent) -> EditTarget {
+    Some(match event {
+        ChannelEvent::RenameChannel(channel, _)
+        | ChannelEvent::SetChannelColor(channel, _)
+        | ChannelEvent::RouteChannel(channel, _)
+        | ChannelEvent::SetHardwareOutput(channel, _)
+        | ChannelEvent::SetExclusiveArm(channel, _)
+        | ChannelEvent::SetArmGroup(channel, _)
+        | ChannelEvent::SetRecordInput(channel, _) => (*channel, 0),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(bpm: f64) -> ProjectSaveState {
+        ProjectSaveState {
+            bpm,
+            tempo_changes: vec![],
+            time_signatures: vec![],
+            loop_region: Default::default(),
+            input_quantize: Default::default(),
+            arranger_start_beats: 0.0,
+            sections: vec![],
+            lanes: vec![],
+            channels: vec![],
+            clips: vec![],
+        }
+    }
+
+    fn undo(history: &mut EditHistory, current: f64) -> Option<(&'static str, f64)> {
+        history.step_back(|| project(current)).map(|entry| (entry.label, entry.project.bpm))
+    }
+
+    fn redo(history: &mut EditHistory, current: f64) -> Option<(&'static str, f64)> {
+        history.step_forward(|| project(current)).map(|entry| (entry.label, entry.project.bpm))
+    }
+
+    #[test]
+    fn undo_and_redo_step_through_the_edits() {
+        let mut history = EditHistory::new();
+        let now = Instant::now();
+        history.record_project("history-tempo", None, now, || project(120.0));
+        history.record_project("history-loop", None, now, || project(130.0));
+
+        assert_eq!(undo(&mut history, 140.0), Some(("history-loop", 130.0)));
+        assert_eq!(undo(&mut history, 130.0), Some(("history-tempo", 120.0)));
+        assert_eq!(undo(&mut history, 120.0), None);
+
+        assert_eq!(redo(&mut history, 120.0), Some(("history-tempo", 130.0)));
+        assert_eq!(redo(&mut history, 130.0), Some(("history-loop", 140.0)));
+        assert_eq!(redo(&mut history, 140.0), None);
+
+        // A new edit forgets what could be redone.
+        undo(&mut history, 140.0);
+        history.record_project("history-tempo", None, now, || project(130.0));
+        assert_eq!(history.redo_label(), None);
+        assert_eq!(history.undo_label(), Some("history-tempo"));
+    }
+
+    #[test]
+    fn quick_edits_to_the_same_target_are_undone_together() {
+        let mut history = EditHistory::new();
+        let now = Instant::now();
+        history.record_project("history-clip-gain", Some((0, 0)), now, || project(1.0));
+        history.record_project("history-clip-gain", Some((0, 0)), now, || project(2.0));
+        assert_eq!(undo(&mut history, 3.0), Some(("history-clip-gain", 1.0)));
+        assert_eq!(undo(&mut history, 1.0), None);
+    }
+
+    #[test]
+    fn edits_to_other_targets_are_undone_apart() {
+        let mut history = EditHistory::new();
+        let now = Instant::now();
+        history.record_project("history-clip-gain", Some((0, 0)), now, || project(1.0));
+        history.record_project("history-clip-gain", Some((1, 0)), now, || project(2.0));
+        history.record_project("history-clip-fade", Some((1, 0)), now, || project(3.0));
+        assert_eq!(undo(&mut history, 4.0), Some(("history-clip-fade", 3.0)));
+        assert_eq!(undo(&mut history, 3.0), Some(("history-clip-gain", 2.0)));
+        assert_eq!(undo(&mut history, 2.0), Some(("history-clip-gain", 1.0)));
+    }
+
+    #[test]
+    fn slow_edits_are_undone_apart() {
+        let mut history = EditHistory::new();
+        let now = Instant::now();
+        history.record_project("history-tempo", None, now, || project(120.0));
+        let later = now + COALESCE_INTERVAL;
+        history.record_project("history-tempo", None, later, || project(121.0));
+        assert_eq!(undo(&mut history, 122.0), Some(("history-tempo", 121.0)));
+        assert_eq!(undo(&mut history, 121.0), Some(("history-tempo", 120.0)));
+    }
+
+    #[test]
+    fn only_the_latest_edits_are_kept() {
+        let mut history = EditHistory::new();
+        history.set_depth(2);
+        let now = Instant::now();
+        for (i, label) in ["history-tempo", "history-loop", "history-sections"].iter().enumerate() {
+            history.record_project(label, None, now, || project(i as f64));
+        }
+        assert_eq!(undo(&mut history, 3.0), Some(("history-sections", 2.0)));
+        assert_eq!(undo(&mut history, 2.0), Some(("history-loop", 1.0)));
+        assert_eq!(undo(&mut history, 1.0), None);
+    }
+}
@@ -1,10 +1,13 @@
 use vizia::prelude::*;
 
+use super::{FxRackState, ParameterRange, PresetMorph};
+
 /// An effect on the horizontal effect rack.
 #[derive(Debug, Lens, Clone, Data)]
 pub enum HRackEffectState {
     Internal(InternalEffectState),
     External(ExternalEffectState),
+    Rack(FxRackState),
 }
 
 #[derive(Debug, Clone, PartialEq, Data)]
@@ -76,6 +79,64 @@ pub struct ExternalEffectState {
     ///
     /// This will be empty when "all_parameters_shown" is false.
     pub all_parameters: Vec<ParameterState>,
+
+    /// The parameters that randomizing changes, and the ranges it picks their
+    /// values from.
+    pub randomize_ranges: Vec<ParameterRange>,
+
+    pub morph: PresetMorph,
+
+    /// The plugin in the audio graph that plays this effect, or `None` if it
+    /// hasn't been added to the graph (i.e. the plugin wasn't found).
+    pub host_id: Option<u64>,
+
+    /// The automation plugin that moves the parameters of this effect, or
+    /// `None` if it hasn't been added to the graph. Only effects with
+    /// automated parameters have one.
+    pub automation_host_id: Option<u64>,
+
+    /// The state the plugin saved, to restore it when the project is loaded.
+    /// The internal effects are asked for their latest state when the project
+    /// is saved, see `UiState::update_saved_states()`.
+    pub saved_state: Option<SavedPluginState>,
+}
+
+impl ExternalEffectState {
+    pub fn new(name: String, rdn: String, version: String) -> Self {
+        Self {
+            name,
+            rdn,
+            version,
+            product_url: None,
+            manual_url: None,
+            support_url: None,
+            collapsed: false,
+            status: ActivatedStatus::Deactivated,
+            has_gui: false,
+            gui_is_open: false,
+            bypassed: false,
+            delay: 0,
+            preset_name: None,
+            preset_changed: false,
+            last_tweaked_parameter: None,
+            quick_access_parameters: Vec::new(),
+            all_parameters_shown: false,
+            all_parameters: Vec::new(),
+            randomize_ranges: Vec::new(),
+            morph: PresetMorph::default(),
+            host_id: None,
+            automation_host_id: None,
+            saved_state: None,
+        }
+    }
+}
+
+/// The state of a plugin as the plugin saved it. Only the plugin knows what
+/// the bytes mean.
+#[derive(Debug, Clone, PartialEq, Data)]
+pub struct SavedPluginState {
+    pub version: u32,
+    pub bytes: Vec<u8>,
 }
 
 #[derive(Debug, Clone, Data)]
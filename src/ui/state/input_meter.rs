@@ -0,0 +1,49 @@
+use vizia::prelude::*;
+
+use crate::backend::input_meter::InputLevel;
+
+/// The levels of the inputs of the audio device, metered whether or not
+/// anything is recording.
+#[derive(Debug, Lens, Clone, Default)]
+pub struct InputMeterState {
+    /// The name of the input device, or empty if no input is open.
+    pub device_name: String,
+
+    /// The peak of each input since the last poll, in `[0.0, 1.0]`.
+    pub levels: Vec<f32>,
+
+    /// Whether each input has clipped. This stays set until it is reset, so
+    /// a clip isn't missed while looking away.
+    pub clipped: Vec<bool>,
+}
+
+impl InputMeterState {
+    pub fn update(&mut self, levels: &[InputLevel]) {
+        self.levels = levels.iter().map(|level| level.peak.min(1.0)).collect();
+        self.clipped.resize(levels.len(), false);
+        for (clipped, level) in self.clipped.iter_mut().zip(levels.iter()) {
+            *clipped |= level.clipped;
+        }
+    }
+
+    /// The level of an input, or of the first one if the device has fewer
+    /// inputs (i.e. a mono input shown on a stereo meter).
+    pub fn level(&self, channel: usize) -> f32 {
+        self.levels.get(channel).or_else(|| self.levels.first()).copied().unwrap_or(0.0)
+    }
+
+    pub fn any_clipped(&self) -> bool {
+        self.clipped.iter().any(|clipped| *clipped)
+    }
+
+    pub fn reset_clipped(&mut self) {
+        self.clipped.iter_mut().for_each(|clipped| *clipped = false);
+    }
+
+    /// Called when the input device is closed.
+    pub fn clear(&mut self) {
+        self.device_name.clear();
+        self.levels.clear();
+        self.clipped.clear();
+    }
+}
@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use vizia::prelude::*;
+
+use super::{musical_from_beats, ClipType, PianoRollNote, UiState};
+
+/// The grids that recorded notes can be snapped to, in beats, with the keys
+/// of their names.
+pub const INPUT_QUANTIZE_GRIDS: [(&str, f64); 5] = [
+    ("quantize-grid-quarter", 1.0),
+    ("quantize-grid-eighth", 0.5),
+    ("quantize-grid-eighth-triplet", 1.0 / 3.0),
+    ("quantize-grid-sixteenth", 0.25),
+    ("quantize-grid-thirty-second", 0.125),
+];
+
+/// Snaps the starts of notes to the grid as they are recorded. This is a
+/// setting of the project.
+///
+/// Where each note was really played is kept, so the strength can be changed
+/// afterwards with `UiState::requantize_clip()`.
+#[derive(Debug, Lens, Clone, Copy, PartialEq, Data, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputQuantize {
+    pub enabled: bool,
+    /// The length of a step of the grid in beats.
+    pub grid_beats: f64,
+    /// How far notes are moved towards the grid, from 0.0 (not at all) to
+    /// 1.0 (right onto it).
+    pub strength: f64,
+}
+
+impl InputQuantize {
+    /// Move a position in beats towards the closest step of the grid.
+    pub fn quantize_beats(&self, beats: f64) -> f64 {
+        if !self.enabled || self.grid_beats <= 0.0 {
+            return beats;
+        }
+        let snapped = (beats / self.grid_beats).round() * self.grid_beats;
+        (beats + (snapped - beats) * self.strength.clamp(0.0, 1.0)).max(0.0)
+    }
+
+    /// Quantize a note that was just recorded, remembering where it was
+    /// played.
+    pub fn quantize_note(&self, note: PianoRollNote) -> PianoRollNote {
+        let played = note.recorded_start.unwrap_or(note.start);
+        let beats = self.quantize_beats(played.get().as_beats_f64());
+        PianoRollNote {
+            start: musical_from_beats(beats).into(),
+            recorded_start: Some(played),
+            ..note
+        }
+    }
+}
+
+impl Default for InputQuantize {
+    fn default() -> Self {
+        Self { enabled: false, grid_beats: 0.25, strength: 1.0 }
+    }
+}
+
+impl UiState {
+    /// Quantize the recorded notes of a piano roll clip again from where they
+    /// were played, with the current settings of the project. With input
+    /// quantize turned off this puts the notes back where they were played.
+    ///
+    /// Notes that weren't recorded with input quantize are left alone.
+    pub fn requantize_clip(&mut self, index: usize) {
+        let notes = match self.clips.get(index).map(|clip| &clip.type_) {
+            Some(ClipType::PianoRoll(piano_roll)) => piano_roll.notes.clone(),
+            _ => return,
+        };
+        let quantize = self.input_quantize;
+
+        let mut notes: Vec<PianoRollNote> = notes
+            .into_iter()
+            .map(|note| match note.recorded_start {
+                Some(_) => quantize.quantize_note(note),
+                None => note,
+            })
+            .collect();
+        notes.sort_by(|a, b| {
+            let (a, b) = (a.start.get().as_beats_f64(), b.start.get().as_beats_f64());
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.set_clip_notes(index, notes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::state::{ClipStart, ClipState, PianoRollClipState};
+    use meadowlark_core_types::time::MusicalTime;
+
+    fn quantize(grid_beats: f64, strength: f64) -> InputQuantize {
+        InputQuantize { enabled: true, grid_beats, strength }
+    }
+
+    fn note(start: f64, recorded_start: Option<f64>) -> PianoRollNote {
+        PianoRollNote {
+            start: musical_from_beats(start).into(),
+            length: MusicalTime::from_beats(1).into(),
+            key: 60,
+            velocity: 1.0,
+            recorded_start: recorded_start.map(|beats| musical_from_beats(beats).into()),
+        }
+    }
+
+    fn piano_roll(notes: Vec<PianoRollNote>, link: Option<u32>) -> ClipState {
+        ClipState {
+            name: String::new(),
+            timeline_start: ClipStart::NotInTimeline,
+            length: MusicalTime::from_beats(4).into(),
+            channel: 0,
+            muted: false,
+            effects: Vec::new(),
+            recording: None,
+            player_host_id: None,
+            type_: ClipType::PianoRoll(PianoRollClipState { notes, link }),
+        }
+    }
+
+    /// The starts of the notes of a clip, and where they were played.
+    fn starts(clip: &ClipState) -> Vec<(f64, Option<f64>)> {
+        match &clip.type_ {
+            ClipType::PianoRoll(piano_roll) => piano_roll
+                .notes
+                .iter()
+                .map(|note| {
+                    (
+                        note.start.get().as_beats_f64(),
+                        note.recorded_start.map(|start| start.get().as_beats_f64()),
+                    )
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn notes_move_towards_the_grid_by_the_strength() {
+        assert_eq!(quantize(0.25, 1.0).quantize_beats(1.0625), 1.0);
+        assert_eq!(quantize(0.25, 1.0).quantize_beats(1.1875), 1.25);
+        assert_eq!(quantize(0.25, 0.5).quantize_beats(1.0625), 1.03125);
+        assert_eq!(quantize(0.25, 0.0).quantize_beats(1.0625), 1.0625);
+        assert_eq!(quantize(0.25, 2.0).quantize_beats(1.0625), 1.0);
+        assert!((quantize(1.0 / 3.0, 1.0).quantize_beats(0.7) - 2.0 / 3.0).abs() < 1e-9);
+
+        // Nothing is moved before the start.
+        assert_eq!(quantize(1.0, 1.0).quantize_beats(-0.2), 0.0);
+
+        let off = InputQuantize { enabled: false, ..quantize(0.25, 1.0) };
+        assert_eq!(off.quantize_beats(1.0625), 1.0625);
+        assert_eq!(quantize(0.0, 1.0).quantize_beats(1.0625), 1.0625);
+    }
+
+    #[test]
+    fn quantized_notes_remember_where_they_were_played() {
+        let quantized = quantize(0.25, 1.0).quantize_note(note(1.0625, None));
+        assert_eq!(quantized.start.get().as_beats_f64(), 1.0);
+        assert_eq!(quantized.recorded_start.map(|start| start.get().as_beats_f64()), Some(1.0625));
+
+        // Quantizing again starts from where the note was played.
+        let again = quantize(0.25, 0.5).quantize_note(quantized);
+        assert_eq!(again.start.get().as_beats_f64(), 1.03125);
+        assert_eq!(again.recorded_start.map(|start| start.get().as_beats_f64()), Some(1.0625));
+    }
+
+    #[test]
+    fn requantized_clips_start_from_where_notes_were_played() {
+        let mut state = UiState::for_tests(1, 1);
+        let notes = vec![note(1.0, Some(1.0625)), note(1.125, None), note(1.25, Some(1.1875))];
+        state.clips = vec![piano_roll(notes.clone(), Some(7)), piano_roll(notes, Some(7))];
+
+        // Turned off, the notes go back to where they were played, and the
+        // linked clip follows.
+        state.requantize_clip(0);
+        let expected = [(1.0625, Some(1.0625)), (1.125, None), (1.1875, Some(1.1875))];
+        assert_eq!(starts(&state.clips[0]), expected);
+        assert_eq!(starts(&state.clips[1]), expected);
+
+        // Notes that weren't quantized stay where they are, and the notes are
+        // sorted again.
+        state.input_quantize = quantize(1.0, 1.0);
+        state.requantize_clip(1);
+        let expected = [(1.0, Some(1.0625)), (1.0, Some(1.1875)), (1.125, None)];
+        assert_eq!(starts(&state.clips[0]), expected);
+        assert_eq!(starts(&state.clips[1]), expected);
+    }
+}
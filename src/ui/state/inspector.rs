@@ -0,0 +1,104 @@
+use meadowlark_core_types::time::Seconds;
+use vizia::prelude::*;
+
+use super::{musical_from_beats, ClipStart, ClipState, ClipType, UiState};
+use crate::backend::dsp::MIN_DB;
+
+/// The shortest a clip can be made in the inspector, in beats.
+pub const MIN_CLIP_LENGTH_BEATS: f64 = 1.0 / 16.0;
+
+/// The most a clip can be boosted, in decibels.
+pub const MAX_CLIP_GAIN_DB: f32 = 24.0;
+
+/// What the inspector panel shows. This is whatever was selected last.
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+pub enum Inspected {
+    Clip(usize),
+    Channel(usize),
+    Section(usize),
+}
+
+impl UiState {
+    /// Move a clip on the timeline to start at the given position in beats.
+    /// Clips that are not on the timeline are left alone.
+    pub fn set_clip_start(&mut self, index: usize, beats: f64) {
+        let length = match self.clips.get_mut(index) {
+            Some(clip) => match &mut clip.timeline_start {
+                ClipStart::OnLane(on_lane) => {
+                    on_lane.timeline_start = musical_from_beats(beats).into();
+                    clip.length.get().as_beats_f64()
+                }
+                ClipStart::NotInTimeline => return,
+            },
+            None => return,
+        };
+        self.extend_project_to(beats.max(0.0) + length);
+    }
+
+    /// Set the length of a clip in beats. Its fades are shortened to fit.
+    pub fn set_clip_length(&mut self, index: usize, beats: f64) {
+        let beats = beats.max(MIN_CLIP_LENGTH_BEATS);
+        let timeline_grid = &self.timeline_grid;
+        let start = match self.clips.get_mut(index) {
+            Some(clip) => {
+                clip.length = musical_from_beats(beats).into();
+                let length_secs = timeline_grid.length_to_seconds(timeline_start(clip), beats);
+                if let ClipType::Audio(audio) = &mut clip.type_ {
+                    let fade_in = audio.fade_in_secs.get().0.min(length_secs);
+                    let fade_out = audio.fade_out_secs.get().0.min(length_secs - fade_in);
+                    audio.fade_in_secs = Seconds(fade_in).into();
+                    audio.fade_out_secs = Seconds(fade_out).into();
+                }
+                match &clip.timeline_start {
+                    ClipStart::OnLane(on_lane) => on_lane.timeline_start.get().as_beats_f64(),
+                    ClipStart::NotInTimeline => return,
+                }
+            }
+            None => return,
+        };
+        self.extend_project_to(start + beats);
+    }
+
+    /// Set the gain of an audio clip in decibels.
+    pub fn set_clip_gain(&mut self, index: usize, db: f32) {
+        if let Some(ClipType::Audio(audio)) = self.clips.get_mut(index).map(|clip| &mut clip.type_)
+        {
+            audio.gain_db = db.clamp(MIN_DB, MAX_CLIP_GAIN_DB);
+        }
+    }
+
+    /// Set the fade in (`fade_out == false`) or fade out of an audio clip in
+    /// seconds. The fades can't overlap.
+    pub fn set_clip_fade(&mut self, index: usize, fade_out: bool, secs: f64) {
+        let clip = match self.clips.get_mut(index) {
+            Some(clip) => clip,
+            None => return,
+        };
+        let length_secs = self
+            .timeline_grid
+            .length_to_seconds(timeline_start(clip), clip.length.get().as_beats_f64());
+        if let ClipType::Audio(audio) = &mut clip.type_ {
+            let (fade, other) = if fade_out {
+                (&mut audio.fade_out_secs, audio.fade_in_secs)
+            } else {
+                (&mut audio.fade_in_secs, audio.fade_out_secs)
+            };
+            *fade = Seconds(secs.clamp(0.0, (length_secs - other.get().0).max(0.0))).into();
+        }
+    }
+
+    fn extend_project_to(&mut self, beats: f64) {
+        if beats > self.timeline_grid.project_length.get().as_beats_f64() {
+            self.timeline_grid.project_length = musical_from_beats(beats).into();
+        }
+    }
+}
+
+/// Where a clip starts on the timeline in beats. A clip that isn't on the
+/// timeline is taken to start at the start of the project.
+fn timeline_start(clip: &ClipState) -> f64 {
+    match &clip.timeline_start {
+        ClipStart::OnLane(on_lane) => on_lane.timeline_start.get().as_beats_f64(),
+        ClipStart::NotInTimeline => 0.0,
+    }
+}
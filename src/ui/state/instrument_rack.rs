@@ -0,0 +1,88 @@
+use vizia::prelude::*;
+
+use super::UiState;
+use crate::backend::dsp::MIN_DB;
+use crate::backend::instrument_rack::{InstrumentRack, RackChain, MAX_CHAINS, MAX_CHAIN_DB};
+use crate::backend::midi_transform::{MAX_KEY, MIN_KEY};
+
+impl Data for InstrumentRack {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// A change to one of the chains of the instrument rack of a channel. Keys
+/// are MIDI note numbers and velocities are from 0.0 to 1.0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RackEdit {
+    /// Add a chain that plays the whole keyboard, with the given name.
+    AddChain(String),
+    Rename(String),
+    /// Raising the lowest key above the highest raises the highest too, and
+    /// the other way around. The same goes for the velocities.
+    SetLowKey(i16),
+    SetHighKey(i16),
+    SetMinVelocity(f32),
+    SetMaxVelocity(f32),
+    SetGain(f32),
+    SetPan(f32),
+    SetMuted(bool),
+    SetSoloed(bool),
+    Remove,
+}
+
+impl UiState {
+    /// Edit a chain of the rack of a channel. The chain is ignored when
+    /// adding one.
+    pub fn edit_instrument_rack(&mut self, index: usize, chain: usize, edit: &RackEdit) {
+        let rack = match self.channels.get_mut(index) {
+            Some(channel) => &mut channel.instrument_rack,
+            None => return,
+        };
+        if let RackEdit::AddChain(name) = edit {
+            if rack.chains.len() < MAX_CHAINS {
+                rack.chains.push(RackChain::new(name.clone()));
+            }
+            return;
+        }
+        if let RackEdit::Remove = edit {
+            if chain < rack.chains.len() {
+                rack.chains.remove(chain);
+            }
+            return;
+        }
+
+        let chain = match rack.chains.get_mut(chain) {
+            Some(chain) => chain,
+            None => return,
+        };
+        let zone = &mut chain.zone;
+        let key = |key: &i16| (*key).clamp(MIN_KEY, MAX_KEY);
+        let velocity = |velocity: &f32| velocity.clamp(0.0, 1.0);
+
+        match edit {
+            RackEdit::Rename(name) => chain.name = name.clone(),
+            RackEdit::SetLowKey(low) => {
+                zone.low_key = key(low);
+                zone.high_key = zone.high_key.max(zone.low_key);
+            }
+            RackEdit::SetHighKey(high) => {
+                zone.high_key = key(high);
+                zone.low_key = zone.low_key.min(zone.high_key);
+            }
+            RackEdit::SetMinVelocity(min) => {
+                zone.min_velocity = velocity(min);
+                zone.max_velocity = zone.max_velocity.max(zone.min_velocity);
+            }
+            RackEdit::SetMaxVelocity(max) => {
+                zone.max_velocity = velocity(max);
+                zone.min_velocity = zone.min_velocity.min(zone.max_velocity);
+            }
+            RackEdit::SetGain(db) => chain.gain_db = db.clamp(MIN_DB, MAX_CHAIN_DB),
+            RackEdit::SetPan(pan) => chain.pan = pan.clamp(-1.0, 1.0),
+            RackEdit::SetMuted(muted) => chain.muted = *muted,
+            RackEdit::SetSoloed(soloed) => chain.soloed = *soloed,
+            RackEdit::AddChain(_) | RackEdit::Remove => {}
+        }
+    }
+}
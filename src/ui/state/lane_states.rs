@@ -1,4 +1,4 @@
-use super::{ChannelBaseColor, UiEvent};
+use super::{ChannelBaseColor, ClipState, Localization, UiEvent};
 use std::ops::RangeBounds;
 use vizia::prelude::*;
 
@@ -177,6 +177,22 @@ impl LaneStates {
             .map(|(index, _)| index)
             .collect()
     }
+
+    /// Announce the name of the active lane after the selection moved to it.
+    fn announce_active_lane(&self, cx: &mut EventContext) {
+        if let Some(lane) = self.lanes.get(self.active_lane) {
+            let name = match &lane.name {
+                Some(name) => name.clone(),
+                None => format!("lane {}", self.active_lane),
+            };
+            let key = if lane.disabled {
+                "announce-lane-selected-disabled"
+            } else {
+                "announce-lane-selected"
+            };
+            cx.emit(UiEvent::Announce { key, args: vec![("lane", name)] });
+        }
+    }
 }
 
 impl Model for LaneStates {
@@ -205,6 +221,17 @@ impl Model for LaneStates {
                 }
 
                 self.select_lane(*index);
+                self.announce_active_lane(cx);
+            }
+            UiEvent::RenameLane(index, name) => {
+                if let Some(lane) = self.lanes.get_mut(*index) {
+                    lane.name = Some(name.clone());
+                }
+            }
+            UiEvent::SetLaneColor(index, color) => {
+                if let Some(lane) = self.lanes.get_mut(*index) {
+                    lane.color = Some((*color).into());
+                }
             }
             UiEvent::InsertLane => {
                 self.unselect_all_lanes();
@@ -245,12 +272,14 @@ impl Model for LaneStates {
                 if let Some(index) = self.index_moved_by(-1, self.active_lane) {
                     self.unselect_all_lanes();
                     self.select_lane(index);
+                    self.announce_active_lane(cx);
                 }
             }
             UiEvent::SelectLaneBelow => {
                 if let Some(index) = self.index_moved_by(1, self.active_lane) {
                     self.unselect_all_lanes();
                     self.select_lane(index);
+                    self.announce_active_lane(cx);
                 }
             }
             UiEvent::ActivateSelectedLanes => {
@@ -292,10 +321,91 @@ pub struct LaneState {
 
     /// Represents if the lane is currently selected.
     pub selected: bool,
+
+    /// Alternative sets of clips for this lane, of which only the active one
+    /// is on the timeline. This is empty until a second playlist is added.
+    pub playlists: Vec<LanePlaylist>,
+
+    /// The index of the playlist whose clips are on the timeline.
+    pub active_playlist: usize,
 }
 
 impl Default for LaneState {
     fn default() -> Self {
-        Self { name: None, color: None, height: None, disabled: false, selected: false }
+        Self {
+            name: None,
+            color: None,
+            height: None,
+            disabled: false,
+            selected: false,
+            playlists: Vec::new(),
+            active_playlist: 0,
+        }
+    }
+}
+
+/// An alternative set of clips for a lane.
+#[derive(Debug, Lens, Clone)]
+pub struct LanePlaylist {
+    /// The name of this playlist.
+    ///
+    /// This will be `None` if this just uses the default name.
+    pub name: Option<String>,
+
+    /// The clips of this playlist while it is not the active one. The clips
+    /// of the active playlist are on the timeline instead.
+    pub clips: Vec<ClipState>,
+}
+
+impl LanePlaylist {
+    /// The name of this playlist, or the default name for the playlist at
+    /// `index` if it has none.
+    pub fn display_name(&self, index: usize, localization: &Localization) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => localization.tr_args("playlist-default-name", &[("number", &(index + 1))]),
+        }
+    }
+    /// Move the clips to the new indices of their channels after channels
+    /// were removed, and drop the clips of the removed channels. See
+    /// `ClipState::with_new_channels()`.
+    pub fn remove_channels(&mut self, new_index: &[Option<usize>]) {
+        self.clips =
+            self.clips.iter().filter_map(|clip| clip.with_new_channels(new_index)).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::state::{AutomationClipState, ClipStart, ClipType};
+    use meadowlark_core_types::time::MusicalTime;
+
+    fn clip(name: &str, channel: usize) -> ClipState {
+        ClipState {
+            name: name.into(),
+            timeline_start: ClipStart::NotInTimeline,
+            length: MusicalTime::new(1, 0).into(),
+            channel,
+            muted: false,
+            effects: Vec::new(),
+            recording: None,
+            player_host_id: None,
+            type_: ClipType::Automation(AutomationClipState {}),
+        }
+    }
+
+    #[test]
+    fn playlists_lose_the_clips_of_removed_channels() {
+        let mut playlist = LanePlaylist {
+            name: None,
+            clips: vec![clip("a", 0), clip("b", 2), clip("c", 1), clip("d", 3)],
+        };
+        // Channel 2 of 4 is removed.
+        playlist.remove_channels(&[Some(0), Some(1), None, Some(2)]);
+
+        let clips: Vec<(&str, usize)> =
+            playlist.clips.iter().map(|clip| (clip.name.as_str(), clip.channel)).collect();
+        assert_eq!(clips, vec![("a", 0), ("c", 1), ("d", 2)]);
     }
 }
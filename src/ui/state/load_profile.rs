@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+/// How long each phase of loading a project took, logged once the audio graph
+/// of the project is built, to find out what makes large projects slow to
+/// open.
+#[derive(Debug)]
+pub struct LoadProfile {
+    started: Instant,
+    /// When the current phase started.
+    phase_started: Instant,
+    phases: Vec<(&'static str, Duration)>,
+    /// The number of plugins asked to be added to the graph, each of which
+    /// makes the engine compile the graph again.
+    plugins_hosted: usize,
+}
+
+impl LoadProfile {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self { started: now, phase_started: now, phases: Vec::new(), plugins_hosted: 0 }
+    }
+
+    /// End the current phase under the given name and start the next one.
+    pub fn phase(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.phases.push((name, now - self.phase_started));
+        self.phase_started = now;
+    }
+
+    pub fn add_plugins_hosted(&mut self, plugins: usize) {
+        self.plugins_hosted += plugins;
+    }
+
+    /// End the last phase, which waits for the engine to build the graph, and
+    /// log the timings.
+    pub fn finish(mut self) {
+        self.phase("graph");
+        log::info!("{}", self.report());
+    }
+
+    pub fn report(&self) -> String {
+        let phases: Vec<String> = self
+            .phases
+            .iter()
+            .map(|(name, duration)| format!("{} {:.1} ms", name, duration.as_secs_f64() * 1e3))
+            .collect();
+        format!(
+            "Loaded the project in {:.1} ms with {} plugins ({})",
+            self.started.elapsed().as_secs_f64() * 1e3,
+            self.plugins_hosted,
+            phases.join(", ")
+        )
+    }
+}
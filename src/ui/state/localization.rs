@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use vizia::prelude::*;
+
+use super::UiData;
+
+/// The locale that every other locale falls back to. Its translations must
+/// contain every key.
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// The translations that are built into the application, and the name of
+/// each language in that language.
+const LOCALES: [(&str, &str, &str); 2] = [
+    ("en-US", "English", include_str!("../resources/locales/en-US.ftl")),
+    ("de-DE", "Deutsch", include_str!("../resources/locales/de-DE.ftl")),
+];
+
+/// The translations of one locale.
+#[derive(Debug)]
+struct Bundle {
+    locale: &'static str,
+    name: &'static str,
+    messages: HashMap<String, String>,
+}
+
+/// Translates the user-facing strings of the UI.
+///
+/// Translations are written in a subset of the [`Fluent`] syntax: one
+/// `key = value` message per line, where indented lines continue the message
+/// of the line before (joined with a space), `#` starts a comment, and
+/// `{ $name }` is replaced by the argument with that name.
+///
+/// A message that is missing in the selected locale is looked up in the
+/// locale of the same language (e.g. `de-DE` for `de-AT`), then in
+/// `DEFAULT_LOCALE`. If it is missing there too, the key itself is shown.
+///
+/// [`Fluent`]: https://projectfluent.org/
+#[derive(Debug, Clone)]
+pub struct Localization {
+    locale: String,
+
+    /// The bundles to look messages up in, in order.
+    fallback_chain: Vec<usize>,
+
+    bundles: Rc<Vec<Bundle>>,
+}
+
+impl Localization {
+    pub fn new(locale: &str) -> Self {
+        let bundles = LOCALES
+            .iter()
+            .map(|(locale, name, source)| Bundle { locale, name, messages: parse_messages(source) })
+            .collect();
+
+        let mut localization =
+            Self { locale: String::new(), fallback_chain: Vec::new(), bundles: Rc::new(bundles) };
+        localization.set_locale(locale);
+        localization
+    }
+
+    /// The locale set in the environment of the process (e.g. `de_DE.UTF-8` in
+    /// `LANG` becomes `de-DE`), or `DEFAULT_LOCALE` if none is set.
+    pub fn system_locale() -> String {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty() && value != "C" && value != "POSIX")
+            .map(|value| value.split('.').next().unwrap_or_default().replace('_', "-"))
+            .unwrap_or_else(|| String::from(DEFAULT_LOCALE))
+    }
+
+    pub fn locale(&self) -> &str {
+        &self.locale
+    }
+
+    pub fn set_locale(&mut self, locale: &str) {
+        let language = locale.split('-').next().unwrap_or_default();
+        let find = |matches: &dyn Fn(&str) -> bool| {
+            self.bundles.iter().position(|bundle| matches(bundle.locale))
+        };
+
+        let mut fallback_chain = Vec::new();
+        for bundle in [
+            find(&|l| l.eq_ignore_ascii_case(locale)),
+            find(&|l| l.split('-').next().unwrap_or_default().eq_ignore_ascii_case(language)),
+            find(&|l| l == DEFAULT_LOCALE),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !fallback_chain.contains(&bundle) {
+                fallback_chain.push(bundle);
+            }
+        }
+
+        self.locale = locale.to_string();
+        self.fallback_chain = fallback_chain;
+    }
+
+    /// The locales that have translations, and the name of each language.
+    pub fn available_locales(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        self.bundles.iter().map(|bundle| (bundle.locale, bundle.name))
+    }
+
+    /// The message with the given key in the current locale.
+    pub fn tr(&self, key: &str) -> String {
+        self.tr_args(key, &[])
+    }
+
+    /// The message with the given key in the current locale, with every
+    /// `{ $name }` replaced by the argument with that name.
+    pub fn tr_args(&self, key: &str, args: &[(&str, &dyn ToString)]) -> String {
+        let message = match self
+            .fallback_chain
+            .iter()
+            .find_map(|bundle| self.bundles[*bundle].messages.get(key))
+        {
+            Some(message) => message,
+            None => {
+                log::warn!("Missing translation for \"{}\"", key);
+                return key.to_string();
+            }
+        };
+
+        substitute(message, args.iter().map(|(name, value)| (*name, value.to_string())))
+    }
+
+    /// The translation of a message that was made without knowing the
+    /// language, like an error.
+    pub fn tr_message(&self, message: &LocalizedMessage) -> String {
+        let args: Vec<(&str, &dyn ToString)> =
+            message.args.iter().map(|(name, value)| (*name, value as &dyn ToString)).collect();
+        self.tr_args(message.key, &args)
+    }
+}
+
+impl PartialEq for Localization {
+    fn eq(&self, other: &Self) -> bool {
+        // The bundles never change, so the locale decides what every message is.
+        self.locale == other.locale
+    }
+}
+
+/// A message for the user that is translated where it is shown, i.e. an error
+/// returned by code that doesn't know the language of the UI.
+///
+/// `Display` gives the message in `DEFAULT_LOCALE`, for the log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalizedMessage {
+    /// The key of the message.
+    pub key: &'static str,
+    /// The arguments of the message, see `Localization::tr_args()`.
+    pub args: Vec<(&'static str, String)>,
+}
+
+impl LocalizedMessage {
+    pub fn new(key: &'static str) -> Self {
+        Self { key, args: Vec::new() }
+    }
+
+    /// Add an argument to the message.
+    pub fn arg(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.args.push((name, value.to_string()));
+        self
+    }
+}
+
+impl fmt::Display for LocalizedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages = LOCALES
+            .iter()
+            .find(|(locale, _, _)| *locale == DEFAULT_LOCALE)
+            .map(|(_, _, source)| parse_messages(source))
+            .unwrap_or_default();
+        match messages.get(self.key) {
+            Some(message) => f.write_str(&substitute(message, self.args.iter().cloned())),
+            None => f.write_str(self.key),
+        }
+    }
+}
+
+/// Replace every `{ $name }` in a message with the argument with that name.
+fn substitute<'a>(message: &str, args: impl Iterator<Item = (&'a str, String)>) -> String {
+    let mut text = message.to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{ ${} }}", name), &value);
+    }
+    text
+}
+
+/// A lens to the translation of `key`, so the text of a view is updated when
+/// the language is changed.
+pub fn localized(key: &'static str) -> impl Lens<Source = UiData, Target = String> {
+    UiData::localization.map(move |localization| localization.tr(key))
+}
+
+fn parse_messages(source: &str) -> HashMap<String, String> {
+    let mut messages = HashMap::new();
+    let mut current: Option<(String, String)> = None;
+
+    for line in source.lines() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        // Indented lines continue the message before them.
+        if line.starts_with(char::is_whitespace) {
+            if let Some((_, value)) = &mut current {
+                if !line.trim().is_empty() {
+                    if !value.is_empty() {
+                        value.push(' ');
+                    }
+                    value.push_str(line.trim());
+                }
+            }
+            continue;
+        }
+
+        if let Some((key, value)) = current.take() {
+            messages.insert(key, value);
+        }
+
+        if let Some((key, value)) = line.split_once('=') {
+            current = Some((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    if let Some((key, value)) = current {
+        messages.insert(key, value);
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locales_have_the_same_keys() {
+        let (_, _, default_source) =
+            LOCALES.iter().find(|(locale, _, _)| *locale == DEFAULT_LOCALE).unwrap();
+        let default_messages = parse_messages(default_source);
+
+        for (locale, _, source) in LOCALES.iter() {
+            let messages = parse_messages(source);
+            let mut missing: Vec<&String> =
+                default_messages.keys().filter(|key| !messages.contains_key(*key)).collect();
+            let mut extra: Vec<&String> =
+                messages.keys().filter(|key| !default_messages.contains_key(*key)).collect();
+            missing.sort();
+            extra.sort();
+            assert!(
+                missing.is_empty() && extra.is_empty(),
+                "{} is missing {:?} and has extra keys {:?}",
+                locale,
+                missing,
+                extra
+            );
+        }
+    }
+
+    #[test]
+    fn messages_display_in_the_default_locale() {
+        let message = LocalizedMessage::new("error-route-loop").arg("channel", 2).arg("to", 5);
+        assert_eq!(message.to_string(), "Routing channel 2 to 5 makes a loop");
+
+        let unknown = LocalizedMessage::new("error-that-does-not-exist");
+        assert_eq!(unknown.to_string(), "error-that-does-not-exist");
+    }
+}
@@ -0,0 +1,83 @@
+use vizia::prelude::*;
+
+use crate::backend::dsp::loudness::{LoudnessReading, MIN_LUFS};
+
+/// If two consecutive readings are further apart than this (in beats), then the
+/// playhead was moved and the readings are not part of the same pass.
+const MAX_GAP_BEATS: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessPoint {
+    /// The position of this reading on the timeline.
+    pub beats: f64,
+    pub momentary: f32,
+    pub short_term: f32,
+}
+
+/// The loudness of the master bus over the course of the timeline.
+#[derive(Debug, Lens, Clone)]
+pub struct LoudnessHistoryState {
+    /// All recorded points, sorted by their position on the timeline.
+    #[lens(ignore)]
+    pub points: Vec<LoudnessPoint>,
+
+    /// The latest momentary loudness in LUFS.
+    pub momentary: f32,
+
+    /// The latest short-term loudness in LUFS.
+    pub short_term: f32,
+
+    #[lens(ignore)]
+    last_recorded_beats: Option<f64>,
+}
+
+impl LoudnessHistoryState {
+    /// Set the latest reading, regardless of whether the transport is playing.
+    pub fn set_latest(&mut self, reading: LoudnessReading) {
+        self.momentary = reading.momentary;
+        self.short_term = reading.short_term;
+    }
+
+    /// Record a reading measured while the transport was playing.
+    ///
+    /// Playing over a part of the timeline that already has a history replaces
+    /// the old points in that region.
+    pub fn record(&mut self, beats: f64, reading: LoudnessReading) {
+        let point =
+            LoudnessPoint { beats, momentary: reading.momentary, short_term: reading.short_term };
+
+        // Remove any old points between the last reading of this pass and this one.
+        let start = match self.last_recorded_beats {
+            Some(last) if beats > last && beats - last <= MAX_GAP_BEATS => {
+                self.points.partition_point(|p| p.beats <= last)
+            }
+            _ => self.points.partition_point(|p| p.beats < beats),
+        };
+        let end = self.points.partition_point(|p| p.beats <= beats);
+
+        self.points.splice(start..end, std::iter::once(point));
+
+        self.last_recorded_beats = Some(beats);
+    }
+
+    /// Called when the transport stops, so the next reading starts a new pass.
+    pub fn end_pass(&mut self) {
+        self.last_recorded_beats = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.last_recorded_beats = None;
+    }
+}
+
+impl Default for LoudnessHistoryState {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            momentary: MIN_LUFS,
+            short_term: MIN_LUFS,
+            last_recorded_beats: None,
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use vizia::prelude::*;
+
+use super::{moved_effect_index, AutomationTarget, EffectSlot, ParameterValue, UiState};
+use crate::backend::macro_knob::{MacroCurve, MacroMapping, MAX_MACRO_KNOBS};
+
+impl Data for MacroCurve {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Data for MacroMapping {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// A knob of a channel that turns parameters of the effects of the channel.
+/// It can be automated with an `AutomationTarget::Macro` lane.
+#[derive(Debug, Lens, Clone, PartialEq, Data, Serialize, Deserialize)]
+pub struct MacroKnobState {
+    pub name: String,
+    /// The position of the knob, from 0.0 to 1.0.
+    pub value: f64,
+    pub targets: Vec<MacroKnobTarget>,
+}
+
+/// A parameter that follows a macro knob.
+#[derive(Debug, Lens, Clone, Copy, PartialEq, Data, Serialize, Deserialize)]
+pub struct MacroKnobTarget {
+    /// The index of the effect in the effects of the channel.
+    pub effect: usize,
+    pub id: u32,
+    pub mapping: MacroMapping,
+}
+
+impl MacroKnobState {
+    pub fn new(name: String) -> Self {
+        Self { name, value: 0.0, targets: Vec::new() }
+    }
+}
+
+/// A change to the macro knobs of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MacroKnobEdit {
+    /// Add a knob with the given name and nothing mapped to it.
+    AddKnob(String),
+    RemoveKnob(usize),
+    Rename(usize, String),
+    /// Turn a knob, which moves the parameters mapped to it.
+    SetValue(usize, f64),
+    /// Map a parameter to a knob over its whole range, unless it already is.
+    MapParameter {
+        knob: usize,
+        effect: usize,
+        id: u32,
+    },
+    UnmapParameter {
+        knob: usize,
+        target: usize,
+    },
+    /// Set the value of a parameter when the knob is all the way down.
+    SetMin {
+        knob: usize,
+        target: usize,
+        min: f64,
+    },
+    /// Set the value of a parameter when the knob is all the way up.
+    SetMax {
+        knob: usize,
+        target: usize,
+        max: f64,
+    },
+    SetCurve {
+        knob: usize,
+        target: usize,
+        curve: MacroCurve,
+    },
+}
+
+impl UiState {
+    pub fn edit_macro_knobs(&mut self, index: usize, edit: &MacroKnobEdit) {
+        let knobs = match self.channels.get_mut(index) {
+            Some(channel) => &mut channel.macro_knobs,
+            None => return,
+        };
+
+        match edit {
+            MacroKnobEdit::AddKnob(name) => {
+                if knobs.len() < MAX_MACRO_KNOBS {
+                    knobs.push(MacroKnobState::new(name.clone()));
+                }
+            }
+            MacroKnobEdit::RemoveKnob(knob) => {
+                if *knob < knobs.len() {
+                    knobs.remove(*knob);
+                    self.on_macro_knob_removed_automation(index, *knob);
+                }
+            }
+            MacroKnobEdit::Rename(knob, name) => {
+                if let Some(knob) = knobs.get_mut(*knob) {
+                    knob.name = name.clone();
+                }
+            }
+            MacroKnobEdit::SetValue(knob, value) => {
+                if let Some(knob) = knobs.get_mut(*knob) {
+                    knob.value = value.clamp(0.0, 1.0);
+                }
+                self.apply_macro_knob(index, *knob);
+            }
+            MacroKnobEdit::MapParameter { knob, effect, id } => {
+                if let Some(knob) = knobs.get_mut(*knob) {
+                    if !knob.targets.iter().any(|t| t.effect == *effect && t.id == *id) {
+                        knob.targets.push(MacroKnobTarget {
+                            effect: *effect,
+                            id: *id,
+                            mapping: MacroMapping::default(),
+                        });
+                    }
+                }
+            }
+            MacroKnobEdit::UnmapParameter { knob, target } => {
+                if let Some(knob) = knobs.get_mut(*knob) {
+                    if *target < knob.targets.len() {
+                        knob.targets.remove(*target);
+                    }
+                }
+            }
+            MacroKnobEdit::SetMin { knob, target, min } => {
+                if let Some(target) = knobs.get_mut(*knob).and_then(|k| k.targets.get_mut(*target))
+                {
+                    target.mapping.min = min.clamp(0.0, 1.0);
+                }
+                self.apply_macro_knob(index, *knob);
+            }
+            MacroKnobEdit::SetMax { knob, target, max } => {
+                if let Some(target) = knobs.get_mut(*knob).and_then(|k| k.targets.get_mut(*target))
+                {
+                    target.mapping.max = max.clamp(0.0, 1.0);
+                }
+                self.apply_macro_knob(index, *knob);
+            }
+            MacroKnobEdit::SetCurve { knob, target, curve } => {
+                if let Some(target) = knobs.get_mut(*knob).and_then(|k| k.targets.get_mut(*target))
+                {
+                    target.mapping.curve = *curve;
+                }
+                self.apply_macro_knob(index, *knob);
+            }
+        }
+    }
+
+    /// Give the parameters mapped to a knob the values for its position.
+    pub fn apply_macro_knob(&mut self, index: usize, knob: usize) {
+        let knob = match self.channels.get(index).and_then(|c| c.macro_knobs.get(knob)) {
+            Some(knob) => knob.clone(),
+            None => return,
+        };
+        for target in knob.targets.iter() {
+            let slot = EffectSlot { channel: index, effect: target.effect };
+            if let Some(effect) = self.external_effect_mut(slot) {
+                let value = target.mapping.value(knob.value);
+                effect.set_parameter_values(&[ParameterValue { id: target.id, value }]);
+            }
+        }
+    }
+
+    /// Follow the effects of a channel to their new slots after one of them
+    /// was moved.
+    pub fn on_effect_moved_macro_knobs(&mut self, channel: usize, from: usize, to: usize) {
+        if let Some(channel) = self.channels.get_mut(channel) {
+            for target in channel.macro_knobs.iter_mut().flat_map(|knob| knob.targets.iter_mut()) {
+                target.effect = moved_effect_index(target.effect, from, to);
+            }
+        }
+    }
+
+    /// Forget the parameters of an effect that was removed, and follow the
+    /// effects after it to their new slots.
+    pub fn on_effect_removed_macro_knobs(&mut self, channel: usize, effect: usize) {
+        let knobs = match self.channels.get_mut(channel) {
+            Some(channel) => &mut channel.macro_knobs,
+            None => return,
+        };
+        for knob in knobs.iter_mut() {
+            knob.targets.retain(|target| target.effect != effect);
+            for target in knob.targets.iter_mut() {
+                if target.effect > effect {
+                    target.effect -= 1;
+                }
+            }
+        }
+    }
+
+    /// Forget the lane of a knob that was removed, and follow the knobs after
+    /// it to their new indexes.
+    fn on_macro_knob_removed_automation(&mut self, channel: usize, knob: usize) {
+        let lanes = match self.channels.get_mut(channel) {
+            Some(channel) => &mut channel.automation_lanes,
+            None => return,
+        };
+        lanes.retain(|lane| lane.target != AutomationTarget::Macro(knob));
+        for lane in lanes.iter_mut() {
+            if let AutomationTarget::Macro(k) = &mut lane.target {
+                if *k > knob {
+                    *k -= 1;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use vizia::prelude::*;
+
+use super::{script, LocalizedMessage};
+
+/// The directory that macros are saved in. Macros are available in every
+/// project.
+pub const GLOBAL_MACROS_DIR: &str = "macros";
+
+/// Macros can be bound to Ctrl+Alt+1 up to this number.
+pub const MAX_MACRO_SHORTCUT: u32 = 9;
+
+const MACRO_EXTENSION: &str = "macro";
+
+/// The first line of a macro file can hold its shortcut, as a comment so the
+/// file is still a valid script.
+const SHORTCUT_PREFIX: &str = "# shortcut =";
+
+/// A script that was recorded from what was done in the UI, or written by
+/// hand. `$1`, `$2`... in its lines are replaced with the arguments it is run
+/// with.
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct Macro {
+    pub name: String,
+    /// The number that runs the macro with Ctrl+Alt.
+    pub shortcut: Option<u32>,
+    pub lines: Vec<String>,
+}
+
+impl Macro {
+    pub fn to_text(&self) -> String {
+        let shortcut = self.shortcut.map(|n| format!("{} {}", SHORTCUT_PREFIX, n));
+        shortcut.into_iter().chain(self.lines.iter().cloned()).collect::<Vec<_>>().join("\n")
+    }
+
+    pub fn from_text(name: String, text: &str) -> Self {
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        let shortcut = lines
+            .first()
+            .and_then(|line| line.strip_prefix(SHORTCUT_PREFIX))
+            .and_then(|n| n.trim().parse().ok())
+            .filter(|n| (1..=MAX_MACRO_SHORTCUT).contains(n));
+        if shortcut.is_some() {
+            lines.remove(0);
+        }
+        Self { name, shortcut, lines }
+    }
+
+    /// The number of arguments the macro takes, i.e. the highest `$n` in it.
+    pub fn parameters(&self) -> usize {
+        (1..=9)
+            .rev()
+            .find(|n| self.lines.iter().any(|line| line.contains(&format!("${}", n))))
+            .unwrap_or(0)
+    }
+
+    /// The lines of the macro with the arguments filled in.
+    pub fn expand(&self, args: &[String]) -> Result<Vec<String>, LocalizedMessage> {
+        let parameters = self.parameters();
+        if args.len() < parameters {
+            return Err(LocalizedMessage::new("error-macro-arguments")
+                .arg("name", &self.name)
+                .arg("parameters", parameters)
+                .arg("args", args.len()));
+        }
+
+        Ok(self
+            .lines
+            .iter()
+            .map(|line| {
+                (1..=parameters).rev().fold(line.clone(), |line, n| {
+                    line.replace(&format!("${}", n), &script::quote(&args[n - 1]))
+                })
+            })
+            .collect())
+    }
+
+    fn global_path(name: &str) -> PathBuf {
+        Path::new(GLOBAL_MACROS_DIR).join(format!("{}.{}", name, MACRO_EXTENSION))
+    }
+
+    pub fn save_global(&self) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(GLOBAL_MACROS_DIR)?;
+        std::fs::write(Self::global_path(&self.name), self.to_text())?;
+        Ok(())
+    }
+
+    pub fn delete_global(name: &str) -> Result<(), Box<dyn Error>> {
+        std::fs::remove_file(Self::global_path(name))?;
+        Ok(())
+    }
+
+    /// Load all macros in the macros directory, sorted by name. Returns an
+    /// empty list if the directory doesn't exist yet.
+    pub fn load_global() -> Vec<Macro> {
+        let entries = match std::fs::read_dir(GLOBAL_MACROS_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut macros: Vec<Macro> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != MACRO_EXTENSION {
+                    return None;
+                }
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => Some(Macro::from_text(name, &text)),
+                    Err(e) => {
+                        log::error!("Failed to read macro {:?}: {}", &path, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        macros.sort_by(|a, b| a.name.cmp(&b.name));
+        macros
+    }
+}
+
+#[derive(Debug, Lens, Clone, Default)]
+pub struct MacroState {
+    pub macros: Vec<Macro>,
+
+    /// The macro whose lines are shown in the console.
+    pub editing: Option<usize>,
+
+    /// True while the changes made in the UI are being recorded.
+    pub recording: bool,
+
+    /// The lines recorded so far.
+    #[lens(ignore)]
+    pub recorded: Vec<String>,
+}
+
+impl MacroState {
+    /// The index of the macro bound to the given shortcut.
+    pub fn with_shortcut(&self, shortcut: u32) -> Option<usize> {
+        self.macros.iter().position(|m| m.shortcut == Some(shortcut))
+    }
+
+    /// A name that no macro has yet, made from the given one, e.g. "Macro 2".
+    pub fn unused_name(&self, base: &str) -> String {
+        (1..)
+            .map(|n| format!("{} {}", base, n))
+            .find(|name| self.macros.iter().all(|m| &m.name != name))
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+use vizia::prelude::*;
+
+use crate::backend::dsp::MIN_DB;
+use crate::backend::metronome_plug::{DEFAULT_METRONOME_DB, MAX_COUNT_IN_BARS};
+
+/// The sound of the clicks of the metronome.
+#[derive(Debug, Clone, PartialEq, Data)]
+pub enum MetronomeSound {
+    Beep,
+    Woodblock,
+    /// Audio files for the first beat of each bar and for the other beats.
+    Samples {
+        accent: PathBuf,
+        beat: PathBuf,
+    },
+}
+
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct MetronomeState {
+    /// Whether the metronome clicks while the transport plays.
+    pub enabled: bool,
+    pub sound: MetronomeSound,
+    pub level_db: f32,
+    /// The bars that are clicked before a recording starts, or 0 to start
+    /// right away.
+    pub count_in_bars: u32,
+}
+
+impl MetronomeState {
+    pub fn set_level_db(&mut self, db: f32) {
+        self.level_db = db.clamp(MIN_DB, 0.0);
+    }
+
+    pub fn set_count_in_bars(&mut self, bars: u32) {
+        self.count_in_bars = bars.min(MAX_COUNT_IN_BARS);
+    }
+}
+
+impl Default for MetronomeState {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sound: MetronomeSound::Beep,
+            level_db: DEFAULT_METRONOME_DB,
+            count_in_bars: 0,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use vizia::prelude::*;
+
+use super::{PianoRollNote, UiState};
+use crate::backend::midi_capture_plug::CapturedNote;
+use crate::backend::midi_transform::{
+    KeyZone, MidiTransform, TransformNote, VelocityCurve, ALL_CHANNELS, MAX_KEY, MAX_ZONES, MIN_KEY,
+};
+
+impl Data for MidiTransform {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// A change to the MIDI input processing of a channel. Keys are MIDI note
+/// numbers and velocities are from 0.0 to 1.0.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MidiInputEdit {
+    /// Let through only the given MIDI channel (0 to 15), or all of them with
+    /// `None`.
+    SetChannel(Option<i16>),
+    /// Raising the lowest key above the highest raises the highest too, and
+    /// the other way around.
+    SetLowKey(i16),
+    SetHighKey(i16),
+    SetMinVelocity(f32),
+    SetMaxVelocity(f32),
+    SetTranspose(i16),
+    SetVelocityCurve(VelocityCurve),
+    /// Add a zone covering the whole keyboard, to be narrowed down.
+    AddZone,
+    SetZoneLowKey(usize, i16),
+    SetZoneHighKey(usize, i16),
+    SetZoneTranspose(usize, i16),
+    RemoveZone(usize),
+    Reset,
+}
+
+impl UiState {
+    pub fn edit_midi_input(&mut self, index: usize, edit: &MidiInputEdit) {
+        let transform = match self.channels.get_mut(index) {
+            Some(channel) => &mut channel.midi_input,
+            None => return,
+        };
+        let key = |key: &i16| (*key).clamp(MIN_KEY, MAX_KEY);
+        let transpose = |semitones: &i16| (*semitones).clamp(-MAX_KEY, MAX_KEY);
+        let velocity = |velocity: &f32| velocity.clamp(0.0, 1.0);
+
+        match edit {
+            MidiInputEdit::SetChannel(channel) => {
+                transform.channels = match channel {
+                    Some(channel) => 1 << (*channel).clamp(0, 15),
+                    None => ALL_CHANNELS,
+                };
+            }
+            MidiInputEdit::SetLowKey(low) => {
+                transform.low_key = key(low);
+                transform.high_key = transform.high_key.max(transform.low_key);
+            }
+            MidiInputEdit::SetHighKey(high) => {
+                transform.high_key = key(high);
+                transform.low_key = transform.low_key.min(transform.high_key);
+            }
+            MidiInputEdit::SetMinVelocity(min) => {
+                transform.min_velocity = velocity(min);
+                transform.max_velocity = transform.max_velocity.max(transform.min_velocity);
+            }
+            MidiInputEdit::SetMaxVelocity(max) => {
+                transform.max_velocity = velocity(max);
+                transform.min_velocity = transform.min_velocity.min(transform.max_velocity);
+            }
+            MidiInputEdit::SetTranspose(semitones) => {
+                transform.transpose = transpose(semitones);
+            }
+            MidiInputEdit::SetVelocityCurve(curve) => {
+                transform.velocity_curve = *curve;
+            }
+            MidiInputEdit::AddZone => {
+                if transform.zones.len() < MAX_ZONES {
+                    transform.zones.push(KeyZone {
+                        low_key: MIN_KEY,
+                        high_key: MAX_KEY,
+                        transpose: 0,
+                    });
+                }
+            }
+            MidiInputEdit::SetZoneLowKey(zone, low) => {
+                if let Some(zone) = transform.zones.get_mut(*zone) {
+                    zone.low_key = key(low);
+                    zone.high_key = zone.high_key.max(zone.low_key);
+                }
+            }
+            MidiInputEdit::SetZoneHighKey(zone, high) => {
+                if let Some(zone) = transform.zones.get_mut(*zone) {
+                    zone.high_key = key(high);
+                    zone.low_key = zone.low_key.min(zone.high_key);
+                }
+            }
+            MidiInputEdit::SetZoneTranspose(zone, semitones) => {
+                if let Some(zone) = transform.zones.get_mut(*zone) {
+                    zone.transpose = transpose(semitones);
+                }
+            }
+            MidiInputEdit::RemoveZone(zone) => {
+                if *zone < transform.zones.len() {
+                    transform.zones.remove(*zone);
+                }
+            }
+            MidiInputEdit::Reset => {
+                *transform = MidiTransform::default();
+            }
+        }
+    }
+
+    /// The notes of a captured performance as a channel hears them, after its
+    /// MIDI input processing. A note that is layered comes out once for each
+    /// zone it is in.
+    pub fn process_captured_notes(
+        &self,
+        index: usize,
+        notes: &[CapturedNote],
+        to_note: impl Fn(&CapturedNote, TransformNote) -> PianoRollNote,
+    ) -> Vec<PianoRollNote> {
+        let transform = self.channels.get(index).map(|channel| &channel.midi_input);
+        notes
+            .iter()
+            .flat_map(|note| {
+                let input =
+                    TransformNote { channel: note.channel, key: note.key, velocity: note.velocity };
+                let out: Vec<TransformNote> = match transform {
+                    Some(transform) => {
+                        transform.process(input, true).into_iter().map(|(_, n)| n).collect()
+                    }
+                    None => vec![input],
+                };
+                out.into_iter().map(|out| to_note(note, out)).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,178 @@
+use vizia::prelude::*;
+
+use super::{ChannelState, LocalizedMessage, MidiOutputRoute, RecordInput, UiState};
+use crate::backend::graph_interface::PluginHost;
+use crate::backend::midi_out::{
+    MidiOutHandle, MidiOutMessage, MAX_LATENCY_OFFSET_MS, MIDI_CHANNELS,
+};
+use crate::backend::midi_track_plug::{MidiTrackPlugHandle, PlayedNote};
+
+/// A MIDI output device that channels can send to.
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct MidiOutDeviceState {
+    pub name: String,
+
+    /// How much later (or earlier, if negative) MIDI is sent to the device,
+    /// to line it up with the rest of the project.
+    pub latency_offset_ms: f64,
+}
+
+impl MidiOutputRoute {
+    /// The program and bank messages that select the sound of the route, in
+    /// the order they are sent. Bank select only takes effect with the
+    /// program change that follows it.
+    pub fn sound_messages(&self) -> Vec<MidiOutMessage> {
+        let channel = self.channel;
+        let bank = self.bank.map(|bank| MidiOutMessage::BankSelect { channel, bank });
+        let program =
+            self.program.map(|program| MidiOutMessage::ProgramChange { channel, program });
+        bank.into_iter().chain(program).collect()
+    }
+
+    /// A note the channel played, as a message on the channel of the route.
+    /// Returns `None` for keys outside the MIDI range.
+    pub fn note_message(&self, note: &PlayedNote) -> Option<MidiOutMessage> {
+        let channel = self.channel;
+        let key = u8::try_from(note.key).ok().filter(|key| *key < 128)?;
+        Some(if note.on {
+            // A note on with a velocity of 0 would be a note off.
+            let velocity = (note.velocity * 127.0).round().clamp(1.0, 127.0) as u8;
+            MidiOutMessage::NoteOn { channel, key, velocity }
+        } else {
+            MidiOutMessage::NoteOff { channel, key }
+        })
+    }
+}
+
+impl UiState {
+    /// Send a channel's MIDI to a device, or stop sending it with `None`. The
+    /// route keeps its MIDI channel and sound when it moves to another device.
+    pub fn set_midi_output(&mut self, index: usize, device: Option<String>) {
+        if let Some(channel) = self.channels.get_mut(index) {
+            channel.midi_output = match (device, channel.midi_output.take()) {
+                (Some(device), Some(route)) => Some(MidiOutputRoute { device, ..route }),
+                (Some(device), None) => Some(MidiOutputRoute::new(device)),
+                (None, _) => None,
+            };
+        }
+    }
+
+    /// Change the MIDI channel, program or bank of a channel's route.
+    pub fn edit_midi_output(&mut self, index: usize, edit: impl FnOnce(&mut MidiOutputRoute)) {
+        if let Some(route) = self.channels.get_mut(index).and_then(|c| c.midi_output.as_mut()) {
+            edit(route);
+            route.channel = route.channel.min(MIDI_CHANNELS - 1);
+            route.program = route.program.map(|program| program.min(127));
+            route.bank = route.bank.map(|bank| bank.min(16383));
+        }
+    }
+
+    /// Send the notes the MIDI track plugins played to the devices their
+    /// channels are routed to. The notes of channels without a route are
+    /// dropped.
+    pub fn send_played_notes(
+        &self,
+        plugin_host: &mut PluginHost,
+        midi_out_handle: &mut Option<MidiOutHandle>,
+    ) {
+        for (index, midi) in self.midi_channels().into_iter().enumerate() {
+            let host_id = self.channels[index].midi_track_host_id.filter(|_| midi);
+            let track = match host_id
+                .and_then(|id| plugin_host.handle_mut(id))
+                .and_then(|h| h.internal.as_mut())
+                .and_then(|h| h.downcast_mut::<MidiTrackPlugHandle>())
+            {
+                Some(track) => track,
+                None => continue,
+            };
+            let route = self.channels[index].midi_output.as_ref();
+            for note in track.poll_played() {
+                if let (Some(route), Some(midi_out_handle)) = (route, midi_out_handle.as_mut()) {
+                    if let Some(message) = route.note_message(&note) {
+                        midi_out_handle.send_at(&route.device, note.at, message);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn set_midi_out_latency(&mut self, device: &str, ms: f64) -> Option<f64> {
+        let ms = ms.clamp(-MAX_LATENCY_OFFSET_MS, MAX_LATENCY_OFFSET_MS);
+        let state = self.midi_out_devices.iter_mut().find(|d| d.name == device)?;
+        state.latency_offset_ms = ms;
+        Some(ms)
+    }
+
+    /// Replace the list of devices, keeping the latency offsets of the ones
+    /// that are still there.
+    pub fn set_midi_out_devices(&mut self, names: Vec<String>) {
+        self.midi_out_devices = names
+            .into_iter()
+            .map(|name| {
+                let latency_offset_ms = self
+                    .midi_out_devices
+                    .iter()
+                    .find(|d| d.name == name)
+                    .map_or(0.0, |d| d.latency_offset_ms);
+                MidiOutDeviceState { name, latency_offset_ms }
+            })
+            .collect();
+    }
+
+    /// Add a channel next to a MIDI channel that records the audio coming
+    /// back from its hardware, armed together with it. Returns the index of
+    /// the new channel.
+    pub fn add_midi_return_channel(&mut self, index: usize) -> Result<usize, LocalizedMessage> {
+        let (midi_channel, parent) = match self.channels.get(index) {
+            Some(channel) => match channel.parent_channel {
+                Some(parent) => (channel, parent),
+                None => return Err(LocalizedMessage::new("error-midi-master")),
+            },
+            None => {
+                return Err(LocalizedMessage::new("error-channel-missing").arg("channel", index))
+            }
+        };
+
+        let channel = ChannelState {
+            name: format!("{} Return", midi_channel.name),
+            color: midi_channel.color.clone(),
+            selected: true,
+            record_input: RecordInput::default(),
+            ..Default::default()
+        };
+        let return_channel = self.add_channel(channel, parent)?;
+        self.link_arm(&[index, return_channel]);
+        Ok(return_channel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn played(on: bool, key: i16, velocity: f32) -> PlayedNote {
+        PlayedNote { at: Instant::now(), on, channel: 0, key, velocity }
+    }
+
+    #[test]
+    fn notes_go_out_on_the_channel_of_the_route() {
+        let route = MidiOutputRoute { channel: 9, ..MidiOutputRoute::new("Synth".into()) };
+
+        assert_eq!(
+            route.note_message(&played(true, 60, 1.0)),
+            Some(MidiOutMessage::NoteOn { channel: 9, key: 60, velocity: 127 })
+        );
+        assert_eq!(
+            route.note_message(&played(false, 60, 0.0)),
+            Some(MidiOutMessage::NoteOff { channel: 9, key: 60 })
+        );
+        // Quiet notes still sound instead of turning into note offs.
+        assert_eq!(
+            route.note_message(&played(true, 60, 0.0)),
+            Some(MidiOutMessage::NoteOn { channel: 9, key: 60, velocity: 1 })
+        );
+        assert_eq!(route.note_message(&played(true, 128, 1.0)), None);
+        assert_eq!(route.note_message(&played(true, -1, 1.0)), None);
+    }
+}
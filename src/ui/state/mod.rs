@@ -1,59 +1,239 @@
+use basedrop::Shared;
 use crossbeam::channel::Receiver;
-use dropseed::plugin::PluginSaveState;
 use dropseed::plugin::{HostInfo, ParamID, PluginInstanceID};
+use dropseed::plugin::{PluginPreset, PluginSaveState};
 use dropseed::{
-    transport::TransportHandle, ActivateEngineSettings, ActivatePluginError, DSEngineEvent,
-    DSEngineHandle, DSEngineRequest, EdgeReq, EdgeReqPortID, EngineActivatedInfo,
-    EngineDeactivatedInfo, ModifyGraphRequest, ModifyGraphRes, ParamModifiedInfo,
-    PluginActivationStatus, PluginEvent, PluginHandle, PluginIDReq, PluginScannerEvent, PortType,
-    RescanPluginDirectoriesRes,
+    transport::{LoopState, TransportHandle},
+    ActivateEngineSettings, ActivatePluginError, DSEngineEvent, DSEngineHandle, DSEngineRequest,
+    EdgeReq, EdgeReqPortID, EngineActivatedInfo, EngineDeactivatedInfo, ModifyGraphRequest,
+    ModifyGraphRes, ParamModifiedInfo, PluginActivationStatus, PluginEvent, PluginHandle,
+    PluginIDReq, PluginScannerEvent, PortType, RescanPluginDirectoriesRes, ScannedPluginKey,
 };
 
 use fnv::FnvHashMap;
-use meadowlark_core_types::time::{MusicalTime, SampleRate};
-use pcm_loader::ResampleQuality;
+use meadowlark_core_types::time::{Frames, MusicalTime, SampleRate, Seconds, SuperFrames};
+use pcm_loader::PcmRAM;
 use smallvec::SmallVec;
 use std::error::Error;
-use std::{fmt::Debug, path::PathBuf};
+use std::ops::Range;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
 use vizia::prelude::*;
 
-use crate::backend::resource_loader::{PcmKey, ResourceLoader};
+use crate::backend::amp_sim_plug::AmpSimPlugFactory;
+use crate::backend::automation_plug::{AutomationPlugFactory, AUTOMATION_PLUG_RDN};
+use crate::backend::capture_plug::CapturePlugFactory;
+use crate::backend::channel_graph::{channel_edges, edge_changes, Edge, GraphNodes};
+use crate::backend::collision::{analyze_collisions, mix_into_mono};
+use crate::backend::compressor_plug::CompressorPlugFactory;
+use crate::backend::de_esser_plug::DeEsserPlugFactory;
+use crate::backend::delay_plug::DelayPlugFactory;
+use crate::backend::denoise::{self, DenoiseSettings, NoiseProfile, DEFAULT_FFT_SIZE};
+use crate::backend::disk_writer::{DiskWriterPool, RecordingTrackId, DEFAULT_DISK_WRITER_THREADS};
+use crate::backend::drum_rack_plug::{DrumRackPlugFactory, DRUM_RACK_PLUG_RDN};
+use crate::backend::dsp::WhiteNoise;
+use crate::backend::eq_plug::{EqPlugFactory, EqPlugHandle, EQ_PLUG_RDN};
+use crate::backend::fader_plug::{FaderPlugFactory, FADER_PLUG_RDN};
+use crate::backend::graph_interface::{edge_request, PluginAdded, PluginHost};
+use crate::backend::graph_swap::GraphSwap;
+use crate::backend::harmonizer_plug::HarmonizerPlugFactory;
+use crate::backend::input_meter::{temp_spawn_cpal_default_input_meter, InputMeterHandle};
+use crate::backend::loudness_plug::{LoudnessPlugFactory, LoudnessPlugHandle, LOUDNESS_PLUG_RDN};
+use crate::backend::metronome_plug::{
+    ClickSound, MetronomePlugFactory, MetronomePlugHandle, METRONOME_PLUG_RDN,
+};
+use crate::backend::midi_capture_plug::{
+    MidiCapturePlugFactory, MidiCapturePlugHandle, MIDI_CAPTURE_PLUG_RDN,
+};
+use crate::backend::midi_out::MidiOutHandle;
+use crate::backend::midi_track_plug::{MidiTrackPlugFactory, MIDI_TRACK_PLUG_RDN};
+use crate::backend::offline_render::ExportOptions;
+use crate::backend::param_state;
+use crate::backend::record_plug::{
+    RecordPlugFactory, RecordPlugHandle, MAX_RECORDED_CHANNELS, RECORD_PLUG_RDN,
+};
+use crate::backend::resource_loader::{PcmKey, ResampleQuality, ResourceLoader};
+use crate::backend::reverb_plug::ReverbPlugFactory;
 use crate::backend::sample_browser_plug::{
     SampleBrowserPlugFactory, SampleBrowserPlugHandle, SAMPLE_BROWSER_PLUG_RDN,
 };
-use crate::backend::system_io::{self, SystemIOStreamHandle};
+use crate::backend::sample_edit::SampleEditor;
+use crate::backend::signal_gen_plug::SignalGenPlugFactory;
+use crate::backend::silence::{self, StripSilenceSettings};
+use crate::backend::system_io::{
+    self, OutputBuffering, OutputChannelMap, SystemIOStreamHandle, ENGINE_OUT_CHANNELS,
+};
+use crate::backend::tap_tempo::TapTempo;
+use crate::backend::tempo_bus::TempoBus;
+use crate::backend::tempo_map::TempoMap;
+use crate::backend::time_signature::{TimeSignature, TimeSignatureTrack};
+use crate::backend::timeline_track::{TimelineTrackPlugFactory, TIMELINE_TRACK_PLUG_RDN};
+use crate::backend::tuner_plug::{TunerPlugFactory, TunerPlugHandle, TUNER_PLUG_RDN};
+use crate::backend::vocoder_plug::VocoderPlugFactory;
+use crate::util::{write_wav_f32, Unit};
 
+mod arm;
+mod arranger;
+mod audio_recording;
+mod automation;
 mod browser;
 mod channel;
+mod channel_mix;
 mod clip;
+mod clipboard;
+mod collision;
+mod console;
 mod core_types;
+mod drum_rack;
+mod eq;
 mod event;
+mod export;
+mod fx_rack;
+mod history;
 mod hrack_effect;
+mod input_meter;
+mod input_quantize;
+mod inspector;
+mod instrument_rack;
 mod lane_states;
+mod load_profile;
+mod localization;
+mod loudness;
+mod macro_knob;
+mod macros;
+mod metronome;
+mod midi_input;
+mod midi_out;
+mod musical_typing;
+mod onscreen_instruments;
 mod panel;
+mod parameter_tools;
+mod playlist;
+mod plugins;
+mod project_file;
+#[cfg(test)]
+mod project_file_fuzz;
+mod project_ui;
+mod routing;
+mod sample_editor;
+pub mod script;
 mod timeline_grid;
+mod transaction;
+mod tuner;
+mod workspace;
 
+pub use arranger::*;
+pub use audio_recording::*;
+pub use automation::*;
 pub use browser::*;
 pub use channel::*;
+pub use channel_mix::*;
 pub use clip::*;
+pub use clipboard::*;
+pub use collision::*;
+pub use console::*;
 pub use core_types::*;
+pub use drum_rack::*;
+pub use eq::*;
 pub use event::*;
+pub use export::*;
+pub use fx_rack::*;
+pub use history::*;
 pub use hrack_effect::*;
+pub use input_meter::*;
+pub use input_quantize::*;
+pub use inspector::*;
+pub use instrument_rack::*;
 pub use lane_states::*;
+pub use load_profile::*;
+pub use localization::*;
+pub use loudness::*;
+pub use macro_knob::*;
+pub use macros::*;
+pub use metronome::*;
+pub use midi_input::*;
+pub use midi_out::*;
+pub use musical_typing::*;
+pub use onscreen_instruments::*;
 pub use panel::*;
+pub use parameter_tools::*;
+pub use plugins::*;
+pub use project_file::*;
+pub use project_ui::*;
+pub use routing::*;
+pub use sample_editor::*;
 pub use timeline_grid::*;
+pub use transaction::*;
+pub use tuner::*;
+pub use workspace::*;
 
 // TODO: Have these be configurable.
 const MIN_FRAMES: u32 = 1;
 const MAX_FRAMES: u32 = 512;
 const GRAPH_IN_CHANNELS: u16 = 2;
-const GRAPH_OUT_CHANNELS: u16 = 2;
+/// The master bus and the channels with hardware outputs, in pairs.
+const GRAPH_OUT_CHANNELS: u16 = ENGINE_OUT_CHANNELS as u16;
+
+/// TODO: Let the user choose where projects are saved.
+const PROJECT_PATH: &str = "project.meadowlark";
+
+/// TODO: Let the user choose where exports are saved.
+const EXPORT_PATH: &str = "export.wav";
+const EXPORT_STEMS_DIR: &str = "stems";
+
+/// How often to try to open an audio device again after it was lost.
+const DEVICE_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many plugins of a project that is loaded lazily are added to the audio
+/// graph on each poll.
+const PLUGINS_HOSTED_PER_POLL: usize = 4;
 
 pub struct EngineHandles {
     ds_handle: DSEngineHandle,
 
     activated_info: Option<ActivatedEngineInfo>,
     sample_browser_plug_handle: Option<PluginHandle>,
+    tuner_plug_handle: Option<PluginHandle>,
+    loudness_plug_handle: Option<PluginHandle>,
+    eq_plug_handle: Option<PluginHandle>,
+    midi_capture_plug_handle: Option<PluginHandle>,
+    record_plug_handle: Option<PluginHandle>,
+    metronome_plug_handle: Option<PluginHandle>,
+    /// Set once the settings of the metronome were sent to its plugin.
+    metronome_synced: bool,
+    /// The time signature last sent to the metronome, and where its bars
+    /// start.
+    metronome_meter: Option<(TimeSignature, f64)>,
+    /// Set once the loop region was sent to the transport.
+    loop_synced: bool,
+    /// The loop region last sent to the transport in frames, or `None` if
+    /// looping was off.
+    loop_frames: Option<(Frames, Frames)>,
+    plugin_host: PluginHost,
+    /// The key of the fader plugin at the end of every channel.
+    fader_plug_key: Option<ScannedPluginKey>,
+    /// The key of the plugin that plays the MIDI clips of a channel.
+    midi_track_plug_key: Option<ScannedPluginKey>,
+    timeline_track_plug_key: Option<ScannedPluginKey>,
+    /// The key of the plugin that plays the drum rack of a channel.
+    drum_rack_plug_key: Option<ScannedPluginKey>,
+    /// The key of the plugin that plays the automation lanes of a plugin.
+    automation_plug_key: Option<ScannedPluginKey>,
+    /// The EQ plugin at the start of the master bus, which the master channel
+    /// plays into.
+    eq_plug_id: Option<PluginInstanceID>,
+    /// The plugin that records the armed channels.
+    record_plug_id: Option<PluginInstanceID>,
+    /// The edges between the channels that are connected in the graph.
+    channel_edges: Vec<Edge<PluginInstanceID>>,
+    /// Changes to the audio graph made while handling events, to be sent
+    /// through `UiData::modify_graph()` so they join an open transaction.
+    graph_requests: Vec<ModifyGraphRequest>,
+    /// The changes to the audio graph that wait for the master to fade out.
+    graph_swap: GraphSwap,
 }
 
 pub struct ActivatedEngineInfo {
@@ -112,6 +292,13 @@ pub struct UiData {
     /// The UI may mutate this directly without an event.
     pub notification_log: Vec<NotificationLogType>,
 
+    /// A description of the last change that was made, shown in the bottom
+    /// bar. See `UiEvent::Announce`.
+    pub announcement: String,
+
+    /// The translations of the user-facing strings in the selected language.
+    pub localization: Localization,
+
     /// True if a backend engine is currently running, false if not.
     ///
     /// Nothing except the settings menu can be accessed when this is false.
@@ -123,11 +310,113 @@ pub struct UiData {
     #[lens(ignore)]
     last_clicked_browser_file: Option<PathBuf>,
 
+    /// The noise profile used by the denoise clip operation.
+    #[lens(ignore)]
+    noise_profile: Option<NoiseProfile>,
+
+    /// The batch of edits currently being made, if any.
+    #[lens(ignore)]
+    edit_transaction: Option<EditTransaction>,
+
+    /// The edits that can be undone and redone.
+    #[lens(ignore)]
+    pub history: EditHistory,
+
+    /// The contents of the application's internal clipboard.
+    #[lens(ignore)]
+    clipboard: Option<ClipboardContents>,
+
+    /// The source of random values for randomizing parameters.
+    #[lens(ignore)]
+    random: WhiteNoise,
+
+    /// The workspaces that are available in every project.
+    #[lens(ignore)]
+    pub global_workspaces: Vec<Workspace>,
+
     #[lens(ignore)]
     system_io_stream_handle: Option<SystemIOStreamHandle>,
 
+    #[lens(ignore)]
+    output_buffering: OutputBuffering,
+
+    /// How loaded audio files are converted to the sample rate of the project.
+    #[lens(ignore)]
+    pub resample_quality: ResampleQuality,
+
+    /// Whether the audio graph of a loaded project is built a few plugins at
+    /// a time, so the UI shows and the first channels play before all of the
+    /// plugins are loaded.
+    #[lens(ignore)]
+    lazy_graph: bool,
+
+    /// Set while the plugins of a project that is loaded lazily are still
+    /// being added to the audio graph.
+    #[lens(ignore)]
+    hosting_lazily: bool,
+
+    /// The timings of the project that is being loaded, until its audio graph
+    /// is built.
+    #[lens(ignore)]
+    load_profile: Option<LoadProfile>,
+
+    /// Set when the audio device needs to be (re)opened, along with the last
+    /// time that was tried and the output channel mapping to restore.
+    #[lens(ignore)]
+    device_retry: Option<(Option<Instant>, OutputChannelMap)>,
+
+    /// Where the transport was in seconds as the loudness meter last reported
+    /// it, and whether it was playing.
+    #[lens(ignore)]
+    transport_position: Option<(f64, bool)>,
+
+    /// The transport position to go back to once the engine is activated
+    /// again, after it was restarted for a new audio device.
+    #[lens(ignore)]
+    restore_transport: Option<(f64, bool)>,
+
+    /// The input stream that the input meters read. This is open whenever an
+    /// input device is available, independent of the engine.
+    #[lens(ignore)]
+    input_meter_handle: Option<InputMeterHandle>,
+
+    /// The last time opening the input device was tried, while it isn't open.
+    #[lens(ignore)]
+    input_meter_retry: Option<Instant>,
+
+    /// Sends the MIDI of channels to hardware devices.
+    #[lens(ignore)]
+    midi_out_handle: Option<MidiOutHandle>,
+
+    /// The keys held down with musical typing and the notes they play, so
+    /// the notes still end right after the octave changes.
+    #[lens(ignore)]
+    typing_held: Vec<(Code, i16)>,
+
+    /// The `(channel, key)` of the notes held with the mouse on the on-screen
+    /// keyboard and pad grid, so they end on the channel they started on.
+    #[lens(ignore)]
+    onscreen_held: Vec<(i16, i16)>,
+
     #[lens(ignore)]
     engine_handles: Option<(EngineHandles, Receiver<DSEngineEvent>)>,
+
+    /// The export that is in progress, if any.
+    #[lens(ignore)]
+    export: Option<ProjectExport>,
+
+    /// Writes recordings to disk. Started with the first recording.
+    #[lens(ignore)]
+    disk_writer: Option<DiskWriterPool>,
+
+    /// The recording of the armed channels that is in progress, if any.
+    #[lens(ignore)]
+    recording: Option<AudioRecording>,
+
+    /// Set while the metronome counts in a recording that starts once it is
+    /// done.
+    #[lens(ignore)]
+    counting_in: bool,
 }
 
 impl UiData {
@@ -135,7 +424,9 @@ impl UiData {
     pub fn new() -> Result<Self, Box<dyn Error>> {
         // This is temporary. Eventually we will have a more sophisticated and
         // configurable system using `rainout`.
-        let system_io_stream_handle = system_io::temp_spawn_cpal_default_output_only()?;
+        let output_buffering = OutputBuffering::default();
+        let system_io_stream_handle =
+            system_io::temp_spawn_cpal_default_output_only(output_buffering)?;
         let sample_rate = system_io_stream_handle.sample_rate();
 
         let resource_loader = ResourceLoader::new(sample_rate);
@@ -148,6 +439,7 @@ impl UiData {
                         name: String::from("Master"),
                         selected: false,
                         color: Color::from("#D4D5D5").into(),
+                        parent_channel: None,
                         subchannels: vec![1, 5],
                         ..Default::default()
                     },
@@ -162,6 +454,7 @@ impl UiData {
                         name: String::from("Kick"),
                         selected: false,
                         color: Color::from("#EDE171").into(),
+                        parent_channel: Some(1),
                         subchannels: vec![],
                         ..Default::default()
                     },
@@ -169,6 +462,7 @@ impl UiData {
                         name: String::from("Snare"),
                         selected: true,
                         color: Color::from("#EDE171").into(),
+                        parent_channel: Some(1),
                         subchannels: vec![],
                         ..Default::default()
                     },
@@ -176,6 +470,7 @@ impl UiData {
                         name: String::from("Hat"),
                         selected: false,
                         color: Color::from("#EDE171").into(),
+                        parent_channel: Some(1),
                         subchannels: vec![],
                         ..Default::default()
                     },
@@ -190,6 +485,10 @@ impl UiData {
                 clips: vec![ClipState {
                     name: String::from("Drum Group 1"),
                     channel: 1,
+                    muted: false,
+                    effects: Vec::new(),
+                    recording: None,
+                    player_host_id: None,
                     timeline_start: ClipStart::NotInTimeline,
                     length: MusicalTime::from_beats(4).into(),
                     type_: ClipType::Automation(AutomationClipState {}),
@@ -207,6 +506,7 @@ impl UiData {
                             height: Some(2.0),
                             disabled: false,
                             selected: false,
+                            ..Default::default()
                         },
                         LaneState {
                             name: Some(String::from("Track 2")),
@@ -214,6 +514,7 @@ impl UiData {
                             height: None,
                             disabled: false,
                             selected: false,
+                            ..Default::default()
                         },
                         LaneState {
                             name: Some(String::from("Track 3")),
@@ -221,11 +522,23 @@ impl UiData {
                             height: None,
                             disabled: false,
                             selected: false,
+                            ..Default::default()
                         },
                     ]),
                     project_length: MusicalTime::from_beats(16).into(),
                     used_lanes: 0,
+                    bpm: 120.0,
+                    tap_tempo: TapTempo::new(),
+                    tempo_bus: TempoBus::new(&resource_loader.coll_handle(), 120.0),
+                    tempo_map: TempoMap::default(),
+                    time_signatures: TimeSignatureTrack::default(),
+                    loop_region: LoopRegion::default(),
+                    time_ruler_format: TimeRulerFormat::Time,
+                    view_width: 0.0,
+                    view_height: 0.0,
+                    view_history: ViewHistory::default(),
                 },
+                arranger: ArrangerState::default(),
                 browser: BrowserState::default(),
                 panels: PanelState {
                     channel_rack_orientation: ChannelRackOrientation::Horizontal,
@@ -234,24 +547,103 @@ impl UiData {
                     browser_width: 200.0,
                     lane_header_width: 100.0,
                     hide_browser: false,
+                    hide_tuner: true,
+                    hide_inspector: true,
+                    hide_loudness_history: false,
+                    hide_sample_editor: true,
+                    hide_eq: true,
+                    hide_collisions: true,
+                    hide_console: true,
+                    hide_keyboard: true,
+                    hide_pads: true,
+                    side_panel_order: SidePanel::DEFAULT_ORDER.to_vec(),
+                    main_panel_order: MainPanel::DEFAULT_ORDER.to_vec(),
                 },
                 dragging_channel: None,
+                inspected: None,
+                tuner: TunerState::default(),
+                metronome: MetronomeState::default(),
+                loudness_history: LoudnessHistoryState::default(),
+                sample_editor: SampleEditorState::default(),
+                eq: EqState::default(),
+                collision: CollisionState::default(),
+                console: ConsoleState::default(),
+                input_meter: InputMeterState::default(),
+                input_quantize: InputQuantize::default(),
+                musical_typing: MusicalTypingState::default(),
+                onscreen_instruments: OnScreenInstrumentsState::default(),
+                available_plugins: Vec::new(),
+                export_progress: None,
+                recording_audio: false,
+                macros: MacroState::default(),
+                fx_rack_presets: Vec::new(),
+                midi_out_devices: Vec::new(),
+                audio_device_outputs: system_io_stream_handle.num_out_channels(),
+                workspaces: Vec::new(),
             },
             resource_loader,
             notification_log: Vec::new(),
+            announcement: String::new(),
+            localization: Localization::new(&Localization::system_locale()),
             engine_running: false,
             system_io_stream_handle: Some(system_io_stream_handle),
+            output_buffering,
+            resample_quality: ResampleQuality::default(),
+            lazy_graph: true,
+            hosting_lazily: false,
+            load_profile: None,
+            device_retry: None,
+            transport_position: None,
+            restore_transport: None,
+            input_meter_handle: None,
+            input_meter_retry: None,
+            midi_out_handle: None,
+            typing_held: Vec::new(),
+            onscreen_held: Vec::new(),
             last_clicked_browser_file: None,
+            noise_profile: None,
+            edit_transaction: None,
+            history: EditHistory::new(),
+            clipboard: None,
+            random: WhiteNoise::new(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.subsec_nanos()),
+            ),
+            global_workspaces: Vec::new(),
             engine_handles: None,
+            export: None,
+            disk_writer: None,
+            recording: None,
+            counting_in: false,
         };
 
+        app_data.global_workspaces = Workspace::load_global(&app_data.state.panels);
+        app_data.state.macros.macros = Macro::load_global();
+        app_data.state.fx_rack_presets = FxRackState::load_global();
+
+        match MidiOutHandle::new() {
+            Ok(midi_out_handle) => app_data.midi_out_handle = Some(midi_out_handle),
+            Err(e) => log::error!("Failed to start the MIDI output thread: {}", e),
+        }
+        app_data.refresh_midi_out_devices();
+
         app_data.activate_engine();
+        app_data.poll_input_meter();
 
         Ok(app_data)
     }
 
+    /// Activate the engine at the sample rate of the audio device, or at the
+    /// sample rate of the export while exporting.
     pub fn activate_engine(&mut self) {
-        if let Some(system_io_stream_handle) = &mut self.system_io_stream_handle {
+        let sample_rate = match (&self.export, &self.system_io_stream_handle) {
+            (Some(export), _) => Some(SampleRate::from(export.options().sample_rate)),
+            (None, Some(system_io_stream_handle)) => Some(system_io_stream_handle.sample_rate()),
+            (None, None) => None,
+        };
+
+        if let Some(sample_rate) = sample_rate {
             let (mut engine_handle, engine_rx) = DSEngineHandle::new(
                 HostInfo::new(
                     String::from("RustyDAW integration test"),
@@ -259,19 +651,44 @@ impl UiData {
                     None,
                     None,
                 ),
-                vec![Box::new(SampleBrowserPlugFactory)],
+                vec![
+                    Box::new(SampleBrowserPlugFactory),
+                    Box::new(DeEsserPlugFactory),
+                    Box::new(HarmonizerPlugFactory),
+                    Box::new(TunerPlugFactory),
+                    Box::new(AmpSimPlugFactory),
+                    Box::new(VocoderPlugFactory),
+                    Box::new(SignalGenPlugFactory::new(self.state.timeline_grid.tempo_bus.clone())),
+                    Box::new(CapturePlugFactory),
+                    Box::new(LoudnessPlugFactory),
+                    Box::new(EqPlugFactory),
+                    Box::new(FaderPlugFactory),
+                    Box::new(MidiCapturePlugFactory),
+                    Box::new(MidiTrackPlugFactory::new(self.state.timeline_grid.tempo_bus.clone())),
+                    Box::new(TimelineTrackPlugFactory),
+                    Box::new(DrumRackPlugFactory),
+                    Box::new(AutomationPlugFactory::new(
+                        self.state.timeline_grid.tempo_bus.clone(),
+                    )),
+                    Box::new(RecordPlugFactory),
+                    Box::new(CompressorPlugFactory),
+                    Box::new(DelayPlugFactory::new(self.state.timeline_grid.tempo_bus.clone())),
+                    Box::new(ReverbPlugFactory),
+                    Box::new(MetronomePlugFactory::new(self.state.timeline_grid.tempo_bus.clone())),
+                ],
             );
 
             log::debug!("{:?}", &engine_handle.internal_plugins_res);
 
-            let sample_rate = system_io_stream_handle.sample_rate();
-
             engine_handle.send(DSEngineRequest::ActivateEngine(Box::new(ActivateEngineSettings {
                 sample_rate,
                 min_frames: MIN_FRAMES,
                 max_frames: MAX_FRAMES,
                 num_audio_in_channels: GRAPH_IN_CHANNELS,
-                num_audio_out_channels: GRAPH_OUT_CHANNELS,
+                num_audio_out_channels: self
+                    .export
+                    .as_ref()
+                    .map_or(GRAPH_OUT_CHANNELS, |export| export.out_channels()),
                 ..ActivateEngineSettings::default()
             })));
 
@@ -282,6 +699,27 @@ impl UiData {
                     ds_handle: engine_handle,
                     activated_info: None,
                     sample_browser_plug_handle: None,
+                    tuner_plug_handle: None,
+                    loudness_plug_handle: None,
+                    eq_plug_handle: None,
+                    midi_capture_plug_handle: None,
+                    record_plug_handle: None,
+                    metronome_plug_handle: None,
+                    metronome_synced: false,
+                    metronome_meter: None,
+                    loop_synced: false,
+                    loop_frames: None,
+                    plugin_host: PluginHost::new(),
+                    fader_plug_key: None,
+                    midi_track_plug_key: None,
+                    timeline_track_plug_key: None,
+                    drum_rack_plug_key: None,
+                    automation_plug_key: None,
+                    eq_plug_id: None,
+                    record_plug_id: None,
+                    channel_edges: Vec::new(),
+                    graph_requests: Vec::new(),
+                    graph_swap: GraphSwap::new(),
                 },
                 engine_rx,
             ));
@@ -291,7 +729,21 @@ impl UiData {
     }
 
     pub fn poll_engine(&mut self) {
-        let Self { state, system_io_stream_handle, engine_handles, resource_loader, .. } = self;
+        let Self {
+            state,
+            notification_log,
+            localization,
+            system_io_stream_handle,
+            engine_handles,
+            resource_loader,
+            resample_quality,
+            export,
+            hosting_lazily,
+            midi_out_handle,
+            transport_position,
+            restore_transport,
+            ..
+        } = self;
 
         if let Some((engine_handles, engine_rx)) = engine_handles {
             //let EngineHandles { handle, rx, activated_info, sample_browser_plug_handle } = engine_handle;
@@ -303,21 +755,55 @@ impl UiData {
                         plugin_id,
                         modified_params,
                     }) => {
-                        state.on_plugin_params_modified(plugin_id, modified_params);
+                        state.on_plugin_params_modified(plugin_id, modified_params, engine_handles);
                     }
                     // TODO: Hint to the compiler that this is the next most likely event?
                     DSEngineEvent::AudioGraphModified(event) => {
-                        state.on_audio_graph_modified(event, engine_handles);
+                        engine_handles.graph_swap.on_graph_modified();
+                        state.on_audio_graph_modified(
+                            event,
+                            engine_handles,
+                            notification_log,
+                            localization,
+                        );
+                        // The audio of the clips is loaded here, since the
+                        // state doesn't have the resource loader.
+                        state.sync_timeline_tracks(
+                            &mut engine_handles.plugin_host,
+                            resource_loader,
+                            *resample_quality,
+                        );
+                        state.sync_drum_racks(
+                            &mut engine_handles.plugin_host,
+                            resource_loader,
+                            *resample_quality,
+                        );
+
+                        // The graph of the project is built once every plugin
+                        // that was asked for has been added.
+                        if let Some(export) = export {
+                            if engine_handles.activated_info.is_some()
+                                && !engine_handles.plugin_host.has_pending()
+                                && !*hosting_lazily
+                            {
+                                export.set_graph_ready();
+                            }
+                        }
                     }
                     DSEngineEvent::Plugin(PluginEvent::Activated {
                         plugin_id,
                         new_handle,
                         new_param_values,
                     }) => {
-                        state.on_plugin_activated(plugin_id, new_handle, new_param_values);
+                        state.on_plugin_activated(
+                            plugin_id,
+                            new_handle,
+                            new_param_values,
+                            engine_handles,
+                        );
                     }
                     DSEngineEvent::Plugin(PluginEvent::Deactivated { plugin_id, status }) => {
-                        state.on_plugin_deactivated(plugin_id, status);
+                        state.on_plugin_deactivated(plugin_id, status, engine_handles);
                     }
                     DSEngineEvent::EngineDeactivated(event) => {
                         self.engine_running = false;
@@ -325,221 +811,3100 @@ impl UiData {
                     }
                     DSEngineEvent::EngineActivated(event) => {
                         self.engine_running = true;
-                        state.on_engine_activated(event, engine_handles, system_io_stream_handle);
+                        state.on_engine_activated(
+                            event,
+                            engine_handles,
+                            system_io_stream_handle,
+                            export,
+                            notification_log,
+                            localization,
+                        );
                     }
                     DSEngineEvent::AudioGraphCleared => {
                         state.on_audio_graph_cleared();
                     }
                     DSEngineEvent::PluginScanner(PluginScannerEvent::ClapScanPathAdded(path)) => {
-                        state.on_clap_scan_path_added(path);
+                        state.on_clap_scan_path_added(path, engine_handles);
                     }
                     DSEngineEvent::PluginScanner(PluginScannerEvent::ClapScanPathRemoved(path)) => {
-                        state.on_clap_scan_path_removed(path);
+                        state.on_clap_scan_path_removed(path, engine_handles);
                     }
                     DSEngineEvent::PluginScanner(PluginScannerEvent::RescanFinished(event)) => {
-                        state.on_plugin_scanner_rescan_finished(event);
+                        state.on_plugin_scanner_rescan_finished(event, engine_handles);
                     }
                     unkown_event => {
                         log::warn!("{:?}", unkown_event);
                     }
                 }
             }
-        }
 
-        // Clean up loaded resources that are no longer being used.
-        //
-        // TODO: Only call this periodically (i.e. every 3 seconds or so), because
-        // this can get expensive when a lot of resources are loaded in the project.
-        resource_loader.collect();
-    }
-}
+            state.send_played_notes(&mut engine_handles.plugin_host, midi_out_handle);
 
-impl Model for UiData {
-    // Update the program layer here
-    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
-        event.map(|program_event, _| match program_event {
-            UiEvent::PollEngine => {
-                self.poll_engine();
+            // Pick up where the transport was before the engine restarted.
+            if let Some(activated_info) = &mut engine_handles.activated_info {
+                if let Some((seconds, playing)) = restore_transport.take() {
+                    let frame = Seconds(seconds).to_nearest_frame_round(activated_info.sample_rate);
+                    activated_info.transport_handle.seek_to(frame);
+                    activated_info.transport_handle.set_playing(playing);
+                }
             }
-            UiEvent::SaveProject => {
-                //let save_state = serde_json::to_string(&self.state).unwrap();
-                //std::fs::write("project.json", save_state).unwrap();
+
+            let master_fader = state.master_fader(&mut engine_handles.plugin_host);
+            for request in engine_handles.graph_swap.poll(master_fader) {
+                engine_handles.ds_handle.send(DSEngineRequest::ModifyGraph(request));
             }
-            UiEvent::LoadProject => {
-                //let save_state = std::fs::read_to_string("project.json").unwrap();
-                //let project_state = serde_json::from_str(&save_state).unwrap();
-                //self.state = project_state;
+
+            // The loop region is kept in beats, so it moves in the transport
+            // when the tempo changes as well.
+            if let Some(activated_info) = &mut engine_handles.activated_info {
+                let loop_frames = state.timeline_grid.loop_frames(activated_info.sample_rate);
+                if !engine_handles.loop_synced || engine_handles.loop_frames != loop_frames {
+                    activated_info.transport_handle.set_loop_state(match loop_frames {
+                        Some((loop_start, loop_end)) => LoopState::Active { loop_start, loop_end },
+                        None => LoopState::Inactive,
+                    });
+                    engine_handles.loop_frames = loop_frames;
+                    engine_handles.loop_synced = true;
+                }
             }
-            UiEvent::BrowserFileClicked(path) => {
-                if let Some((engine_handles, _)) = &mut self.engine_handles {
-                    if let Some(browser_plug_handle) =
-                        &mut engine_handles.sample_browser_plug_handle
-                    {
-                        let browser_plug_handle = browser_plug_handle
-                            .internal
-                            .as_mut()
-                            .unwrap()
-                            .downcast_mut::<SampleBrowserPlugHandle>()
-                            .unwrap();
 
-                        // TODO: Only play audio files.
-                        let already_loaded =
-                            if let Some(last_path) = &self.last_clicked_browser_file {
-                                last_path == path
-                            } else {
-                                false
-                            };
+            if let Some(tuner_plug_handle) = &mut engine_handles.tuner_plug_handle {
+                if !state.panels.hide_tuner {
+                    let tuner_plug_handle = tuner_plug_handle
+                        .internal
+                        .as_mut()
+                        .unwrap()
+                        .downcast_mut::<TunerPlugHandle>()
+                        .unwrap();
 
-                        if already_loaded {
-                            browser_plug_handle.replay_sample();
-                        } else {
-                            let (pcm, res) = self.resource_loader.load_pcm(&PcmKey {
-                                path: path.clone(),
-                                resample_to_project_sr: true,
-                                resample_quality: ResampleQuality::Linear,
-                            });
+                    state.tuner.update(tuner_plug_handle.reading());
+                }
+            }
 
-                            match res {
-                                Ok(()) => {
-                                    browser_plug_handle.play_sample(pcm);
-                                    self.last_clicked_browser_file = Some(path.clone());
-                                }
-                                Err(e) => {
-                                    log::error!("Failed to load pcm resource: {}", e);
-                                    self.last_clicked_browser_file = None;
-                                }
-                            }
+            if let (Some(loudness_plug_handle), Some(activated_info)) =
+                (&mut engine_handles.loudness_plug_handle, &engine_handles.activated_info)
+            {
+                let loudness_plug_handle = loudness_plug_handle
+                    .internal
+                    .as_mut()
+                    .unwrap()
+                    .downcast_mut::<LoudnessPlugHandle>()
+                    .unwrap();
+
+                let mut playhead_beats = None;
+                for r in loudness_plug_handle.poll_readings() {
+                    let seconds = r.playhead_frame as f64 / activated_info.sample_rate.0;
+                    *transport_position = Some((seconds, r.playing));
+                    if r.playing {
+                        let beats = state.timeline_grid.seconds_to_beats(seconds);
+                        state.loudness_history.record(beats, r.reading);
+                        playhead_beats = Some(beats);
+                    } else {
+                        state.loudness_history.end_pass();
+                    }
+                }
+                if let Some(beats) = playhead_beats {
+                    state.timeline_grid.follow_playhead(beats);
+
+                    // Time signatures only reach the metronome when the
+                    // playhead passes a change, like the tempo.
+                    let meter = state.timeline_grid.time_signatures.meter_at(beats);
+                    if let Some(metronome_plug_handle) = &mut engine_handles.metronome_plug_handle {
+                        if engine_handles.metronome_meter != Some(meter) {
+                            metronome_plug_handle
+                                .internal
+                                .as_mut()
+                                .unwrap()
+                                .downcast_mut::<MetronomePlugHandle>()
+                                .unwrap()
+                                .set_meter(meter.0, meter.1);
+                            engine_handles.metronome_meter = Some(meter);
                         }
                     }
                 }
+
+                if let Some(reading) = loudness_plug_handle.latest() {
+                    state.loudness_history.set_latest(reading);
+                }
             }
-            UiEvent::BrowserFileStop() => {
-                if let Some((engine_handles, _)) = &mut self.engine_handles {
-                    if let Some(browser_plug_handle) =
-                        &mut engine_handles.sample_browser_plug_handle
-                    {
-                        let browser_plug_handle = browser_plug_handle
-                            .internal
-                            .as_mut()
-                            .unwrap()
-                            .downcast_mut::<SampleBrowserPlugHandle>()
-                            .unwrap();
 
-                        self.last_clicked_browser_file = None;
-                        browser_plug_handle.stop();
+            if let Some(eq_plug_handle) = &mut engine_handles.eq_plug_handle {
+                if !state.panels.hide_eq {
+                    let eq_plug_handle = eq_plug_handle
+                        .internal
+                        .as_mut()
+                        .unwrap()
+                        .downcast_mut::<EqPlugHandle>()
+                        .unwrap();
+
+                    if eq_plug_handle.update_spectrums() {
+                        state.eq.update_from_handle(eq_plug_handle);
                     }
                 }
             }
-            _ => {}
-        });
 
-        self.state.event(cx, event);
+            // Keep the MIDI history up to date even when nothing is recording.
+            if let Some(midi_capture_plug_handle) = &mut engine_handles.midi_capture_plug_handle {
+                let midi_capture_plug_handle = midi_capture_plug_handle
+                    .internal
+                    .as_mut()
+                    .unwrap()
+                    .downcast_mut::<MidiCapturePlugHandle>()
+                    .unwrap();
+                midi_capture_plug_handle.poll();
+
+                let mut active_keys: Vec<i16> =
+                    midi_capture_plug_handle.held_notes().iter().map(|(_, key)| *key).collect();
+                active_keys.sort_unstable();
+                active_keys.dedup();
+                if state.onscreen_instruments.active_keys != active_keys {
+                    state.onscreen_instruments.active_keys = active_keys;
+                }
+            }
+        }
+
+        // Clean up loaded resources that are no longer being used.
+        resource_loader.collect_if_due();
+
+        self.flush_graph_requests();
     }
 }
 
-#[derive(Debug, Lens, Clone)]
-pub struct UiState {
-    /// A "channel" refers to a mixer channel.
-    ///
-    /// This also contains the state of all clips.
-    pub channels: Vec<ChannelState>,
+impl UiData {
+    /// Split the given audio clip into its non-silent regions.
+    fn strip_silence(
+        &mut self,
+        clip_index: usize,
+        settings: &StripSilenceSettings,
+        mode: StripSilenceMode,
+    ) {
+        let pcm = match self.load_clip_pcm(clip_index, false, "action-strip-silence") {
+            Some(pcm) => pcm,
+            None => return,
+        };
 
-    // Index of channel being dragged
-    pub dragging_channel: Option<usize>,
+        let regions = silence::find_non_silent_regions(&pcm, settings);
+        let sample_rate = SampleRate(f64::from(pcm.sample_rate()));
 
-    pub clips: Vec<ClipState>,
+        if let Some(new_clips) = self.state.clips[clip_index].strip_silence(
+            &regions,
+            mode,
+            sample_rate,
+            &self.state.timeline_grid,
+        ) {
+            self.state.clips.splice(clip_index..clip_index + 1, new_clips);
+        }
+    }
 
-    /// The state of the timeline grid.
-    ///
-    /// (This does not contain the state of the clips.)
-    pub timeline_grid: TimelineGridState,
+    /// Learn a noise profile from a selection (in seconds) of the given audio
+    /// clip's original file.
+    fn learn_noise_profile(&mut self, clip_index: usize, selection: &Range<f64>) {
+        let pcm = match self.load_clip_pcm(clip_index, true, "action-learn-noise-profile") {
+            Some(pcm) => pcm,
+            None => return,
+        };
 
-    pub browser: BrowserState,
+        let sample_rate = f64::from(pcm.sample_rate());
+        let frames = (selection.start.max(0.0) * sample_rate) as usize
+            ..(selection.end.max(0.0) * sample_rate) as usize;
 
-    /// State of the UI panels.
-    ///
-    /// This is visual state that is used by the UI and must be serialized.
-    pub panels: PanelState,
-}
+        match NoiseProfile::learn(&pcm, frames, DEFAULT_FFT_SIZE) {
+            Some(profile) => {
+                self.noise_profile = Some(profile);
+                self.notification_log.push(NotificationLogType::Info(
+                    self.localization.tr("info-learned-noise-profile"),
+                ));
+            }
+            None => {
+                self.notification_log.push(NotificationLogType::Error(
+                    self.localization.tr("error-noise-profile-too-short"),
+                ));
+            }
+        }
+    }
 
-impl UiState {
-    /// Sent whenever the engine is deactivated.
-    ///
-    /// The DSEngineAudioThread sent in a previous EngineActivated event is now
-    /// invalidated. Please drop it and wait for a new EngineActivated event to
-    /// replace it.
-    ///
-    /// To keep using the audio graph, you must reactivate the engine with
-    /// `DSEngineRequest::ActivateEngine`, and then restore the audio graph
-    /// from an existing save state if you wish using
-    /// `DSEngineRequest::RestoreFromSaveState`.
-    fn on_engine_deactivated(
-        &mut self,
-        event: EngineDeactivatedInfo,
-        engine_handles: &mut EngineHandles,
-        system_io_stream_handle: &mut Option<SystemIOStreamHandle>,
-    ) {
-        engine_handles.activated_info = None;
-        engine_handles.sample_browser_plug_handle = None;
+    /// Render a denoised take of the given audio clip and make it the active take.
+    fn denoise_clip(&mut self, clip_index: usize, settings: &DenoiseSettings) {
+        let profile = match &self.noise_profile {
+            Some(profile) => profile.clone(),
+            None => {
+                self.notification_log.push(NotificationLogType::Error(
+                    self.localization.tr("error-no-noise-profile"),
+                ));
+                return;
+            }
+        };
 
-        if let Some(system_io_stream_handle) = system_io_stream_handle.as_mut() {
-            system_io_stream_handle.engine_deactivated();
+        let pcm = match self.load_clip_pcm(clip_index, true, "action-denoise") {
+            Some(pcm) => pcm,
+            None => return,
+        };
+
+        let (l, r) = denoise::denoise(&pcm, &profile, settings);
+
+        if let ClipType::Audio(audio) = &mut self.state.clips[clip_index].type_ {
+            let stem = audio.pcm_path.file_stem().unwrap_or_default().to_string_lossy();
+            let take_path = audio.pcm_path.with_file_name(format!(
+                "{}.denoise{}.wav",
+                stem,
+                audio.alternate_takes.len() + 1
+            ));
+
+            if let Err(e) = write_wav_f32(&take_path, pcm.sample_rate(), &l, &r) {
+                self.notification_log.push(NotificationLogType::Error(self.localization.tr_args(
+                    "error-write-denoised-take",
+                    &[("path", &take_path.display()), ("error", &e)],
+                )));
+                return;
+            }
+
+            audio.alternate_takes.push(take_path);
+            audio.active_take = Some(audio.alternate_takes.len() - 1);
         }
+    }
 
-        // TODO
+    /// Start a batch of edits. All changes to the audio graph made before the
+    /// matching `commit_edit()` are sent to the engine as a single request, so
+    /// the graph is only rebuilt once, and the edits are undone as one step.
+    ///
+    /// Transactions may be nested, in which case only the outermost one takes
+    /// effect.
+    pub fn begin_edit(&mut self) {
+        match &mut self.edit_transaction {
+            Some(transaction) => transaction.push_depth(),
+            None => {
+                self.edit_transaction = Some(EditTransaction::new(&self.state));
+                self.history.begin_transaction();
+            }
+        }
     }
 
-    /// This message is sent whenever the engine successfully activates.
-    fn on_engine_activated(
-        &mut self,
-        event: EngineActivatedInfo,
-        engine_handles: &mut EngineHandles,
-        system_io_stream_handle: &mut Option<SystemIOStreamHandle>,
-    ) {
-        engine_handles.activated_info = Some(ActivatedEngineInfo {
-            graph_in_node_id: event.graph_in_node_id.clone(),
-            graph_out_node_id: event.graph_out_node_id.clone(),
-            transport_handle: event.transport_handle,
-            sample_rate: event.sample_rate,
-            min_frames: event.min_frames,
-            max_frames: event.max_frames,
-            num_audio_in_channels: event.num_audio_in_channels,
-            num_audio_out_channels: event.num_audio_out_channels,
-        });
+    /// Apply all of the edits made since the matching `begin_edit()`.
+    pub fn commit_edit(&mut self) {
+        let outermost = match &mut self.edit_transaction {
+            Some(transaction) => transaction.pop_depth(),
+            None => return,
+        };
 
-        // Collect the keys for the internal plugins.
-        let mut sample_browser_plug_key = None;
-        for p in engine_handles.ds_handle.internal_plugins_res.iter() {
-            if let Ok(key) = p {
-                if &key.rdn == SAMPLE_BROWSER_PLUG_RDN {
-                    sample_browser_plug_key = Some(key.clone());
+        if outermost {
+            // The plugins are brought in line with the edits while the
+            // transaction is still open, so their requests are merged with
+            // the rest.
+            self.sync_graph(usize::MAX);
+            if let Some(transaction) = self.edit_transaction.take() {
+                let (request, project) = transaction.finish();
+                if let Some(request) = request {
+                    self.modify_graph(request);
                 }
+                self.history.commit_transaction(project);
             }
         }
-        let sample_browser_plug_key = sample_browser_plug_key.unwrap();
+    }
 
-        system_io_stream_handle.as_mut().unwrap().engine_activated(event.audio_thread);
+    /// Undo all of the edits made since the outermost `begin_edit()`.
+    ///
+    /// The audio graph isn't brought in line with the project while a
+    /// transaction is open, so the edits never reached it. What was queued
+    /// for it anyway (i.e. plugins the engine added that have to be taken out
+    /// again) is still sent.
+    pub fn rollback_edit(&mut self) {
+        if let Some(transaction) = self.edit_transaction.take() {
+            let (request, project) = transaction.finish();
+            project.apply(&mut self.state);
+            self.history.rollback_transaction();
+            if let Some(request) = request {
+                self.modify_graph(request);
+            }
+            self.host_plugins();
+        }
+    }
 
-        // Add the sample-browser plugin and connect it directly to the output.
-        engine_handles.ds_handle.send(DSEngineRequest::ModifyGraph(ModifyGraphRequest {
-            add_plugin_instances: vec![PluginSaveState::new_with_default_preset(
-                sample_browser_plug_key,
-            )],
-            remove_plugin_instances: vec![],
-            connect_new_edges: vec![
-                EdgeReq {
-                    edge_type: PortType::Audio,
-                    src_plugin_id: PluginIDReq::Added(0),
-                    dst_plugin_id: PluginIDReq::Existing(event.graph_out_node_id.clone()),
-                    src_port_id: EdgeReqPortID::Main,
-                    src_port_channel: 0,
-                    dst_port_id: EdgeReqPortID::Main,
-                    dst_port_channel: 0,
-                    log_error_on_fail: true,
-                },
+    /// Send a request to modify the audio graph to the engine, or add it to
+    /// the current transaction if there is one.
+    ///
+    /// Changing the graph never clicks: the master fades out before the
+    /// request is sent, and back in once the new graph plays (see
+    /// `GraphSwap`). An export isn't heard, so its requests are sent right
+    /// away.
+    pub fn modify_graph(&mut self, request: ModifyGraphRequest) {
+        if let Some(transaction) = &mut self.edit_transaction {
+            transaction.queue_graph_request(request);
+            return;
+        }
+
+        if let Some((engine_handles, _)) = &mut self.engine_handles {
+            let request = if self.export.is_none() {
+                let master_fader = self.state.master_fader(&mut engine_handles.plugin_host);
+                engine_handles.graph_swap.queue(request, master_fader)
+            } else {
+                Some(request)
+            };
+            if let Some(request) = request {
+                engine_handles.ds_handle.send(DSEngineRequest::ModifyGraph(request));
+            }
+        }
+    }
+
+    /// Pass the requests to modify the graph that were made while handling
+    /// the events of the engine through `modify_graph()`.
+    fn flush_graph_requests(&mut self) {
+        let requests = match &mut self.engine_handles {
+            Some((engine_handles, _)) => std::mem::take(&mut engine_handles.graph_requests),
+            None => return,
+        };
+        for request in requests {
+            self.modify_graph(request);
+        }
+    }
+
+    /// Compare the spectrums of the first two selected channels over the whole
+    /// timeline.
+    fn analyze_collisions(&mut self) {
+        let selected: Vec<usize> = self
+            .state
+            .channels
+            .iter()
+            .enumerate()
+            .filter(|(_, channel)| channel.selected)
+            .map(|(i, _)| i)
+            .take(2)
+            .collect();
+        if selected.len() < 2 {
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization.tr("error-collision-needs-two-channels"),
+            ));
+            return;
+        }
+
+        let (a, sample_rate_a) = self.render_channel_mono(selected[0]);
+        let (b, sample_rate_b) = self.render_channel_mono(selected[1]);
+
+        let sample_rate = match sample_rate_a.or(sample_rate_b) {
+            Some(sample_rate) => sample_rate,
+            None => {
+                self.notification_log.push(NotificationLogType::Error(
+                    self.localization.tr("error-collision-no-clips"),
+                ));
+                return;
+            }
+        };
+
+        let collision = &mut self.state.collision;
+        collision.map = Some(analyze_collisions(&a, &b, sample_rate as f32, &collision.settings));
+        collision.description = format!(
+            "{} / {}",
+            self.state.channels[selected[0]].name, self.state.channels[selected[1]].name
+        );
+    }
+
+    /// Mix all of the unmuted audio clips of a channel that are on the timeline
+    /// down to a single mono signal. The audio clips inside containers on the
+    /// channel are included.
+    ///
+    /// Also returns the sample rate of the signal, or `None` if the channel has
+    /// no audio clips on the timeline.
+    fn render_channel_mono(&mut self, channel: usize) -> (Vec<f32>, Option<u32>) {
+        let clips: Vec<ClipState> = self.state.clips.iter().flat_map(ClipState::resolve).collect();
+
+        let mut out = Vec::new();
+        let mut sample_rate = None;
+        for clip in clips.iter() {
+            if clip.channel != channel || clip.muted {
+                continue;
+            }
+
+            let (timeline_start, clip_start_offset) = match (&clip.timeline_start, &clip.type_) {
+                (ClipStart::OnLane(on_lane), ClipType::Audio(audio)) => {
+                    (on_lane.timeline_start.get(), audio.clip_start_offset.get())
+                }
+                _ => continue,
+            };
+            let length = clip.length.get();
+
+            let pcm = match self.load_pcm_of_clip(clip, false, "action-analyze") {
+                Some(pcm) => pcm,
+                None => continue,
+            };
+            sample_rate.get_or_insert(pcm.sample_rate());
+
+            let sr = SampleRate(f64::from(pcm.sample_rate()));
+            let src_start = clip_start_offset.to_nearest_frame_round(sr).0 as usize;
+            let start_beats = timeline_start.as_beats_f64();
+            let end_beats = start_beats + length.as_beats_f64();
+            let timeline_grid = &self.state.timeline_grid;
+            let start_secs = timeline_grid.beats_to_seconds(start_beats);
+            let end_secs = timeline_grid.beats_to_seconds(end_beats);
+            let len = Seconds(end_secs - start_secs).to_nearest_frame_round(sr).0 as usize;
+            let dst_start = Seconds(start_secs).to_nearest_frame_round(sr).0 as usize;
+
+            mix_into_mono(&mut out, &pcm, src_start..src_start + len, dst_start);
+        }
+
+        (out, sample_rate)
+    }
+
+    /// Stop the engine, release the audio device, and free all loaded resources.
+    ///
+    /// Unsaved edits in the sample editor are written to a new take first so
+    /// they aren't lost, and a recording in progress is stopped and its files
+    /// are finished.
+    ///
+    /// This is called when the window is closed, and it is safe to call more
+    /// than once.
+    pub fn shutdown(&mut self) {
+        if self.state.sample_editor.dirty {
+            self.save_sample_editor();
+        }
+
+        self.stop_recording();
+        // Dropping the pool waits for the writers to write what is left in
+        // their buffers and close the files.
+        self.disk_writer = None;
+        self.recording = None;
+        self.state.recording_audio = false;
+
+        self.stop_engine();
+        self.device_retry = None;
+
+        // Dropping the stream closes the audio device.
+        self.system_io_stream_handle = None;
+
+        // Nothing is using any of the loaded resources anymore.
+        self.resource_loader.collect();
+    }
+
+    fn stop_engine(&mut self) {
+        if let Some((engine_handles, _)) = &mut self.engine_handles {
+            engine_handles.ds_handle.send(DSEngineRequest::DeactivateEngine);
+
+            // Make sure the audio thread lets go of the engine before the engine
+            // is dropped.
+            if let Some(system_io_stream_handle) = &mut self.system_io_stream_handle {
+                system_io_stream_handle.engine_deactivated();
+            }
+        }
+        self.engine_handles = None;
+        self.engine_running = false;
+    }
+
+    /// Render the master output of the whole project to a WAV file, faster
+    /// than realtime. The engine is restarted at the sample rate of the export
+    /// without the audio device, and restarted again on the device once the
+    /// export is done. See `poll_export()`.
+    pub fn export_master(
+        &mut self,
+        path: &Path,
+        options: ExportOptions,
+    ) -> Result<(), LocalizedMessage> {
+        if self.export.is_some() {
+            return Err(LocalizedMessage::new("error-export-running"));
+        }
+        if options.sample_rate == 0 {
+            return Err(LocalizedMessage::new("error-export-sample-rate"));
+        }
+
+        let frames = self.state.export_frames(options.sample_rate);
+        self.start_export(ProjectExport::master(path, options, frames));
+        Ok(())
+    }
+
+    /// Render the output of every channel to a WAV file of its own in `dir`,
+    /// all in one pass, like `export_master()`. The files are numbered in the
+    /// order of the channels, starting with the master channel.
+    ///
+    /// A stem is taken after the fader of its channel, so it has the gain,
+    /// pan and mute of the channel. The master stem is what plays out of the
+    /// master bus. Each stem takes a pair of outputs of the graph, so there
+    /// can't be more channels than fit in them.
+    pub fn export_stems(
+        &mut self,
+        dir: &Path,
+        options: ExportOptions,
+    ) -> Result<(), LocalizedMessage> {
+        if self.export.is_some() {
+            return Err(LocalizedMessage::new("error-export-running"));
+        }
+        if options.sample_rate == 0 {
+            return Err(LocalizedMessage::new("error-export-sample-rate"));
+        }
+
+        let names: Vec<String> =
+            self.state.channels.iter().map(|channel| channel.name.clone()).collect();
+        if names.len().checked_mul(2).and_then(|n| u16::try_from(n).ok()).is_none() {
+            return Err(LocalizedMessage::new("error-export-stem-count"));
+        }
+        let frames = self.state.export_frames(options.sample_rate);
+        self.start_export(ProjectExport::stems(dir, &names, options, frames));
+        Ok(())
+    }
+
+    fn start_export(&mut self, export: ProjectExport) {
+        self.stop_engine();
+        self.export = Some(export);
+        self.state.export_progress = Some(0.0);
+        self.activate_engine();
+    }
+
+    /// Start the export once the engine is ready for it, and go back to the
+    /// audio device once it is done.
+    fn poll_export(&mut self) {
+        let export = match &mut self.export {
+            Some(export) => export,
+            None => return,
+        };
+
+        if export.is_ready() {
+            // Play the project from the start.
+            if let Some((engine_handles, _)) = &mut self.engine_handles {
+                if let Some(activated_info) = &mut engine_handles.activated_info {
+                    activated_info.transport_handle.seek_to(Frames(0));
+                    activated_info.transport_handle.set_playing(true);
+                }
+            }
+            if let Err(e) = export.start() {
+                self.finish_export(Err(e));
+                return;
+            }
+        }
+
+        self.state.export_progress = Some(export.progress());
+        if export.is_finished() {
+            let export = self.export.take().unwrap();
+            let path = export.path().to_path_buf();
+            let result = export.finish();
+            if result.is_ok() {
+                self.notification_log.push(NotificationLogType::Info(
+                    self.localization.tr_args("info-export-finished", &[("path", &path.display())]),
+                ));
+            }
+            self.finish_export(result);
+        }
+    }
+
+    /// Record the audio input onto every armed channel from where the playhead
+    /// is, and start playing. Each channel gets a file of its own in
+    /// `RECORDINGS_DIR`, and a clip on a lane of its own once the recording
+    /// is stopped, starting at the last selected lane (or the first lane).
+    pub fn start_recording(&mut self) -> Result<(), LocalizedMessage> {
+        if self.recording.is_some() {
+            return Err(LocalizedMessage::new("error-recording-running"));
+        }
+        let armed: Vec<usize> = self
+            .state
+            .channels
+            .iter()
+            .enumerate()
+            .filter(|(_, channel)| channel.armed)
+            .map(|(index, _)| index)
+            .take(MAX_RECORDED_CHANNELS)
+            .collect();
+        if armed.is_empty() {
+            self.notification_log
+                .push(NotificationLogType::Info(self.localization.tr("info-nothing-armed")));
+            return Ok(());
+        }
+
+        // Connect the inputs of the armed channels to the record plugin, in
+        // the same order as the recorders.
+        if self.edit_transaction.is_none() {
+            self.sync_graph(0);
+        }
+
+        let not_running = || LocalizedMessage::new("error-engine-not-running");
+        let (engine_handles, _) = self.engine_handles.as_mut().ok_or_else(not_running)?;
+        let handle = engine_handles
+            .record_plug_handle
+            .as_mut()
+            .ok_or_else(not_running)?
+            .internal
+            .as_mut()
+            .unwrap()
+            .downcast_mut::<RecordPlugHandle>()
+            .unwrap();
+
+        if self.disk_writer.is_none() {
+            self.disk_writer =
+                Some(DiskWriterPool::new(DEFAULT_DISK_WRITER_THREADS).map_err(system_error)?);
+        }
+        let disk_writer = self.disk_writer.as_mut().unwrap();
+        std::fs::create_dir_all(RECORDINGS_DIR).map_err(system_error)?;
+
+        let first_lane = self.state.timeline_grid.lane_states.last_selected_index().unwrap_or(0);
+        let mut recording = AudioRecording::new();
+        let mut recorders = Vec::new();
+        for (i, channel) in armed.into_iter().enumerate() {
+            let path = AudioRecording::new_path(&self.state.channels[channel].name);
+            match disk_writer.start_track(&path, handle.sample_rate(), handle.max_frames()) {
+                Ok((id, recorder)) => {
+                    recording.tracks.push(RecordedTrack {
+                        id,
+                        channel,
+                        lane: first_lane + i,
+                        path,
+                    });
+                    recorders.push(recorder);
+                }
+                Err(e) => {
+                    // Close the files that were already opened, and delete
+                    // them since nothing was recorded into them.
+                    let ids: Vec<RecordingTrackId> =
+                        recording.tracks.iter().map(|track| track.id).collect();
+                    disk_writer.discard_tracks(&ids);
+                    return Err(system_error(e));
+                }
+            }
+        }
+
+        handle.start(recorders);
+        if let Some(activated_info) = &mut engine_handles.activated_info {
+            activated_info.transport_handle.set_playing(true);
+        }
+        self.recording = Some(recording);
+        self.state.recording_audio = true;
+        Ok(())
+    }
+
+    /// Stop playing and recording. The clips are added once the rest of the
+    /// recording is written, see `poll_recording()`.
+    pub fn stop_recording(&mut self) {
+        if self.counting_in {
+            if let Some(handle) = self.metronome_plug_handle() {
+                handle.cancel_count_in();
+            }
+            self.counting_in = false;
+            self.state.recording_audio = false;
+            return;
+        }
+
+        let recording = match &mut self.recording {
+            Some(recording) if !recording.stopped => recording,
+            _ => return,
+        };
+
+        if let Some((engine_handles, _)) = &mut self.engine_handles {
+            if let Some(handle) = &mut engine_handles.record_plug_handle {
+                let handle =
+                    handle.internal.as_mut().unwrap().downcast_mut::<RecordPlugHandle>().unwrap();
+                handle.stop();
+                recording.start_secs =
+                    handle.start_frame().map(|frame| frame as f64 / handle.sample_rate().0);
+            }
+            if let Some(activated_info) = &mut engine_handles.activated_info {
+                activated_info.transport_handle.set_playing(false);
+            }
+        }
+
+        if let Some(disk_writer) = &mut self.disk_writer {
+            for track in recording.tracks.iter() {
+                disk_writer.stop_track(track.id);
+            }
+        }
+        recording.stopped = true;
+    }
+
+    fn metronome_plug_handle(&mut self) -> Option<&mut MetronomePlugHandle> {
+        let (engine_handles, _) = self.engine_handles.as_mut()?;
+        let handle = engine_handles.metronome_plug_handle.as_mut()?;
+        Some(handle.internal.as_mut().unwrap().downcast_mut::<MetronomePlugHandle>().unwrap())
+    }
+
+    /// Send the settings of the metronome to its plugin.
+    fn sync_metronome(&mut self) {
+        let metronome = self.state.metronome.clone();
+        let sound = match &metronome.sound {
+            MetronomeSound::Beep => ClickSound::Beep,
+            MetronomeSound::Woodblock => ClickSound::Woodblock,
+            MetronomeSound::Samples { accent, beat } => {
+                let mut load = |path: &PathBuf| {
+                    let (pcm, res) = self.resource_loader.load_pcm(&PcmKey {
+                        path: path.clone(),
+                        resample_to_project_sr: true,
+                        resample_quality: self.resample_quality,
+                    });
+                    if let Err(e) = res {
+                        self.notification_log.push(NotificationLogType::Error(
+                            self.localization.tr_args("error-load-click", &[("error", &e)]),
+                        ));
+                    }
+                    pcm
+                };
+                ClickSound::Samples { accent: load(accent), beat: load(beat) }
+            }
+        };
+
+        let meter = self.state.timeline_grid.time_signatures.meter_at(0.0);
+        if let Some(handle) = self.metronome_plug_handle() {
+            handle.set_enabled(metronome.enabled);
+            handle.set_meter(meter.0, meter.1);
+            handle.set_level_db(metronome.level_db);
+            handle.set_sound(sound);
+            if let Some((engine_handles, _)) = &mut self.engine_handles {
+                engine_handles.metronome_synced = true;
+                engine_handles.metronome_meter = Some(meter);
+            }
+        }
+    }
+
+    /// Start a recording, after the metronome counts in if a count-in is
+    /// set.
+    fn start_recording_with_count_in(&mut self) {
+        let bars = self.state.metronome.count_in_bars;
+        let armed = self.state.channels.iter().any(|channel| channel.armed);
+        if bars > 0 && armed && self.recording.is_none() && !self.counting_in {
+            if let Some(handle) = self.metronome_plug_handle() {
+                handle.count_in(bars);
+                self.counting_in = true;
+                self.state.recording_audio = true;
+                return;
+            }
+        }
+
+        self.start_recording_or_notify();
+    }
+
+    /// Start the recording once its count-in is done.
+    fn poll_count_in(&mut self) {
+        if !self.counting_in {
+            return;
+        }
+        if self.metronome_plug_handle().map_or(true, |handle| handle.count_in_finished()) {
+            self.counting_in = false;
+            self.state.recording_audio = false;
+            self.start_recording_or_notify();
+        }
+    }
+
+    fn start_recording_or_notify(&mut self) {
+        if let Err(e) = self.start_recording() {
+            let e = self.localization.tr_message(&e);
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization.tr_args("error-start-recording", &[("error", &e)]),
+            ));
+        }
+    }
+
+    /// Once every file of a stopped recording is closed, place the files on
+    /// the timeline as clips.
+    fn poll_recording(&mut self) {
+        let (recording, disk_writer) = match (&self.recording, &mut self.disk_writer) {
+            (Some(recording), Some(disk_writer)) if recording.stopped => (recording, disk_writer),
+            _ => return,
+        };
+        let statuses = disk_writer.status();
+        let finished = recording
+            .tracks
+            .iter()
+            .all(|track| statuses.iter().any(|status| status.id == track.id && status.finished));
+        if !finished {
+            return;
+        }
+        disk_writer.remove_finished();
+        let recording = self.recording.take().unwrap();
+        self.state.recording_audio = false;
+
+        let sample_rate = match &self.engine_handles {
+            Some((engine_handles, _)) => engine_handles
+                .activated_info
+                .as_ref()
+                .map(|activated_info| activated_info.sample_rate.0),
+            None => None,
+        };
+        let (start_secs, sample_rate) = match (recording.start_secs, sample_rate) {
+            (Some(start_secs), Some(sample_rate)) => (start_secs, sample_rate),
+            // The transport never played, so nothing was recorded.
+            _ => return,
+        };
+
+        self.history.record("history-record-audio", None, &self.state);
+        let source = self.localization.tr("recording-source-audio-input");
+        for track in recording.tracks.iter() {
+            let status = match statuses.iter().find(|status| status.id == track.id) {
+                Some(status) => status,
+                None => continue,
+            };
+            let channel_name =
+                self.state.channels.get(track.channel).map_or("", |channel| channel.name.as_str());
+            if status.failed {
+                self.notification_log.push(NotificationLogType::Error(self.localization.tr_args(
+                    "error-recording-failed",
+                    &[("channel", &channel_name), ("path", &track.path.display())],
+                )));
+                continue;
+            }
+            if status.dropped_frames > 0 {
+                self.notification_log.push(NotificationLogType::Error(self.localization.tr_args(
+                    "error-recording-dropped",
+                    &[("channel", &channel_name), ("frames", &status.dropped_frames)],
+                )));
+            }
+            if status.written_frames > 0 {
+                let length_secs = status.written_frames as f64 / sample_rate;
+                self.state.add_recorded_clip(track, start_secs, length_secs, source.clone());
+            }
+        }
+    }
+
+    fn finish_export(&mut self, result: std::io::Result<()>) {
+        if let Err(e) = result {
+            log::error!("Failed to export the project: {}", e);
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization.tr_args("error-export", &[("error", &e)]),
+            ));
+        }
+
+        self.export = None;
+        self.state.export_progress = None;
+        self.stop_engine();
+        self.activate_engine();
+    }
+
+    /// Update the input meters, opening the default input device if it isn't
+    /// open yet or was lost. Not having an input is not an error, so this just
+    /// tries again every `DEVICE_RETRY_INTERVAL`.
+    fn poll_input_meter(&mut self) {
+        if let Some(input_meter_handle) = &self.input_meter_handle {
+            if !input_meter_handle.device_lost() {
+                self.state.input_meter.update(&input_meter_handle.take_levels());
+                return;
+            }
+
+            log::warn!("Lost audio input device {:?}", input_meter_handle.device_name());
+            self.input_meter_handle = None;
+            self.input_meter_retry = None;
+            self.state.input_meter.clear();
+        }
+
+        if self.input_meter_retry.map_or(false, |t| t.elapsed() < DEVICE_RETRY_INTERVAL) {
+            return;
+        }
+
+        match temp_spawn_cpal_default_input_meter() {
+            Ok(input_meter_handle) => {
+                self.state.input_meter.device_name = input_meter_handle.device_name().to_string();
+                self.input_meter_handle = Some(input_meter_handle);
+                self.input_meter_retry = None;
+            }
+            Err(e) => {
+                log::debug!("No audio input device available yet: {}", e);
+                self.input_meter_retry = Some(Instant::now());
+            }
+        }
+    }
+
+    /// Check whether the audio device has gone away (i.e. it was unplugged).
+    ///
+    /// When it has, the engine is stopped and we keep trying to open the
+    /// default device until one becomes available, at which point the engine
+    /// is started again with the same output channel mapping (or the closest
+    /// one the new device supports).
+    fn check_audio_device(&mut self) {
+        if let Some(system_io_stream_handle) = &self.system_io_stream_handle {
+            if !system_io_stream_handle.device_lost() {
+                return;
+            }
+
+            let device_name = system_io_stream_handle.device_name().to_string();
+            let output_channel_map = system_io_stream_handle.output_channel_map();
+
+            log::warn!("Lost audio device {:?}", &device_name);
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization
+                    .tr_args("error-audio-device-disconnected", &[("device", &device_name)]),
+            ));
+
+            self.restore_transport = self.transport_position.take();
+            self.stop_engine();
+            self.system_io_stream_handle = None;
+
+            // Try to reconnect straight away.
+            self.device_retry = Some((None, output_channel_map));
+        }
+
+        let output_channel_map = match self.device_retry {
+            Some((last_try, map))
+                if last_try.map_or(true, |t| t.elapsed() >= DEVICE_RETRY_INTERVAL) =>
+            {
+                map
+            }
+            _ => return,
+        };
+
+        match system_io::temp_spawn_cpal_default_output_only(self.output_buffering) {
+            Ok(mut system_io_stream_handle) => {
+                system_io_stream_handle.set_output_channel_map(output_channel_map);
+
+                if system_io_stream_handle.sample_rate() != self.resource_loader.project_sr() {
+                    log::info!(
+                        "The new audio device runs at {:?}, reloading resources at that rate",
+                        system_io_stream_handle.sample_rate(),
+                    );
+                    self.resource_loader.set_project_sr(system_io_stream_handle.sample_rate());
+                }
+
+                self.notification_log.push(NotificationLogType::Info(self.localization.tr_args(
+                    "info-switched-audio-device",
+                    &[("device", &system_io_stream_handle.device_name())],
+                )));
+
+                self.state.audio_device_outputs = system_io_stream_handle.num_out_channels();
+                self.system_io_stream_handle = Some(system_io_stream_handle);
+                self.device_retry = None;
+                // The project may have changed while there was no device.
+                self.sync_output_channel_map();
+
+                // The transport goes back to where it was once the engine is
+                // activated, see `poll_engine()`.
+                self.activate_engine();
+            }
+            Err(e) => {
+                log::debug!("No audio device available yet: {}", e);
+                self.device_retry = Some((Some(Instant::now()), output_channel_map));
+            }
+        }
+    }
+
+    /// Switch between rendering straight into the audio device for the lowest
+    /// latency, and rendering ahead of it for fewer dropouts.
+    ///
+    /// This restarts the audio stream and the engine.
+    fn set_output_buffering(&mut self, buffering: OutputBuffering) {
+        if self.output_buffering == buffering {
+            return;
+        }
+        self.output_buffering = buffering;
+
+        if let Some(system_io_stream_handle) = &self.system_io_stream_handle {
+            let output_channel_map = system_io_stream_handle.output_channel_map();
+
+            self.restore_transport = self.transport_position.take();
+            self.stop_engine();
+            self.system_io_stream_handle = None;
+
+            // The stream is reopened with the new buffering on the next poll.
+            self.device_retry = Some((None, output_channel_map));
+        }
+    }
+
+    /// Write the contents of the sample editor to a new file, and switch all
+    /// audio clips that were playing the edited file over to the new file.
+    fn save_sample_editor(&mut self) {
+        let sample_editor = &mut self.state.sample_editor;
+        let (old_path, editor) = match (&sample_editor.path, &mut sample_editor.editor) {
+            (Some(path), Some(editor)) => (path.clone(), editor),
+            _ => return,
+        };
+
+        // Never overwrite the original file, since other projects may use it.
+        let stem = old_path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let mut n = 1;
+        let new_path = loop {
+            let path = old_path.with_file_name(format!("{}.edit{}.wav", stem, n));
+            if !path.exists() {
+                break path;
+            }
+            n += 1;
+        };
+
+        if let Err(e) = editor.save(&new_path) {
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization
+                    .tr_args("error-save-sample", &[("path", &new_path.display()), ("error", &e)]),
+            ));
+            return;
+        }
+
+        for clip in self.state.clips.iter_mut() {
+            if let ClipType::Audio(audio) = &mut clip.type_ {
+                if audio.active_pcm_path() == &old_path {
+                    audio.alternate_takes.push(new_path.clone());
+                    audio.active_take = Some(audio.alternate_takes.len() - 1);
+                }
+            }
+        }
+
+        sample_editor.name =
+            new_path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        sample_editor.path = Some(new_path);
+        sample_editor.sync();
+    }
+
+    /// Save the current arrangement of the panels as a workspace, replacing
+    /// any workspace with the same name.
+    fn save_workspace(&mut self, name: &str, global: bool) {
+        let workspace = Workspace::new(name.to_string(), &self.state.panels);
+
+        let workspaces = if global {
+            if let Err(e) = workspace.save_global() {
+                self.notification_log.push(NotificationLogType::Error(
+                    self.localization
+                        .tr_args("error-save-workspace", &[("name", &name), ("error", &e)]),
+                ));
+                return;
+            }
+            &mut self.global_workspaces
+        } else {
+            &mut self.state.workspaces
+        };
+
+        match workspaces.iter_mut().find(|w| w.name == name) {
+            Some(w) => *w = workspace,
+            None => workspaces.push(workspace),
+        }
+    }
+
+    /// Announce which playlist of a lane is now on the timeline.
+    fn announce_active_playlist(&mut self, lane: usize) {
+        let lane_state = match self.state.timeline_grid.lane_states.lanes.get(lane) {
+            Some(lane_state) => lane_state,
+            None => return,
+        };
+        let lane_name = lane_state.name.clone().unwrap_or_else(|| format!("lane {}", lane));
+        let playlist = match lane_state.playlists.get(lane_state.active_playlist) {
+            Some(playlist) => playlist.display_name(lane_state.active_playlist, &self.localization),
+            None => return,
+        };
+
+        self.announcement = self
+            .localization
+            .tr_args("announce-playlist-active", &[("lane", &lane_name), ("playlist", &playlist)]);
+    }
+
+    /// Add a copy of a clip right after the original on the same lane.
+    ///
+    /// If `alias` is true, the copy is linked to the original so they share
+    /// their notes. Otherwise the copy can be edited on its own.
+    fn duplicate_clip(&mut self, clip_index: usize, alias: bool) {
+        if alias && !self.state.link_clip(clip_index) {
+            return;
+        }
+
+        let clip = match self.state.clips.get(clip_index) {
+            Some(clip) => clip,
+            None => return,
+        };
+
+        if let ClipStart::OnLane(on_lane) = &clip.timeline_start {
+            let lane = on_lane.lane_index as usize;
+            let end_beats =
+                on_lane.timeline_start.get().as_beats_f64() + clip.length.get().as_beats_f64();
+
+            if let Some(ClipboardContents::Clips(copied)) = self.state.copy_clips(&[clip_index]) {
+                self.state.paste_clips(&copied, lane, end_beats);
+            }
+        } else {
+            let clip = clip.clone();
+            self.state.clips.push(clip);
+        }
+
+        if !alias {
+            self.state.unlink_clip(self.state.clips.len() - 1);
+        }
+    }
+
+    /// Render the clips of a channel into a new audio file, mute them, and add
+    /// a single clip playing the rendered file in their place.
+    ///
+    /// The file is written next to the file of the first clip on the channel.
+    fn freeze_channel(&mut self, channel: usize) {
+        let channel_name = match self.state.channels.get(channel) {
+            Some(channel) => channel.name.clone(),
+            None => return,
+        };
+
+        // The clips that are being frozen and the lowest lane they are on.
+        // Containers with audio in them are frozen as a whole.
+        let mut frozen = Vec::new();
+        let mut lane = None;
+        let mut first_path = None;
+        for (i, clip) in self.state.clips.iter().enumerate() {
+            let on_lane = match &clip.timeline_start {
+                ClipStart::OnLane(on_lane) if clip.channel == channel && !clip.muted => on_lane,
+                _ => continue,
+            };
+            let path = clip.resolve().into_iter().find_map(|clip| match clip.type_ {
+                ClipType::Audio(audio) => Some(audio.pcm_path),
+                _ => None,
+            });
+            if let Some(path) = path {
+                frozen.push(i);
+                lane = Some(lane.unwrap_or(u32::MAX).min(on_lane.lane_index));
+                first_path.get_or_insert(path);
+            }
+        }
+
+        let (mono, sample_rate) = self.render_channel_mono(channel);
+        let (lane, first_path, sample_rate) = match (lane, first_path, sample_rate) {
+            (Some(lane), Some(path), Some(sample_rate)) if !mono.is_empty() => {
+                (lane, path, sample_rate)
+            }
+            _ => {
+                self.notification_log.push(NotificationLogType::Info(
+                    self.localization
+                        .tr_args("info-no-clips-to-freeze", &[("channel", &channel_name)]),
+                ));
+                return;
+            }
+        };
+
+        let mut n = 1;
+        let path = loop {
+            let path = first_path.with_file_name(format!("{}.freeze{}.wav", channel_name, n));
+            if !path.exists() {
+                break path;
+            }
+            n += 1;
+        };
+
+        if let Err(e) = write_wav_f32(&path, sample_rate, &mono, &mono) {
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization
+                    .tr_args("error-freeze-channel", &[("channel", &channel_name), ("error", &e)]),
+            ));
+            return;
+        }
+
+        for i in frozen {
+            self.state.clips[i].muted = true;
+        }
+
+        // The render starts at the start of the project.
+        let length = Frames(mono.len() as u64).to_seconds(SampleRate(f64::from(sample_rate)));
+        let length = self.state.timeline_grid.seconds_to_musical(length.0);
+        self.state.clips.push(ClipState {
+            name: format!("{} (frozen)", channel_name),
+            timeline_start: ClipStart::OnLane(OnLane {
+                lane_index: lane,
+                timeline_start: MusicalTime::from_beats(0).into(),
+            }),
+            length: length.into(),
+            channel,
+            muted: false,
+            effects: Vec::new(),
+            recording: None,
+            player_host_id: None,
+            type_: ClipType::Audio(AudioClipState {
+                gain_db: 0.0,
+                fade_in_secs: Seconds(0.0).into(),
+                fade_out_secs: Seconds(0.0).into(),
+                clip_start_offset: SuperFrames(0).into(),
+                pcm_path: path,
+                alternate_takes: Vec::new(),
+                active_take: None,
+            }),
+        });
+    }
+
+    /// Create a new audio clip from an audio file at the given lane and
+    /// position on the timeline (in beats).
+    ///
+    /// If the lane doesn't exist yet, enough new lanes are added to the end of
+    /// the timeline to hold it. The clip is assigned to the first selected
+    /// channel, or to the master channel if none are selected.
+    fn import_audio_file(&mut self, path: &PathBuf, lane_index: usize, start_beats: f64) {
+        if !path.is_file() {
+            return;
+        }
+
+        let (pcm, res) = self.resource_loader.load_pcm(&PcmKey {
+            path: path.clone(),
+            resample_to_project_sr: true,
+            resample_quality: self.resample_quality,
+        });
+        if let Err(e) = res {
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization
+                    .tr_args("error-import-file", &[("path", &path.display()), ("error", &e)]),
+            ));
+            return;
+        }
+
+        let start_beats = start_beats.max(0.0);
+        let length_secs =
+            Frames(pcm.len_frames() as u64).to_seconds(SampleRate(f64::from(pcm.sample_rate()))).0;
+        let length_beats = self.state.timeline_grid.length_to_beats(start_beats, length_secs);
+
+        let lane_states = &mut self.state.timeline_grid.lane_states;
+        while lane_states.lanes.len() <= lane_index {
+            lane_states.push_lane(LaneState::default());
+        }
+
+        let channel = self.state.channels.iter().position(|channel| channel.selected).unwrap_or(0);
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("Audio"));
+
+        self.state.clips.push(ClipState {
+            name,
+            timeline_start: ClipStart::OnLane(OnLane {
+                lane_index: lane_index as u32,
+                timeline_start: musical_from_beats(start_beats).into(),
+            }),
+            length: musical_from_beats(length_beats).into(),
+            channel,
+            muted: false,
+            effects: Vec::new(),
+            recording: None,
+            player_host_id: None,
+            type_: ClipType::Audio(AudioClipState {
+                gain_db: 0.0,
+                fade_in_secs: Seconds(0.0).into(),
+                fade_out_secs: Seconds(0.0).into(),
+                clip_start_offset: SuperFrames(0).into(),
+                pcm_path: path.clone(),
+                alternate_takes: Vec::new(),
+                active_take: None,
+            }),
+        });
+
+        let timeline_grid = &mut self.state.timeline_grid;
+        let end_beats = start_beats + length_beats;
+        if end_beats > timeline_grid.project_length.get().as_beats_f64() {
+            timeline_grid.project_length = musical_from_beats(end_beats).into();
+        }
+        timeline_grid.used_lanes = timeline_grid.used_lanes.max(lane_index as u32);
+    }
+
+    /// Run a script from the console and print what it does. The changes of
+    /// each line are made before the next line runs, and the script stops at
+    /// the first line that fails.
+    fn run_script(&mut self, cx: &mut EventContext, script: &str) {
+        let lines: Vec<String> =
+            script.split(|c| c == '\n' || c == ';').map(String::from).collect();
+        self.run_script_lines(cx, &lines, 0);
+        cx.needs_redraw();
+    }
+
+    /// Run the lines of a script, and the lines those run (e.g. the lines of a
+    /// macro) one level deeper. Returns false if a line failed.
+    fn run_script_lines(&mut self, cx: &mut EventContext, lines: &[String], depth: usize) -> bool {
+        for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+            self.state
+                .console
+                .push(format!("{}> {}", "  ".repeat(depth), line), ConsoleLineKind::Input);
+
+            let output = match script::run_line(&self.state, line) {
+                Ok(output) => output,
+                Err(e) => {
+                    self.state
+                        .console
+                        .push(self.localization.tr_message(&e), ConsoleLineKind::Error);
+                    return false;
+                }
+            };
+            if let Some(text) = output.text {
+                self.state.console.push(text, ConsoleLineKind::Output);
+            }
+            for event in output.events {
+                match event {
+                    script::ScriptEvent::Ui(event) => self.event(cx, &mut Event::new(event)),
+                    script::ScriptEvent::Channel(event) => self.event(cx, &mut Event::new(event)),
+                }
+            }
+
+            if !output.run.is_empty() {
+                if depth >= script::MAX_SCRIPT_DEPTH {
+                    self.state.console.push(
+                        self.localization.tr_args("error-script-depth", &[("depth", &depth)]),
+                        ConsoleLineKind::Error,
+                    );
+                    return false;
+                }
+                if !self.run_script_lines(cx, &output.run, depth + 1) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Add the lines that make the same change as the event to the macro that
+    /// is being recorded.
+    fn record_macro_event(&mut self, event: &mut Event) {
+        let mut lines = Vec::new();
+        event.map(|ui_event: &UiEvent, _| lines = script::record_ui_event(&self.state, ui_event));
+        event.map(|channel_event: &ChannelEvent, _| {
+            lines = script::record_channel_event(channel_event)
+        });
+        self.state.macros.recorded.extend(lines);
+    }
+
+    /// Save what was recorded as a new macro and show it in the console.
+    fn stop_macro_recording(&mut self) {
+        let macros = &mut self.state.macros;
+        macros.recording = false;
+        if macros.recorded.is_empty() {
+            return;
+        }
+
+        let new_macro = Macro {
+            name: macros.unused_name(&self.localization.tr("macro-default-name")),
+            shortcut: None,
+            lines: std::mem::take(&mut macros.recorded),
+        };
+        if let Err(e) = new_macro.save_global() {
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization
+                    .tr_args("error-save-macro", &[("name", &new_macro.name), ("error", &e)]),
+            ));
+        }
+        self.notification_log.push(NotificationLogType::Info(self.localization.tr_args(
+            "info-macro-recorded",
+            &[("name", &new_macro.name), ("lines", &new_macro.lines.len())],
+        )));
+
+        let name = new_macro.name.clone();
+        macros.macros.push(new_macro);
+        macros.macros.sort_by(|a, b| a.name.cmp(&b.name));
+        macros.editing = macros.macros.iter().position(|m| m.name == name);
+        self.state.panels.hide_console = false;
+    }
+
+    /// Save a rack as a preset under its name, replacing any preset of the
+    /// same name.
+    fn save_fx_rack_preset(&mut self, slot: EffectSlot) {
+        let rack = match self.state.fx_rack(slot) {
+            Some(rack) => rack.clone(),
+            None => return,
+        };
+        if let Err(e) = rack.save_global() {
+            self.notification_log.push(NotificationLogType::Error(
+                self.localization
+                    .tr_args("error-save-fx-rack", &[("name", &rack.name), ("error", &e)]),
+            ));
+            return;
+        }
+
+        let presets = &mut self.state.fx_rack_presets;
+        presets.retain(|preset| preset.name != rack.name);
+        presets.push(rack);
+        presets.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    /// Save a macro after it was changed, reporting any problem.
+    fn save_macro(&mut self, index: usize) {
+        if let Some(m) = self.state.macros.macros.get(index) {
+            if let Err(e) = m.save_global() {
+                self.notification_log.push(NotificationLogType::Error(
+                    self.localization
+                        .tr_args("error-save-macro", &[("name", &m.name), ("error", &e)]),
+                ));
+            }
+        }
+    }
+
+    fn refresh_midi_out_devices(&mut self) {
+        match crate::backend::midi_out::output_device_names() {
+            Ok(names) => self.state.set_midi_out_devices(names),
+            Err(e) => log::error!("Failed to list MIDI output devices: {}", e),
+        }
+    }
+
+    /// Connect to the device of a channel's MIDI output and select its
+    /// program and bank there.
+    fn send_midi_output_setup(&mut self, index: usize) {
+        let route = match self.state.channels.get(index).and_then(|c| c.midi_output.clone()) {
+            Some(route) => route,
+            None => return,
+        };
+        let latency_offset_ms = self
+            .state
+            .midi_out_devices
+            .iter()
+            .find(|d| d.name == route.device)
+            .map_or(0.0, |d| d.latency_offset_ms);
+
+        if let Some(midi_out_handle) = &mut self.midi_out_handle {
+            midi_out_handle.open(&route.device);
+            midi_out_handle.set_latency_offset(&route.device, latency_offset_ms);
+            for message in route.sound_messages() {
+                midi_out_handle.send(&route.device, message);
+            }
+        }
+    }
+
+    /// Play or stop the notes of a key pressed or released with musical
+    /// typing on, or change the octave or velocity. Returns false if the key
+    /// isn't used by musical typing.
+    fn musical_typing_key(&mut self, cx: &mut EventContext, code: Code, down: bool) -> bool {
+        let typing = self.state.musical_typing.clone();
+        let key = match TypingKey::from_code(code) {
+            Some(key) if typing.enabled => key,
+            _ => return false,
+        };
+
+        match (key, down) {
+            (TypingKey::Note(semitones), true) => {
+                // Held keys repeat, but the note is only played once.
+                if !self.typing_held.iter().any(|(held, _)| *held == code) {
+                    if let Some(note) = typing.note(semitones) {
+                        self.typing_held.push((code, note));
+                        self.send_note(0, note, Some(typing.velocity));
+                    }
+                }
+            }
+            (TypingKey::Note(_), false) => {
+                if let Some(i) = self.typing_held.iter().position(|(held, _)| *held == code) {
+                    let (_, note) = self.typing_held.remove(i);
+                    self.send_note(0, note, None);
+                }
+            }
+            (TypingKey::OctaveDown, true) => cx.emit(UiEvent::SetTypingOctave(typing.octave - 1)),
+            (TypingKey::OctaveUp, true) => cx.emit(UiEvent::SetTypingOctave(typing.octave + 1)),
+            (TypingKey::VelocityDown, true) => {
+                cx.emit(UiEvent::SetTypingVelocity(typing.velocity - TYPING_VELOCITY_STEP))
+            }
+            (TypingKey::VelocityUp, true) => {
+                cx.emit(UiEvent::SetTypingVelocity(typing.velocity + TYPING_VELOCITY_STEP))
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Play a note on the MIDI input, or end it if `velocity` is `None`.
+    fn send_note(&mut self, channel: i16, key: i16, velocity: Option<f32>) {
+        let handle = match self
+            .engine_handles
+            .as_mut()
+            .and_then(|(engine_handles, _)| engine_handles.midi_capture_plug_handle.as_mut())
+        {
+            Some(handle) => {
+                handle.internal.as_mut().unwrap().downcast_mut::<MidiCapturePlugHandle>().unwrap()
+            }
+            None => return,
+        };
+        match velocity {
+            Some(velocity) => handle.play_note(channel, key, velocity),
+            None => handle.release_note(channel, key),
+        }
+    }
+
+    /// End every note held with musical typing.
+    fn release_typed_notes(&mut self) {
+        for (_, note) in std::mem::take(&mut self.typing_held) {
+            self.send_note(0, note, None);
+        }
+    }
+
+    /// Bring the plugins in the audio graph in line with the effects of the
+    /// project, after effects were added, removed or replaced.
+    ///
+    /// While a transaction is open this waits until it is committed.
+    fn host_plugins(&mut self) {
+        self.hosting_lazily = false;
+        if self.edit_transaction.is_none() {
+            self.sync_graph(usize::MAX);
+        }
+    }
+
+    /// Add at most `max_added` plugins of the project to the audio graph,
+    /// and remove the ones that are gone. Returns the number that were added.
+    fn sync_graph(&mut self, max_added: usize) -> usize {
+        let added = match &mut self.engine_handles {
+            Some((engine_handles, _)) => {
+                let added = self.state.host_plugins(engine_handles, max_added);
+                self.state.sync_timeline_tracks(
+                    &mut engine_handles.plugin_host,
+                    &mut self.resource_loader,
+                    self.resample_quality,
+                );
+                self.state.sync_drum_racks(
+                    &mut engine_handles.plugin_host,
+                    &mut self.resource_loader,
+                    self.resample_quality,
+                );
+                added
+            }
+            None => return 0,
+        };
+        if let Some(profile) = &mut self.load_profile {
+            profile.add_plugins_hosted(added);
+        }
+        self.flush_graph_requests();
+        self.sync_output_channel_map();
+        added
+    }
+
+    /// Send the outputs of the engine to the outputs of the audio device the
+    /// channels with hardware outputs play on. Only sent when it changed.
+    fn sync_output_channel_map(&mut self) {
+        if let Some(system_io_stream_handle) = &mut self.system_io_stream_handle {
+            let current = system_io_stream_handle.output_channel_map();
+            let map = self.state.output_channel_map(current);
+            if map.resolve(system_io_stream_handle.num_out_channels()) != current {
+                system_io_stream_handle.set_output_channel_map(map);
+            }
+        }
+    }
+
+    /// Add the next few plugins of a project that is loaded lazily to the
+    /// audio graph.
+    fn host_next_plugins(&mut self) {
+        if self.edit_transaction.is_some() {
+            return;
+        }
+        let added = self.sync_graph(PLUGINS_HOSTED_PER_POLL);
+        self.hosting_lazily = added == PLUGINS_HOSTED_PER_POLL;
+        if let Some((engine_handles, _)) = &mut self.engine_handles {
+            // The last plugins may have been added before hosting was known
+            // to be done.
+            if let Some(export) = &mut self.export {
+                if !self.hosting_lazily
+                    && engine_handles.activated_info.is_some()
+                    && !engine_handles.plugin_host.has_pending()
+                {
+                    export.set_graph_ready();
+                }
+            }
+        }
+    }
+
+    /// Log the timings of a loaded project once its audio graph is built.
+    fn poll_load_profile(&mut self) {
+        if self.hosting_lazily {
+            return;
+        }
+        let graph_built = match &self.engine_handles {
+            Some((engine_handles, _)) => !engine_handles.plugin_host.has_pending(),
+            None => true,
+        };
+        if graph_built {
+            if let Some(profile) = self.load_profile.take() {
+                profile.finish();
+            }
+        }
+    }
+
+    /// Turn the most recent performance on the MIDI input into a piano roll
+    /// clip, whether or not anything was recording.
+    ///
+    /// The clip starts at the first bar line in view on the last selected lane
+    /// (or the first lane), with the first note on its first beat. Its length
+    /// is rounded up to whole bars.
+    fn capture_last_performance(&mut self) -> bool {
+        let notes = match self
+            .engine_handles
+            .as_mut()
+            .and_then(|(engine_handles, _)| engine_handles.midi_capture_plug_handle.as_mut())
+        {
+            Some(handle) => handle
+                .internal
+                .as_mut()
+                .unwrap()
+                .downcast_mut::<MidiCapturePlugHandle>()
+                .unwrap()
+                .last_performance(),
+            None => Vec::new(),
+        };
+        if notes.is_empty() {
+            self.notification_log.push(NotificationLogType::Info(
+                self.localization.tr("info-no-performance-to-capture"),
+            ));
+            return false;
+        }
+
+        let timeline_grid = &mut self.state.timeline_grid;
+        let time_signatures = &timeline_grid.time_signatures;
+        let start_beats = time_signatures.next_bar(timeline_grid.left_start.get().as_beats_f64());
+        let bar = time_signatures.signature_at(start_beats).bar_length();
+        let bpm = timeline_grid.bpm_at(start_beats);
+
+        let end_secs = notes.iter().map(|note| note.start_secs + note.len_secs).fold(0.0, f64::max);
+        let end_beats = timeline_grid.length_to_beats(start_beats, end_secs);
+        let length_beats = (end_beats / bar).ceil() * bar;
+        let lane_index = timeline_grid.lane_states.last_selected_index().unwrap_or(0);
+
+        while timeline_grid.lane_states.lanes.len() <= lane_index {
+            timeline_grid.lane_states.push_lane(LaneState::default());
+        }
+
+        // The clip starts on a bar line, so the grid of the clip lines up with
+        // the grid of the timeline.
+        let channel = self.state.channels.iter().position(|channel| channel.selected).unwrap_or(0);
+        // The notes were played from the start of the clip, at the tempo there.
+        let quantize = self.state.input_quantize;
+        let timeline_grid = &self.state.timeline_grid;
+        let clip_start_secs = timeline_grid.beats_to_seconds(start_beats);
+        let to_clip_beats = |secs: f64| {
+            timeline_grid.seconds_to_beats(clip_start_secs + secs.max(0.0)) - start_beats
+        };
+        let mut notes = self.state.process_captured_notes(channel, &notes, |note, out| {
+            let note_start = to_clip_beats(note.start_secs);
+            let note_end = to_clip_beats(note.start_secs + note.len_secs);
+            let note = PianoRollNote {
+                start: musical_from_beats(note_start).into(),
+                length: musical_from_beats(note_end - note_start).into(),
+                key: out.key,
+                velocity: out.velocity,
+                recorded_start: None,
+            };
+            if quantize.enabled {
+                quantize.quantize_note(note)
+            } else {
+                note
+            }
+        });
+        notes.sort_by(|a, b| {
+            let (a, b) = (a.start.get().as_beats_f64(), b.start.get().as_beats_f64());
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let recording = RecordingInfo {
+            source: self.localization.tr("recording-source-note-input"),
+            take: self.state.next_take(lane_index),
+            bpm,
+            date: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+        };
+
+        self.state.clips.push(ClipState {
+            name: self
+                .localization
+                .tr_args("clip-captured-performance", &[("take", &recording.take)]),
+            timeline_start: ClipStart::OnLane(OnLane {
+                lane_index: lane_index as u32,
+                timeline_start: musical_from_beats(start_beats).into(),
+            }),
+            length: musical_from_beats(length_beats).into(),
+            channel,
+            muted: false,
+            effects: Vec::new(),
+            recording: Some(recording),
+            player_host_id: None,
+            type_: ClipType::PianoRoll(PianoRollClipState { notes, link: None }),
+        });
+
+        let timeline_grid = &mut self.state.timeline_grid;
+        let end_beats = start_beats + length_beats;
+        if end_beats > timeline_grid.project_length.get().as_beats_f64() {
+            timeline_grid.project_length = musical_from_beats(end_beats).into();
+        }
+        timeline_grid.used_lanes = timeline_grid.used_lanes.max(lane_index as u32);
+
+        true
+    }
+
+    /// Load the PCM resource of an audio clip. Either the original file or the
+    /// active take is loaded.
+    ///
+    /// Failures are reported in the notification log, where `action` is the key
+    /// of the message that describes what was being attempted.
+    fn load_clip_pcm(
+        &mut self,
+        clip_index: usize,
+        original: bool,
+        action: &str,
+    ) -> Option<Shared<PcmRAM>> {
+        let clip = self.state.clips.get(clip_index)?.clone();
+        self.load_pcm_of_clip(&clip, original, action)
+    }
+
+    /// Same as `load_clip_pcm()`, for a clip that doesn't have to be in the
+    /// list of clips (i.e. one inside a container).
+    fn load_pcm_of_clip(
+        &mut self,
+        clip: &ClipState,
+        original: bool,
+        action: &str,
+    ) -> Option<Shared<PcmRAM>> {
+        let path = match &clip.type_ {
+            ClipType::Audio(audio) if original => audio.pcm_path.clone(),
+            ClipType::Audio(audio) => audio.active_pcm_path().clone(),
+            _ => return None,
+        };
+
+        let (pcm, res) = self.resource_loader.load_pcm(&PcmKey {
+            path,
+            resample_to_project_sr: true,
+            resample_quality: self.resample_quality,
+        });
+
+        match res {
+            Ok(()) => Some(pcm),
+            Err(e) => {
+                self.notification_log.push(NotificationLogType::Error(self.localization.tr_args(
+                    "error-load-clip",
+                    &[
+                        ("action", &self.localization.tr(action)),
+                        ("clip", &clip.name),
+                        ("error", &e),
+                    ],
+                )));
+                None
+            }
+        }
+    }
+}
+
+impl Model for UiData {
+    // Update the program layer here
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        if self.state.macros.recording {
+            self.record_macro_event(event);
+        }
+
+        // Remember the project before anything edits it. Events sent from
+        // within this one (i.e. by a script) belong to the same edit.
+        let mut history_label = None;
+        let mut history_target = None;
+        event.map(|ui_event: &UiEvent, _| {
+            history_label = history::history_label(ui_event);
+            history_target = history::history_target(ui_event);
+        });
+        event.map(|channel_event: &ChannelEvent, _| {
+            history_label = history::channel_history_label(channel_event);
+            history_target = history::channel_history_target(channel_event);
+        });
+        if let Some(label) = history_label {
+            self.history.record(label, history_target, &self.state);
+        }
+        self.history.begin_group();
+
+        event.map(|program_event, _| match program_event {
+            UiEvent::PollEngine => {
+                self.check_audio_device();
+                if self.hosting_lazily {
+                    self.host_next_plugins();
+                }
+                self.poll_engine();
+                self.poll_load_profile();
+                let metronome_added = self.engine_handles.as_ref().map_or(false, |(h, _)| {
+                    h.metronome_plug_handle.is_some() && !h.metronome_synced
+                });
+                if metronome_added {
+                    self.sync_metronome();
+                }
+                self.poll_count_in();
+                self.poll_export();
+                self.poll_recording();
+                self.poll_input_meter();
+
+                let new_waveforms = self.resource_loader.poll_waveform_peaks();
+                if new_waveforms
+                    || !self.state.panels.hide_loudness_history
+                    || !self.state.panels.hide_eq
+                {
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::Announce { key, args } => {
+                let args: Vec<(&str, &dyn ToString)> =
+                    args.iter().map(|(name, value)| (*name, value as &dyn ToString)).collect();
+                self.announcement = self.localization.tr_args(key, &args);
+            }
+            UiEvent::SetLocale(locale) => {
+                self.localization.set_locale(locale);
+            }
+            UiEvent::BeginEdit => {
+                self.begin_edit();
+            }
+            UiEvent::CommitEdit => {
+                self.commit_edit();
+                cx.needs_redraw();
+            }
+            UiEvent::RollbackEdit => {
+                self.rollback_edit();
+                cx.needs_redraw();
+            }
+            UiEvent::Undo => {
+                if let Some(label) = self.history.undo(&mut self.state) {
+                    self.host_plugins();
+                    cx.emit(UiEvent::Announce {
+                        key: "announce-undo",
+                        args: vec![("action", self.localization.tr(label))],
+                    });
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::Redo => {
+                if let Some(label) = self.history.redo(&mut self.state) {
+                    self.host_plugins();
+                    cx.emit(UiEvent::Announce {
+                        key: "announce-redo",
+                        args: vec![("action", self.localization.tr(label))],
+                    });
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::SetHistoryDepth(depth) => {
+                self.history.set_depth(*depth);
+            }
+            UiEvent::CollectResources => {
+                self.resource_loader.collect();
+
+                let stats = self.resource_loader.stats();
+                log::debug!(
+                    "Collected resources in {:?}: {} loaded, {} outstanding allocations",
+                    stats.last_collection_duration,
+                    stats.loaded_resources,
+                    stats.outstanding_allocations
+                );
+            }
+            UiEvent::SetMasterOutputChannels { left, right } => {
+                if let Some(system_io_stream_handle) = &mut self.system_io_stream_handle {
+                    let mut map = system_io_stream_handle.output_channel_map();
+                    map.channels[0] = Some(*left);
+                    map.channels[1] = Some(*right);
+
+                    let resolved = system_io_stream_handle.set_output_channel_map(map);
+                    if resolved != map {
+                        self.notification_log.push(NotificationLogType::Info(
+                            self.localization.tr_args(
+                                "info-master-outputs-remapped",
+                                &[
+                                    ("outputs", &system_io_stream_handle.num_out_channels()),
+                                    ("left", &(resolved.channels[0].unwrap_or(0) + 1)),
+                                    ("right", &(resolved.channels[1].unwrap_or(0) + 1)),
+                                ],
+                            ),
+                        ));
+                    }
+                }
+            }
+            UiEvent::ResetInputClip => {
+                self.state.input_meter.reset_clipped();
+            }
+            UiEvent::SaveWorkspace { name, global } => {
+                self.save_workspace(name, *global);
+            }
+            UiEvent::LoadWorkspace(name) => {
+                // Workspaces saved with the project take priority over global ones.
+                let workspace = self
+                    .state
+                    .workspaces
+                    .iter()
+                    .chain(self.global_workspaces.iter())
+                    .find(|w| &w.name == name);
+                if let Some(workspace) = workspace {
+                    self.state.panels = workspace.panels.clone();
+                }
+            }
+            UiEvent::DeleteWorkspace { name, global } => {
+                if *global {
+                    if let Err(e) = Workspace::delete_global(name) {
+                        self.notification_log.push(NotificationLogType::Error(
+                            self.localization.tr_args(
+                                "error-delete-workspace",
+                                &[("name", name), ("error", &e)],
+                            ),
+                        ));
+                    }
+                    self.global_workspaces.retain(|w| &w.name != name);
+                } else {
+                    self.state.workspaces.retain(|w| &w.name != name);
+                }
+            }
+            UiEvent::SetOutputBuffering(buffering) => {
+                self.set_output_buffering(*buffering);
+            }
+            UiEvent::SetResampleQuality(quality) => self.resample_quality = *quality,
+            UiEvent::SetLazyGraph(lazy) => self.lazy_graph = *lazy,
+            UiEvent::ToggleLoop => {
+                let loop_region = &mut self.state.timeline_grid.loop_region;
+                loop_region.enabled = !loop_region.enabled;
+                cx.needs_redraw();
+            }
+            UiEvent::SetLoopRegion { start_beats, end_beats } => {
+                let loop_region = LoopRegion {
+                    start_beats: *start_beats,
+                    end_beats: *end_beats,
+                    ..self.state.timeline_grid.loop_region
+                };
+                if loop_region.is_valid() {
+                    self.state.timeline_grid.loop_region = loop_region;
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::ToggleMetronome => {
+                self.state.metronome.enabled = !self.state.metronome.enabled;
+                self.sync_metronome();
+            }
+            UiEvent::SetMetronomeSound(sound) => {
+                self.state.metronome.sound = sound.clone();
+                self.sync_metronome();
+            }
+            UiEvent::SetMetronomeLevel(db) => {
+                self.state.metronome.set_level_db(*db);
+                self.sync_metronome();
+            }
+            UiEvent::SetCountInBars(bars) => self.state.metronome.set_count_in_bars(*bars),
+            UiEvent::AddSection => {
+                let name = self.localization.tr_args(
+                    "section-default-name",
+                    &[("number", &(self.state.arranger.sections.len() + 1))],
+                );
+                self.state.add_section(name);
+                cx.needs_redraw();
+            }
+            UiEvent::SelectSection(index) => {
+                if let Some(section) = self.state.arranger.sections.get(*index) {
+                    self.state.arranger.selected = Some(*index);
+                    self.state.inspected = Some(Inspected::Section(*index));
+                    self.announcement = self
+                        .localization
+                        .tr_args("announce-section-selected", &[("section", &section.name)]);
+                }
+            }
+            UiEvent::RenameSection(index, name) => {
+                if let Some(section) = self.state.arranger.sections.get_mut(*index) {
+                    section.name = name.clone();
+                }
+            }
+            UiEvent::SetSectionLength(index, beats) => {
+                self.state.set_section_length(*index, *beats);
+                cx.needs_redraw();
+            }
+            UiEvent::MoveSection { from, to } => {
+                self.state.move_section(*from, *to);
+                cx.needs_redraw();
+            }
+            UiEvent::DuplicateSection(index) => {
+                self.state.duplicate_section(*index);
+                cx.needs_redraw();
+            }
+            UiEvent::RemoveSection(index) => {
+                self.state.remove_section(*index);
+                cx.needs_redraw();
+            }
+            UiEvent::CaptureLastPerformance => {
+                if self.capture_last_performance() {
+                    cx.emit(UiEvent::Announce {
+                        key: "announce-performance-captured",
+                        args: Vec::new(),
+                    });
+                }
+                cx.needs_redraw();
+            }
+            UiEvent::SetInputQuantize(quantize) => {
+                self.state.input_quantize = InputQuantize {
+                    grid_beats: quantize.grid_beats.max(1.0 / 64.0),
+                    strength: quantize.strength.clamp(0.0, 1.0),
+                    ..*quantize
+                };
+            }
+            UiEvent::RequantizeClip(clip) => {
+                self.state.requantize_clip(*clip);
+                cx.needs_redraw();
+            }
+            UiEvent::ToggleMusicalTyping => {
+                let typing = &mut self.state.musical_typing;
+                typing.enabled ^= true;
+                let key = if typing.enabled {
+                    "announce-musical-typing-on"
+                } else {
+                    "announce-musical-typing-off"
+                };
+                if !typing.enabled {
+                    self.release_typed_notes();
+                }
+                self.announcement = self.localization.tr(key);
+            }
+            UiEvent::SetTypingOctave(octave) => {
+                let typing = &mut self.state.musical_typing;
+                typing.octave = (*octave).clamp(MIN_TYPING_OCTAVE, MAX_TYPING_OCTAVE);
+                let octave = typing.octave_name();
+                self.announcement =
+                    self.localization.tr_args("announce-typing-octave", &[("octave", &octave)]);
+            }
+            UiEvent::PlayOnScreenNote { key, velocity } => {
+                let channel = self.state.focused_midi_channel();
+                if !self.onscreen_held.contains(&(channel, *key)) {
+                    self.onscreen_held.push((channel, *key));
+                    self.send_note(channel, *key, Some(velocity.clamp(0.0, 1.0)));
+                }
+            }
+            UiEvent::ReleaseOnScreenNote(key) => {
+                if let Some(i) = self.onscreen_held.iter().position(|(_, held)| held == key) {
+                    let (channel, key) = self.onscreen_held.remove(i);
+                    self.send_note(channel, key, None);
+                }
+            }
+            UiEvent::SetKeyboardOctave(octave) => {
+                self.state.onscreen_instruments.keyboard_octave =
+                    (*octave).clamp(MIN_KEYBOARD_OCTAVE, MAX_KEYBOARD_OCTAVE);
+            }
+            UiEvent::SetFirstPadKey(key) => {
+                self.state.onscreen_instruments.first_pad_key =
+                    (*key).clamp(0, 128 - PAD_ROWS * PAD_COLUMNS);
+            }
+            UiEvent::SetTypingVelocity(velocity) => {
+                let typing = &mut self.state.musical_typing;
+                typing.velocity = velocity.clamp(1.0 / 127.0, 1.0);
+                let velocity = (typing.velocity * 127.0).round();
+                self.announcement = self
+                    .localization
+                    .tr_args("announce-typing-velocity", &[("velocity", &velocity)]);
+            }
+            UiEvent::ImportAudioFile { path, lane, start_beats } => {
+                self.import_audio_file(path, *lane, *start_beats);
+                cx.needs_redraw();
+            }
+            UiEvent::SaveProject => {
+                let path = Path::new(PROJECT_PATH);
+                if let Some((engine_handles, _)) = &mut self.engine_handles {
+                    self.state.update_saved_states(&mut engine_handles.plugin_host);
+                }
+                if let Err(e) = ProjectSaveState::from_state(&self.state).save_to_file(path) {
+                    self.notification_log.push(NotificationLogType::Error(
+                        self.localization.tr_args("error-save-project", &[("error", &e)]),
+                    ));
+                } else if let Err(e) = ProjectUiState::from_state(&self.state).save(path) {
+                    self.notification_log.push(NotificationLogType::Error(
+                        self.localization.tr_args("error-save-ui-state", &[("error", &e)]),
+                    ));
+                }
+            }
+            UiEvent::ExportMaster(options) => {
+                if let Err(e) = self.export_master(Path::new(EXPORT_PATH), *options) {
+                    let e = self.localization.tr_message(&e);
+                    self.notification_log.push(NotificationLogType::Error(
+                        self.localization.tr_args("error-export", &[("error", &e)]),
+                    ));
+                }
+            }
+            UiEvent::ExportStems(options) => {
+                if let Err(e) = self.export_stems(Path::new(EXPORT_STEMS_DIR), *options) {
+                    let e = self.localization.tr_message(&e);
+                    self.notification_log.push(NotificationLogType::Error(
+                        self.localization.tr_args("error-export", &[("error", &e)]),
+                    ));
+                }
+            }
+            UiEvent::StartRecording => self.start_recording_with_count_in(),
+            UiEvent::StopRecording => self.stop_recording(),
+            UiEvent::LoadProject => {
+                let path = Path::new(PROJECT_PATH);
+                let mut profile = LoadProfile::new();
+                match ProjectSaveState::load_from_file(path) {
+                    Ok(project) => {
+                        profile.phase("read");
+                        project.apply(&mut self.state);
+                        self.history.clear();
+                        profile.phase("apply");
+
+                        // The view is restored after the project, so it
+                        // applies to the loaded lanes.
+                        if let Some(ui_state) = ProjectUiState::load(path, &self.state) {
+                            ui_state.apply(&mut self.state);
+                        }
+                        profile.phase("view");
+
+                        // The rest of a lazily loaded graph is built while
+                        // polling the engine.
+                        self.load_profile = Some(profile);
+                        if self.lazy_graph {
+                            self.host_next_plugins();
+                        } else {
+                            self.host_plugins();
+                        }
+                        if let Some(profile) = &mut self.load_profile {
+                            profile.phase("host");
+                        }
+                        cx.needs_redraw();
+                    }
+                    Err(e) => {
+                        self.notification_log.push(NotificationLogType::Error(
+                            self.localization.tr_args("error-load-project", &[("error", &e)]),
+                        ));
+                    }
+                }
+            }
+            UiEvent::BrowserFileClicked(path) => {
+                if let Some((engine_handles, _)) = &mut self.engine_handles {
+                    if let Some(browser_plug_handle) =
+                        &mut engine_handles.sample_browser_plug_handle
+                    {
+                        let browser_plug_handle = browser_plug_handle
+                            .internal
+                            .as_mut()
+                            .unwrap()
+                            .downcast_mut::<SampleBrowserPlugHandle>()
+                            .unwrap();
+
+                        // TODO: Only play audio files.
+                        let already_loaded =
+                            if let Some(last_path) = &self.last_clicked_browser_file {
+                                last_path == path
+                            } else {
+                                false
+                            };
+
+                        if already_loaded {
+                            browser_plug_handle.replay_sample();
+                        } else {
+                            let (pcm, res) = self.resource_loader.load_pcm(&PcmKey {
+                                path: path.clone(),
+                                resample_to_project_sr: true,
+                                resample_quality: self.resample_quality,
+                            });
+
+                            match res {
+                                Ok(()) => {
+                                    browser_plug_handle.play_sample(pcm);
+                                    self.last_clicked_browser_file = Some(path.clone());
+                                }
+                                Err(e) => {
+                                    log::error!("Failed to load pcm resource: {}", e);
+                                    self.last_clicked_browser_file = None;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            UiEvent::RenameClip(clip, name) => {
+                if let Some(clip) = self.state.clips.get_mut(*clip) {
+                    clip.name = name.clone();
+                }
+            }
+            UiEvent::DuplicateClip(clip) => {
+                self.duplicate_clip(*clip, false);
+                cx.needs_redraw();
+            }
+            UiEvent::DuplicateClipAsAlias(clip) => {
+                self.duplicate_clip(*clip, true);
+                cx.needs_redraw();
+            }
+            UiEvent::UnlinkClip(clip) => {
+                self.state.unlink_clip(*clip);
+            }
+            UiEvent::AddLanePlaylist { lane, copy } => {
+                self.state.add_playlist(*lane, *copy);
+                self.announce_active_playlist(*lane);
+                cx.needs_redraw();
+            }
+            UiEvent::SwitchLanePlaylist { lane, playlist } => {
+                self.state.switch_playlist(*lane, *playlist);
+                self.announce_active_playlist(*lane);
+                cx.needs_redraw();
+            }
+            UiEvent::RemoveLanePlaylist(lane) => {
+                self.state.remove_active_playlist(*lane);
+                self.announce_active_playlist(*lane);
+                cx.needs_redraw();
+            }
+            UiEvent::SetClipNotes(clip, notes) => {
+                self.state.set_clip_notes(*clip, notes.clone());
+                cx.needs_redraw();
+            }
+            UiEvent::ToggleClipMute(clip) => {
+                if let Some(clip) = self.state.clips.get_mut(*clip) {
+                    clip.muted ^= true;
+                    let key =
+                        if clip.muted { "announce-clip-muted" } else { "announce-clip-unmuted" };
+                    self.announcement = self.localization.tr_args(key, &[("clip", &clip.name)]);
+                }
+            }
+            UiEvent::InspectClip(clip) => {
+                self.state.inspected = Some(Inspected::Clip(*clip));
+            }
+            UiEvent::RemoveClips(clips) => {
+                self.state.remove_clips(clips);
+                cx.needs_redraw();
+            }
+            UiEvent::ZoomToSelection => match self.state.selection_range() {
+                Some(range) => {
+                    self.state.timeline_grid.show_range(range.start, range.end);
+                    cx.needs_redraw();
+                }
+                None => self.notification_log.push(NotificationLogType::Info(
+                    self.localization.tr("info-nothing-to-zoom-to"),
+                )),
+            },
+            UiEvent::GroupSelectedLaneClips => {
+                let lanes = self.state.timeline_grid.lane_states.lane_indices(|lane| lane.selected);
+                let clips: Vec<usize> = self
+                    .state
+                    .clips
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, clip)| match &clip.timeline_start {
+                        ClipStart::OnLane(on_lane) => {
+                            lanes.contains(&(on_lane.lane_index as usize))
+                        }
+                        ClipStart::NotInTimeline => false,
+                    })
+                    .map(|(i, _)| i)
+                    .collect();
+                if clips.is_empty() {
+                    self.notification_log.push(NotificationLogType::Info(
+                        self.localization.tr("info-no-clips-to-group"),
+                    ));
+                } else {
+                    self.state.group_clips(&clips, self.localization.tr("clip-container"));
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::UnpackContainer(clip) => {
+                self.state.unpack_container(*clip);
+                cx.needs_redraw();
+            }
+            UiEvent::SetContainerOffset(clip, beats) => {
+                self.state.set_container_offset(*clip, *beats);
+                cx.needs_redraw();
+            }
+            UiEvent::SetClipStart(clip, beats) => {
+                self.state.set_clip_start(*clip, *beats);
+                cx.needs_redraw();
+            }
+            UiEvent::SetClipLength(clip, beats) => {
+                self.state.set_clip_length(*clip, *beats);
+                cx.needs_redraw();
+            }
+            UiEvent::SetClipGain(clip, db) => {
+                self.state.set_clip_gain(*clip, *db);
+            }
+            UiEvent::SetClipFade { clip, fade_out, secs } => {
+                self.state.set_clip_fade(*clip, *fade_out, *secs);
+            }
+            UiEvent::FreezeChannel(channel) => {
+                self.freeze_channel(*channel);
+                cx.needs_redraw();
+            }
+            UiEvent::CopyClips(clips) => {
+                if let Some(contents) = self.state.copy_clips(clips) {
+                    self.clipboard = Some(contents);
+                }
+            }
+            UiEvent::CutClips(clips) => {
+                if let Some(contents) = self.state.copy_clips(clips) {
+                    self.clipboard = Some(contents);
+                    self.state.remove_clips(clips);
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::PasteClips { lane, start_beats } => {
+                if let Some(ClipboardContents::Clips(clips)) = &self.clipboard {
+                    self.state.paste_clips(clips, *lane, *start_beats);
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::CopySelectedLanes => {
+                if let Some(contents) = self.state.copy_selected_lanes() {
+                    self.clipboard = Some(contents);
+                }
+            }
+            UiEvent::CutSelectedLanes => {
+                if let Some(contents) = self.state.copy_selected_lanes() {
+                    self.clipboard = Some(contents);
+                    self.state.remove_selected_lanes();
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::PasteLanes => {
+                if let Some(ClipboardContents::Lanes { lanes, clips }) = &self.clipboard {
+                    self.state.paste_lanes(lanes, clips);
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::CopyEffects(channel) => {
+                if let Some(contents) = self.state.copy_effects(*channel) {
+                    self.clipboard = Some(contents);
+                }
+            }
+            UiEvent::PasteEffects => {
+                if let Some(ClipboardContents::Effects(effects)) = &self.clipboard {
+                    self.state.paste_effects(effects);
+                    self.host_plugins();
+                }
+            }
+            UiEvent::AddPlugin { channel, rdn } => {
+                let plugin = self
+                    .engine_handles
+                    .as_ref()
+                    .and_then(|(engine_handles, _)| engine_handles.plugin_host.find_available(rdn))
+                    .cloned();
+                if let Some(plugin) = plugin {
+                    if self.state.add_plugin_effect(*channel, &plugin).is_some() {
+                        self.host_plugins();
+                        cx.emit(UiEvent::Announce {
+                            key: "announce-plugin-added",
+                            args: vec![("plugin", plugin.name)],
+                        });
+                    }
+                }
+            }
+            UiEvent::RemoveEffect(slot) => {
+                if self.state.remove_effect(*slot).is_some() {
+                    self.host_plugins();
+                }
+            }
+            UiEvent::MoveEffect { slot, to } => {
+                self.state.move_effect(*slot, *to);
+            }
+            UiEvent::SetEffectBypassed { slot, bypassed } => {
+                self.state.set_effect_bypassed(*slot, *bypassed);
+            }
+            UiEvent::AddFxRack(channel) => {
+                let rack = FxRackState::new(self.localization.tr("fx-rack-default-name"));
+                self.state.add_fx_rack(*channel, rack);
+            }
+            UiEvent::EditFxRack { slot, edit } => {
+                let edit = match edit {
+                    FxRackEdit::AddChain(_) => {
+                        let chains = self.state.fx_rack(*slot).map_or(0, |r| r.chains.len());
+                        let number = chains + 1;
+                        FxRackEdit::AddChain(
+                            self.localization.tr_args("chain-default-name", &[("number", &number)]),
+                        )
+                    }
+                    edit => edit.clone(),
+                };
+                self.state.edit_fx_rack(*slot, &edit);
+                self.host_plugins();
+            }
+            UiEvent::SaveFxRackPreset(slot) => self.save_fx_rack_preset(*slot),
+            UiEvent::LoadFxRackPreset { channel, preset } => {
+                if let Some(rack) = self.state.fx_rack_presets.get(*preset).cloned() {
+                    self.state.add_fx_rack(*channel, rack);
+                    self.host_plugins();
+                }
+            }
+            UiEvent::RescanPlugins => {
+                if let Some((engine_handles, _)) = &mut self.engine_handles {
+                    engine_handles.ds_handle.send(DSEngineRequest::RescanPluginDirectories);
+                }
+            }
+            UiEvent::RandomizeParameters(slot) => {
+                if let Some(effect) = self.state.external_effect_mut(*slot) {
+                    effect.randomize_parameters(&mut self.random);
+                }
+            }
+            UiEvent::SetRandomizeRange { slot, parameter, range } => {
+                if let Some(effect) = self.state.external_effect_mut(*slot) {
+                    effect.set_randomize_range(*parameter, *range);
+                }
+            }
+            UiEvent::StoreMorphPreset { slot, b } => {
+                if let Some(effect) = self.state.external_effect_mut(*slot) {
+                    effect.store_morph_preset(*b);
+                }
+            }
+            UiEvent::SetMorphAmount { slot, amount } => {
+                if let Some(effect) = self.state.external_effect_mut(*slot) {
+                    effect.set_morph_amount(*amount);
+                }
+            }
+            UiEvent::CopyClipEffects(clip) => {
+                if let Some(contents) = self.state.copy_clip_effects(*clip) {
+                    self.clipboard = Some(contents);
+                }
+            }
+            UiEvent::PasteClipEffects(clip) => {
+                if let Some(ClipboardContents::Effects(effects)) = &self.clipboard {
+                    self.state.paste_clip_effects(*clip, effects);
+                }
+            }
+            UiEvent::ClearClipEffects(clip) => {
+                if let Some(clip) = self.state.clips.get_mut(*clip) {
+                    clip.effects.clear();
+                }
+            }
+            UiEvent::StripSilence { clip, settings, mode } => {
+                self.strip_silence(*clip, settings, *mode);
+            }
+            UiEvent::LearnNoiseProfile { clip, selection } => {
+                self.learn_noise_profile(*clip, selection);
+            }
+            UiEvent::DenoiseClip { clip, settings } => {
+                self.denoise_clip(*clip, settings);
+            }
+            UiEvent::AnalyzeCollisions => {
+                self.analyze_collisions();
+                cx.needs_redraw();
+            }
+            UiEvent::RefreshMidiOutDevices => {
+                self.refresh_midi_out_devices();
+            }
+            UiEvent::SetMidiOutput { channel, device } => {
+                self.state.set_midi_output(*channel, device.clone());
+                self.send_midi_output_setup(*channel);
+            }
+            UiEvent::SetMidiOutChannel { channel, midi_channel } => {
+                self.state.edit_midi_output(*channel, |route| route.channel = *midi_channel);
+                self.send_midi_output_setup(*channel);
+            }
+            UiEvent::SetMidiProgram { channel, program } => {
+                self.state.edit_midi_output(*channel, |route| route.program = *program);
+                self.send_midi_output_setup(*channel);
+            }
+            UiEvent::SetMidiBank { channel, bank } => {
+                self.state.edit_midi_output(*channel, |route| route.bank = *bank);
+                self.send_midi_output_setup(*channel);
+            }
+            UiEvent::SetMidiOutLatency { device, ms } => {
+                if let Some(ms) = self.state.set_midi_out_latency(device, *ms) {
+                    if let Some(midi_out_handle) = &mut self.midi_out_handle {
+                        midi_out_handle.set_latency_offset(device, ms);
+                    }
+                }
+            }
+            UiEvent::EditMidiInput { channel, edit } => {
+                self.state.edit_midi_input(*channel, edit);
+            }
+            UiEvent::EditAutomation { channel, edit } => {
+                self.state.edit_automation(*channel, edit);
+            }
+            UiEvent::AddMacroKnob(channel) => {
+                let knobs = self.state.channels.get(*channel).map_or(0, |c| c.macro_knobs.len());
+                let name = self
+                    .localization
+                    .tr_args("macro-knob-default-name", &[("number", &(knobs + 1))]);
+                self.state.edit_macro_knobs(*channel, &MacroKnobEdit::AddKnob(name));
+            }
+            UiEvent::EditMacroKnobs { channel, edit } => {
+                self.state.edit_macro_knobs(*channel, edit);
+            }
+            UiEvent::AddRackChain(channel) => {
+                let chains =
+                    self.state.channels.get(*channel).map_or(0, |c| c.instrument_rack.chains.len());
+                let name =
+                    self.localization.tr_args("chain-default-name", &[("number", &(chains + 1))]);
+                self.state.edit_instrument_rack(*channel, 0, &RackEdit::AddChain(name));
+            }
+            UiEvent::EditRackChain { channel, chain, edit } => {
+                self.state.edit_instrument_rack(*channel, *chain, edit);
+            }
+            UiEvent::AddDrumPads(channel) => {
+                let localization = &self.localization;
+                self.state.add_drum_pads(*channel, |number| {
+                    localization.tr_args("drum-pad-default-name", &[("number", &number)])
+                });
+            }
+            UiEvent::EditDrumPad { channel, pad, edit } => {
+                self.state.edit_drum_rack(*channel, *pad, edit);
+            }
+            UiEvent::AddMidiReturnChannel(index) => {
+                if let Err(e) = self.state.add_midi_return_channel(*index) {
+                    log::error!("Failed to add return channel: {}", e);
+                }
+            }
+            UiEvent::AddSend { channel, to } => {
+                if let Err(e) = self.state.add_send(*channel, *to) {
+                    log::error!("Failed to add send: {}", e);
+                }
+            }
+            UiEvent::EditChannelMix { channel, edit } => {
+                self.state.edit_channel_mix(*channel, *edit);
+            }
+            UiEvent::EditSend { channel, send, edit } => {
+                self.state.edit_send(*channel, *send, edit);
+            }
+            UiEvent::AddReturnBus => {
+                if let Err(e) = self.state.add_return_bus() {
+                    log::error!("Failed to add return bus: {}", e);
+                }
+            }
+            UiEvent::RunScript(script) => {
+                self.state.console.set_input(String::new());
+                self.run_script(cx, script);
+            }
+            UiEvent::SetConsoleInput(input) => {
+                self.state.console.set_input(input.clone());
+            }
+            UiEvent::ClearConsole => {
+                self.state.console.lines.clear();
+            }
+            UiEvent::StartMacroRecording => {
+                self.state.macros.recorded.clear();
+                self.state.macros.recording = true;
+            }
+            UiEvent::StopMacroRecording => {
+                self.stop_macro_recording();
+            }
+            UiEvent::RunMacro { index, args } => {
+                if let Some(m) = self.state.macros.macros.get(*index) {
+                    let line = std::iter::once(&m.name)
+                        .chain(args.iter())
+                        .map(|arg| script::quote(arg))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    self.run_script(cx, &format!("macro {}", line));
+                }
+            }
+            UiEvent::RunMacroShortcut(shortcut) => {
+                if let Some(index) = self.state.macros.with_shortcut(*shortcut) {
+                    cx.emit(UiEvent::RunMacro { index, args: Vec::new() });
+                }
+            }
+            UiEvent::EditMacro(index) => {
+                self.state.macros.editing = index.filter(|i| *i < self.state.macros.macros.len());
+            }
+            UiEvent::RenameMacro { index, name } => {
+                let name = name.trim();
+                let macros = &mut self.state.macros;
+                // The name is used as the name of the file.
+                if name.is_empty()
+                    || name.contains(|c| c == '/' || c == '\\')
+                    || macros.macros.iter().any(|m| m.name == name)
+                {
+                    return;
+                }
+                if let Some(m) = macros.macros.get_mut(*index) {
+                    if let Err(e) = Macro::delete_global(&m.name) {
+                        log::error!("Failed to delete macro {}: {}", &m.name, e);
+                    }
+                    m.name = name.to_string();
+                    macros.macros.sort_by(|a, b| a.name.cmp(&b.name));
+                    macros.editing = macros.macros.iter().position(|m| m.name == name);
+                    if let Some(index) = macros.editing {
+                        self.save_macro(index);
+                    }
+                }
+            }
+            UiEvent::SetMacroLine { line, text } => {
+                let index = match self.state.macros.editing {
+                    Some(index) => index,
+                    None => return,
+                };
+                if let Some(m) = self.state.macros.macros.get_mut(index) {
+                    match m.lines.get_mut(*line) {
+                        Some(_) if text.trim().is_empty() => {
+                            m.lines.remove(*line);
+                        }
+                        Some(old) => *old = text.clone(),
+                        None if !text.trim().is_empty() => m.lines.push(text.clone()),
+                        None => return,
+                    }
+                    self.save_macro(index);
+                }
+            }
+            UiEvent::BindMacro { index, shortcut } => {
+                if *index >= self.state.macros.macros.len() {
+                    return;
+                }
+                for i in 0..self.state.macros.macros.len() {
+                    let m = &mut self.state.macros.macros[i];
+                    if i == *index {
+                        m.shortcut = *shortcut;
+                    } else if shortcut.is_some() && m.shortcut == *shortcut {
+                        m.shortcut = None;
+                    } else {
+                        continue;
+                    }
+                    self.save_macro(i);
+                }
+            }
+            UiEvent::DeleteMacro(index) => {
+                let macros = &mut self.state.macros;
+                if *index >= macros.macros.len() {
+                    return;
+                }
+                let m = macros.macros.remove(*index);
+                macros.editing = match macros.editing {
+                    Some(editing) if editing == *index => None,
+                    Some(editing) if editing > *index => Some(editing - 1),
+                    editing => editing,
+                };
+                if let Err(e) = Macro::delete_global(&m.name) {
+                    self.notification_log.push(NotificationLogType::Error(
+                        self.localization
+                            .tr_args("error-delete-macro", &[("name", &m.name), ("error", &e)]),
+                    ));
+                }
+            }
+            UiEvent::SetActiveTake { clip, take } => {
+                if let Some(ClipType::Audio(audio)) =
+                    self.state.clips.get_mut(*clip).map(|c| &mut c.type_)
+                {
+                    audio.active_take = take.filter(|i| *i < audio.alternate_takes.len());
+                }
+            }
+            UiEvent::OpenSampleEditor(path) => {
+                let key = PcmKey {
+                    path: path.clone(),
+                    resample_to_project_sr: false,
+                    resample_quality: ResampleQuality::Linear,
+                };
+                let (pcm, res) = self.resource_loader.load_pcm(&key);
+
+                match res {
+                    Ok(()) => {
+                        let peaks = self.resource_loader.waveform_peaks(&key).map(|p| (*p).clone());
+                        self.state.sample_editor.open(path.clone(), SampleEditor::new(&pcm), peaks);
+                        cx.emit(PanelEvent::ShowSampleEditor);
+                        cx.needs_redraw();
+                    }
+                    Err(e) => {
+                        self.notification_log.push(NotificationLogType::Error(
+                            self.localization.tr_args(
+                                "error-open-sample-editor",
+                                &[("path", &path.display()), ("error", &e)],
+                            ),
+                        ));
+                    }
+                }
+            }
+            UiEvent::SaveSampleEditor => {
+                self.save_sample_editor();
+            }
+            UiEvent::SetEqBand(index, band) => {
+                if let Some((engine_handles, _)) = &mut self.engine_handles {
+                    if let Some(eq_plug_handle) = &mut engine_handles.eq_plug_handle {
+                        let eq_plug_handle = eq_plug_handle
+                            .internal
+                            .as_mut()
+                            .unwrap()
+                            .downcast_mut::<EqPlugHandle>()
+                            .unwrap();
+
+                        eq_plug_handle.set_band(*index, *band);
+                        self.state.eq.bands = *eq_plug_handle.bands();
+
+                        if let Some(band) = self.state.eq.bands.get(*index) {
+                            let key = if band.enabled {
+                                "announce-eq-band"
+                            } else {
+                                "announce-eq-band-off"
+                            };
+                            self.announcement = self.localization.tr_args(
+                                key,
+                                &[
+                                    ("band", &(index + 1)),
+                                    ("freq", &Unit::Hertz.format(band.freq.into())),
+                                    ("gain", &Unit::Decibels.format(band.gain_db.into())),
+                                    ("q", &Unit::Plain { decimals: 2 }.format(band.q.into())),
+                                ],
+                            );
+                        }
+                        cx.needs_redraw();
+                    }
+                }
+            }
+            UiEvent::SelectEqBand(band) => {
+                self.state.eq.selected_band = (*band).min(self.state.eq.bands.len() - 1);
+            }
+            UiEvent::ToggleEqBypass => {
+                if let Some((engine_handles, _)) = &mut self.engine_handles {
+                    if let Some(eq_plug_handle) = &mut engine_handles.eq_plug_handle {
+                        let eq_plug_handle = eq_plug_handle
+                            .internal
+                            .as_mut()
+                            .unwrap()
+                            .downcast_mut::<EqPlugHandle>()
+                            .unwrap();
+
+                        eq_plug_handle.set_bypassed(!eq_plug_handle.bypassed());
+                        self.state.eq.bypassed = eq_plug_handle.bypassed();
+                        self.announcement = self.localization.tr(if self.state.eq.bypassed {
+                            "announce-eq-bypassed"
+                        } else {
+                            "announce-eq-active"
+                        });
+                    }
+                }
+            }
+            UiEvent::ToggleEqAutoGain => {
+                if let Some((engine_handles, _)) = &mut self.engine_handles {
+                    if let Some(eq_plug_handle) = &mut engine_handles.eq_plug_handle {
+                        let eq_plug_handle = eq_plug_handle
+                            .internal
+                            .as_mut()
+                            .unwrap()
+                            .downcast_mut::<EqPlugHandle>()
+                            .unwrap();
+
+                        eq_plug_handle.set_auto_gain(!eq_plug_handle.auto_gain());
+                        self.state.eq.auto_gain = eq_plug_handle.auto_gain();
+                        self.announcement = self.localization.tr(if self.state.eq.auto_gain {
+                            "announce-eq-auto-gain-on"
+                        } else {
+                            "announce-eq-auto-gain-off"
+                        });
+                    }
+                }
+            }
+            UiEvent::NudgeTunerReferencePitch(amount) => {
+                let mut reference_pitch = self.state.tuner.reference_pitch + *amount;
+
+                if let Some((engine_handles, _)) = &mut self.engine_handles {
+                    if let Some(tuner_plug_handle) = &mut engine_handles.tuner_plug_handle {
+                        let tuner_plug_handle = tuner_plug_handle
+                            .internal
+                            .as_mut()
+                            .unwrap()
+                            .downcast_mut::<TunerPlugHandle>()
+                            .unwrap();
+
+                        tuner_plug_handle.set_reference_pitch(reference_pitch);
+                        reference_pitch = tuner_plug_handle.reference_pitch();
+                    }
+                }
+
+                self.state.tuner.reference_pitch = reference_pitch;
+                self.announcement = self.localization.tr_args(
+                    "announce-reference-pitch",
+                    &[("pitch", &Unit::Hertz.format(reference_pitch.into()))],
+                );
+            }
+            UiEvent::ClearLoudnessHistory => {
+                self.state.loudness_history.clear();
+                cx.needs_redraw();
+            }
+            UiEvent::BrowserFileStop() => {
+                if let Some((engine_handles, _)) = &mut self.engine_handles {
+                    if let Some(browser_plug_handle) =
+                        &mut engine_handles.sample_browser_plug_handle
+                    {
+                        let browser_plug_handle = browser_plug_handle
+                            .internal
+                            .as_mut()
+                            .unwrap()
+                            .downcast_mut::<SampleBrowserPlugHandle>()
+                            .unwrap();
+
+                        self.last_clicked_browser_file = None;
+                        browser_plug_handle.stop();
+                    }
+                }
+            }
+            _ => {}
+        });
+
+        event.map(|window_event, _| {
+            if let WindowEvent::WindowClose = window_event {
+                self.shutdown();
+            }
+        });
+
+        // Musical typing takes the letter keys that are pressed without a
+        // modifier, so shortcuts still work.
+        event.map(|window_event, meta| match window_event {
+            WindowEvent::KeyDown(code, _)
+                if !cx.modifiers.intersects(Modifiers::CTRL | Modifiers::ALT | Modifiers::LOGO) =>
+            {
+                if self.musical_typing_key(cx, *code, true) {
+                    meta.consume();
+                }
+            }
+            WindowEvent::KeyUp(code, _) => {
+                if self.musical_typing_key(cx, *code, false) {
+                    meta.consume();
+                }
+            }
+            _ => {}
+        });
+
+        self.state.event(cx, event);
+
+        // Bring the audio graph in line with the edit, i.e. add the fader of
+        // a new channel or connect a channel that was routed elsewhere.
+        if history_label.is_some() {
+            if self.hosting_lazily {
+                self.host_next_plugins();
+            } else {
+                self.host_plugins();
+            }
+        }
+
+        self.history.end_group();
+    }
+}
+
+impl Drop for UiData {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[derive(Debug, Lens, Clone)]
+pub struct UiState {
+    /// A "channel" refers to a mixer channel.
+    ///
+    /// This also contains the state of all clips.
+    pub channels: Vec<ChannelState>,
+
+    // Index of channel being dragged
+    pub dragging_channel: Option<usize>,
+
+    pub clips: Vec<ClipState>,
+
+    /// What is shown in the inspector panel.
+    pub inspected: Option<Inspected>,
+
+    /// The state of the timeline grid.
+    ///
+    /// (This does not contain the state of the clips.)
+    pub timeline_grid: TimelineGridState,
+
+    /// The sections of the song on the arranger track.
+    pub arranger: ArrangerState,
+
+    pub browser: BrowserState,
+
+    /// State of the UI panels.
+    ///
+    /// This is visual state that is used by the UI and must be serialized.
+    pub panels: PanelState,
+
+    pub tuner: TunerState,
+
+    pub metronome: MetronomeState,
+
+    /// The loudness of the master bus over the course of the timeline.
+    pub loudness_history: LoudnessHistoryState,
+
+    pub sample_editor: SampleEditorState,
+
+    pub eq: EqState,
+
+    pub collision: CollisionState,
+
+    pub console: ConsoleState,
+
+    /// The levels of the audio device's inputs.
+    pub input_meter: InputMeterState,
+
+    /// How recorded notes are snapped to the grid.
+    pub input_quantize: InputQuantize,
+
+    /// Playing notes with the computer keyboard.
+    pub musical_typing: MusicalTypingState,
+
+    /// The on-screen keyboard and pad grid.
+    pub onscreen_instruments: OnScreenInstrumentsState,
+
+    /// The CLAP plugins that can be added to a channel.
+    pub available_plugins: Vec<AvailablePluginState>,
+
+    /// How much of the export that is running is done, from 0.0 to 1.0.
+    pub export_progress: Option<f32>,
+
+    /// Whether the audio input is being recorded onto the armed channels, up
+    /// until the last recorded file is closed.
+    pub recording_audio: bool,
+
+    /// The macros, which are available in every project.
+    pub macros: MacroState,
+
+    /// The rack presets, which are available in every project.
+    pub fx_rack_presets: Vec<FxRackState>,
+
+    /// The MIDI output devices that were found, with their settings.
+    pub midi_out_devices: Vec<MidiOutDeviceState>,
+
+    /// The number of outputs of the audio device, which channels can play on
+    /// in pairs.
+    pub audio_device_outputs: usize,
+
+    /// The workspaces saved with this project.
+    pub workspaces: Vec<Workspace>,
+}
+
+impl UiState {
+    /// Sent whenever the engine is deactivated.
+    ///
+    /// The DSEngineAudioThread sent in a previous EngineActivated event is now
+    /// invalidated. Please drop it and wait for a new EngineActivated event to
+    /// replace it.
+    ///
+    /// To keep using the audio graph, you must reactivate the engine with
+    /// `DSEngineRequest::ActivateEngine`, and then restore the audio graph
+    /// from an existing save state if you wish using
+    /// `DSEngineRequest::RestoreFromSaveState`.
+    fn on_engine_deactivated(
+        &mut self,
+        event: EngineDeactivatedInfo,
+        engine_handles: &mut EngineHandles,
+        system_io_stream_handle: &mut Option<SystemIOStreamHandle>,
+    ) {
+        engine_handles.activated_info = None;
+        engine_handles.sample_browser_plug_handle = None;
+        engine_handles.tuner_plug_handle = None;
+        engine_handles.loudness_plug_handle = None;
+        engine_handles.eq_plug_handle = None;
+        engine_handles.midi_capture_plug_handle = None;
+        engine_handles.record_plug_handle = None;
+        engine_handles.metronome_plug_handle = None;
+        engine_handles.metronome_meter = None;
+        engine_handles.loop_synced = false;
+        engine_handles.plugin_host.forget_hosted();
+        engine_handles.eq_plug_id = None;
+        engine_handles.record_plug_id = None;
+        engine_handles.channel_edges.clear();
+        engine_handles.graph_swap.clear();
+
+        if let Some(system_io_stream_handle) = system_io_stream_handle.as_mut() {
+            system_io_stream_handle.engine_deactivated();
+        }
+
+        // TODO
+    }
+
+    /// This message is sent whenever the engine successfully activates.
+    fn on_engine_activated(
+        &mut self,
+        event: EngineActivatedInfo,
+        engine_handles: &mut EngineHandles,
+        system_io_stream_handle: &mut Option<SystemIOStreamHandle>,
+        export: &mut Option<ProjectExport>,
+        notification_log: &mut Vec<NotificationLogType>,
+        localization: &Localization,
+    ) {
+        engine_handles.activated_info = Some(ActivatedEngineInfo {
+            graph_in_node_id: event.graph_in_node_id.clone(),
+            graph_out_node_id: event.graph_out_node_id.clone(),
+            transport_handle: event.transport_handle,
+            sample_rate: event.sample_rate,
+            min_frames: event.min_frames,
+            max_frames: event.max_frames,
+            num_audio_in_channels: event.num_audio_in_channels,
+            num_audio_out_channels: event.num_audio_out_channels,
+        });
+
+        // Collect the keys for the internal plugins.
+        let mut sample_browser_plug_key = None;
+        let mut tuner_plug_key = None;
+        let mut loudness_plug_key = None;
+        let mut eq_plug_key = None;
+        let mut midi_capture_plug_key = None;
+        let mut record_plug_key = None;
+        let mut metronome_plug_key = None;
+        for p in engine_handles.ds_handle.internal_plugins_res.iter() {
+            match p {
+                Ok(key) => {
+                    if &key.rdn == SAMPLE_BROWSER_PLUG_RDN {
+                        sample_browser_plug_key = Some(key.clone());
+                    } else if &key.rdn == TUNER_PLUG_RDN {
+                        tuner_plug_key = Some(key.clone());
+                    } else if &key.rdn == LOUDNESS_PLUG_RDN {
+                        loudness_plug_key = Some(key.clone());
+                    } else if &key.rdn == EQ_PLUG_RDN {
+                        eq_plug_key = Some(key.clone());
+                    } else if &key.rdn == MIDI_CAPTURE_PLUG_RDN {
+                        midi_capture_plug_key = Some(key.clone());
+                    } else if &key.rdn == RECORD_PLUG_RDN {
+                        record_plug_key = Some(key.clone());
+                    } else if &key.rdn == METRONOME_PLUG_RDN {
+                        metronome_plug_key = Some(key.clone());
+                    } else if &key.rdn == FADER_PLUG_RDN {
+                        engine_handles.fader_plug_key = Some(key.clone());
+                    } else if &key.rdn == MIDI_TRACK_PLUG_RDN {
+                        engine_handles.midi_track_plug_key = Some(key.clone());
+                    } else if &key.rdn == TIMELINE_TRACK_PLUG_RDN {
+                        engine_handles.timeline_track_plug_key = Some(key.clone());
+                    } else if &key.rdn == DRUM_RACK_PLUG_RDN {
+                        engine_handles.drum_rack_plug_key = Some(key.clone());
+                    } else if &key.rdn == AUTOMATION_PLUG_RDN {
+                        engine_handles.automation_plug_key = Some(key.clone());
+                    }
+                }
+                Err(e) => {
+                    log::error!("Failed to load internal plugin: {}", e);
+                    notification_log.push(NotificationLogType::Error(
+                        localization.tr_args("error-load-internal-plugin", &[("error", &e)]),
+                    ));
+                }
+            }
+        }
+
+        // While exporting, the engine is driven by the render instead of the
+        // audio device.
+        let exporting = export.is_some();
+        match export {
+            Some(export) => export.engine_activated(event.audio_thread),
+            None => system_io_stream_handle.as_mut().unwrap().engine_activated(event.audio_thread),
+        }
+
+        // Leave the graph empty rather than half built if any of the plugins
+        // that make up the default graph are missing.
+        let (
+            sample_browser_plug_key,
+            tuner_plug_key,
+            loudness_plug_key,
+            eq_plug_key,
+            midi_capture_plug_key,
+            record_plug_key,
+        ) = match (
+            sample_browser_plug_key,
+            tuner_plug_key,
+            loudness_plug_key,
+            eq_plug_key,
+            midi_capture_plug_key,
+            record_plug_key,
+        ) {
+            (Some(a), Some(b), Some(c), Some(d), Some(e), Some(f)) => (a, b, c, d, e, f),
+            _ => {
+                notification_log.push(NotificationLogType::Error(
+                    localization.tr("error-internal-plugin-missing"),
+                ));
+                return;
+            }
+        };
+
+        // Add the sample-browser plugin and connect it to the output through the
+        // EQ plugin and then the loudness meter plugin, which acts as the meter
+        // on the master bus.
+        //
+        // Also add the tuner plugin and connect it to the system input. Its output
+        // is left unconnected since it only needs to analyze the input. The MIDI
+        // capture plugin listens to the note input of the graph in the same way.
+        // The record plugin is connected to the inputs of the armed channels
+        // along with the channels.
+        let mut request = ModifyGraphRequest {
+            add_plugin_instances: vec![
+                PluginSaveState::new_with_default_preset(sample_browser_plug_key),
+                PluginSaveState::new_with_default_preset(tuner_plug_key),
+                PluginSaveState::new_with_default_preset(loudness_plug_key),
+                PluginSaveState::new_with_default_preset(eq_plug_key),
+                PluginSaveState::new_with_default_preset(midi_capture_plug_key),
+                PluginSaveState::new_with_default_preset(record_plug_key),
+            ],
+            remove_plugin_instances: vec![],
+            connect_new_edges: vec![
+                EdgeReq {
+                    edge_type: PortType::Audio,
+                    src_plugin_id: PluginIDReq::Added(0),
+                    dst_plugin_id: PluginIDReq::Added(3),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: 0,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: 0,
+                    log_error_on_fail: true,
+                },
+                EdgeReq {
+                    edge_type: PortType::Audio,
+                    src_plugin_id: PluginIDReq::Added(0),
+                    dst_plugin_id: PluginIDReq::Added(3),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: 1,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: 1,
+                    log_error_on_fail: true,
+                },
+                EdgeReq {
+                    edge_type: PortType::Audio,
+                    src_plugin_id: PluginIDReq::Added(3),
+                    dst_plugin_id: PluginIDReq::Added(2),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: 0,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: 0,
+                    log_error_on_fail: true,
+                },
+                EdgeReq {
+                    edge_type: PortType::Audio,
+                    src_plugin_id: PluginIDReq::Added(3),
+                    dst_plugin_id: PluginIDReq::Added(2),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: 1,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: 1,
+                    log_error_on_fail: true,
+                },
+                EdgeReq {
+                    edge_type: PortType::Audio,
+                    src_plugin_id: PluginIDReq::Added(2),
+                    dst_plugin_id: PluginIDReq::Existing(event.graph_out_node_id.clone()),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: 0,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: 0,
+                    log_error_on_fail: true,
+                },
                 EdgeReq {
                     edge_type: PortType::Audio,
-                    src_plugin_id: PluginIDReq::Added(0),
+                    src_plugin_id: PluginIDReq::Added(2),
                     dst_plugin_id: PluginIDReq::Existing(event.graph_out_node_id.clone()),
                     src_port_id: EdgeReqPortID::Main,
                     src_port_channel: 1,
@@ -547,9 +3912,60 @@ impl UiState {
                     dst_port_channel: 1,
                     log_error_on_fail: true,
                 },
+                EdgeReq {
+                    edge_type: PortType::Audio,
+                    src_plugin_id: PluginIDReq::Existing(event.graph_in_node_id.clone()),
+                    dst_plugin_id: PluginIDReq::Added(1),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: 0,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: 0,
+                    log_error_on_fail: true,
+                },
+                EdgeReq {
+                    edge_type: PortType::Audio,
+                    src_plugin_id: PluginIDReq::Existing(event.graph_in_node_id.clone()),
+                    dst_plugin_id: PluginIDReq::Added(1),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: 1,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: 1,
+                    log_error_on_fail: true,
+                },
+                EdgeReq {
+                    edge_type: PortType::Note,
+                    src_plugin_id: PluginIDReq::Existing(event.graph_in_node_id.clone()),
+                    dst_plugin_id: PluginIDReq::Added(4),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: 0,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: 0,
+                    log_error_on_fail: true,
+                },
             ],
             disconnect_edges: vec![],
-        }));
+        };
+
+        // The metronome goes straight to the output, past the master bus, so
+        // it isn't metered. It is left out of exports.
+        if let (Some(key), false) = (metronome_plug_key, exporting) {
+            request.add_plugin_instances.push(PluginSaveState::new_with_default_preset(key));
+            for channel in 0..2 {
+                request.connect_new_edges.push(EdgeReq {
+                    edge_type: PortType::Audio,
+                    src_plugin_id: PluginIDReq::Added(6),
+                    dst_plugin_id: PluginIDReq::Existing(event.graph_out_node_id.clone()),
+                    src_port_id: EdgeReqPortID::Main,
+                    src_port_channel: channel,
+                    dst_port_id: EdgeReqPortID::Main,
+                    dst_port_channel: channel,
+                    log_error_on_fail: true,
+                });
+            }
+        }
+        engine_handles.graph_requests.push(request);
+
+        self.host_plugins(engine_handles, usize::MAX);
     }
 
     /// When this message is received, it means that the audio graph is starting
@@ -571,8 +3987,20 @@ impl UiState {
         &mut self,
         mut event: ModifyGraphRes,
         engine_handles: &mut EngineHandles,
+        notification_log: &mut Vec<NotificationLogType>,
+        localization: &Localization,
     ) {
         for new_plugin in event.new_plugins.drain(..) {
+            // Plugins added through the plugin host play an effect of a channel.
+            let host_id = match engine_handles.plugin_host.on_plugin_added(&new_plugin.plugin_id) {
+                Some(PluginAdded::Hosted(host_id)) => Some(host_id),
+                Some(PluginAdded::Removed(request)) => {
+                    engine_handles.graph_requests.push(request);
+                    continue;
+                }
+                None => None,
+            };
+
             match new_plugin.status {
                 // This means the plugin successfully activated and returned
                 // its new audio/event port configuration and its new
@@ -583,28 +4011,119 @@ impl UiState {
                         if new_plugin.plugin_id.rdn().as_str() == SAMPLE_BROWSER_PLUG_RDN {
                             engine_handles.sample_browser_plug_handle = Some(new_handle);
                             // TODO: Update state of the gain parameter for this plugin.
+                            continue;
+                        }
+                    }
+
+                    // There is only ever one tuner plugin.
+                    if engine_handles.tuner_plug_handle.is_none() {
+                        if new_plugin.plugin_id.rdn().as_str() == TUNER_PLUG_RDN {
+                            let mut new_handle = new_handle;
+                            new_handle
+                                .internal
+                                .as_mut()
+                                .unwrap()
+                                .downcast_mut::<TunerPlugHandle>()
+                                .unwrap()
+                                .set_reference_pitch(self.tuner.reference_pitch);
+
+                            engine_handles.tuner_plug_handle = Some(new_handle);
+                            continue;
                         }
                     }
 
-                    // TODO: Handle other plugins.
+                    // There is only ever one EQ plugin on the master bus.
+                    if engine_handles.eq_plug_handle.is_none() {
+                        if new_plugin.plugin_id.rdn().as_str() == EQ_PLUG_RDN {
+                            engine_handles.eq_plug_handle = Some(new_handle);
+                            engine_handles.eq_plug_id = Some(new_plugin.plugin_id.clone());
+                            continue;
+                        }
+                    }
+
+                    // There is only ever one MIDI capture plugin.
+                    if engine_handles.midi_capture_plug_handle.is_none() {
+                        if new_plugin.plugin_id.rdn().as_str() == MIDI_CAPTURE_PLUG_RDN {
+                            engine_handles.midi_capture_plug_handle = Some(new_handle);
+                            continue;
+                        }
+                    }
+
+                    // There is only ever one record plugin.
+                    if engine_handles.record_plug_handle.is_none() {
+                        if new_plugin.plugin_id.rdn().as_str() == RECORD_PLUG_RDN {
+                            engine_handles.record_plug_handle = Some(new_handle);
+                            engine_handles.record_plug_id = Some(new_plugin.plugin_id.clone());
+                            continue;
+                        }
+                    }
+
+                    // There is only ever one metronome plugin. Its settings
+                    // are sent on the next poll.
+                    if engine_handles.metronome_plug_handle.is_none() {
+                        if new_plugin.plugin_id.rdn().as_str() == METRONOME_PLUG_RDN {
+                            engine_handles.metronome_plug_handle = Some(new_handle);
+                            engine_handles.metronome_synced = false;
+                            continue;
+                        }
+                    }
+
+                    // There is only ever one loudness meter plugin on the master bus.
+                    if engine_handles.loudness_plug_handle.is_none() {
+                        if new_plugin.plugin_id.rdn().as_str() == LOUDNESS_PLUG_RDN {
+                            engine_handles.loudness_plug_handle = Some(new_handle);
+                            continue;
+                        }
+                    }
+
+                    if host_id.is_some() {
+                        self.on_plugin_activated(
+                            new_plugin.plugin_id,
+                            new_handle,
+                            new_param_values,
+                            engine_handles,
+                        );
+                    }
                 }
                 // This means that the plugin loaded but did not activate yet. This
                 // can happen when the user loads a project with a deactivated
                 // plugin.
                 PluginActivationStatus::Inactive => {
-                    // TODO: Deactivate plugin in UI.
+                    if let Some(effect) = host_id.and_then(|id| self.hosted_effect_mut(id)) {
+                        effect.status = ActivatedStatus::Deactivated;
+                    }
                 }
                 // There was an error loading the plugin.
                 PluginActivationStatus::LoadError(e) => {
-                    // TODO: Display error to user in UI.
+                    if let Some(effect) = host_id.and_then(|id| self.hosted_effect_mut(id)) {
+                        effect.set_activated(Err(e.to_string()));
+                    }
+                    log::error!("Failed to load plugin {:?}: {}", &new_plugin.plugin_id, e);
+                    notification_log.push(NotificationLogType::Error(localization.tr_args(
+                        "error-load-plugin",
+                        &[("plugin", &new_plugin.plugin_id.rdn()), ("error", &e)],
+                    )));
                 }
                 // There was an error activating the plugin.
                 PluginActivationStatus::ActivationError(e) => {
-                    // TODO: Display error to user in UI.
+                    if let Some(effect) = host_id.and_then(|id| self.hosted_effect_mut(id)) {
+                        effect.set_activated(Err(e.to_string()));
+                    }
+                    log::error!("Failed to activate plugin {:?}: {}", &new_plugin.plugin_id, e);
+                    notification_log.push(NotificationLogType::Error(localization.tr_args(
+                        "error-activate-plugin",
+                        &[("plugin", &new_plugin.plugin_id.rdn()), ("error", &e)],
+                    )));
                 }
             }
         }
 
+        // Connect the plugins that were just added.
+        self.connect_channels(engine_handles);
+        self.sync_faders(&mut engine_handles.plugin_host);
+        self.sync_midi_tracks(&mut engine_handles.plugin_host);
+        self.sync_automation(&mut engine_handles.plugin_host);
+
         // TODO
     }
 
@@ -617,8 +4136,32 @@ impl UiState {
         plugin_id: PluginInstanceID,
         new_handle: PluginHandle,
         new_param_values: FnvHashMap<ParamID, f64>,
+        engine_handles: &mut EngineHandles,
     ) {
-        // TODO
+        let values: Vec<ParameterValue> = new_param_values
+            .iter()
+            .map(|(param_id, value)| ParameterValue { id: param_id.0, value: *value })
+            .collect();
+        if let Some(host_id) =
+            engine_handles.plugin_host.on_plugin_activated(&plugin_id, new_handle, new_param_values)
+        {
+            if let Some(effect) = self.hosted_effect_mut(host_id) {
+                effect.set_activated(Ok(()));
+                effect.set_reported_values(&values);
+
+                // The internal effects are given their state through their
+                // handle, every time they are activated.
+                let internal = engine_handles
+                    .plugin_host
+                    .handle_mut(host_id)
+                    .and_then(|handle| handle.internal.as_mut());
+                if let (Some(state), Some(internal)) = (&effect.saved_state, internal) {
+                    param_state::load_state(internal.as_mut(), state.version, &state.bytes);
+                }
+            }
+        }
+
+        // TODO: Handle the internal plugins restarting.
     }
 
     /// Sent whenever a plugin becomes deactivated. When a plugin is deactivated
@@ -632,32 +4175,253 @@ impl UiState {
         // If this is `Err(e)`, then it means the plugin became deactivated
         // because it failed to restart.
         status: Result<(), ActivatePluginError>,
+        engine_handles: &mut EngineHandles,
     ) {
-        // TODO
+        if let Some(host_id) = engine_handles.plugin_host.on_plugin_deactivated(&plugin_id) {
+            if let Some(effect) = self.hosted_effect_mut(host_id) {
+                match status {
+                    Ok(()) => effect.status = ActivatedStatus::Deactivated,
+                    Err(e) => effect.set_activated(Err(e.to_string())),
+                }
+            }
+        }
     }
 
+    /// The plugin changed the values of some of its parameters, e.g. because
+    /// it is being automated or its GUI was used.
+    ///
+    /// TODO: The values are in the units of the plugin. Normalize them once
+    /// the parameter info of plugins is shown.
     fn on_plugin_params_modified(
         &mut self,
         plugin_id: PluginInstanceID,
         modified_params: SmallVec<[ParamModifiedInfo; 4]>,
+        engine_handles: &mut EngineHandles,
     ) {
-        // TODO
+        if let Some((host_id, values)) =
+            engine_handles.plugin_host.on_params_modified(&plugin_id, &modified_params)
+        {
+            let values: Vec<ParameterValue> = values
+                .into_iter()
+                .map(|(param_id, value)| ParameterValue { id: param_id.0, value })
+                .collect();
+            if let Some(effect) = self.hosted_effect_mut(host_id) {
+                effect.set_reported_values(&values);
+            }
+        }
     }
 
     /// A new CLAP plugin scan path was added.
-    fn on_clap_scan_path_added(&mut self, path: PathBuf) {
-        // TODO
+    fn on_clap_scan_path_added(&mut self, path: PathBuf, engine_handles: &mut EngineHandles) {
+        engine_handles.plugin_host.add_clap_scan_path(path);
     }
 
     /// A CLAP plugin scan path was removed.
-    fn on_clap_scan_path_removed(&mut self, path: PathBuf) {
-        // TODO
+    fn on_clap_scan_path_removed(&mut self, path: PathBuf, engine_handles: &mut EngineHandles) {
+        engine_handles.plugin_host.remove_clap_scan_path(&path);
     }
 
     /// A request to rescan all plugin directories has finished. Update
     /// the list of available plugins in your UI.
-    fn on_plugin_scanner_rescan_finished(&mut self, info: RescanPluginDirectoriesRes) {
-        // TODO
+    fn on_plugin_scanner_rescan_finished(
+        &mut self,
+        info: RescanPluginDirectoriesRes,
+        engine_handles: &mut EngineHandles,
+    ) {
+        let plugin_host = &mut engine_handles.plugin_host;
+        plugin_host.set_scan_result(&info);
+        self.available_plugins =
+            plugin_host.available().iter().map(AvailablePluginState::new).collect();
+
+        // Plugins of a project loaded before the scan can be found now.
+        self.host_plugins(engine_handles, usize::MAX);
+    }
+
+    /// Add the plugins of effects that aren't in the audio graph yet to it,
+    /// and remove the plugins of effects that are gone, e.g. after an undo.
+    /// Effects whose plugin wasn't found stay deactivated.
+    ///
+    /// At most `max_added` plugins are added, in the order of the channels,
+    /// so a large project can be built over several calls. Returns the number
+    /// of plugins that were added.
+    fn host_plugins(&mut self, engine_handles: &mut EngineHandles, max_added: usize) -> usize {
+        if engine_handles.activated_info.is_none() {
+            return 0;
+        }
+        let plugin_host = &mut engine_handles.plugin_host;
+
+        let mut in_project = self.effect_host_ids();
+        in_project.extend(self.fader_host_ids());
+        in_project.extend(self.send_host_ids());
+        in_project.extend(self.rack_mix_host_ids());
+        in_project.extend(self.midi_track_host_ids());
+        in_project.extend(self.timeline_track_host_ids());
+        in_project.extend(self.drum_rack_host_ids());
+        in_project.extend(self.clip_effect_host_ids());
+        in_project.extend(self.automation_host_ids());
+        let gone: Vec<u64> =
+            plugin_host.hosted_ids().filter(|id| !in_project.contains(id)).collect();
+        for id in gone {
+            if let Some(request) = plugin_host.remove_plugin(id) {
+                engine_handles.graph_requests.push(request);
+            }
+        }
+
+        // Faders, sends, the mixes of rack chains, MIDI tracks, timeline
+        // tracks, drum racks and automation don't count towards `max_added`,
+        // since they are part of the channel.
+        let hosted: Vec<u64> = plugin_host.hosted_ids().collect();
+        if let Some(key) = &engine_handles.fader_plug_key {
+            for index in self.unhosted_faders(&hosted) {
+                let (id, request) = plugin_host.add_plugin(key.clone(), None);
+                self.channels[index].fader_host_id = Some(id);
+                engine_handles.graph_requests.push(request);
+            }
+            for (index, send) in self.unhosted_sends(&hosted) {
+                let (id, request) = plugin_host.add_plugin(key.clone(), None);
+                self.channels[index].sends[send].host_id = Some(id);
+                engine_handles.graph_requests.push(request);
+            }
+            for (slot, chain) in self.unhosted_rack_mixes(&hosted) {
+                let (id, request) = plugin_host.add_plugin(key.clone(), None);
+                if let Some(rack) = self.fx_rack_mut(slot) {
+                    rack.chains[chain].mix_host_id = Some(id);
+                }
+                engine_handles.graph_requests.push(request);
+            }
+        }
+        if let Some(key) = &engine_handles.midi_track_plug_key {
+            for index in self.unhosted_midi_tracks(&hosted) {
+                let (id, request) = plugin_host.add_plugin(key.clone(), None);
+                self.channels[index].midi_track_host_id = Some(id);
+                engine_handles.graph_requests.push(request);
+            }
+        }
+        if let Some(key) = &engine_handles.automation_plug_key {
+            for (channel, plugin) in self.unhosted_automation(&hosted) {
+                let (id, request) = plugin_host.add_plugin(key.clone(), None);
+                self.set_automation_host_id(channel, plugin, id);
+                engine_handles.graph_requests.push(request);
+            }
+        }
+
+        if let Some(key) = &engine_handles.timeline_track_plug_key {
+            for index in self.unhosted_timeline_tracks(&hosted) {
+                let (id, request) = plugin_host.add_plugin(key.clone(), None);
+                self.channels[index].timeline_track_host_id = Some(id);
+                engine_handles.graph_requests.push(request);
+            }
+            for index in self.unhosted_clip_players(&hosted) {
+                let (id, request) = plugin_host.add_plugin(key.clone(), None);
+                self.clips[index].player_host_id = Some(id);
+                engine_handles.graph_requests.push(request);
+            }
+        }
+        if let Some(key) = &engine_handles.drum_rack_plug_key {
+            for index in self.unhosted_drum_racks(&hosted) {
+                let (id, request) = plugin_host.add_plugin(key.clone(), None);
+                self.channels[index].drum_rack_host_id = Some(id);
+                engine_handles.graph_requests.push(request);
+            }
+        }
+
+        let add_effect = |effect: &mut ExternalEffectState, plugin_host: &mut PluginHost| {
+            effect.host_id = None;
+            let key = plugin_host.find_available(&effect.rdn)?.key.clone();
+            let preset = effect
+                .saved_state
+                .clone()
+                .map(|state| PluginPreset { version: state.version, bytes: state.bytes });
+
+            let (id, request) = plugin_host.add_plugin(key, preset);
+            effect.host_id = Some(id);
+            Some(request)
+        };
+        let mut added = 0;
+        for slot in self.unhosted_effects(&hosted) {
+            if added == max_added {
+                break;
+            }
+            if let Some(request) =
+                self.external_effect_mut(slot).and_then(|effect| add_effect(effect, plugin_host))
+            {
+                engine_handles.graph_requests.push(request);
+                added += 1;
+            }
+        }
+        for (slot, chain, effect) in self.unhosted_rack_effects(&hosted) {
+            if added == max_added {
+                break;
+            }
+            if let Some(request) = self
+                .rack_effect_mut(slot, chain, effect)
+                .and_then(|effect| add_effect(effect, plugin_host))
+            {
+                engine_handles.graph_requests.push(request);
+                added += 1;
+            }
+        }
+        for (clip, effect) in self.unhosted_clip_effects(&hosted) {
+            if added == max_added {
+                break;
+            }
+            let effect = match self.clips[clip].effects.get_mut(effect) {
+                Some(HRackEffectState::External(effect)) => effect,
+                _ => continue,
+            };
+            if let Some(request) = add_effect(effect, plugin_host) {
+                engine_handles.graph_requests.push(request);
+                added += 1;
+            }
+        }
+
+        self.connect_channels(engine_handles);
+        self.sync_faders(&mut engine_handles.plugin_host);
+        self.sync_midi_tracks(&mut engine_handles.plugin_host);
+        self.sync_automation(&mut engine_handles.plugin_host);
+        added
+    }
+
+    /// Connect the plugins of the channels in the audio graph the way the
+    /// channels are routed. Only the edges that changed are sent.
+    fn connect_channels(&self, engine_handles: &mut EngineHandles) {
+        let nodes = match (&engine_handles.activated_info, &engine_handles.eq_plug_id) {
+            (Some(info), Some(eq_plug_id)) => GraphNodes {
+                graph_in: info.graph_in_node_id.clone(),
+                graph_out: info.graph_out_node_id.clone(),
+                master_bus: eq_plug_id.clone(),
+                graph_in_channels: info.num_audio_in_channels,
+                record: engine_handles.record_plug_id.clone(),
+            },
+            _ => return,
+        };
+        let out_channels =
+            engine_handles.activated_info.as_ref().map_or(0, |info| info.num_audio_out_channels);
+        let desired =
+            channel_edges(&self.channel_nodes(&engine_handles.plugin_host, out_channels), &nodes);
+
+        // The edges of plugins that were removed went with them.
+        let plugin_host = &engine_handles.plugin_host;
+        let in_graph = |id: &PluginInstanceID| {
+            *id == nodes.graph_in
+                || *id == nodes.graph_out
+                || *id == nodes.master_bus
+                || nodes.record.as_ref() == Some(id)
+                || plugin_host.is_hosted(id)
+        };
+        engine_handles.channel_edges.retain(|edge| in_graph(&edge.src) && in_graph(&edge.dst));
+
+        let (connect, disconnect) = edge_changes(&engine_handles.channel_edges, &desired);
+        if connect.is_empty() && disconnect.is_empty() {
+            return;
+        }
+        engine_handles.graph_requests.push(ModifyGraphRequest {
+            add_plugin_instances: vec![],
+            remove_plugin_instances: vec![],
+            connect_new_edges: connect.iter().map(edge_request).collect(),
+            disconnect_edges: disconnect.iter().map(edge_request).collect(),
+        });
+        engine_handles.channel_edges = desired;
     }
 }
 
@@ -670,6 +4434,31 @@ impl Model for UiState {
 
                 if let Some(channel_data) = self.channels.get_mut(*index) {
                     channel_data.selected = true;
+                    self.inspected = Some(Inspected::Channel(*index));
+                    cx.emit(UiEvent::Announce {
+                        key: "announce-channel-selected",
+                        args: vec![("channel", channel_data.name.clone())],
+                    });
+                }
+            }
+
+            // Select the channel before or after the selected one, in the order
+            // the channels are shown in the channels panel
+            ChannelEvent::SelectPreviousChannel | ChannelEvent::SelectNextChannel => {
+                let mut order = vec![];
+                select_channel(&self.channels, 0, &mut order);
+
+                let position = order.iter().position(|index| self.channels[*index].selected);
+                let next = match (position, channel_event) {
+                    (None, _) => Some(0),
+                    (Some(position), ChannelEvent::SelectPreviousChannel) => {
+                        position.checked_sub(1)
+                    }
+                    (Some(position), _) => Some(position + 1),
+                };
+
+                if let Some(index) = next.and_then(|position| order.get(position)) {
+                    cx.emit(ChannelEvent::SelectChannel(*index));
                 }
             }
 
@@ -691,32 +4480,421 @@ impl Model for UiState {
 
             // Add a new channel to the channels panel
             ChannelEvent::AddChannel => {
-                deselect_channels(&mut self.channels);
-
-                let channel_id = self.channels.len();
-
-                // Create a new channel
-                self.channels.push(ChannelState {
+                let channel = ChannelState {
                     name: String::from("New Channel"),
                     path: PathBuf::from("New Channel"),
                     color: ChannelBaseColor::Color(Color::rgb(200, 50, 50)),
                     selected: true,
                     ..Default::default()
-                });
+                };
 
                 // Add new channel to master group
-                if let Some(master) = self.channels.get_mut(0) {
-                    master.subchannels.push(channel_id);
+                if let Err(e) = self.add_channel(channel, 0) {
+                    log::error!("Failed to add channel: {}", e);
+                }
+            }
+
+            // Remove the selected channels from the channels panel
+            ChannelEvent::RemoveChannel => {
+                let selected: Vec<usize> = self
+                    .channels
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, channel)| channel.selected)
+                    .map(|(i, _)| i)
+                    .collect();
+
+                if let Err(e) = self.remove_channels(&selected) {
+                    log::error!("Failed to remove channels: {}", e);
+                }
+            }
+
+            ChannelEvent::RenameChannel(index, name) => {
+                if let Some(channel) = self.channels.get_mut(*index) {
+                    channel.name = name.clone();
+                }
+            }
+
+            ChannelEvent::SetChannelColor(index, color) => {
+                if let Some(channel) = self.channels.get_mut(*index) {
+                    channel.color = (*color).into();
+                }
+            }
+
+            ChannelEvent::RouteChannel(index, to) => {
+                if let Err(e) = self.route_channel(*index, *to) {
+                    log::error!("Failed to route channel: {}", e);
+                }
+            }
+
+            // Add a copy of a channel to the same group
+            ChannelEvent::DuplicateChannel(index) => {
+                let (channel, parent) = match self.channels.get(*index) {
+                    Some(ChannelState { parent_channel: Some(parent), .. }) => {
+                        (self.channels[*index].clone(), *parent)
+                    }
+                    // The master channel can't be duplicated.
+                    _ => return,
+                };
+
+                // The copy is a new source, so it isn't armed with the original.
+                let channel = ChannelState {
+                    name: format!("{} copy", channel.name),
+                    selected: true,
+                    armed: false,
+                    arm_group: None,
+                    ..channel
+                };
+                if let Err(e) = self.add_channel(channel, parent) {
+                    log::error!("Failed to duplicate channel: {}", e);
+                }
+            }
+
+            ChannelEvent::ToggleArm(index) => {
+                if let Some(channel) = self.channels.get(*index) {
+                    let armed = !channel.armed;
+                    self.set_armed(*index, armed);
                 }
             }
 
-            // Remove the specified channel from the channels panel
-            ChannelEvent::RemoveChannel => {}
+            ChannelEvent::SetExclusiveArm(index, exclusive) => {
+                self.set_exclusive_arm(*index, *exclusive);
+            }
+
+            ChannelEvent::SetArmGroup(index, group) => {
+                self.set_arm_group(*index, *group);
+            }
+
+            ChannelEvent::LinkArm(channels) => {
+                self.link_arm(channels);
+            }
+
+            ChannelEvent::SetRecordInput(index, input) => {
+                if let Err(e) = self.set_record_input(*index, *input) {
+                    log::error!("Failed to set record input: {}", e);
+                }
+            }
+            ChannelEvent::SetHardwareOutput(index, output) => {
+                if let Err(e) = self.set_hardware_output(*index, *output) {
+                    log::error!("Failed to set hardware output: {}", e);
+                }
+            }
         });
 
         self.panels.event(cx, event);
         self.timeline_grid.event(cx, event);
         self.browser.event(cx, event);
+        self.sample_editor.event(cx, event);
+    }
+}
+
+impl UiState {
+    /// Add a channel to the end of the list as a subchannel of `parent`, and
+    /// select it. Returns the index of the new channel.
+    ///
+    /// Nothing is changed if this fails.
+    fn add_channel(
+        &mut self,
+        mut channel: ChannelState,
+        parent: usize,
+    ) -> Result<usize, LocalizedMessage> {
+        // Validate everything up front so a failure leaves the state untouched.
+        if parent >= self.channels.len() {
+            return Err(LocalizedMessage::new("error-channel-missing").arg("channel", parent));
+        }
+
+        let index = self.channels.len();
+        channel.parent_channel = Some(parent);
+        channel.routed_to = parent;
+        channel.subchannels.clear();
+
+        deselect_channels(&mut self.channels);
+        self.channels.push(channel);
+        self.channels[parent].subchannels.push(index);
+
+        self.debug_check_consistency();
+        Ok(index)
+    }
+
+    /// Route the output of a channel to another channel.
+    ///
+    /// Nothing is changed if this fails, i.e. if the master channel would be
+    /// routed somewhere or the signal would feed back into the channel.
+    fn route_channel(&mut self, index: usize, to: usize) -> Result<(), LocalizedMessage> {
+        let error = |key| LocalizedMessage::new(key).arg("channel", index).arg("to", to);
+        if index == 0 {
+            return Err(LocalizedMessage::new("error-route-master"));
+        }
+        if index >= self.channels.len() || to >= self.channels.len() {
+            return Err(error("error-route-channel"));
+        }
+
+        // Sends count too, so a channel can't be routed to its own return.
+        if self.feeds(to, index) {
+            return Err(error("error-route-loop"));
+        }
+
+        self.channels[index].routed_to = to;
+
+        self.debug_check_consistency();
+        Ok(())
+    }
+
+    /// Remove the given channels along with all of their clips.
+    ///
+    /// The subchannels of a removed channel are moved into its parent, and any
+    /// channel that was routed to a removed channel is routed to the master
+    /// channel instead. Sends to a removed channel are removed. Channels that recorded a removed channel record the
+    /// device input instead.
+    ///
+    /// Nothing is changed if any of the channels can't be removed.
+    fn remove_channels(&mut self, to_remove: &[usize]) -> Result<(), LocalizedMessage> {
+        // Validate everything up front so a failure leaves the state untouched.
+        for &index in to_remove {
+            if index == 0 {
+                return Err(LocalizedMessage::new("error-remove-master"));
+            }
+            if index >= self.channels.len() {
+                return Err(LocalizedMessage::new("error-channel-missing").arg("channel", index));
+            }
+        }
+        if to_remove.is_empty() {
+            return Ok(());
+        }
+
+        let removed = |index: usize| to_remove.contains(&index);
+
+        // Maps the old index of each channel to its new index.
+        let mut new_index = Vec::with_capacity(self.channels.len());
+        let mut num_kept = 0;
+        for i in 0..self.channels.len() {
+            if removed(i) {
+                new_index.push(None);
+            } else {
+                new_index.push(Some(num_kept));
+                num_kept += 1;
+            }
+        }
+
+        // The closest ancestor of a channel that is being kept.
+        let kept_ancestor = |mut index: usize| {
+            // Bounded in case the tree somehow contains a cycle.
+            for _ in 0..self.channels.len() {
+                if !removed(index) {
+                    return index;
+                }
+                index = self.channels[index].parent_channel.unwrap_or(0);
+            }
+            0
+        };
+
+        // The subchannels of a channel, with any removed subchannels replaced by
+        // their own (kept) subchannels.
+        fn kept_subchannels(
+            channels: &[ChannelState],
+            removed: &dyn Fn(usize) -> bool,
+            index: usize,
+            out: &mut Vec<usize>,
+        ) {
+            for &sub in channels[index].subchannels.iter() {
+                if removed(sub) {
+                    kept_subchannels(channels, removed, sub, out);
+                } else {
+                    out.push(sub);
+                }
+            }
+        }
+
+        // Build the new state completely before replacing the old one.
+        let mut channels = Vec::with_capacity(num_kept);
+        for (i, channel) in self.channels.iter().enumerate() {
+            if removed(i) {
+                continue;
+            }
+
+            let mut subchannels = Vec::new();
+            kept_subchannels(&self.channels, &removed, i, &mut subchannels);
+
+            let mut channel = channel.clone();
+            channel.parent_channel =
+                channel.parent_channel.and_then(|parent| new_index[kept_ancestor(parent)]);
+            channel.routed_to = new_index.get(channel.routed_to).copied().flatten().unwrap_or(0);
+            for pad in channel.drum_rack.pads.iter_mut() {
+                pad.output_channel =
+                    new_index.get(pad.output_channel).copied().flatten().unwrap_or(0);
+            }
+            channel.sends = channel
+                .sends
+                .iter()
+                .filter_map(|send| {
+                    let to = new_index.get(send.to).copied().flatten()?;
+                    Some(ChannelSend { to, ..send.clone() })
+                })
+                .collect();
+            if let RecordInput::Bus(bus) = channel.record_input {
+                channel.record_input = match new_index.get(bus).copied().flatten() {
+                    Some(bus) => RecordInput::Bus(bus),
+                    None => RecordInput::default(),
+                };
+            }
+            channel.subchannels = subchannels.iter().filter_map(|sub| new_index[*sub]).collect();
+            channels.push(channel);
+        }
+
+        let clips =
+            self.clips.iter().filter_map(|clip| clip.with_new_channels(&new_index)).collect();
+
+        self.channels = channels;
+        self.clips = clips;
+        for lane in self.timeline_grid.lane_states.lanes.iter_mut() {
+            for playlist in lane.playlists.iter_mut() {
+                playlist.remove_channels(&new_index);
+            }
+        }
+        self.inspected = None;
+
+        self.debug_check_consistency();
+        Ok(())
+    }
+
+    /// Check that the channel tree and the clips all agree with each other.
+    /// This does nothing in release builds.
+    fn debug_check_consistency(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        debug_assert!(!self.channels.is_empty(), "There must always be a master channel");
+        debug_assert!(self.channels[0].parent_channel.is_none(), "The master has no parent");
+
+        for (i, channel) in self.channels.iter().enumerate() {
+            debug_assert!(
+                channel.routed_to < self.channels.len(),
+                "Channel {} routed to nowhere",
+                i
+            );
+            debug_assert!(
+                channel.sends.iter().all(|send| send.to < self.channels.len() && send.to != i),
+                "Channel {} sends to nowhere",
+                i
+            );
+            debug_assert!(
+                channel.drum_rack.pads.iter().all(|pad| pad.output_channel < self.channels.len()),
+                "Channel {} has a drum pad that plays into nowhere",
+                i
+            );
+
+            for &sub in channel.subchannels.iter() {
+                debug_assert!(sub < self.channels.len(), "Channel {} has a missing subchannel", i);
+                debug_assert_eq!(
+                    self.channels[sub].parent_channel,
+                    Some(i),
+                    "Subchannel {} does not point back to its parent {}",
+                    sub,
+                    i
+                );
+            }
+
+            if let Some(parent) = channel.parent_channel {
+                debug_assert!(
+                    self.channels.get(parent).map(|p| p.subchannels.contains(&i)) == Some(true),
+                    "Channel {} is missing from its parent {}",
+                    i,
+                    parent
+                );
+            } else {
+                debug_assert_eq!(i, 0, "Only the master channel may have no parent");
+            }
+        }
+
+        let playlist_clips = self
+            .timeline_grid
+            .lane_states
+            .lanes
+            .iter()
+            .flat_map(|lane| lane.playlists.iter().flat_map(|playlist| playlist.clips.iter()));
+        for clip in self.clips.iter().chain(playlist_clips) {
+            debug_assert!(
+                clip.channel < self.channels.len(),
+                "Clip \"{}\" is on a missing channel",
+                clip.name
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+impl UiState {
+    /// A project with the given number of channels and lanes and nothing
+    /// else, for testing edits without an engine.
+    pub(crate) fn for_tests(channels: usize, lanes: usize) -> Self {
+        let collector = basedrop::Collector::new();
+        UiState {
+            channels: vec![ChannelState::default(); channels],
+            dragging_channel: None,
+            clips: Vec::new(),
+            inspected: None,
+            timeline_grid: TimelineGridState {
+                horizontal_zoom_level: 1.0,
+                vertical_zoom_level: 1.0,
+                left_start: MusicalTime::from_beats(0).into(),
+                top_start: 0.0,
+                lane_height: 1.0,
+                lane_states: LaneStates::new(vec![LaneState::default(); lanes]),
+                project_length: MusicalTime::from_beats(16).into(),
+                used_lanes: 0,
+                bpm: 120.0,
+                tap_tempo: TapTempo::new(),
+                tempo_bus: TempoBus::new(&collector.handle(), 120.0),
+                tempo_map: TempoMap::default(),
+                time_signatures: TimeSignatureTrack::default(),
+                loop_region: LoopRegion::default(),
+                time_ruler_format: TimeRulerFormat::Time,
+                view_width: 0.0,
+                view_height: 0.0,
+                view_history: ViewHistory::default(),
+            },
+            arranger: ArrangerState::default(),
+            browser: BrowserState::default(),
+            panels: PanelState {
+                channel_rack_orientation: ChannelRackOrientation::Horizontal,
+                hide_clips: false,
+                hide_piano_roll: false,
+                browser_width: 200.0,
+                lane_header_width: 100.0,
+                hide_browser: false,
+                hide_tuner: true,
+                hide_inspector: true,
+                hide_loudness_history: false,
+                hide_sample_editor: true,
+                hide_eq: true,
+                hide_collisions: true,
+                hide_console: true,
+                hide_keyboard: true,
+                hide_pads: true,
+                side_panel_order: SidePanel::DEFAULT_ORDER.to_vec(),
+                main_panel_order: MainPanel::DEFAULT_ORDER.to_vec(),
+            },
+            tuner: TunerState::default(),
+            metronome: MetronomeState::default(),
+            loudness_history: LoudnessHistoryState::default(),
+            sample_editor: SampleEditorState::default(),
+            eq: EqState::default(),
+            collision: CollisionState::default(),
+            console: ConsoleState::default(),
+            input_meter: InputMeterState::default(),
+            input_quantize: InputQuantize::default(),
+            musical_typing: MusicalTypingState::default(),
+            onscreen_instruments: OnScreenInstrumentsState::default(),
+            available_plugins: Vec::new(),
+            export_progress: None,
+            recording_audio: false,
+            macros: MacroState::default(),
+            fx_rack_presets: Vec::new(),
+            midi_out_devices: Vec::new(),
+            audio_device_outputs: 2,
+            workspaces: Vec::new(),
+        }
     }
 }
 
@@ -736,3 +4914,8 @@ fn deselect_channels(channel_data: &mut Vec<ChannelState>) {
         channel.selected = false;
     }
 }
+
+// Helper function for passing on an error from the OS, which has no key
+fn system_error(e: impl ToString) -> LocalizedMessage {
+    LocalizedMessage::new("error-system").arg("error", e)
+}
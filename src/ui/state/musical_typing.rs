@@ -0,0 +1,84 @@
+use vizia::prelude::*;
+
+/// The keys that play notes, with the semitones above the C of the current
+/// octave. The middle row of letters plays the white keys and the row above
+/// it the black keys, like on a piano.
+const NOTE_KEYS: [(Code, i16); 17] = [
+    (Code::KeyA, 0),
+    (Code::KeyW, 1),
+    (Code::KeyS, 2),
+    (Code::KeyE, 3),
+    (Code::KeyD, 4),
+    (Code::KeyF, 5),
+    (Code::KeyT, 6),
+    (Code::KeyG, 7),
+    (Code::KeyY, 8),
+    (Code::KeyH, 9),
+    (Code::KeyU, 10),
+    (Code::KeyJ, 11),
+    (Code::KeyK, 12),
+    (Code::KeyO, 13),
+    (Code::KeyL, 14),
+    (Code::KeyP, 15),
+    (Code::Semicolon, 16),
+];
+
+/// The range of the octave of the A key. Octave 4 starts at middle C.
+pub const MIN_TYPING_OCTAVE: i16 = -1;
+pub const MAX_TYPING_OCTAVE: i16 = 9;
+
+/// How much the velocity keys change the velocity.
+pub const TYPING_VELOCITY_STEP: f32 = 20.0 / 127.0;
+
+/// What a key does while musical typing is on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypingKey {
+    /// Play the key with this many semitones above the C of the octave.
+    Note(i16),
+    OctaveDown,
+    OctaveUp,
+    VelocityDown,
+    VelocityUp,
+}
+
+impl TypingKey {
+    pub fn from_code(code: Code) -> Option<Self> {
+        match code {
+            Code::KeyZ => Some(TypingKey::OctaveDown),
+            Code::KeyX => Some(TypingKey::OctaveUp),
+            Code::KeyC => Some(TypingKey::VelocityDown),
+            Code::KeyV => Some(TypingKey::VelocityUp),
+            _ => NOTE_KEYS.iter().find(|(c, _)| *c == code).map(|(_, n)| TypingKey::Note(*n)),
+        }
+    }
+}
+
+/// Musical typing turns the computer keyboard into a MIDI keyboard, for
+/// playing without a controller. The notes go to the MIDI input like the
+/// notes of a device, so they can be captured and recorded.
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct MusicalTypingState {
+    pub enabled: bool,
+    pub octave: i16,
+    /// The velocity of the notes, from 0.0 to 1.0.
+    pub velocity: f32,
+}
+
+impl MusicalTypingState {
+    /// The MIDI note number played by a key, if it is in range.
+    pub fn note(&self, semitones: i16) -> Option<i16> {
+        let key = (self.octave + 1) * 12 + semitones;
+        (0..=127).contains(&key).then(|| key)
+    }
+
+    /// The name of the C played by the A key, e.g. "C4".
+    pub fn octave_name(&self) -> String {
+        format!("C{}", self.octave)
+    }
+}
+
+impl Default for MusicalTypingState {
+    fn default() -> Self {
+        Self { enabled: false, octave: 4, velocity: 100.0 / 127.0 }
+    }
+}
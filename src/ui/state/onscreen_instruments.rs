@@ -0,0 +1,90 @@
+use vizia::prelude::*;
+
+use super::UiState;
+use crate::backend::scale::NOTE_NAMES;
+
+/// The number of octaves shown on the on-screen keyboard.
+pub const KEYBOARD_OCTAVES: i16 = 3;
+/// The range of the octave of the lowest C of the on-screen keyboard, so all
+/// of its keys stay within MIDI notes 0 to 127.
+pub const MIN_KEYBOARD_OCTAVE: i16 = -1;
+pub const MAX_KEYBOARD_OCTAVE: i16 = 10 - KEYBOARD_OCTAVES;
+
+/// The pad grid is 4 by 4 pads, like on most drum machines.
+pub const PAD_ROWS: i16 = 4;
+pub const PAD_COLUMNS: i16 = 4;
+/// The kick drum of the General MIDI drum map.
+pub const DEFAULT_FIRST_PAD_KEY: i16 = 36;
+
+/// The velocity of a click at the very top of a key or pad. It goes up to
+/// 1.0 towards the bottom, like playing closer to the front of a real key.
+pub const MIN_CLICK_VELOCITY: f32 = 0.1;
+
+/// Playing notes with the mouse on the on-screen keyboard and pad grid, and
+/// the notes held on the MIDI input.
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct OnScreenInstrumentsState {
+    /// The octave of the lowest C of the keyboard. Octave 4 starts at middle
+    /// C.
+    pub keyboard_octave: i16,
+    /// The key of the pad in the bottom left corner of the pad grid. The
+    /// other pads follow it left to right, bottom to top.
+    pub first_pad_key: i16,
+    /// The keys held on the MIDI input, from devices as well as from the UI.
+    pub active_keys: Vec<i16>,
+}
+
+impl OnScreenInstrumentsState {
+    pub fn is_active(&self, key: i16) -> bool {
+        self.active_keys.contains(&key)
+    }
+}
+
+impl Default for OnScreenInstrumentsState {
+    fn default() -> Self {
+        Self { keyboard_octave: 3, first_pad_key: DEFAULT_FIRST_PAD_KEY, active_keys: Vec::new() }
+    }
+}
+
+/// The keys of the keyboard starting at the C of the given octave, lowest
+/// first.
+pub fn keyboard_keys(octave: i16) -> Vec<i16> {
+    let first = (octave + 1) * 12;
+    (first..first + KEYBOARD_OCTAVES * 12).filter(|key| (0..=127).contains(key)).collect()
+}
+
+/// The key of a pad, counting rows from the bottom, if it is in range.
+pub fn pad_key(first_pad_key: i16, row: i16, column: i16) -> Option<i16> {
+    let key = first_pad_key + row * PAD_COLUMNS + column;
+    (0..=127).contains(&key).then(|| key)
+}
+
+/// The name of a key, e.g. "C4" for middle C.
+pub fn key_name(key: i16) -> String {
+    format!("{}{}", NOTE_NAMES[key.rem_euclid(12) as usize], key.div_euclid(12) - 1)
+}
+
+/// True for the keys that are black on a piano.
+pub fn is_black_key(key: i16) -> bool {
+    matches!(key.rem_euclid(12), 1 | 3 | 6 | 8 | 10)
+}
+
+/// The velocity of a click at `y`, from 0.0 at the top of a key or pad to 1.0
+/// at the bottom.
+pub fn click_velocity(y: f32) -> f32 {
+    MIN_CLICK_VELOCITY + (1.0 - MIN_CLICK_VELOCITY) * y.clamp(0.0, 1.0)
+}
+
+impl UiState {
+    /// The MIDI channel that notes played in the UI are sent on, so they
+    /// reach the selected channel: the lowest MIDI channel its input lets
+    /// through, or channel 0 if nothing is selected.
+    pub fn focused_midi_channel(&self) -> i16 {
+        self.channels
+            .iter()
+            .find(|channel| channel.selected)
+            .map(|channel| channel.midi_input.channels)
+            .filter(|channels| *channels != 0)
+            .map_or(0, |channels| channels.trailing_zeros() as i16)
+    }
+}
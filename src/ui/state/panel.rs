@@ -9,8 +9,23 @@ pub struct PanelState {
     pub browser_width: f32,
     pub lane_header_width: f32,
     pub hide_browser: bool,
+    pub hide_tuner: bool,
+    pub hide_inspector: bool,
+    pub hide_loudness_history: bool,
+    pub hide_sample_editor: bool,
+    pub hide_eq: bool,
+    pub hide_collisions: bool,
+    pub hide_console: bool,
+    pub hide_keyboard: bool,
+    pub hide_pads: bool,
+
+    /// The order of the panels from left to right.
+    pub side_panel_order: Vec<SidePanel>,
+    /// The order of the panels in the main area from top to bottom.
+    pub main_panel_order: Vec<MainPanel>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub enum PanelEvent {
     ToggleChannelRackOrientation,
     ToggleClips,
@@ -19,6 +34,20 @@ pub enum PanelEvent {
     SetBrowserWidth(f32),
     SetLaneHeaderWidth(f32),
     ToggleBrowser,
+    ToggleTuner,
+    ToggleInspector,
+    ToggleLoudnessHistory,
+    ToggleSampleEditor,
+    ShowSampleEditor,
+    ToggleEq,
+    ToggleCollisions,
+    ToggleConsole,
+    ToggleKeyboard,
+    TogglePads,
+    /// Move a panel one place to the left (`-1`) or right (`1`).
+    MoveSidePanel(SidePanel, isize),
+    /// Move a panel in the main area one place up (`-1`) or down (`1`).
+    MoveMainPanel(MainPanel, isize),
 }
 
 impl Model for PanelState {
@@ -61,10 +90,143 @@ impl Model for PanelState {
             PanelEvent::ToggleBrowser => {
                 self.hide_browser ^= true;
             }
+
+            PanelEvent::ToggleTuner => {
+                self.hide_tuner ^= true;
+            }
+
+            PanelEvent::ToggleInspector => {
+                self.hide_inspector ^= true;
+            }
+
+            PanelEvent::ToggleLoudnessHistory => {
+                self.hide_loudness_history ^= true;
+            }
+
+            PanelEvent::ToggleSampleEditor => {
+                self.hide_sample_editor ^= true;
+            }
+
+            PanelEvent::ShowSampleEditor => {
+                self.hide_sample_editor = false;
+            }
+
+            PanelEvent::ToggleEq => {
+                self.hide_eq ^= true;
+            }
+
+            PanelEvent::ToggleCollisions => {
+                self.hide_collisions ^= true;
+            }
+
+            PanelEvent::ToggleConsole => {
+                self.hide_console ^= true;
+            }
+
+            PanelEvent::ToggleKeyboard => {
+                self.hide_keyboard ^= true;
+            }
+
+            PanelEvent::TogglePads => {
+                self.hide_pads ^= true;
+            }
+
+            PanelEvent::MoveSidePanel(panel, offset) => {
+                move_panel(&mut self.side_panel_order, *panel, *offset);
+            }
+
+            PanelEvent::MoveMainPanel(panel, offset) => {
+                move_panel(&mut self.main_panel_order, *panel, *offset);
+            }
         });
     }
 }
 
+fn move_panel<T: PartialEq>(order: &mut [T], panel: T, offset: isize) {
+    if let Some(index) = order.iter().position(|p| *p == panel) {
+        let new_index = (index as isize + offset).clamp(0, order.len() as isize - 1) as usize;
+        order.swap(index, new_index);
+    }
+}
+
+/// A panel that sits side by side with the others in the middle of the window.
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+pub enum SidePanel {
+    Browser,
+    ChannelRack,
+    /// The area holding the timeline and the other `MainPanel`s.
+    Main,
+    Tuner,
+    Inspector,
+}
+
+impl SidePanel {
+    pub const DEFAULT_ORDER: [SidePanel; 5] = [
+        SidePanel::Browser,
+        SidePanel::ChannelRack,
+        SidePanel::Main,
+        SidePanel::Tuner,
+        SidePanel::Inspector,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SidePanel::Browser => "browser",
+            SidePanel::ChannelRack => "channel_rack",
+            SidePanel::Main => "main",
+            SidePanel::Tuner => "tuner",
+            SidePanel::Inspector => "inspector",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::DEFAULT_ORDER.iter().copied().find(|p| p.name() == name)
+    }
+}
+
+/// A panel in the main area, stacked on top of the others.
+#[derive(Debug, Clone, Copy, PartialEq, Data)]
+pub enum MainPanel {
+    Timeline,
+    PianoRoll,
+    SampleEditor,
+    Eq,
+    Collisions,
+    Console,
+    Keyboard,
+    Pads,
+}
+
+impl MainPanel {
+    pub const DEFAULT_ORDER: [MainPanel; 8] = [
+        MainPanel::Timeline,
+        MainPanel::PianoRoll,
+        MainPanel::SampleEditor,
+        MainPanel::Eq,
+        MainPanel::Collisions,
+        MainPanel::Console,
+        MainPanel::Keyboard,
+        MainPanel::Pads,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MainPanel::Timeline => "timeline",
+            MainPanel::PianoRoll => "piano_roll",
+            MainPanel::SampleEditor => "sample_editor",
+            MainPanel::Eq => "eq",
+            MainPanel::Collisions => "collisions",
+            MainPanel::Console => "console",
+            MainPanel::Keyboard => "keyboard",
+            MainPanel::Pads => "pads",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::DEFAULT_ORDER.iter().copied().find(|p| p.name() == name)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Data)]
 pub enum ChannelRackOrientation {
     Horizontal,
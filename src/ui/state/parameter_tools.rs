@@ -0,0 +1,147 @@
+use vizia::prelude::*;
+
+use super::{ExternalEffectState, HRackEffectState, ParameterState, UiState};
+use crate::backend::dsp::WhiteNoise;
+
+/// An effect in the effect rack of a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectSlot {
+    pub channel: usize,
+    pub effect: usize,
+}
+
+/// The normalized value of a parameter.
+#[derive(Debug, Lens, Clone, Copy, PartialEq, Data)]
+pub struct ParameterValue {
+    pub id: u32,
+    pub value: f64,
+}
+
+/// The normalized range that randomizing picks the values of a parameter
+/// from.
+#[derive(Debug, Lens, Clone, Copy, PartialEq, Data)]
+pub struct ParameterRange {
+    pub id: u32,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Two snapshots of the parameters of an effect and how far the effect is
+/// between them.
+#[derive(Debug, Lens, Clone, Default, PartialEq, Data)]
+pub struct PresetMorph {
+    /// Empty until a snapshot is stored.
+    pub a: Vec<ParameterValue>,
+    pub b: Vec<ParameterValue>,
+    /// 0.0 is all `a` and 1.0 is all `b`.
+    pub amount: f64,
+}
+
+impl ExternalEffectState {
+    /// Every parameter the UI knows the value of, once each. This is all of
+    /// them if they are shown, and the quick access ones otherwise.
+    pub fn parameters(&self) -> Vec<ParameterState> {
+        let mut parameters: Vec<ParameterState> = Vec::new();
+        let known = self
+            .last_tweaked_parameter
+            .iter()
+            .chain(self.quick_access_parameters.iter())
+            .chain(self.all_parameters.iter());
+        for parameter in known {
+            if !parameters.iter().any(|p| p.id == parameter.id) {
+                parameters.push(parameter.clone());
+            }
+        }
+        parameters
+    }
+
+    pub fn parameter_values(&self) -> Vec<ParameterValue> {
+        self.parameters()
+            .iter()
+            .map(|p| ParameterValue { id: p.id, value: p.normalized_value })
+            .collect()
+    }
+
+    /// Set the normalized values of parameters, everywhere they are listed.
+    ///
+    /// TODO: Send the new values to the plugin once the UI can set parameters
+    /// in the engine. The plugin formats the display values, so those are only
+    /// updated when it reports the values back.
+    pub fn set_parameter_values(&mut self, values: &[ParameterValue]) {
+        let parameters = self
+            .last_tweaked_parameter
+            .iter_mut()
+            .chain(self.quick_access_parameters.iter_mut())
+            .chain(self.all_parameters.iter_mut());
+        for parameter in parameters {
+            if let Some(v) = values.iter().find(|v| v.id == parameter.id) {
+                parameter.normalized_value = v.value.clamp(0.0, 1.0);
+            }
+        }
+        if !values.is_empty() {
+            self.preset_changed = true;
+        }
+    }
+
+    /// Give every parameter that has a randomize range a random value in it.
+    pub fn randomize_parameters(&mut self, noise: &mut WhiteNoise) {
+        let values: Vec<ParameterValue> = self
+            .randomize_ranges
+            .iter()
+            .map(|range| {
+                let random = f64::from(noise.next()) * 0.5 + 0.5;
+                ParameterValue { id: range.id, value: range.min + (range.max - range.min) * random }
+            })
+            .collect();
+        self.set_parameter_values(&values);
+    }
+
+    /// Include a parameter in randomizing with the given range, or leave it
+    /// out with `None`.
+    pub fn set_randomize_range(&mut self, id: u32, range: Option<(f64, f64)>) {
+        self.randomize_ranges.retain(|r| r.id != id);
+        if let Some((min, max)) = range {
+            let (min, max) = (min.clamp(0.0, 1.0), max.clamp(0.0, 1.0));
+            self.randomize_ranges.push(ParameterRange { id, min: min.min(max), max: min.max(max) });
+        }
+    }
+
+    /// Store the current values as snapshot `a` or `b` of the morph.
+    pub fn store_morph_preset(&mut self, b: bool) {
+        let values = self.parameter_values();
+        if b {
+            self.morph.b = values;
+        } else {
+            self.morph.a = values;
+        }
+    }
+
+    /// Move the morph between its two snapshots. Parameters that are in both
+    /// are blended linearly, in normalized values.
+    pub fn set_morph_amount(&mut self, amount: f64) {
+        let amount = amount.clamp(0.0, 1.0);
+        self.morph.amount = amount;
+
+        let values: Vec<ParameterValue> = self
+            .morph
+            .a
+            .iter()
+            .filter_map(|a| {
+                let b = self.morph.b.iter().find(|b| b.id == a.id)?;
+                Some(ParameterValue { id: a.id, value: a.value + (b.value - a.value) * amount })
+            })
+            .collect();
+        self.set_parameter_values(&values);
+    }
+}
+
+impl UiState {
+    /// The plugin in the given slot. Internal effects don't have parameters
+    /// yet.
+    pub fn external_effect_mut(&mut self, slot: EffectSlot) -> Option<&mut ExternalEffectState> {
+        match self.channels.get_mut(slot.channel)?.effects.get_mut(slot.effect)? {
+            HRackEffectState::External(effect) => Some(effect),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use super::{ClipStart, ClipState, Inspected, LanePlaylist, UiState};
+
+impl UiState {
+    /// Add a playlist to a lane and make it the active one. The new playlist
+    /// either starts empty or with a copy of the clips of the active one.
+    pub fn add_playlist(&mut self, lane: usize, copy: bool) {
+        let clips = if copy { self.clips_on_lane(lane) } else { Vec::new() };
+
+        let lane_state = match self.timeline_grid.lane_states.lanes.get_mut(lane) {
+            Some(lane_state) => lane_state,
+            None => return,
+        };
+        if lane_state.playlists.is_empty() {
+            // The clips that are on the lane now become the first playlist.
+            lane_state.playlists.push(LanePlaylist { name: None, clips: Vec::new() });
+            lane_state.active_playlist = 0;
+        }
+        lane_state.playlists.push(LanePlaylist { name: None, clips });
+
+        let new = lane_state.playlists.len() - 1;
+        self.switch_playlist(lane, new);
+    }
+
+    /// Swap the clips on a lane for the clips of another of its playlists.
+    pub fn switch_playlist(&mut self, lane: usize, playlist: usize) {
+        let lane_state = match self.timeline_grid.lane_states.lanes.get(lane) {
+            Some(lane_state) => lane_state,
+            None => return,
+        };
+        let active = lane_state.active_playlist;
+        if playlist >= lane_state.playlists.len() || playlist == active {
+            return;
+        }
+
+        let current = self.take_clips_on_lane(lane);
+        let lane_state = &mut self.timeline_grid.lane_states.lanes[lane];
+        lane_state.playlists[active].clips = current;
+        lane_state.active_playlist = playlist;
+
+        // The lane may have moved since the clips were stored.
+        let mut clips = std::mem::take(&mut lane_state.playlists[playlist].clips);
+        for clip in clips.iter_mut() {
+            if let ClipStart::OnLane(on_lane) = &mut clip.timeline_start {
+                on_lane.lane_index = lane as u32;
+            }
+        }
+        self.clips.extend(clips);
+    }
+
+    /// Remove the active playlist of a lane along with its clips, and switch to
+    /// the one before it.
+    pub fn remove_active_playlist(&mut self, lane: usize) {
+        let active = match self.timeline_grid.lane_states.lanes.get(lane) {
+            Some(lane_state) if lane_state.playlists.len() > 1 => lane_state.active_playlist,
+            _ => return,
+        };
+
+        self.switch_playlist(lane, active.checked_sub(1).unwrap_or(1));
+
+        let lane_state = &mut self.timeline_grid.lane_states.lanes[lane];
+        lane_state.playlists.remove(active);
+        if lane_state.active_playlist > active {
+            lane_state.active_playlist -= 1;
+        }
+        if lane_state.playlists.len() == 1 {
+            lane_state.playlists.clear();
+            lane_state.active_playlist = 0;
+        }
+    }
+
+    fn clips_on_lane(&self, lane: usize) -> Vec<ClipState> {
+        self.clips.iter().filter(|clip| is_on_lane(clip, lane)).cloned().collect()
+    }
+
+    fn take_clips_on_lane(&mut self, lane: usize) -> Vec<ClipState> {
+        let (taken, kept): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.clips).into_iter().partition(|clip| is_on_lane(clip, lane));
+        self.clips = kept;
+        if matches!(self.inspected, Some(Inspected::Clip(_))) {
+            self.inspected = None;
+        }
+        taken
+    }
+}
+
+fn is_on_lane(clip: &ClipState, lane: usize) -> bool {
+    match &clip.timeline_start {
+        ClipStart::OnLane(on_lane) => on_lane.lane_index as usize == lane,
+        ClipStart::NotInTimeline => false,
+    }
+}
@@ -0,0 +1,230 @@
+use vizia::prelude::*;
+
+use super::{
+    ActivatedStatus, EffectSlot, ExternalEffectState, HRackEffectState, ParameterValue,
+    SavedPluginState, UiState,
+};
+use crate::backend::graph_interface::{AvailablePlugin, PluginHost};
+use crate::backend::param_state;
+
+/// A plugin the scanner found, as listed in the UI.
+#[derive(Debug, Lens, Clone, PartialEq, Data)]
+pub struct AvailablePluginState {
+    pub rdn: String,
+    pub name: String,
+    pub vendor: String,
+    pub version: String,
+}
+
+impl AvailablePluginState {
+    pub fn new(plugin: &AvailablePlugin) -> Self {
+        Self {
+            rdn: plugin.key.rdn.clone(),
+            name: plugin.name.clone(),
+            vendor: plugin.vendor.clone(),
+            version: plugin.version.clone(),
+        }
+    }
+}
+
+/// The index an effect at `index` ends up at when the effect at `from` is
+/// moved to `to`.
+pub(super) fn moved_effect_index(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+impl UiState {
+    /// Append a plugin to the effects of a channel. Returns the slot of the
+    /// new effect. It stays deactivated until the plugin is added to the
+    /// audio graph.
+    pub fn add_plugin_effect(
+        &mut self,
+        channel: usize,
+        plugin: &AvailablePlugin,
+    ) -> Option<EffectSlot> {
+        let effects = &mut self.channels.get_mut(channel)?.effects;
+
+        let mut effect = ExternalEffectState::new(
+            plugin.name.clone(),
+            plugin.key.rdn.clone(),
+            plugin.version.clone(),
+        );
+        effect.product_url = plugin.product_url.clone();
+        effect.manual_url = plugin.manual_url.clone();
+        effect.support_url = plugin.support_url.clone();
+        effects.push(HRackEffectState::External(effect));
+
+        Some(EffectSlot { channel, effect: effects.len() - 1 })
+    }
+
+    pub fn remove_effect(&mut self, slot: EffectSlot) -> Option<HRackEffectState> {
+        let effects = &mut self.channels.get_mut(slot.channel)?.effects;
+        let effect = (slot.effect < effects.len()).then(|| effects.remove(slot.effect))?;
+        self.on_effect_removed_automation(slot.channel, slot.effect);
+        self.on_effect_removed_macro_knobs(slot.channel, slot.effect);
+        Some(effect)
+    }
+
+    /// Move an effect to another place in the effects of its channel, where
+    /// `to` is its index once it has moved. Returns whether it moved.
+    pub fn move_effect(&mut self, slot: EffectSlot, to: usize) -> bool {
+        let effects = match self.channels.get_mut(slot.channel) {
+            Some(channel) => &mut channel.effects,
+            None => return false,
+        };
+        if slot.effect >= effects.len() || to >= effects.len() || slot.effect == to {
+            return false;
+        }
+        let effect = effects.remove(slot.effect);
+        effects.insert(to, effect);
+        self.on_effect_moved_automation(slot.channel, slot.effect, to);
+        self.on_effect_moved_macro_knobs(slot.channel, slot.effect, to);
+        true
+    }
+
+    /// Bypass a plugin, or stop bypassing it. A bypassed plugin is taken out
+    /// of the chain of its channel in the audio graph, so the signal goes
+    /// straight from the effect before it to the one after it.
+    pub fn set_effect_bypassed(&mut self, slot: EffectSlot, bypassed: bool) {
+        if let Some(effect) = self.external_effect_mut(slot) {
+            effect.bypassed = bypassed;
+        }
+    }
+
+    /// The plugin effects on the channels, in the chains of their racks and
+    /// on the clips.
+    fn external_effects_mut(&mut self) -> impl Iterator<Item = &mut ExternalEffectState> {
+        let channel_effects =
+            self.channels.iter_mut().flat_map(|channel| channel.effects.iter_mut());
+        let clip_effects = self.clips.iter_mut().flat_map(|clip| clip.effects.iter_mut());
+        channel_effects.chain(clip_effects).flat_map(|effect| match effect {
+            HRackEffectState::External(effect) => vec![effect],
+            HRackEffectState::Rack(rack) => rack
+                .chains
+                .iter_mut()
+                .flat_map(|chain| chain.effects.iter_mut())
+                .filter_map(|effect| match effect {
+                    HRackEffectState::External(effect) => Some(effect),
+                    _ => None,
+                })
+                .collect(),
+            HRackEffectState::Internal(_) => Vec::new(),
+        })
+    }
+
+    /// The effect played by the plugin with the given host ID, on a channel,
+    /// in a rack or on a clip.
+    pub fn hosted_effect_mut(&mut self, host_id: u64) -> Option<&mut ExternalEffectState> {
+        self.external_effects_mut().find(|effect| effect.host_id == Some(host_id))
+    }
+
+    /// Ask the internal effects for their latest state, i.e. before the
+    /// project is saved. Other plugins keep the state they were loaded with.
+    pub fn update_saved_states(&mut self, plugin_host: &mut PluginHost) {
+        for effect in self.external_effects_mut() {
+            let state = effect
+                .host_id
+                .and_then(|id| plugin_host.handle_mut(id))
+                .and_then(|handle| handle.internal.as_mut())
+                .and_then(|internal| param_state::save_state(internal.as_mut()));
+            if let Some((version, bytes)) = state {
+                effect.saved_state = Some(SavedPluginState { version, bytes });
+            }
+        }
+    }
+
+    /// The slots of the plugins that aren't played by a plugin in the audio
+    /// graph, i.e. after a project was loaded. Copies of an effect (i.e. from
+    /// pasting it) need plugins of their own, so only the first effect with a
+    /// host ID counts as hosted.
+    pub fn unhosted_effects(&self, hosted: &[u64]) -> Vec<EffectSlot> {
+        let mut slots = Vec::new();
+        let mut seen = Vec::new();
+        for (channel, state) in self.channels.iter().enumerate() {
+            for (effect, state) in state.effects.iter().enumerate() {
+                if let HRackEffectState::External(state) = state {
+                    match state.host_id {
+                        Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                        _ => slots.push(EffectSlot { channel, effect }),
+                    }
+                }
+            }
+        }
+        slots
+    }
+
+    /// The effects of the clips with effects of their own that aren't played
+    /// by a plugin in the audio graph, as the index of the clip and the index
+    /// of the effect. See `unhosted_effects()`.
+    pub fn unhosted_clip_effects(&self, hosted: &[u64]) -> Vec<(usize, usize)> {
+        let mut slots = Vec::new();
+        let mut seen = Vec::new();
+        for clip in self.clip_chains() {
+            for (effect, state) in self.clips[clip].effects.iter().enumerate() {
+                if let HRackEffectState::External(state) = state {
+                    match state.host_id {
+                        Some(id) if hosted.contains(&id) && !seen.contains(&id) => seen.push(id),
+                        _ => slots.push((clip, effect)),
+                    }
+                }
+            }
+        }
+        slots
+    }
+
+    /// The host IDs of the effects of the clips with effects of their own.
+    pub fn clip_effect_host_ids(&self) -> Vec<u64> {
+        self.clip_chains()
+            .into_iter()
+            .flat_map(|clip| self.clips[clip].effects.iter())
+            .filter_map(|effect| match effect {
+                HRackEffectState::External(effect) => effect.host_id,
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The host IDs of every plugin that is on a channel or in the chains of
+    /// its racks.
+    pub fn effect_host_ids(&self) -> Vec<u64> {
+        self.channels
+            .iter()
+            .flat_map(|channel| channel.effects.iter())
+            .flat_map(|effect| match effect {
+                HRackEffectState::Rack(rack) => {
+                    rack.chains.iter().flat_map(|chain| chain.effects.iter()).collect()
+                }
+                effect => vec![effect],
+            })
+            .filter_map(|effect| match effect {
+                HRackEffectState::External(effect) => effect.host_id,
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl ExternalEffectState {
+    /// Show the values of parameters as the plugin reported them. Unlike
+    /// `set_parameter_values()`, this doesn't mark the preset as changed.
+    pub fn set_reported_values(&mut self, values: &[ParameterValue]) {
+        let changed = self.preset_changed;
+        self.set_parameter_values(values);
+        self.preset_changed = changed;
+    }
+
+    pub fn set_activated(&mut self, result: Result<(), String>) {
+        self.status = match result {
+            Ok(()) => ActivatedStatus::Activated,
+            Err(error_msg) => ActivatedStatus::DeactivatedDueToError { error_msg },
+        };
+    }
+}
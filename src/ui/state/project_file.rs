@@ -0,0 +1,903 @@
+use meadowlark_core_types::time::{MusicalTime, Seconds, SuperFrames};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use vizia::prelude::*;
+
+use super::core_types::{musical_from_beats, WMusicalTime};
+use super::{
+    ArrangerSection, AudioClipState, AutomationClipState, AutomationLaneState, AutomationTarget,
+    ChannelBaseColor, ChannelSend, ChannelState, ClipStart, ClipState, ClipType,
+    ContainerClipState, ExternalEffectState, FxChainState, FxRackState, HRackEffectState,
+    HardwareOutput, InputQuantize, LanePlaylist, LaneState, LaneStates, LoopRegion, MacroKnobState,
+    MidiOutputRoute, OnLane, PianoRollClipState, PianoRollNote, RecordInput, RecordingInfo,
+    SavedPluginState, UiState,
+};
+use crate::backend::automation::{AutomationCurve, AutomationLane, AutomationPoint};
+use crate::backend::drum_rack::DrumRack;
+use crate::backend::fx_rack::FxChainMix;
+use crate::backend::instrument_rack::InstrumentRack;
+use crate::backend::midi_transform::MidiTransform;
+use crate::backend::tempo_map::{TempoChange, TempoMap};
+use crate::backend::time_signature::{TimeSignatureChange, TimeSignatureTrack};
+
+/// The version of the format written by `ProjectSaveState::to_ron()`. Fields
+/// that are missing get their defaults and unknown fields are skipped, so
+/// older versions can read newer files as long as this doesn't change. It
+/// only goes up when an existing field changes its meaning.
+pub const PROJECT_FILE_VERSION: u32 = 1;
+
+/// The largest project file that is read. Anything bigger is corrupt, or not
+/// a project at all.
+pub const MAX_PROJECT_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The tempo of a project that doesn't have a valid one.
+const DEFAULT_BPM: f64 = 120.0;
+
+/// How deep containers can be nested. Clips that are nested deeper are
+/// skipped.
+const MAX_CLIP_DEPTH: usize = 32;
+
+/// How deep the values in a file can be nested, i.e. containers in
+/// containers or racks in racks. Deeper files are rejected instead of running
+/// out of stack while they are read. This leaves room for `MAX_CLIP_DEPTH`
+/// containers, each of which takes a few levels.
+const MAX_NESTING: usize = 8 * MAX_CLIP_DEPTH;
+
+/// The latest time a project can refer to, in beats.
+const MAX_BEATS: f64 = 1.0e7;
+
+/// Everything about a project that is saved to disk: the tempo, the loop
+/// region, the input quantize settings, the arranger sections, the lanes with
+/// their playlists, the channels and the clips.
+///
+/// Where the project was being edited is saved separately in a
+/// `ProjectUiState`.
+///
+/// The plugins on the channels are saved with the state they saved, which is
+/// given back to them when they are added to the audio graph again. The
+/// internal effects (i.e. the compressor) are plugins too, and save the values
+/// of their parameters this way.
+///
+/// It is saved as RON through `ProjectFile`, which checks everything that is
+/// read back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "ProjectFile", try_from = "ProjectFile")]
+pub struct ProjectSaveState {
+    pub bpm: f64,
+    pub tempo_changes: Vec<TempoChange>,
+    pub time_signatures: Vec<TimeSignatureChange>,
+    pub loop_region: LoopRegion,
+    pub input_quantize: InputQuantize,
+    pub arranger_start_beats: f64,
+    pub sections: Vec<ArrangerSection>,
+    pub lanes: Vec<LaneState>,
+    pub channels: Vec<ChannelState>,
+    pub clips: Vec<ClipState>,
+}
+
+/// Make sure a clip and the clips inside it are on channels that exist.
+fn check_clip_channels(clip: &ClipState, len: usize) -> Result<(), String> {
+    if clip.channel >= len {
+        return Err(format!("Clip {:?} is on a channel that doesn't exist", clip.name));
+    }
+    if let ClipType::Container(container) = &clip.type_ {
+        for clip in container.clips.iter() {
+            check_clip_channels(clip, len)?;
+        }
+    }
+    Ok(())
+}
+
+impl ProjectSaveState {
+    pub fn from_state(state: &UiState) -> Self {
+        Self {
+            bpm: state.timeline_grid.bpm,
+            tempo_changes: state.timeline_grid.tempo_map.changes().to_vec(),
+            time_signatures: state.timeline_grid.time_signatures.changes().to_vec(),
+            loop_region: state.timeline_grid.loop_region,
+            input_quantize: state.input_quantize,
+            arranger_start_beats: state.arranger.start.get().as_beats_f64(),
+            sections: state.arranger.sections.clone(),
+            lanes: state.timeline_grid.lane_states.lanes.clone(),
+            channels: state.channels.clone(),
+            clips: state.clips.clone(),
+        }
+    }
+
+    /// Replace the project in `state` with this one.
+    pub fn apply(&self, state: &mut UiState) {
+        let bpm = self.bpm;
+
+        state.channels = self.channels.clone();
+        state.clips = self.clips.clone();
+        state.inspected = None;
+        state.input_quantize = self.input_quantize;
+        state.arranger.start = musical_from_beats(self.arranger_start_beats).into();
+        state.arranger.sections = self.sections.clone();
+        state.arranger.selected = None;
+
+        let timeline_grid = &mut state.timeline_grid;
+        timeline_grid.bpm = bpm;
+        timeline_grid.tempo_bus.set_bpm(bpm);
+        timeline_grid.tempo_map = TempoMap::new(self.tempo_changes.clone());
+        timeline_grid.publish_tempo();
+        timeline_grid.time_signatures = TimeSignatureTrack::new(self.time_signatures.clone());
+        timeline_grid.loop_region = self.loop_region;
+        timeline_grid.lane_states = LaneStates::new(self.lanes.clone());
+
+        let mut end_beats: f64 = 0.0;
+        let mut used_lanes = 0;
+        for clip in self.clips.iter() {
+            if let ClipStart::OnLane(on_lane) = &clip.timeline_start {
+                let start = on_lane.timeline_start.get().as_beats_f64();
+                end_beats = end_beats.max(start + clip.length.get().as_beats_f64());
+                used_lanes = used_lanes.max(on_lane.lane_index);
+            }
+        }
+        let project_length = if end_beats > 0.0 {
+            musical_from_beats(end_beats)
+        } else {
+            MusicalTime::from_beats(16)
+        };
+        timeline_grid.project_length = project_length.into();
+        timeline_grid.used_lanes = used_lanes;
+
+        state.debug_check_consistency();
+    }
+
+    /// Write the project as RON.
+    pub fn to_ron(&self) -> Result<String, Box<dyn Error>> {
+        Ok(ron::ser::to_string_pretty(self, PrettyConfig::default())?)
+    }
+
+    /// Read a project written by `to_ron()`.
+    ///
+    /// Fails if the text was written by a newer version, if it is too large
+    /// or too deeply nested, or if its channels don't fit together.
+    pub fn from_ron(text: &str) -> Result<Self, Box<dyn Error>> {
+        if text.len() as u64 > MAX_PROJECT_FILE_BYTES {
+            return Err(
+                format!("The project is larger than {} bytes", MAX_PROJECT_FILE_BYTES).into()
+            );
+        }
+        Ok(ron_options().from_str(text)?)
+    }
+
+    /// Make sure every index into the channels points at a channel.
+    fn check_channels(&self) -> Result<(), String> {
+        let len = self.channels.len();
+        if len == 0 {
+            return Err(String::from("The project has no master channel"));
+        }
+
+        let in_range = |i: &usize| *i < len;
+        for (i, channel) in self.channels.iter().enumerate() {
+            let valid = channel.parent_channel.iter().all(in_range)
+                && channel.subchannels.iter().all(in_range)
+                && in_range(&channel.routed_to)
+                && channel.sends.iter().all(|send| in_range(&send.to))
+                && channel.drum_rack.pads.iter().all(|pad| in_range(&pad.output_channel))
+                && match channel.record_input {
+                    RecordInput::Bus(bus) => in_range(&bus),
+                    RecordInput::Device { .. } => true,
+                };
+            if !valid {
+                return Err(format!("Channel {} refers to a channel that doesn't exist", i));
+            }
+        }
+        // Following the parents of a channel has to end at a root.
+        for i in 0..len {
+            let mut parent = self.channels[i].parent_channel;
+            for _ in 0..len {
+                parent = parent.and_then(|p| self.channels[p].parent_channel);
+            }
+            if parent.is_some() {
+                return Err(format!("Channel {} is its own parent", i));
+            }
+        }
+        let playlist_clips = self
+            .lanes
+            .iter()
+            .flat_map(|lane| lane.playlists.iter().flat_map(|playlist| playlist.clips.iter()));
+        for clip in self.clips.iter().chain(playlist_clips) {
+            check_clip_channels(clip, len)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if std::fs::metadata(path)?.len() > MAX_PROJECT_FILE_BYTES {
+            return Err(
+                format!("The project is larger than {} bytes", MAX_PROJECT_FILE_BYTES).into()
+            );
+        }
+        let text = std::fs::read_to_string(path)?;
+        Self::from_ron(&text)
+    }
+}
+
+fn ron_options() -> ron::Options {
+    ron::Options::default().with_recursion_limit(MAX_NESTING)
+}
+
+/// Write the effects of a channel or rack as they are written in a project.
+pub(super) fn effects_to_ron(effects: &[HRackEffectState]) -> Result<String, Box<dyn Error>> {
+    let effects: Vec<EffectFile> = effects.iter().filter_map(EffectFile::new).collect();
+    Ok(ron::ser::to_string_pretty(&effects, PrettyConfig::default())?)
+}
+
+/// Read effects written by `effects_to_ron()` outside of a project, i.e.
+/// from a rack preset.
+pub(super) fn effects_from_ron(text: &str) -> Result<Vec<HRackEffectState>, Box<dyn Error>> {
+    let effects: Vec<EffectFile> = ron_options().from_str(text)?;
+    Ok(effects.into_iter().map(EffectFile::into_state).collect())
+}
+
+/// A `ProjectSaveState` as it is written to disk. Times are in beats, and
+/// only what can't be rebuilt after loading is kept.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct ProjectFile {
+    version: u32,
+    bpm: f64,
+    tempo_changes: Vec<TempoChange>,
+    time_signatures: Vec<TimeSignatureChange>,
+    loop_region: LoopRegion,
+    input_quantize: InputQuantize,
+    arranger_start: f64,
+    sections: Vec<SectionFile>,
+    lanes: Vec<LaneFile>,
+    channels: Vec<ChannelFile>,
+    clips: Vec<ClipFile>,
+}
+
+impl Default for ProjectFile {
+    fn default() -> Self {
+        Self {
+            version: PROJECT_FILE_VERSION,
+            bpm: DEFAULT_BPM,
+            tempo_changes: Vec::new(),
+            time_signatures: Vec::new(),
+            loop_region: LoopRegion::default(),
+            input_quantize: InputQuantize::default(),
+            arranger_start: 0.0,
+            sections: Vec::new(),
+            lanes: Vec::new(),
+            channels: Vec::new(),
+            clips: Vec::new(),
+        }
+    }
+}
+
+impl From<ProjectSaveState> for ProjectFile {
+    fn from(project: ProjectSaveState) -> Self {
+        Self {
+            version: PROJECT_FILE_VERSION,
+            bpm: project.bpm,
+            tempo_changes: project.tempo_changes,
+            time_signatures: project.time_signatures,
+            loop_region: project.loop_region,
+            input_quantize: project.input_quantize,
+            arranger_start: project.arranger_start_beats,
+            sections: project
+                .sections
+                .iter()
+                .map(|section| SectionFile {
+                    name: section.name.clone(),
+                    length: section.length.get().as_beats_f64(),
+                })
+                .collect(),
+            lanes: project.lanes.iter().map(LaneFile::new).collect(),
+            channels: project.channels.iter().map(ChannelFile::new).collect(),
+            clips: project.clips.iter().map(ClipFile::new).collect(),
+        }
+    }
+}
+
+impl TryFrom<ProjectFile> for ProjectSaveState {
+    type Error = String;
+
+    fn try_from(file: ProjectFile) -> Result<Self, String> {
+        if file.version > PROJECT_FILE_VERSION {
+            return Err(format!("Unsupported project version {}", file.version));
+        }
+
+        let bpm = if file.bpm > 0.0 && file.bpm.is_finite() { file.bpm } else { DEFAULT_BPM };
+
+        let mut input_quantize = file.input_quantize;
+        if !(input_quantize.grid_beats > 0.0 && input_quantize.grid_beats <= MAX_BEATS) {
+            input_quantize.grid_beats = InputQuantize::default().grid_beats;
+        }
+        input_quantize.strength = finite_or_zero(input_quantize.strength).clamp(0.0, 1.0);
+
+        let mut loop_region = file.loop_region;
+        if !loop_region.is_valid() || loop_region.end_beats > MAX_BEATS {
+            loop_region = LoopRegion::default();
+        }
+
+        let mut lanes: Vec<LaneState> =
+            file.lanes.into_iter().map(|lane| lane.into_state()).collect();
+        for lane in lanes.iter_mut() {
+            if lane.active_playlist >= lane.playlists.len().max(1) {
+                lane.active_playlist = 0;
+            }
+        }
+
+        let project = Self {
+            bpm,
+            tempo_changes: file
+                .tempo_changes
+                .into_iter()
+                .filter(|change| change.is_valid() && change.beats <= MAX_BEATS)
+                .collect(),
+            time_signatures: file
+                .time_signatures
+                .into_iter()
+                .filter(|change| change.signature.is_valid())
+                .collect(),
+            loop_region,
+            input_quantize,
+            arranger_start_beats: finite_or_zero(file.arranger_start).clamp(0.0, MAX_BEATS),
+            sections: file
+                .sections
+                .into_iter()
+                .map(|section| ArrangerSection {
+                    name: section.name,
+                    length: beats_to_musical(section.length),
+                })
+                .collect(),
+            lanes,
+            channels: file.channels.into_iter().map(ChannelFile::into_state).collect(),
+            clips: file.clips.into_iter().map(|clip| clip.into_state(0)).collect(),
+        };
+        project.check_channels()?;
+
+        Ok(project)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SectionFile {
+    name: String,
+    length: f64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct LaneFile {
+    name: Option<String>,
+    color: Option<ColorFile>,
+    disabled: bool,
+    playlists: Vec<PlaylistFile>,
+    active_playlist: usize,
+}
+
+impl LaneFile {
+    fn new(lane: &LaneState) -> Self {
+        Self {
+            name: lane.name.clone(),
+            color: lane.color.as_ref().map(ColorFile::new),
+            disabled: lane.disabled,
+            playlists: lane
+                .playlists
+                .iter()
+                .map(|playlist| PlaylistFile {
+                    name: playlist.name.clone(),
+                    clips: playlist.clips.iter().map(ClipFile::new).collect(),
+                })
+                .collect(),
+            active_playlist: lane.active_playlist,
+        }
+    }
+
+    fn into_state(self) -> LaneState {
+        LaneState {
+            name: self.name,
+            color: self.color.map(ColorFile::into_state),
+            disabled: self.disabled,
+            playlists: self
+                .playlists
+                .into_iter()
+                .map(|playlist| LanePlaylist {
+                    name: playlist.name,
+                    clips: playlist.clips.into_iter().map(|clip| clip.into_state(0)).collect(),
+                })
+                .collect(),
+            active_playlist: self.active_playlist,
+            ..LaneState::default()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct PlaylistFile {
+    name: Option<String>,
+    clips: Vec<ClipFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum ColorFile {
+    Preset(u16),
+    Rgba(u8, u8, u8, u8),
+}
+
+impl ColorFile {
+    fn new(color: &ChannelBaseColor) -> Self {
+        match color {
+            ChannelBaseColor::Preset(preset) => ColorFile::Preset(*preset),
+            ChannelBaseColor::Color(color) => {
+                ColorFile::Rgba(color.r(), color.g(), color.b(), color.a())
+            }
+        }
+    }
+
+    fn into_state(self) -> ChannelBaseColor {
+        match self {
+            ColorFile::Preset(preset) => ChannelBaseColor::Preset(preset),
+            ColorFile::Rgba(r, g, b, a) => ChannelBaseColor::Color(Color::rgba(r, g, b, a)),
+        }
+    }
+}
+
+/// A channel without what is rebuilt when it is added to the audio graph.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct ChannelFile {
+    name: String,
+    path: PathBuf,
+    color: ColorFile,
+    parent: Option<usize>,
+    subchannels: Vec<usize>,
+    routed_to: usize,
+    gain: f64,
+    pan: f64,
+    gain_display: String,
+    pan_display: String,
+    soloed: bool,
+    muted: bool,
+    armed: bool,
+    exclusive_arm: bool,
+    arm_group: Option<u32>,
+    record_input: RecordInput,
+    hardware_output: Option<HardwareOutput>,
+    midi_output: Option<MidiOutputRoute>,
+    midi_input: MidiTransform,
+    sends: Vec<ChannelSend>,
+    instrument_rack: InstrumentRack,
+    drum_rack: DrumRack,
+    effects: Vec<EffectFile>,
+    macro_knobs: Vec<MacroKnobState>,
+    automation_lanes: Vec<AutomationLaneFile>,
+}
+
+impl Default for ChannelFile {
+    fn default() -> Self {
+        Self::new(&ChannelState::default())
+    }
+}
+
+impl ChannelFile {
+    fn new(channel: &ChannelState) -> Self {
+        Self {
+            name: channel.name.clone(),
+            path: channel.path.clone(),
+            color: ColorFile::new(&channel.color),
+            parent: channel.parent_channel,
+            subchannels: channel.subchannels.clone(),
+            routed_to: channel.routed_to,
+            gain: channel.out_gain_normalized,
+            pan: channel.out_pan_normalized,
+            gain_display: channel.out_gain_display.clone(),
+            pan_display: channel.out_pan_display.clone(),
+            soloed: channel.soloed,
+            muted: channel.muted,
+            armed: channel.armed,
+            exclusive_arm: channel.exclusive_arm,
+            arm_group: channel.arm_group,
+            record_input: channel.record_input,
+            hardware_output: channel.hardware_output,
+            midi_output: channel.midi_output.clone(),
+            midi_input: channel.midi_input.clone(),
+            sends: channel.sends.clone(),
+            instrument_rack: channel.instrument_rack.clone(),
+            drum_rack: channel.drum_rack.clone(),
+            effects: channel.effects.iter().filter_map(EffectFile::new).collect(),
+            macro_knobs: channel.macro_knobs.clone(),
+            automation_lanes: channel
+                .automation_lanes
+                .iter()
+                .map(|lane| AutomationLaneFile {
+                    target: lane.target,
+                    points: lane.lane.points().to_vec(),
+                })
+                .collect(),
+        }
+    }
+
+    fn into_state(self) -> ChannelState {
+        let mut macro_knobs = self.macro_knobs;
+        for knob in macro_knobs.iter_mut() {
+            knob.value = finite_or_zero(knob.value).clamp(0.0, 1.0);
+        }
+
+        ChannelState {
+            name: self.name,
+            path: self.path,
+            color: self.color.into_state(),
+            parent_channel: self.parent,
+            subchannels: self.subchannels,
+            routed_to: self.routed_to,
+            out_gain_normalized: finite_or_zero(self.gain).clamp(0.0, 1.0),
+            out_pan_normalized: if self.pan.is_finite() { self.pan.clamp(0.0, 1.0) } else { 0.5 },
+            out_gain_display: self.gain_display,
+            out_pan_display: self.pan_display,
+            soloed: self.soloed,
+            muted: self.muted,
+            armed: self.armed,
+            exclusive_arm: self.exclusive_arm,
+            arm_group: self.arm_group,
+            record_input: self.record_input,
+            hardware_output: self.hardware_output,
+            midi_output: self.midi_output,
+            midi_input: self.midi_input,
+            sends: self.sends,
+            instrument_rack: self.instrument_rack,
+            drum_rack: self.drum_rack,
+            effects: self.effects.into_iter().map(EffectFile::into_state).collect(),
+            macro_knobs,
+            automation_lanes: self
+                .automation_lanes
+                .into_iter()
+                .map(AutomationLaneFile::into_state)
+                .collect(),
+            ..ChannelState::default()
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AutomationLaneFile {
+    target: AutomationTarget,
+    points: Vec<AutomationPoint>,
+}
+
+impl AutomationLaneFile {
+    /// Points that aren't finite are skipped, and so are curves that aren't.
+    fn into_state(self) -> AutomationLaneState {
+        let mut lane = AutomationLane::new();
+        for mut point in self.points {
+            if !(point.beats.is_finite() && point.value.is_finite()) {
+                continue;
+            }
+            let curve_is_finite = match point.curve {
+                AutomationCurve::Exponential(amount) => amount.is_finite(),
+                AutomationCurve::Bezier { handle_a, handle_b } => {
+                    [handle_a.0, handle_a.1, handle_b.0, handle_b.1].iter().all(|v| v.is_finite())
+                }
+                _ => true,
+            };
+            if !curve_is_finite {
+                point.curve = AutomationCurve::Linear;
+            }
+            lane.add_point(point);
+        }
+        AutomationLaneState { target: self.target, lane }
+    }
+}
+
+/// An effect of a channel, a clip or a rack chain.
+#[derive(Serialize, Deserialize)]
+enum EffectFile {
+    Plugin {
+        name: String,
+        rdn: String,
+        version: String,
+        #[serde(default)]
+        bypassed: bool,
+        #[serde(default)]
+        state: Option<PluginStateFile>,
+    },
+    Rack {
+        name: String,
+        #[serde(default)]
+        chains: Vec<FxChainFile>,
+    },
+}
+
+/// The state a plugin saved. The bytes are written as hex digits.
+#[derive(Serialize, Deserialize)]
+struct PluginStateFile {
+    version: u32,
+    bytes: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FxChainFile {
+    name: String,
+    mix: FxChainMix,
+    #[serde(default)]
+    effects: Vec<EffectFile>,
+}
+
+impl EffectFile {
+    /// Returns `None` for internal effects, which aren't saved yet.
+    fn new(effect: &HRackEffectState) -> Option<Self> {
+        match effect {
+            HRackEffectState::External(effect) => Some(EffectFile::Plugin {
+                name: effect.name.clone(),
+                rdn: effect.rdn.clone(),
+                version: effect.version.clone(),
+                bypassed: effect.bypassed,
+                state: effect.saved_state.as_ref().map(|state| PluginStateFile {
+                    version: state.version,
+                    bytes: to_hex(&state.bytes),
+                }),
+            }),
+            HRackEffectState::Rack(rack) => Some(EffectFile::Rack {
+                name: rack.name.clone(),
+                chains: rack
+                    .chains
+                    .iter()
+                    .map(|chain| FxChainFile {
+                        name: chain.name.clone(),
+                        mix: chain.mix,
+                        effects: chain.effects.iter().filter_map(EffectFile::new).collect(),
+                    })
+                    .collect(),
+            }),
+            HRackEffectState::Internal(_) => None,
+        }
+    }
+
+    fn into_state(self) -> HRackEffectState {
+        match self {
+            EffectFile::Plugin { name, rdn, version, bypassed, state } => {
+                let mut effect = ExternalEffectState::new(name, rdn, version);
+                effect.bypassed = bypassed;
+                effect.saved_state = state.and_then(|state| {
+                    Some(SavedPluginState {
+                        version: state.version,
+                        bytes: from_hex(&state.bytes)?,
+                    })
+                });
+                HRackEffectState::External(effect)
+            }
+            EffectFile::Rack { name, chains } => {
+                let mut rack = FxRackState::new(name);
+                rack.chains = chains
+                    .into_iter()
+                    .map(|chain| FxChainState {
+                        name: chain.name,
+                        mix: chain.mix,
+                        effects: chain.effects.into_iter().map(EffectFile::into_state).collect(),
+                        mix_host_id: None,
+                    })
+                    .collect();
+                HRackEffectState::Rack(rack)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct ClipFile {
+    name: String,
+    /// The lane the clip is on, or `None` if it is only in the clips panel.
+    lane: Option<u32>,
+    start: f64,
+    length: f64,
+    channel: usize,
+    muted: bool,
+    effects: Vec<EffectFile>,
+    recording: Option<RecordingInfo>,
+    content: ClipContentFile,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+enum ClipContentFile {
+    Audio {
+        #[serde(default)]
+        gain_db: f32,
+        #[serde(default)]
+        fade_in: f64,
+        #[serde(default)]
+        fade_out: f64,
+        #[serde(default)]
+        start_offset: u64,
+        pcm_path: PathBuf,
+        #[serde(default)]
+        takes: Vec<PathBuf>,
+        #[serde(default)]
+        active_take: Option<usize>,
+    },
+    PianoRoll {
+        #[serde(default)]
+        link: Option<u32>,
+        #[serde(default)]
+        notes: Vec<NoteFile>,
+    },
+    #[default]
+    Automation,
+    Container {
+        #[serde(default)]
+        content_offset: f64,
+        #[serde(default)]
+        clips: Vec<ClipFile>,
+    },
+}
+
+/// Notes moved by input quantize also have where they were played.
+#[derive(Serialize, Deserialize)]
+struct NoteFile {
+    start: f64,
+    length: f64,
+    key: i16,
+    velocity: f32,
+    #[serde(default)]
+    recorded_start: Option<f64>,
+}
+
+impl ClipFile {
+    fn new(clip: &ClipState) -> Self {
+        let (lane, start) = match &clip.timeline_start {
+            ClipStart::OnLane(on_lane) => {
+                (Some(on_lane.lane_index), on_lane.timeline_start.get().as_beats_f64())
+            }
+            ClipStart::NotInTimeline => (None, 0.0),
+        };
+
+        let content = match &clip.type_ {
+            ClipType::Audio(audio) => ClipContentFile::Audio {
+                gain_db: audio.gain_db,
+                fade_in: audio.fade_in_secs.get().0,
+                fade_out: audio.fade_out_secs.get().0,
+                start_offset: audio.clip_start_offset.get().0,
+                pcm_path: audio.pcm_path.clone(),
+                takes: audio.alternate_takes.clone(),
+                active_take: audio.active_take,
+            },
+            ClipType::PianoRoll(piano_roll) => ClipContentFile::PianoRoll {
+                link: piano_roll.link,
+                notes: piano_roll
+                    .notes
+                    .iter()
+                    .map(|note| NoteFile {
+                        start: note.start.get().as_beats_f64(),
+                        length: note.length.get().as_beats_f64(),
+                        key: note.key,
+                        velocity: note.velocity,
+                        recorded_start: note.recorded_start.map(|s| s.get().as_beats_f64()),
+                    })
+                    .collect(),
+            },
+            ClipType::Automation(_) => ClipContentFile::Automation,
+            ClipType::Container(container) => ClipContentFile::Container {
+                content_offset: container.content_offset.get().as_beats_f64(),
+                clips: container.clips.iter().map(ClipFile::new).collect(),
+            },
+        };
+
+        Self {
+            name: clip.name.clone(),
+            lane,
+            start,
+            length: clip.length.get().as_beats_f64(),
+            channel: clip.channel,
+            muted: clip.muted,
+            effects: clip.effects.iter().filter_map(EffectFile::new).collect(),
+            recording: clip.recording.clone(),
+            content,
+        }
+    }
+
+    /// The clip at `depth` containers deep. The clips inside a container at
+    /// `MAX_CLIP_DEPTH` are skipped.
+    fn into_state(self, depth: usize) -> ClipState {
+        let timeline_start = match self.lane {
+            Some(lane_index) => ClipStart::OnLane(OnLane {
+                lane_index,
+                timeline_start: beats_to_musical(self.start),
+            }),
+            None => ClipStart::NotInTimeline,
+        };
+
+        let type_ = match self.content {
+            ClipContentFile::Audio {
+                gain_db,
+                fade_in,
+                fade_out,
+                start_offset,
+                pcm_path,
+                takes,
+                active_take,
+            } => ClipType::Audio(AudioClipState {
+                gain_db: if gain_db.is_finite() { gain_db } else { 0.0 },
+                fade_in_secs: Seconds(finite_or_zero(fade_in).max(0.0)).into(),
+                fade_out_secs: Seconds(finite_or_zero(fade_out).max(0.0)).into(),
+                clip_start_offset: SuperFrames(start_offset).into(),
+                pcm_path,
+                alternate_takes: takes,
+                active_take,
+            }),
+            ClipContentFile::PianoRoll { link, notes } => {
+                let mut notes: Vec<PianoRollNote> = notes
+                    .into_iter()
+                    .filter(|note| note.velocity.is_finite())
+                    .map(|note| PianoRollNote {
+                        start: beats_to_musical(note.start),
+                        length: beats_to_musical(note.length),
+                        key: note.key,
+                        velocity: note.velocity.clamp(0.0, 1.0),
+                        recorded_start: note.recorded_start.map(beats_to_musical),
+                    })
+                    .collect();
+                notes.sort_by(|a, b| {
+                    let (a, b) = (a.start.get().as_beats_f64(), b.start.get().as_beats_f64());
+                    a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+                });
+                ClipType::PianoRoll(PianoRollClipState { notes, link })
+            }
+            ClipContentFile::Automation => ClipType::Automation(AutomationClipState {}),
+            ClipContentFile::Container { content_offset, clips } => {
+                let clips = if depth < MAX_CLIP_DEPTH {
+                    clips.into_iter().map(|clip| clip.into_state(depth + 1)).collect()
+                } else {
+                    Vec::new()
+                };
+                ClipType::Container(ContainerClipState {
+                    clips,
+                    content_offset: beats_to_musical(content_offset),
+                })
+            }
+        };
+
+        ClipState {
+            name: self.name,
+            timeline_start,
+            length: beats_to_musical(self.length),
+            channel: self.channel,
+            muted: self.muted,
+            effects: self.effects.into_iter().map(EffectFile::into_state).collect(),
+            recording: self
+                .recording
+                .map(|recording| RecordingInfo { bpm: finite_or_zero(recording.bpm), ..recording }),
+            player_host_id: None,
+            type_,
+        }
+    }
+}
+
+/// The state of a plugin is binary, so it is written as hex digits.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn beats_to_musical(beats: f64) -> WMusicalTime {
+    musical_from_beats(finite_or_zero(beats).clamp(0.0, MAX_BEATS)).into()
+}
+
+/// `NaN` and infinity can be written in RON, but nothing should be set to
+/// them.
+fn finite_or_zero(value: f64) -> f64 {
+    if value.is_finite() {
+        value
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,317 @@
+//! Feeds corrupt projects to the project file loader. Whatever the input,
+//! loading has to either fail with an error or give a project that can be
+//! saved and loaded again, without panicking or running out of memory.
+
+use super::project_file::{MAX_PROJECT_FILE_BYTES, PROJECT_FILE_VERSION};
+use super::{ClipState, ClipType, HRackEffectState, ProjectSaveState, SavedPluginState};
+use crate::backend::param_state::{self, PARAM_STATE_VERSION};
+use crate::util::{fuzz_iterations, Mutator};
+
+/// A project that uses most of the format.
+const SEED: &str = r#"(
+    version: 1,
+    bpm: 128.0,
+    tempo_changes: [(beats: 32.0, bpm: 140.0, ramp: true)],
+    time_signatures: [(bar: 8, signature: (numerator: 7, denominator: 8))],
+    loop_region: (enabled: true, start_beats: 4.0, end_beats: 12.0),
+    input_quantize: (enabled: true, grid_beats: 0.25, strength: 0.5),
+    arranger_start: 4.0,
+    sections: [(name: " Intro ", length: 16.0)],
+    lanes: [(
+        name: Some("Drums"),
+        color: Some(Preset(2)),
+        disabled: false,
+        playlists: [(
+            name: Some("Main"),
+            clips: [(
+                name: "Loop",
+                lane: Some(0),
+                start: 0.0,
+                length: 4.0,
+                channel: 1,
+                content: Audio(
+                    gain_db: -3.0,
+                    fade_in: 0.01,
+                    fade_out: 0.1,
+                    pcm_path: "loop.wav",
+                    takes: ["loop 2.wav"],
+                    active_take: Some(0),
+                ),
+            )],
+        )],
+        active_playlist: 0,
+    )],
+    channels: [
+        (
+            name: "Master",
+            path: "Master",
+            color: Rgba(255, 128, 0, 255),
+            parent: None,
+            subchannels: [1, 2],
+            routed_to: 0,
+            gain: 1.0,
+            pan: 0.5,
+            record_input: Device(left: 0, right: 1),
+        ),
+        (
+            name: "Synth",
+            parent: Some(0),
+            routed_to: 0,
+            record_input: Bus(2),
+            midi_input: (
+                channels: 65535,
+                low_key: 0,
+                high_key: 127,
+                zones: [(low_key: 36, high_key: 48, transpose: 12)],
+            ),
+            sends: [(to: 2, level_db: -6.0, pre_fader: false)],
+            instrument_rack: (chains: [(
+                name: "Layer",
+                zone: (low_key: 0, high_key: 127, min_velocity: 0.0, max_velocity: 1.0),
+                gain_db: 0.0,
+                pan: 0.0,
+                muted: false,
+                soloed: false,
+            )]),
+            drum_rack: (pads: [(
+                name: "Kick",
+                key: 36,
+                sample: Some("kick.wav"),
+                tune: 0.0,
+                cutoff_hz: 20000.0,
+                gain_db: 0.0,
+                pan: 0.0,
+                choke_group: Some(1),
+                separate_output: true,
+            )]),
+            effects: [
+                Plugin(
+                    name: "Comp",
+                    rdn: "app.meadowlark.comp",
+                    version: "1.0",
+                    state: Some((version: 1, bytes: "00ff")),
+                ),
+                Rack(
+                    name: "Parallel",
+                    chains: [(name: "Wet", mix: (gain_db: 0.0, pan: 0.0, muted: false))],
+                ),
+            ],
+            macro_knobs: [(
+                name: "Tone",
+                value: 0.5,
+                targets: [(effect: 0, id: 3, mapping: (min: 0.0, max: 1.0, curve: SCurve))],
+            )],
+            automation_lanes: [(
+                target: Gain,
+                points: [
+                    (
+                        beats: 0.0,
+                        value: 0.5,
+                        curve: Bezier(handle_a: (0.25, 0.0), handle_b: (0.75, 1.0)),
+                    ),
+                    (beats: 8.0, value: 1.0, curve: Exponential(0.5)),
+                ],
+            )],
+        ),
+        (name: "Bus", parent: Some(0), routed_to: 0),
+    ],
+    clips: [(
+        name: "Group",
+        lane: Some(0),
+        start: 8.0,
+        length: 8.0,
+        channel: 1,
+        content: Container(
+            content_offset: 0.0,
+            clips: [(
+                name: "Chords",
+                lane: Some(0),
+                length: 4.0,
+                channel: 1,
+                recording: Some((source: "In 1", take: 1, bpm: 128.0, date: "")),
+                content: PianoRoll(notes: [
+                    (start: 0.0, length: 1.0, key: 60, velocity: 0.8),
+                    (start: 1.0, length: 1.0, key: 64, velocity: 0.8, recorded_start: Some(0.9)),
+                ]),
+            )],
+        ),
+    )],
+)"#;
+
+/// The words of the format, and values that are likely to be out of range.
+const TOKENS: &[&[u8]] = &[
+    b"Some(",
+    b"None",
+    b"Container(clips: [(",
+    b"channels: [(",
+    b"parent: ",
+    b"subchannels: ",
+    b"routed_to: ",
+    b"channel: ",
+    b"length: ",
+    b"bpm: ",
+    b"version: ",
+    b"(",
+    b")",
+    b"[",
+    b"]",
+    b":",
+    b",",
+    b"\"",
+    b"\\",
+    b"0",
+    b"-1",
+    b"NaN",
+    b"inf",
+    b"-inf",
+    b"1e308",
+    b"4294967296",
+    b"18446744073709551615",
+    b"18446744073709551616",
+    "\u{feff}".as_bytes(),
+    &[0xff, 0xfe],
+];
+
+/// Load the text, and if that works, check that the project saves to
+/// something that loads again.
+fn check(text: &str) {
+    if let Ok(project) = ProjectSaveState::from_ron(text) {
+        let saved = project.to_ron().unwrap();
+        if let Err(e) = ProjectSaveState::from_ron(&saved) {
+            panic!("Saving this project gave one that failed to load ({}):\n{}", e, text);
+        }
+    }
+}
+
+/// How many containers deep the clips go.
+fn depth(clips: &[ClipState]) -> usize {
+    clips
+        .iter()
+        .map(|clip| match &clip.type_ {
+            ClipType::Container(container) => 1 + depth(&container.clips),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+#[test]
+fn seed_loads() {
+    let project = ProjectSaveState::from_ron(SEED).unwrap();
+    assert_eq!(project.channels.len(), 3);
+    assert!(project.loop_region.enabled);
+    assert_eq!(project.loop_region.end_beats, 12.0);
+    assert_eq!(project.channels[1].automation_lanes[0].lane.points().len(), 2);
+    check(SEED);
+}
+
+#[test]
+fn mutated_projects_load_or_fail_cleanly() {
+    let mut mutator = Mutator::new(764);
+    for _ in 0..fuzz_iterations(5_000) {
+        let bytes = mutator.mutate(SEED.as_bytes(), TOKENS);
+        check(&String::from_utf8_lossy(&bytes));
+    }
+}
+
+#[test]
+fn names_keep_their_spaces() {
+    let mut project = ProjectSaveState::from_ron(SEED).unwrap();
+    project.channels[1].name = String::from("  Lead  ");
+    project.clips[0].name = String::from(" Verse\n");
+
+    let loaded = ProjectSaveState::from_ron(&project.to_ron().unwrap()).unwrap();
+    assert_eq!(loaded.sections[0].name, " Intro ");
+    assert_eq!(loaded.channels[1].name, "  Lead  ");
+    assert_eq!(loaded.clips[0].name, " Verse\n");
+}
+
+#[test]
+fn effect_states_survive_a_round_trip() {
+    let values = [-24.0, 8.0, 2.5, 250.0, 3.0, 6.0];
+    let mut project = ProjectSaveState::from_ron(SEED).unwrap();
+    match &mut project.channels[1].effects[0] {
+        HRackEffectState::External(effect) => {
+            effect.saved_state = Some(SavedPluginState {
+                version: PARAM_STATE_VERSION,
+                bytes: param_state::encode(&values),
+            })
+        }
+        _ => unreachable!(),
+    }
+
+    let loaded = ProjectSaveState::from_ron(&project.to_ron().unwrap()).unwrap();
+    let state = match &loaded.channels[1].effects[0] {
+        HRackEffectState::External(effect) => effect.saved_state.clone().unwrap(),
+        _ => unreachable!(),
+    };
+    assert_eq!(param_state::decode(state.version, &state.bytes, values.len()).unwrap(), values);
+}
+
+#[test]
+fn non_finite_numbers_are_ignored() {
+    let text = "(bpm: inf, arranger_start: NaN, loop_region: (enabled: true, end_beats: NaN), \
+                channels: [(name: \"Master\", parent: None)], clips: [(lane: Some(0), \
+                start: NaN, length: inf, channel: 0, content: PianoRoll(notes: [\
+                (start: 0.0, length: 1.0, key: 60, velocity: NaN), \
+                (start: -inf, length: 1e308, key: 60, velocity: 1.0)]))])";
+    let project = ProjectSaveState::from_ron(text).unwrap();
+    assert_eq!(project.bpm, 120.0);
+    assert_eq!(project.arranger_start_beats, 0.0);
+    assert!(!project.loop_region.enabled);
+    check(text);
+}
+
+#[test]
+fn deeply_nested_containers_are_cut_off() {
+    let nested = |levels: usize| {
+        let mut text = String::from("(channels: [(name: \"Master\", parent: None)], clips: [");
+        for _ in 0..levels {
+            text.push_str("(channel: 0, content: Container(clips: [");
+        }
+        for _ in 0..levels {
+            text.push_str("]))");
+        }
+        text.push_str("])");
+        text
+    };
+
+    let project = ProjectSaveState::from_ron(&nested(34)).unwrap();
+    assert!(depth(&project.clips) < 34);
+    check(&project.to_ron().unwrap());
+
+    // Too deep to even be read.
+    assert!(ProjectSaveState::from_ron(&nested(100_000)).is_err());
+}
+
+#[test]
+fn parent_cycles_are_rejected() {
+    let text = "(channels: [(name: \"A\", parent: Some(1)), (name: \"B\", parent: Some(0))])";
+    assert!(ProjectSaveState::from_ron(text).is_err());
+}
+
+#[test]
+fn clips_on_missing_channels_are_rejected() {
+    let playlist = "(channels: [(name: \"Master\", parent: None)], \
+                    lanes: [(playlists: [(clips: [(channel: 1)])])])";
+    assert!(ProjectSaveState::from_ron(playlist).is_err());
+
+    let container = "(channels: [(name: \"Master\", parent: None)], \
+                     clips: [(channel: 0, content: Container(clips: [(channel: 1)]))])";
+    assert!(ProjectSaveState::from_ron(container).is_err());
+}
+
+#[test]
+fn newer_versions_are_rejected() {
+    let text = format!(
+        "(version: {}, channels: [(name: \"Master\", parent: None)])",
+        PROJECT_FILE_VERSION + 1
+    );
+    assert!(ProjectSaveState::from_ron(&text).is_err());
+}
+
+#[test]
+fn oversized_projects_are_rejected() {
+    let text = " ".repeat(MAX_PROJECT_FILE_BYTES as usize + 1);
+    assert!(ProjectSaveState::from_ron(&text).is_err());
+}
@@ -0,0 +1,158 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use super::{musical_from_beats, PanelState, TimeRulerFormat, UiState, Workspace};
+
+/// The version of the format written by `ProjectUiState::to_text()`. Files
+/// with a newer version are ignored instead of being half understood.
+pub const PROJECT_UI_STATE_VERSION: u32 = 1;
+
+const PROJECT_UI_STATE_EXTENSION: &str = "ui";
+
+/// Where the project was being edited: the view of the timeline, the layout
+/// of the panels and the heights of the lanes.
+///
+/// This is saved in its own file next to the project and has its own version,
+/// so it can change without touching the project format, and a project can be
+/// loaded without it.
+#[derive(Debug, Clone)]
+pub struct ProjectUiState {
+    pub panels: PanelState,
+    pub horizontal_zoom_level: f64,
+    pub vertical_zoom_level: f64,
+    pub left_start_beats: f64,
+    pub top_start: f64,
+    pub lane_height: f64,
+    /// The height of every lane, in order.
+    pub lane_heights: Vec<Option<f64>>,
+    pub time_ruler_format: TimeRulerFormat,
+}
+
+impl ProjectUiState {
+    pub fn from_state(state: &UiState) -> Self {
+        let timeline_grid = &state.timeline_grid;
+        Self {
+            panels: state.panels.clone(),
+            horizontal_zoom_level: timeline_grid.horizontal_zoom_level,
+            vertical_zoom_level: timeline_grid.vertical_zoom_level,
+            left_start_beats: timeline_grid.left_start.get().as_beats_f64(),
+            top_start: timeline_grid.top_start,
+            lane_height: timeline_grid.lane_height,
+            lane_heights: timeline_grid.lane_states.lanes.iter().map(|lane| lane.height).collect(),
+            time_ruler_format: timeline_grid.time_ruler_format,
+        }
+    }
+
+    /// Restore the editing context. Lane heights are only restored for lanes
+    /// that exist.
+    pub fn apply(&self, state: &mut UiState) {
+        state.panels = self.panels.clone();
+
+        let timeline_grid = &mut state.timeline_grid;
+        timeline_grid.horizontal_zoom_level = self.horizontal_zoom_level;
+        timeline_grid.vertical_zoom_level = self.vertical_zoom_level;
+        timeline_grid.left_start = musical_from_beats(self.left_start_beats).into();
+        timeline_grid.top_start = self.top_start;
+        timeline_grid.lane_height = self.lane_height;
+        timeline_grid.time_ruler_format = self.time_ruler_format;
+        for (lane, height) in timeline_grid.lane_states.lanes.iter_mut().zip(&self.lane_heights) {
+            lane.height = *height;
+        }
+    }
+
+    /// Write the state as `key = value` lines. The panel layout uses the same
+    /// keys as a workspace.
+    pub fn to_text(&self) -> String {
+        let lane_heights: Vec<String> = self
+            .lane_heights
+            .iter()
+            .map(|height| height.map(|h| h.to_string()).unwrap_or_default())
+            .collect();
+
+        [
+            format!("version = {}", PROJECT_UI_STATE_VERSION),
+            Workspace::new(String::new(), &self.panels).to_text(),
+            format!("horizontal_zoom_level = {}", self.horizontal_zoom_level),
+            format!("vertical_zoom_level = {}", self.vertical_zoom_level),
+            format!("left_start = {}", self.left_start_beats),
+            format!("top_start = {}", self.top_start),
+            format!("lane_height = {}", self.lane_height),
+            format!("lane_heights = {}", lane_heights.join(",")),
+            format!(
+                "time_ruler_format = {}",
+                match self.time_ruler_format {
+                    TimeRulerFormat::Time => String::from("time"),
+                    TimeRulerFormat::Timecode { fps } => format!("timecode {}", fps),
+                }
+            ),
+        ]
+        .join("\n")
+    }
+
+    /// Read a state written by `to_text()`. Anything that is missing or can't
+    /// be parsed is taken from `defaults`.
+    ///
+    /// Returns `None` if the text was written by a newer version.
+    pub fn from_text(text: &str, defaults: &UiState) -> Option<Self> {
+        let mut s = Self::from_state(defaults);
+        s.panels = Workspace::from_text(String::new(), text, &defaults.panels).panels;
+
+        for line in text.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+
+            let set_f64 = |field: &mut f64| {
+                if let Ok(v) = value.parse() {
+                    *field = v;
+                }
+            };
+
+            match key {
+                "version" => {
+                    if value.parse::<u32>().map_or(true, |v| v > PROJECT_UI_STATE_VERSION) {
+                        return None;
+                    }
+                }
+                "horizontal_zoom_level" => set_f64(&mut s.horizontal_zoom_level),
+                "vertical_zoom_level" => set_f64(&mut s.vertical_zoom_level),
+                "left_start" => set_f64(&mut s.left_start_beats),
+                "top_start" => set_f64(&mut s.top_start),
+                "lane_height" => set_f64(&mut s.lane_height),
+                "lane_heights" => {
+                    s.lane_heights = value.split(',').map(|h| h.trim().parse().ok()).collect();
+                }
+                "time_ruler_format" => {
+                    s.time_ruler_format = match value.split_once(' ') {
+                        Some(("timecode", fps)) => match fps.trim().parse() {
+                            Ok(fps) if fps > 0 => TimeRulerFormat::Timecode { fps },
+                            _ => s.time_ruler_format,
+                        },
+                        _ => TimeRulerFormat::Time,
+                    };
+                }
+                _ => {}
+            }
+        }
+
+        Some(s)
+    }
+
+    /// The path of the UI state that belongs to the given project file.
+    pub fn path_for(project: &Path) -> PathBuf {
+        project.with_extension(PROJECT_UI_STATE_EXTENSION)
+    }
+
+    pub fn save(&self, project: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(Self::path_for(project), self.to_text())?;
+        Ok(())
+    }
+
+    /// Load the UI state of the given project. Returns `None` if there is none
+    /// or it can't be read.
+    pub fn load(project: &Path, defaults: &UiState) -> Option<Self> {
+        let text = std::fs::read_to_string(Self::path_for(project)).ok()?;
+        Self::from_text(&text, defaults)
+    }
+}
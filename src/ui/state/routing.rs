@@ -0,0 +1,158 @@
+use vizia::prelude::*;
+
+use super::{
+    ChannelBaseColor, ChannelSend, ChannelState, HardwareOutput, LocalizedMessage, UiState,
+};
+use crate::backend::system_io::{OutputChannelMap, ENGINE_OUT_CHANNELS};
+
+/// The range of the level of a send in decibels.
+pub const MIN_SEND_DB: f32 = -60.0;
+pub const MAX_SEND_DB: f32 = 6.0;
+
+/// A change to one of the sends of a channel.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SendEdit {
+    SetLevel(f32),
+    SetPreFader(bool),
+    Remove,
+}
+
+impl UiState {
+    /// Play a channel on outputs of the audio device instead of the channel
+    /// it is routed to, or through that channel again with `None`. The master
+    /// plays on the outputs the audio device is set up with instead.
+    pub fn set_hardware_output(
+        &mut self,
+        index: usize,
+        output: Option<HardwareOutput>,
+    ) -> Result<(), LocalizedMessage> {
+        if index == 0 {
+            return Err(LocalizedMessage::new("error-hardware-output-master"));
+        }
+        let channel = self
+            .channels
+            .get_mut(index)
+            .ok_or_else(|| LocalizedMessage::new("error-channel-missing").arg("channel", index))?;
+        channel.hardware_output = output;
+        Ok(())
+    }
+
+    /// The first of the pair of outputs of the engine each channel plays on,
+    /// for the channels with hardware outputs of their own. The master bus
+    /// has the first pair, and the other channels get the next ones in order.
+    /// Channels past the last pair play through the channel they are routed
+    /// to.
+    pub fn hardware_out_pairs(&self) -> Vec<Option<u16>> {
+        let mut next = 2;
+        self.channels
+            .iter()
+            .enumerate()
+            .map(|(index, channel)| {
+                if index == 0 || channel.hardware_output.is_none() || next >= ENGINE_OUT_CHANNELS {
+                    return None;
+                }
+                next += 2;
+                Some((next - 2) as u16)
+            })
+            .collect()
+    }
+
+    /// The outputs of the audio device each output of the engine goes to:
+    /// the master on the outputs of `map`, and the channels with hardware
+    /// outputs on theirs.
+    pub fn output_channel_map(&self, map: OutputChannelMap) -> OutputChannelMap {
+        let mut channels = [None; ENGINE_OUT_CHANNELS];
+        channels[0..2].copy_from_slice(&map.channels[0..2]);
+        for (channel, first) in self.channels.iter().zip(self.hardware_out_pairs()) {
+            if let (Some(output), Some(first)) = (channel.hardware_output, first) {
+                channels[usize::from(first)] = Some(output.left);
+                channels[usize::from(first) + 1] = Some(output.right);
+            }
+        }
+        OutputChannelMap { channels }
+    }
+
+    /// True if the output of the first channel reaches the second one, either
+    /// through the channels it is routed to or through any of their sends.
+    pub fn feeds(&self, from: usize, to: usize) -> bool {
+        let mut visited = vec![false; self.channels.len()];
+        let mut stack = vec![from];
+        while let Some(index) = stack.pop() {
+            if index == to {
+                return true;
+            }
+            match (self.channels.get(index), visited.get_mut(index)) {
+                (Some(channel), Some(visited)) if !*visited => {
+                    *visited = true;
+                    // The master channel isn't routed anywhere.
+                    if index != 0 {
+                        stack.push(channel.routed_to);
+                    }
+                    stack.extend(channel.sends.iter().map(|send| send.to));
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Add a send from a channel to another one. Returns the index of the
+    /// new send.
+    ///
+    /// Nothing is changed if this fails, i.e. if the master channel would
+    /// send somewhere, the channel already sends there, or the signal would
+    /// feed back into the channel.
+    pub fn add_send(&mut self, index: usize, to: usize) -> Result<usize, LocalizedMessage> {
+        let error = |key| LocalizedMessage::new(key).arg("channel", index).arg("to", to);
+        if index == 0 {
+            return Err(LocalizedMessage::new("error-send-master"));
+        }
+        if index >= self.channels.len() || to >= self.channels.len() || index == to {
+            return Err(error("error-send-channel"));
+        }
+        if self.channels[index].sends.iter().any(|send| send.to == to) {
+            return Err(error("error-send-exists"));
+        }
+        if self.feeds(to, index) {
+            return Err(error("error-send-loop"));
+        }
+
+        self.channels[index].sends.push(ChannelSend::new(to));
+
+        self.debug_check_consistency();
+        Ok(self.channels[index].sends.len() - 1)
+    }
+
+    pub fn edit_send(&mut self, index: usize, send: usize, edit: &SendEdit) {
+        let sends = match self.channels.get_mut(index) {
+            Some(channel) if send < channel.sends.len() => &mut channel.sends,
+            _ => return,
+        };
+
+        match edit {
+            SendEdit::SetLevel(level_db) => {
+                sends[send].level_db = level_db.clamp(MIN_SEND_DB, MAX_SEND_DB)
+            }
+            SendEdit::SetPreFader(pre_fader) => sends[send].pre_fader = *pre_fader,
+            SendEdit::Remove => {
+                sends.remove(send);
+            }
+        }
+    }
+
+    /// Add a channel to the master group for other channels to send to, e.g.
+    /// for a shared reverb. Returns the index of the new channel.
+    pub fn add_return_bus(&mut self) -> Result<usize, LocalizedMessage> {
+        let number = self.channels.iter().filter(|c| c.name.starts_with("Return ")).count() + 1;
+        let name = format!("Return {}", number);
+
+        let channel = ChannelState {
+            name: name.clone(),
+            path: name.into(),
+            color: ChannelBaseColor::Color(Color::rgb(50, 120, 200)),
+            selected: true,
+            ..Default::default()
+        };
+        self.add_channel(channel, 0)
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+use vizia::prelude::*;
+
+use crate::backend::sample_edit::{SampleBuffer, SampleEditOp, SampleEditor};
+use crate::backend::waveform::WaveformPeaks;
+
+/// The state of the sample editor panel.
+#[derive(Debug, Lens, Clone, Default)]
+pub struct SampleEditorState {
+    /// The file that is open in the editor.
+    pub path: Option<PathBuf>,
+
+    /// The name displayed in the header of the panel.
+    pub name: String,
+
+    /// The selected range in frames. The selection is empty when both are equal,
+    /// in which case `selection_start` is the insertion point for pasting.
+    pub selection_start: usize,
+    pub selection_end: usize,
+
+    pub can_undo: bool,
+    pub can_redo: bool,
+
+    /// True if there are edits that have not been saved.
+    pub dirty: bool,
+
+    #[lens(ignore)]
+    pub editor: Option<SampleEditor>,
+
+    #[lens(ignore)]
+    pub clipboard: Option<SampleBuffer>,
+
+    /// The peaks of the left and right channels of the buffer.
+    #[lens(ignore)]
+    pub peaks: Option<[WaveformPeaks; 2]>,
+
+    /// Incremented every time the buffer changes, so the waveform knows when
+    /// it has to be drawn again.
+    #[lens(ignore)]
+    pub buffer_generation: u64,
+}
+
+pub enum SampleEditorEvent {
+    /// Set the selection, where `0.0` is the start of the buffer and `1.0` is the end.
+    SetSelectionNormalized(f32, f32),
+    SelectAll,
+    Cut,
+    Copy,
+    Paste,
+    Apply(SampleEditOp),
+    Undo,
+    Redo,
+}
+
+impl SampleEditorState {
+    /// Open a file in the editor. `peaks` are the cached peaks of the file if
+    /// there are any, otherwise they are computed from the buffer.
+    pub fn open(&mut self, path: PathBuf, editor: SampleEditor, peaks: Option<[WaveformPeaks; 2]>) {
+        self.name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        self.path = Some(path);
+        self.editor = Some(editor);
+        self.selection_start = 0;
+        self.selection_end = 0;
+        self.sync();
+        match peaks {
+            Some(peaks) => {
+                self.peaks = Some(peaks);
+                self.buffer_generation += 1;
+            }
+            None => self.sync_buffer(),
+        }
+    }
+
+    /// Update the waveform after the content of the buffer has changed.
+    pub fn sync_buffer(&mut self) {
+        self.peaks = self.editor.as_ref().map(|editor| {
+            let buffer = editor.buffer();
+            [WaveformPeaks::new(&buffer.left), WaveformPeaks::new(&buffer.right)]
+        });
+        self.buffer_generation += 1;
+    }
+
+    /// Update the UI-facing state after the editor has changed.
+    pub fn sync(&mut self) {
+        if let Some(editor) = &self.editor {
+            let len = editor.buffer().len_frames();
+
+            self.selection_start = self.selection_start.min(len);
+            self.selection_end = self.selection_end.min(len);
+            self.can_undo = editor.can_undo();
+            self.can_redo = editor.can_redo();
+            self.dirty = editor.is_dirty();
+        } else {
+            self.can_undo = false;
+            self.can_redo = false;
+            self.dirty = false;
+        }
+    }
+
+    fn selection(&self) -> std::ops::Range<usize> {
+        self.selection_start..self.selection_end
+    }
+}
+
+impl Model for SampleEditorState {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|sample_editor_event, _| {
+            let selection = self.selection();
+            let editor = match &mut self.editor {
+                Some(editor) => editor,
+                None => return,
+            };
+
+            let changes_buffer = !matches!(
+                sample_editor_event,
+                SampleEditorEvent::SetSelectionNormalized(..)
+                    | SampleEditorEvent::SelectAll
+                    | SampleEditorEvent::Copy
+            );
+
+            match sample_editor_event {
+                SampleEditorEvent::SetSelectionNormalized(a, b) => {
+                    let len = editor.buffer().len_frames() as f32;
+                    let a = (a.clamp(0.0, 1.0) * len) as usize;
+                    let b = (b.clamp(0.0, 1.0) * len) as usize;
+
+                    self.selection_start = a.min(b);
+                    self.selection_end = a.max(b);
+                }
+                SampleEditorEvent::SelectAll => {
+                    self.selection_start = 0;
+                    self.selection_end = editor.buffer().len_frames();
+                }
+                SampleEditorEvent::Cut => {
+                    self.clipboard = Some(editor.cut(selection.clone()));
+                    self.selection_end = selection.start;
+                }
+                SampleEditorEvent::Copy => {
+                    self.clipboard = Some(editor.copy(selection));
+                }
+                SampleEditorEvent::Paste => {
+                    if let Some(clipboard) = &self.clipboard {
+                        editor.paste(selection.clone(), clipboard);
+                        self.selection_end = selection.start + clipboard.len_frames();
+                    }
+                }
+                SampleEditorEvent::Apply(op) => {
+                    editor.apply(selection, *op);
+                }
+                SampleEditorEvent::Undo => {
+                    editor.undo();
+                }
+                SampleEditorEvent::Redo => {
+                    editor.redo();
+                }
+            }
+
+            self.sync();
+            if changes_buffer {
+                self.sync_buffer();
+            }
+            cx.needs_redraw();
+        });
+    }
+}
@@ -0,0 +1,550 @@
+//! A small command language for driving the project from the script console.
+//!
+//! Every line of a script (lines can also be separated with `;`) is one
+//! command followed by its arguments, separated by spaces. Arguments with
+//! spaces in them can be put in double quotes, and everything after a `#` is a
+//! comment. Commands that change the project do so by sending the same events
+//! as the rest of the UI, which is also how macros are recorded: see
+//! `record_ui_event()`.
+
+use super::{ChannelEvent, ClipStart, LocalizedMessage, UiEvent, UiState, MAX_MACRO_SHORTCUT};
+use crate::backend::time_signature::TimeSignature;
+use crate::util::Unit;
+
+/// How deep scripts can run other scripts, i.e. macros that run macros. This
+/// stops a macro that runs itself.
+pub const MAX_SCRIPT_DEPTH: usize = 8;
+
+/// The most times `repeat` runs a command.
+pub const MAX_REPEAT_COUNT: usize = 1_000;
+
+/// A command of the scripting API.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScriptCommand {
+    pub name: &'static str,
+    /// The arguments, with optional ones in square brackets.
+    pub args: &'static str,
+    pub help: &'static str,
+}
+
+impl ScriptCommand {
+    /// The name of the command followed by its arguments.
+    pub fn signature(&self) -> String {
+        if self.args.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} {}", self.name, self.args)
+        }
+    }
+}
+
+/// Every command of the scripting API. This is what `api()` returns.
+const SCRIPT_API: [ScriptCommand; 28] = [
+    ScriptCommand { name: "help", args: "[command]", help: "List the commands or explain one" },
+    ScriptCommand { name: "tempo", args: "[bpm]", help: "Show or set the tempo" },
+    ScriptCommand {
+        name: "tempo_change",
+        args: "<bar:beat> <bpm> [ramp]",
+        help: "Change the tempo at a position, gliding there with `ramp`",
+    },
+    ScriptCommand { name: "remove_tempo_change", args: "<index>", help: "Remove a tempo change" },
+    ScriptCommand {
+        name: "time_signature",
+        args: "<bar> <n/d>",
+        help: "Change the time signature from a bar on",
+    },
+    ScriptCommand {
+        name: "remove_time_signature",
+        args: "<index>",
+        help: "Remove a change of the time signature",
+    },
+    ScriptCommand { name: "channels", args: "", help: "List the channels" },
+    ScriptCommand {
+        name: "add_channel",
+        args: "[name]",
+        help: "Add a channel to the master group",
+    },
+    ScriptCommand {
+        name: "rename_channel",
+        args: "<channel> <name>",
+        help: "Rename a channel. Channels are given by number, name or `last`",
+    },
+    ScriptCommand {
+        name: "route_channel",
+        args: "<channel> <output>",
+        help: "Send the output of a channel to another channel",
+    },
+    ScriptCommand { name: "lanes", args: "", help: "List the lanes of the timeline" },
+    ScriptCommand { name: "clips", args: "", help: "List the clips" },
+    ScriptCommand { name: "sections", args: "", help: "List the sections of the arranger" },
+    ScriptCommand { name: "select_lane", args: "<lane>", help: "Select a lane" },
+    ScriptCommand { name: "rename_clip", args: "<clip> <name>", help: "Rename a clip" },
+    ScriptCommand {
+        name: "move_clip",
+        args: "<clip> <bar:beat>",
+        help: "Move a clip on the timeline",
+    },
+    ScriptCommand {
+        name: "set_clip_length",
+        args: "<clip> <beats>",
+        help: "Set the length of a clip",
+    },
+    ScriptCommand { name: "mute_clip", args: "<clip>", help: "Mute or unmute a clip" },
+    ScriptCommand { name: "remove_clip", args: "<clip>", help: "Remove a clip" },
+    ScriptCommand { name: "add_section", args: "[name]", help: "Add a section to the arranger" },
+    ScriptCommand { name: "rename_section", args: "<section> <name>", help: "Rename a section" },
+    ScriptCommand { name: "capture", args: "", help: "Capture the last MIDI performance" },
+    ScriptCommand { name: "save", args: "", help: "Save the project" },
+    ScriptCommand {
+        name: "repeat",
+        args: "<count> <command...>",
+        help: "Run a command several times. `$i` is replaced with 1, 2, 3...",
+    },
+    ScriptCommand { name: "macros", args: "", help: "List the macros" },
+    ScriptCommand {
+        name: "macro",
+        args: "<name> [args...]",
+        help: "Run a macro. `$1`, `$2`... in it are replaced with the arguments",
+    },
+    ScriptCommand {
+        name: "bind_macro",
+        args: "<name> <1-9|none>",
+        help: "Run a macro with Ctrl+Alt and a number",
+    },
+    ScriptCommand { name: "delete_macro", args: "<name>", help: "Delete a macro" },
+];
+
+/// The commands of the scripting API, for autocompletion and `help`.
+pub fn api() -> &'static [ScriptCommand] {
+    &SCRIPT_API
+}
+
+/// The commands that complete what has been typed so far. Only the name of
+/// the command is completed, so this is empty once an argument is typed.
+pub fn completions(input: &str) -> Vec<&'static ScriptCommand> {
+    let input = input.trim_start();
+    if input.is_empty() || input.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+    api().iter().filter(|command| command.name.starts_with(input)).collect()
+}
+
+/// An event sent by a script.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptEvent {
+    Ui(UiEvent),
+    Channel(ChannelEvent),
+}
+
+impl From<UiEvent> for ScriptEvent {
+    fn from(event: UiEvent) -> Self {
+        ScriptEvent::Ui(event)
+    }
+}
+
+impl From<ChannelEvent> for ScriptEvent {
+    fn from(event: ChannelEvent) -> Self {
+        ScriptEvent::Channel(event)
+    }
+}
+
+/// The result of running one line of a script.
+#[derive(Debug, Default)]
+pub struct ScriptOutput {
+    /// Text to print in the console.
+    pub text: Option<String>,
+    /// Events to send to make the changes the line asked for.
+    pub events: Vec<ScriptEvent>,
+    /// Lines to run after the events were sent, e.g. the lines of a macro.
+    pub run: Vec<String>,
+}
+
+impl ScriptOutput {
+    fn print(text: String) -> Self {
+        Self { text: Some(text), ..Default::default() }
+    }
+
+    fn emit<E: Into<ScriptEvent>>(events: Vec<E>) -> Self {
+        Self { events: events.into_iter().map(Into::into).collect(), ..Default::default() }
+    }
+
+    fn run(lines: Vec<String>) -> Self {
+        Self { run: lines, ..Default::default() }
+    }
+}
+
+/// Run a line of a script against the project. Returns a message that
+/// describes the problem if the line can't be run.
+pub fn run_line(state: &UiState, line: &str) -> Result<ScriptOutput, LocalizedMessage> {
+    let line = match line.split_once('#') {
+        Some((code, _)) => code,
+        None => line,
+    };
+    let tokens = tokenize(line)?;
+    let (name, args) = match tokens.split_first() {
+        Some((name, args)) => (name.as_str(), args),
+        None => return Ok(ScriptOutput::default()),
+    };
+
+    let command = api().iter().find(|command| command.name == name).ok_or_else(|| {
+        LocalizedMessage::new("error-script-unknown-command").arg("command", name)
+    })?;
+    let usage = || LocalizedMessage::new("error-script-usage").arg("usage", command.signature());
+    let arg = |i: usize| args.get(i).map(String::as_str).ok_or_else(usage);
+    let clip = |i: usize| {
+        let index = parse_index(arg(i)?)?;
+        match state.clips.get(index) {
+            Some(_) => Ok(index),
+            None => Err(LocalizedMessage::new("error-clip-missing").arg("clip", index)),
+        }
+    };
+    let channel = |i: usize| find_channel(state, arg(i)?);
+    let find_macro = |i: usize| {
+        let name = arg(i)?;
+        state
+            .macros
+            .macros
+            .iter()
+            .position(|m| m.name == name)
+            .ok_or_else(|| LocalizedMessage::new("error-script-no-macro").arg("name", name))
+    };
+    let time_signatures = &state.timeline_grid.time_signatures;
+
+    let output = match name {
+        "help" => match args.first() {
+            Some(name) => match api().iter().find(|command| command.name == name.as_str()) {
+                Some(command) => {
+                    ScriptOutput::print(format!("{}: {}", command.signature(), command.help))
+                }
+                None => {
+                    return Err(
+                        LocalizedMessage::new("error-script-unknown-command").arg("command", name)
+                    )
+                }
+            },
+            None => ScriptOutput::print(
+                api()
+                    .iter()
+                    .map(|command| format!("{} - {}", command.signature(), command.help))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        },
+        "tempo" => match args.first() {
+            Some(bpm) => ScriptOutput::emit(vec![UiEvent::SetTempo(parse_number(bpm)?)]),
+            None => ScriptOutput::print(format!("{} BPM", state.timeline_grid.bpm)),
+        },
+        "tempo_change" => {
+            let beats = time_signatures
+                .parse_position(arg(0)?)
+                .ok_or_else(|| expected("error-script-expected-position", &args[0]))?;
+            let bpm = parse_number(arg(1)?)?;
+            let ramp = match args.get(2).map(String::as_str) {
+                None => false,
+                Some("ramp") => true,
+                Some(other) => return Err(expected("error-script-expected-ramp", other)),
+            };
+            ScriptOutput::emit(vec![UiEvent::InsertTempoChange { beats, bpm, ramp }])
+        }
+        "remove_tempo_change" => {
+            let index = parse_index(arg(0)?)?;
+            if index >= state.timeline_grid.tempo_map.changes().len() {
+                return Err(LocalizedMessage::new("error-tempo-change-missing").arg("index", index));
+            }
+            ScriptOutput::emit(vec![UiEvent::RemoveTempoChange(index)])
+        }
+        "time_signature" => {
+            let bar = arg(0)?
+                .parse::<u32>()
+                .ok()
+                .and_then(|bar| bar.checked_sub(1))
+                .ok_or_else(|| expected("error-script-expected-bar", &args[0]))?;
+            let signature = arg(1)?;
+            let (numerator, denominator) = signature
+                .split_once('/')
+                .and_then(|(n, d)| Some((n.trim().parse().ok()?, d.trim().parse().ok()?)))
+                .ok_or_else(|| expected("error-script-expected-time-signature", signature))?;
+            if !TimeSignature::new(numerator, denominator).is_valid() {
+                return Err(LocalizedMessage::new("error-invalid-time-signature")
+                    .arg("signature", signature));
+            }
+            ScriptOutput::emit(vec![UiEvent::InsertTimeSignature { bar, numerator, denominator }])
+        }
+        "remove_time_signature" => {
+            let index = parse_index(arg(0)?)?;
+            if index >= time_signatures.changes().len() {
+                return Err(LocalizedMessage::new("error-time-signature-change-missing")
+                    .arg("index", index));
+            }
+            ScriptOutput::emit(vec![UiEvent::RemoveTimeSignature(index)])
+        }
+        "channels" => ScriptOutput::print(list(state.channels.iter().map(|c| c.name.clone()))),
+        "add_channel" => {
+            let mut events = vec![ScriptEvent::from(ChannelEvent::AddChannel)];
+            if !args.is_empty() {
+                events
+                    .push(ChannelEvent::RenameChannel(state.channels.len(), args.join(" ")).into());
+            }
+            ScriptOutput::emit(events)
+        }
+        "rename_channel" => {
+            let channel = channel(0)?;
+            arg(1)?;
+            ScriptOutput::emit(vec![ChannelEvent::RenameChannel(channel, args[1..].join(" "))])
+        }
+        "route_channel" => {
+            let (channel, output) = (channel(0)?, channel(1)?);
+            if channel == 0 {
+                return Err(LocalizedMessage::new("error-route-master"));
+            }
+            ScriptOutput::emit(vec![ChannelEvent::RouteChannel(channel, output)])
+        }
+        "lanes" => ScriptOutput::print(list(
+            state.timeline_grid.lane_states.lanes.iter().enumerate().map(|(i, lane)| {
+                let name = lane.name.clone().unwrap_or_else(|| format!("lane {}", i));
+                if lane.disabled {
+                    format!("{} (disabled)", name)
+                } else {
+                    name
+                }
+            }),
+        )),
+        "clips" => ScriptOutput::print(list(state.clips.iter().map(|clip| {
+            let position = match &clip.timeline_start {
+                ClipStart::OnLane(on_lane) => format!(
+                    "lane {} at {}",
+                    on_lane.lane_index,
+                    time_signatures.format_position(on_lane.timeline_start.get().as_beats_f64())
+                ),
+                ClipStart::NotInTimeline => String::from("not on the timeline"),
+            };
+            format!(
+                "{}, {}, {}",
+                clip.name,
+                position,
+                Unit::Beats.format(clip.length.get().as_beats_f64())
+            )
+        }))),
+        "sections" => ScriptOutput::print(list(
+            state.arranger.sections.iter().zip(state.arranger.section_ranges()).map(
+                |(section, range)| {
+                    format!("{} at {}", section.name, time_signatures.format_position(range.start))
+                },
+            ),
+        )),
+        "select_lane" => {
+            let lane = parse_index(arg(0)?)?;
+            if lane >= state.timeline_grid.lane_states.lanes.len() {
+                return Err(LocalizedMessage::new("error-lane-missing").arg("lane", lane));
+            }
+            ScriptOutput::emit(vec![UiEvent::SelectLane(lane)])
+        }
+        "rename_clip" => {
+            let clip = clip(0)?;
+            arg(1)?;
+            ScriptOutput::emit(vec![UiEvent::RenameClip(clip, args[1..].join(" "))])
+        }
+        "move_clip" => {
+            let clip = clip(0)?;
+            let beats = time_signatures
+                .parse_position(arg(1)?)
+                .ok_or_else(|| expected("error-script-expected-position", &args[1]))?;
+            ScriptOutput::emit(vec![UiEvent::SetClipStart(clip, beats)])
+        }
+        "set_clip_length" => {
+            let clip = clip(0)?;
+            let beats = Unit::Beats
+                .parse(arg(1)?)
+                .ok_or_else(|| expected("error-script-expected-beats", &args[1]))?;
+            ScriptOutput::emit(vec![UiEvent::SetClipLength(clip, beats)])
+        }
+        "mute_clip" => ScriptOutput::emit(vec![UiEvent::ToggleClipMute(clip(0)?)]),
+        "remove_clip" => ScriptOutput::emit(vec![UiEvent::RemoveClips(vec![clip(0)?])]),
+        "add_section" => {
+            let mut events = vec![UiEvent::AddSection];
+            if !args.is_empty() {
+                events.push(UiEvent::RenameSection(state.arranger.sections.len(), args.join(" ")));
+            }
+            ScriptOutput::emit(events)
+        }
+        "rename_section" => {
+            let section = parse_index(arg(0)?)?;
+            if section >= state.arranger.sections.len() {
+                return Err(LocalizedMessage::new("error-section-missing").arg("section", section));
+            }
+            arg(1)?;
+            ScriptOutput::emit(vec![UiEvent::RenameSection(section, args[1..].join(" "))])
+        }
+        "capture" => ScriptOutput::emit(vec![UiEvent::CaptureLastPerformance]),
+        "save" => ScriptOutput::emit(vec![UiEvent::SaveProject]),
+        "repeat" => {
+            let count = parse_index(arg(0)?)?;
+            if count > MAX_REPEAT_COUNT {
+                return Err(LocalizedMessage::new("error-script-repeat-count")
+                    .arg("count", MAX_REPEAT_COUNT));
+            }
+            arg(1)?;
+            let command = join_quoted(&args[1..]);
+            ScriptOutput::run((1..=count).map(|i| command.replace("$i", &i.to_string())).collect())
+        }
+        "macros" => {
+            ScriptOutput::print(list(state.macros.macros.iter().map(|m| match m.shortcut {
+                Some(shortcut) => format!("{} (Ctrl+Alt+{})", m.name, shortcut),
+                None => m.name.clone(),
+            })))
+        }
+        "macro" => ScriptOutput::run(state.macros.macros[find_macro(0)?].expand(&args[1..])?),
+        "bind_macro" => {
+            let index = find_macro(0)?;
+            let shortcut = match arg(1)? {
+                "none" => None,
+                n => match n.parse() {
+                    Ok(n) if (1..=MAX_MACRO_SHORTCUT).contains(&n) => Some(n),
+                    _ => return Err(usage()),
+                },
+            };
+            ScriptOutput::emit(vec![UiEvent::BindMacro { index, shortcut }])
+        }
+        "delete_macro" => ScriptOutput::emit(vec![UiEvent::DeleteMacro(find_macro(0)?)]),
+        _ => unreachable!("Every command of the API is handled"),
+    };
+
+    Ok(output)
+}
+
+/// Split a line into words. Double quotes group words together.
+fn tokenize(line: &str) -> Result<Vec<String>, LocalizedMessage> {
+    let mut tokens = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted ^= true;
+                current.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => {
+                tokens.extend(current.take());
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err(LocalizedMessage::new("error-script-missing-quote"));
+    }
+    tokens.extend(current);
+
+    Ok(tokens)
+}
+
+/// The lines of a script that make the same change as the given event. This
+/// is what recording a macro writes, so events that can't be scripted give
+/// no lines.
+pub fn record_ui_event(state: &UiState, event: &UiEvent) -> Vec<String> {
+    let time_signatures = &state.timeline_grid.time_signatures;
+    let line = match event {
+        UiEvent::SetTempo(bpm) => format!("tempo {}", bpm),
+        UiEvent::InsertTempoChange { beats, bpm, ramp } => format!(
+            "tempo_change {} {}{}",
+            time_signatures.format_position(*beats),
+            bpm,
+            if *ramp { " ramp" } else { "" }
+        ),
+        UiEvent::RemoveTempoChange(index) => format!("remove_tempo_change {}", index),
+        UiEvent::InsertTimeSignature { bar, numerator, denominator } => {
+            format!("time_signature {} {}/{}", bar + 1, numerator, denominator)
+        }
+        UiEvent::RemoveTimeSignature(index) => format!("remove_time_signature {}", index),
+        UiEvent::SelectLane(lane) => format!("select_lane {}", lane),
+        UiEvent::RenameClip(clip, name) => format!("rename_clip {} {}", clip, quote(name)),
+        UiEvent::SetClipStart(clip, beats) => {
+            format!("move_clip {} {}", clip, time_signatures.format_position(*beats))
+        }
+        UiEvent::SetClipLength(clip, beats) => format!("set_clip_length {} {}", clip, beats),
+        UiEvent::ToggleClipMute(clip) => format!("mute_clip {}", clip),
+        // Remove the last clip first so the numbers of the others don't change.
+        UiEvent::RemoveClips(clips) => {
+            let mut clips = clips.clone();
+            clips.sort_unstable_by(|a, b| b.cmp(a));
+            clips.dedup();
+            return clips.iter().map(|clip| format!("remove_clip {}", clip)).collect();
+        }
+        UiEvent::AddSection => String::from("add_section"),
+        UiEvent::RenameSection(section, name) => {
+            format!("rename_section {} {}", section, quote(name))
+        }
+        UiEvent::CaptureLastPerformance => String::from("capture"),
+        UiEvent::SaveProject => String::from("save"),
+        _ => return Vec::new(),
+    };
+    vec![line]
+}
+
+/// Like `record_ui_event()`, for events of the channels panel.
+pub fn record_channel_event(event: &ChannelEvent) -> Vec<String> {
+    let line = match event {
+        ChannelEvent::AddChannel => String::from("add_channel"),
+        ChannelEvent::RenameChannel(channel, name) => {
+            format!("rename_channel {} {}", channel, quote(name))
+        }
+        ChannelEvent::RouteChannel(channel, output) => {
+            format!("route_channel {} {}", channel, output)
+        }
+        _ => return Vec::new(),
+    };
+    vec![line]
+}
+
+/// Put an argument in double quotes if it has spaces in it.
+pub fn quote(arg: &str) -> String {
+    if arg.is_empty() || arg.contains(char::is_whitespace) {
+        format!("\"{}\"", arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+fn join_quoted(args: &[String]) -> String {
+    args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" ")
+}
+
+/// A channel given by its number, its name or `last` for the newest one.
+fn find_channel(state: &UiState, text: &str) -> Result<usize, LocalizedMessage> {
+    if text == "last" {
+        return Ok(state.channels.len().saturating_sub(1));
+    }
+    match text.parse::<usize>() {
+        Ok(index) if index < state.channels.len() => Ok(index),
+        Ok(index) => Err(LocalizedMessage::new("error-channel-missing").arg("channel", index)),
+        Err(_) => state
+            .channels
+            .iter()
+            .position(|channel| channel.name == text)
+            .ok_or_else(|| LocalizedMessage::new("error-script-no-channel").arg("name", text)),
+    }
+}
+
+/// One numbered line per item.
+fn list(items: impl Iterator<Item = String>) -> String {
+    let lines: Vec<String> =
+        items.enumerate().map(|(i, item)| format!("{}: {}", i, item)).collect();
+    if lines.is_empty() {
+        String::from("(none)")
+    } else {
+        lines.join("\n")
+    }
+}
+
+/// An argument that isn't what the command expects.
+fn expected(key: &'static str, text: &str) -> LocalizedMessage {
+    LocalizedMessage::new(key).arg("text", text)
+}
+
+fn parse_index(text: &str) -> Result<usize, LocalizedMessage> {
+    text.parse().map_err(|_| expected("error-script-expected-number", text))
+}
+
+fn parse_number(text: &str) -> Result<f64, LocalizedMessage> {
+    text.parse().map_err(|_| expected("error-script-expected-number", text))
+}
@@ -1,5 +1,13 @@
-use super::core_types::WMusicalTime;
+use super::core_types::{musical_from_beats, WMusicalTime};
 use super::{LaneStates, UiEvent};
+use crate::backend::tap_tempo::TapTempo;
+use crate::backend::tempo_bus::TempoBus;
+use crate::backend::tempo_map::{ProjectTempo, TempoChange, TempoMap};
+use crate::backend::time_signature::{TimeSignature, TimeSignatureChange, TimeSignatureTrack};
+use crate::util::Unit;
+use meadowlark_core_types::time::{Frames, MusicalTime, SampleRate, Seconds};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
 use vizia::prelude::*;
 
 #[derive(Debug, Lens, Clone)]
@@ -48,21 +56,361 @@ pub struct TimelineGridState {
     /// The index of the highest-indexed lane that currently has a clip on it. This
     /// can be used to properly set the vertical scroll bar.
     pub used_lanes: u32,
-    // TODO: Time signature
+
+    /// The tempo at the start of the project in beats per minute.
+    pub bpm: f64,
+
+    /// The taps of the tap tempo button.
+    #[lens(ignore)]
+    pub tap_tempo: TapTempo,
+
+    /// Shares the tempo at the playhead with the nodes in the audio graph.
+    #[lens(ignore)]
+    pub tempo_bus: TempoBus,
+
+    /// How the tempo changes after the start of the project.
+    #[lens(ignore)]
+    pub tempo_map: TempoMap,
+
+    /// The time signatures of the project, which divide it into bars.
+    #[lens(ignore)]
+    pub time_signatures: TimeSignatureTrack,
+
+    /// The part of the project the transport plays over and over.
+    pub loop_region: LoopRegion,
+
+    /// What the ruler below the musical one shows.
+    pub time_ruler_format: TimeRulerFormat,
+
+    /// The width of the visible part of the timeline in logical pixels.
+    pub view_width: f32,
+
+    /// The height of the visible part of the lanes in logical pixels.
+    pub view_height: f32,
+
+    /// The views before and after the current one.
+    #[lens(ignore)]
+    pub view_history: ViewHistory,
 }
 
+/// What the second ruler of the timeline shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+pub enum TimeRulerFormat {
+    /// Minutes, seconds and milliseconds.
+    Time,
+    /// SMPTE timecode (hours:minutes:seconds:frames) at the given frame rate.
+    Timecode { fps: u32 },
+}
+
+impl TimeRulerFormat {
+    /// The format after this one when cycling through them.
+    pub fn next(&self) -> Self {
+        match self {
+            TimeRulerFormat::Time => TimeRulerFormat::Timecode { fps: 24 },
+            TimeRulerFormat::Timecode { fps: 24 } => TimeRulerFormat::Timecode { fps: 25 },
+            TimeRulerFormat::Timecode { fps: 25 } => TimeRulerFormat::Timecode { fps: 30 },
+            TimeRulerFormat::Timecode { .. } => TimeRulerFormat::Time,
+        }
+    }
+}
+
+/// A position and zoom level of the timeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelineView {
+    pub left_start_beats: f64,
+    pub horizontal_zoom_level: f64,
+}
+
+/// The part of the project the transport repeats while looping is on.
+#[derive(Debug, Lens, Clone, Copy, PartialEq, Data, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoopRegion {
+    pub enabled: bool,
+    /// The start of the loop in beats.
+    pub start_beats: f64,
+    /// The end of the loop in beats, after the start.
+    pub end_beats: f64,
+}
+
+impl Default for LoopRegion {
+    fn default() -> Self {
+        Self { enabled: false, start_beats: 0.0, end_beats: 16.0 }
+    }
+}
+
+impl LoopRegion {
+    /// True if the loop starts on the timeline and ends after it starts.
+    pub fn is_valid(&self) -> bool {
+        self.start_beats >= 0.0 && self.end_beats > self.start_beats && self.end_beats.is_finite()
+    }
+}
+
+/// The views that the timeline can go back and forward to.
+#[derive(Debug, Clone, Default)]
+pub struct ViewHistory {
+    back: Vec<TimelineView>,
+    forward: Vec<TimelineView>,
+}
+
+/// The number of beats in a bar of the default time signature, for positions
+/// that are shown without the time signatures of the project.
+pub const BEATS_PER_BAR: u32 = 4;
+
+/// The width of a single beat in logical pixels at the default zoom level.
+pub const BEAT_WIDTH: f32 = 100.0;
+/// The height of a lane in logical pixels when its height is 1.0 at the
+/// default zoom level.
+pub const DEFAULT_LANE_HEIGHT_PX: f32 = 100.0;
+pub const TIMELINE_GAP_BETWEEN_LANES: f32 = 1.0;
+
 pub const VERTICAL_ZOOM_STEP: f64 = 0.25;
-// TODO: Horizontal zoom
+// TODO: Horizontal zoom steps
 // pub const HORIZONTAL_ZOOM_STEP: f64 = 0.25;
+pub const MINIMUM_HORIZONTAL_ZOOM: f64 = 0.005;
+pub const MAXIMUM_HORIZONTAL_ZOOM: f64 = 16.0;
 pub const MINIMUM_VERTICAL_ZOOM: f64 = 0.25;
 pub const MAXIMUM_VERTICAL_ZOOM: f64 = 4.0;
 pub const MINIMUM_LANE_HEIGHT: f64 = 0.25;
 pub const MAXIMUM_LANE_HEIGHT: f64 = 4.0;
 pub const LANE_HEIGHT_STEP: f64 = 0.25;
+pub const MIN_BPM: f64 = 20.0;
+pub const MAX_BPM: f64 = 999.0;
+/// How much the tempo changes with each press of the nudge buttons.
+pub const TEMPO_NUDGE_STEP: f64 = 0.1;
+/// How many views the timeline remembers for going back.
+pub const MAX_VIEW_HISTORY: usize = 50;
+/// The space left on both sides of a range when zooming to it, as a fraction
+/// of the range.
+const ZOOM_TO_RANGE_MARGIN: f64 = 0.05;
+
+impl TimelineGridState {
+    /// Convert a time in seconds to a position on the timeline in beats.
+    pub fn seconds_to_beats(&self, seconds: f64) -> f64 {
+        self.tempo_map.seconds_to_beats(self.bpm, seconds)
+    }
+
+    /// Convert a position on the timeline in beats to a time in seconds.
+    pub fn beats_to_seconds(&self, beats: f64) -> f64 {
+        self.tempo_map.beats_to_seconds(self.bpm, beats)
+    }
+
+    /// The position on the timeline that a time from the start of the
+    /// project falls on.
+    pub fn seconds_to_musical(&self, seconds: f64) -> MusicalTime {
+        musical_from_beats(self.seconds_to_beats(seconds))
+    }
+
+    /// How long a stretch of the timeline that starts at `start_beats` takes
+    /// to play, in seconds.
+    pub fn length_to_seconds(&self, start_beats: f64, length_beats: f64) -> f64 {
+        self.beats_to_seconds(start_beats + length_beats) - self.beats_to_seconds(start_beats)
+    }
+
+    /// How many beats a time of `length_secs` that starts at `start_beats`
+    /// covers on the timeline.
+    pub fn length_to_beats(&self, start_beats: f64, length_secs: f64) -> f64 {
+        self.seconds_to_beats(self.beats_to_seconds(start_beats) + length_secs) - start_beats
+    }
+
+    /// The tempo at a position on the timeline in beats.
+    pub fn bpm_at(&self, beats: f64) -> f64 {
+        self.tempo_map.bpm_at(self.bpm, beats)
+    }
+
+    /// Send the tempo at the playhead to the nodes in the audio graph.
+    ///
+    /// This is called every time the engine is polled while playing, so
+    /// ramps move in steps of one poll.
+    pub fn follow_playhead(&self, beats: f64) {
+        self.tempo_bus.set_bpm(self.bpm_at(beats));
+    }
+
+    /// The start and end of the loop region in frames at the given sample
+    /// rate, or `None` if looping is off.
+    pub fn loop_frames(&self, sample_rate: SampleRate) -> Option<(Frames, Frames)> {
+        let region = &self.loop_region;
+        if !region.enabled {
+            return None;
+        }
+        let to_frames =
+            |beats: f64| Seconds(self.beats_to_seconds(beats)).to_nearest_frame_round(sample_rate);
+        Some((to_frames(region.start_beats), to_frames(region.end_beats)))
+    }
+
+    /// Send the tempo and the tempo changes to the nodes in the audio graph,
+    /// after either of them changed.
+    pub fn publish_tempo(&self) {
+        self.tempo_bus.set_tempo(ProjectTempo::new(self.bpm, self.tempo_map.clone()));
+    }
+
+    /// Give every lane the same height so that all of them fit in the view,
+    /// as far as the minimum lane height allows.
+    pub fn fit_all_lanes(&mut self) {
+        let num_lanes = self.lane_states.lanes.len();
+        if num_lanes == 0 || self.view_height <= 0.0 {
+            return;
+        }
+
+        let lane_px = self.view_height / num_lanes as f32 / self.vertical_zoom_level as f32
+            - TIMELINE_GAP_BETWEEN_LANES;
+        let height = f64::from(lane_px / DEFAULT_LANE_HEIGHT_PX)
+            .clamp(MINIMUM_LANE_HEIGHT, MAXIMUM_LANE_HEIGHT);
+        for lane in self.lane_states.lanes.iter_mut() {
+            lane.height = Some(height);
+        }
+    }
+
+    /// The width of a single beat in logical pixels at the current zoom level.
+    pub fn beat_width(&self) -> f32 {
+        BEAT_WIDTH * self.horizontal_zoom_level as f32
+    }
+
+    pub fn current_view(&self) -> TimelineView {
+        TimelineView {
+            left_start_beats: self.left_start.get().as_beats_f64(),
+            horizontal_zoom_level: self.horizontal_zoom_level,
+        }
+    }
+
+    fn set_view(&mut self, view: TimelineView) {
+        self.left_start = musical_from_beats(view.left_start_beats).into();
+        self.horizontal_zoom_level = view.horizontal_zoom_level;
+    }
+
+    /// Zoom and scroll so the given range (in beats) fills the view. The view
+    /// before is remembered for going back.
+    pub fn show_range(&mut self, start: f64, end: f64) {
+        let margin = (end - start) * ZOOM_TO_RANGE_MARGIN;
+        let (start, end) = ((start - margin).max(0.0), end + margin);
+        if end <= start || self.view_width <= 0.0 {
+            return;
+        }
+
+        let zoom = f64::from(self.view_width) / ((end - start) * f64::from(BEAT_WIDTH));
+        let view = TimelineView {
+            left_start_beats: start,
+            horizontal_zoom_level: zoom.clamp(MINIMUM_HORIZONTAL_ZOOM, MAXIMUM_HORIZONTAL_ZOOM),
+        };
+        if view == self.current_view() {
+            return;
+        }
+
+        let back = &mut self.view_history.back;
+        back.push(self.current_view());
+        if back.len() > MAX_VIEW_HISTORY {
+            back.remove(0);
+        }
+        self.view_history.forward.clear();
+        self.set_view(view);
+    }
+
+    /// Go back to the view before the last zoom. Returns false if there is none.
+    pub fn view_back(&mut self) -> bool {
+        match self.view_history.back.pop() {
+            Some(view) => {
+                self.view_history.forward.push(self.current_view());
+                self.set_view(view);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Undo going back. Returns false if there is nothing to go forward to.
+    pub fn view_forward(&mut self) -> bool {
+        match self.view_history.forward.pop() {
+            Some(view) => {
+                self.view_history.back.push(self.current_view());
+                self.set_view(view);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set_bpm(&mut self, cx: &mut EventContext, bpm: f64) {
+        // Rounded so that nudging doesn't accumulate floating point errors.
+        self.bpm = (bpm.clamp(MIN_BPM, MAX_BPM) * 100.0).round() / 100.0;
+        self.tempo_bus.set_bpm(self.bpm);
+        self.publish_tempo();
+        cx.emit(UiEvent::Announce {
+            key: "announce-tempo",
+            args: vec![("bpm", Unit::Plain { decimals: 2 }.format(self.bpm))],
+        });
+        cx.needs_redraw();
+    }
+}
 
 impl Model for TimelineGridState {
     fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
         event.map(|event, _| match event {
+            UiEvent::TapTempo => {
+                if let Some(bpm) = self.tap_tempo.tap(Instant::now()) {
+                    self.set_bpm(cx, bpm);
+                }
+            }
+            UiEvent::NudgeTempo(amount) => {
+                self.tap_tempo.reset();
+                self.set_bpm(cx, self.bpm + amount);
+            }
+            UiEvent::SetTempo(bpm) => {
+                self.tap_tempo.reset();
+                self.set_bpm(cx, *bpm);
+            }
+            UiEvent::InsertTempoChange { beats, bpm, ramp } => {
+                let bpm = bpm.clamp(MIN_BPM, MAX_BPM);
+                if self.tempo_map.insert(TempoChange { beats: *beats, bpm, ramp: *ramp }).is_some()
+                {
+                    self.publish_tempo();
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::RemoveTempoChange(index) => {
+                if self.tempo_map.remove(*index).is_some() {
+                    self.publish_tempo();
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::InsertTimeSignature { bar, numerator, denominator } => {
+                let signature = TimeSignature::new(*numerator, *denominator);
+                let change = TimeSignatureChange { bar: *bar, signature };
+                if self.time_signatures.insert(change).is_some() {
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::RemoveTimeSignature(index) => {
+                if self.time_signatures.remove(*index).is_some() {
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::SetTimelineViewWidth(width) => {
+                self.view_width = *width;
+            }
+            UiEvent::ScrollTimelineTo(beats) => {
+                self.set_view(TimelineView {
+                    left_start_beats: *beats,
+                    horizontal_zoom_level: self.horizontal_zoom_level,
+                });
+                cx.needs_redraw();
+            }
+            UiEvent::ZoomToFit => {
+                self.show_range(0.0, self.project_length.get().as_beats_f64());
+                cx.needs_redraw();
+            }
+            UiEvent::ViewBack => {
+                if self.view_back() {
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::ViewForward => {
+                if self.view_forward() {
+                    cx.needs_redraw();
+                }
+            }
+            UiEvent::CycleTimeRulerFormat => {
+                self.time_ruler_format = self.time_ruler_format.next();
+                cx.needs_redraw();
+            }
             UiEvent::ZoomInVertically => {
                 self.vertical_zoom_level =
                     (self.vertical_zoom_level + VERTICAL_ZOOM_STEP).min(MAXIMUM_VERTICAL_ZOOM);
@@ -74,30 +422,33 @@ impl Model for TimelineGridState {
                 cx.needs_redraw();
             }
             UiEvent::SetSelectedLaneHeight(index, height) => {
-                for (i, lane) in self.lane_states.lanes.iter_mut().enumerate() {
-                    if *index == i {
-                        lane.height = Some(*height as f64);
-                    }
+                // The height is measured on screen, so it includes the zoom.
+                let height = f64::from(*height) / self.vertical_zoom_level;
+                if let Some(lane) = self.lane_states.lanes.get_mut(*index) {
+                    lane.height = Some(height.clamp(MINIMUM_LANE_HEIGHT, MAXIMUM_LANE_HEIGHT));
                 }
             }
             UiEvent::DecreaseSelectedLaneHeight => {
+                let default_height = self.lane_height;
                 for lane in self.lane_states.selected_lanes_mut() {
-                    if let Some(height) = lane.height {
-                        lane.height = Some((height - LANE_HEIGHT_STEP).max(MINIMUM_LANE_HEIGHT));
-                    } else {
-                        lane.height = Some(self.lane_height - LANE_HEIGHT_STEP);
-                    }
+                    let height = lane.height.unwrap_or(default_height);
+                    lane.height = Some((height - LANE_HEIGHT_STEP).max(MINIMUM_LANE_HEIGHT));
                 }
             }
             UiEvent::IncreaseSelectedLaneHeight => {
+                let default_height = self.lane_height;
                 for lane in self.lane_states.selected_lanes_mut() {
-                    if let Some(height) = lane.height {
-                        lane.height = Some((height + LANE_HEIGHT_STEP).min(MAXIMUM_LANE_HEIGHT));
-                    } else {
-                        lane.height = Some(self.lane_height + LANE_HEIGHT_STEP);
-                    }
+                    let height = lane.height.unwrap_or(default_height);
+                    lane.height = Some((height + LANE_HEIGHT_STEP).min(MAXIMUM_LANE_HEIGHT));
                 }
             }
+            UiEvent::SetTimelineViewHeight(height) => {
+                self.view_height = *height;
+            }
+            UiEvent::FitAllLanes => {
+                self.fit_all_lanes();
+                cx.needs_redraw();
+            }
             _ => {}
         });
         self.lane_states.event(cx, event);
@@ -0,0 +1,151 @@
+use dropseed::{EdgeReq, ModifyGraphRequest, PluginIDReq};
+
+use super::{ProjectSaveState, UiState};
+
+/// A batch of edits to the project that is applied as a single unit.
+///
+/// While a transaction is open, all requests to modify the audio graph are
+/// merged together and only sent to the engine once the transaction ends, so
+/// the engine only has to recompile the graph once. If the transaction is
+/// rolled back, the project is restored to the way it was when the
+/// transaction began.
+pub struct EditTransaction {
+    /// The number of times `begin_edit()` has been called without a matching
+    /// `commit_edit()` or `rollback_edit()`. Only the outermost transaction
+    /// does anything when it ends.
+    depth: usize,
+
+    /// The whole project as it was when the transaction began, like the
+    /// entries of the undo history.
+    project: ProjectSaveState,
+
+    graph_request: ModifyGraphRequest,
+}
+
+impl EditTransaction {
+    pub fn new(state: &UiState) -> Self {
+        Self {
+            depth: 1,
+            project: ProjectSaveState::from_state(state),
+            graph_request: ModifyGraphRequest {
+                add_plugin_instances: vec![],
+                remove_plugin_instances: vec![],
+                connect_new_edges: vec![],
+                disconnect_edges: vec![],
+            },
+        }
+    }
+
+    pub fn push_depth(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Returns `true` if this was the outermost transaction.
+    pub fn pop_depth(&mut self) -> bool {
+        self.depth -= 1;
+        self.depth == 0
+    }
+
+    /// Merge a graph request into this transaction.
+    pub fn queue_graph_request(&mut self, request: ModifyGraphRequest) {
+        merge_graph_request(&mut self.graph_request, request);
+    }
+
+    /// The merged graph request, or `None` if nothing in the graph changed,
+    /// and the project as it was when the transaction began.
+    pub fn finish(self) -> (Option<ModifyGraphRequest>, ProjectSaveState) {
+        let r = &self.graph_request;
+        let request = if r.add_plugin_instances.is_empty()
+            && r.remove_plugin_instances.is_empty()
+            && r.connect_new_edges.is_empty()
+            && r.disconnect_edges.is_empty()
+        {
+            None
+        } else {
+            Some(self.graph_request)
+        };
+        (request, self.project)
+    }
+}
+
+/// Append `request` to `merged`.
+///
+/// `PluginIDReq::Added` indices in the edges of the request refer to the
+/// plugins added by that same request, so they are offset to point at the
+/// right plugins in the merged request.
+fn merge_graph_request(merged: &mut ModifyGraphRequest, request: ModifyGraphRequest) {
+    let offset = merged.add_plugin_instances.len();
+    merged.add_plugin_instances.extend(request.add_plugin_instances);
+    merged.remove_plugin_instances.extend(request.remove_plugin_instances);
+    merged
+        .connect_new_edges
+        .extend(request.connect_new_edges.into_iter().map(|edge| offset_added(edge, offset)));
+    merged
+        .disconnect_edges
+        .extend(request.disconnect_edges.into_iter().map(|edge| offset_added(edge, offset)));
+}
+
+fn offset_added(mut edge: EdgeReq, offset: usize) -> EdgeReq {
+    let offset_id = |id: PluginIDReq| match id {
+        PluginIDReq::Added(i) => PluginIDReq::Added(i + offset),
+        id => id,
+    };
+    edge.src_plugin_id = offset_id(edge.src_plugin_id);
+    edge.dst_plugin_id = offset_id(edge.dst_plugin_id);
+    edge
+}
+
+#[cfg(test)]
+mod tests {
+    use dropseed::{EdgeReqPortID, PortType};
+
+    use super::*;
+
+    fn edge(src: usize, dst: usize) -> EdgeReq {
+        EdgeReq {
+            edge_type: PortType::Audio,
+            src_plugin_id: PluginIDReq::Added(src),
+            dst_plugin_id: PluginIDReq::Added(dst),
+            src_port_id: EdgeReqPortID::Main,
+            src_port_channel: 0,
+            dst_port_id: EdgeReqPortID::Main,
+            dst_port_channel: 0,
+            log_error_on_fail: false,
+        }
+    }
+
+    fn request(connect: Vec<EdgeReq>, disconnect: Vec<EdgeReq>) -> ModifyGraphRequest {
+        ModifyGraphRequest {
+            add_plugin_instances: vec![],
+            remove_plugin_instances: vec![],
+            connect_new_edges: connect,
+            disconnect_edges: disconnect,
+        }
+    }
+
+    fn added(edges: &[EdgeReq]) -> Vec<(usize, usize)> {
+        edges
+            .iter()
+            .map(|edge| match (&edge.src_plugin_id, &edge.dst_plugin_id) {
+                (PluginIDReq::Added(src), PluginIDReq::Added(dst)) => (*src, *dst),
+                _ => panic!("edge between existing plugins"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn edges_of_added_plugins_are_offset() {
+        let edge = offset_added(edge(0, 2), 3);
+        assert_eq!(added(&[edge]), [(3, 5)]);
+    }
+
+    #[test]
+    fn both_kinds_of_edges_are_merged_in_order() {
+        let mut merged = request(vec![], vec![]);
+        merge_graph_request(&mut merged, request(vec![edge(0, 1)], vec![edge(1, 0)]));
+        merge_graph_request(&mut merged, request(vec![edge(2, 3)], vec![edge(3, 2)]));
+
+        assert_eq!(added(&merged.connect_new_edges), [(0, 1), (2, 3)]);
+        assert_eq!(added(&merged.disconnect_edges), [(1, 0), (3, 2)]);
+    }
+}
@@ -0,0 +1,55 @@
+use vizia::prelude::*;
+
+use crate::backend::dsp::pitch::DEFAULT_A4_FREQ;
+use crate::backend::tuner_plug::TunerReading;
+
+/// The state of the tuner panel.
+#[derive(Debug, Lens, Clone)]
+pub struct TunerState {
+    /// The name of the detected note (i.e. "A4"), or "-" if no pitch is detected.
+    pub note_name: String,
+
+    /// How far off the detected pitch is from the nearest note in the range
+    /// `[-50.0, 50.0]`.
+    pub cents: f32,
+
+    /// The detected frequency in Hz (`0.0` if no pitch is detected).
+    pub freq: f32,
+
+    /// True if a pitch is currently being detected.
+    pub has_pitch: bool,
+
+    /// The frequency of A4 in Hz.
+    pub reference_pitch: f32,
+}
+
+impl TunerState {
+    pub fn update(&mut self, reading: Option<TunerReading>) {
+        match reading {
+            Some(reading) => {
+                self.note_name = reading.note_name();
+                self.cents = reading.cents;
+                self.freq = reading.freq;
+                self.has_pitch = true;
+            }
+            None => {
+                self.note_name = String::from("-");
+                self.cents = 0.0;
+                self.freq = 0.0;
+                self.has_pitch = false;
+            }
+        }
+    }
+}
+
+impl Default for TunerState {
+    fn default() -> Self {
+        Self {
+            note_name: String::from("-"),
+            cents: 0.0,
+            freq: 0.0,
+            has_pitch: false,
+            reference_pitch: DEFAULT_A4_FREQ,
+        }
+    }
+}
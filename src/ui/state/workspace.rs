@@ -0,0 +1,182 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use super::{ChannelRackOrientation, MainPanel, PanelState, SidePanel};
+
+/// The directory that global workspaces are saved in.
+pub const GLOBAL_WORKSPACES_DIR: &str = "workspaces";
+
+const WORKSPACE_EXTENSION: &str = "workspace";
+
+/// A named arrangement of the panels.
+///
+/// Workspaces can be saved with a project, or globally so they are available
+/// in every project.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub name: String,
+    pub panels: PanelState,
+}
+
+impl Workspace {
+    pub fn new(name: String, panels: &PanelState) -> Self {
+        Self { name, panels: panels.clone() }
+    }
+
+    /// Write the layout as `key = value` lines.
+    pub fn to_text(&self) -> String {
+        let p = &self.panels;
+        let join = |names: Vec<&str>| names.join(",");
+
+        [
+            format!(
+                "channel_rack_orientation = {}",
+                match p.channel_rack_orientation {
+                    ChannelRackOrientation::Horizontal => "horizontal",
+                    ChannelRackOrientation::Vertical => "vertical",
+                }
+            ),
+            format!("hide_clips = {}", p.hide_clips),
+            format!("hide_piano_roll = {}", p.hide_piano_roll),
+            format!("browser_width = {}", p.browser_width),
+            format!("lane_header_width = {}", p.lane_header_width),
+            format!("hide_browser = {}", p.hide_browser),
+            format!("hide_tuner = {}", p.hide_tuner),
+            format!("hide_inspector = {}", p.hide_inspector),
+            format!("hide_loudness_history = {}", p.hide_loudness_history),
+            format!("hide_sample_editor = {}", p.hide_sample_editor),
+            format!("hide_eq = {}", p.hide_eq),
+            format!("hide_collisions = {}", p.hide_collisions),
+            format!("hide_console = {}", p.hide_console),
+            format!("hide_keyboard = {}", p.hide_keyboard),
+            format!("hide_pads = {}", p.hide_pads),
+            format!(
+                "side_panel_order = {}",
+                join(p.side_panel_order.iter().map(|p| p.name()).collect())
+            ),
+            format!(
+                "main_panel_order = {}",
+                join(p.main_panel_order.iter().map(|p| p.name()).collect())
+            ),
+        ]
+        .join("\n")
+    }
+
+    /// Read a layout written by `to_text()`. Anything that is missing or can't
+    /// be parsed is taken from `defaults`.
+    pub fn from_text(name: String, text: &str, defaults: &PanelState) -> Self {
+        let mut p = defaults.clone();
+
+        for line in text.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+
+            let set_bool = |field: &mut bool| {
+                if let Ok(v) = value.parse() {
+                    *field = v;
+                }
+            };
+            let set_f32 = |field: &mut f32| {
+                if let Ok(v) = value.parse() {
+                    *field = v;
+                }
+            };
+
+            match key {
+                "channel_rack_orientation" => {
+                    p.channel_rack_orientation = match value {
+                        "vertical" => ChannelRackOrientation::Vertical,
+                        _ => ChannelRackOrientation::Horizontal,
+                    }
+                }
+                "hide_clips" => set_bool(&mut p.hide_clips),
+                "hide_piano_roll" => set_bool(&mut p.hide_piano_roll),
+                "browser_width" => set_f32(&mut p.browser_width),
+                "lane_header_width" => set_f32(&mut p.lane_header_width),
+                "hide_browser" => set_bool(&mut p.hide_browser),
+                "hide_tuner" => set_bool(&mut p.hide_tuner),
+                "hide_inspector" => set_bool(&mut p.hide_inspector),
+                "hide_loudness_history" => set_bool(&mut p.hide_loudness_history),
+                "hide_sample_editor" => set_bool(&mut p.hide_sample_editor),
+                "hide_eq" => set_bool(&mut p.hide_eq),
+                "hide_collisions" => set_bool(&mut p.hide_collisions),
+                "hide_console" => set_bool(&mut p.hide_console),
+                "hide_keyboard" => set_bool(&mut p.hide_keyboard),
+                "hide_pads" => set_bool(&mut p.hide_pads),
+                "side_panel_order" => {
+                    p.side_panel_order = complete_order(
+                        value.split(',').filter_map(|n| SidePanel::from_name(n.trim())),
+                        &SidePanel::DEFAULT_ORDER,
+                    );
+                }
+                "main_panel_order" => {
+                    p.main_panel_order = complete_order(
+                        value.split(',').filter_map(|n| MainPanel::from_name(n.trim())),
+                        &MainPanel::DEFAULT_ORDER,
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        Self { name, panels: p }
+    }
+
+    fn global_path(name: &str) -> PathBuf {
+        Path::new(GLOBAL_WORKSPACES_DIR).join(format!("{}.{}", name, WORKSPACE_EXTENSION))
+    }
+
+    /// Save this workspace to the global workspaces directory.
+    pub fn save_global(&self) -> Result<(), Box<dyn Error>> {
+        std::fs::create_dir_all(GLOBAL_WORKSPACES_DIR)?;
+        std::fs::write(Self::global_path(&self.name), self.to_text())?;
+        Ok(())
+    }
+
+    pub fn delete_global(name: &str) -> Result<(), Box<dyn Error>> {
+        std::fs::remove_file(Self::global_path(name))?;
+        Ok(())
+    }
+
+    /// Load all workspaces in the global workspaces directory, sorted by name.
+    /// Returns an empty list if the directory doesn't exist yet.
+    pub fn load_global(defaults: &PanelState) -> Vec<Workspace> {
+        let entries = match std::fs::read_dir(GLOBAL_WORKSPACES_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut workspaces: Vec<Workspace> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != WORKSPACE_EXTENSION {
+                    return None;
+                }
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                match std::fs::read_to_string(&path) {
+                    Ok(text) => Some(Workspace::from_text(name, &text, defaults)),
+                    Err(e) => {
+                        log::error!("Failed to read workspace {:?}: {}", &path, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        workspaces.sort_by(|a, b| a.name.cmp(&b.name));
+        workspaces
+    }
+}
+
+/// Every panel must appear exactly once, so drop duplicates and append any
+/// panels that are missing (i.e. panels added after the workspace was saved).
+fn complete_order<T: Copy + PartialEq>(order: impl Iterator<Item = T>, all: &[T]) -> Vec<T> {
+    let mut complete: Vec<T> = Vec::with_capacity(all.len());
+    for panel in order.chain(all.iter().copied()) {
+        if !complete.contains(&panel) {
+            complete.push(panel);
+        }
+    }
+    complete
+}
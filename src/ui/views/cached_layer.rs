@@ -0,0 +1,127 @@
+use std::cell::RefCell;
+
+use vizia::prelude::*;
+use vizia::vg::{ImageFlags, ImageId, Paint, Path, PixelFormat, RenderTarget};
+
+/// Keeps what a view draws in an image on the GPU, so it only has to be drawn
+/// again when it changes instead of on every frame.
+///
+/// The image is drawn at the physical size of the view, so it stays sharp on
+/// high-DPI displays.
+///
+/// Views don't have access to the canvas when they are removed, so the image
+/// of a dropped layer is freed the next time any layer is drawn.
+pub struct CachedLayer {
+    /// The image, its size in physical pixels, and the key it was drawn with.
+    image: RefCell<Option<(ImageId, (usize, usize), u64)>>,
+}
+
+thread_local! {
+    /// The images of the layers that were dropped, to be freed once there is
+    /// a canvas to free them with.
+    static DROPPED_IMAGES: RefCell<Vec<ImageId>> = RefCell::new(Vec::new());
+}
+
+impl CachedLayer {
+    pub fn new() -> Self {
+        Self { image: RefCell::new(None) }
+    }
+
+    /// Draw the layer into the given bounds (in physical pixels).
+    ///
+    /// `render` is only called when the size of the bounds or `key` changed
+    /// since the last time, with the canvas set up to draw into the cached
+    /// image and the size of the image. Anything that changes what `render`
+    /// draws must be part of the key.
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas,
+        (x, y, w, h): (f32, f32, f32, f32),
+        key: u64,
+        render: impl FnOnce(&mut Canvas, f32, f32),
+    ) {
+        DROPPED_IMAGES.with(|dropped| {
+            for id in dropped.borrow_mut().drain(..) {
+                canvas.delete_image(id);
+            }
+        });
+
+        let size = (w.ceil() as usize, h.ceil() as usize);
+        if size.0 == 0 || size.1 == 0 {
+            return;
+        }
+
+        let mut image = self.image.borrow_mut();
+
+        let id = match *image {
+            Some((id, cached_size, cached_key)) if cached_size == size && cached_key == key => id,
+            cached => {
+                let id = match cached {
+                    Some((id, cached_size, _)) if cached_size == size => id,
+                    _ => {
+                        if let Some((old_id, _, _)) = cached {
+                            canvas.delete_image(old_id);
+                        }
+                        match canvas.create_image_empty(
+                            size.0,
+                            size.1,
+                            PixelFormat::Rgba8,
+                            ImageFlags::FLIP_Y | ImageFlags::PREMULTIPLIED,
+                        ) {
+                            Ok(id) => id,
+                            Err(e) => {
+                                log::error!("Failed to create image for cached layer: {:?}", e);
+                                *image = None;
+
+                                // Draw directly instead.
+                                canvas.save();
+                                canvas.translate(x, y);
+                                render(canvas, w, h);
+                                canvas.restore();
+                                return;
+                            }
+                        }
+                    }
+                };
+
+                canvas.save();
+                canvas.reset_transform();
+                canvas.reset_scissor();
+                canvas.set_render_target(RenderTarget::Image(id));
+                canvas.clear_rect(
+                    0,
+                    0,
+                    size.0 as u32,
+                    size.1 as u32,
+                    vizia::vg::Color::rgba(0, 0, 0, 0),
+                );
+                render(canvas, size.0 as f32, size.1 as f32);
+                canvas.set_render_target(RenderTarget::Screen);
+                canvas.restore();
+
+                *image = Some((id, size, key));
+                id
+            }
+        };
+
+        let (w, h) = (size.0 as f32, size.1 as f32);
+        let mut path = Path::new();
+        path.rect(x, y, w, h);
+        canvas.fill_path(&mut path, Paint::image(id, x, y, w, h, 0.0, 1.0));
+    }
+}
+
+impl Default for CachedLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for CachedLayer {
+    fn drop(&mut self) {
+        if let Some((id, _, _)) = self.image.get_mut().take() {
+            // The images are all gone with the window once the thread exits.
+            let _ = DROPPED_IMAGES.try_with(|dropped| dropped.borrow_mut().push(id));
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use vizia::prelude::*;
+
+use crate::ui::state::{localized, ChannelEvent, Localization, PanelEvent, UiEvent};
+
+/// The colors offered by the "color" entries of context menus, and the keys of
+/// their names.
+pub const CONTEXT_MENU_COLORS: [(&str, &str); 6] = [
+    ("color-red", "#D25050"),
+    ("color-orange", "#E0904A"),
+    ("color-yellow", "#EDE171"),
+    ("color-green", "#6FC26B"),
+    ("color-blue", "#5A8FD8"),
+    ("color-purple", "#A070D0"),
+];
+
+/// Something that can be (re)named from a context menu.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RenameTarget {
+    Lane(usize),
+    Channel(usize),
+    Clip(usize),
+    /// A section of the arranger track.
+    Section(usize),
+    /// Save the current workspace under the entered name.
+    Workspace {
+        global: bool,
+    },
+}
+
+impl RenameTarget {
+    fn into_event(self, name: String) -> ContextMenuAction {
+        match self {
+            RenameTarget::Lane(index) => ContextMenuAction::Ui(UiEvent::RenameLane(index, name)),
+            RenameTarget::Channel(index) => {
+                ContextMenuAction::Channel(ChannelEvent::RenameChannel(index, name))
+            }
+            RenameTarget::Clip(index) => ContextMenuAction::Ui(UiEvent::RenameClip(index, name)),
+            RenameTarget::Section(index) => {
+                ContextMenuAction::Ui(UiEvent::RenameSection(index, name))
+            }
+            RenameTarget::Workspace { global } => {
+                ContextMenuAction::Ui(UiEvent::SaveWorkspace { name, global })
+            }
+        }
+    }
+}
+
+/// What happens when an entry of a context menu is clicked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContextMenuAction {
+    Ui(UiEvent),
+    Channel(ChannelEvent),
+    Panel(PanelEvent),
+    /// Show a text box in the menu to enter a new name.
+    Rename {
+        target: RenameTarget,
+        current: String,
+    },
+}
+
+impl ContextMenuAction {
+    fn emit(self, cx: &mut EventContext) {
+        match self {
+            ContextMenuAction::Ui(event) => cx.emit(event),
+            ContextMenuAction::Channel(event) => cx.emit(event),
+            ContextMenuAction::Panel(event) => cx.emit(event),
+            ContextMenuAction::Rename { .. } => {}
+        }
+    }
+}
+
+#[derive(Debug, Lens, Clone, PartialEq)]
+pub struct ContextMenuItem {
+    pub label: String,
+    #[lens(ignore)]
+    pub action: ContextMenuAction,
+}
+
+impl ContextMenuItem {
+    pub fn new(label: impl Into<String>, action: ContextMenuAction) -> Self {
+        Self { label: label.into(), action }
+    }
+
+    pub fn ui(label: impl Into<String>, event: UiEvent) -> Self {
+        Self::new(label, ContextMenuAction::Ui(event))
+    }
+
+    pub fn channel(label: impl Into<String>, event: ChannelEvent) -> Self {
+        Self::new(label, ContextMenuAction::Channel(event))
+    }
+
+    /// One entry per color in `CONTEXT_MENU_COLORS`.
+    pub fn colors(
+        localization: &Localization,
+        action: impl Fn(Color) -> ContextMenuAction,
+    ) -> Vec<Self> {
+        CONTEXT_MENU_COLORS
+            .iter()
+            .map(|(key, hex)| {
+                let label = localization.tr_args("item-color", &[("color", &localization.tr(key))]);
+                Self::new(label, action(Color::from(*hex)))
+            })
+            .collect()
+    }
+}
+
+impl Data for ContextMenuItem {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// The state of the (single) context menu of the application.
+#[derive(Debug, Lens, Clone)]
+pub struct ContextMenuData {
+    pub open: bool,
+
+    /// The position of the top-left corner of the menu in logical pixels.
+    pub x: f32,
+    pub y: f32,
+
+    pub items: Vec<ContextMenuItem>,
+
+    /// True while the menu shows a text box to rename something instead of
+    /// the list of items.
+    pub renaming: bool,
+    pub rename_text: String,
+    #[lens(ignore)]
+    rename_target: Option<RenameTarget>,
+}
+
+impl Default for ContextMenuData {
+    fn default() -> Self {
+        Self {
+            open: false,
+            x: 0.0,
+            y: 0.0,
+            items: Vec::new(),
+            renaming: false,
+            rename_text: String::new(),
+            rename_target: None,
+        }
+    }
+}
+
+pub enum ContextMenuEvent {
+    /// Open the menu at the given position (in logical pixels).
+    Open {
+        x: f32,
+        y: f32,
+        items: Vec<ContextMenuItem>,
+    },
+    Close,
+    Activate(usize),
+    SetRenameText(String),
+    SubmitRename,
+}
+
+impl Model for ContextMenuData {
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|context_menu_event, _| match context_menu_event {
+            ContextMenuEvent::Open { x, y, items } => {
+                self.open = true;
+                self.x = *x;
+                self.y = *y;
+                self.items = items.clone();
+                self.renaming = false;
+                self.rename_target = None;
+            }
+            ContextMenuEvent::Close => {
+                self.open = false;
+                self.renaming = false;
+                self.rename_target = None;
+            }
+            ContextMenuEvent::Activate(index) => {
+                let action = match self.items.get(*index) {
+                    Some(item) => item.action.clone(),
+                    None => return,
+                };
+
+                if let ContextMenuAction::Rename { target, current } = action {
+                    self.renaming = true;
+                    self.rename_text = current;
+                    self.rename_target = Some(target);
+                } else {
+                    self.open = false;
+                    action.emit(cx);
+                }
+            }
+            ContextMenuEvent::SetRenameText(text) => {
+                self.rename_text = text.clone();
+            }
+            ContextMenuEvent::SubmitRename => {
+                if let Some(target) = self.rename_target.take() {
+                    let name = self.rename_text.trim().to_string();
+                    if !name.is_empty() {
+                        target.into_event(name).emit(cx);
+                    }
+                }
+                self.open = false;
+                self.renaming = false;
+            }
+        });
+    }
+}
+
+/// Open the context menu at the mouse cursor.
+pub fn open_context_menu(cx: &mut EventContext, items: Vec<ContextMenuItem>) {
+    let dpi = cx.scale_factor();
+    let (x, y) = (cx.mouse.cursorx / dpi, cx.mouse.cursory / dpi);
+    cx.emit(ContextMenuEvent::Open { x, y, items });
+}
+
+/// Open the context menu below the current view, for when it was opened with
+/// the keyboard.
+pub fn open_context_menu_below(cx: &mut EventContext, items: Vec<ContextMenuItem>) {
+    let dpi = cx.scale_factor();
+    let current = cx.current();
+    let x = cx.cache.get_posx(current) / dpi;
+    let y = (cx.cache.get_posy(current) + cx.cache.get_height(current)) / dpi;
+    cx.emit(ContextMenuEvent::Open { x, y, items });
+}
+
+/// Returns true if the key opens the context menu of the focused view: the
+/// menu key, or Shift + F10.
+pub fn is_context_menu_key(cx: &EventContext, code: Code) -> bool {
+    code == Code::ContextMenu || (code == Code::F10 && cx.modifiers.contains(Modifiers::SHIFT))
+}
+
+/// The context menu of the application. This must be built last in the root
+/// of the window so it is drawn on top of everything else.
+pub fn context_menu(cx: &mut Context) {
+    ContextMenuData::default().build(cx);
+
+    // Clicking anywhere outside of the menu closes it.
+    Element::new(cx)
+        .class("context_menu_backdrop")
+        .toggle_class("hidden", ContextMenuData::open.map(|open| !open))
+        .on_press(|cx| cx.emit(ContextMenuEvent::Close));
+
+    VStack::new(cx, |cx| {
+        List::new(cx, ContextMenuData::items, |cx, index, item| {
+            Button::new(
+                cx,
+                move |cx| cx.emit(ContextMenuEvent::Activate(index)),
+                move |cx| Label::new(cx, item.then(ContextMenuItem::label)).class("small"),
+            );
+        })
+        .toggle_class("hidden", ContextMenuData::renaming);
+
+        HStack::new(cx, |cx| {
+            Textbox::new(cx, ContextMenuData::rename_text)
+                .on_edit(|cx, text| cx.emit(ContextMenuEvent::SetRenameText(text)));
+            Button::new(
+                cx,
+                |cx| cx.emit(ContextMenuEvent::SubmitRename),
+                |cx| Label::new(cx, localized("button-rename")).class("small"),
+            );
+        })
+        .class("context_menu_rename")
+        .toggle_class("hidden", ContextMenuData::renaming.map(|renaming| !renaming));
+    })
+    .class("context_menu")
+    .left(ContextMenuData::x.map(|x| Pixels(*x)))
+    .top(ContextMenuData::y.map(|y| Pixels(*y)))
+    .toggle_class("hidden", ContextMenuData::open.map(|open| !open));
+}
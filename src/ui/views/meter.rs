@@ -154,6 +154,8 @@ impl View for Meter {
         event.map(|meter_event, _| {
             match meter_event {
                 MeterEvents::UpdatePosition(n) => {
+                    let (old_pos, old_max) = (self.pos, self.max);
+
                     let new_pos = match self.scale {
                         MeterScale::Linear => (*n).abs(),
                         MeterScale::Logarithmic => {
@@ -188,7 +190,17 @@ impl View for Meter {
                         self.max_delay_ticker -= 1;
                     }
 
-                    cx.needs_redraw();
+                    // Only redraw when the bar or the peak line moved by at least
+                    // half a pixel. This is called on every poll of the engine,
+                    // even when the input is silent.
+                    let current = cx.current();
+                    let length =
+                        cx.cache.get_width(current).max(cx.cache.get_height(current)).max(1.0);
+                    if ((self.pos - old_pos) * length).abs() >= 0.5
+                        || ((self.max - old_max) * length).abs() >= 0.5
+                    {
+                        cx.needs_redraw();
+                    }
                 }
                 MeterEvents::ChangeMeterScale(scale) => {
                     self.scale = *scale;
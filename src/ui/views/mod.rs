@@ -7,3 +7,9 @@ pub mod icon;
 pub use icon::*;
 pub mod panel;
 pub use panel::*;
+pub mod cached_layer;
+pub use cached_layer::*;
+pub mod context_menu;
+pub use context_menu::*;
+pub mod note_pad;
+pub use note_pad::*;
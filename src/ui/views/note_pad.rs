@@ -0,0 +1,55 @@
+use vizia::prelude::*;
+
+use crate::ui::state::{click_velocity, UiData, UiEvent, UiState};
+
+/// A key of the on-screen keyboard or a pad of the pad grid. Pressing it
+/// plays its note on the selected channel until it is released, louder the
+/// lower it is pressed. It lights up while its note is held on the MIDI
+/// input, whatever played it.
+pub struct NotePad {
+    key: i16,
+    pressed: bool,
+}
+
+impl NotePad {
+    pub fn new(cx: &mut Context, key: i16) -> Handle<Self> {
+        Self { key, pressed: false }.build(cx, |_| {}).toggle_class(
+            "active",
+            UiData::state.then(UiState::onscreen_instruments).map(move |o| o.is_active(key)),
+        )
+    }
+}
+
+impl View for NotePad {
+    fn element(&self) -> Option<&'static str> {
+        Some("note_pad")
+    }
+
+    fn event(&mut self, cx: &mut EventContext, event: &mut Event) {
+        event.map(|window_event, _| match window_event {
+            WindowEvent::MouseDown(MouseButton::Left) => {
+                let current = cx.current();
+                let height = cx.cache.get_height(current);
+                let y = if height > 0.0 {
+                    (cx.mouse.cursory - cx.cache.get_posy(current)) / height
+                } else {
+                    1.0
+                };
+
+                cx.emit(UiEvent::PlayOnScreenNote { key: self.key, velocity: click_velocity(y) });
+                self.pressed = true;
+                cx.capture();
+            }
+
+            WindowEvent::MouseUp(MouseButton::Left) => {
+                if self.pressed {
+                    self.pressed = false;
+                    cx.release();
+                    cx.emit(UiEvent::ReleaseOnScreenNote(self.key));
+                }
+            }
+
+            _ => {}
+        });
+    }
+}
@@ -0,0 +1,74 @@
+//! Seeded mutation of valid inputs, for the tests that feed corrupt files to
+//! the loaders. Every run mutates the same way, so a failure can be repeated.
+//!
+//! Set `MEADOWLARK_FUZZ_ITERATIONS` to run more (or fewer) mutations than the
+//! default.
+
+const ITERATIONS_VAR: &str = "MEADOWLARK_FUZZ_ITERATIONS";
+
+/// The most mutations applied to one input.
+const MAX_MUTATIONS: usize = 8;
+
+/// The number of mutated inputs each fuzz test should try.
+pub fn fuzz_iterations(default: usize) -> usize {
+    std::env::var(ITERATIONS_VAR).ok().and_then(|n| n.parse().ok()).unwrap_or(default)
+}
+
+pub struct Mutator {
+    state: u64,
+}
+
+impl Mutator {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Self { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number in `0..n`, or 0 if `n` is 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        if n == 0 {
+            0
+        } else {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    /// Return a copy of `input` with a few random changes: bytes flipped,
+    /// ranges removed, duplicated or cut off, and `tokens` spliced in. The
+    /// tokens should be the words of the format (i.e. keys, and numbers that
+    /// are likely to be out of range) so the mutations get past the parser.
+    pub fn mutate(&mut self, input: &[u8], tokens: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = input.to_vec();
+        for _ in 0..1 + self.below(MAX_MUTATIONS) {
+            let at = self.below(bytes.len() + 1);
+            match self.below(6) {
+                0 if at < bytes.len() => bytes[at] ^= 1 << self.below(8),
+                1 if at < bytes.len() => bytes[at] = self.next() as u8,
+                2 => {
+                    let end = (at + self.below(64)).min(bytes.len());
+                    bytes.drain(at..end);
+                }
+                3 => {
+                    let end = (at + self.below(256)).min(bytes.len());
+                    let copy = bytes[at..end].to_vec();
+                    let to = self.below(bytes.len() + 1);
+                    bytes.splice(to..to, copy);
+                }
+                4 => bytes.truncate(at),
+                _ if !tokens.is_empty() => {
+                    let token = tokens[self.below(tokens.len())];
+                    bytes.splice(at..at, token.iter().copied());
+                }
+                _ => {}
+            }
+        }
+        bytes
+    }
+}
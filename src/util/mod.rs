@@ -1,3 +1,11 @@
+#[cfg(test)]
+mod fuzz;
 mod twox_hash_map;
+mod units;
+mod wav;
 
+#[cfg(test)]
+pub use fuzz::{fuzz_iterations, Mutator};
 pub use twox_hash_map::TwoXHashMap;
+pub use units::Unit;
+pub use wav::{write_wav_f32, WavSampleFormat, WavWriter};
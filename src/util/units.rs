@@ -0,0 +1,237 @@
+use crate::backend::dsp::MIN_DB;
+
+/// The unit of a value shown to the user.
+///
+/// Every place that shows a value of a given kind should format it with the
+/// same unit, so values look the same in every panel. Text entered by the
+/// user is read with `parse()`, which accepts the formatted text as well as
+/// plain numbers and common ways of writing the unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    /// Gain in decibels. Values at or below `MIN_DB` are shown as `-inf dB`.
+    Decibels,
+    /// Frequency in Hz. Values of 1 kHz and up are shown in kHz.
+    Hertz,
+    /// Time in milliseconds. Values of 1 s and up are shown in seconds.
+    Milliseconds,
+    /// A ratio where `1.0` is 100%.
+    Percent,
+    Semitones,
+    Cents,
+    /// A position in beats, shown as the 1-based `bar:beat` it falls on.
+    BarsBeats {
+        beats_per_bar: u32,
+    },
+    /// A length in beats.
+    Beats,
+    /// A number without a unit.
+    Plain {
+        decimals: usize,
+    },
+}
+
+impl Unit {
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            Unit::Decibels => {
+                if value <= f64::from(MIN_DB) {
+                    String::from("-inf dB")
+                } else {
+                    format!("{:+.1} dB", value)
+                }
+            }
+            Unit::Hertz => {
+                if value.abs() >= 1_000.0 {
+                    format!("{:.2} kHz", value / 1_000.0)
+                } else if value.abs() >= 100.0 {
+                    format!("{:.0} Hz", value)
+                } else {
+                    format!("{:.1} Hz", value)
+                }
+            }
+            Unit::Milliseconds => {
+                if value.abs() >= 1_000.0 {
+                    format!("{:.2} s", value / 1_000.0)
+                } else {
+                    format!("{:.1} ms", value)
+                }
+            }
+            Unit::Percent => format!("{:.0}%", value * 100.0),
+            Unit::Semitones => format!("{:+.1} st", value),
+            Unit::Cents => format!("{:+.1} cents", value),
+            Unit::BarsBeats { beats_per_bar } => {
+                let beats_per_bar = f64::from((*beats_per_bar).max(1));
+                // Snap to the whole beat before finding the bar, so a value
+                // just under the next bar isn't shown past the last beat.
+                let whole = (value - value.round()).abs() < 0.005;
+                let value = if whole { value.round() } else { value };
+                let bar = (value / beats_per_bar).floor();
+                let beat = value - bar * beats_per_bar + 1.0;
+                if whole {
+                    format!("{}:{}", bar as i64 + 1, beat.round() as i64)
+                } else {
+                    format!("{}:{:.2}", bar as i64 + 1, beat)
+                }
+            }
+            Unit::Beats => format!("{:.2} beats", value),
+            Unit::Plain { decimals } => format!("{:.*}", decimals, value),
+        }
+    }
+
+    /// Read a value entered by the user. Returns `None` if the text can't be
+    /// understood.
+    pub fn parse(&self, text: &str) -> Option<f64> {
+        let text = text.trim().to_lowercase();
+
+        match self {
+            Unit::Decibels => {
+                let number = strip_suffix(&text, &["db"]);
+                if number == "-inf" || number == "-infinity" {
+                    Some(f64::from(MIN_DB))
+                } else {
+                    number.parse().ok()
+                }
+            }
+            Unit::Hertz => {
+                if let Some(number) = strip_suffix_exact(&text, &["khz", "k"]) {
+                    number.parse::<f64>().ok().map(|v| v * 1_000.0)
+                } else {
+                    strip_suffix(&text, &["hz"]).parse().ok()
+                }
+            }
+            Unit::Milliseconds => {
+                if let Some(number) = strip_suffix_exact(&text, &["ms"]) {
+                    number.parse().ok()
+                } else if let Some(number) = strip_suffix_exact(&text, &["sec", "s"]) {
+                    number.parse::<f64>().ok().map(|v| v * 1_000.0)
+                } else {
+                    text.parse().ok()
+                }
+            }
+            Unit::Percent => strip_suffix(&text, &["%"]).parse::<f64>().ok().map(|v| v / 100.0),
+            Unit::Semitones => strip_suffix(&text, &["semitones", "semitone", "st"]).parse().ok(),
+            Unit::Cents => strip_suffix(&text, &["cents", "cent", "ct"]).parse().ok(),
+            Unit::BarsBeats { beats_per_bar } => {
+                let beats_per_bar = f64::from((*beats_per_bar).max(1));
+                let (bar, beat) = match text.split_once(':') {
+                    Some((bar, beat)) => {
+                        (bar.trim().parse::<f64>().ok()?, beat.trim().parse().ok()?)
+                    }
+                    None => (text.parse::<f64>().ok()?, 1.0),
+                };
+                if bar < 1.0 || beat < 1.0 {
+                    return None;
+                }
+                Some((bar - 1.0) * beats_per_bar + beat - 1.0)
+            }
+            Unit::Beats => strip_suffix(&text, &["beats", "beat", "b"]).parse().ok(),
+            Unit::Plain { .. } => text.parse().ok(),
+        }
+    }
+}
+
+/// `text` without the first of `suffixes` it ends with, if any.
+fn strip_suffix<'a>(text: &'a str, suffixes: &[&str]) -> &'a str {
+    strip_suffix_exact(text, suffixes).unwrap_or(text)
+}
+
+fn strip_suffix_exact<'a>(text: &'a str, suffixes: &[&str]) -> Option<&'a str> {
+    suffixes.iter().find_map(|suffix| text.strip_suffix(suffix)).map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_every_unit() {
+        assert_eq!(Unit::Decibels.format(-6.02), "-6.0 dB");
+        assert_eq!(Unit::Decibels.format(3.0), "+3.0 dB");
+        assert_eq!(Unit::Decibels.format(f64::from(MIN_DB)), "-inf dB");
+        assert_eq!(Unit::Hertz.format(55.0), "55.0 Hz");
+        assert_eq!(Unit::Hertz.format(440.0), "440 Hz");
+        assert_eq!(Unit::Hertz.format(12_500.0), "12.50 kHz");
+        assert_eq!(Unit::Milliseconds.format(12.34), "12.3 ms");
+        assert_eq!(Unit::Milliseconds.format(1_500.0), "1.50 s");
+        assert_eq!(Unit::Percent.format(0.255), "26%");
+        assert_eq!(Unit::Semitones.format(-7.0), "-7.0 st");
+        assert_eq!(Unit::Cents.format(12.0), "+12.0 cents");
+        assert_eq!(Unit::Beats.format(1.5), "1.50 beats");
+        assert_eq!(Unit::Plain { decimals: 0 }.format(60.4), "60");
+        assert_eq!(Unit::Plain { decimals: 2 }.format(0.5), "0.50");
+    }
+
+    #[test]
+    fn bars_and_beats_start_at_one() {
+        let unit = Unit::BarsBeats { beats_per_bar: 4 };
+        assert_eq!(unit.format(0.0), "1:1");
+        assert_eq!(unit.format(3.0), "1:4");
+        assert_eq!(unit.format(4.0), "2:1");
+        assert_eq!(unit.format(5.5), "2:2.50");
+        // Off by less than the precision shown, so the beat is whole.
+        assert_eq!(unit.format(3.001), "1:4");
+        assert_eq!(unit.format(7.999), "3:1");
+
+        let unit = Unit::BarsBeats { beats_per_bar: 3 };
+        assert_eq!(unit.format(6.0), "3:1");
+        // A bar of no beats is treated as a bar of one.
+        assert_eq!(Unit::BarsBeats { beats_per_bar: 0 }.format(2.0), "3:1");
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        let units = [
+            (Unit::Decibels, -12.5),
+            (Unit::Hertz, 80.5),
+            (Unit::Hertz, 250.0),
+            (Unit::Hertz, 2_500.0),
+            (Unit::Milliseconds, 35.5),
+            (Unit::Milliseconds, 2_250.0),
+            (Unit::Percent, 0.75),
+            (Unit::Semitones, 12.0),
+            (Unit::Cents, -50.0),
+            (Unit::BarsBeats { beats_per_bar: 4 }, 9.0),
+            (Unit::BarsBeats { beats_per_bar: 4 }, 9.5),
+            (Unit::Beats, 0.25),
+            (Unit::Plain { decimals: 1 }, 0.5),
+        ];
+        for (unit, value) in units {
+            let text = unit.format(value);
+            assert_eq!(unit.parse(&text), Some(value), "{:?} of {}", unit, text);
+        }
+        assert_eq!(Unit::Decibels.parse("-inf dB"), Some(f64::from(MIN_DB)));
+    }
+
+    #[test]
+    fn parses_plain_numbers_and_other_ways_of_writing_units() {
+        assert_eq!(Unit::Decibels.parse("-3"), Some(-3.0));
+        assert_eq!(Unit::Decibels.parse(" -3DB "), Some(-3.0));
+        assert_eq!(Unit::Decibels.parse("-Infinity"), Some(f64::from(MIN_DB)));
+        assert_eq!(Unit::Hertz.parse("1.5k"), Some(1_500.0));
+        assert_eq!(Unit::Hertz.parse("1.5 kHz"), Some(1_500.0));
+        assert_eq!(Unit::Hertz.parse("440hz"), Some(440.0));
+        assert_eq!(Unit::Milliseconds.parse("2s"), Some(2_000.0));
+        assert_eq!(Unit::Milliseconds.parse("0.5 sec"), Some(500.0));
+        assert_eq!(Unit::Milliseconds.parse("20"), Some(20.0));
+        assert_eq!(Unit::Percent.parse("50"), Some(0.5));
+        assert_eq!(Unit::Semitones.parse("-2 semitones"), Some(-2.0));
+        assert_eq!(Unit::Cents.parse("5 ct"), Some(5.0));
+        assert_eq!(Unit::Beats.parse("3 b"), Some(3.0));
+        // A bar without a beat is its first beat.
+        assert_eq!(Unit::BarsBeats { beats_per_bar: 4 }.parse("3"), Some(8.0));
+        assert_eq!(Unit::BarsBeats { beats_per_bar: 4 }.parse(" 3 : 2 "), Some(9.0));
+    }
+
+    #[test]
+    fn rejects_what_it_cant_read() {
+        assert_eq!(Unit::Decibels.parse("loud"), None);
+        assert_eq!(Unit::Hertz.parse("k"), None);
+        assert_eq!(Unit::Milliseconds.parse(""), None);
+        assert_eq!(Unit::Percent.parse("%"), None);
+        assert_eq!(Unit::Plain { decimals: 0 }.parse("1,5"), None);
+        // There is no bar or beat 0.
+        assert_eq!(Unit::BarsBeats { beats_per_bar: 4 }.parse("0:1"), None);
+        assert_eq!(Unit::BarsBeats { beats_per_bar: 4 }.parse("1:0"), None);
+        assert_eq!(Unit::BarsBeats { beats_per_bar: 4 }.parse("1:x"), None);
+    }
+}
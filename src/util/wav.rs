@@ -0,0 +1,252 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const HEADER_LEN: u32 = 36;
+
+/// How the samples of a WAV file are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WavSampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl WavSampleFormat {
+    pub fn bytes(&self) -> u32 {
+        match self {
+            WavSampleFormat::Int16 => 2,
+            WavSampleFormat::Int24 => 3,
+            WavSampleFormat::Float32 => 4,
+        }
+    }
+
+    /// The largest integer sample, or `None` for floating point.
+    pub fn int_max(&self) -> Option<f32> {
+        match self {
+            WavSampleFormat::Int16 => Some(32767.0),
+            WavSampleFormat::Int24 => Some(8_388_607.0),
+            WavSampleFormat::Float32 => None,
+        }
+    }
+}
+
+/// Write the given stereo buffers to a 32 bit floating point WAV file.
+pub fn write_wav_f32<P: AsRef<Path>>(
+    path: P,
+    sample_rate: u32,
+    left: &[f32],
+    right: &[f32],
+) -> std::io::Result<()> {
+    let frames = left.len().min(right.len());
+    let data_len = (frames * 2 * 4) as u32;
+
+    let mut w = BufWriter::new(File::create(path)?);
+    write_header(&mut w, sample_rate, WavSampleFormat::Float32, data_len)?;
+    for (l, r) in left[0..frames].iter().zip(right[0..frames].iter()) {
+        w.write_all(&l.to_le_bytes())?;
+        w.write_all(&r.to_le_bytes())?;
+    }
+
+    w.flush()
+}
+
+/// Writes a stereo WAV file a block at a time, for when the audio doesn't fit
+/// in memory or isn't all there yet.
+///
+/// The header is written with a length of zero and filled in by `finish()`.
+pub struct WavWriter {
+    w: BufWriter<File>,
+    format: WavSampleFormat,
+    frames: u64,
+}
+
+impl WavWriter {
+    /// Create a 32 bit floating point file.
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> std::io::Result<Self> {
+        Self::create_with_format(path, sample_rate, WavSampleFormat::Float32)
+    }
+
+    pub fn create_with_format<P: AsRef<Path>>(
+        path: P,
+        sample_rate: u32,
+        format: WavSampleFormat,
+    ) -> std::io::Result<Self> {
+        let mut w = BufWriter::new(File::create(path)?);
+        write_header(&mut w, sample_rate, format, 0)?;
+        Ok(Self { w, format, frames: 0 })
+    }
+
+    /// Append interleaved stereo frames. Integer formats round the samples
+    /// and clip them to `[-1.0, 1.0]`, so any dither has to be added first.
+    pub fn write_interleaved(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for sample in samples {
+            match self.format {
+                WavSampleFormat::Int16 => {
+                    let s = (sample.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+                    self.w.write_all(&s.to_le_bytes())?;
+                }
+                WavSampleFormat::Int24 => {
+                    let s = (sample.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32;
+                    self.w.write_all(&s.to_le_bytes()[0..3])?;
+                }
+                WavSampleFormat::Float32 => self.w.write_all(&sample.to_le_bytes())?,
+            }
+        }
+        self.frames += samples.len() as u64 / 2;
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Fill in the lengths in the header and close the file.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        let bytes = u64::from(self.format.bytes());
+        let data_len = (self.frames * 2 * bytes).min(u64::from(u32::MAX - HEADER_LEN)) as u32;
+        self.w.seek(SeekFrom::Start(4))?;
+        self.w.write_all(&(HEADER_LEN + data_len).to_le_bytes())?;
+        self.w.seek(SeekFrom::Start(u64::from(HEADER_LEN) + 4))?;
+        self.w.write_all(&data_len.to_le_bytes())?;
+        self.w.flush()
+    }
+}
+
+fn write_header<W: Write>(
+    w: &mut W,
+    sample_rate: u32,
+    format: WavSampleFormat,
+    data_len: u32,
+) -> std::io::Result<()> {
+    let bytes = format.bytes();
+    let format_tag: u16 = match format {
+        WavSampleFormat::Float32 => 3,                        // IEEE float
+        WavSampleFormat::Int16 | WavSampleFormat::Int24 => 1, // PCM
+    };
+
+    w.write_all(b"RIFF")?;
+    w.write_all(&(HEADER_LEN + data_len).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&format_tag.to_le_bytes())?;
+    w.write_all(&2u16.to_le_bytes())?; // channels
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&(sample_rate * 2 * bytes).to_le_bytes())?; // bytes per second
+    w.write_all(&(2 * bytes as u16).to_le_bytes())?; // block align
+    w.write_all(&(8 * bytes as u16).to_le_bytes())?; // bits per sample
+
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("meadowlark-wav-{}-{}.wav", std::process::id(), name))
+    }
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    /// Write the interleaved samples in the given format, and read back the
+    /// bytes of the file.
+    fn write(name: &str, format: WavSampleFormat, samples: &[f32]) -> Vec<u8> {
+        let path = test_path(name);
+        let mut wav = WavWriter::create_with_format(&path, 44_100, format).unwrap();
+        // In two blocks, like a render.
+        let (first, second) = samples.split_at(samples.len() / 4 * 2);
+        wav.write_interleaved(first).unwrap();
+        wav.write_interleaved(second).unwrap();
+        assert_eq!(wav.frames(), samples.len() as u64 / 2);
+        wav.finish().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn headers_describe_every_format() {
+        let formats = [
+            (WavSampleFormat::Int16, 1, 16),
+            (WavSampleFormat::Int24, 1, 24),
+            (WavSampleFormat::Float32, 3, 32),
+        ];
+        for (format, format_tag, bits) in formats {
+            let bytes = write(&format!("{:?}", format), format, &[0.0; 200]);
+            let data_len = 100 * 2 * format.bytes();
+            assert_eq!(bytes.len() as u32, HEADER_LEN + 8 + data_len);
+
+            assert_eq!(&bytes[0..4], b"RIFF");
+            assert_eq!(u32_at(&bytes, 4), HEADER_LEN + data_len);
+            assert_eq!(&bytes[8..16], b"WAVEfmt ");
+            assert_eq!(u16_at(&bytes, 20), format_tag);
+            assert_eq!(u16_at(&bytes, 22), 2);
+            assert_eq!(u32_at(&bytes, 24), 44_100);
+            assert_eq!(u32_at(&bytes, 28), 44_100 * 2 * format.bytes());
+            assert_eq!(u16_at(&bytes, 32), 2 * format.bytes() as u16);
+            assert_eq!(u16_at(&bytes, 34), bits);
+            assert_eq!(&bytes[36..40], b"data");
+            assert_eq!(u32_at(&bytes, 40), data_len);
+        }
+    }
+
+    #[test]
+    fn integer_samples_are_rounded_and_clipped() {
+        let samples = [0.5, -0.5, 1.5, -1.5, 0.4 / 32_767.0, -0.6 / 32_767.0, 1.0, -1.0];
+
+        let bytes = write("int16", WavSampleFormat::Int16, &samples);
+        let decoded: Vec<i16> = bytes[44..]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(decoded, [16_384, -16_384, 32_767, -32_767, 0, -1, 32_767, -32_767]);
+
+        let bytes = write("int24", WavSampleFormat::Int24, &samples);
+        let decoded: Vec<i32> = bytes[44..]
+            .chunks_exact(3)
+            .map(|sample| i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8)
+            .collect();
+        assert_eq!(
+            decoded,
+            [4_194_304, -4_194_304, 8_388_607, -8_388_607, 102, -154, 8_388_607, -8_388_607]
+        );
+    }
+
+    #[test]
+    fn float_samples_are_written_as_they_are() {
+        let samples = [0.25, -0.75, 1.5, -1e-9];
+        let bytes = write("float", WavSampleFormat::Float32, &samples);
+        let decoded: Vec<f32> = bytes[44..]
+            .chunks_exact(4)
+            .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+            .collect();
+        assert_eq!(decoded, samples);
+    }
+
+    #[test]
+    fn whole_files_are_as_long_as_the_shorter_channel() {
+        let path = test_path("whole");
+        write_wav_f32(&path, 48_000, &[0.5, 0.25, 0.125], &[-0.5, -0.25]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(u32_at(&bytes, 24), 48_000);
+        assert_eq!(u32_at(&bytes, 40), 2 * 2 * 4);
+        let decoded: Vec<f32> = bytes[44..]
+            .chunks_exact(4)
+            .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]))
+            .collect();
+        assert_eq!(decoded, [0.5, -0.5, 0.25, -0.25]);
+    }
+}